@@ -0,0 +1,51 @@
+#!/bin/bash
+#
+# Times simple awk programs on a large generated input, where most of the
+# time goes into reading records, splitting fields and checking whether
+# fields are numeric strings.
+#
+# usage: awk/benches/records.sh [LINES [AWK...]]
+#
+# LINES defaults to 2000000, about 80 MB of input. Each AWK is timed in
+# turn, by default the release build of this awk. Give more than one to
+# compare them, e.g. the release builds of two commits, or gawk.
+
+set -eu
+
+lines=${1:-2000000}
+[ $# -gt 0 ] && shift
+root=$(cd "$(dirname "$0")/../.." && pwd)
+if [ $# -eq 0 ]; then
+    if ! output=$(cargo build --release -p posixutils-awk --manifest-path "$root/Cargo.toml" 2>&1); then
+        echo "$output" >&2
+        exit 1
+    fi
+    set -- "$root/target/release/awk"
+fi
+
+input=$(mktemp)
+trap 'rm -f "$input"' EXIT
+
+# A word, two integers, a decimal number and another word on each line
+"$1" -v n="$lines" 'BEGIN {
+    for (i = 1; i <= n; i++)
+        printf "key%d %d %d %d.%02d name%d\n", i % 1000, i, i * 7 % 10000, i % 100, i % 97, i % 13
+}' > "$input"
+
+programs=(
+    'END { print NR }'
+    '{ n += NF } END { print n }'
+    '{ s += $3 } END { print s }'
+    '$2 > 1000 { c++ } END { print c }'
+    '/key99 / { c++ } END { print c }'
+    '{ print $1, $4 }'
+)
+
+TIMEFORMAT=%R
+for program in "${programs[@]}"; do
+    echo "$program"
+    for awk in "$@"; do
+        seconds=$( { time "$awk" "$program" "$input" > /dev/null; } 2>&1 )
+        printf '  %8ss  %s\n' "$seconds" "$awk"
+    done
+done
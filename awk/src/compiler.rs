@@ -210,8 +210,7 @@ fn is_octal_digit(c: char) -> bool {
 /// a pair containing the escaped character and the next character in the iterator
 /// # Errors
 /// returns an error if the escape sequence is invalid
-fn parse_escape_sequence(iter: &mut Chars) -> Result<(char, Option<char>), String> {
-    let mut char_after_escape_sequence = None;
+fn parse_escape_sequence(iter: &mut Chars) -> Result<char, String> {
     let next_char = iter.next().ok_or("invalid escape sequence".to_string())?;
     let escaped_char = match next_char {
         '"' => '"',
@@ -227,13 +226,14 @@ fn parse_escape_sequence(iter: &mut Chars) -> Result<(char, Option<char>), Strin
         n if is_octal_digit(n) => {
             let mut char_code = n.to_digit(8).unwrap();
             for _ in 0..2 {
-                if let Some(c) = iter.next() {
-                    if is_octal_digit(c) {
+                // the character after the sequence is left in `iter`, as it can start
+                // another escape sequence
+                match iter.clone().next() {
+                    Some(c) if is_octal_digit(c) => {
                         char_code = char_code * 8 + c.to_digit(8).unwrap();
-                    } else {
-                        char_after_escape_sequence = Some(c);
-                        break;
+                        iter.next();
                     }
+                    _ => break,
                 }
             }
             if char_code == 0 {
@@ -243,8 +243,7 @@ fn parse_escape_sequence(iter: &mut Chars) -> Result<(char, Option<char>), Strin
         }
         other => return Err(format!("invalid escape sequence: \\{}", other)),
     };
-    let char_after_escape_sequence = char_after_escape_sequence.or_else(|| iter.next());
-    Ok((escaped_char, char_after_escape_sequence))
+    Ok(escaped_char)
 }
 
 pub fn escape_string_contents(s: &str) -> Result<Rc<str>, String> {
@@ -253,11 +252,7 @@ pub fn escape_string_contents(s: &str) -> Result<Rc<str>, String> {
     while let Some(c) = chars.next() {
         match c {
             '\\' => {
-                let (escaped_char, next) = parse_escape_sequence(&mut chars)?;
-                result.push(escaped_char);
-                if let Some(next) = next {
-                    result.push(next);
-                }
+                result.push(parse_escape_sequence(&mut chars)?);
             }
             other => result.push(other),
         }
@@ -265,6 +260,26 @@ pub fn escape_string_contents(s: &str) -> Result<Rc<str>, String> {
     Ok(result.into())
 }
 
+/// processes the escape sequences in the value of an assignment on the command line.
+/// Unlike in string literals, a backslash that doesn't start an escape sequence stays
+/// in the value, as the value can be a regex, like in `-F '\|'`
+pub fn escape_command_line_value(s: &str) -> Rc<str> {
+    let mut result = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            let mut escape = chars.clone();
+            if let Ok(escaped) = parse_escape_sequence(&mut escape) {
+                result.push(escaped);
+                chars = escape;
+                continue;
+            }
+        }
+        result.push(c);
+    }
+    result.into()
+}
+
 fn post_increment(val: &Cell<u32>) -> u32 {
     let result = val.get();
     val.set(result + 1);
@@ -1161,8 +1176,8 @@ impl Compiler {
                     .get_var(name.as_str(), locals)
                     .map_err(|msg| pest_error_from_span(name.as_span(), msg))?;
                 instructions.push(get_instruction, stmt_line_col);
-                if let Some(index) = inner.next() {
-                    self.compile_expr(index, instructions, locals)?;
+                if inner.peek().is_some() {
+                    self.compile_array_index(inner, instructions, locals)?;
                     instructions.push(OpCode::DeleteElement, stmt_line_col);
                 } else {
                     instructions.push(OpCode::ClearArray, stmt_line_col);
@@ -1306,6 +1321,8 @@ impl Compiler {
             _ => unreachable!(),
         }
 
+        self.loop_stack.push(LoopStubs::default());
+
         let iter_deref_location = instructions.len();
         instructions.push(OpCode::Invalid, array_var_line_col);
 
@@ -1317,8 +1334,25 @@ impl Compiler {
             array_var_line_col,
         );
 
+        // the iterator is still on the stack after a `break`, so it is popped before
+        // continuing after the loop
+        let loop_stubs = self.loop_stack.pop().unwrap();
+        let loop_end = if loop_stubs.break_stubs.is_empty() {
+            instructions.len()
+        } else {
+            let pop_iterator = instructions.len();
+            instructions.push(OpCode::Pop, array_var_line_col);
+            for stub in loop_stubs.break_stubs {
+                instructions.opcodes[stub] = OpCode::Jump(distance(stub, pop_iterator));
+            }
+            instructions.len()
+        };
+        for stub in loop_stubs.continue_stubs {
+            instructions.opcodes[stub] = OpCode::Jump(distance(stub, iter_deref_location));
+        }
+
         instructions.opcodes[iter_deref_location] =
-            OpCode::AdvanceIterOrJump(distance(iter_deref_location, instructions.len()));
+            OpCode::AdvanceIterOrJump(distance(iter_deref_location, loop_end));
 
         Ok(())
     }
@@ -1632,7 +1666,15 @@ impl Compiler {
                     }
                     _ => {}
                 }
-                param_map.insert(param.as_str().to_string(), parameters_count as u32);
+                if param_map
+                    .insert(param.as_str().to_string(), parameters_count as u32)
+                    .is_some()
+                {
+                    return Err(pest_error_from_span(
+                        param.as_span(),
+                        format!("parameter '{}' is declared multiple times", param.as_str()),
+                    ));
+                }
                 parameters_count += 1;
             }
             inner.next().unwrap()
@@ -1673,20 +1715,34 @@ impl Compiler {
                 } else {
                     0
                 };
+                match self.names.borrow().get(name.as_str()) {
+                    Some(GlobalName::SpecialVar(_)) => {
+                        errors.push(pest_error_from_span(
+                            name.as_span(),
+                            format!(
+                                "cannot use special variable '{}' as a function name",
+                                name.as_str()
+                            ),
+                        ));
+                        continue;
+                    }
+                    Some(_) => {
+                        errors.push(pest_error_from_span(
+                            name.as_span(),
+                            format!("function '{}' is defined multiple times", name.as_str()),
+                        ));
+                        continue;
+                    }
+                    None => {}
+                }
                 let function_id = post_increment(&self.last_global_function_id);
-                let previous_value = self.names.borrow_mut().insert(
+                self.names.borrow_mut().insert(
                     name.as_str().to_string(),
                     GlobalName::Function {
                         id: function_id,
                         parameter_count,
                     },
                 );
-                if previous_value.is_some() {
-                    errors.push(pest_error_from_span(
-                        name.as_span(),
-                        format!("function '{}' is defined multiple times", name),
-                    ));
-                }
             }
         }
     }
@@ -1832,7 +1888,7 @@ pub fn compile_program(sources: &[SourceFile]) -> Result<Program, CompilerErrors
         .into_inner()
         .into_iter()
         .filter_map(|(k, v)| match v {
-            GlobalName::Variable(id) => Some((k, id)),
+            GlobalName::Variable(id) | GlobalName::SpecialVar(id) => Some((k, id)),
             _ => None,
         })
         .collect();
@@ -1852,9 +1908,41 @@ pub fn compile_program(sources: &[SourceFile]) -> Result<Program, CompilerErrors
     }
 }
 
-/// Returns true if the given string is a valid number token.
+/// Returns true if the whole of `s` is a decimal number, with an optional fraction and
+/// exponent but no sign. Unlike the `number` rule of the grammar, this doesn't accept a
+/// float suffix, as it is used to check if input strings look like numbers.
+/// This is done by hand, as it is called for every field of the input
 pub fn is_valid_number(s: &str) -> bool {
-    AwkParser::parse(Rule::number, s).is_ok()
+    let bytes = s.as_bytes();
+    let digits_from = |start: usize| {
+        bytes[start..]
+            .iter()
+            .take_while(|b| b.is_ascii_digit())
+            .count()
+    };
+
+    let integer_digits = digits_from(0);
+    let mut end = integer_digits;
+    let mut fraction_digits = 0;
+    if bytes.get(end) == Some(&b'.') {
+        fraction_digits = digits_from(end + 1);
+        end += 1 + fraction_digits;
+    }
+    if integer_digits == 0 && fraction_digits == 0 {
+        return false;
+    }
+    if matches!(bytes.get(end), Some(b'e' | b'E')) {
+        let mut exponent_start = end + 1;
+        if matches!(bytes.get(exponent_start), Some(b'+' | b'-')) {
+            exponent_start += 1;
+        }
+        let exponent_digits = digits_from(exponent_start);
+        if exponent_digits == 0 {
+            return false;
+        }
+        end = exponent_start + exponent_digits;
+    }
+    end == bytes.len()
 }
 
 #[cfg(test)]
@@ -1965,6 +2053,24 @@ mod test {
         assert_eq!(constants, vec![Constant::Number(5.34)]);
     }
 
+    #[test]
+    fn test_is_valid_number() {
+        for number in ["0", "12", "1.", ".5", "1.5", "1e5", "1.5E-3", ".5e+2"] {
+            assert!(is_valid_number(number), "{}", number);
+        }
+        for not_number in [
+            "", ".", "e5", "1e", "1e+", "12abc", "1.5.", "1 2", "5f", "2.5f", "0x1",
+        ] {
+            assert!(!is_valid_number(not_number), "{}", not_number);
+        }
+    }
+
+    #[test]
+    fn test_escape_command_line_value() {
+        assert_eq!(&*escape_command_line_value(r"a\tb\\c\101"), "a\tb\\cA");
+        assert_eq!(&*escape_command_line_value(r"\|\.x\"), r"\|\.x\");
+    }
+
     #[test]
     fn test_compile_string() {
         let (_, constants) = compile_expr(r#""hello""#);
@@ -1987,6 +2093,9 @@ mod test {
 
         let (_, constants) = compile_expr(r#""hello\141world""#);
         assert_eq!(constants, vec![Constant::from("helloaworld")]);
+
+        let (_, constants) = compile_expr(r#""\"\\\/\t\41\\""#);
+        assert_eq!(constants, vec![Constant::from("\"\\/\t!\\")]);
     }
 
     #[test]
@@ -3084,6 +3193,23 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_compile_delete_multidimensional_element() {
+        let (instructions, _) = compile_stmt("delete a[1, 2];");
+        assert_eq!(
+            instructions,
+            vec![
+                OpCode::GetGlobal(FIRST_GLOBAL_VAR),
+                OpCode::PushConstant(0),
+                OpCode::GetGlobal(SpecialVar::Subsep as u32),
+                OpCode::Concat,
+                OpCode::PushConstant(1),
+                OpCode::Concat,
+                OpCode::DeleteElement,
+            ]
+        );
+    }
+
     #[test]
     fn test_compile_clear_array() {
         let (instructions, _) = compile_stmt("delete a");
@@ -3434,6 +3560,21 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_function_with_repeated_parameter_is_err() {
+        does_not_compile("function f(a, b, a) { return a; }");
+    }
+
+    #[test]
+    fn test_function_named_as_special_variable_is_err() {
+        does_not_compile("function NR(a) { return a; }");
+    }
+
+    #[test]
+    fn test_function_defined_multiple_times_is_err() {
+        does_not_compile("function f(a) { return a; } function f(b) { return b; }");
+    }
+
     #[test]
     fn test_compile_function_call_no_params() {
         let program = compile_correct_program(
@@ -4035,6 +4176,41 @@ mod test {
         assert_eq!(constants, vec![Constant::Number(1.0)]);
     }
 
+    #[test]
+    fn test_compile_for_each_with_break_and_continue() {
+        let (program, _) = compile_stmt("for (a in array) { if (a) break; continue }");
+        assert_eq!(
+            program,
+            vec![
+                OpCode::GlobalScalarRef(FIRST_GLOBAL_VAR),
+                OpCode::CreateGlobalIterator(FIRST_GLOBAL_VAR + 1),
+                OpCode::AdvanceIterOrJump(7),
+                OpCode::GetGlobal(FIRST_GLOBAL_VAR),
+                OpCode::JumpIfFalse(2),
+                OpCode::Jump(3),
+                OpCode::Jump(-4),
+                OpCode::Jump(-5),
+                OpCode::Pop,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compile_unterminated_for_each_stmt() {
+        let (program, _) = compile_stmt("for (a in array) a");
+        assert_eq!(
+            program,
+            vec![
+                OpCode::GlobalScalarRef(FIRST_GLOBAL_VAR),
+                OpCode::CreateGlobalIterator(FIRST_GLOBAL_VAR + 1),
+                OpCode::AdvanceIterOrJump(4),
+                OpCode::GetGlobal(FIRST_GLOBAL_VAR),
+                OpCode::Pop,
+                OpCode::Jump(-3),
+            ]
+        );
+    }
+
     #[test]
     fn test_compile_recursive_function() {
         let program = compile_correct_program(
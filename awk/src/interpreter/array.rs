@@ -14,8 +14,12 @@ use std::{
 
 use super::AwkValue;
 
+/// Iterates over the keys an array had when the iteration started, in the
+/// order they were inserted, leaving out the ones deleted since then.
+/// Elements inserted during the iteration are not visited.
 #[derive(Debug, Clone, PartialEq)]
 pub struct KeyIterator {
+    keys: Vec<Key>,
     index: usize,
 }
 
@@ -31,9 +35,7 @@ pub type KeyValuePair = (Key, AwkValue);
 #[derive(Debug, Clone, PartialEq, Default)]
 pub struct Array {
     key_map: HashMap<Key, usize>,
-    pairs: Vec<Option<KeyValuePair>>,
-    iterator_count: usize,
-    empty_slots: usize,
+    pairs: Vec<KeyValuePair>,
 }
 
 impl Array {
@@ -41,91 +43,63 @@ impl Array {
     /// This is possible even if there is an active iterator.
     pub fn delete(&mut self, key: &str) {
         if let Some(pair_index) = self.key_map.remove(key) {
-            if self.iterator_count == 0 {
-                self.pairs.swap_remove(pair_index);
-                if !self.pairs.is_empty() {
-                    let (key, _) = self.pairs[pair_index].as_ref().unwrap();
-                    *self.key_map.get_mut(key).unwrap() = pair_index;
-                }
-            } else {
-                self.pairs[pair_index] = None;
-                self.empty_slots += 1;
+            self.pairs.swap_remove(pair_index);
+            if let Some((moved_key, _)) = self.pairs.get(pair_index) {
+                *self.key_map.get_mut(moved_key).unwrap() = pair_index;
             }
         }
     }
 
-    pub fn key_iter(&mut self) -> KeyIterator {
-        self.iterator_count += 1;
-        KeyIterator { index: 0 }
+    pub fn key_iter(&self) -> KeyIterator {
+        KeyIterator {
+            keys: self.pairs.iter().map(|(key, _)| key.clone()).collect(),
+            index: 0,
+        }
     }
 
-    pub fn key_iter_next(&mut self, iter: &mut KeyIterator) -> Option<Key> {
-        for maybe_key in &self.pairs[iter.index..] {
+    pub fn key_iter_next(&self, iter: &mut KeyIterator) -> Option<Key> {
+        while let Some(key) = iter.keys.get(iter.index) {
             iter.index += 1;
-            if let Some((key, _)) = maybe_key {
+            if self.key_map.contains_key(key) {
                 return Some(key.clone());
             }
         }
-        iter.index = usize::MAX;
-        self.iterator_count -= 1;
         None
     }
 
     /// Get the `ValueIndex` of the key in the array. If the key does not exist, it will be inserted.
-    /// # Errors
-    /// If the array has an active iterator, an error will be returned.
-    pub fn get_value_index(&mut self, key: Key) -> Result<ValueIndex, String> {
+    pub fn get_value_index(&mut self, key: Key) -> ValueIndex {
         match self.key_map.entry(key.clone()) {
-            Entry::Occupied(e) => Ok(ValueIndex { index: *e.get() }),
+            Entry::Occupied(e) => ValueIndex { index: *e.get() },
             Entry::Vacant(e) => {
-                if self.iterator_count == 0 {
-                    let pair_index = insert_pair(
-                        key,
-                        AwkValue::uninitialized_scalar(),
-                        &mut self.pairs,
-                        self.empty_slots == 0,
-                    );
-                    e.insert(pair_index);
-                    Ok(ValueIndex { index: pair_index })
-                } else {
-                    Err("cannot insert into an array with an active iterator".to_string())
-                }
+                let pair_index = self.pairs.len();
+                self.pairs.push((key, AwkValue::uninitialized_scalar()));
+                e.insert(pair_index);
+                ValueIndex { index: pair_index }
             }
         }
     }
 
+    /// Returns the value at `index`, or `None` if the array was cleared since the index was obtained.
     pub fn index_to_value(&mut self, index: ValueIndex) -> Option<&mut AwkValue> {
-        self.pairs[index.index].as_mut().map(|(_, val)| val)
+        self.pairs.get_mut(index.index).map(|(_, val)| val)
     }
 
-    pub fn get_value(&mut self, key: Key) -> Result<&mut AwkValue, String> {
-        let index = self.get_value_index(key)?;
-        Ok(self.index_to_value(index).unwrap())
+    pub fn get_value(&mut self, key: Key) -> &mut AwkValue {
+        let index = self.get_value_index(key);
+        &mut self.pairs[index.index].1
     }
 
     /// Set the array element at the given key to the given value
-    /// # Errors
-    /// If the array has an active iterator, an error will be returned.
-    pub fn set<V: Into<AwkValue>>(&mut self, key: String, value: V) -> Result<ValueIndex, String> {
-        if self.iterator_count == 0 {
-            let key = Rc::<str>::from(key);
-            let value = value.into();
-            match self.key_map.entry(key.clone()) {
-                Entry::Occupied(e) => {
-                    let pair_index = *e.get();
-                    self.pairs[pair_index].as_mut().unwrap().1 = value;
-                    Ok(ValueIndex { index: pair_index })
-                }
-                Entry::Vacant(e) => {
-                    let pair_index =
-                        insert_pair(key, value, &mut self.pairs, self.empty_slots == 0);
-                    e.insert(pair_index);
-                    Ok(ValueIndex { index: pair_index })
-                }
-            }
-        } else {
-            Err("cannot insert into an array with an active iterator".to_string())
-        }
+    pub fn set<V: Into<AwkValue>>(&mut self, key: String, value: V) -> ValueIndex {
+        let index = self.get_value_index(Rc::from(key));
+        self.pairs[index.index].1 = value.into();
+        index
+    }
+
+    /// Returns the element with the given key, without inserting it if it does not exist
+    pub fn get(&self, key: &str) -> Option<&AwkValue> {
+        self.key_map.get(key).map(|index| &self.pairs[*index].1)
     }
 
     pub fn contains(&self, key: &str) -> bool {
@@ -135,7 +109,6 @@ impl Array {
     pub fn clear(&mut self) {
         self.key_map.clear();
         self.pairs.clear();
-        self.empty_slots = 0;
     }
 
     pub fn len(&self) -> usize {
@@ -147,43 +120,19 @@ impl<S: Into<String>, A: Into<AwkValue>> FromIterator<(S, A)> for Array {
     fn from_iter<T: IntoIterator<Item = (S, A)>>(iter: T) -> Self {
         let mut result = Self::default();
         for (key, val) in iter {
-            result
-                .set(key.into(), val)
-                .expect("failed to insert into array");
+            result.set(key.into(), val);
         }
         result
     }
 }
 
-fn insert_pair(
-    key: Key,
-    value: AwkValue,
-    pairs: &mut Vec<Option<KeyValuePair>>,
-    has_empty_slots: bool,
-) -> usize {
-    if has_empty_slots {
-        let index = pairs.len();
-        pairs.push(Some((key, value)));
-        index
-    } else {
-        let index = pairs
-            .iter()
-            .enumerate()
-            .filter_map(|(i, v)| v.as_ref().map(|_| i))
-            .next()
-            .expect("map has no empty key slots");
-        pairs[index] = Some((key, value));
-        index
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn iterate_through_empty_array() {
-        let mut array = Array::default();
+        let array = Array::default();
         let mut iter = array.key_iter();
         assert_eq!(array.key_iter_next(&mut iter), None);
     }
@@ -191,9 +140,9 @@ mod tests {
     #[test]
     fn iterate_through_array() {
         let mut array = Array::default();
-        array.set("a".to_string(), 1.0).unwrap();
-        array.set("b".to_string(), 2.0).unwrap();
-        array.set("c".to_string(), 3.0).unwrap();
+        array.set("a".to_string(), 1.0);
+        array.set("b".to_string(), 2.0);
+        array.set("c".to_string(), 3.0);
         let mut iter = array.key_iter();
         assert_eq!(array.key_iter_next(&mut iter), Some(Rc::from("a")));
         assert_eq!(array.key_iter_next(&mut iter), Some(Rc::from("b")));
@@ -204,45 +153,39 @@ mod tests {
     #[test]
     fn delete_from_array() {
         let mut array = Array::default();
-        array.set("a".to_string(), 1.0).unwrap();
+        array.set("a".to_string(), 1.0);
         array.delete("a");
         assert_eq!(array.len(), 0);
         assert_eq!(
-            array.get_value("a".into()).cloned(),
-            Ok(AwkValue::uninitialized_scalar())
+            array.get_value("a".into()).clone(),
+            AwkValue::uninitialized_scalar()
         );
     }
 
     #[test]
     fn insert_element() {
         let mut array = Array::default();
-        array.set("a".to_string(), 1.0).unwrap();
+        array.set("a".to_string(), 1.0);
         assert_eq!(array.len(), 1);
-        assert_eq!(
-            array.get_value("a".into()).cloned(),
-            Ok(AwkValue::from(1.0))
-        );
+        assert_eq!(array.get_value("a".into()).clone(), AwkValue::from(1.0));
     }
 
     #[test]
     fn insert_element_twice() {
         let mut array = Array::default();
-        array.set("a".to_string(), 1.0).unwrap();
-        array.set("a".to_string(), 2.0).unwrap();
+        array.set("a".to_string(), 1.0);
+        array.set("a".to_string(), 2.0);
         assert_eq!(array.len(), 1);
-        assert_eq!(
-            array.get_value("a".into()).cloned(),
-            Ok(AwkValue::from(2.0))
-        );
+        assert_eq!(array.get_value("a".into()).clone(), AwkValue::from(2.0));
     }
 
     #[test]
     fn delete_element_with_active_iterator() {
         let mut array = Array::default();
-        array.set("a".to_string(), 1.0).unwrap();
-        array.set("b".to_string(), 1.0).unwrap();
-        array.set("c".to_string(), 1.0).unwrap();
-        array.set("d".to_string(), 1.0).unwrap();
+        array.set("a".to_string(), 1.0);
+        array.set("b".to_string(), 1.0);
+        array.set("c".to_string(), 1.0);
+        array.set("d".to_string(), 1.0);
         let mut iter = array.key_iter();
         array.delete("b");
         array.delete("d");
@@ -252,33 +195,57 @@ mod tests {
     }
 
     #[test]
-    fn insert_with_active_iterator_is_error() {
+    fn insert_with_active_iterator_is_not_visited() {
+        let mut array = Array::default();
+        array.set("a".to_string(), 1.0);
+        let mut iter = array.key_iter();
+        array.set("b".to_string(), 2.0);
+        assert_eq!(array.key_iter_next(&mut iter), Some(Rc::from("a")));
+        assert_eq!(array.key_iter_next(&mut iter), None);
+        assert_eq!(array.len(), 2);
+    }
+
+    #[test]
+    fn delete_last_element() {
+        let mut array = Array::default();
+        array.set("a".to_string(), 1.0);
+        array.set("b".to_string(), 2.0);
+        array.delete("b");
+        array.delete("a");
+        assert_eq!(array.len(), 0);
+        array.set("c".to_string(), 3.0);
+        assert_eq!(array.get_value("c".into()).clone(), AwkValue::from(3.0));
+    }
+
+    #[test]
+    fn clear_with_active_iterator() {
         let mut array = Array::default();
-        let _ = array.key_iter();
-        assert!(array.set("a".to_string(), 1.0).is_err());
+        array.set("a".to_string(), 1.0);
+        array.set("b".to_string(), 2.0);
+        let mut iter = array.key_iter();
+        assert_eq!(array.key_iter_next(&mut iter), Some(Rc::from("a")));
+        array.clear();
+        assert_eq!(array.key_iter_next(&mut iter), None);
     }
 
     #[test]
     fn insert_element_after_iterator_has_completed_is_ok() {
         let mut array = Array::default();
-        array.set("a".to_string(), 1.0).unwrap();
+        array.set("a".to_string(), 1.0);
         let mut iter = array.key_iter();
         assert_eq!(array.key_iter_next(&mut iter), Some(Rc::from("a")));
         assert_eq!(array.key_iter_next(&mut iter), None);
-        assert!(array.set("e".to_string(), 2.0).is_ok());
+        array.set("e".to_string(), 2.0);
         assert_eq!(array.len(), 2);
-        assert_eq!(
-            array.get_value("e".into()).cloned(),
-            Ok(AwkValue::from(2.0))
-        );
+        assert_eq!(array.get_value("e".into()).clone(), AwkValue::from(2.0));
     }
 
     #[test]
     fn interleave_iteration_and_deletion_with_multiple_iterators() {
         let mut array = Array::default();
-        array.set("a".to_string(), 1.0).unwrap();
-        array.set("b".to_string(), 2.0).unwrap();
-        array.set("c".to_string(), 3.0).unwrap();
+        array.set("a".to_string(), 1.0);
+        array.set("b".to_string(), 2.0);
+        array.set("c".to_string(), 3.0);
         let mut iter1 = array.key_iter();
         let mut iter2 = array.key_iter();
         assert_eq!(array.key_iter_next(&mut iter1), Some(Rc::from("a")));
@@ -269,9 +269,13 @@ pub struct FormatArgs {
 /// # Arguments
 /// `iter` - An iterator over the characters of the format string. The iterator should be positioned
 /// after the '%' character that starts the conversion specifier.
+/// `star_arg` - Called to get the value of a width or precision given as '*'.
 /// # Returns
 /// A tuple containing the conversion specifier character and the parsed arguments.
-pub fn parse_conversion_specifier_args(iter: &mut Chars) -> Result<(char, FormatArgs), String> {
+pub fn parse_conversion_specifier_args(
+    iter: &mut Chars,
+    mut star_arg: impl FnMut() -> Result<i64, String>,
+) -> Result<(char, FormatArgs), String> {
     let iter_next = |iter: &mut Chars| iter.next().ok_or("invalid format string".to_string());
 
     let parse_number = |next: &mut char, iter: &mut Chars| -> Result<usize, String> {
@@ -302,11 +306,27 @@ pub fn parse_conversion_specifier_args(iter: &mut Chars) -> Result<(char, Format
         next = iter_next(iter)?;
     }
 
-    result.width = parse_number(&mut next, iter)?;
+    result.width = if next == '*' {
+        next = iter_next(iter)?;
+        // a negative width is taken as a '-' flag followed by a positive width
+        let width = star_arg()?;
+        if width < 0 {
+            result.left_justified = true;
+        }
+        width.unsigned_abs() as usize
+    } else {
+        parse_number(&mut next, iter)?
+    };
 
     result.precision = if next == decimal_point() {
         next = iter_next(iter)?;
-        Some(parse_number(&mut next, iter)?)
+        if next == '*' {
+            next = iter_next(iter)?;
+            // a negative precision is taken as if it was omitted
+            usize::try_from(star_arg()?).ok()
+        } else {
+            Some(parse_number(&mut next, iter)?)
+        }
     } else {
         None
     };
@@ -773,15 +793,21 @@ pub fn fmt_write_float_general(
 }
 
 pub fn fmt_write_string(target: &mut String, value: &str, args: &FormatArgs) {
-    let precision = args.precision.unwrap_or(usize::MAX);
-    let str_len = value.len().min(precision);
-    let padding = args.width.saturating_sub(str_len);
+    // the precision and width count characters, not bytes
+    let value = match args.precision {
+        Some(precision) => match value.char_indices().nth(precision) {
+            Some((end, _)) => &value[..end],
+            None => value,
+        },
+        None => value,
+    };
+    let padding = args.width.saturating_sub(value.chars().count());
     if args.left_justified {
-        target.push_str(&value[..str_len]);
+        target.push_str(value);
         pad_target(target, padding, b' ');
     } else {
         pad_target(target, padding, b' ');
-        target.push_str(&value[..str_len]);
+        target.push_str(value);
     }
 }
 
@@ -792,7 +818,8 @@ mod tests {
     #[test]
     fn test_parse_conversion_specifier_args() {
         let mut iter = "-+ #0123.456d".chars();
-        let (specifier, args) = parse_conversion_specifier_args(&mut iter).unwrap();
+        let (specifier, args) =
+            parse_conversion_specifier_args(&mut iter, || unreachable!()).unwrap();
         assert_eq!(specifier, 'd');
         assert!(args.left_justified);
         assert!(args.signed);
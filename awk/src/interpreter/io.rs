@@ -12,242 +12,285 @@ use std::{
     collections::{hash_map::Entry, HashMap},
     ffi::CString,
     fs::File,
-    io::{BufReader, Bytes, Read, Write},
+    io::{BufWriter, Read, Write},
     rc::Rc,
 };
 
 use super::string::AwkString;
+use crate::regex::Regex;
 
 pub enum RecordSeparator {
     Char(u8),
+    /// records are separated by blank lines
     Null,
+    Ere(Rc<Regex>),
 }
 
 impl TryFrom<AwkString> for RecordSeparator {
     type Error = String;
 
     fn try_from(value: AwkString) -> Result<Self, Self::Error> {
-        let mut iter = value.bytes();
-        let result = match iter.next() {
-            Some(c) => RecordSeparator::Char(c),
-            None => RecordSeparator::Null,
-        };
-        if iter.next().is_some() {
-            Err("the record separator cannot contain more than one characters".to_string())
-        } else {
-            Ok(result)
+        match value.as_bytes() {
+            [] => Ok(RecordSeparator::Null),
+            [c] => Ok(RecordSeparator::Char(*c)),
+            _ => Ok(RecordSeparator::Ere(Rc::new(Regex::new(
+                value.try_into()?,
+            )?))),
         }
     }
 }
 
-type ReadResult = Result<u8, String>;
-
-macro_rules! read_iter_next {
-    ($iter:expr, $ret:expr) => {
-        match $iter.next() {
-            Some(byte_result) => byte_result?,
-            None => return $ret,
-        }
-    };
-    ($iter:expr) => {
-        read_iter_next!($iter, Ok(None))
-    };
-}
-
-pub trait RecordReader: Iterator<Item = ReadResult> {
-    fn is_done(&self) -> bool;
-
-    fn last_byte_read(&self) -> Option<u8>;
-
-    fn read_next_record(&mut self, separator: &RecordSeparator) -> Result<Option<String>, String> {
-        if self.is_done() {
-            return Ok(None);
-        }
-        match separator {
-            RecordSeparator::Char(sep) => {
-                let mut str = String::new();
-                let mut next = read_iter_next!(self);
-                while next != *sep {
-                    str.push(next as char);
-                    next = read_iter_next!(self, Ok(Some(str)));
-                }
-                Ok(Some(str))
-            }
-            RecordSeparator::Null => {
-                let mut next = if let Some(byte) = self.last_byte_read() {
-                    byte
-                } else {
-                    read_iter_next!(self)
-                };
-                while next.is_ascii_whitespace() {
-                    next = read_iter_next!(self);
-                }
-                let mut str = String::new();
-                while next != b'\n' {
-                    str.push(next as char);
-                    next = read_iter_next!(self, Ok(Some(str)));
-                }
-                while next.is_ascii_whitespace() {
-                    next = read_iter_next!(self, Ok(Some(str)));
-                }
-                Ok(Some(str))
-            }
-        }
-    }
+// The size of the reads from the input. Reads get larger as a record
+// that doesn't fit into the buffer grows it.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// How the buffered input splits into the next record
+enum Split {
+    Record {
+        start: usize,
+        end: usize,
+        next: usize,
+    },
+    NeedMore,
+    End,
 }
 
-pub struct FileStream {
-    bytes: Bytes<BufReader<File>>,
-    last_byte_read: Option<u8>,
-    is_done: bool,
+/// The kind of the line that starts at some position in the input
+enum Line {
+    /// a line of only blanks, with the position after its newline
+    Blank(usize),
+    NotBlank,
+    /// the input read so far ends before the line does
+    Incomplete,
 }
 
-impl FileStream {
-    pub fn open(path: &str) -> Result<Self, String> {
-        let file = File::open(path).map_err(|e| e.to_string())?;
-        let reader = BufReader::new(file);
-        Ok(Self {
-            bytes: reader.bytes(),
-            last_byte_read: None,
-            is_done: false,
-        })
+fn line_at(data: &[u8], start: usize) -> Line {
+    let blanks = data[start..]
+        .iter()
+        .take_while(|b| **b == b' ' || **b == b'\t')
+        .count();
+    match data.get(start + blanks) {
+        Some(b'\n') => Line::Blank(start + blanks + 1),
+        Some(_) => Line::NotBlank,
+        None => Line::Incomplete,
     }
 }
 
-impl Iterator for FileStream {
-    type Item = ReadResult;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        match self.bytes.next() {
-            Some(Ok(byte)) => {
-                self.last_byte_read = Some(byte);
-                Some(Ok(byte))
-            }
-            Some(Err(e)) => Some(Err(e.to_string())),
-            None => {
-                self.is_done = true;
-                None
-            }
+/// finds the first match of `re` in `data` that is not empty. The regex can't
+/// match NUL bytes, so the parts of `data` around them are searched separately
+fn find_separator(re: &Regex, data: &[u8]) -> Option<(usize, usize)> {
+    let mut offset = 0;
+    for part in data.split(|b| *b == 0) {
+        let string = CString::new(part).expect("part contains a NUL byte");
+        if let Some(m) = re.match_locations(string).find(|m| m.start != m.end) {
+            return Some((offset + m.start, offset + m.end));
         }
+        offset += part.len() + 1;
     }
+    None
 }
 
-impl RecordReader for FileStream {
-    fn is_done(&self) -> bool {
-        self.is_done
-    }
-
-    fn last_byte_read(&self) -> Option<u8> {
-        self.last_byte_read
-    }
-}
-
-pub struct StringRecordReader {
-    string: String,
-    index: usize,
+/// Reads records from an input, as they are separated by `RS`.
+///
+/// The input is read in chunks, so the separator can be a regex that matches
+/// across reads. Records are not required to be valid UTF-8, invalid sequences
+/// are replaced.
+pub struct RecordReader {
+    source: Box<dyn Read>,
+    // read but not yet consumed input is `buf[pos..]`
+    buf: Vec<u8>,
+    pos: usize,
+    eof: bool,
 }
 
-impl<S: Into<String>> From<S> for StringRecordReader {
-    fn from(value: S) -> Self {
+impl RecordReader {
+    pub fn new(source: Box<dyn Read>) -> Self {
         Self {
-            string: value.into(),
-            index: 0,
+            source,
+            buf: Vec::new(),
+            pos: 0,
+            eof: false,
         }
     }
-}
-
-impl Iterator for StringRecordReader {
-    type Item = ReadResult;
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.index == self.string.len() {
-            None
-        } else {
-            let result = self.string.as_bytes()[self.index];
-            self.index += 1;
-            Some(Ok(result))
-        }
+    pub fn open_file(path: &str) -> Result<Self, String> {
+        let file = File::open(path).map_err(|e| format!("can't open file '{}': {}", path, e))?;
+        Ok(Self::new(Box::new(file)))
     }
-}
 
-impl RecordReader for StringRecordReader {
-    fn is_done(&self) -> bool {
-        self.index == self.string.len()
+    pub fn stdin() -> Self {
+        Self::new(Box::new(std::io::stdin()))
     }
 
-    fn last_byte_read(&self) -> Option<u8> {
-        if self.index == 0 {
-            None
-        } else {
-            Some(self.string.as_bytes()[self.index - 1])
+    pub fn read_next_record(
+        &mut self,
+        separator: &RecordSeparator,
+    ) -> Result<Option<String>, String> {
+        loop {
+            match self.split(separator) {
+                Split::Record { start, end, next } => {
+                    let record = &self.buf[self.pos + start..self.pos + end];
+                    let record = String::from_utf8_lossy(record).into_owned();
+                    self.pos += next;
+                    return Ok(Some(record));
+                }
+                Split::NeedMore => self.fill()?,
+                Split::End => return Ok(None),
+            }
         }
     }
-}
-
-pub type EmptyRecordReader = std::iter::Empty<ReadResult>;
 
-impl RecordReader for EmptyRecordReader {
-    fn is_done(&self) -> bool {
-        true
+    fn split(&self, separator: &RecordSeparator) -> Split {
+        let data = &self.buf[self.pos..];
+        let rest = |start: usize| {
+            if !self.eof {
+                Split::NeedMore
+            } else if start == data.len() {
+                Split::End
+            } else {
+                Split::Record {
+                    start,
+                    end: data.len(),
+                    next: data.len(),
+                }
+            }
+        };
+        match separator {
+            RecordSeparator::Char(sep) => match data.iter().position(|b| b == sep) {
+                Some(end) => Split::Record {
+                    start: 0,
+                    end,
+                    next: end + 1,
+                },
+                None => rest(0),
+            },
+            RecordSeparator::Ere(re) => match find_separator(re, data) {
+                // a longer match may need more of the input
+                Some((_, end)) if end == data.len() && !self.eof => Split::NeedMore,
+                Some((end, next)) => Split::Record {
+                    start: 0,
+                    end,
+                    next,
+                },
+                None => rest(0),
+            },
+            RecordSeparator::Null => {
+                // the blank lines before the record are skipped
+                let mut start = 0;
+                loop {
+                    match line_at(data, start) {
+                        Line::Blank(next) => start = next,
+                        Line::NotBlank => break,
+                        Line::Incomplete if self.eof => return Split::End,
+                        Line::Incomplete => return Split::NeedMore,
+                    }
+                }
+                // the record ends at a newline followed by a blank line
+                let mut newlines = data[start..]
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, b)| **b == b'\n')
+                    .map(|(i, _)| start + i);
+                newlines
+                    .find_map(|newline| match line_at(data, newline + 1) {
+                        Line::Blank(_) => Some(Split::Record {
+                            start,
+                            end: newline,
+                            next: newline + 1,
+                        }),
+                        Line::NotBlank => None,
+                        Line::Incomplete if self.eof => Some(Split::Record {
+                            start,
+                            end: newline,
+                            next: data.len(),
+                        }),
+                        Line::Incomplete => Some(Split::NeedMore),
+                    })
+                    .unwrap_or_else(|| rest(start))
+            }
+        }
     }
 
-    fn last_byte_read(&self) -> Option<u8> {
-        None
+    // reads more of the input, after the part that isn't consumed yet
+    fn fill(&mut self) -> Result<(), String> {
+        self.buf.drain(..self.pos);
+        self.pos = 0;
+
+        let len = self.buf.len();
+        self.buf.resize(len + len.max(CHUNK_SIZE), 0);
+        loop {
+            match self.source.read(&mut self.buf[len..]) {
+                Ok(n) => {
+                    self.buf.truncate(len + n);
+                    self.eof = n == 0;
+                    return Ok(());
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(err) => {
+                    self.buf.truncate(len);
+                    return Err(format!("failed to read the input: {}", err));
+                }
+            }
+        }
     }
 }
 
+/// Files written by output redirections. The writes are buffered, so that
+/// programs printing many records to a file don't make a system call for
+/// each one, and the files are flushed on `fflush`, `close` and on exit.
 #[derive(Default)]
 pub struct WriteFiles {
-    files: HashMap<String, File>,
+    files: HashMap<String, BufWriter<File>>,
 }
 
 impl WriteFiles {
+    /// writes to `filename`, opening it if it isn't open yet. `append` only
+    /// matters on that first write, where a file opened without it is truncated
     pub fn write(&mut self, filename: &str, contents: &str, append: bool) -> Result<(), String> {
-        match self.files.entry(filename.to_string()) {
-            Entry::Occupied(mut e) => {
-                e.get_mut()
-                    .write_all(contents.as_bytes())
-                    .map_err(|e| e.to_string())?;
-            }
+        let file = match self.files.entry(filename.to_string()) {
+            Entry::Occupied(e) => e.into_mut(),
             Entry::Vacant(e) => {
-                let mut file = File::options()
+                let file = File::options()
                     .write(true)
                     .create(true)
                     .append(append)
+                    .truncate(!append)
                     .open(filename)
-                    .map_err(|e| e.to_string())?;
-                file.write_all(contents.as_bytes())
-                    .map_err(|e| e.to_string())?;
-                e.insert(file);
+                    .map_err(|err| format!("can't redirect to '{}': {}", filename, err))?;
+                e.insert(BufWriter::new(file))
             }
-        }
-        Ok(())
+        };
+        file.write_all(contents.as_bytes())
+            .map_err(|err| format!("failed to write to '{}': {}", filename, err))
     }
 
-    pub fn flush_file(&mut self, filename: &str) -> bool {
-        if let Some(file) = self.files.get_mut(filename) {
-            file.flush().is_ok()
-        } else {
-            false
-        }
+    /// flushes the file, returning `None` if it is not open
+    pub fn flush_file(&mut self, filename: &str) -> Option<bool> {
+        self.files
+            .get_mut(filename)
+            .map(|file| file.flush().is_ok())
     }
 
     pub fn flush_all(&mut self) -> bool {
         let mut success = true;
         for file in self.files.values_mut() {
-            success = success && file.flush().is_ok();
+            success = file.flush().is_ok() && success;
         }
         success
     }
 
-    pub fn close_file(&mut self, filename: &str) {
-        self.files.remove(filename);
+    /// closes the file, returning `None` if it was not open, or -1 if the
+    /// buffered output could not be written
+    pub fn close_file(&mut self, filename: &str) -> Option<i32> {
+        self.files
+            .remove(filename)
+            .map(|mut file| if file.flush().is_ok() { 0 } else { -1 })
     }
 }
 
 #[derive(Default)]
 pub struct ReadFiles {
-    files: HashMap<Rc<str>, FileStream>,
+    files: HashMap<Rc<str>, RecordReader>,
 }
 
 impl ReadFiles {
@@ -260,7 +303,7 @@ impl ReadFiles {
         match self.files.entry(filename.clone()) {
             Entry::Occupied(mut e) => e.get_mut().read_next_record(separator),
             Entry::Vacant(e) => {
-                let mut file = FileStream::open(&filename)?;
+                let mut file = RecordReader::open_file(&filename)?;
                 let result = file.read_next_record(separator);
                 e.insert(file);
                 result
@@ -268,8 +311,22 @@ impl ReadFiles {
         }
     }
 
-    pub fn close_file(&mut self, filename: &str) {
-        self.files.remove(filename);
+    /// closes the file, returning `None` if it was not open
+    pub fn close_file(&mut self, filename: &str) -> Option<i32> {
+        self.files.remove(filename).map(|_| 0)
+    }
+}
+
+/// converts the status returned by `pclose` to the exit status of the command,
+/// or -1 if it could not be waited for
+fn pclose_exit_status(status: libc::c_int) -> i32 {
+    if status == -1 {
+        -1
+    } else if libc::WIFEXITED(status) {
+        libc::WEXITSTATUS(status)
+    } else {
+        // terminated by a signal, reported the same way as by the shell
+        256 + libc::WTERMSIG(status)
     }
 }
 
@@ -288,7 +345,7 @@ impl WritePipes {
                 let file = unsafe {
                     let file = libc::popen(command.as_ptr(), "w\0".as_ptr() as *const i8);
                     if file.is_null() {
-                        return Err("failed to open pipe".to_string());
+                        return Err(format!("failed to open a pipe to '{}'", e.key()));
                     }
                     file
                 };
@@ -304,28 +361,27 @@ impl WritePipes {
         Ok(())
     }
 
-    pub fn flush_file(&mut self, filename: &str) -> bool {
-        if let Some(file) = self.pipes.get(filename) {
-            unsafe { libc::fflush(*file) == 0 }
-        } else {
-            false
-        }
+    /// flushes the pipe, returning `None` if it is not open
+    pub fn flush_file(&mut self, filename: &str) -> Option<bool> {
+        self.pipes
+            .get(filename)
+            .map(|file| unsafe { libc::fflush(*file) == 0 })
     }
 
     pub fn flush_all(&mut self) -> bool {
         let mut success = true;
         for file in self.pipes.values() {
-            success = success && unsafe { libc::fflush(*file) == 0 };
+            success = unsafe { libc::fflush(*file) == 0 } && success;
         }
         success
     }
 
-    pub fn close_pipe(&mut self, filename: &str) {
-        if let Some(file) = self.pipes.remove(filename) {
-            unsafe {
-                libc::pclose(file);
-            }
-        }
+    /// closes the pipe, returning `None` if it was not open, or the exit
+    /// status of the command
+    pub fn close_pipe(&mut self, filename: &str) -> Option<i32> {
+        self.pipes
+            .remove(filename)
+            .map(|file| pclose_exit_status(unsafe { libc::pclose(file) }))
     }
 }
 
@@ -341,57 +397,65 @@ impl Drop for WritePipes {
     }
 }
 
+/// the output of a command, read straight from the file descriptor of the pipe
+/// rather than through the buffer of the `FILE`
+struct PipeSource(libc::c_int);
+
+impl Read for PipeSource {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = unsafe { libc::read(self.0, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+        if n < 0 {
+            Err(std::io::Error::last_os_error())
+        } else {
+            Ok(n as usize)
+        }
+    }
+}
+
 pub struct PipeRecordReader {
     pipe: *mut libc::FILE,
-    last_byte_read: Option<u8>,
-    is_done: bool,
+    reader: RecordReader,
 }
 
 impl PipeRecordReader {
     pub fn open(command: &str) -> Result<Self, String> {
-        let command = CString::new(command).map_err(|e| e.to_string())?;
+        let command_str = CString::new(command).map_err(|e| e.to_string())?;
         let file = unsafe {
-            let file = libc::popen(command.as_ptr(), "r\0".as_ptr() as *const i8);
+            let file = libc::popen(command_str.as_ptr(), c"r".as_ptr());
             if file.is_null() {
-                return Err("failed to open pipe".to_string());
+                return Err(format!("failed to open a pipe from '{}'", command));
             }
             file
         };
+        let fd = unsafe { libc::fileno(file) };
         Ok(Self {
             pipe: file,
-            last_byte_read: None,
-            is_done: false,
+            reader: RecordReader::new(Box::new(PipeSource(fd))),
         })
     }
-}
 
-impl Iterator for PipeRecordReader {
-    type Item = ReadResult;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        let result = unsafe { libc::fgetc(self.pipe) };
-        if result == libc::EOF {
-            self.is_done = true;
-            None
-        } else {
-            self.last_byte_read = Some(result as u8);
-            Some(Ok(result as u8))
-        }
+    pub fn read_next_record(
+        &mut self,
+        separator: &RecordSeparator,
+    ) -> Result<Option<String>, String> {
+        self.reader.read_next_record(separator)
     }
 }
 
-impl RecordReader for PipeRecordReader {
-    fn is_done(&self) -> bool {
-        self.is_done
-    }
-
-    fn last_byte_read(&self) -> Option<u8> {
-        self.last_byte_read
+impl PipeRecordReader {
+    /// waits for the command to finish, returning its exit status
+    pub fn close(mut self) -> i32 {
+        let status = unsafe { libc::pclose(self.pipe) };
+        self.pipe = std::ptr::null_mut();
+        pclose_exit_status(status)
     }
 }
 
 impl Drop for PipeRecordReader {
     fn drop(&mut self) {
+        if self.pipe.is_null() {
+            return;
+        }
         unsafe {
             if libc::pclose(self.pipe) == -1 {
                 panic!("failed to close pipe");
@@ -423,68 +487,77 @@ impl ReadPipes {
         }
     }
 
-    pub fn close_pipe(&mut self, command: &str) {
-        self.pipes.remove(command);
+    /// closes the pipe, returning `None` if it was not open, or the exit
+    /// status of the command
+    pub fn close_pipe(&mut self, command: &str) -> Option<i32> {
+        self.pipes.remove(command).map(PipeRecordReader::close)
     }
 }
 
-#[derive(Default)]
-pub struct StdinRecordReader {
-    last_byte_read: Option<u8>,
-    is_done: bool,
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-impl Iterator for StdinRecordReader {
-    type Item = ReadResult;
+    // returns the input a byte at a time, so that every record is split across reads
+    struct ByteReader(std::vec::IntoIter<u8>);
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let next = std::io::stdin().lock().bytes().next();
-        match next {
-            Some(Ok(byte)) => {
-                self.last_byte_read = Some(byte);
-                Some(Ok(byte))
-            }
-            Some(Err(e)) => Some(Err(e.to_string())),
-            None => {
-                self.is_done = true;
-                None
+    impl Read for ByteReader {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            match self.0.next() {
+                Some(byte) => {
+                    buf[0] = byte;
+                    Ok(1)
+                }
+                None => Ok(0),
             }
         }
     }
-}
-
-impl RecordReader for StdinRecordReader {
-    fn is_done(&self) -> bool {
-        self.is_done
-    }
-
-    fn last_byte_read(&self) -> Option<u8> {
-        self.last_byte_read
-    }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
 
     fn split_records(file_contents: &str, separator: RecordSeparator) -> Vec<String> {
-        let mut reader = StringRecordReader::from(file_contents);
-        let mut result = Vec::new();
-        while let Some(record) = reader.read_next_record(&separator).unwrap() {
-            result.push(record);
-        }
+        let records = |source: Box<dyn Read>| {
+            let mut reader = RecordReader::new(source);
+            let mut result = Vec::new();
+            while let Some(record) = reader.read_next_record(&separator).unwrap() {
+                result.push(record);
+            }
+            result
+        };
+        let bytes = file_contents.as_bytes().to_vec();
+        let result = records(Box::new(std::io::Cursor::new(bytes.clone())));
+        assert_eq!(result, records(Box::new(ByteReader(bytes.into_iter()))));
         result
     }
 
+    fn ere_separator(re: &str) -> RecordSeparator {
+        RecordSeparator::Ere(Rc::new(Regex::new(CString::new(re).unwrap()).unwrap()))
+    }
+
     #[test]
     fn split_empty_file() {
         assert!(split_records("", RecordSeparator::Null).is_empty());
+        assert!(split_records("", RecordSeparator::Char(b'\n')).is_empty());
+        assert!(split_records("", ere_separator("x+")).is_empty());
     }
 
     #[test]
-    fn split_records_with_default_separator() {
-        let records = split_records("record1\nrecord2\n  \t\nrecord3\n", RecordSeparator::Null);
-        assert_eq!(records, vec!["record1", "record2", "record3"]);
+    fn split_records_with_newlines() {
+        let records = split_records(
+            "record1\nrecord2\n\nrecord3\n",
+            RecordSeparator::Char(b'\n'),
+        );
+        assert_eq!(records, vec!["record1", "record2", "", "record3"]);
+    }
+
+    #[test]
+    fn split_records_separated_by_blank_lines() {
+        let records = split_records(
+            "\n  \nrecord1\nline2\n\n\nrecord2\n  \t\n  record3\nline2\n\n",
+            RecordSeparator::Null,
+        );
+        assert_eq!(
+            records,
+            vec!["record1\nline2", "record2", "  record3\nline2"]
+        );
     }
 
     #[test]
@@ -492,4 +565,25 @@ mod tests {
         let records = split_records("record1,record2,record3", RecordSeparator::Char(b','));
         assert_eq!(records, vec!["record1", "record2", "record3"]);
     }
+
+    #[test]
+    fn split_records_with_regex_separator() {
+        let records = split_records("record1<>record2<<>>>record3>", ere_separator("<*>+"));
+        assert_eq!(records, vec!["record1", "record2", "record3"]);
+    }
+
+    #[test]
+    fn split_records_with_regex_separator_at_the_end() {
+        let records = split_records("a\n\nb\n\n\n", ere_separator("\n+"));
+        assert_eq!(records, vec!["a", "b"]);
+    }
+
+    #[test]
+    fn invalid_utf8_in_records_is_replaced() {
+        let mut reader = RecordReader::new(Box::new(std::io::Cursor::new(b"a\xffb\n".to_vec())));
+        assert_eq!(
+            reader.read_next_record(&RecordSeparator::Char(b'\n')),
+            Ok(Some("a\u{fffd}b".to_string()))
+        );
+    }
 }
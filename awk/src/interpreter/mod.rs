@@ -8,15 +8,12 @@
 //
 
 use array::{Array, KeyIterator, ValueIndex};
-use io::{
-    EmptyRecordReader, FileStream, ReadFiles, ReadPipes, RecordReader, RecordSeparator,
-    StdinRecordReader, WriteFiles, WritePipes,
-};
+use io::{ReadFiles, ReadPipes, RecordReader, RecordSeparator, WriteFiles, WritePipes};
 use rand::rngs::SmallRng;
 use rand::{Rng, SeedableRng};
 use string::AwkString;
 
-use crate::compiler::{escape_string_contents, is_valid_number};
+use crate::compiler::{escape_command_line_value, is_valid_number};
 use crate::program::{
     Action, BuiltinFunction, Constant, Function, OpCode, Pattern, Program, SourceLocation,
     SpecialVar,
@@ -27,7 +24,7 @@ use format::{
     fmt_write_scientific_float, fmt_write_signed, fmt_write_string, fmt_write_unsigned,
     parse_conversion_specifier_args, IntegerFormat,
 };
-use std::cell::{RefCell, UnsafeCell};
+use std::cell::{Ref, RefCell, UnsafeCell};
 use std::collections::HashMap;
 use std::ffi::CString;
 use std::fmt::Write;
@@ -41,7 +38,11 @@ mod format;
 mod io;
 mod string;
 
-const STACK_SIZE: usize = 2048;
+const STACK_SIZE: usize = 65536;
+
+/// the number of frames printed at each end of the call trace of a runtime error.
+/// The frames in between are left out, so that deep recursion doesn't flood the output
+const CALL_TRACE_ENDS: usize = 8;
 
 fn bool_to_f64(p: bool) -> f64 {
     if p {
@@ -52,8 +53,9 @@ fn bool_to_f64(p: bool) -> f64 {
 }
 
 fn strtod(s: &str) -> f64 {
+    // like the C function, leading blanks are skipped
     lexical::parse_partial_with_options::<f64, _, { lexical::format::C_STRING }>(
-        s,
+        s.trim_start(),
         &lexical::ParseFloatOptions::default(),
     )
     .map(|(val, _)| val)
@@ -68,6 +70,23 @@ fn gather_values(stack: &mut Stack, count: u16) -> Result<Vec<AwkValue>, String>
     Ok(values)
 }
 
+/// reports an error writing to the standard output. If the output is a pipe whose reader
+/// has gone away, awk exits quietly instead, as it would when killed by `SIGPIPE`
+fn stdout_error(error: std::io::Error) -> String {
+    if error.kind() == std::io::ErrorKind::BrokenPipe {
+        std::process::exit(1);
+    }
+    format!("failed to write to the standard output: {}", error)
+}
+
+fn write_to_stdout(output: &str) -> Result<(), String> {
+    std::io::Write::write_all(&mut std::io::stdout(), output.as_bytes()).map_err(stdout_error)
+}
+
+fn flush_stdout() -> Result<(), String> {
+    std::io::Write::flush(&mut std::io::stdout()).map_err(stdout_error)
+}
+
 fn print_to_string(
     stack: &mut Stack,
     argc: u16,
@@ -92,8 +111,10 @@ fn print_to_string(
     Ok(output.into())
 }
 
+/// checks if `num` is converted to a string as an integer. Integral values that don't
+/// fit in an `i64` are converted with `CONVFMT` instead, like other numbers
 fn is_integer(num: f64) -> bool {
-    num.is_finite() && num.fract() == 0.0
+    num.fract() == 0.0 && num >= i64::MIN as f64 && num < i64::MAX as f64
 }
 
 fn swap_with_default<T: Default>(value: &mut T) -> T {
@@ -104,12 +125,10 @@ fn swap_with_default<T: Default>(value: &mut T) -> T {
 
 fn maybe_numeric_string<S: Into<AwkString>>(str: S) -> AwkString {
     let mut str = str.into();
-    let numeric_string = is_valid_number(
-        str.as_str()
-            .trim()
-            .trim_start_matches(|c| c == '+' || c == '-'),
-    );
-    str.is_numeric = numeric_string;
+    // leading and trailing blanks are allowed around a number, and so is a sign
+    let trimmed = str.as_str().trim();
+    let unsigned = trimmed.strip_prefix(['+', '-']).unwrap_or(trimmed);
+    str.is_numeric = is_valid_number(unsigned);
     str
 }
 
@@ -125,28 +144,31 @@ fn sprintf(
     while let Some(c) = next {
         match c {
             '%' => {
-                let (specifier, args) = parse_conversion_specifier_args(&mut iter)?;
+                let mut next_value = || {
+                    if current_arg == 0 {
+                        return Err("not enough arguments for format string".to_string());
+                    }
+                    current_arg -= 1;
+                    Ok(swap_with_default(&mut values[current_arg]))
+                };
+                let (specifier, args) = parse_conversion_specifier_args(&mut iter, || {
+                    Ok(next_value()?.scalar_as_f64() as i64)
+                })?;
                 if specifier == '%' {
                     result.push('%');
                     next = iter.next();
                     continue;
                 }
 
-                if current_arg == 0 {
-                    return Err("not enough arguments for format string".to_string());
-                }
-                current_arg -= 1;
-                let value = swap_with_default(&mut values[current_arg]);
+                let value = next_value()?;
                 match specifier {
                     'd' | 'i' => {
                         let value = value.scalar_as_f64() as i64;
                         fmt_write_signed(&mut result, value, &args);
                     }
                     'u' | 'o' | 'x' | 'X' => {
+                        // negative values wrap around, as they do in C
                         let value = value.scalar_as_f64() as i64;
-                        if value.is_negative() {
-                            return Err("negative value for unsigned format specifier".to_string());
-                        }
                         let format = match specifier {
                             'u' => IntegerFormat::Decimal,
                             'o' => IntegerFormat::Octal,
@@ -173,8 +195,17 @@ fn sprintf(
                         fmt_write_float_general(&mut result, value, specifier == 'g', &args);
                     }
                     'c' => {
-                        let value = value.scalar_as_f64() as i64 as u8;
-                        result.push(value as char);
+                        // a number is written as the character with that encoding, a string
+                        // as its first character
+                        let char = match &value.value {
+                            AwkValueVariant::String(s) if !s.is_numeric => {
+                                s.as_str().chars().next()
+                            }
+                            _ => Some(value.scalar_as_f64() as i64 as u8 as char),
+                        };
+                        let mut buffer = [0; 4];
+                        let char = char.map_or("", |c| c.encode_utf8(&mut buffer));
+                        fmt_write_string(&mut result, char, &args);
                     }
                     's' => {
                         let value = value.scalar_to_string(float_format)?;
@@ -206,7 +237,7 @@ fn builtin_sprintf(
 }
 
 fn builtin_match(stack: &mut Stack, global_env: &mut GlobalEnv) -> Result<(f64, f64), String> {
-    let ere = stack.pop_value().into_ere()?;
+    let ere = stack.pop_scalar_value()?.into_ere(&global_env.convfmt)?;
     let string = stack
         .pop_scalar_value()?
         .scalar_to_string(&global_env.convfmt)?;
@@ -230,43 +261,38 @@ fn gsub(
     in_str: &str,
     only_replace_first: bool,
 ) -> Result<(AwkString, usize), String> {
-    let mut result = String::with_capacity(in_str.len());
-    let mut last_match_end = 0;
-
+    // the replacement is split at each '&', which stands for the matched text.
+    // A backslash before a '&' or before another backslash makes it literal
     let mut repl_parts = Vec::new();
     let mut current_repl_part = String::new();
     let mut repl_iter = repl.chars();
     while let Some(c) = repl_iter.next() {
-        if c == '\\' {
-            match repl_iter.next() {
-                Some('\\') => current_repl_part.push('\\'),
-                Some('&') => current_repl_part.push_str(&in_str[last_match_end..]),
-                Some(c) => {
-                    current_repl_part.push('\\');
+        match c {
+            '\\' => match repl_iter.clone().next() {
+                Some(c @ ('\\' | '&')) => {
                     current_repl_part.push(c);
+                    repl_iter.next();
                 }
-                None => {
-                    current_repl_part.push('\\');
-                    break;
-                }
-            }
-        } else if c == '&' {
-            repl_parts.push(current_repl_part);
-            current_repl_part = String::new();
-        } else {
-            current_repl_part.push(c);
+                _ => current_repl_part.push('\\'),
+            },
+            '&' => repl_parts.push(swap_with_default(&mut current_repl_part)),
+            other => current_repl_part.push(other),
         }
     }
     repl_parts.push(current_repl_part);
 
+    // the regex works on bytes, so a match can start or end within a character
+    let in_bytes = in_str.as_bytes();
+    let mut result = Vec::with_capacity(in_bytes.len());
+    let mut last_match_end = 0;
     let mut num_replacements = 0;
     for m in ere.match_locations(AwkString::from(in_str).try_into()?) {
-        result.push_str(&in_str[last_match_end..m.start]);
-        let replaced_string = &in_str[m.start..m.end];
-        result.push_str(&repl_parts[0]);
+        result.extend_from_slice(&in_bytes[last_match_end..m.start]);
+        let matched = &in_bytes[m.start..m.end];
+        result.extend_from_slice(repl_parts[0].as_bytes());
         for part in repl_parts.iter().skip(1) {
-            result.push_str(part);
-            result.push_str(replaced_string);
+            result.extend_from_slice(matched);
+            result.extend_from_slice(part.as_bytes());
         }
         last_match_end = m.end;
         num_replacements += 1;
@@ -274,8 +300,11 @@ fn gsub(
             break;
         }
     }
-    result.push_str(&in_str[last_match_end..]);
-    Ok((result.into(), num_replacements))
+    result.extend_from_slice(&in_bytes[last_match_end..]);
+    Ok((
+        String::from_utf8_lossy(&result).into_owned().into(),
+        num_replacements,
+    ))
 }
 
 fn builtin_gsub(
@@ -286,7 +315,7 @@ fn builtin_gsub(
     let repl = stack
         .pop_scalar_value()?
         .scalar_to_string(&global_env.convfmt)?;
-    let ere = stack.pop_value().into_ere()?;
+    let ere = stack.pop_scalar_value()?.into_ere(&global_env.convfmt)?;
     let in_str = stack.pop_ref();
     in_str.ensure_value_is_scalar()?;
     let (result, count) = gsub(
@@ -366,18 +395,32 @@ fn call_simple_builtin(
             let separator = if argc == 2 {
                 None
             } else {
-                Some(FieldSeparator::Ere(stack.pop_value().into_ere()?))
+                // a string is a field separator like a value of FS, so that a single
+                // character is not a regex and " " is the default separator
+                let separator = stack.pop_scalar_value()?;
+                Some(
+                    if let AwkValueVariant::Regex { ere, .. } = &separator.value {
+                        FieldSeparator::Ere(ere.clone())
+                    } else {
+                        separator
+                            .scalar_to_string(&global_env.convfmt)?
+                            .try_into()?
+                    },
+                )
             };
             let s = stack
                 .pop_scalar_value()?
                 .scalar_to_string(&global_env.convfmt)?;
-            let array = stack.pop_ref().as_array()?;
+            let array = stack.pop_array()?;
             array.clear();
 
             split_record(
-                s,
+                &s,
                 separator.iter().next().unwrap_or(&global_env.fs),
-                |i, s| array.set((i + 1).to_string(), s).map(|_| ()),
+                |i, s| {
+                    array.set((i + 1).to_string(), s);
+                    Ok(())
+                },
             )?;
             let n = array.len();
             stack.push_value(n as f64)?;
@@ -388,17 +431,27 @@ fn call_simple_builtin(
         }
         BuiltinFunction::Substr => {
             let n = if argc == 2 {
-                usize::MAX
+                f64::INFINITY
             } else {
-                stack.pop_scalar_value()?.scalar_as_f64() as usize
+                stack.pop_scalar_value()?.scalar_as_f64().round()
             };
-            // the behaviour for values < 1 is not specified. Here we follow what other
-            // implementations do
-            let m = stack.pop_scalar_value()?.scalar_as_f64().max(1.0) as usize;
+            let m = stack.pop_scalar_value()?.scalar_as_f64().round();
             let s = stack
                 .pop_scalar_value()?
                 .scalar_to_string(&global_env.convfmt)?;
-            let substr = s.chars().skip(m - 1).take(n).collect::<String>();
+            // the substring has the characters at the positions from m up to, but not
+            // including, m + n. Positions before the first character and after the last
+            // one are not in the string, so they shorten the substring
+            let start = m.max(1.0);
+            let end = m + n;
+            let substr = if end > start {
+                s.chars()
+                    .skip(start as usize - 1)
+                    .take((end - start).min(usize::MAX as f64) as usize)
+                    .collect::<String>()
+            } else {
+                String::new()
+            };
             stack.push_value(substr)?;
         }
         BuiltinFunction::ToLower => {
@@ -421,16 +474,18 @@ fn call_simple_builtin(
                 .pop_scalar_value()?
                 .scalar_to_string(&global_env.convfmt)?
                 .try_into()?;
+            // the output of the command comes after the output written so far
+            flush_stdout()?;
             let status = unsafe { libc::system(command.as_ptr()) };
             if status == -1 {
                 return Err("system call failed".to_string());
             }
         }
         BuiltinFunction::Print => {
-            print!("{}", print_to_string(stack, argc, global_env)?);
+            write_to_stdout(&print_to_string(stack, argc, global_env)?)?;
         }
         BuiltinFunction::Printf => {
-            print!("{}", builtin_sprintf(stack, argc, global_env)?);
+            write_to_stdout(&builtin_sprintf(stack, argc, global_env)?)?;
         }
         _ => unreachable!("call_simple_builtin was passed an invalid builtin function kind"),
     }
@@ -444,24 +499,21 @@ enum FieldSeparator {
 }
 
 /// Splits a record into fields and calls the provided closure for each field.
-/// If the record is a numeric string, fields will be numeric strings if appropriate.
+/// Fields that look like numbers are numeric strings.
 fn split_record<S: FnMut(usize, AwkString) -> Result<(), String>>(
-    record: AwkString,
+    record: &AwkString,
     field_separator: &FieldSeparator,
     mut store_result: S,
 ) -> Result<(), String> {
-    let is_record_numeric = record.is_numeric;
-    let string = |s: &str| -> AwkString {
-        if is_record_numeric {
-            AwkString::numeric_string(s)
-        } else {
-            s.into()
-        }
-    };
+    let string = maybe_numeric_string::<&str>;
+    // an empty record has no fields, whatever the separator
+    if record.is_empty() {
+        return Ok(());
+    }
     match field_separator {
         FieldSeparator::Default => record
-            .trim_start()
-            .split_ascii_whitespace()
+            .split([' ', '\t', '\n'])
+            .filter(|s| !s.is_empty())
             .enumerate()
             .try_for_each(|(i, s)| store_result(i, string(s))),
         FieldSeparator::Char(c) => record
@@ -471,7 +523,11 @@ fn split_record<S: FnMut(usize, AwkString) -> Result<(), String>>(
         FieldSeparator::Ere(re) => {
             let mut split_start = 0;
             let mut index = 0;
-            for separator_range in re.match_locations(record.clone().try_into()?) {
+            // a separator has to contain at least a character
+            for separator_range in re
+                .match_locations(record.clone().try_into()?)
+                .filter(|m| m.start != m.end)
+            {
                 store_result(index, string(&record[split_start..separator_range.start]))?;
                 split_start = separator_range.end;
                 index += 1;
@@ -481,6 +537,29 @@ fn split_record<S: FnMut(usize, AwkString) -> Result<(), String>>(
     }
 }
 
+/// Splits a record read from the input into fields, as `split_record` does with `FS`.
+/// When records are separated by blank lines, a newline also separates fields.
+fn split_record_fields<S: FnMut(usize, AwkString) -> Result<(), String>>(
+    record: &AwkString,
+    global_env: &GlobalEnv,
+    mut store_result: S,
+) -> Result<(), String> {
+    if !matches!(global_env.rs, RecordSeparator::Null)
+        || matches!(global_env.fs, FieldSeparator::Default)
+    {
+        return split_record(record, &global_env.fs, store_result);
+    }
+    let mut index = 0;
+    for line in record.split('\n') {
+        split_record(&line.into(), &global_env.fs, |_, field| {
+            store_result(index, field)?;
+            index += 1;
+            Ok(())
+        })?;
+    }
+    Ok(())
+}
+
 impl TryFrom<AwkString> for FieldSeparator {
     type Error = String;
 
@@ -539,8 +618,8 @@ impl Default for GlobalEnv {
             ors: AwkString::from("\n"),
             ofmt: AwkString::from("%.6g"),
             rs: RecordSeparator::Char(b'\n'),
-            nr: 1,
-            fnr: 1,
+            nr: 0,
+            fnr: 0,
             nf: 0,
         }
     }
@@ -554,7 +633,8 @@ enum FieldsState {
 }
 
 struct Record {
-    record: RefCell<CString>,
+    // `$0` as a C string for matching regexes, converted the first time it's needed
+    record: RefCell<Option<CString>>,
     fields: Vec<AwkValueRef>,
     last_field: RefCell<usize>,
 }
@@ -562,11 +642,11 @@ struct Record {
 impl Record {
     const MAX_FIELDS: usize = 1024;
 
-    fn reset(&mut self, record: String, field_separator: &FieldSeparator) -> Result<(), String> {
+    fn reset(&mut self, record: String, global_env: &GlobalEnv) -> Result<(), String> {
         let previous_last_field = *self.last_field.get_mut();
         let mut last_field = 0;
         let record = maybe_numeric_string(record);
-        split_record(record.clone(), field_separator, |i, s| {
+        split_record_fields(&record, global_env, |i, s| {
             let field_index = i + 1;
             last_field += 1;
             *self.fields[field_index].get_mut() = AwkValue::field_ref(s, field_index as u16);
@@ -578,8 +658,8 @@ impl Record {
                 field.get_mut().value = AwkValueVariant::UninitializedScalar;
             }
         }
-        *self.fields[0].get_mut() = AwkValue::field_ref(record.clone(), 0);
-        *self.record.get_mut() = record.try_into()?;
+        *self.fields[0].get_mut() = AwkValue::field_ref(record, 0);
+        *self.record.get_mut() = None;
         *self.last_field.get_mut() = last_field;
         Ok(())
     }
@@ -623,8 +703,8 @@ impl Record {
         // Most other implementations don't really handle this case. Here we just
         // mark it as a numeric string if appropriate
         let record_str = maybe_numeric_string(new_record);
-        *self.fields[0].get() = AwkValue::field_ref(record_str.clone(), 0);
-        *self.record.borrow_mut() = record_str.try_into()?;
+        *self.fields[0].get() = AwkValue::field_ref(record_str, 0);
+        *self.record.borrow_mut() = None;
         *self.last_field.borrow_mut() = last_field;
         Ok(())
     }
@@ -636,19 +716,35 @@ impl Record {
         let record_str = (*self.fields[0].get())
             .to_owned()
             .scalar_to_string(&global_env.convfmt)?;
-        split_record(record_str.clone(), &global_env.fs, |i, s| {
+        split_record_fields(&record_str, global_env, |i, s| {
             let field_index = i + 1;
             last_field += 1;
             *self.fields[field_index].get() = AwkValue::field_ref(s, field_index as u16);
             Ok(())
         })
         .expect("error splitting record");
-        *self.fields[0].get() = AwkValue::field_ref(record_str.clone(), 0);
-        *self.record.borrow_mut() = record_str.try_into()?;
+        *self.fields[0].get() = AwkValue::field_ref(record_str, 0);
+        *self.record.borrow_mut() = None;
         *self.last_field.borrow_mut() = last_field;
         Ok(())
     }
 
+    /// returns `$0` as a C string
+    fn as_cstring(&self) -> Result<Ref<'_, CString>, String> {
+        if self.record.borrow().is_none() {
+            // the record is always a string
+            let record = match unsafe { &(*self.fields[0].get()).value } {
+                AwkValueVariant::String(record) => record.as_str(),
+                _ => "",
+            };
+            let record = CString::new(record).map_err(|_| "invalid string".to_string())?;
+            *self.record.borrow_mut() = Some(record);
+        }
+        Ok(Ref::map(self.record.borrow(), |record| {
+            record.as_ref().expect("record was converted")
+        }))
+    }
+
     fn get_last_field(&self) -> usize {
         *self.last_field.borrow()
     }
@@ -664,7 +760,7 @@ impl Default for Record {
             })
             .collect();
         Self {
-            record: CString::default().into(),
+            record: None.into(),
             fields,
             last_field: 0.into(),
         }
@@ -810,11 +906,14 @@ impl AwkValue {
         Self { ref_type, ..self }
     }
 
-    fn into_ere(self) -> Result<Rc<Regex>, String> {
-        match self.value {
-            AwkValueVariant::Regex { ere, .. } => Ok(ere),
-            _ => Err("expected extended regular expression".to_string()),
+    /// the regex for a regex literal, or for any other scalar the dynamic regex
+    /// given by its string value
+    fn into_ere(self, convfmt: &str) -> Result<Rc<Regex>, String> {
+        if let AwkValueVariant::Regex { ere, .. } = &self.value {
+            return Ok(ere.clone());
         }
+        let string = self.scalar_to_string(convfmt)?;
+        Ok(Rc::new(Regex::new(string.try_into()?)?))
     }
 
     fn uninitialized() -> Self {
@@ -1105,6 +1204,23 @@ impl<'i, 's> Stack<'i, 's> {
         unsafe { &mut *self.pop().expect("empty stack").unwrap_ptr() }
     }
 
+    /// pops a value used as an array. Scalars that were passed by value, like
+    /// the result of an expression given as a function argument, are not lvalues,
+    /// so they can't be converted to an array
+    fn pop_array_ref(&mut self) -> Result<*mut AwkValue, String> {
+        match self.pop().expect("empty stack") {
+            StackValue::Value(_) => Err("scalar used in array context".to_string()),
+            // safe by type invariance
+            value => Ok(unsafe { value.unwrap_ptr() }),
+        }
+    }
+
+    fn pop_array(&mut self) -> Result<&mut Array, String> {
+        let array = self.pop_array_ref()?;
+        // safe by type invariance
+        unsafe { &mut *array }.as_array()
+    }
+
     fn push_value<V: Into<AwkValue>>(&mut self, value: V) -> Result<(), String> {
         // a `StackValue::Value` is always valid, so this is safe
         unsafe { self.push(StackValue::from(value.into())) }
@@ -1234,6 +1350,12 @@ macro_rules! compare_op {
             (AwkValueVariant::UninitializedScalar, AwkValueVariant::Number(rhs)) => {
                 $stack.push_value(bool_to_f64(0.0 $op *rhs))?;
             }
+            (AwkValueVariant::String(lhs), AwkValueVariant::UninitializedScalar) if lhs.is_numeric => {
+                $stack.push_value(bool_to_f64(strtod(lhs) $op 0.0))?;
+            }
+            (AwkValueVariant::UninitializedScalar, AwkValueVariant::String(rhs)) if rhs.is_numeric => {
+                $stack.push_value(bool_to_f64(0.0 $op strtod(rhs)))?;
+            }
             (AwkValueVariant::String(s), AwkValueVariant::Number(x)) if s.is_numeric => {
                 $stack.push_value(bool_to_f64(lhs.scalar_as_f64() $op *x))?;
             }
@@ -1259,7 +1381,22 @@ fn stack_trace(error: String, stack: Stack) -> String {
         error_location.column
     )
     .expect("error writing to string");
-    for frame in stack.call_frames.iter().rev() {
+    let frames_count = stack.call_frames.len();
+    for (i, frame) in stack.call_frames.iter().rev().enumerate() {
+        // the innermost frame was already printed above
+        if frames_count > 2 * CALL_TRACE_ENDS
+            && (CALL_TRACE_ENDS - 1..frames_count - CALL_TRACE_ENDS).contains(&i)
+        {
+            if i == CALL_TRACE_ENDS - 1 {
+                writeln!(
+                    result,
+                    "   ... {} more calls",
+                    frames_count + 1 - 2 * CALL_TRACE_ENDS
+                )
+                .expect("error writing to string");
+            }
+            continue;
+        }
         let source_location = frame.source_locations[frame.ip as usize];
         writeln!(
             result,
@@ -1280,10 +1417,10 @@ impl Interpreter {
         record: &mut Record,
         stack: &mut [StackValue],
         global_env: &mut GlobalEnv,
-        current_file: &mut dyn RecordReader,
+        main_input: &mut MainInput,
     ) -> Result<ExecutionResult, String> {
         let mut stack = Stack::new(action, stack);
-        match self.run_internal(functions, record, &mut stack, global_env, current_file) {
+        match self.run_internal(functions, record, &mut stack, global_env, main_input) {
             Err(err) => Err(stack_trace(err, stack)),
             Ok(result) => Ok(result),
         }
@@ -1295,7 +1432,7 @@ impl Interpreter {
         record: &Record,
         stack: &mut Stack<'a, 'a>,
         global_env: &mut GlobalEnv,
-        current_file: &mut dyn RecordReader,
+        main_input: &mut MainInput,
     ) -> Result<ExecutionResult, String> {
         // # Safety
         // To meat the requirements of stacked borrows (as checked by miri),
@@ -1345,7 +1482,7 @@ impl Interpreter {
                     compare_op!(stack, &global_env.convfmt, !=);
                 }
                 OpCode::Match => {
-                    let ere = stack.pop_value().into_ere()?;
+                    let ere = stack.pop_scalar_value()?.into_ere(&global_env.convfmt)?;
                     let string = stack
                         .pop_scalar_value()?
                         .scalar_to_string(&global_env.convfmt)?;
@@ -1366,7 +1503,7 @@ impl Interpreter {
                     let key = stack
                         .pop_scalar_value()?
                         .scalar_to_string(&global_env.convfmt)?;
-                    let array = stack.pop_ref().as_array()?;
+                    let array = stack.pop_array()?;
                     let result = array.contains(&key);
                     stack.push_value(bool_to_f64(result))?;
                 }
@@ -1484,8 +1621,8 @@ impl Interpreter {
                     let key = stack
                         .pop_scalar_value()?
                         .scalar_to_string(&global_env.convfmt)?;
-                    let array = stack.pop_ref().as_array()?;
-                    let element = array.get_value(key.into())?.clone();
+                    let array = stack.pop_array()?;
+                    let element = array.get_value(key.into()).clone();
                     stack.push_value(element)?
                 }
                 OpCode::GlobalScalarRef(index) => unsafe {
@@ -1510,11 +1647,11 @@ impl Interpreter {
                     let key = stack
                         .pop_scalar_value()?
                         .scalar_to_string(&global_env.convfmt)?;
-                    let array = unsafe { stack.pop().expect("empty stack").unwrap_ptr() };
+                    let array = stack.pop_array_ref()?;
                     // safe by type invariance
                     let value_index = unsafe { &mut *array }
                         .as_array()?
-                        .get_value_index(key.into())?;
+                        .get_value_index(key.into());
                     // array is valid at least until this stack value is popped by stack invariance,
                     // so this is safe
                     unsafe {
@@ -1535,11 +1672,11 @@ impl Interpreter {
                     let key = stack
                         .pop_scalar_value()?
                         .scalar_to_string(&global_env.convfmt)?;
-                    let array = stack.pop_ref().as_array()?;
+                    let array = stack.pop_array()?;
                     array.delete(&key);
                 }
                 OpCode::ClearArray => {
-                    let array = stack.pop_ref().as_array()?;
+                    let array = stack.pop_array()?;
                     array.clear();
                 }
                 OpCode::JumpIfFalse(offset) => {
@@ -1593,7 +1730,15 @@ impl Interpreter {
                             BuiltinFunction::RedirectedPrintfAppend
                                 | BuiltinFunction::RedirectedPrintAppend
                         );
-                        self.write_files.write(&filename, &str, is_append)?;
+                        match filename.as_str() {
+                            // written to directly, to keep them in order with the rest of the output
+                            "/dev/stdout" => write_to_stdout(&str)?,
+                            "/dev/stderr" => {
+                                flush_stdout()?;
+                                eprint!("{}", str.as_str());
+                            }
+                            _ => self.write_files.write(&filename, &str, is_append)?,
+                        }
                     }
                     BuiltinFunction::RedirectedPrintPipe
                     | BuiltinFunction::RedirectedPrintfPipe => {
@@ -1605,47 +1750,67 @@ impl Interpreter {
                         } else {
                             builtin_sprintf(stack, argc - 1, global_env)?
                         };
+                        // the command may write to the same output as awk
+                        flush_stdout()?;
                         self.write_pipes.write(command, str)?;
                     }
                     BuiltinFunction::Close => {
                         let filename = stack
                             .pop_scalar_value()?
                             .scalar_to_string(&global_env.convfmt)?;
-                        self.write_files.close_file(&filename);
-                        self.read_files.close_file(&filename);
-                        self.write_pipes.close_pipe(&filename);
-                        self.read_pipes.close_pipe(&filename);
+                        // a file and a pipe, or an output and an input stream, can have the same name,
+                        // so all of them are closed
+                        flush_stdout()?;
+                        let status = [
+                            self.write_files.close_file(&filename),
+                            self.read_files.close_file(&filename),
+                            self.write_pipes.close_pipe(&filename),
+                            self.read_pipes.close_pipe(&filename),
+                        ]
+                        .into_iter()
+                        .flatten()
+                        .next()
+                        .unwrap_or(-1);
+                        stack.push_value(status as f64)?;
                     }
                     BuiltinFunction::FFlush => {
-                        let expr_str = if argc == 1 {
+                        let name = if argc == 1 {
                             stack
                                 .pop_scalar_value()?
                                 .scalar_to_string(&global_env.convfmt)?
                         } else {
                             AwkString::default()
                         };
-                        let result = if expr_str.is_empty() {
-                            self.write_files.flush_file(&expr_str)
-                                && self.write_pipes.flush_file(&expr_str)
-                        } else {
+                        // `fflush()` and `fflush("")` flush all the output
+                        let success = if name.is_empty() {
+                            flush_stdout()?;
                             self.write_files.flush_all() && self.write_pipes.flush_all()
+                        } else if name.as_str() == "/dev/stdout" {
+                            flush_stdout()?;
+                            true
+                        } else {
+                            match (
+                                self.write_files.flush_file(&name),
+                                self.write_pipes.flush_file(&name),
+                            ) {
+                                (None, None) => false,
+                                (file, pipe) => file.unwrap_or(true) && pipe.unwrap_or(true),
+                            }
                         };
-                        stack.push_value(bool_to_f64(result))?;
+                        stack.push_value(if success { 0.0 } else { -1.0 })?;
                     }
                     BuiltinFunction::GetLine => {
+                        // NR and FNR are updated by `main_input`
+                        let next_record = main_input.read_next_record(&self.globals, global_env);
                         let var = stack.pop_ref();
-                        if let Some(next_record) = current_file.read_next_record(&global_env.rs)? {
-                            fields_state =
-                                var.assign(maybe_numeric_string(next_record), global_env)?;
-                            // borrowing `self.globas` mutably here breaks the stacked borrows rules
-                            // so we have to use unsafe code to get around that
-                            let nr = unsafe { &mut *self.globals[SpecialVar::Nr as usize].get() };
-                            nr.assign(global_env.nr as f64 + 1.0, global_env)?;
-                            let fnr = unsafe { &mut *self.globals[SpecialVar::Fnr as usize].get() };
-                            fnr.assign(global_env.fnr as f64 + 1.0, global_env)?;
-                            stack.push_value(1.0)?;
-                        } else {
-                            stack.push_value(0.0)?;
+                        match next_record {
+                            Ok(Some(next_record)) => {
+                                fields_state =
+                                    var.assign(maybe_numeric_string(next_record), global_env)?;
+                                stack.push_value(1.0)?;
+                            }
+                            Ok(None) => stack.push_value(0.0)?,
+                            Err(_) => stack.push_value(-1.0)?,
                         }
                     }
                     BuiltinFunction::GetLineFromFile | BuiltinFunction::GetLineFromPipe => {
@@ -1654,16 +1819,28 @@ impl Interpreter {
                             .scalar_to_string(&global_env.convfmt)?;
                         let var = stack.pop_ref();
                         let maybe_next_record = if function == BuiltinFunction::GetLineFromFile {
-                            self.read_files.read_next_record(filename, &global_env.rs)?
+                            self.read_files.read_next_record(filename, &global_env.rs)
                         } else {
-                            self.read_pipes.read_next_record(filename, &global_env.rs)?
+                            self.read_pipes.read_next_record(filename, &global_env.rs)
                         };
-                        if let Some(next_record) = maybe_next_record {
-                            fields_state =
-                                var.assign(maybe_numeric_string(next_record), global_env)?;
-                            stack.push_value(1.0)?;
-                        } else {
-                            stack.push_value(0.0)?;
+                        match maybe_next_record {
+                            Ok(Some(next_record)) => {
+                                fields_state =
+                                    var.assign(maybe_numeric_string(next_record), global_env)?;
+                                // records read from a command count as input records, unlike those
+                                // read from a file
+                                if function == BuiltinFunction::GetLineFromPipe {
+                                    // borrowing `self.globals` mutably here breaks the stacked borrows rules
+                                    // so we have to use unsafe code to get around that
+                                    let nr = unsafe {
+                                        &mut *self.globals[SpecialVar::Nr as usize].get()
+                                    };
+                                    nr.assign(global_env.nr as f64 + 1.0, global_env)?;
+                                }
+                                stack.push_value(1.0)?;
+                            }
+                            Ok(None) => stack.push_value(0.0)?,
+                            Err(_) => stack.push_value(-1.0)?,
                         }
                     }
                     BuiltinFunction::Rand => {
@@ -1689,7 +1866,7 @@ impl Interpreter {
                     Constant::Number(num) => stack.push_value(num)?,
                     Constant::String(s) => stack.push_value(AwkString::from(s))?,
                     Constant::Regex(ere) => {
-                        stack.push_value(AwkValue::from_ere(ere, &record.record.borrow()))?
+                        stack.push_value(AwkValue::from_ere(ere, &*record.as_cstring()?))?
                     }
                 },
                 OpCode::PushOne => {
@@ -1799,7 +1976,7 @@ impl Interpreter {
             AwkValue::from("\n".to_string()).into_ref(AwkRefType::SpecialGlobalVar(SpecialVar::Rs));
         *globals[SpecialVar::Rstart as usize].get_mut() =
             AwkValue::from(0.0).into_ref(AwkRefType::SpecialGlobalVar(SpecialVar::Rstart));
-        *globals[SpecialVar::Subsep as usize].get_mut() = AwkValue::from(" ".to_string())
+        *globals[SpecialVar::Subsep as usize].get_mut() = AwkValue::from("\x1c".to_string())
             .into_ref(AwkRefType::SpecialGlobalVar(SpecialVar::Subsep));
 
         Self {
@@ -1813,6 +1990,16 @@ impl Interpreter {
             rng: SmallRng::seed_from_u64(0),
         }
     }
+
+    /// writes out the buffered output when the program ends. Pipes are flushed when
+    /// they are closed on drop
+    fn flush_output(&mut self) -> Result<(), String> {
+        flush_stdout()?;
+        if !self.write_files.flush_all() {
+            return Err("failed to write to an output file".to_string());
+        }
+        Ok(())
+    }
 }
 
 fn is_valid_variable(s: &str) -> bool {
@@ -1837,18 +2024,135 @@ fn set_globals_with_assignment_arguments(
     global_env: &mut GlobalEnv,
     assignments: &[String],
 ) -> Result<(), String> {
-    assignments
-        .iter()
-        .filter_map(|s| parse_assignment(s))
-        .filter_map(|(var, value)| globals.get(var).copied().map(|index| (index, value)))
-        .try_for_each(|(global_index, value)| {
-            let value = escape_string_contents(value)?;
+    for assignment in assignments {
+        let (var, value) = parse_assignment(assignment)
+            .ok_or_else(|| format!("invalid variable assignment '{}'", assignment))?;
+        // variables the program doesn't use don't exist
+        if let Some(&global_index) = globals.get(var) {
             interpreter.globals[global_index as usize]
                 .get_mut()
-                .assign(maybe_numeric_string(value), global_env)
-                .expect("failed to assign value");
-            Ok(())
-        })
+                .assign(
+                    maybe_numeric_string(escape_command_line_value(value)),
+                    global_env,
+                )?;
+        }
+    }
+    Ok(())
+}
+
+/// The input of the rules and of a plain `getline`: the files named by the
+/// operands in `ARGV`, read one after the other, or the standard input if there
+/// are none. The operands that are assignments are performed when they are reached.
+struct MainInput<'a> {
+    program_globals: &'a HashMap<String, u32>,
+    next_arg_index: usize,
+    input_read: bool,
+    reader: Option<RecordReader>,
+}
+
+impl<'a> MainInput<'a> {
+    fn new(program_globals: &'a HashMap<String, u32>) -> Self {
+        Self {
+            program_globals,
+            next_arg_index: 1,
+            input_read: false,
+            reader: None,
+        }
+    }
+
+    /// reads the next record, continuing with the next file when the current one
+    /// ends, and updates `NR` and `FNR`.
+    /// # Returns
+    /// `None` once all the input has been read
+    fn read_next_record(
+        &mut self,
+        globals: &[AwkValueRef],
+        global_env: &mut GlobalEnv,
+    ) -> Result<Option<String>, String> {
+        loop {
+            if let Some(reader) = &mut self.reader {
+                if let Some(record) = reader.read_next_record(&global_env.rs)? {
+                    global_env.nr += 1;
+                    global_env.fnr += 1;
+                    // the stack can hold pointers to the globals, so they
+                    // cannot be borrowed mutably
+                    unsafe {
+                        (*globals[SpecialVar::Nr as usize].get()).value =
+                            AwkValue::from(global_env.nr as f64).value;
+                        (*globals[SpecialVar::Fnr as usize].get()).value =
+                            AwkValue::from(global_env.fnr as f64).value;
+                    }
+                    return Ok(Some(record));
+                }
+                self.reader = None;
+            }
+            if !self.open_next_file(globals, global_env)? {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// stops reading the current file, as for `nextfile`
+    fn skip_file(&mut self) {
+        self.reader = None;
+    }
+
+    fn open_next_file(
+        &mut self,
+        globals: &[AwkValueRef],
+        global_env: &mut GlobalEnv,
+    ) -> Result<bool, String> {
+        loop {
+            // the globals are accessed through pointers for the same reason
+            // as in `read_next_record`
+            let argc =
+                unsafe { &*globals[SpecialVar::Argc as usize].get() }.scalar_as_f64() as usize;
+
+            let arg = if self.next_arg_index >= argc {
+                if self.input_read {
+                    return Ok(false);
+                } else {
+                    "-".into()
+                }
+            } else {
+                // elements of ARGV can be deleted, and are then skipped
+                match unsafe { &mut *globals[SpecialVar::Argv as usize].get() }
+                    .as_array()?
+                    .get(&self.next_arg_index.to_string())
+                {
+                    Some(arg) => arg.clone().scalar_to_string(&global_env.convfmt)?,
+                    None => AwkString::default(),
+                }
+            };
+            self.next_arg_index += 1;
+
+            if arg.is_empty() {
+                continue;
+            }
+
+            if let Some((var, value)) = parse_assignment(&arg) {
+                if let Some(&global_index) = self.program_globals.get(var) {
+                    unsafe { &mut *globals[global_index as usize].get() }.assign(
+                        maybe_numeric_string(escape_command_line_value(value)),
+                        global_env,
+                    )?;
+                }
+                continue;
+            }
+
+            unsafe { &mut *globals[SpecialVar::Filename as usize].get() }.value =
+                AwkValueVariant::String(maybe_numeric_string(arg.clone()));
+
+            self.reader = Some(if arg.as_str() == "-" {
+                RecordReader::stdin()
+            } else {
+                RecordReader::open_file(&arg)?
+            });
+            self.input_read = true;
+            global_env.fnr = 0;
+            return Ok(true);
+        }
+    }
 }
 
 pub fn interpret(
@@ -1866,8 +2170,13 @@ pub fn interpret(
         }))
         .collect();
 
-    let env = std::env::vars()
-        .map(|(k, v)| (k, maybe_numeric_string(v)))
+    let env = std::env::vars_os()
+        .map(|(k, v)| {
+            (
+                k.to_string_lossy().into_owned(),
+                maybe_numeric_string(v.to_string_lossy().as_ref()),
+            )
+        })
         .collect();
 
     let mut stack = iter::repeat_with(|| StackValue::Invalid)
@@ -1889,9 +2198,14 @@ pub fn interpret(
     if let Some(separator) = separator {
         interpreter.globals[SpecialVar::Fs as usize]
             .get_mut()
-            .assign(AwkString::from(separator), &mut global_env)?;
+            .assign(
+                AwkString::from(escape_command_line_value(&separator)),
+                &mut global_env,
+            )?;
     }
 
+    let mut main_input = MainInput::new(&program.globals);
+
     for action in program.begin_actions {
         let begin_result = interpreter.run(
             &action,
@@ -1899,7 +2213,7 @@ pub fn interpret(
             &mut current_record,
             &mut stack,
             &mut global_env,
-            &mut EmptyRecordReader::default(),
+            &mut main_input,
         )?;
         if let ExecutionResult::Exit(val) = begin_result {
             return_value = val;
@@ -1908,148 +2222,85 @@ pub fn interpret(
     }
 
     if program.rules.is_empty() && program.end_actions.is_empty() {
+        interpreter.flush_output()?;
         return Ok(return_value);
     }
 
-    let mut current_arg_index = 1;
-    let mut input_read = false;
-    'file_loop: loop {
-        let argc = interpreter.globals[SpecialVar::Argc as usize]
-            .get_mut()
-            .scalar_as_f64() as usize;
-
-        let arg = if current_arg_index >= argc {
-            if input_read {
-                break;
-            } else {
-                "-".into()
-            }
-        } else {
-            interpreter.globals[SpecialVar::Argv as usize]
-                .get_mut()
-                .as_array()
-                .expect("ARGV is not an array")
-                .get_value(current_arg_index.to_string().into())
-                // there cannot be active iterators at this point, so this is safe
-                .unwrap()
-                .clone()
-                .scalar_to_string(&global_env.convfmt)?
-        };
-
-        if arg.is_empty() {
-            current_arg_index += 1;
-            continue;
-        }
-
-        if let Some((var, value)) = parse_assignment(&arg) {
-            if let Some(&global_index) = program.globals.get(var) {
-                interpreter.globals[global_index as usize]
-                    .get_mut()
-                    .assign(
-                        maybe_numeric_string(escape_string_contents(value)?),
-                        &mut global_env,
-                    )?;
-            }
-            current_arg_index += 1;
-            continue;
-        }
-
-        interpreter.globals[SpecialVar::Filename as usize]
-            .get_mut()
-            .value = AwkValueVariant::String(maybe_numeric_string(arg.clone()));
-
-        let reader: &mut dyn RecordReader = if arg.as_str() == "-" {
-            &mut StdinRecordReader::default()
-        } else {
-            &mut FileStream::open(&arg)?
-        };
-
-        // at this point we know that some input will be read
-        input_read = true;
-
-        global_env.fnr = 1;
-        'record_loop: while let Some(record) = reader.read_next_record(&global_env.rs)? {
-            current_record.reset(record, &global_env.fs)?;
-            interpreter.globals[SpecialVar::Nf as usize].get_mut().value =
-                AwkValue::from(current_record.get_last_field() as f64).value;
-            global_env.nf = current_record.get_last_field();
-
-            interpreter.globals[SpecialVar::Fnr as usize]
-                .get_mut()
-                .value = AwkValue::from(global_env.fnr as f64).value;
-            interpreter.globals[SpecialVar::Nr as usize].get_mut().value =
-                AwkValue::from(global_env.nr as f64).value;
-
-            for (i, rule) in program.rules.iter().enumerate() {
-                let should_execute = match &rule.pattern {
-                    Pattern::All => true,
-                    Pattern::Expr(expr) => interpreter
-                        .run(
-                            expr,
-                            &program.functions,
-                            &mut current_record,
-                            &mut stack,
-                            &mut global_env,
-                            reader,
-                        )?
-                        .expr_to_bool(),
-                    Pattern::Range { start, end } => {
-                        if range_pattern_started[i] {
-                            let should_end = !interpreter
-                                .run(
-                                    end,
-                                    &program.functions,
-                                    &mut current_record,
-                                    &mut stack,
-                                    &mut global_env,
-                                    reader,
-                                )?
-                                .expr_to_bool();
-                            range_pattern_started[i] = should_end;
-                            // range is inclusive
-                            true
-                        } else {
-                            let should_start = interpreter
-                                .run(
-                                    start,
-                                    &program.functions,
-                                    &mut current_record,
-                                    &mut stack,
-                                    &mut global_env,
-                                    reader,
-                                )?
-                                .expr_to_bool();
-                            range_pattern_started[i] = should_start;
-                            should_start
-                        }
-                    }
-                };
-                if should_execute {
-                    let rule_result = interpreter.run(
-                        &rule.action,
+    'record_loop: while let Some(record) =
+        main_input.read_next_record(&interpreter.globals, &mut global_env)?
+    {
+        current_record.reset(record, &global_env)?;
+        interpreter.globals[SpecialVar::Nf as usize].get_mut().value =
+            AwkValue::from(current_record.get_last_field() as f64).value;
+        global_env.nf = current_record.get_last_field();
+
+        for (i, rule) in program.rules.iter().enumerate() {
+            let should_execute = match &rule.pattern {
+                Pattern::All => true,
+                Pattern::Expr(expr) => interpreter
+                    .run(
+                        expr,
                         &program.functions,
                         &mut current_record,
                         &mut stack,
                         &mut global_env,
-                        reader,
-                    )?;
-                    match rule_result {
-                        ExecutionResult::Next => break,
-                        ExecutionResult::NextFile => break 'record_loop,
-                        ExecutionResult::Exit(val) => {
-                            return_value = val;
-                            break 'file_loop;
-                        }
-                        ExecutionResult::Expression(_) => {}
+                        &mut main_input,
+                    )?
+                    .expr_to_bool(),
+                Pattern::Range { start, end } => {
+                    if range_pattern_started[i] {
+                        let should_end = !interpreter
+                            .run(
+                                end,
+                                &program.functions,
+                                &mut current_record,
+                                &mut stack,
+                                &mut global_env,
+                                &mut main_input,
+                            )?
+                            .expr_to_bool();
+                        range_pattern_started[i] = should_end;
+                        // range is inclusive
+                        true
+                    } else {
+                        let should_start = interpreter
+                            .run(
+                                start,
+                                &program.functions,
+                                &mut current_record,
+                                &mut stack,
+                                &mut global_env,
+                                &mut main_input,
+                            )?
+                            .expr_to_bool();
+                        range_pattern_started[i] = should_start;
+                        should_start
+                    }
+                }
+            };
+            if should_execute {
+                let rule_result = interpreter.run(
+                    &rule.action,
+                    &program.functions,
+                    &mut current_record,
+                    &mut stack,
+                    &mut global_env,
+                    &mut main_input,
+                )?;
+                match rule_result {
+                    ExecutionResult::Next => break,
+                    ExecutionResult::NextFile => {
+                        main_input.skip_file();
+                        break;
+                    }
+                    ExecutionResult::Exit(val) => {
+                        return_value = val;
+                        break 'record_loop;
                     }
+                    ExecutionResult::Expression(_) => {}
                 }
             }
-
-            global_env.fnr += 1;
-            global_env.nr += 1;
         }
-
-        current_arg_index += 1;
     }
 
     for action in program.end_actions {
@@ -2059,7 +2310,7 @@ pub fn interpret(
             &mut current_record,
             &mut stack,
             &mut global_env,
-            &mut EmptyRecordReader::default(),
+            &mut main_input,
         )?;
         if let ExecutionResult::Exit(val) = end_result {
             return_value = val;
@@ -2067,6 +2318,7 @@ pub fn interpret(
         }
     }
 
+    interpreter.flush_output()?;
     Ok(return_value)
 }
 
@@ -2120,7 +2372,7 @@ mod tests {
 
         fn add_record(mut self, record_string: &str) -> Self {
             self.record
-                .reset(record_string.to_string(), &FieldSeparator::Default)
+                .reset(record_string.to_string(), &GlobalEnv::default())
                 .expect("could not split record");
             self
         }
@@ -2147,7 +2399,7 @@ mod tests {
                     &mut self.record,
                     &mut stack,
                     &mut GlobalEnv::default(),
-                    &mut EmptyRecordReader::default(),
+                    &mut MainInput::new(&HashMap::new()),
                 )
                 .expect("execution generated an error");
 
@@ -2345,6 +2597,28 @@ mod tests {
         assert_eq!(interpret_expr(instructions, constant), AwkValue::from(1.0));
     }
 
+    #[test]
+    fn test_maybe_numeric_string() {
+        for numeric in ["1", " 1 ", "-1.5", "+.5e3", "\t10\n"] {
+            assert!(maybe_numeric_string(numeric).is_numeric, "{:?}", numeric);
+        }
+        for not_numeric in ["", " ", "+", "+-1", "1 2", "1x", "0x1A", "inf"] {
+            assert!(
+                !maybe_numeric_string(not_numeric).is_numeric,
+                "{:?}",
+                not_numeric
+            );
+        }
+    }
+
+    #[test]
+    fn test_integral_numbers_outside_i64_are_not_integers() {
+        assert!(is_integer(-(2.0f64.powi(63))));
+        assert!(!is_integer(2.0f64.powi(63)));
+        assert!(!is_integer(f64::INFINITY));
+        assert!(!is_integer(f64::NAN));
+    }
+
     #[test]
     fn test_interpret_in_for_global_array() {
         let instructions = vec![
@@ -3051,6 +3325,71 @@ mod tests {
         assert_eq!(test_sprintf("%c", vec![Constant::Number(548.0)]), "$");
     }
 
+    #[test]
+    fn test_builtin_sprintf_char_from_string() {
+        assert_eq!(test_sprintf("%c", vec![Constant::from("hello")]), "h");
+        assert_eq!(test_sprintf("%c", vec![Constant::from("")]), "");
+        assert_eq!(
+            test_sprintf(
+                "%3c|%-3c",
+                vec![Constant::from("x"), Constant::Number(66.0)]
+            ),
+            "  x|B  "
+        );
+    }
+
+    #[test]
+    fn test_builtin_sprintf_width_and_precision_from_args() {
+        assert_eq!(
+            test_sprintf("%*d", vec![Constant::Number(5.0), Constant::Number(42.0)]),
+            "   42"
+        );
+        assert_eq!(
+            test_sprintf("%*d|", vec![Constant::Number(-5.0), Constant::Number(42.0)]),
+            "42   |"
+        );
+        assert_eq!(
+            test_sprintf(
+                "%.*f",
+                vec![Constant::Number(2.0), Constant::Number(2.34567)]
+            ),
+            "2.35"
+        );
+        assert_eq!(
+            test_sprintf(
+                "%*.*s|",
+                vec![
+                    Constant::Number(6.0),
+                    Constant::Number(3.0),
+                    Constant::from("abcdef")
+                ]
+            ),
+            "   abc|"
+        );
+        assert_eq!(
+            test_sprintf("%.*s", vec![Constant::Number(-1.0), Constant::from("abc")]),
+            "abc"
+        );
+    }
+
+    #[test]
+    fn test_builtin_sprintf_unsigned_negative_value() {
+        assert_eq!(
+            test_sprintf("%x", vec![Constant::Number(-1.0)]),
+            "ffffffffffffffff"
+        );
+        assert_eq!(
+            test_sprintf("%u", vec![Constant::Number(-1.0)]),
+            "18446744073709551615"
+        );
+    }
+
+    #[test]
+    fn test_builtin_sprintf_string_precision_counts_characters() {
+        assert_eq!(test_sprintf("%.2s", vec![Constant::from("éèà")]), "éè");
+        assert_eq!(test_sprintf("%3s", vec![Constant::from("é")]), "  é");
+    }
+
     #[test]
     #[cfg_attr(miri, ignore)]
     fn test_match_op() {
@@ -47,40 +47,75 @@ pub struct RegexMatch {
     pub end: usize,
 }
 
+/// Iterator over the non-overlapping matches of a regex in a string, from left to right.
+/// Empty matches are included, except for one that immediately follows a non-empty match,
+/// so that `/b*/` matches "abc" before 'a', at "b" and after 'c'.
 pub struct MatchIter<'re> {
     string: CString,
     next_start: usize,
+    // the end of the last match, if it was not empty
+    last_match_end: Option<usize>,
     regex: &'re Regex,
 }
 
+/// the length of the UTF-8 sequence that starts with `byte`
+fn utf8_sequence_len(byte: u8) -> usize {
+    match byte {
+        0xF0.. => 4,
+        0xE0.. => 3,
+        0xC0.. => 2,
+        _ => 1,
+    }
+}
+
 impl Iterator for MatchIter<'_> {
     type Item = RegexMatch;
     fn next(&mut self) -> Option<Self::Item> {
-        if self.next_start >= self.string.as_bytes().len() {
-            return None;
+        let bytes = self.string.as_bytes();
+        while self.next_start <= bytes.len() {
+            let mut match_range = libc::regmatch_t {
+                rm_so: -1,
+                rm_eo: -1,
+            };
+            // the search starts in the middle of the string, so it is not at the
+            // beginning of a line
+            let flags = if self.next_start > 0 {
+                libc::REG_NOTBOL
+            } else {
+                0
+            };
+            let exec_status = unsafe {
+                libc::regexec(
+                    ptr::from_ref(&self.regex.raw_regex),
+                    self.string.as_ptr().add(self.next_start),
+                    1,
+                    ptr::from_mut(&mut match_range),
+                    flags,
+                )
+            };
+            if exec_status == libc::REG_NOMATCH {
+                self.next_start = bytes.len() + 1;
+                return None;
+            }
+            let result = RegexMatch {
+                start: self.next_start + match_range.rm_so as usize,
+                end: self.next_start + match_range.rm_eo as usize,
+            };
+            if result.start == result.end {
+                // the next search starts after the next character, so that the same
+                // empty match is not found again
+                self.next_start =
+                    result.start + bytes.get(result.start).map_or(1, |b| utf8_sequence_len(*b));
+                if self.last_match_end.take() == Some(result.start) {
+                    continue;
+                }
+            } else {
+                self.next_start = result.end;
+                self.last_match_end = Some(result.end);
+            }
+            return Some(result);
         }
-        let mut match_range = libc::regmatch_t {
-            rm_so: -1,
-            rm_eo: -1,
-        };
-        let exec_status = unsafe {
-            libc::regexec(
-                ptr::from_ref(&self.regex.raw_regex),
-                self.string.as_ptr().add(self.next_start),
-                1,
-                ptr::from_mut(&mut match_range),
-                0,
-            )
-        };
-        if exec_status == libc::REG_NOMATCH {
-            return None;
-        }
-        let result = RegexMatch {
-            start: self.next_start + match_range.rm_so as usize,
-            end: self.next_start + match_range.rm_eo as usize,
-        };
-        self.next_start += match_range.rm_eo as usize;
-        Some(result)
+        None
     }
 }
 
@@ -99,6 +134,7 @@ impl Regex {
     pub fn match_locations(&self, string: CString) -> MatchIter {
         MatchIter {
             next_start: 0,
+            last_match_end: None,
             regex: self,
             string,
         }
@@ -172,4 +208,33 @@ mod tests {
         assert_eq!(iter.next(), Some(RegexMatch { start: 24, end: 29 }));
         assert_eq!(iter.next(), None);
     }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_regex_match_locations_with_empty_matches() {
+        let ere = regex_from_str("b*");
+        let mut iter = ere.match_locations(CString::new("abc").unwrap());
+        assert_eq!(iter.next(), Some(RegexMatch { start: 0, end: 0 }));
+        assert_eq!(iter.next(), Some(RegexMatch { start: 1, end: 2 }));
+        assert_eq!(iter.next(), Some(RegexMatch { start: 3, end: 3 }));
+        assert_eq!(iter.next(), None);
+
+        let ere = regex_from_str("x*");
+        let iter = ere.match_locations(CString::new("").unwrap());
+        assert_eq!(
+            iter.collect::<Vec<_>>(),
+            vec![RegexMatch { start: 0, end: 0 }]
+        );
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_regex_match_locations_anchored_at_start() {
+        let ere = regex_from_str("^a");
+        let iter = ere.match_locations(CString::new("aaa").unwrap());
+        assert_eq!(
+            iter.collect::<Vec<_>>(),
+            vec![RegexMatch { start: 0, end: 1 }]
+        );
+    }
 }
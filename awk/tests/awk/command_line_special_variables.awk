@@ -0,0 +1,12 @@
+FNR == 1 {
+    print "reading " FILENAME
+}
+
+FNR <= 2 {
+    $1 = $1
+    print NF ": " $0
+}
+
+END {
+    print "PATH in ENVIRON:", ("PATH" in ENVIRON)
+}
@@ -0,0 +1,26 @@
+# CONVFMT is used to convert numbers to strings, except by print, which uses
+# OFMT. Integral numbers are always converted as integers
+BEGIN {
+	CONVFMT = "%.2g"
+	OFMT = "%.3f"
+	x = 3.14159
+	print x, x ""
+	printf "%s %s\n", x, x ""
+	a[x] = 1
+	for (key in a)
+		print "subscript:", key
+	print "concatenated:", (x " items")
+	print "integers:", 17, 17 "", -3 "", 1e6 "", 2^53 ""
+	print "out of range:", 2^63 "", -2^64 "", 1e30 ""
+	print "infinite:", 1e300 * 1e300 "", -1e300 * 1e300 ""
+	y = 0.1 + 0.2
+	print "computed:", y, y "", (y "" == 0.3)
+	CONVFMT = "%d"
+	b[2.5] = 1
+	for (key in b)
+		print "integer format:", key
+	CONVFMT = "%.6g"
+	OFMT = "%.6g"
+	print "defaults:", 100 / 3, 100 / 3 "", 1e-5, 123456789.5
+	print "uninitialized:", u "", length(u), u + 0, -u
+}
@@ -0,0 +1,20 @@
+function count(n) {
+	if (n == 0) {
+		return 0;
+	}
+	return 1 + count(n - 1);
+}
+
+function fill(a, n,    i) {
+	for (i = 1; i <= n; i++) {
+		a[i] = i * i;
+	}
+	return n;
+}
+
+BEGIN {
+	print count(10000);
+	i = "global";
+	fill(squares, 5);
+	print i, squares[3], squares[5];
+}
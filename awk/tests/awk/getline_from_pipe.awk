@@ -0,0 +1,13 @@
+BEGIN {
+	"echo first record" | getline;
+	print $0, NF, NR, FNR;
+	"echo second record" | getline var;
+	print var, NF, NR, FNR;
+	while (("printf 'a\\nb\\n'" | getline line) > 0) {
+		print line;
+	}
+	print "status", close("printf 'a\\nb\\n'");
+	"exit 3" | getline;
+	print "status", close("exit 3");
+	print "not open", close("exit 3");
+}
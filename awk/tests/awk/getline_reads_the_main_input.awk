@@ -0,0 +1,16 @@
+BEGIN {
+	getline;
+	print "BEGIN", $1, NR, FNR;
+}
+
+NR == 6 {
+	print FILENAME, $1, NR, FNR;
+	while ((getline line) > 0) {
+		split(line, fields);
+		print FILENAME, fields[1], NR, FNR;
+	}
+}
+
+END {
+	print "END", (getline), NR;
+}
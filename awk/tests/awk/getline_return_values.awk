@@ -0,0 +1,9 @@
+BEGIN {
+	print (getline line < "tests/awk/does_not_exist.txt");
+	print (getline line < "tests/awk/test_data.txt"), line;
+	while ((getline line < "tests/awk/test_data.txt") > 0) {
+		count++;
+	}
+	print (getline line < "tests/awk/test_data.txt"), count;
+	print close("tests/awk/test_data.txt"), close("tests/awk/test_data.txt");
+}
@@ -0,0 +1,19 @@
+BEGIN {
+    # deleted and empty operands are skipped, and added ones are read
+    delete ARGV[1]
+    ARGV[2] = ""
+    ARGV[ARGC++] = "prefix=second:"
+    ARGV[ARGC++] = "tests/awk/test_data.csv"
+    for (i = 0; i < ARGC; i++)
+        if (i in ARGV)
+            n++
+    print n " operands"
+}
+
+FNR == 1 {
+    print prefix, FILENAME
+}
+
+END {
+    print NR " records"
+}
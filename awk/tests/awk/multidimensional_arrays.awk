@@ -0,0 +1,44 @@
+BEGIN {
+    for (i = 1; i <= 3; i++)
+        for (j = 1; j <= 3; j++)
+            m[i, j] = i * j
+
+    if ((2, 3) in m)
+        print "(2, 3) in m:", m[2, 3]
+    if (!((4, 1) in m))
+        print "(4, 1) not in m"
+
+    n = 0
+    for (k in m) {
+        split(k, indices, SUBSEP)
+        sum += indices[1] * indices[2] - m[k]
+        n++
+    }
+    print n, "elements, difference", sum
+
+    delete m[1, 1]
+    delete m[3, 3]
+    print "after delete:", length(m), ((1, 1) in m), ((2, 2) in m)
+
+    # deleting and adding elements while iterating
+    for (k in m) {
+        delete m[k]
+        added[k] = 1
+        m["new" k] = 1
+    }
+    for (k in added)
+        if (k in m)
+            print "not deleted:", k
+    print "after the loop:", length(m), length(added)
+
+    SUBSEP = ":"
+    a["x", "y"] = 1
+    for (k in a) {
+        print k
+        break
+    }
+    delete a
+    print "after delete a:", length(a)
+    for (k in a)
+        print "unexpected", k
+}
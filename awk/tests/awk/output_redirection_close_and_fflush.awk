@@ -0,0 +1,25 @@
+BEGIN {
+    file = "tests/awk/output_redirection_reopen.txt"
+    print "first" > file
+    print "second" > file
+    close(file)
+    # reopening the file with > truncates it again
+    print "third" > file
+    print fflush(file)
+    while ((getline line < file) > 0)
+        print "read:", line
+    close(file)
+    print "appended" >> file
+    close(file)
+    while ((getline line < file) > 0)
+        print "read again:", line
+    close(file)
+    system("rm " file)
+
+    printf "before the pipe "
+    print "b\na" | "sort"
+    print close("sort")
+    print fflush("sort"), fflush(), fflush("")
+    print "to a failing command" | "cat; exit 3"
+    print close("cat; exit 3")
+}
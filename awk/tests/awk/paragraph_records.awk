@@ -0,0 +1,13 @@
+BEGIN { RS = "" }
+
+{
+    printf "record %d has %d fields and %d lines\n", NR, NF, split($0, lines, "\n")
+    print "  name: " lines[1]
+}
+
+NR == 2 {
+    # a newline separates fields also with other field separators
+    FS = ":"
+}
+
+END { print NR " records" }
@@ -0,0 +1,16 @@
+BEGIN {
+    RS = ";+\n*|\n\n+"
+    FS = ", *| +-> +"
+}
+
+{
+    printf "%d:", NR
+    for (i = 1; i <= NF; i++)
+        printf " [%s]", $i
+    print ""
+}
+
+NR == 2 {
+    # a single character is not a regex
+    FS = "|"
+}
@@ -0,0 +1,36 @@
+BEGIN {
+	s = "hello world";
+	n = gsub(/o/, "[&]", s);
+	print n, s;
+	s = "aaa";
+	gsub(/a/, "\\&", s);
+	print s;
+	s = "abc";
+	sub(/b/, "\\\\&", s);
+	print s;
+	s = "abc";
+	print gsub(/x*/, "-", s), s;
+	s = "abc";
+	print gsub(/b*/, "-", s), s;
+	s = "aaa";
+	print gsub(/^a/, "x", s), s;
+	s = "a.b.c";
+	print gsub(".", "-", s), s;
+	s = "a+b";
+	re = "\\+";
+	print gsub(re, "-", s), s;
+
+	print match("foobar", /o+/), RSTART, RLENGTH;
+	print match("foobar", "x"), RSTART, RLENGTH;
+	print match("", //), RSTART, RLENGTH;
+
+	print split("  a b\tc  ", parts), parts[1], parts[3];
+	print split("a:b::c", parts, ":"), parts[3], parts[4];
+	print split("a.b", parts, "."), parts[2];
+	print split(" a  b ", parts, " "), parts[2];
+	print split("a12b3c", parts, /[0-9]+/), parts[2], parts[3];
+	print split("", parts), length(parts);
+
+	s = "hello";
+	print substr(s, 2, 3) "|" substr(s, 0) "|" substr(s, -1, 3) "|" substr(s, 0, 2) "|" substr(s, 4, 100) "|" substr(s, 10) "|" substr(s, 3, -1) "|" substr(s, 1.5, 2.3) "|";
+}
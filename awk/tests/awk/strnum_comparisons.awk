@@ -0,0 +1,51 @@
+# fields, getline input, split elements and command-line values that look like
+# numbers are compared as numbers, other strings are compared as strings
+NR == 1 {
+	print "fields:", ($1 < $2), ($1 > 9), ($1 < "9")
+	x = $1
+	print "copied field:", (x > 9), (x "" > 9), (substr(x, 1) > 9)
+	$2 = "9"
+	print "assigned constant:", ($1 < $2)
+	print "uninitialized:", ($1 > u), (u == 0), (u == ""), (u "" == 0)
+	next
+}
+NR == 2 {
+	print "equal as numbers:", ($1 == $2), ($1 "" == $2 ""), ($1 == 1), ($1 == "1")
+	FS = ","
+	next
+}
+NR == 3 {
+	print "blanks and sign:", ($1 == 10), ($1 < 9), ($2 == 10)
+	FS = " "
+	next
+}
+NR == 4 {
+	print "not a number:", ($1 < $2), ($1 > 1)
+	next
+}
+NR == 5 {
+	print "fraction:", ($1 == $2), ($1 == 0.5)
+	next
+}
+NR == 6 {
+	print "hexadecimal:", ($1 == $2), ($1 == 16)
+	next
+}
+NR == 7 {
+	print "negative zero:", ($1 == $2), ($1 == 0), ($1 == "0")
+	next
+}
+
+END {
+	"echo 010" | getline piped
+	print "getline from command:", (piped == 10), (piped < 9)
+	getline line < ARGV[2]
+	print "getline from file:", (line < 9)
+	close(ARGV[2])
+	getline < ARGV[2]
+	print "getline record:", ($0 < 9), ($1 < 9), ($2 > 8)
+	split("2 10 1e1", parts)
+	print "split:", (parts[1] < parts[2]), (parts[2] == parts[3])
+	print "assignment operand:", (value < 5), (value "" < 5)
+	print "constants:", ("10" < "9"), (10 < 9), ("abc" < 1), ("1e1" == 10)
+}
@@ -222,6 +222,16 @@ fn test_change_record_separator() {
     test_awk!(change_record_separator, "tests/awk/test_data.txt");
 }
 
+#[test]
+fn test_awk_paragraph_records() {
+    test_awk!(paragraph_records, "tests/awk/paragraphs.txt");
+}
+
+#[test]
+fn test_awk_regex_separators() {
+    test_awk!(regex_separators, "tests/awk/regex_separators.txt");
+}
+
 #[test]
 fn test_awk_subscript_separator() {
     test_awk!(subscript_separator);
@@ -373,6 +383,11 @@ fn test_awk_output_redirection() {
     }
 }
 
+#[test]
+fn test_awk_output_redirection_close_and_fflush() {
+    test_awk!(output_redirection_close_and_fflush);
+}
+
 #[test]
 fn test_awk_builtin_arithmetic_functions() {
     test_awk!(builtin_arithmetic_functions);
@@ -383,11 +398,21 @@ fn builtin_string_functions() {
     test_awk!(builtin_string_functions, "tests/awk/test_data.txt");
 }
 
+#[test]
+fn test_awk_string_functions_edge_cases() {
+    test_awk!(string_functions_edge_cases);
+}
+
 #[test]
 fn test_awk_delete_array_elements_in_for_each() {
     test_awk!(delete_array_elements_in_for_each);
 }
 
+#[test]
+fn test_awk_multidimensional_arrays() {
+    test_awk!(multidimensional_arrays);
+}
+
 #[test]
 fn test_awk_call_function_no_args() {
     test_awk!(call_function_no_args);
@@ -413,6 +438,28 @@ fn test_awk_recursive_function() {
     test_awk!(recursive_function);
 }
 
+#[test]
+fn test_awk_deep_recursion() {
+    test_awk!(deep_recursion);
+}
+
+#[test]
+fn test_awk_scalar_argument_used_as_array() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec!["function f(a) { a[1] = 1 } BEGIN { f(1) }".to_string()],
+        stdin_data: String::new(),
+        expected_out: String::new(),
+        expected_err: String::from(
+            "runtime error: scalar used in array context\n\
+             call trace:\n\
+             => f at :1:17\n\
+             => <start> at :1:36\n\n",
+        ),
+        expected_exit_code: 1,
+    });
+}
+
 #[test]
 fn test_awk_mutually_recursive_functions() {
     test_awk!(mutually_recursive_functions);
@@ -451,6 +498,25 @@ fn test_awk_getline_from_file() {
     test_awk!(getline_from_file, "tests/awk/test_data.txt");
 }
 
+#[test]
+fn test_awk_getline_from_pipe() {
+    test_awk!(getline_from_pipe);
+}
+
+#[test]
+fn test_awk_getline_reads_the_main_input() {
+    test_awk!(
+        getline_reads_the_main_input,
+        "tests/awk/test_data.txt",
+        "tests/awk/test_data2.txt"
+    );
+}
+
+#[test]
+fn test_awk_getline_return_values() {
+    test_awk!(getline_return_values);
+}
+
 #[test]
 fn test_awk_read_records_from_stdin() {
     run_test(TestPlan {
@@ -493,11 +559,75 @@ fn test_awk_variable_assignment_arguments() {
     test_awk!(variable_assignment_arguments, "tests/awk/test_data.txt");
 }
 
+#[test]
+fn test_awk_command_line_special_variables() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![
+            "-F".to_string(),
+            "\\t".to_string(),
+            "-v".to_string(),
+            "OFS=-".to_string(),
+            "-f".to_string(),
+            "tests/awk/command_line_special_variables.awk".to_string(),
+            "-".to_string(),
+            "OFS=\\t".to_string(),
+            "FS=,".to_string(),
+            "tests/awk/test_data.csv".to_string(),
+        ],
+        stdin_data: String::from("a\tb c\td\n1\t2\n"),
+        expected_out: String::from(include_str!("awk/command_line_special_variables.out")),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    });
+}
+
+#[test]
+fn test_awk_invalid_cli_variable_assignment() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![
+            "-v".to_string(),
+            "1x=2".to_string(),
+            "BEGIN { print \"not reached\" }".to_string(),
+        ],
+        stdin_data: String::new(),
+        expected_out: String::new(),
+        expected_err: String::from("invalid variable assignment '1x=2'\n"),
+        expected_exit_code: 1,
+    });
+}
+
+#[test]
+fn test_awk_modify_argv() {
+    test_awk!(
+        modify_argv,
+        "/nonexistent1",
+        "/nonexistent2",
+        "prefix=first:",
+        "tests/awk/test_data.txt"
+    );
+}
+
 #[test]
 fn test_awk_correct_comparisons() {
     test_awk!(correct_comparisons, "tests/awk/test_data.txt");
 }
 
+#[test]
+fn test_awk_strnum_comparisons() {
+    test_awk!(
+        strnum_comparisons,
+        "value=10",
+        "tests/awk/strnum_comparisons.txt"
+    );
+}
+
+#[test]
+fn test_awk_convfmt_and_ofmt() {
+    test_awk!(convfmt_and_ofmt);
+}
+
 #[test]
 fn test_awk_execute_program_from_args() {
     run_test(TestPlan {
@@ -15,6 +15,7 @@
 use clap::Parser;
 use gettextrs::{bind_textdomain_codeset, setlocale, textdomain, LocaleCategory};
 use plib::PROJECT_NAME;
+use std::fs::File;
 use std::io::{self, Read, Write};
 use std::path::PathBuf;
 
@@ -26,26 +27,215 @@ struct Args {
     #[arg(short, long, default_value_t = true)]
     unbuffered: bool,
 
+    /// Number the output lines, starting at 1.
+    #[arg(short, long)]
+    number: bool,
+
+    /// Number the output lines that aren't empty, overriding -n.
+    #[arg(short = 'b', long)]
+    number_nonblank: bool,
+
+    /// Squeeze runs of empty output lines into one.
+    #[arg(short, long)]
+    squeeze_blank: bool,
+
+    /// Show the nonprinting characters other than <tab> and <newline>, with ^ for the
+    /// control characters and M- for the bytes with the high bit set.
+    #[arg(short = 'v', long)]
+    show_nonprinting: bool,
+
+    /// Show a $ at the end of each line.
+    #[arg(short = 'E', long)]
+    show_ends: bool,
+
+    /// Show <tab> as ^I.
+    #[arg(short = 'T', long)]
+    show_tabs: bool,
+
+    /// The same as -vE.
+    #[arg(short = 'e')]
+    nonprinting_ends: bool,
+
+    /// The same as -vT.
+    #[arg(short = 't')]
+    nonprinting_tabs: bool,
+
+    /// The same as -vET.
+    #[arg(short = 'A', long)]
+    show_all: bool,
+
     /// Files to read as input.  Use "-" or no-args for stdin.
     files: Vec<PathBuf>,
 }
 
-fn cat_file(pathname: &PathBuf) -> io::Result<()> {
-    let mut file = plib::io::input_stream(pathname, true)?;
-    let mut buffer = [0; plib::BUFSZ];
+/// Which output lines are numbered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Numbering {
+    None,
+    All,
+    NonBlank,
+}
+
+/// Transforms the data for the options that change the output: numbering the lines,
+/// squeezing the empty lines and showing the nonprinting characters. The files are
+/// transformed as a single input, so the line numbers continue from one file to the next.
+struct OutputFilter {
+    numbering: Numbering,
+    squeeze_blank: bool,
+    show_nonprinting: bool,
+    show_ends: bool,
+    show_tabs: bool,
+    // the number of the last numbered line
+    line: u64,
+    at_line_start: bool,
+    // whether the last line written was empty
+    last_blank: bool,
+}
+
+impl OutputFilter {
+    /// Returns the filter for the options, or `None` if the data is copied unchanged.
+    fn new(args: &Args) -> Option<Self> {
+        let numbering = if args.number_nonblank {
+            Numbering::NonBlank
+        } else if args.number {
+            Numbering::All
+        } else {
+            Numbering::None
+        };
+        let filter = Self {
+            numbering,
+            squeeze_blank: args.squeeze_blank,
+            show_nonprinting: args.show_nonprinting
+                || args.nonprinting_ends
+                || args.nonprinting_tabs
+                || args.show_all,
+            show_ends: args.show_ends || args.nonprinting_ends || args.show_all,
+            show_tabs: args.show_tabs || args.nonprinting_tabs || args.show_all,
+            line: 0,
+            at_line_start: true,
+            last_blank: false,
+        };
+
+        let changes_output = filter.numbering != Numbering::None
+            || filter.squeeze_blank
+            || filter.show_nonprinting
+            || filter.show_ends
+            || filter.show_tabs;
+        changes_output.then_some(filter)
+    }
+
+    fn push_number(&mut self, out: &mut Vec<u8>) {
+        self.line += 1;
+        out.extend_from_slice(format!("{:>6}\t", self.line).as_bytes());
+    }
 
+    fn push_newline(&self, out: &mut Vec<u8>) {
+        if self.show_ends {
+            out.push(b'$');
+        }
+        out.push(b'\n');
+    }
+
+    /// Pushes a byte of a line, in the notation of `-v` if it isn't printable.
+    fn push_byte(&self, byte: u8, out: &mut Vec<u8>) {
+        if byte == b'\t' {
+            if self.show_tabs {
+                out.extend_from_slice(b"^I");
+            } else {
+                out.push(byte);
+            }
+            return;
+        }
+        if !self.show_nonprinting {
+            out.push(byte);
+            return;
+        }
+
+        let mut byte = byte;
+        if byte >= 0x80 {
+            out.extend_from_slice(b"M-");
+            byte -= 0x80;
+        }
+        match byte {
+            0..=0x1f => out.extend_from_slice(&[b'^', byte + b'@']),
+            0x7f => out.extend_from_slice(b"^?"),
+            _ => out.push(byte),
+        }
+    }
+
+    /// Transforms the data, appending it to `out`.
+    fn filter(&mut self, data: &[u8], out: &mut Vec<u8>) {
+        for &byte in data {
+            if self.at_line_start {
+                if byte == b'\n' {
+                    // An empty line
+                    if self.squeeze_blank && self.last_blank {
+                        continue;
+                    }
+                    self.last_blank = true;
+                    if self.numbering == Numbering::All {
+                        self.push_number(out);
+                    }
+                    self.push_newline(out);
+                    continue;
+                }
+
+                self.last_blank = false;
+                self.at_line_start = false;
+                if self.numbering != Numbering::None {
+                    self.push_number(out);
+                }
+            }
+
+            if byte == b'\n' {
+                self.push_newline(out);
+                self.at_line_start = true;
+            } else {
+                self.push_byte(byte, out);
+            }
+        }
+    }
+}
+
+/// Copies the input to stdout, through the filter if there is one. Without one, the
+/// input is copied with `io::copy`, which lets the kernel copy the data between the
+/// files where it can.
+fn cat_stream<R: Read>(
+    input: &mut R,
+    filter: Option<&mut OutputFilter>,
+    out: &mut io::StdoutLock,
+) -> io::Result<()> {
+    let Some(filter) = filter else {
+        io::copy(input, out)?;
+        return Ok(());
+    };
+
+    let mut buffer = [0; plib::BUFSZ];
+    let mut filtered = Vec::with_capacity(plib::BUFSZ);
     loop {
-        let n_read = file.read(&mut buffer[..])?;
+        let n_read = input.read(&mut buffer[..])?;
         if n_read == 0 {
             break;
         }
 
-        io::stdout().write_all(&buffer[0..n_read])?;
+        filtered.clear();
+        filter.filter(&buffer[0..n_read], &mut filtered);
+        out.write_all(&filtered)?;
     }
 
     Ok(())
 }
 
+fn cat_file(pathname: &PathBuf, filter: Option<&mut OutputFilter>) -> io::Result<()> {
+    let mut out = io::stdout().lock();
+    // open file, or stdin
+    if pathname.as_os_str() == "-" {
+        cat_stream(&mut io::stdin().lock(), filter, &mut out)
+    } else {
+        cat_stream(&mut File::open(pathname)?, filter, &mut out)
+    }
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     // parse command line arguments
     let mut args = Args::parse();
@@ -60,9 +250,10 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     }
 
     let mut exit_code = 0;
+    let mut filter = OutputFilter::new(&args);
 
     for filename in &args.files {
-        if let Err(e) = cat_file(filename) {
+        if let Err(e) = cat_file(filename, filter.as_mut()) {
             exit_code = 1;
             eprintln!("{}: {}", filename.display(), e);
         }
@@ -8,6 +8,7 @@
 //
 
 use gettextrs::{bind_textdomain_codeset, gettext, setlocale, textdomain, LocaleCategory};
+use plib::progress::{self, Progress};
 use plib::PROJECT_NAME;
 use std::fs;
 use std::io::{self, Read, Write};
@@ -102,6 +103,7 @@ struct Config {
     conversions: Vec<Conversion>,
     noerror: bool,
     notrunc: bool,
+    progress: bool,
 }
 
 impl Config {
@@ -118,6 +120,7 @@ impl Config {
             conversions: Vec::new(),
             noerror: false,
             notrunc: false,
+            progress: false,
         }
     }
 }
@@ -225,11 +228,22 @@ fn apply_conversions(data: &mut Vec<u8>, config: &Config) {
 }
 
 fn copy_convert_file(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let progress = Progress::new("dd", config.progress);
+    let mut total_bytes = (config.count > 0).then_some(config.count as u64 * config.ibs as u64);
+
     let mut ifile: Box<dyn Read> = if config.ifile.is_empty() {
         Box::new(io::stdin().lock())
     } else {
-        Box::new(fs::File::open(&config.ifile)?)
+        let file = fs::File::open(&config.ifile)?;
+        let md = file.metadata()?;
+        if md.is_file() {
+            total_bytes = Some(total_bytes.map_or(md.len(), |total| total.min(md.len())));
+        }
+        Box::new(file)
     };
+    if let Some(total_bytes) = total_bytes {
+        progress.set_total_bytes(total_bytes);
+    }
     let mut ofile: Box<dyn Write> = if config.ofile.is_empty() {
         Box::new(io::stdout().lock())
     } else {
@@ -283,8 +297,10 @@ fn copy_convert_file(config: &Config) -> Result<(), Box<dyn std::error::Error>>
         } else {
             ofile.write_all(&obuf[..n])?;
         }
+        progress.add_bytes(n as u64);
     }
 
+    progress.finish();
     Ok(())
 }
 
@@ -374,6 +390,14 @@ fn parse_cmdline(args: &[String]) -> Result<Config, Box<dyn std::error::Error>>
             "seek" => config.seek = oparg.parse::<usize>()?,
             "count" => config.count = oparg.parse::<usize>()?,
             "conv" => parse_conv_list(&mut config, &oparg)?,
+            "status" => match oparg.as_str() {
+                "progress" => config.progress = true,
+                _ => {
+                    let msg = format!("{}: {}", gettext("invalid status"), oparg);
+                    eprintln!("{}", msg);
+                    return Err(msg.into());
+                }
+            },
 
             _ => {
                 eprintln!("{}: {}", gettext("invalid option"), op);
@@ -391,6 +415,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args: Vec<String> = std::env::args().skip(1).collect();
     let config = parse_cmdline(&args)?;
 
+    progress::install_signal_handler();
     copy_convert_file(&config)?;
 
     Ok(())
@@ -0,0 +1,140 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+use gettextrs::gettext;
+use plib::argmax::{arg_size, available};
+use std::cell::RefCell;
+use std::ffi::{OsStr, OsString};
+use std::io;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::process;
+
+fn report_failure(utility: &OsStr, e: &io::Error) {
+    eprintln!("find: {}: {}", utility.to_string_lossy(), e);
+}
+
+// Run `args` as a command, returning whether it exited with status 0
+fn run(args: &[OsString]) -> Result<bool, io::Error> {
+    let status = process::Command::new(&args[0]).args(&args[1..]).status()?;
+    Ok(status.success())
+}
+
+/// The command of `-exec utility [argument...] ;` and `-ok`, run for each file.
+#[derive(Debug, Clone)]
+pub struct Command {
+    args: Vec<OsString>,
+}
+
+impl Command {
+    pub fn new(args: Vec<OsString>) -> Self {
+        Self { args }
+    }
+
+    // Arguments with every "{}" in them replaced by `path`
+    fn args_for(&self, path: &OsStr) -> Vec<OsString> {
+        self.args
+            .iter()
+            .map(|arg| {
+                let arg = arg.as_bytes();
+                let mut res = Vec::with_capacity(arg.len());
+                let mut rest = arg;
+                while let Some(pos) = rest.windows(2).position(|w| w == b"{}") {
+                    res.extend_from_slice(&rest[..pos]);
+                    res.extend_from_slice(path.as_bytes());
+                    rest = &rest[pos + 2..];
+                }
+                res.extend_from_slice(rest);
+                OsString::from_vec(res)
+            })
+            .collect()
+    }
+
+    /// Run the command for `path`, after asking the user if `prompt` is set. Returns whether the
+    /// command exited with status 0, or `None` if it could not be run.
+    pub fn run(&self, path: &OsStr, prompt: bool) -> Option<bool> {
+        let args = self.args_for(path);
+        if prompt {
+            let command: Vec<_> = args.iter().map(|arg| arg.to_string_lossy()).collect();
+            let message = gettext!("< {} > ?", command.join(" "));
+            if !plib::prompt::prompt_user("find", &message) {
+                return Some(false);
+            }
+        }
+
+        match run(&args) {
+            Ok(success) => Some(success),
+            Err(e) => {
+                report_failure(&args[0], &e);
+                None
+            }
+        }
+    }
+}
+
+/// The command of `-exec utility [argument...] {} +`, run for as many files at a time as fit in
+/// its argument list.
+#[derive(Debug)]
+pub struct Batch {
+    args: Vec<OsString>,
+    limit: usize,
+    pending: RefCell<Pending>,
+}
+
+#[derive(Debug, Default)]
+struct Pending {
+    paths: Vec<OsString>,
+    size: usize,
+}
+
+impl Batch {
+    /// The `args` are the utility and the arguments that come before the paths.
+    pub fn new(args: Vec<OsString>) -> Self {
+        let size = args.iter().map(|arg| arg_size(arg)).sum::<usize>();
+        Self {
+            args,
+            limit: available().saturating_sub(size),
+            pending: RefCell::new(Pending::default()),
+        }
+    }
+
+    /// Add `path` to the paths to run the command for, running it first if the arguments would
+    /// not fit otherwise. Returns `false` if the command failed.
+    pub fn add(&self, path: &OsStr) -> bool {
+        let size = arg_size(path);
+        let full = {
+            let pending = self.pending.borrow();
+            !pending.paths.is_empty() && pending.size + size > self.limit
+        };
+        let success = !full || self.flush();
+
+        let mut pending = self.pending.borrow_mut();
+        pending.paths.push(path.to_os_string());
+        pending.size += size;
+        success
+    }
+
+    /// Run the command for the paths added since it last ran, if any. Returns `false` if the
+    /// command failed.
+    pub fn flush(&self) -> bool {
+        let pending = std::mem::take(&mut *self.pending.borrow_mut());
+        if pending.paths.is_empty() {
+            return true;
+        }
+
+        let mut args = self.args.clone();
+        args.extend(pending.paths);
+        match run(&args) {
+            Ok(success) => success,
+            Err(e) => {
+                report_failure(&args[0], &e);
+                false
+            }
+        }
+    }
+}
@@ -0,0 +1,349 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+use super::exec::{Batch, Command};
+use ftw::{DirEntry, Metadata, WalkError};
+use plib::owner;
+use plib::quote::{quote, Quoting};
+use std::cell::OnceCell;
+use std::ffi::{CString, OsStr};
+use std::io::{self, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+extern "C" {
+    // Not exported by the `libc` crate
+    fn fnmatch(
+        pattern: *const libc::c_char,
+        string: *const libc::c_char,
+        flags: libc::c_int,
+    ) -> libc::c_int;
+}
+
+const NANOS_PER_DAY: i128 = 86400 * 1_000_000_000;
+
+/// A time in nanoseconds since the epoch, from its seconds and nanoseconds.
+pub fn timestamp(sec: i64, nsec: i64) -> i128 {
+    sec as i128 * 1_000_000_000 + nsec as i128
+}
+
+/// A numeric argument to a primary. `n` matches exactly n, `+n` more than n and `-n` less than n.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Number {
+    Exactly(i64),
+    MoreThan(i64),
+    LessThan(i64),
+}
+
+impl Number {
+    pub fn matches(self, value: i64) -> bool {
+        match self {
+            Number::Exactly(n) => value == n,
+            Number::MoreThan(n) => value > n,
+            Number::LessThan(n) => value < n,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileType {
+    BlockDevice,
+    CharDevice,
+    Dir,
+    Symlink,
+    Fifo,
+    File,
+    Socket,
+}
+
+impl FileType {
+    fn matches(self, file_type: ftw::FileType) -> bool {
+        let expected = match self {
+            FileType::BlockDevice => ftw::FileType::BlockDevice,
+            FileType::CharDevice => ftw::FileType::CharacterDevice,
+            FileType::Dir => ftw::FileType::Directory,
+            FileType::Symlink => ftw::FileType::SymbolicLink,
+            FileType::Fifo => ftw::FileType::Fifo,
+            FileType::File => ftw::FileType::RegularFile,
+            FileType::Socket => ftw::FileType::Socket,
+        };
+        file_type == expected
+    }
+}
+
+/// The file time compared by `-atime`, `-ctime` and `-mtime`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeField {
+    Access,
+    StatusChange,
+    Modification,
+}
+
+/// How `-perm` compares the mode of a file with its argument.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PermMatch {
+    /// `mode`: the bits are the same.
+    Exactly,
+    /// `-mode`: all of the bits are set.
+    All,
+    /// `/mode`: any of the bits is set, or the mode is 0.
+    Any,
+}
+
+/// A shell pattern for `-name` and `-path`.
+#[derive(Debug, Clone)]
+pub struct Pattern(CString);
+
+impl Pattern {
+    pub fn new(pattern: &OsStr) -> Self {
+        // Command line arguments can't hold a NUL byte
+        Self(CString::new(pattern.as_bytes()).unwrap())
+    }
+
+    fn matches(&self, s: &OsStr) -> bool {
+        let Ok(s) = CString::new(s.as_bytes()) else {
+            return false;
+        };
+        unsafe { fnmatch(self.0.as_ptr(), s.as_ptr(), 0) == 0 }
+    }
+}
+
+/// The parsed expression of find.
+#[derive(Debug)]
+pub enum Expr {
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Name(Pattern),
+    Path(Pattern),
+    Type(FileType),
+    User(u32),
+    Group(u32),
+    NoUser,
+    NoGroup,
+    /// The size in units of the given number of bytes, rounded up.
+    Size(Number, u64),
+    /// The number of whole days from the time of the file to the start of find.
+    Time(TimeField, Number),
+    Links(Number),
+    /// The permission bits, along with the set-user-ID, set-group-ID and sticky bits.
+    Perm(PermMatch, u32),
+    /// The modification time of the reference file.
+    Newer(i128),
+    /// `-depth`, `-xdev` and the like, which apply to the whole walk and are always true.
+    GlobalOption,
+    Prune,
+    Print,
+    Print0,
+    /// `-exec` with the command ending in `;`.
+    Exec(Command),
+    /// `-exec` with the command ending in `{} +`.
+    ExecBatch(Batch),
+    Ok(Command),
+}
+
+impl Expr {
+    /// Check if `f` is true for any of the primaries in the expression.
+    pub fn any(&self, f: &impl Fn(&Expr) -> bool) -> bool {
+        match self {
+            Expr::Not(e) => e.any(f),
+            Expr::And(l, r) | Expr::Or(l, r) => l.any(f) || r.any(f),
+            e => f(e),
+        }
+    }
+
+    /// Check if the expression has an action. Without one, the files it's true for are printed.
+    pub fn has_action(&self) -> bool {
+        self.any(&|e| {
+            matches!(
+                e,
+                Expr::Print | Expr::Print0 | Expr::Exec(_) | Expr::ExecBatch(_) | Expr::Ok(_)
+            )
+        })
+    }
+
+    /// Run the commands of `-exec ... {} +` for the files they still have pending. Returns `false`
+    /// if any of them failed.
+    pub fn flush_batches(&self) -> bool {
+        match self {
+            Expr::Not(e) => e.flush_batches(),
+            // Both sides are flushed even if one of them fails
+            Expr::And(l, r) | Expr::Or(l, r) => l.flush_batches() & r.flush_batches(),
+            Expr::ExecBatch(batch) => batch.flush(),
+            _ => true,
+        }
+    }
+
+    /// Evaluate the expression for `file`. Operands of `!`, `-a` and `-o` are only evaluated as
+    /// far as needed to decide the result.
+    pub fn eval(&self, file: &File, ctx: &mut Context) -> io::Result<bool> {
+        let res = match self {
+            Expr::Not(e) => !e.eval(file, ctx)?,
+            Expr::And(l, r) => l.eval(file, ctx)? && r.eval(file, ctx)?,
+            Expr::Or(l, r) => l.eval(file, ctx)? || r.eval(file, ctx)?,
+            Expr::Name(pattern) => pattern.matches(file.name()),
+            Expr::Path(pattern) => pattern.matches(file.path().as_os_str()),
+            Expr::Type(t) => file
+                .file_type(ctx)
+                .is_some_and(|file_type| t.matches(file_type)),
+            Expr::Prune => {
+                ctx.prune = true;
+                true
+            }
+            Expr::GlobalOption => true,
+            Expr::Print => {
+                let path = file.path().as_os_str().as_bytes();
+                match ctx.quoting {
+                    Some(quoting) => ctx.out.write_all(&quote(path, quoting))?,
+                    None => ctx.out.write_all(path)?,
+                }
+                ctx.out.write_all(b"\n")?;
+                true
+            }
+            Expr::Print0 => {
+                ctx.out.write_all(file.path().as_os_str().as_bytes())?;
+                ctx.out.write_all(b"\0")?;
+                true
+            }
+            Expr::Exec(command) | Expr::Ok(command) => {
+                // Keep the output in order with that of the command
+                ctx.out.flush()?;
+                let prompt = matches!(self, Expr::Ok(_));
+                command
+                    .run(file.path().as_os_str(), prompt)
+                    .unwrap_or_else(|| {
+                        ctx.failed = true;
+                        false
+                    })
+            }
+            Expr::ExecBatch(batch) => {
+                ctx.out.flush()?;
+                if !batch.add(file.path().as_os_str()) {
+                    ctx.failed = true;
+                }
+                true
+            }
+            e => match file.metadata(ctx) {
+                Some(metadata) => e.eval_metadata(metadata, ctx),
+                None => false,
+            },
+        };
+        Ok(res)
+    }
+
+    fn eval_metadata(&self, metadata: &Metadata, ctx: &Context) -> bool {
+        match self {
+            Expr::User(uid) => *uid == metadata.uid(),
+            Expr::Group(gid) => *gid == metadata.gid(),
+            Expr::NoUser => owner::user_name(metadata.uid()).is_none(),
+            Expr::NoGroup => owner::group_name(metadata.gid()).is_none(),
+            Expr::Size(n, unit) => n.matches(metadata.size().div_ceil(*unit) as i64),
+            Expr::Time(field, n) => {
+                let time = match field {
+                    TimeField::Access => timestamp(metadata.atime(), metadata.atime_nsec()),
+                    TimeField::StatusChange => timestamp(metadata.ctime(), metadata.ctime_nsec()),
+                    TimeField::Modification => timestamp(metadata.mtime(), metadata.mtime_nsec()),
+                };
+                // The remainder is discarded, so a file less than a day in the future is 0 days
+                // old too
+                let days = (ctx.start_time - time) / NANOS_PER_DAY;
+                n.matches(days as i64)
+            }
+            Expr::Links(n) => n.matches(metadata.nlink() as i64),
+            Expr::Perm(how, mode) => {
+                let file_mode = metadata.mode() & 0o7777;
+                match how {
+                    PermMatch::Exactly => file_mode == *mode,
+                    PermMatch::All => file_mode & mode == *mode,
+                    PermMatch::Any => *mode == 0 || file_mode & mode != 0,
+                }
+            }
+            Expr::Newer(time) => timestamp(metadata.mtime(), metadata.mtime_nsec()) > *time,
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// State shared by the evaluations of the expression.
+pub struct Context<'a> {
+    pub out: &'a mut dyn Write,
+    /// How `-print` writes paths, or `None` to write them as they are.
+    pub quoting: Option<Quoting>,
+    /// The time find was started, see `timestamp`.
+    pub start_time: i128,
+    /// Set by `-prune` when it's evaluated.
+    pub prune: bool,
+    /// Set when an error is reported.
+    pub failed: bool,
+}
+
+/// A file being evaluated. An error reading its status is reported once.
+pub struct File<'a> {
+    entry: &'a DirEntry,
+    metadata: OnceCell<Option<&'a Metadata>>,
+}
+
+impl<'a> File<'a> {
+    pub fn new(entry: &'a DirEntry) -> Self {
+        Self {
+            entry,
+            metadata: OnceCell::new(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        self.entry.path()
+    }
+
+    // The last component of the path, which for the path find was given isn't necessarily what
+    // `file_name` holds
+    fn name(&self) -> &OsStr {
+        if self.entry.depth() > 0 {
+            return OsStr::from_bytes(self.entry.file_name().to_bytes());
+        }
+
+        let path = self.path().as_os_str().as_bytes();
+        let end = path.iter().rposition(|&b| b != b'/').map_or(0, |i| i + 1);
+        if end == 0 {
+            // Only slashes
+            return OsStr::from_bytes(&path[..path.len().min(1)]);
+        }
+        let start = path[..end]
+            .iter()
+            .rposition(|&b| b == b'/')
+            .map_or(0, |i| i + 1);
+        OsStr::from_bytes(&path[start..end])
+    }
+
+    fn metadata(&self, ctx: &mut Context) -> Option<&'a Metadata> {
+        *self.metadata.get_or_init(|| match self.entry.metadata() {
+            Ok(metadata) => Some(metadata),
+            Err(e) => {
+                eprintln!("find: {}: {}", self.path().display(), e);
+                ctx.failed = true;
+                None
+            }
+        })
+    }
+
+    // Usually known without reading the status
+    fn file_type(&self, ctx: &mut Context) -> Option<ftw::FileType> {
+        match self.entry.file_type() {
+            Ok(file_type) => Some(file_type),
+            Err(_) => self.metadata(ctx).map(|metadata| metadata.file_type()),
+        }
+    }
+}
+
+/// Write an error from walking the tree to standard error.
+pub fn report_error(e: WalkError) {
+    let path = e.path().to_path_buf();
+    eprintln!("find: {}: {}", path.display(), e.into_error().inner());
+}
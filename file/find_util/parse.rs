@@ -0,0 +1,329 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+use super::exec::{Batch, Command};
+use super::expr::{timestamp, Expr, FileType, Number, Pattern, PermMatch, TimeField};
+use gettextrs::gettext;
+use plib::{modestr, owner};
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+
+/// The settings of the walk from the global options in the expression.
+#[derive(Debug, Default)]
+pub struct Options {
+    /// `-depth`: Evaluate the contents of directories before the directories themselves.
+    pub depth: bool,
+    /// `-xdev`: Don't descend into directories on other file systems.
+    pub xdev: bool,
+    /// `-maxdepth`: Don't descend below this depth.
+    pub max_depth: Option<usize>,
+    /// `-mindepth`: Don't evaluate files above this depth.
+    pub min_depth: usize,
+}
+
+/// Parses the expression operands of find, from the lowest precedence up:
+///
+/// ```text
+/// expr  = and { "-o" and }
+/// and   = not { ["-a"] not }
+/// not   = "!" not | "(" expr ")" | primary
+/// ```
+///
+/// The expression is `None` if there are no operands. With `follow_symlinks`, as set by `-H` or
+/// `-L`, the file of `-newer` is dereferenced if it's a symlink.
+pub fn parse_expression(
+    args: &[OsString],
+    follow_symlinks: bool,
+) -> Result<(Option<Expr>, Options), String> {
+    let mut parser = Parser {
+        args,
+        pos: 0,
+        follow_symlinks,
+        options: Options::default(),
+    };
+    if args.is_empty() {
+        return Ok((None, parser.options));
+    }
+
+    let expr = parser.parse_or()?;
+    match parser.peek() {
+        None => Ok((Some(expr), parser.options)),
+        Some(arg) if arg == ")" => Err(gettext("unexpected ')'")),
+        // `parse_or` only stops at the end or at ")"
+        Some(_) => unreachable!(),
+    }
+}
+
+struct Parser<'a> {
+    args: &'a [OsString],
+    pos: usize,
+    follow_symlinks: bool,
+    options: Options,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a OsStr> {
+        self.args.get(self.pos).map(|arg| arg.as_os_str())
+    }
+
+    fn next(&mut self) -> Option<&'a OsStr> {
+        let arg = self.peek()?;
+        self.pos += 1;
+        Some(arg)
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.parse_and()?;
+        while self.peek().is_some_and(|arg| arg == "-o") {
+            self.next();
+            self.expect_operand("-o")?;
+            let rhs = self.parse_and()?;
+            expr = Expr::Or(Box::new(expr), Box::new(rhs));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.parse_not()?;
+        loop {
+            match self.peek() {
+                None => break,
+                Some(arg) if arg == "-o" || arg == ")" => break,
+                Some(arg) if arg == "-a" => {
+                    self.next();
+                    self.expect_operand("-a")?;
+                }
+                // Juxtaposed expressions are joined by an implicit "-a"
+                Some(_) => {}
+            }
+            let rhs = self.parse_not()?;
+            expr = Expr::And(Box::new(expr), Box::new(rhs));
+        }
+        Ok(expr)
+    }
+
+    fn parse_not(&mut self) -> Result<Expr, String> {
+        match self.peek() {
+            Some(arg) if arg == "!" => {
+                self.next();
+                self.expect_operand("!")?;
+                let expr = self.parse_not()?;
+                Ok(Expr::Not(Box::new(expr)))
+            }
+            Some(arg) if arg == "(" => {
+                self.next();
+                self.expect_operand("(")?;
+                let expr = self.parse_or()?;
+                match self.next() {
+                    Some(arg) if arg == ")" => Ok(expr),
+                    _ => Err(gettext("missing ')'")),
+                }
+            }
+            _ => self.parse_primary(),
+        }
+    }
+
+    // An operator has to be followed by a primary, "!" or "("
+    fn expect_operand(&self, operator: &str) -> Result<(), String> {
+        match self.peek() {
+            Some(arg) if arg != "-o" && arg != "-a" && arg != ")" => Ok(()),
+            _ => Err(gettext!("expected an expression after '{}'", operator)),
+        }
+    }
+
+    fn argument(&mut self, primary: &str) -> Result<&'a OsStr, String> {
+        self.next()
+            .ok_or_else(|| gettext!("missing argument to '{}'", primary))
+    }
+
+    fn str_argument(&mut self, primary: &str) -> Result<&'a str, String> {
+        let arg = self.argument(primary)?;
+        arg.to_str().ok_or_else(|| invalid_argument(arg, primary))
+    }
+
+    fn number_argument(&mut self, primary: &str) -> Result<Number, String> {
+        let arg = self.str_argument(primary)?;
+        parse_number(arg).ok_or_else(|| invalid_argument(OsStr::new(arg), primary))
+    }
+
+    // The utility and arguments of `-exec` or `-ok`, up to ";" or, if `batch` is allowed, up to
+    // "{}" followed by "+". The "{}" of the latter is left out of the arguments.
+    fn command(&mut self, primary: &str, batch: bool) -> Result<(Vec<OsString>, bool), String> {
+        let mut args = Vec::new();
+        loop {
+            let Some(arg) = self.next() else {
+                return Err(gettext!("missing argument to '{}'", primary));
+            };
+            if arg == ";" {
+                break;
+            }
+            if batch && arg == "+" && args.last().is_some_and(|arg: &OsString| arg == "{}") {
+                args.pop();
+                if args.is_empty() {
+                    break;
+                }
+                return Ok((args, true));
+            }
+            args.push(arg.to_os_string());
+        }
+
+        if args.is_empty() {
+            return Err(gettext!("missing argument to '{}'", primary));
+        }
+        Ok((args, false))
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, String> {
+        let Some(arg) = self.next() else {
+            unreachable!()
+        };
+        let Some(primary) = arg.to_str() else {
+            return Err(unknown_primary(arg));
+        };
+
+        let expr = match primary {
+            // Only reached for an operator at the start of the expression
+            "-o" | "-a" | ")" => {
+                return Err(gettext!("expected an expression before '{}'", primary))
+            }
+            "-name" => Expr::Name(Pattern::new(self.argument(primary)?)),
+            "-path" => Expr::Path(Pattern::new(self.argument(primary)?)),
+            "-type" => {
+                let arg = self.str_argument(primary)?;
+                let file_type = match arg {
+                    "b" => FileType::BlockDevice,
+                    "c" => FileType::CharDevice,
+                    "d" => FileType::Dir,
+                    "l" => FileType::Symlink,
+                    "p" => FileType::Fifo,
+                    "f" => FileType::File,
+                    "s" => FileType::Socket,
+                    _ => return Err(invalid_argument(OsStr::new(arg), primary)),
+                };
+                Expr::Type(file_type)
+            }
+            "-user" => {
+                let arg = self.str_argument(primary)?;
+                let uid = owner::parse_user(arg)
+                    .map_err(|_| gettext!("'{}' is not the name of a known user", arg))?;
+                Expr::User(uid)
+            }
+            "-group" => {
+                let arg = self.str_argument(primary)?;
+                let gid = owner::parse_group(arg)
+                    .map_err(|_| gettext!("'{}' is not the name of a known group", arg))?;
+                Expr::Group(gid)
+            }
+            "-nouser" => Expr::NoUser,
+            "-nogroup" => Expr::NoGroup,
+            "-size" => {
+                let arg = self.str_argument(primary)?;
+                let (n, unit) = if let Some(n) = arg.strip_suffix('c') {
+                    (n, 1)
+                } else if let Some(n) = arg.strip_suffix('k') {
+                    (n, 1024)
+                } else {
+                    (arg, 512)
+                };
+                let n =
+                    parse_number(n).ok_or_else(|| invalid_argument(OsStr::new(arg), primary))?;
+                Expr::Size(n, unit)
+            }
+            "-atime" => Expr::Time(TimeField::Access, self.number_argument(primary)?),
+            "-ctime" => Expr::Time(TimeField::StatusChange, self.number_argument(primary)?),
+            "-mtime" => Expr::Time(TimeField::Modification, self.number_argument(primary)?),
+            "-links" => Expr::Links(self.number_argument(primary)?),
+            "-perm" => {
+                let arg = self.str_argument(primary)?;
+                let (how, mode) = if let Some(mode) = arg.strip_prefix('-') {
+                    (PermMatch::All, mode)
+                } else if let Some(mode) = arg.strip_prefix('/') {
+                    (PermMatch::Any, mode)
+                } else {
+                    (PermMatch::Exactly, arg)
+                };
+                // A symbolic mode starts from no bits set, regardless of the umask
+                let mode = modestr::parse(mode)
+                    .map_err(|e| gettext!("invalid argument '{}' to '{}': {}", arg, primary, e))?;
+                Expr::Perm(how, mode.apply(0, 0, false))
+            }
+            "-newer" => {
+                let file = self.argument(primary)?;
+                let metadata = if self.follow_symlinks {
+                    fs::metadata(file)
+                } else {
+                    fs::symlink_metadata(file)
+                };
+                let metadata = metadata
+                    .map_err(|e| gettext!("cannot access '{}': {}", file.to_string_lossy(), e))?;
+                Expr::Newer(timestamp(metadata.mtime(), metadata.mtime_nsec()))
+            }
+            "-depth" => {
+                self.options.depth = true;
+                Expr::GlobalOption
+            }
+            "-xdev" => {
+                self.options.xdev = true;
+                Expr::GlobalOption
+            }
+            "-maxdepth" | "-mindepth" => {
+                let arg = self.str_argument(primary)?;
+                let depth = arg
+                    .parse()
+                    .map_err(|_| invalid_argument(OsStr::new(arg), primary))?;
+                if primary == "-maxdepth" {
+                    self.options.max_depth = Some(depth);
+                } else {
+                    self.options.min_depth = depth;
+                }
+                Expr::GlobalOption
+            }
+            "-prune" => Expr::Prune,
+            "-print" => Expr::Print,
+            "-print0" => Expr::Print0,
+            "-exec" => {
+                let (args, batch) = self.command(primary, true)?;
+                if batch {
+                    Expr::ExecBatch(Batch::new(args))
+                } else {
+                    Expr::Exec(Command::new(args))
+                }
+            }
+            "-ok" => Expr::Ok(Command::new(self.command(primary, false)?.0)),
+            _ => return Err(unknown_primary(arg)),
+        };
+        Ok(expr)
+    }
+}
+
+fn unknown_primary(arg: &OsStr) -> String {
+    gettext!("unknown primary or operator '{}'", arg.to_string_lossy())
+}
+
+fn invalid_argument(arg: &OsStr, primary: &str) -> String {
+    gettext!(
+        "invalid argument '{}' to '{}'",
+        arg.to_string_lossy(),
+        primary
+    )
+}
+
+// A decimal integer with an optional leading "+" or "-"
+fn parse_number(s: &str) -> Option<Number> {
+    let (digits, make): (_, fn(i64) -> Number) = match s.as_bytes().first()? {
+        b'+' => (&s[1..], Number::MoreThan),
+        b'-' => (&s[1..], Number::LessThan),
+        _ => (s, Number::Exactly),
+    };
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok().map(make)
+}
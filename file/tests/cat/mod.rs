@@ -0,0 +1,75 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+use plib::{run_test, run_test_u8, TestPlan, TestPlanU8};
+
+fn cat_test(args: &[&str], test_data: &str, expected_output: &str) {
+    let str_args: Vec<String> = args.iter().map(|s| String::from(*s)).collect();
+
+    run_test(TestPlan {
+        cmd: String::from("cat"),
+        args: str_args,
+        stdin_data: String::from(test_data),
+        expected_out: String::from(expected_output),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    });
+}
+
+#[test]
+fn cat_plain() {
+    cat_test(&[], "a\nb\n", "a\nb\n");
+    cat_test(&["-u", "-"], "no newline", "no newline");
+}
+
+#[test]
+fn cat_number() {
+    cat_test(&["-n"], "a\n\nb", "     1\ta\n     2\t\n     3\tb");
+    cat_test(&["-b"], "a\n\nb\n", "     1\ta\n\n     2\tb\n");
+    // -b overrides -n
+    cat_test(&["-n", "-b"], "a\n\nb\n", "     1\ta\n\n     2\tb\n");
+}
+
+#[test]
+fn cat_number_files() {
+    // The lines are numbered across the files
+    let file = format!("{}/tests/cat/lines.txt", env!("CARGO_MANIFEST_DIR"));
+    cat_test(
+        &["-n", &file, "-", &file],
+        "x\n",
+        "     1\tone\n     2\ttwo\n     3\tx\n     4\tone\n     5\ttwo\n",
+    );
+}
+
+#[test]
+fn cat_squeeze_blank() {
+    cat_test(&["-s"], "\n\n\na\n\n\nb\n\n", "\na\n\nb\n\n");
+    cat_test(&["-sn"], "a\n\n\n\nb\n", "     1\ta\n     2\t\n     3\tb\n");
+}
+
+#[test]
+fn cat_show_ends_tabs() {
+    cat_test(&["-E"], "a\tb\n\n", "a\tb$\n$\n");
+    cat_test(&["-T"], "a\tb\n", "a^Ib\n");
+    cat_test(&["-A"], "a\tb\n", "a^Ib$\n");
+}
+
+#[test]
+fn cat_show_nonprinting() {
+    run_test_u8(TestPlanU8 {
+        cmd: String::from("cat"),
+        args: vec![String::from("-v")],
+        stdin_data: b"\x00\x1b\t\x7f\x80\x9b\xa0\xe9\xff\n".to_vec(),
+        expected_out: b"^@^[\t^?M-^@M-^[M- M-iM-^?\n".to_vec(),
+        expected_err: Vec::new(),
+        expected_exit_code: 0,
+    });
+    cat_test(&["-e"], "\x01\tx\n", "^A\tx$\n");
+    cat_test(&["-t"], "\x01\tx\n", "^A^Ix\n");
+}
@@ -2,7 +2,7 @@ use std::fs::File;
 use std::io::Read;
 use std::path::PathBuf;
 
-use plib::{run_test_u8, TestPlanU8};
+use plib::{run_test_u8, run_test_with_checker, TestPlan, TestPlanU8};
 
 fn get_test_file_path(filename: &str) -> PathBuf {
     let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
@@ -312,3 +312,21 @@ fn test_conv_block() {
         expected_exit_code: 0,
     });
 }
+
+#[test]
+fn test_status_progress() {
+    let plan = TestPlan {
+        cmd: String::from("dd"),
+        args: vec![String::from("status=progress")],
+        stdin_data: String::from("hello\n"),
+        expected_out: String::from("hello\n"),
+        expected_err: String::new(),
+        expected_exit_code: 0,
+    };
+    run_test_with_checker(plan, |plan, output| {
+        assert_eq!(String::from_utf8_lossy(&output.stdout), plan.expected_out);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.starts_with("dd: 6 bytes (6 B) copied, "), "{stderr}");
+        assert_eq!(output.status.code(), Some(plan.expected_exit_code));
+    });
+}
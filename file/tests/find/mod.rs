@@ -1,5 +1,7 @@
-use std::fs::{remove_file, File};
+use std::fs::{remove_file, File, FileTimes};
 use std::io::Write;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::time::{Duration, SystemTime};
 
 use plib::{run_test, TestPlan};
 
@@ -200,9 +202,31 @@ fn find_links_test() {
 fn find_group_test() {
     let project_root = env!("CARGO_MANIFEST_DIR");
     let test_dir = format!("{}/tests/find/other", project_root);
-    let args = [&test_dir, "-group", "name"];
+    let gid = std::fs::metadata(&test_dir).unwrap().gid().to_string();
+    let args = [&test_dir, "-group", &gid, "-name", "file1.txt"];
 
-    run_test_find(&args, "", "", 0)
+    let expected_output = format!("{}/file1.txt\n", test_dir);
+
+    run_test_find(&args, &expected_output, "", 0)
+}
+
+#[test]
+fn find_unknown_owner_test() {
+    let project_root = env!("CARGO_MANIFEST_DIR");
+    let test_dir = format!("{}/tests/find/other", project_root);
+
+    run_test_find(
+        &[&test_dir, "-user", "nosuchuserxyz"],
+        "",
+        "find: 'nosuchuserxyz' is not the name of a known user\n",
+        1,
+    );
+    run_test_find(
+        &[&test_dir, "-group", "nosuchgroupxyz"],
+        "",
+        "find: 'nosuchgroupxyz' is not the name of a known group\n",
+        1,
+    );
 }
 
 #[test]
@@ -218,3 +242,459 @@ fn find_newer_test() {
 
     remove_file(&path_to_test_file).unwrap();
 }
+
+#[test]
+fn find_precedence_test() {
+    let project_root = env!("CARGO_MANIFEST_DIR");
+    let test_dir = format!("{}/tests/find/other", project_root);
+    let args = [
+        &test_dir, "-name", "*.rs", "-o", "-name", "*.txt", "-a", "-size", "+2",
+    ];
+
+    let expected_output = format!("{}/file1.txt\n{}/rust_file.rs\n", test_dir, test_dir);
+
+    run_test_find(&args, &expected_output, "", 0)
+}
+
+#[test]
+fn find_parentheses_test() {
+    let project_root = env!("CARGO_MANIFEST_DIR");
+    let test_dir = format!("{}/tests/find/other", project_root);
+    let args = [
+        &test_dir, "(", "-name", "*.rs", "-o", "-name", "*.txt", ")", "-size", "+2",
+    ];
+
+    let expected_output = format!("{}/file1.txt\n", test_dir);
+
+    run_test_find(&args, &expected_output, "", 0)
+}
+
+#[test]
+fn find_short_circuit_test() {
+    let project_root = env!("CARGO_MANIFEST_DIR");
+    let test_dir = format!("{}/tests/find/other", project_root);
+    let args = [&test_dir, "-name", "*.rs", "-o", "-print"];
+
+    // The expression has an action, so the files matching "*.rs" are not printed
+    let expected_output = format!(
+        "{}\n{}/empty_file.txt\n{}/file with space.txt\n{}/file1.txt\n",
+        test_dir, test_dir, test_dir, test_dir
+    );
+
+    run_test_find(&args, &expected_output, "", 0)
+}
+
+#[test]
+fn find_not_precedence_test() {
+    let project_root = env!("CARGO_MANIFEST_DIR");
+    let test_dir = format!("{}/tests/find/other", project_root);
+    let args = [&test_dir, "!", "-type", "d", "!", "-name", "*.txt"];
+
+    let expected_output = format!("{}/rust_file.rs\n", test_dir);
+
+    run_test_find(&args, &expected_output, "", 0)
+}
+
+#[test]
+fn find_expression_errors_test() {
+    let project_root = env!("CARGO_MANIFEST_DIR");
+    let test_dir = format!("{}/tests/find/other", project_root);
+
+    run_test_find(
+        &[&test_dir, "(", "-name", "*.rs"],
+        "",
+        "find: missing ')'\n",
+        1,
+    );
+    run_test_find(
+        &[&test_dir, "-name", "*.rs", "-o"],
+        "",
+        "find: expected an expression after '-o'\n",
+        1,
+    );
+    run_test_find(
+        &[&test_dir, "-type", "x"],
+        "",
+        "find: invalid argument 'x' to '-type'\n",
+        1,
+    );
+    run_test_find(
+        &[&test_dir, "-unknown"],
+        "",
+        "find: unknown primary or operator '-unknown'\n",
+        1,
+    );
+}
+
+#[test]
+fn find_exec_test() {
+    let project_root = env!("CARGO_MANIFEST_DIR");
+    let test_dir = format!("{}/tests/find/other", project_root);
+    let args = [
+        &test_dir, "-type", "f", "-exec", "test", "-s", "{}", ";", "-exec", "echo", "<{}>", ";",
+    ];
+
+    let expected_output = format!("<{}/file1.txt>\n<{}/rust_file.rs>\n", test_dir, test_dir);
+
+    run_test_find(&args, &expected_output, "", 0)
+}
+
+#[test]
+fn find_exec_batch_test() {
+    let project_root = env!("CARGO_MANIFEST_DIR");
+    let test_dir = format!("{}/tests/find/other", project_root);
+    let args = [
+        &test_dir, "-name", "*.txt", "-exec", "echo", "files:", "{}", "+",
+    ];
+
+    let expected_output = format!(
+        "files: {}/empty_file.txt {}/file with space.txt {}/file1.txt\n",
+        test_dir, test_dir, test_dir
+    );
+
+    run_test_find(&args, &expected_output, "", 0)
+}
+
+#[test]
+fn find_exec_status_test() {
+    let project_root = env!("CARGO_MANIFEST_DIR");
+    let test_dir = format!("{}/tests/find/other", project_root);
+
+    // A failing command is false for the file, but only affects the exit status with "+"
+    let expected_output = format!("{}/rust_file.rs\n", test_dir);
+    run_test_find(
+        &[
+            &test_dir, "-name", "*.rs", "!", "-exec", "false", ";", "-print",
+        ],
+        &expected_output,
+        "",
+        0,
+    );
+    run_test_find(&[&test_dir, "-exec", "false", "{}", "+"], "", "", 1);
+}
+
+#[test]
+fn find_ok_test() {
+    let project_root = env!("CARGO_MANIFEST_DIR");
+    let test_dir = format!("{}/tests/find/other", project_root);
+
+    run_test(TestPlan {
+        cmd: String::from("find"),
+        args: vec![
+            test_dir.clone(),
+            String::from("-name"),
+            String::from("*.txt"),
+            String::from("-ok"),
+            String::from("echo"),
+            String::from("{}"),
+            String::from(";"),
+        ],
+        stdin_data: String::from("y\nn\nyes\n"),
+        expected_out: format!(
+            "{}/empty_file.txt\n{}/file1.txt\n",
+            test_dir, test_dir
+        ),
+        expected_err: format!(
+            "find: < echo {0}/empty_file.txt > ? find: < echo {0}/file with space.txt > ? find: < echo {0}/file1.txt > ? ",
+            test_dir
+        ),
+        expected_exit_code: 0,
+    });
+}
+
+#[test]
+fn find_print0_test() {
+    let project_root = env!("CARGO_MANIFEST_DIR");
+    let test_dir = format!("{}/tests/find/other", project_root);
+    let args = [&test_dir, "-name", "*.txt", "-print0"];
+
+    let expected_output = format!(
+        "{}/empty_file.txt\0{}/file with space.txt\0{}/file1.txt\0",
+        test_dir, test_dir, test_dir
+    );
+
+    run_test_find(&args, &expected_output, "", 0)
+}
+
+#[test]
+fn find_print_newline_test() {
+    let test_dir = format!("{}/find_print_newline", env!("CARGO_TARGET_TMPDIR"));
+    std::fs::create_dir(&test_dir).unwrap();
+    File::create(format!("{}/a\nb", test_dir)).unwrap();
+
+    // Names are written as they are when the output is not a terminal
+    run_test_find(
+        &[&test_dir, "-type", "f"],
+        &format!("{}/a\nb\n", test_dir),
+        "",
+        0,
+    );
+    run_test_find(
+        &[&test_dir, "-type", "f", "-print0"],
+        &format!("{}/a\nb\0", test_dir),
+        "",
+        0,
+    );
+
+    std::fs::remove_dir_all(&test_dir).unwrap();
+}
+
+#[test]
+fn find_perm_modes_test() {
+    let test_dir = format!("{}/find_perm_modes", env!("CARGO_TARGET_TMPDIR"));
+    std::fs::create_dir(&test_dir).unwrap();
+    for (name, mode) in [("a", 0o644), ("b", 0o4755), ("c", 0o600)] {
+        let path = format!("{}/{}", test_dir, name);
+        File::create(&path).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode)).unwrap();
+    }
+
+    let find_perm = |mode: &str, expected: &[&str]| {
+        let expected_output: String = expected
+            .iter()
+            .map(|name| format!("{}/{}\n", test_dir, name))
+            .collect();
+        run_test_find(
+            &[&test_dir, "-type", "f", "-perm", mode],
+            &expected_output,
+            "",
+            0,
+        );
+    };
+    find_perm("644", &["a"]);
+    find_perm("u=rw,go=r", &["a"]);
+    find_perm("-644", &["a", "b"]);
+    find_perm("-u+s", &["b"]);
+    find_perm("-g=r", &["a", "b"]);
+    find_perm("/go+w", &[]);
+    find_perm("/u=x,o=x", &["b"]);
+
+    run_test_find(
+        &[&test_dir, "-perm", "u+q"],
+        "",
+        "find: invalid argument 'u+q' to '-perm': invalid mode string: unexpected 'q' at offset 2, \
+         expected permission ('r', 'w', 'x', 'X', 's', 't'), permission copy ('u', 'g', 'o'), \
+         operator ('+', '-', '='), ',' or end of mode\n",
+        1,
+    );
+
+    std::fs::remove_dir_all(&test_dir).unwrap();
+}
+
+// a/b/f, c/g
+fn create_traversal_tree(name: &str) -> String {
+    let test_dir = format!("{}/{}", env!("CARGO_TARGET_TMPDIR"), name);
+    std::fs::create_dir_all(format!("{}/a/b", test_dir)).unwrap();
+    std::fs::create_dir_all(format!("{}/c", test_dir)).unwrap();
+    File::create(format!("{}/a/b/f", test_dir)).unwrap();
+    File::create(format!("{}/c/g", test_dir)).unwrap();
+    test_dir
+}
+
+fn paths(test_dir: &str, names: &[&str]) -> String {
+    names
+        .iter()
+        .map(|name| format!("{}{}\n", test_dir, name))
+        .collect()
+}
+
+#[test]
+fn find_depth_test() {
+    let test_dir = create_traversal_tree("find_depth");
+
+    let expected_output = paths(&test_dir, &["/a/b/f", "/a/b", "/a", "/c/g", "/c", ""]);
+    run_test_find(&[&test_dir, "-depth"], &expected_output, "", 0);
+
+    std::fs::remove_dir_all(&test_dir).unwrap();
+}
+
+#[test]
+fn find_prune_test() {
+    let test_dir = create_traversal_tree("find_prune");
+
+    let expected_output = paths(&test_dir, &["", "/c", "/c/g"]);
+    run_test_find(
+        &[&test_dir, "-name", "a", "-prune", "-o", "-print"],
+        &expected_output,
+        "",
+        0,
+    );
+
+    // The contents were already evaluated by the time a directory is pruned
+    let expected_output = paths(&test_dir, &["/a/b/f", "/a/b", "/c/g", "/c", ""]);
+    run_test_find(
+        &[&test_dir, "-depth", "-name", "a", "-prune", "-o", "-print"],
+        &expected_output,
+        "",
+        0,
+    );
+
+    std::fs::remove_dir_all(&test_dir).unwrap();
+}
+
+#[test]
+fn find_maxdepth_mindepth_test() {
+    let test_dir = create_traversal_tree("find_maxdepth_mindepth");
+
+    run_test_find(
+        &[&test_dir, "-maxdepth", "0"],
+        &paths(&test_dir, &[""]),
+        "",
+        0,
+    );
+    run_test_find(
+        &[&test_dir, "-maxdepth", "1"],
+        &paths(&test_dir, &["", "/a", "/c"]),
+        "",
+        0,
+    );
+    run_test_find(
+        &[&test_dir, "-mindepth", "2", "-maxdepth", "2"],
+        &paths(&test_dir, &["/a/b", "/c/g"]),
+        "",
+        0,
+    );
+    run_test_find(
+        &[&test_dir, "-depth", "-mindepth", "2"],
+        &paths(&test_dir, &["/a/b/f", "/a/b", "/c/g"]),
+        "",
+        0,
+    );
+    run_test_find(
+        &[&test_dir, "-maxdepth", "x"],
+        "",
+        "find: invalid argument 'x' to '-maxdepth'\n",
+        1,
+    );
+
+    std::fs::remove_dir_all(&test_dir).unwrap();
+}
+
+fn set_times(path: &str, accessed: SystemTime, modified: SystemTime) {
+    let times = FileTimes::new()
+        .set_accessed(accessed)
+        .set_modified(modified);
+    File::options()
+        .write(true)
+        .open(path)
+        .unwrap()
+        .set_times(times)
+        .unwrap();
+}
+
+#[test]
+fn find_time_rounding_test() {
+    let test_dir = format!("{}/find_time_rounding", env!("CARGO_TARGET_TMPDIR"));
+    std::fs::create_dir(&test_dir).unwrap();
+    let now = SystemTime::now();
+    let day = Duration::from_secs(86400);
+    let minute = Duration::from_secs(60);
+    // Just under a day, just over a day, just over two days and an hour in the future
+    let ages = [
+        ("a", now - (day - minute)),
+        ("b", now - (day + minute)),
+        ("c", now - (2 * day + minute)),
+        ("d", now + Duration::from_secs(3600)),
+    ];
+    for (name, time) in ages {
+        let path = format!("{}/{}", test_dir, name);
+        File::create(&path).unwrap();
+        set_times(&path, time, time);
+    }
+
+    let find_time = |primary: &str, n: &str, expected: &[&str]| {
+        let expected_output: String = expected
+            .iter()
+            .map(|name| format!("{}/{}\n", test_dir, name))
+            .collect();
+        run_test_find(
+            &[&test_dir, "-type", "f", primary, n],
+            &expected_output,
+            "",
+            0,
+        );
+    };
+    find_time("-mtime", "0", &["a", "d"]);
+    find_time("-mtime", "+0", &["b", "c"]);
+    find_time("-mtime", "1", &["b"]);
+    find_time("-mtime", "-1", &["a", "d"]);
+    find_time("-mtime", "-2", &["a", "b", "d"]);
+    find_time("-mtime", "+1", &["c"]);
+    find_time("-atime", "1", &["b"]);
+    find_time("-atime", "+1", &["c"]);
+
+    std::fs::remove_dir_all(&test_dir).unwrap();
+}
+
+#[test]
+fn find_size_rounding_test() {
+    let test_dir = format!("{}/find_size_rounding", env!("CARGO_TARGET_TMPDIR"));
+    std::fs::create_dir(&test_dir).unwrap();
+    for size in [0, 1, 512, 513, 1024, 1025] {
+        let file = File::create(format!("{}/s{}", test_dir, size)).unwrap();
+        file.set_len(size).unwrap();
+    }
+
+    let find_size = |n: &str, expected: &[&str]| {
+        let expected_output: String = expected
+            .iter()
+            .map(|name| format!("{}/{}\n", test_dir, name))
+            .collect();
+        run_test_find(
+            &[&test_dir, "-type", "f", "-size", n],
+            &expected_output,
+            "",
+            0,
+        );
+    };
+    // Without a suffix, sizes are in 512-byte blocks with any partial block counted as a whole
+    find_size("0", &["s0"]);
+    find_size("1", &["s1", "s512"]);
+    find_size("2", &["s1024", "s513"]);
+    find_size("-1", &["s0"]);
+    find_size("+1", &["s1024", "s1025", "s513"]);
+    find_size("512c", &["s512"]);
+    find_size("+512c", &["s1024", "s1025", "s513"]);
+    find_size("1k", &["s1", "s1024", "s512", "s513"]);
+    find_size("2k", &["s1025"]);
+    find_size("-1k", &["s0"]);
+
+    std::fs::remove_dir_all(&test_dir).unwrap();
+}
+
+#[test]
+fn find_newer_boundary_test() {
+    let test_dir = format!("{}/find_newer_boundary", env!("CARGO_TARGET_TMPDIR"));
+    std::fs::create_dir(&test_dir).unwrap();
+    let time = SystemTime::now() - Duration::from_secs(1000);
+    for (name, time) in [
+        ("ref", time),
+        ("same", time),
+        ("later", time + Duration::from_nanos(1)),
+    ] {
+        let path = format!("{}/{}", test_dir, name);
+        File::create(&path).unwrap();
+        set_times(&path, time, time);
+    }
+    let link = format!("{}/link", test_dir);
+    std::os::unix::fs::symlink("ref", &link).unwrap();
+
+    let reference = format!("{}/ref", test_dir);
+    run_test_find(
+        &[&test_dir, "-type", "f", "-newer", &reference],
+        &format!("{}/later\n", test_dir),
+        "",
+        0,
+    );
+
+    // The link itself was modified after all of the files, unless it's followed
+    run_test_find(&[&test_dir, "-type", "f", "-newer", &link], "", "", 0);
+    run_test_find(
+        &["-H", &test_dir, "-type", "f", "-newer", &link],
+        &format!("{}/later\n", test_dir),
+        "",
+        0,
+    );
+
+    std::fs::remove_dir_all(&test_dir).unwrap();
+}
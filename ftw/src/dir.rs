@@ -1,4 +1,4 @@
-use crate::{open_long_filename, Error, ErrorKind, FileDescriptor};
+use crate::{open_long_filename, Error, ErrorKind, FileDescriptor, FileType};
 use std::{
     cell::{RefCell, RefMut},
     collections::HashSet,
@@ -34,6 +34,23 @@ impl<'a> EntryInternal<'a> {
         }
     }
 
+    // The file type reported by `readdir`, if the file system reports it. Symlinks are not
+    // followed.
+    pub fn file_type(&self) -> Option<FileType> {
+        const OFFSET: isize = std::mem::offset_of!(libc::dirent, d_type) as isize;
+        let d_type = unsafe { self.dirent.byte_offset(OFFSET).cast::<u8>().read() };
+        match d_type {
+            libc::DT_DIR => Some(FileType::Directory),
+            libc::DT_REG => Some(FileType::RegularFile),
+            libc::DT_LNK => Some(FileType::SymbolicLink),
+            libc::DT_BLK => Some(FileType::BlockDevice),
+            libc::DT_CHR => Some(FileType::CharacterDevice),
+            libc::DT_FIFO => Some(FileType::Fifo),
+            libc::DT_SOCK => Some(FileType::Socket),
+            _ => None,
+        }
+    }
+
     pub fn is_dot_or_double_dot(&self) -> bool {
         const DOT: u8 = b'.';
 
@@ -42,6 +59,19 @@ impl<'a> EntryInternal<'a> {
     }
 }
 
+/// Flags for opening a directory to be read.
+///
+/// Without `follow_symlinks`, the open fails instead of following a symlink that replaced the
+/// directory since it was stat'ed.
+pub fn dir_open_flags(follow_symlinks: bool) -> i32 {
+    let flags = libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC;
+    if follow_symlinks {
+        flags
+    } else {
+        flags | libc::O_NOFOLLOW
+    }
+}
+
 /// RAII wrapper for a `*mut libc::DIR`.
 ///
 /// The state of the directory entry listing is preserved so this is more efficient than
@@ -79,10 +109,14 @@ impl OwnedDir {
     pub fn open_at(
         dir_file_descriptor: &FileDescriptor,
         filename: *const libc::c_char,
+        follow_symlinks: bool,
     ) -> Result<Self, Error> {
-        let file_descriptor =
-            FileDescriptor::open_at(dir_file_descriptor, filename, libc::O_RDONLY)
-                .map_err(|e| Error::new(e, ErrorKind::Open))?;
+        let file_descriptor = FileDescriptor::open_at(
+            dir_file_descriptor,
+            filename,
+            dir_open_flags(follow_symlinks),
+        )
+        .map_err(|e| Error::new(e, ErrorKind::Open))?;
         let dir = OwnedDir::new(file_descriptor).map_err(|e| Error::new(e, ErrorKind::OpenDir))?;
         Ok(dir)
     }
@@ -138,14 +172,20 @@ impl<'a> Iterator for OwnedDirIterator<'a> {
 pub struct DeferredDir {
     parent: Rc<(FileDescriptor, PathBuf)>,
     path: PathBuf,
+    follow_symlinks: bool,
     visited: RefCell<HashSet<libc::ino_t>>,
 }
 
 impl DeferredDir {
-    pub fn new(parent: Rc<(FileDescriptor, PathBuf)>, path: PathBuf) -> Self {
+    pub fn new(
+        parent: Rc<(FileDescriptor, PathBuf)>,
+        path: PathBuf,
+        follow_symlinks: bool,
+    ) -> Self {
         Self {
             parent,
             path,
+            follow_symlinks,
             visited: RefCell::new(HashSet::new()),
         }
     }
@@ -177,7 +217,12 @@ impl DeferredDir {
 
         let filename_cstr = CString::new(components.as_path().as_os_str().as_bytes()).unwrap();
 
-        FileDescriptor::open_at(&starting_dir, filename_cstr.as_ptr(), libc::O_RDONLY).unwrap()
+        FileDescriptor::open_at(
+            &starting_dir,
+            filename_cstr.as_ptr(),
+            dir_open_flags(self.follow_symlinks),
+        )
+        .unwrap()
     }
 
     pub fn parent(&self) -> &Rc<(FileDescriptor, PathBuf)> {
@@ -1,7 +1,12 @@
 mod dir;
+mod parallel;
+mod walk;
 
-use dir::{DeferredDir, HybridDir, OwnedDir};
+use dir::{dir_open_flags, DeferredDir, HybridDir, OwnedDir};
+pub use parallel::{ParallelWalk, ParallelWalker, WalkEntry};
 use std::{
+    cell::{Cell, RefCell},
+    collections::HashSet,
     ffi::{CStr, CString, OsStr},
     fmt, io,
     mem::MaybeUninit,
@@ -13,6 +18,7 @@ use std::{
     path::{Path, PathBuf},
     rc::Rc,
 };
+pub use walk::{DirEntry, Walk, Walker};
 
 /// Type of error to be handled by the `err_reporter` of `traverse_directory`.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -23,6 +29,9 @@ pub enum ErrorKind {
     Stat,
     ReadLink,
     DirNotSearchable,
+    /// A directory is one of its own ancestors, through a symlink that was followed or a bind
+    /// mount. The directory is not entered.
+    Cycle,
 }
 
 /// Wrapper for `std::io::Error` with additional context.
@@ -48,6 +57,94 @@ impl Error {
     }
 }
 
+/// What `traverse_directory` does after an error was passed to its `err_reporter`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ErrorAction {
+    /// Carry on with the next entry.
+    #[default]
+    Continue,
+    /// Skip the rest of the directory the error occurred in. For an error reading a directory,
+    /// that is the directory itself. The directory is not passed to `postprocess_dir`.
+    SkipSubtree,
+    /// Stop the traversal. No more callbacks are called.
+    Abort,
+}
+
+/// An error that was passed to the `err_reporter` of `traverse_directory`.
+#[derive(Debug, Clone)]
+pub struct ReportedError {
+    path: PathBuf,
+    kind: ErrorKind,
+    action: ErrorAction,
+}
+
+impl ReportedError {
+    /// Returns the path of the entry that caused the error.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Determines where in the algorithm the error occurred.
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// Returns what `err_reporter` decided to do about the error.
+    pub fn action(&self) -> ErrorAction {
+        self.action
+    }
+}
+
+/// Outcome of `traverse_directory`.
+#[derive(Debug)]
+pub struct TraversalReport {
+    errors: Vec<ReportedError>,
+    success: bool,
+    aborted: bool,
+}
+
+impl TraversalReport {
+    /// Check if the traversal had no errors. This is also `false` if the path given to
+    /// `traverse_directory` was not a directory or one of the callbacks returned `Err`.
+    pub fn is_success(&self) -> bool {
+        self.success
+    }
+
+    /// Check if the traversal was stopped by `ErrorAction::Abort`.
+    pub fn is_aborted(&self) -> bool {
+        self.aborted
+    }
+
+    /// Returns the errors passed to `err_reporter`, in the order they occurred.
+    pub fn errors(&self) -> &[ReportedError] {
+        &self.errors
+    }
+}
+
+/// Error produced by `Walk` and `ParallelWalk`, along with the path it occurred at.
+#[derive(Debug)]
+pub struct WalkError {
+    path: PathBuf,
+    error: Error,
+}
+
+impl WalkError {
+    /// Returns the path of the entry that caused the error.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Determines where in the algorithm the error occurred.
+    pub fn kind(&self) -> ErrorKind {
+        self.error.kind()
+    }
+
+    /// Deconstruct to the contained `Error`.
+    pub fn into_error(self) -> Error {
+        self.error
+    }
+}
+
 /// RAII wrapper for a raw file descriptor.
 #[derive(Debug)]
 pub struct FileDescriptor {
@@ -140,6 +237,11 @@ impl Metadata {
         Ok(Metadata(unsafe { statbuf.assume_init() }))
     }
 
+    // Uniquely identifies the file in the system
+    fn identity(&self) -> (libc::dev_t, libc::ino_t) {
+        (self.0.st_dev, self.0.st_ino)
+    }
+
     /// Query the file type.
     pub fn file_type(&self) -> FileType {
         match self.0.st_mode & libc::S_IFMT {
@@ -290,6 +392,7 @@ pub struct Entry<'a> {
     metadata: Option<&'a Metadata>,
     is_symlink: Option<bool>,
     read_link: Option<Rc<[libc::c_char]>>,
+    is_mount_point: bool,
 }
 
 impl<'a> Entry<'a> {
@@ -306,10 +409,12 @@ impl<'a> Entry<'a> {
             metadata,
             is_symlink: None,
             read_link: None,
+            is_mount_point: false,
         }
     }
 
-    /// Returns the file descriptor of the containing directory.
+    /// Returns the file descriptor of the containing directory. Operating on the entry relative to
+    /// it, with `libc::unlinkat` or `libc::fchmodat` and `file_name`, works at any depth.
     pub fn dir_fd(&self) -> libc::c_int {
         self.dir_file_descriptor.fd
     }
@@ -333,6 +438,13 @@ impl<'a> Entry<'a> {
         self.is_symlink
     }
 
+    /// Check if this entry is on a different file system than the starting path. Directories on
+    /// other file systems are not entered, so this is only ever `true` when the traversal is
+    /// restricted to one file system.
+    pub fn is_mount_point(&self) -> bool {
+        self.is_mount_point
+    }
+
     /// Reads the symbolic link.
     pub fn read_link(&self) -> Option<&CStr> {
         self.read_link
@@ -347,12 +459,12 @@ impl<'a> Entry<'a> {
         DisplayablePath(build_path(self.path_stack, self.filename))
     }
 
-    /// Check if this `Entry` is an empty directory.
+    /// Check if this `Entry` is an empty directory. Symlinks are not followed.
     pub fn is_empty_dir(&self) -> io::Result<bool> {
         let file_descriptor = FileDescriptor::open_at(
             self.dir_file_descriptor,
             self.file_name().as_ptr(),
-            libc::O_RDONLY,
+            dir_open_flags(false),
         )?;
         match OwnedDir::new(file_descriptor) {
             Ok(dir) => {
@@ -421,10 +533,21 @@ enum ProcessFileResult {
     Skipped,
 }
 
+// The error reported for `ErrorKind::Cycle`
+fn cycle_error() -> Error {
+    Error::new(
+        io::Error::other("File system loop detected"),
+        ErrorKind::Cycle,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
 fn process_file<F, H>(
     path_stack: &[Rc<[libc::c_char]>],
     dir_fd: &FileDescriptor,
     entry_filename: Rc<[libc::c_char]>,
+    ancestors: &HashSet<(libc::dev_t, libc::ino_t)>,
+    file_system: Option<libc::dev_t>,
     follow_symlinks: bool,
     file_handler: &mut F,
     err_reporter: &mut H,
@@ -483,10 +606,22 @@ where
     let mut entry = Entry::new(dir_fd, path_stack, &entry_filename, Some(&entry_metadata));
     entry.is_symlink = Some(is_symlink);
     entry.read_link = entry_readlink;
+    entry.is_mount_point = file_system.is_some_and(|dev| entry_metadata.0.st_dev != dev);
+
+    // Entering a directory that is also an ancestor would never end
+    if entry_metadata.file_type() == FileType::Directory
+        && ancestors.contains(&entry_metadata.identity())
+    {
+        err_reporter(entry, cycle_error());
+        return ProcessFileResult::NotProcessed;
+    }
 
     match file_handler(entry.clone()) {
         Ok(true) => {
-            if entry_metadata.file_type() == FileType::Directory {
+            if entry_metadata.file_type() == FileType::Directory && entry.is_mount_point {
+                // Stay on the file system of the starting path
+                ProcessFileResult::Skipped
+            } else if entry_metadata.file_type() == FileType::Directory {
                 // Is the directory searchable?
                 if entry_metadata.is_executable() {
                     if conserve_fds {
@@ -494,7 +629,13 @@ where
                             entry_metadata,
                         ))
                     } else {
-                        match OwnedDir::open_at(dir_fd, entry_filename.as_ptr()) {
+                        let new_dir =
+                            OwnedDir::open_at(dir_fd, entry_filename.as_ptr(), follow_symlinks)
+                                .and_then(|dir| {
+                                    check_same_file(dir.file_descriptor(), &entry_metadata)?;
+                                    Ok(dir)
+                                });
+                        match new_dir {
                             Ok(new_dir) => ProcessFileResult::ProcessedDirectory(
                                 NodeOrMetadata::TreeNode(TreeNode {
                                     dir: HybridDir::Owned(new_dir),
@@ -527,6 +668,24 @@ where
     }
 }
 
+// Make sure that the directory opened is the one that was stat'ed and not one that was moved in
+// its place in the meantime.
+fn check_same_file(file_descriptor: &FileDescriptor, metadata: &Metadata) -> Result<(), Error> {
+    let mut statbuf = MaybeUninit::uninit();
+    let ret = unsafe { libc::fstat(file_descriptor.fd, statbuf.as_mut_ptr()) };
+    if ret != 0 {
+        return Err(Error::new(io::Error::last_os_error(), ErrorKind::Stat));
+    }
+    let statbuf = unsafe { statbuf.assume_init() };
+
+    if statbuf.st_dev == metadata.0.st_dev && statbuf.st_ino == metadata.0.st_ino {
+        Ok(())
+    } else {
+        let e = io::Error::other("directory changed during traversal");
+        Err(Error::new(e, ErrorKind::Open))
+    }
+}
+
 fn open_long_filename<'a, H>(
     mut starting_dir: FileDescriptor,
     path: &'a Path,
@@ -595,11 +754,38 @@ where
     Some((starting_dir, path_components))
 }
 
+// Max allowable open file descriptors
+fn fd_rlim_cur() -> libc::rlim_t {
+    unsafe {
+        let mut rlim = MaybeUninit::uninit();
+        let ret = libc::getrlimit(libc::RLIMIT_NOFILE, rlim.as_mut_ptr());
+        if ret != 0 {
+            panic!("{}", io::Error::last_os_error());
+        }
+        let rlim = rlim.assume_init();
+
+        rlim.rlim_cur
+    }
+}
+
 /// Walk through a directory tree.
 ///
 /// The `file_handler` handles the processing of each entry encountered, starting with `path`
 /// itself. There is no definite order of processing of entries as this function delegates to
-/// `libc::readdir`.
+/// `libc::readdir`. A directory is passed to `postprocess_dir` after all of its contents, so
+/// removing entries there proceeds from the leaves up.
+///
+/// Subdirectories are opened relative to the file descriptor of their parent, so the depth of the
+/// tree is not limited by `libc::PATH_MAX`. Unless `follow_symlinks` is set, a symlink is never
+/// opened as a directory, even if it appears in place of a directory during the traversal. The
+/// callbacks should likewise operate on an entry relative to `Entry::dir_fd`.
+///
+/// A directory that is the same as one of its ancestors, which can happen when following symlinks
+/// or through bind mounts, is reported to `err_reporter` with `ErrorKind::Cycle` instead of being
+/// passed to `file_handler`, and the traversal continues with the next entry.
+///
+/// If `one_file_system` is set, directories on a different file system than `path` are passed to
+/// `file_handler` with `Entry::is_mount_point` returning `true`, but their contents are skipped.
 ///
 /// # Arguments
 /// * `path` - Pathname of the directory. Passing a file to this argument will cause the function to
@@ -612,16 +798,18 @@ where
 ///
 /// * `postprocess_dir` - Called when `traverse_directory` is exiting a directory.
 ///
-/// * `err_reporter` - Callback for reporting the errors encountered during the directory traversal.
+/// * `err_reporter` - Callback for the errors encountered, deciding how the traversal proceeds.
 ///
 /// * `follow_symlinks_on_args` - Whether to dereference `path` if it's a symlink.
 ///
 /// * `follow_symlinks` - Dereference symlinks encountered (also including `path`).
 ///
+/// * `one_file_system` - Don't enter directories on a different file system than `path`.
+///
 /// # Return
 ///
-/// This function can return multiple errors via the `err_reporter` argument so its return value is
-/// a bool indicating no errors occurred (`true`) or there is at least one error (`false`).
+/// The errors are passed to the `err_reporter` argument as they occur. The returned report lists
+/// them again along with the actions taken, and tells whether the traversal had no errors.
 pub fn traverse_directory<P, F, G, H>(
     path: P,
     mut file_handler: F,
@@ -629,26 +817,51 @@ pub fn traverse_directory<P, F, G, H>(
     mut err_reporter: H,
     follow_symlinks_on_args: bool,
     follow_symlinks: bool,
-) -> bool
+    one_file_system: bool,
+) -> TraversalReport
 where
     P: AsRef<Path>,
     F: FnMut(Entry<'_>) -> Result<bool, ()>,
     G: FnMut(Entry<'_>) -> Result<(), ()>,
-    H: FnMut(Entry<'_>, Error),
+    H: FnMut(Entry<'_>, Error) -> ErrorAction,
 {
+    // The errors for the report. `action` is the strongest one asked for since it was last taken.
+    let errors = RefCell::new(Vec::new());
+    let action = Cell::new(ErrorAction::Continue);
+    let mut report_error = |entry: Entry<'_>, error: Error| {
+        let path = build_path(entry.path_stack, entry.filename);
+        let kind = error.kind();
+        let entry_action = err_reporter(entry, error);
+        errors.borrow_mut().push(ReportedError {
+            path,
+            kind,
+            action: entry_action,
+        });
+        action.set(action.get().max(entry_action));
+    };
+    let finish = |success: bool, aborted: bool| TraversalReport {
+        errors: errors.take(),
+        success,
+        aborted,
+    };
+
     // Stack of the directories to process
     let mut stack: Vec<TreeNode> = Vec::new();
     // Stack of the filename (relative to CWD). Updated in sync with `stack` above
     let mut path_stack: Vec<Rc<[libc::c_char]>> = Vec::new();
+    // Identities of the directories in `stack`, to detect cycles
+    let mut ancestors: HashSet<(libc::dev_t, libc::ino_t)> = HashSet::new();
+    // The file system of `path` if the traversal is restricted to it
+    let mut file_system = None;
 
     let (starting_dir, path_components) = match open_long_filename(
         FileDescriptor::cwd(),
         path.as_ref(),
         Some(&mut path_stack),
-        &mut err_reporter,
+        &mut report_error,
     ) {
         Some(pair) => pair,
-        None => return false,
+        None => return finish(false, action.get() == ErrorAction::Abort),
     };
 
     {
@@ -660,22 +873,30 @@ where
             &path_stack,
             &starting_dir,
             dir_filename,
+            &ancestors,
+            None,
             follow_symlinks_on_args || follow_symlinks,
             &mut file_handler,
-            &mut err_reporter,
+            &mut report_error,
             false,
         ) {
             ProcessFileResult::ProcessedDirectory(node) => match node {
-                NodeOrMetadata::TreeNode(node) => stack.push(node),
+                NodeOrMetadata::TreeNode(node) => {
+                    ancestors.insert(node.metadata.identity());
+                    if one_file_system {
+                        file_system = Some(node.metadata.0.st_dev);
+                    }
+                    stack.push(node);
+                }
                 NodeOrMetadata::Metadata(_) => unreachable!(),
             },
             ProcessFileResult::ProcessedFile => {
                 // `path` was not a directory
-                return false;
+                return finish(false, false);
             }
             ProcessFileResult::NotProcessed => {
                 // Signal an error
-                return false;
+                return finish(false, action.get() == ErrorAction::Abort);
             }
             ProcessFileResult::Skipped => (), // Do nothing
         }
@@ -683,20 +904,8 @@ where
 
     let mut success = true;
 
-    // Max allowable open file descriptors
-    let fd_rlim_cur = unsafe {
-        let mut rlim = MaybeUninit::uninit();
-        let ret = libc::getrlimit(libc::RLIMIT_NOFILE, rlim.as_mut_ptr());
-        if ret != 0 {
-            panic!("{}", io::Error::last_os_error());
-        }
-        let rlim = rlim.assume_init();
-
-        rlim.rlim_cur
-    };
-
     // Subtract a few to allow for some FD bookkeeping
-    let fd_threshold: usize = fd_rlim_cur as usize - 7;
+    let fd_threshold: usize = fd_rlim_cur() as usize - 7;
 
     // Depth first traversal main loop
     'outer: loop {
@@ -715,6 +924,9 @@ where
         // directory's filename.
         path_stack.push(current.filename.clone());
 
+        // Set when an error skips the rest of the directory
+        let mut skip_rest = false;
+
         {
             let mut dir_iter = dir.iter();
 
@@ -733,7 +945,7 @@ where
                             },
                             None => &starting_dir,
                         };
-                        err_reporter(
+                        report_error(
                             Entry::new(
                                 prev_dir,
                                 // Need to report the filename of the directory itself so exclude
@@ -746,7 +958,14 @@ where
                         );
 
                         success = false;
-                        continue;
+                        match action.take() {
+                            ErrorAction::Continue => continue,
+                            ErrorAction::SkipSubtree => {
+                                skip_rest = true;
+                                break;
+                            }
+                            ErrorAction::Abort => return finish(false, true),
+                        }
                     }
                 };
 
@@ -769,15 +988,23 @@ where
                     &path_stack,
                     dir_fd,
                     entry_filename.clone(),
+                    &ancestors,
+                    file_system,
                     follow_symlinks,
                     &mut file_handler,
-                    &mut err_reporter,
+                    &mut report_error,
                     conserve_fds,
                 ) {
                     ProcessFileResult::ProcessedDirectory(node) => {
                         // `dir_iter` has a dependency on `stack` so run it's `Drop` method first
                         std::mem::drop(dir_iter);
 
+                        let metadata = match &node {
+                            NodeOrMetadata::TreeNode(node) => &node.metadata,
+                            NodeOrMetadata::Metadata(metadata) => metadata,
+                        };
+                        ancestors.insert(metadata.identity());
+
                         match node {
                             NodeOrMetadata::TreeNode(node) => stack.push(node),
                             NodeOrMetadata::Metadata(metadata) => match dir {
@@ -789,6 +1016,7 @@ where
                                             path.parent().unwrap().to_path_buf(),
                                         )),
                                         path,
+                                        follow_symlinks,
                                     );
                                     stack.push(TreeNode {
                                         dir: HybridDir::Deferred(slow_dir),
@@ -800,6 +1028,7 @@ where
                                     let slow_dir = DeferredDir::new(
                                         current_dir.parent().clone(),
                                         build_path(&path_stack, &entry_filename),
+                                        follow_symlinks,
                                     );
                                     stack.push(TreeNode {
                                         dir: HybridDir::Deferred(slow_dir),
@@ -814,6 +1043,14 @@ where
                     }
                     ProcessFileResult::NotProcessed => {
                         success = false;
+                        match action.take() {
+                            ErrorAction::Continue => (),
+                            ErrorAction::SkipSubtree => {
+                                skip_rest = true;
+                                break;
+                            }
+                            ErrorAction::Abort => return finish(false, true),
+                        }
                     }
                     ProcessFileResult::ProcessedFile | ProcessFileResult::Skipped => (),
                 }
@@ -831,22 +1068,26 @@ where
             },
             None => &starting_dir,
         };
-        if let Err(_) = postprocess_dir(Entry::new(
-            prev_dir,
-            &path_stack,
-            &current.filename,
-            Some(&current.metadata),
-        )) {
+        if !skip_rest
+            && postprocess_dir(Entry::new(
+                prev_dir,
+                &path_stack,
+                &current.filename,
+                Some(&current.metadata),
+            ))
+            .is_err()
+        {
             success = false;
             // Don't `continue` here, falldown below
         }
 
         // Go up a level in the tree
         path_stack.pop();
-        stack.pop().unwrap();
+        let node = stack.pop().unwrap();
+        ancestors.remove(&node.metadata.identity());
     }
 
-    success
+    finish(success, false)
 }
 
 fn cstring_to_rc(filename: &CStr) -> Rc<[libc::c_char]> {
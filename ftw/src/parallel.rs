@@ -0,0 +1,535 @@
+//! Multi-threaded traversal of directory trees.
+//!
+//! `ParallelWalker` walks independent subtrees on a bounded pool of threads. Each thread has a
+//! queue of directories to read: it takes the most recently found directory from its own queue,
+//! which keeps its part of the walk depth-first, and when that runs dry steals the oldest
+//! directory from another thread's queue, which tends to be the root of a large subtree.
+//!
+//! Entries are streamed to the consumer through a bounded channel as they're found, so the
+//! consumer can process them while the walk continues and memory use stays flat for large trees.
+//! There is no definite order of the entries, except that a directory is produced before its
+//! contents.
+
+use crate::{check_same_file, cycle_error, dir::dir_open_flags, dir::OwnedDir};
+use crate::{Error, ErrorKind, FileDescriptor, FileType, Metadata, WalkError};
+use std::{
+    collections::VecDeque,
+    ffi::{CStr, CString, OsStr},
+    io,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::{self, Receiver, SyncSender},
+        Arc, Condvar, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+// Number of entries that can be waiting for the consumer before the walk pauses
+const DEFAULT_CHANNEL_BOUND: usize = 1024;
+
+// How long an idle thread waits before checking for work again, in case a wakeup was missed
+const IDLE_TIMEOUT: Duration = Duration::from_millis(10);
+
+/// An entry found by `ParallelWalker`.
+///
+/// Unlike `Entry`, this owns its data so that it can be passed between threads.
+#[derive(Debug, Clone)]
+pub struct WalkEntry {
+    path: PathBuf,
+    parent: Arc<FileDescriptor>,
+    name: CString,
+    depth: usize,
+    metadata: Metadata,
+    is_symlink: bool,
+    is_mount_point: bool,
+}
+
+impl WalkEntry {
+    /// Returns the path, starting with the path given to the walker.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the file name, or the whole path for the starting points of the walk.
+    pub fn file_name(&self) -> &OsStr {
+        if self.depth == 0 {
+            self.path.as_os_str()
+        } else {
+            self.path.file_name().unwrap_or(self.path.as_os_str())
+        }
+    }
+
+    /// Returns the file descriptor of the directory containing this entry, to use with the `*at`
+    /// functions along with `file_name_cstr`. This is `AT_FDCWD` for the paths given to the
+    /// walker.
+    pub fn dir_fd(&self) -> libc::c_int {
+        self.parent.fd
+    }
+
+    /// Returns the file name as a C string, or the whole path for the paths given to the walker.
+    pub fn file_name_cstr(&self) -> &CStr {
+        &self.name
+    }
+
+    /// Returns the depth in the tree, which is 0 for the paths given to the walker.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Returns the metadata of this entry.
+    ///
+    /// This is either the metadata of the file itself or the metadata of the file it points to.
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    /// Check if this entry is a symlink.
+    pub fn is_symlink(&self) -> bool {
+        self.is_symlink
+    }
+
+    /// Check if this entry is on a different file system than the path given to the walker it was
+    /// found under. This is only ever `true` with `ParallelWalker::one_file_system`.
+    pub fn is_mount_point(&self) -> bool {
+        self.is_mount_point
+    }
+}
+
+type DescendFilter = dyn Fn(&WalkEntry) -> bool + Send + Sync;
+
+/// Builder for a multi-threaded walk through one or more directory trees.
+pub struct ParallelWalker {
+    paths: Vec<PathBuf>,
+    threads: usize,
+    follow_symlinks_on_args: bool,
+    follow_symlinks: bool,
+    one_file_system: bool,
+    channel_bound: usize,
+    descend_filter: Option<Arc<DescendFilter>>,
+}
+
+impl ParallelWalker {
+    /// Create a walker for the trees rooted at `paths`. By default, it uses as many threads as
+    /// there are CPUs available and doesn't follow symlinks.
+    pub fn new<I, P>(paths: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        Self {
+            paths: paths
+                .into_iter()
+                .map(|p| p.as_ref().to_path_buf())
+                .collect(),
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            follow_symlinks_on_args: false,
+            follow_symlinks: false,
+            one_file_system: false,
+            channel_bound: DEFAULT_CHANNEL_BOUND,
+            descend_filter: None,
+        }
+    }
+
+    /// Set the number of threads to walk with. At least one thread is used.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Whether to dereference the paths given to `new` if they are symlinks.
+    pub fn follow_symlinks_on_args(mut self, follow: bool) -> Self {
+        self.follow_symlinks_on_args = follow;
+        self
+    }
+
+    /// Dereference symlinks encountered (also including the paths given to `new`).
+    pub fn follow_symlinks(mut self, follow: bool) -> Self {
+        self.follow_symlinks = follow;
+        self
+    }
+
+    /// Don't enter directories on a different file system than the path given to `new` they were
+    /// found under. These are still produced, with `WalkEntry::is_mount_point` returning `true`.
+    pub fn one_file_system(mut self, one_file_system: bool) -> Self {
+        self.one_file_system = one_file_system;
+        self
+    }
+
+    /// Set the number of entries that can be found ahead of the consumer.
+    pub fn channel_bound(mut self, bound: usize) -> Self {
+        self.channel_bound = bound;
+        self
+    }
+
+    /// Decide whether the contents of a directory are walked. `filter` is called on the walking
+    /// threads for each directory found, before the directory is produced by the iterator.
+    pub fn descend_filter<F>(mut self, filter: F) -> Self
+    where
+        F: Fn(&WalkEntry) -> bool + Send + Sync + 'static,
+    {
+        self.descend_filter = Some(Arc::new(filter));
+        self
+    }
+
+    /// Start the walk. The returned iterator yields the entries as they are found. Dropping it
+    /// stops the walk.
+    pub fn walk(self) -> ParallelWalk {
+        let cwd = Arc::new(FileDescriptor::cwd());
+
+        let mut queues: Vec<_> = (0..self.threads).map(|_| VecDeque::new()).collect();
+        for (i, path) in self.paths.iter().enumerate() {
+            // A path with a NUL byte can't exist, so make it fail with "No such file or directory"
+            let name = CString::new(path.as_os_str().as_bytes()).unwrap_or_default();
+            queues[i % self.threads].push_back(Work {
+                parent: cwd.clone(),
+                name,
+                path: path.clone(),
+                depth: 0,
+                metadata: None,
+                ancestors: None,
+                file_system: None,
+            });
+        }
+
+        let shared = Arc::new(Shared {
+            pending: AtomicUsize::new(self.paths.len()),
+            queues: queues.into_iter().map(Mutex::new).collect(),
+            cancelled: AtomicBool::new(false),
+            idle: Mutex::new(()),
+            wakeup: Condvar::new(),
+            follow_symlinks_on_args: self.follow_symlinks_on_args,
+            follow_symlinks: self.follow_symlinks,
+            one_file_system: self.one_file_system,
+            descend_filter: self.descend_filter,
+        });
+
+        let (sender, receiver) = mpsc::sync_channel(self.channel_bound);
+        let handles = (0..self.threads)
+            .map(|id| {
+                let shared = shared.clone();
+                let sender = sender.clone();
+                thread::spawn(move || Worker { id, shared, sender }.run())
+            })
+            .collect();
+
+        ParallelWalk {
+            receiver,
+            handles,
+            shared,
+        }
+    }
+}
+
+/// Iterator over the entries of a walk started by `ParallelWalker::walk`.
+pub struct ParallelWalk {
+    receiver: Receiver<Result<WalkEntry, WalkError>>,
+    handles: Vec<JoinHandle<()>>,
+    shared: Arc<Shared>,
+}
+
+impl Iterator for ParallelWalk {
+    type Item = Result<WalkEntry, WalkError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.receiver.recv() {
+            Ok(item) => Some(item),
+            Err(_) => {
+                // All threads are done, pass on a panic in `descend_filter`
+                for handle in self.handles.drain(..) {
+                    if let Err(e) = handle.join() {
+                        std::panic::resume_unwind(e);
+                    }
+                }
+                None
+            }
+        }
+    }
+}
+
+impl Drop for ParallelWalk {
+    fn drop(&mut self) {
+        // Threads blocked on sending an entry stop once the receiver is dropped
+        self.shared.cancel();
+    }
+}
+
+// A directory to be read, or one of the paths given to the walker if `metadata` is `None`
+struct Work {
+    parent: Arc<FileDescriptor>,
+    name: CString,
+    path: PathBuf,
+    depth: usize,
+    metadata: Option<Metadata>,
+    ancestors: Option<Arc<Ancestor>>,
+    // The file system the walk is restricted to, for all but the paths given to the walker
+    file_system: Option<libc::dev_t>,
+}
+
+// The directories above a `Work`, shared by all the subdirectories of a directory
+struct Ancestor {
+    identity: (libc::dev_t, libc::ino_t),
+    parent: Option<Arc<Ancestor>>,
+}
+
+impl Ancestor {
+    fn contains(this: &Option<Arc<Ancestor>>, identity: (libc::dev_t, libc::ino_t)) -> bool {
+        let mut ancestor = this;
+        while let Some(node) = ancestor {
+            if node.identity == identity {
+                return true;
+            }
+            ancestor = &node.parent;
+        }
+        false
+    }
+}
+
+struct Shared {
+    queues: Vec<Mutex<VecDeque<Work>>>,
+    // Work that has been queued but not finished. Children are queued before their parent is
+    // finished so this only drops to 0 at the end of the walk.
+    pending: AtomicUsize,
+    cancelled: AtomicBool,
+    idle: Mutex<()>,
+    wakeup: Condvar,
+    follow_symlinks_on_args: bool,
+    follow_symlinks: bool,
+    one_file_system: bool,
+    descend_filter: Option<Arc<DescendFilter>>,
+}
+
+impl Shared {
+    fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        let _guard = self.idle.lock().unwrap_or_else(|e| e.into_inner());
+        self.wakeup.notify_all();
+    }
+
+    fn is_done(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst) || self.pending.load(Ordering::SeqCst) == 0
+    }
+
+    fn should_descend(&self, entry: &WalkEntry) -> bool {
+        entry.metadata.file_type() == FileType::Directory
+            && !entry.is_mount_point
+            && self.descend_filter.as_ref().is_none_or(|f| f(entry))
+    }
+}
+
+struct Worker {
+    id: usize,
+    shared: Arc<Shared>,
+    sender: SyncSender<Result<WalkEntry, WalkError>>,
+}
+
+// Stops the walk if `descend_filter` panics, instead of leaving the other threads waiting for
+// work that will never finish
+struct PanicGuard<'a>(&'a Shared);
+
+impl Drop for PanicGuard<'_> {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            self.0.cancel();
+        }
+    }
+}
+
+impl Worker {
+    fn run(self) {
+        let _guard = PanicGuard(&self.shared);
+
+        loop {
+            if let Some(work) = self.pop() {
+                let result = self.process(work);
+                if self.shared.pending.fetch_sub(1, Ordering::SeqCst) == 1 {
+                    // That was the last of the work, wake up the other threads to exit
+                    self.shared.cancel();
+                }
+                if result.is_err() {
+                    // The consumer is gone
+                    self.shared.cancel();
+                }
+                continue;
+            }
+
+            let guard = self.shared.idle.lock().unwrap_or_else(|e| e.into_inner());
+            if self.shared.is_done() {
+                break;
+            }
+            let _ = self.shared.wakeup.wait_timeout(guard, IDLE_TIMEOUT);
+        }
+    }
+
+    // Take work from the back of this thread's queue or steal it from the front of another's
+    fn pop(&self) -> Option<Work> {
+        if self.shared.cancelled.load(Ordering::SeqCst) {
+            return None;
+        }
+
+        let queues = &self.shared.queues;
+        if let Some(work) = queues[self.id].lock().unwrap().pop_back() {
+            return Some(work);
+        }
+        (1..queues.len())
+            .map(|offset| (self.id + offset) % queues.len())
+            .find_map(|victim| queues[victim].lock().unwrap().pop_front())
+    }
+
+    fn push(&self, work: Work) {
+        self.shared.pending.fetch_add(1, Ordering::SeqCst);
+        self.shared.queues[self.id].lock().unwrap().push_back(work);
+        self.shared.wakeup.notify_one();
+    }
+
+    fn send(&self, item: Result<WalkEntry, WalkError>) -> Result<(), ()> {
+        self.sender.send(item).map_err(|_| ())
+    }
+
+    fn send_error(&self, path: PathBuf, error: Error) -> Result<(), ()> {
+        self.send(Err(WalkError { path, error }))
+    }
+
+    // Stat `name` relative to `dir_fd`, following symlinks if requested
+    fn stat(
+        &self,
+        dir_fd: &FileDescriptor,
+        name: &CStr,
+        follow_symlinks: bool,
+    ) -> io::Result<(Metadata, bool)> {
+        let symlink_metadata = Metadata::new(dir_fd.fd, name.as_ptr(), false)?;
+        let is_symlink = symlink_metadata.file_type() == FileType::SymbolicLink;
+        if !(is_symlink && follow_symlinks) {
+            return Ok((symlink_metadata, is_symlink));
+        }
+
+        match Metadata::new(dir_fd.fd, name.as_ptr(), true) {
+            Ok(md) => Ok((md, is_symlink)),
+            // Don't treat dangling links as an error, use the metadata of the original
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok((symlink_metadata, is_symlink)),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn process(&self, work: Work) -> Result<(), ()> {
+        let metadata = match work.metadata {
+            Some(metadata) => metadata,
+            None => {
+                // One of the paths given to the walker
+                let follow = self.shared.follow_symlinks_on_args || self.shared.follow_symlinks;
+                let (metadata, is_symlink) = match self.stat(&work.parent, &work.name, follow) {
+                    Ok(pair) => pair,
+                    Err(e) => return self.send_error(work.path, Error::new(e, ErrorKind::Stat)),
+                };
+                let entry = WalkEntry {
+                    path: work.path.clone(),
+                    parent: work.parent.clone(),
+                    name: work.name.clone(),
+                    depth: 0,
+                    metadata: metadata.clone(),
+                    is_symlink,
+                    is_mount_point: false,
+                };
+                let descend = self.shared.should_descend(&entry);
+                self.send(Ok(entry))?;
+                if !descend {
+                    return Ok(());
+                }
+                metadata
+            }
+        };
+
+        let follow =
+            self.shared.follow_symlinks || (work.depth == 0 && self.shared.follow_symlinks_on_args);
+        let file_descriptor =
+            match FileDescriptor::open_at(&work.parent, work.name.as_ptr(), dir_open_flags(follow))
+            {
+                Ok(fd) => fd,
+                Err(e) => return self.send_error(work.path, Error::new(e, ErrorKind::Open)),
+            };
+        drop(work.parent);
+        if let Err(error) = check_same_file(&file_descriptor, &metadata) {
+            return self.send_error(work.path, error);
+        }
+        let file_system = match work.file_system {
+            Some(dev) => Some(dev),
+            None if self.shared.one_file_system => Some(metadata.0.st_dev),
+            None => None,
+        };
+        let ancestors = Some(Arc::new(Ancestor {
+            identity: metadata.identity(),
+            parent: work.ancestors,
+        }));
+
+        // Subdirectories are opened relative to this one, using a duplicate of the file
+        // descriptor as `OwnedDir` closes its own
+        let dir_fd = Arc::new(file_descriptor.clone());
+        let dir = match OwnedDir::new(file_descriptor) {
+            Ok(dir) => dir,
+            Err(e) => return self.send_error(work.path, Error::new(e, ErrorKind::OpenDir)),
+        };
+
+        for entry_or_err in dir.iter() {
+            let entry = match entry_or_err {
+                Ok(entry) => entry,
+                Err(e) => return self.send_error(work.path, Error::new(e, ErrorKind::ReadDir)),
+            };
+            if entry.is_dot_or_double_dot() {
+                continue;
+            }
+
+            let name = entry.name_cstr().to_owned();
+            let path = work.path.join(OsStr::from_bytes(name.to_bytes()));
+
+            let (metadata, is_symlink) =
+                match self.stat(&dir_fd, &name, self.shared.follow_symlinks) {
+                    Ok(pair) => pair,
+                    Err(e) => {
+                        self.send_error(path, Error::new(e, ErrorKind::Stat))?;
+                        continue;
+                    }
+                };
+
+            // Entering a directory that is also an ancestor would never end
+            if metadata.file_type() == FileType::Directory
+                && Ancestor::contains(&ancestors, metadata.identity())
+            {
+                self.send_error(path, cycle_error())?;
+                continue;
+            }
+
+            let walk_entry = WalkEntry {
+                path,
+                parent: dir_fd.clone(),
+                name: name.clone(),
+                depth: work.depth + 1,
+                is_mount_point: file_system.is_some_and(|dev| metadata.0.st_dev != dev),
+                metadata,
+                is_symlink,
+            };
+
+            // Produce the directory before anything inside it can be
+            let subdir = if self.shared.should_descend(&walk_entry) {
+                Some(Work {
+                    parent: dir_fd.clone(),
+                    name,
+                    path: walk_entry.path.clone(),
+                    depth: walk_entry.depth,
+                    metadata: Some(walk_entry.metadata.clone()),
+                    ancestors: ancestors.clone(),
+                    file_system,
+                })
+            } else {
+                None
+            };
+            self.send(Ok(walk_entry))?;
+            if let Some(subdir) = subdir {
+                self.push(subdir);
+            }
+        }
+
+        Ok(())
+    }
+}
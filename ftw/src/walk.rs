@@ -0,0 +1,624 @@
+//! Single-threaded, pull-based traversal of a directory tree.
+//!
+//! `Walker` produces the entries of a tree from an iterator instead of passing them to callbacks,
+//! so the consumer can interleave the walk with its own output and stop at any point by dropping
+//! the iterator. A directory is produced before its contents, or after them with
+//! `Walker::contents_first`.
+//!
+//! Files are only stat'ed when needed. The file type returned by `readdir` is usually enough to
+//! find the directories to enter, and the metadata of the other entries is read when the consumer
+//! first asks for it.
+
+use crate::{check_same_file, cycle_error, dir::dir_open_flags, dir::OwnedDir};
+use crate::{fd_rlim_cur, open_long_filename};
+use crate::{Error, ErrorKind, FileDescriptor, FileType, Metadata, WalkError};
+use std::{
+    cell::OnceCell,
+    collections::HashSet,
+    ffi::{CStr, CString, OsStr},
+    io,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+/// An entry found by `Walk`.
+///
+/// Unlike `Entry`, this owns its data so it can be kept after the walk has moved on. The directory
+/// containing the entry stays open for as long as the entry exists.
+#[derive(Debug)]
+pub struct DirEntry {
+    parent: Rc<FileDescriptor>,
+    name: CString,
+    path: PathBuf,
+    depth: usize,
+    follow_symlinks: bool,
+    // The file type reported by `readdir`, without following symlinks
+    file_type: Option<FileType>,
+    symlink_metadata: OnceCell<Metadata>,
+    metadata: OnceCell<Metadata>,
+    is_mount_point: bool,
+}
+
+impl DirEntry {
+    /// Returns the file descriptor of the containing directory. Operating on the entry relative to
+    /// it, with `libc::unlinkat` or `libc::fchmodat` and `file_name`, works at any depth.
+    pub fn dir_fd(&self) -> libc::c_int {
+        self.parent.fd
+    }
+
+    /// Returns the file name, relative to `dir_fd`.
+    ///
+    /// Cast to `*const libc::c_char` for usage in libc functions.
+    pub fn file_name(&self) -> &CStr {
+        &self.name
+    }
+
+    /// Returns the path, starting with the path given to the walker.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the depth in the tree, which is 0 for the path given to the walker.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Returns the file type, following symlinks if the walk does. The file is only stat'ed if
+    /// `readdir` didn't report its type or it's a symlink that is followed.
+    pub fn file_type(&self) -> io::Result<FileType> {
+        match self.file_type {
+            Some(FileType::SymbolicLink) if self.follow_symlinks => {
+                Ok(self.metadata()?.file_type())
+            }
+            Some(file_type) => Ok(file_type),
+            None => Ok(self.metadata()?.file_type()),
+        }
+    }
+
+    /// Check if this entry is a symlink.
+    pub fn is_symlink(&self) -> io::Result<bool> {
+        match self.file_type {
+            Some(file_type) => Ok(file_type == FileType::SymbolicLink),
+            None => Ok(self.symlink_metadata()?.file_type() == FileType::SymbolicLink),
+        }
+    }
+
+    /// Returns the metadata of this entry, which is read on the first call.
+    ///
+    /// This is either the metadata of the file itself or the metadata of the file it points to.
+    pub fn metadata(&self) -> io::Result<&Metadata> {
+        if !self.follow_symlinks {
+            return self.symlink_metadata();
+        }
+        if let Some(metadata) = self.metadata.get() {
+            return Ok(metadata);
+        }
+
+        let metadata = match Metadata::new(self.parent.fd, self.name.as_ptr(), true) {
+            Ok(metadata) => metadata,
+            // Don't treat dangling links as an error, use the metadata of the original
+            Err(e) if e.kind() == io::ErrorKind::NotFound => self.symlink_metadata()?.clone(),
+            Err(e) => return Err(e),
+        };
+        Ok(self.metadata.get_or_init(|| metadata))
+    }
+
+    /// Returns the metadata of the entry itself, without following symlinks.
+    pub fn symlink_metadata(&self) -> io::Result<&Metadata> {
+        if let Some(metadata) = self.symlink_metadata.get() {
+            return Ok(metadata);
+        }
+
+        let metadata = Metadata::new(self.parent.fd, self.name.as_ptr(), false)?;
+        Ok(self.symlink_metadata.get_or_init(|| metadata))
+    }
+
+    /// Check if this entry is on a different file system than the path given to the walker. This
+    /// is only ever `true` with `Walker::one_file_system`.
+    pub fn is_mount_point(&self) -> bool {
+        self.is_mount_point
+    }
+
+    fn error(&self, e: io::Error, kind: ErrorKind) -> WalkError {
+        WalkError {
+            path: self.path.clone(),
+            error: Error::new(e, kind),
+        }
+    }
+}
+
+/// Builder for a single-threaded walk through a directory tree.
+pub struct Walker {
+    path: PathBuf,
+    follow_symlinks_on_args: bool,
+    follow_symlinks: bool,
+    one_file_system: bool,
+    contents_first: bool,
+    max_depth: usize,
+    sort_by_file_name: bool,
+    max_open: usize,
+}
+
+impl Walker {
+    /// Create a walker for the tree rooted at `path`. By default, it doesn't follow symlinks.
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        // Each open directory takes two file descriptors, leave the rest for the consumer
+        let max_open = (fd_rlim_cur() / 4).try_into().unwrap_or(usize::MAX).max(1);
+
+        Self {
+            path: path.as_ref().to_path_buf(),
+            follow_symlinks_on_args: false,
+            follow_symlinks: false,
+            one_file_system: false,
+            contents_first: false,
+            max_depth: usize::MAX,
+            sort_by_file_name: false,
+            max_open,
+        }
+    }
+
+    /// Whether to dereference the path given to `new` if it's a symlink.
+    pub fn follow_symlinks_on_args(mut self, follow: bool) -> Self {
+        self.follow_symlinks_on_args = follow;
+        self
+    }
+
+    /// Dereference symlinks encountered (also including the path given to `new`).
+    pub fn follow_symlinks(mut self, follow: bool) -> Self {
+        self.follow_symlinks = follow;
+        self
+    }
+
+    /// Don't enter directories on a different file system than the path given to `new`. These are
+    /// still produced, with `DirEntry::is_mount_point` returning `true`.
+    pub fn one_file_system(mut self, one_file_system: bool) -> Self {
+        self.one_file_system = one_file_system;
+        self
+    }
+
+    /// Produce the contents of a directory before the directory itself, so that it's empty by the
+    /// time it's produced if the consumer removes what it contains.
+    pub fn contents_first(mut self, contents_first: bool) -> Self {
+        self.contents_first = contents_first;
+        self
+    }
+
+    /// Don't enter directories at `max_depth` or deeper. These are still produced, the path given
+    /// to `new` being at depth 0.
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Produce the contents of each directory in the byte order of their names. The entries of a
+    /// directory are then read into memory when it's entered.
+    pub fn sort_by_file_name(mut self, sort: bool) -> Self {
+        self.sort_by_file_name = sort;
+        self
+    }
+
+    /// Set the number of directories that are kept open. Past that depth, the remaining entries of
+    /// the directories higher up are read into memory so that those can be closed. At least one
+    /// directory is kept open.
+    pub fn max_open(mut self, max_open: usize) -> Self {
+        self.max_open = max_open.max(1);
+        self
+    }
+
+    /// Start the walk. The returned iterator yields the entries as they are found.
+    pub fn walk(self) -> Walk {
+        Walk {
+            follow_symlinks: self.follow_symlinks,
+            one_file_system: self.one_file_system,
+            contents_first: self.contents_first,
+            max_depth: self.max_depth,
+            sort_by_file_name: self.sort_by_file_name,
+            max_open: self.max_open,
+            root: Some((
+                self.path,
+                self.follow_symlinks_on_args || self.follow_symlinks,
+            )),
+            descend: None,
+            pending: None,
+            stack: Vec::new(),
+            ancestors: HashSet::new(),
+            file_system: None,
+        }
+    }
+}
+
+// A name read from a directory
+struct Dirent {
+    name: CString,
+    file_type: Option<FileType>,
+}
+
+enum Listing {
+    Open(OwnedDir),
+    // The rest of the entries, read ahead to close the directory
+    Read(std::vec::IntoIter<io::Result<Dirent>>),
+}
+
+impl Listing {
+    fn next(&mut self) -> Option<io::Result<Dirent>> {
+        match self {
+            Listing::Open(dir) => loop {
+                match dir.iter().next()? {
+                    Ok(entry) if entry.is_dot_or_double_dot() => continue,
+                    Ok(entry) => {
+                        break Some(Ok(Dirent {
+                            name: entry.name_cstr().to_owned(),
+                            file_type: entry.file_type(),
+                        }))
+                    }
+                    Err(e) => break Some(Err(e)),
+                }
+            },
+            Listing::Read(entries) => entries.next(),
+        }
+    }
+
+    // Read the remaining entries into memory, up to and including the first error
+    fn read_ahead(&mut self) -> Vec<io::Result<Dirent>> {
+        let mut entries = Vec::new();
+        while let Some(entry_or_err) = self.next() {
+            let is_err = entry_or_err.is_err();
+            entries.push(entry_or_err);
+            if is_err {
+                break;
+            }
+        }
+        entries
+    }
+}
+
+// A directory that will be entered by the next call to `next`
+struct Descend {
+    parent: Rc<FileDescriptor>,
+    name: CString,
+    path: PathBuf,
+    depth: usize,
+    follow_symlinks: bool,
+    metadata: Metadata,
+    // The directory itself, if it's produced after its contents
+    entry: Option<DirEntry>,
+}
+
+// A directory being read
+struct Level {
+    listing: Listing,
+    // Duplicate of the file descriptor of the directory, for its entries. This is `None` after
+    // the directory was closed to stay under `max_open`.
+    fd: Option<Rc<FileDescriptor>>,
+    path: PathBuf,
+    depth: usize,
+    follow_symlinks: bool,
+    metadata: Metadata,
+    // The directory itself, if it's produced after its contents
+    entry: Option<DirEntry>,
+}
+
+impl Level {
+    fn error(&self, e: io::Error, kind: ErrorKind) -> WalkError {
+        WalkError {
+            path: self.path.clone(),
+            error: Error::new(e, kind),
+        }
+    }
+
+    fn close(&mut self) {
+        if let Listing::Open(_) = self.listing {
+            self.listing = Listing::Read(self.listing.read_ahead().into_iter());
+        }
+        self.fd = None;
+    }
+
+    // Open the directory again by its path, after `close`
+    fn reopen(&mut self) -> Result<Rc<FileDescriptor>, WalkError> {
+        let mut error = None;
+        let (starting_dir, components) = open_long_filename(
+            FileDescriptor::cwd(),
+            &self.path,
+            Some(&mut Vec::new()),
+            &mut |_, e| error = Some(e),
+        )
+        .ok_or_else(|| WalkError {
+            path: self.path.clone(),
+            error: error.unwrap(),
+        })?;
+
+        let filename_cstr = CString::new(components.as_path().as_os_str().as_bytes()).unwrap();
+        let fd = FileDescriptor::open_at(
+            &starting_dir,
+            filename_cstr.as_ptr(),
+            dir_open_flags(self.follow_symlinks),
+        )
+        .map_err(|e| self.error(e, ErrorKind::Open))?;
+        check_same_file(&fd, &self.metadata).map_err(|error| WalkError {
+            path: self.path.clone(),
+            error,
+        })?;
+
+        let fd = Rc::new(fd);
+        self.fd = Some(fd.clone());
+        Ok(fd)
+    }
+}
+
+/// Iterator over the entries of a directory tree, returned by `Walker::walk`.
+///
+/// Errors are produced in place of the entries they occurred at and the walk continues after
+/// them. A directory that failed to open has its error produced next to the directory itself.
+///
+/// The file descriptor of the directory containing an entry stays open at least until the next
+/// entry is produced, so the consumer can operate on the entry relative to it, whatever the length
+/// of its path.
+pub struct Walk {
+    follow_symlinks: bool,
+    one_file_system: bool,
+    contents_first: bool,
+    max_depth: usize,
+    sort_by_file_name: bool,
+    max_open: usize,
+    // The path given to the walker until it's produced, and whether to follow it if a symlink
+    root: Option<(PathBuf, bool)>,
+    descend: Option<Descend>,
+    // A directory to produce after the error that ended its walk
+    pending: Option<DirEntry>,
+    // Directories from the root down to the one being read
+    stack: Vec<Level>,
+    // Identities of the directories in `stack`, to detect cycles
+    ancestors: HashSet<(libc::dev_t, libc::ino_t)>,
+    // The file system of the root if the walk is restricted to it
+    file_system: Option<libc::dev_t>,
+}
+
+impl Walk {
+    /// Don't walk the contents of the directory most recently produced by the iterator. This has
+    /// no effect if that entry was not a directory, or with `Walker::contents_first`.
+    pub fn skip_current_dir(&mut self) {
+        self.descend = None;
+    }
+
+    fn start(
+        &mut self,
+        path: PathBuf,
+        follow_symlinks: bool,
+    ) -> Result<Option<DirEntry>, WalkError> {
+        // A path with a NUL byte can't exist, so make it fail with "No such file or directory"
+        if path.as_os_str().as_bytes().contains(&0) {
+            return Err(WalkError {
+                path,
+                error: Error::new(io::Error::from_raw_os_error(libc::ENOENT), ErrorKind::Stat),
+            });
+        }
+
+        // `path` is not guaranteed to be shorter than `libc::PATH_MAX`
+        let mut error = None;
+        let Some((starting_dir, components)) = open_long_filename(
+            FileDescriptor::cwd(),
+            &path,
+            Some(&mut Vec::new()),
+            &mut |_, e| error = Some(e),
+        ) else {
+            return Err(WalkError {
+                path,
+                error: error.unwrap(),
+            });
+        };
+        let name = CString::new(components.as_path().as_os_str().as_bytes()).unwrap();
+
+        let entry = DirEntry {
+            parent: Rc::new(starting_dir),
+            name,
+            path,
+            depth: 0,
+            follow_symlinks,
+            file_type: None,
+            symlink_metadata: OnceCell::new(),
+            metadata: OnceCell::new(),
+            is_mount_point: false,
+        };
+        if self.one_file_system {
+            let metadata = entry
+                .metadata()
+                .map_err(|e| entry.error(e, ErrorKind::Stat))?;
+            self.file_system = Some(metadata.0.st_dev);
+        }
+        self.produce(entry)
+    }
+
+    // Check a directory before it's produced, and prepare to enter it. Returns `None` for a
+    // directory that is produced after its contents.
+    fn produce(&mut self, mut entry: DirEntry) -> Result<Option<DirEntry>, WalkError> {
+        let may_be_dir = match entry.file_type {
+            Some(FileType::Directory) | None => true,
+            Some(FileType::SymbolicLink) => entry.follow_symlinks,
+            Some(_) => false,
+        };
+        if !may_be_dir || entry.depth >= self.max_depth {
+            return Ok(Some(entry));
+        }
+
+        let metadata = match entry.metadata() {
+            Ok(metadata) if metadata.file_type() == FileType::Directory => metadata.clone(),
+            Ok(_) => return Ok(Some(entry)),
+            Err(e) => return Err(entry.error(e, ErrorKind::Stat)),
+        };
+
+        // Entering a directory that is also an ancestor would never end
+        if self.ancestors.contains(&metadata.identity()) {
+            return Err(WalkError {
+                path: entry.path,
+                error: cycle_error(),
+            });
+        }
+
+        entry.is_mount_point = self.file_system.is_some_and(|dev| metadata.0.st_dev != dev);
+        if entry.is_mount_point {
+            return Ok(Some(entry));
+        }
+
+        let mut descend = Descend {
+            parent: entry.parent.clone(),
+            name: entry.name.clone(),
+            path: entry.path.clone(),
+            depth: entry.depth,
+            follow_symlinks: entry.follow_symlinks,
+            metadata,
+            entry: None,
+        };
+        if self.contents_first {
+            descend.entry = Some(entry);
+            self.descend = Some(descend);
+            Ok(None)
+        } else {
+            self.descend = Some(descend);
+            Ok(Some(entry))
+        }
+    }
+
+    fn enter(&mut self, mut dir: Descend) -> Result<(), WalkError> {
+        let (fd, listing) = match Self::open(&dir) {
+            Ok(pair) => pair,
+            Err(e) => {
+                self.pending = dir.entry.take();
+                return Err(e);
+            }
+        };
+
+        // Close the directory highest up the tree if there are too many open
+        let open = self.stack.iter().filter(|level| level.fd.is_some()).count();
+        if open >= self.max_open {
+            if let Some(level) = self.stack.iter_mut().find(|level| level.fd.is_some()) {
+                level.close();
+            }
+        }
+
+        let mut listing = Listing::Open(listing);
+        if self.sort_by_file_name {
+            let mut entries = listing.read_ahead();
+            // A read error ends the entries, so it stays last
+            entries.sort_by(|a, b| match (a, b) {
+                (Ok(a), Ok(b)) => a.name.cmp(&b.name),
+                (Ok(_), Err(_)) => std::cmp::Ordering::Less,
+                (Err(_), Ok(_)) => std::cmp::Ordering::Greater,
+                (Err(_), Err(_)) => std::cmp::Ordering::Equal,
+            });
+            listing = Listing::Read(entries.into_iter());
+        }
+
+        self.ancestors.insert(dir.metadata.identity());
+        self.stack.push(Level {
+            listing,
+            fd: Some(fd),
+            path: dir.path,
+            depth: dir.depth,
+            follow_symlinks: dir.follow_symlinks,
+            metadata: dir.metadata,
+            entry: dir.entry,
+        });
+        Ok(())
+    }
+
+    fn open(dir: &Descend) -> Result<(Rc<FileDescriptor>, OwnedDir), WalkError> {
+        let error = |e, kind| WalkError {
+            path: dir.path.clone(),
+            error: Error::new(e, kind),
+        };
+
+        let file_descriptor = FileDescriptor::open_at(
+            &dir.parent,
+            dir.name.as_ptr(),
+            dir_open_flags(dir.follow_symlinks),
+        )
+        .map_err(|e| error(e, ErrorKind::Open))?;
+        check_same_file(&file_descriptor, &dir.metadata).map_err(|e| WalkError {
+            path: dir.path.clone(),
+            error: e,
+        })?;
+
+        // The entries get a duplicate of the file descriptor as `OwnedDir` closes its own
+        let fd = Rc::new(file_descriptor.clone());
+        let listing = OwnedDir::new(file_descriptor).map_err(|e| error(e, ErrorKind::OpenDir))?;
+        Ok((fd, listing))
+    }
+
+    // Go up a level, returning the directory that was left if it's produced after its contents
+    fn leave(&mut self) -> Option<DirEntry> {
+        let level = self.stack.pop()?;
+        self.ancestors.remove(&level.metadata.identity());
+        level.entry
+    }
+}
+
+impl Iterator for Walk {
+    type Item = Result<DirEntry, WalkError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(entry) = self.pending.take() {
+                return Some(Ok(entry));
+            }
+
+            if let Some((path, follow_symlinks)) = self.root.take() {
+                match self.start(path, follow_symlinks) {
+                    Ok(Some(entry)) => return Some(Ok(entry)),
+                    Ok(None) => continue,
+                    Err(e) => return Some(Err(e)),
+                }
+            }
+
+            if let Some(dir) = self.descend.take() {
+                if let Err(e) = self.enter(dir) {
+                    return Some(Err(e));
+                }
+            }
+
+            let level = self.stack.last_mut()?;
+
+            let dirent = match level.listing.next() {
+                Some(Ok(dirent)) => dirent,
+                Some(Err(e)) => {
+                    let error = level.error(e, ErrorKind::ReadDir);
+                    self.pending = self.leave();
+                    return Some(Err(error));
+                }
+                None => match self.leave() {
+                    Some(entry) => return Some(Ok(entry)),
+                    None => continue,
+                },
+            };
+
+            let parent = match &level.fd {
+                Some(fd) => fd.clone(),
+                None => match level.reopen() {
+                    Ok(fd) => fd,
+                    Err(e) => {
+                        self.pending = self.leave();
+                        return Some(Err(e));
+                    }
+                },
+            };
+
+            let path = level.path.join(OsStr::from_bytes(dirent.name.to_bytes()));
+            let entry = DirEntry {
+                parent,
+                name: dirent.name,
+                path,
+                depth: level.depth + 1,
+                follow_symlinks: self.follow_symlinks,
+                file_type: dirent.file_type,
+                symlink_metadata: OnceCell::new(),
+                metadata: OnceCell::new(),
+                is_mount_point: false,
+            };
+            match self.produce(entry) {
+                Ok(Some(entry)) => return Some(Ok(entry)),
+                Ok(None) => continue,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
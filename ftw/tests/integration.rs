@@ -2,7 +2,7 @@ use std::{
     collections::HashSet,
     ffi::CString,
     fs, io,
-    os::{fd::AsRawFd, unix},
+    os::{fd::AsRawFd, unix, unix::fs::MetadataExt},
     path::{Path, PathBuf},
 };
 
@@ -43,6 +43,7 @@ fn test_ftw_simple() {
         |_, e| panic!("{}", e.inner()),
         false,
         false,
+        false,
     );
 
     expected_filenames.sort();
@@ -98,6 +99,7 @@ fn test_ftw_symlinks() {
         |_, e| panic!("{}", e.inner()),
         false,
         true,
+        false,
     );
 
     expected_filenames.sort();
@@ -139,6 +141,7 @@ fn test_ftw_deep() {
         |_, e| panic!("{}", e.inner()),
         false,
         false,
+        false,
     );
 
     assert_eq!(count, DIR_HIERARCHY_DEPTH);
@@ -185,6 +188,7 @@ fn test_ftw_deep_symlinks() {
         |_, e| panic!("{}", e.inner()),
         false,
         true,
+        false,
     );
 
     assert_eq!(count, DIR_HIERARCHY_DEPTH);
@@ -242,7 +246,7 @@ fn test_ftw_path_prefix_modification() {
             if e.kind() == ftw::ErrorKind::Open {
                 let path = format!("{}", entry.path().display());
                 if path == a_b_c {
-                    return;
+                    return ftw::ErrorAction::Continue;
                 }
             }
 
@@ -250,6 +254,7 @@ fn test_ftw_path_prefix_modification() {
         },
         true,
         true,
+        false,
     );
 
     // Once a/b is reached, traverse_directory should not go down the wrong directory even if a/b is
@@ -269,6 +274,7 @@ fn test_ftw_path_prefix_modification() {
         |_, e| panic!("{}", e.inner()),
         true,
         true,
+        false,
     );
 
     // Rerunning the directory traversal should now follow the "wrong" directory.
@@ -327,6 +333,7 @@ fn test_ftw_long_filename() {
         |_, e| panic!("{:?}", e.kind()),
         false,
         false,
+        false,
     );
 
     for char in dummy_dirs.chars() {
@@ -336,3 +343,575 @@ fn test_ftw_long_filename() {
 
     fs::remove_dir_all(test_dir).unwrap();
 }
+
+#[test]
+fn test_ftw_parallel() {
+    let test_dir = &format!("{}/test_ftw_parallel", env!("CARGO_TARGET_TMPDIR"));
+
+    let mut expected_filenames = vec![test_dir.clone()];
+    for i in 0..20 {
+        let dir = format!("{test_dir}/{i}");
+        expected_filenames.push(dir.clone());
+        for j in 0..20 {
+            let subdir = format!("{dir}/{j}");
+            fs::create_dir_all(&subdir).unwrap();
+            let file = format!("{subdir}/file");
+            fs::File::create(&file).unwrap();
+            expected_filenames.push(subdir);
+            expected_filenames.push(file);
+        }
+    }
+
+    let mut filenames = Vec::new();
+    for entry in ftw::ParallelWalker::new([test_dir]).threads(4).walk() {
+        let entry = entry.unwrap();
+        let path = entry.path().to_str().unwrap().to_string();
+
+        // Directories come before their contents
+        if entry.depth() > 0 {
+            let parent = entry.path().parent().unwrap().to_str().unwrap();
+            assert!(filenames.iter().any(|f| f == parent), "{path}");
+        }
+        assert_eq!(entry.depth(), path[test_dir.len()..].matches('/').count());
+        assert_eq!(
+            entry.metadata().file_type() == ftw::FileType::Directory,
+            !path.ends_with("/file")
+        );
+
+        // The entry can be found relative to the directory containing it
+        let md =
+            ftw::Metadata::new(entry.dir_fd(), entry.file_name_cstr().as_ptr(), false).unwrap();
+        assert_eq!(md.file_type(), entry.metadata().file_type());
+
+        filenames.push(path);
+    }
+
+    expected_filenames.sort();
+    filenames.sort();
+    assert_eq!(expected_filenames, filenames);
+
+    // Skip the contents of some directories
+    let count = ftw::ParallelWalker::new([test_dir])
+        .threads(3)
+        .descend_filter(|entry| {
+            entry.depth() == 0 || (entry.depth() == 1 && entry.file_name() == "7")
+        })
+        .walk()
+        .inspect(|entry| assert!(entry.is_ok()))
+        .count();
+    assert_eq!(count, 1 + 20 + 20);
+
+    // Stopping early doesn't hang
+    let count = ftw::ParallelWalker::new([test_dir])
+        .threads(4)
+        .channel_bound(1)
+        .walk()
+        .take(5)
+        .count();
+    assert_eq!(count, 5);
+
+    fs::remove_dir_all(test_dir).unwrap();
+}
+
+#[test]
+fn test_ftw_parallel_errors_and_symlinks() {
+    let test_dir = &format!(
+        "{}/test_ftw_parallel_errors_and_symlinks",
+        env!("CARGO_TARGET_TMPDIR")
+    );
+    let a = format!("{test_dir}/a");
+    let b_file = format!("{test_dir}/b/file");
+    fs::create_dir_all(&a).unwrap();
+    fs::create_dir_all(format!("{test_dir}/b")).unwrap();
+    fs::File::create(&b_file).unwrap();
+    unix::fs::symlink(format!("{test_dir}/b"), format!("{a}/link")).unwrap();
+    let missing = format!("{test_dir}/missing");
+
+    let walk = |follow_symlinks: bool| {
+        let mut filenames = Vec::new();
+        let mut errors = Vec::new();
+        for entry in ftw::ParallelWalker::new([&a, &missing])
+            .follow_symlinks(follow_symlinks)
+            .walk()
+        {
+            match entry {
+                Ok(entry) => filenames.push(entry.path().to_str().unwrap().to_string()),
+                Err(e) => errors.push((e.path().to_str().unwrap().to_string(), e.kind())),
+            }
+        }
+        filenames.sort();
+        (filenames, errors)
+    };
+
+    let (filenames, errors) = walk(false);
+    assert_eq!(filenames, [a.clone(), format!("{a}/link")]);
+    assert_eq!(errors, [(missing.clone(), ftw::ErrorKind::Stat)]);
+
+    let (filenames, errors) = walk(true);
+    assert_eq!(
+        filenames,
+        [a.clone(), format!("{a}/link"), format!("{a}/link/file")]
+    );
+    assert_eq!(errors, [(missing.clone(), ftw::ErrorKind::Stat)]);
+
+    fs::remove_dir_all(test_dir).unwrap();
+}
+
+#[test]
+fn test_ftw_cycles() {
+    let test_dir = &format!("{}/test_ftw_cycles", env!("CARGO_TARGET_TMPDIR"));
+    let a = format!("{test_dir}/a");
+    let other = format!("{test_dir}/other");
+    fs::create_dir_all(format!("{a}/b")).unwrap();
+    fs::create_dir(&other).unwrap();
+    fs::File::create(format!("{other}/file")).unwrap();
+    unix::fs::symlink("..", format!("{a}/b/up")).unwrap();
+    unix::fs::symlink(".", format!("{a}/b/self")).unwrap();
+    // The same directory twice, but not as its own ancestor
+    unix::fs::symlink(&other, format!("{a}/b/other1")).unwrap();
+    unix::fs::symlink(&other, format!("{a}/b/other2")).unwrap();
+
+    let expected_filenames = [
+        a.clone(),
+        format!("{a}/b"),
+        format!("{a}/b/other1"),
+        format!("{a}/b/other1/file"),
+        format!("{a}/b/other2"),
+        format!("{a}/b/other2/file"),
+    ];
+    let expected_errors = [
+        (format!("{a}/b/self"), ftw::ErrorKind::Cycle),
+        (format!("{a}/b/up"), ftw::ErrorKind::Cycle),
+    ];
+
+    let mut filenames = Vec::new();
+    let mut errors = Vec::new();
+    let report = ftw::traverse_directory(
+        &a,
+        |entry| {
+            filenames.push(entry.path().to_string());
+            Ok(true)
+        },
+        |_| Ok(()),
+        |entry, e| {
+            errors.push((entry.path().to_string(), e.kind()));
+            ftw::ErrorAction::Continue
+        },
+        false,
+        true,
+        false,
+    );
+    assert!(!report.is_success());
+    filenames.sort();
+    errors.sort_by(|x, y| x.0.cmp(&y.0));
+    assert_eq!(filenames, expected_filenames);
+    assert_eq!(errors, expected_errors);
+
+    let mut filenames = Vec::new();
+    let mut errors = Vec::new();
+    for entry in ftw::ParallelWalker::new([&a]).follow_symlinks(true).walk() {
+        match entry {
+            Ok(entry) => filenames.push(entry.path().to_str().unwrap().to_string()),
+            Err(e) => errors.push((e.path().to_str().unwrap().to_string(), e.kind())),
+        }
+    }
+    filenames.sort();
+    errors.sort_by(|x, y| x.0.cmp(&y.0));
+    assert_eq!(filenames, expected_filenames);
+    assert_eq!(errors, expected_errors);
+
+    let mut filenames = Vec::new();
+    let mut errors = Vec::new();
+    for entry in ftw::Walker::new(&a).follow_symlinks(true).walk() {
+        match entry {
+            Ok(entry) => filenames.push(entry.path().to_str().unwrap().to_string()),
+            Err(e) => errors.push((e.path().to_str().unwrap().to_string(), e.kind())),
+        }
+    }
+    filenames.sort();
+    errors.sort_by(|x, y| x.0.cmp(&y.0));
+    assert_eq!(filenames, expected_filenames);
+    assert_eq!(errors, expected_errors);
+
+    fs::remove_dir_all(test_dir).unwrap();
+}
+
+#[test]
+fn test_ftw_one_file_system() {
+    let test_dir = &format!("{}/test_ftw_one_file_system", env!("CARGO_TARGET_TMPDIR"));
+    let other_fs_dir = &format!("/dev/shm/test_ftw_one_file_system_{}", std::process::id());
+    fs::create_dir(test_dir).unwrap();
+    fs::create_dir_all(format!("{other_fs_dir}/dir")).unwrap();
+
+    // A followed symlink to another file system is treated like a mount point
+    let link = format!("{test_dir}/link");
+    unix::fs::symlink(other_fs_dir, &link).unwrap();
+    let on_same_fs = fs::metadata(test_dir).unwrap().dev() == fs::metadata(&link).unwrap().dev();
+
+    let traverse = |one_file_system: bool| {
+        let mut filenames = Vec::new();
+        let mut mount_points = Vec::new();
+        let report = ftw::traverse_directory(
+            test_dir,
+            |entry| {
+                let path = entry.path().to_string();
+                if entry.is_mount_point() {
+                    mount_points.push(path.clone());
+                }
+                filenames.push(path);
+                Ok(true)
+            },
+            |_| Ok(()),
+            |_, e| panic!("{}", e.inner()),
+            false,
+            true,
+            one_file_system,
+        );
+        assert!(report.is_success());
+        filenames.sort();
+        (filenames, mount_points)
+    };
+
+    let walk = |one_file_system: bool| {
+        let mut filenames = Vec::new();
+        let mut mount_points = Vec::new();
+        for entry in ftw::ParallelWalker::new([test_dir])
+            .follow_symlinks(true)
+            .one_file_system(one_file_system)
+            .walk()
+        {
+            let entry = entry.unwrap();
+            let path = entry.path().to_str().unwrap().to_string();
+            if entry.is_mount_point() {
+                mount_points.push(path.clone());
+            }
+            filenames.push(path);
+        }
+        filenames.sort();
+        (filenames, mount_points)
+    };
+
+    let stream = |one_file_system: bool| {
+        let mut filenames = Vec::new();
+        let mut mount_points = Vec::new();
+        for entry in ftw::Walker::new(test_dir)
+            .follow_symlinks(true)
+            .one_file_system(one_file_system)
+            .walk()
+        {
+            let entry = entry.unwrap();
+            let path = entry.path().to_str().unwrap().to_string();
+            if entry.is_mount_point() {
+                mount_points.push(path.clone());
+            }
+            filenames.push(path);
+        }
+        filenames.sort();
+        (filenames, mount_points)
+    };
+
+    let all = vec![test_dir.clone(), link.clone(), format!("{link}/dir")];
+    assert_eq!(traverse(false), (all.clone(), vec![]));
+    assert_eq!(walk(false), (all.clone(), vec![]));
+    assert_eq!(stream(false), (all, vec![]));
+
+    if !on_same_fs {
+        let outer = vec![test_dir.clone(), link.clone()];
+        assert_eq!(traverse(true), (outer.clone(), vec![link.clone()]));
+        assert_eq!(walk(true), (outer.clone(), vec![link.clone()]));
+        assert_eq!(stream(true), (outer, vec![link.clone()]));
+    }
+
+    fs::remove_dir_all(test_dir).unwrap();
+    fs::remove_dir_all(other_fs_dir).unwrap();
+}
+
+#[test]
+fn test_ftw_walk() {
+    let test_dir = &format!("{}/test_ftw_walk", env!("CARGO_TARGET_TMPDIR"));
+    fs::create_dir(test_dir).unwrap();
+    for dir in ["a", "a/b", "a/b/c", "d"] {
+        fs::create_dir(format!("{test_dir}/{dir}")).unwrap();
+    }
+    for file in ["a/f", "a/b/f", "a/b/c/f", "d/f"] {
+        fs::write(format!("{test_dir}/{file}"), file).unwrap();
+    }
+    unix::fs::symlink("a", format!("{test_dir}/link")).unwrap();
+
+    let relative = |path: &Path| {
+        let path = path.strip_prefix(test_dir).unwrap().to_str().unwrap();
+        path.to_string()
+    };
+
+    let entries: Vec<_> = ftw::Walker::new(test_dir)
+        .walk()
+        .map(Result::unwrap)
+        .collect();
+    let paths: Vec<_> = entries.iter().map(|e| relative(e.path())).collect();
+
+    // Directories come before their contents
+    for (i, path) in paths.iter().enumerate() {
+        if let Some(parent) = Path::new(path).parent() {
+            let parent = parent.to_str().unwrap();
+            assert!(paths[..i].iter().any(|p| p == parent), "{path}");
+        }
+    }
+    let mut sorted = paths.clone();
+    sorted.sort();
+    assert_eq!(
+        sorted,
+        ["", "a", "a/b", "a/b/c", "a/b/c/f", "a/b/f", "a/f", "d", "d/f", "link"]
+    );
+
+    // The entries can still be stat'ed after the walk
+    for entry in &entries {
+        let depth = entry.path().components().count() - Path::new(test_dir).components().count();
+        assert_eq!(entry.depth(), depth);
+
+        let file_type = entry.file_type().unwrap();
+        assert_eq!(entry.metadata().unwrap().file_type(), file_type);
+        assert_eq!(
+            entry.is_symlink().unwrap(),
+            file_type == ftw::FileType::SymbolicLink
+        );
+        if file_type == ftw::FileType::RegularFile {
+            let path = relative(entry.path());
+            assert_eq!(entry.metadata().unwrap().size(), path.len() as u64);
+        }
+    }
+
+    // Skipping a directory's contents
+    let mut paths = Vec::new();
+    let mut walk = ftw::Walker::new(test_dir).follow_symlinks(true).walk();
+    while let Some(entry) = walk.next() {
+        let path = relative(entry.unwrap().path());
+        if path == "a" {
+            walk.skip_current_dir();
+        }
+        paths.push(path);
+    }
+    paths.sort();
+    assert_eq!(
+        paths,
+        [
+            "",
+            "a",
+            "d",
+            "d/f",
+            "link",
+            "link/b",
+            "link/b/c",
+            "link/b/c/f",
+            "link/b/f",
+            "link/f"
+        ]
+    );
+
+    // Stopping early
+    assert_eq!(ftw::Walker::new(test_dir).walk().take(2).count(), 2);
+
+    fs::remove_dir_all(test_dir).unwrap();
+}
+
+#[test]
+fn test_ftw_walk_deep() {
+    let test_dir = &format!("{}/test_ftw_walk_deep", env!("CARGO_TARGET_TMPDIR"));
+    fs::create_dir(test_dir).unwrap();
+
+    // A file and a directory at each level
+    let mut expected = vec![test_dir.clone()];
+    let mut dir = test_dir.clone();
+    for _ in 0..DIR_HIERARCHY_DEPTH {
+        let file = format!("{dir}/file");
+        fs::File::create(&file).unwrap();
+        expected.push(file);
+        dir = format!("{dir}/d");
+        fs::create_dir(&dir).unwrap();
+        expected.push(dir.clone());
+    }
+    expected.sort();
+
+    // Only keeping a few directories open means reading ahead and reopening the rest
+    for max_open in [1, 3, usize::MAX] {
+        let mut paths: Vec<_> = ftw::Walker::new(test_dir)
+            .max_open(max_open)
+            .walk()
+            .map(|e| e.unwrap().path().to_str().unwrap().to_string())
+            .collect();
+        paths.sort();
+        assert_eq!(paths, expected);
+    }
+
+    fs::remove_dir_all(test_dir).unwrap();
+}
+
+#[test]
+fn test_ftw_walk_max_depth_sorted() {
+    let test_dir = &format!(
+        "{}/test_ftw_walk_max_depth_sorted",
+        env!("CARGO_TARGET_TMPDIR")
+    );
+    for dir in ["c/c1/c2", "a/a1", "b"] {
+        fs::create_dir_all(format!("{test_dir}/{dir}")).unwrap();
+    }
+    for file in ["c/c1/file", "a/z", "a/b"] {
+        fs::File::create(format!("{test_dir}/{file}")).unwrap();
+    }
+
+    let walk = |max_depth| -> Vec<_> {
+        ftw::Walker::new(test_dir)
+            .max_depth(max_depth)
+            .sort_by_file_name(true)
+            .walk()
+            .map(|e| {
+                let e = e.unwrap();
+                let path = e.path().strip_prefix(test_dir).unwrap();
+                path.to_str().unwrap().to_string()
+            })
+            .collect()
+    };
+
+    assert_eq!(walk(0), [""]);
+    assert_eq!(walk(1), ["", "a", "b", "c"]);
+    assert_eq!(walk(2), ["", "a", "a/a1", "a/b", "a/z", "b", "c", "c/c1"]);
+    assert_eq!(
+        walk(usize::MAX),
+        [
+            "",
+            "a",
+            "a/a1",
+            "a/b",
+            "a/z",
+            "b",
+            "c",
+            "c/c1",
+            "c/c1/c2",
+            "c/c1/file"
+        ]
+    );
+
+    fs::remove_dir_all(test_dir).unwrap();
+}
+
+#[test]
+fn test_ftw_walk_contents_first() {
+    let test_dir = &format!(
+        "{}/test_ftw_walk_contents_first",
+        env!("CARGO_TARGET_TMPDIR")
+    );
+    let test_dir_name = CString::new(test_dir.as_bytes()).unwrap();
+    let dir_name = CString::new(["x"; 200].join("").as_bytes()).unwrap();
+    let file_name = CString::new("file").unwrap();
+
+    for max_open in [2, usize::MAX] {
+        // Deeper than `libc::PATH_MAX`, with a file at each level
+        let mut fd = ftw::FileDescriptor::cwd();
+        for i in 0..DIR_HIERARCHY_DEPTH {
+            let filename = if i == 0 { &test_dir_name } else { &dir_name };
+            let ret = unsafe { libc::mkdirat(fd.as_raw_fd(), filename.as_ptr(), 0o755) };
+            if ret != 0 {
+                panic!("{}", io::Error::last_os_error());
+            }
+            fd = ftw::FileDescriptor::open_at(&fd, filename.as_ptr(), libc::O_RDONLY).unwrap();
+            let ret = unsafe {
+                libc::openat(
+                    fd.as_raw_fd(),
+                    file_name.as_ptr(),
+                    libc::O_CREAT | libc::O_WRONLY | libc::O_CLOEXEC,
+                    0o644,
+                )
+            };
+            if ret == -1 {
+                panic!("{}", io::Error::last_os_error());
+            }
+            unsafe { libc::close(ret) };
+        }
+        drop(fd);
+
+        // Removing each entry relative to its parent only works if directories come last
+        let mut depths = Vec::new();
+        for entry in ftw::Walker::new(test_dir)
+            .contents_first(true)
+            .max_open(max_open)
+            .walk()
+        {
+            let entry = entry.unwrap();
+            let flags = if entry.file_type().unwrap() == ftw::FileType::Directory {
+                libc::AT_REMOVEDIR
+            } else {
+                0
+            };
+            let ret = unsafe { libc::unlinkat(entry.dir_fd(), entry.file_name().as_ptr(), flags) };
+            if ret != 0 {
+                panic!("{}: {}", entry.path().display(), io::Error::last_os_error());
+            }
+            depths.push(entry.depth());
+        }
+
+        // The starting directory last
+        assert_eq!(depths.len(), 2 * DIR_HIERARCHY_DEPTH);
+        assert_eq!(depths.iter().max(), Some(&DIR_HIERARCHY_DEPTH));
+        assert_eq!(depths.last(), Some(&0));
+        assert!(!Path::new(test_dir).exists());
+    }
+}
+
+#[test]
+fn test_ftw_error_actions() {
+    let test_dir = &format!("{}/test_ftw_error_actions", env!("CARGO_TARGET_TMPDIR"));
+    let a = format!("{test_dir}/a");
+    let b = format!("{test_dir}/b");
+    fs::create_dir_all(&a).unwrap();
+    fs::create_dir_all(&b).unwrap();
+    fs::File::create(format!("{b}/file")).unwrap();
+
+    // Following the symlink is a cycle error, in a directory of its own
+    let loop_link = format!("{a}/loop");
+    unix::fs::symlink("..", &loop_link).unwrap();
+
+    let traverse = |action: ftw::ErrorAction| {
+        let mut postprocessed = Vec::new();
+        let report = ftw::traverse_directory(
+            test_dir,
+            |_| Ok(true),
+            |entry| {
+                postprocessed.push(entry.path().to_string());
+                Ok(())
+            },
+            |_, _| action,
+            false,
+            true,
+            false,
+        );
+        postprocessed.sort();
+        (report, postprocessed)
+    };
+
+    for action in [ftw::ErrorAction::Continue, ftw::ErrorAction::SkipSubtree] {
+        let (report, postprocessed) = traverse(action);
+        assert!(!report.is_success());
+        assert!(!report.is_aborted());
+        let errors = report.errors();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].path(), Path::new(&loop_link));
+        assert_eq!(errors[0].kind(), ftw::ErrorKind::Cycle);
+        assert_eq!(errors[0].action(), action);
+
+        // A skipped directory is not post-processed, the others are
+        let mut expected = vec![test_dir.clone(), a.clone(), b.clone()];
+        if action == ftw::ErrorAction::SkipSubtree {
+            expected.retain(|path| *path != a);
+        }
+        assert_eq!(postprocessed, expected);
+    }
+
+    // Nothing is called after aborting
+    let (report, postprocessed) = traverse(ftw::ErrorAction::Abort);
+    assert!(!report.is_success());
+    assert!(report.is_aborted());
+    assert_eq!(report.errors().len(), 1);
+    assert!(!postprocessed.contains(&a));
+    assert!(!postprocessed.contains(test_dir));
+
+    fs::remove_dir_all(test_dir).unwrap();
+}
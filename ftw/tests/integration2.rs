@@ -50,7 +50,15 @@ fn test_ftw_fd_raii() {
         }
     }
 
-    ftw::traverse_directory(test_dir, |_| Ok(true), |_| Ok(()), |_, _| {}, false, false);
+    ftw::traverse_directory(
+        test_dir,
+        |_| Ok(true),
+        |_| Ok(()),
+        |_, _| ftw::ErrorAction::Continue,
+        false,
+        false,
+        false,
+    );
 
     let final_fd_count = count_open_fds();
     assert_eq!(starting_fd_count, final_fd_count);
@@ -103,9 +111,11 @@ fn test_ftw_too_many_open_files() {
         |_, e| {
             assert!(e.kind() == ftw::ErrorKind::Open);
             assert_eq!(e.inner().raw_os_error(), Some(libc::EMFILE));
+            ftw::ErrorAction::Continue
         },
         false,
         false,
+        false,
     );
 
     assert_eq!(dir_count, DIR_HIERARCHY_DEPTH);
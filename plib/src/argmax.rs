@@ -0,0 +1,49 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+//! The space for the arguments of commands built from many files or words,
+//! as by `xargs` and `find -exec ... {} +`.
+//!
+//! The limit of `execve` covers both the arguments and the environment, and
+//! each string takes up a pointer in addition to its bytes and terminating
+//! NUL. Some room is left for the child to change its environment, as POSIX
+//! recommends for `xargs`.
+
+use std::env;
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+
+// Room left for the child to set up its environment
+const HEADROOM: usize = 2048;
+
+// `_POSIX_ARG_MAX`, the minimum POSIX allows, for when `ARG_MAX` is unknown
+const POSIX_ARG_MAX: usize = 4096;
+
+const POINTER_SIZE: usize = std::mem::size_of::<*const libc::c_char>();
+
+/// The space `arg` takes up in the argument list passed to `execve`.
+pub fn arg_size(arg: &OsStr) -> usize {
+    arg.as_bytes().len() + 1 + POINTER_SIZE
+}
+
+/// The space available for the arguments of a command, after the
+/// environment it inherits from the current process.
+pub fn available() -> usize {
+    let arg_max = unsafe { libc::sysconf(libc::_SC_ARG_MAX) };
+    let arg_max = if arg_max > 0 {
+        arg_max as usize
+    } else {
+        POSIX_ARG_MAX
+    };
+    // "name=value"
+    let env_size: usize = env::vars_os()
+        .map(|(k, v)| k.len() + 1 + v.len() + 1 + POINTER_SIZE)
+        .sum();
+    arg_max.saturating_sub(env_size + HEADROOM)
+}
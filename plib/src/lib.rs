@@ -7,11 +7,16 @@
 // SPDX-License-Identifier: MIT
 //
 
+pub mod argmax;
 pub mod curuser;
 pub mod group;
 pub mod io;
 pub mod lzw;
 pub mod modestr;
+pub mod owner;
+pub mod progress;
+pub mod prompt;
+pub mod quote;
 pub mod sccsfile;
 pub mod testing;
 pub mod utmpx;
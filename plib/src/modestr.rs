@@ -8,9 +8,10 @@
 //
 
 use libc::{
-    S_IRGRP, S_IROTH, S_IRUSR, S_IRWXG, S_IRWXO, S_IRWXU, S_ISUID, S_ISVTX, S_IWGRP, S_IWOTH,
-    S_IWUSR, S_IXGRP, S_IXOTH, S_IXUSR,
+    S_IRGRP, S_IROTH, S_IRUSR, S_IRWXG, S_IRWXO, S_IRWXU, S_ISGID, S_ISUID, S_ISVTX, S_IWGRP,
+    S_IWOTH, S_IWUSR, S_IXGRP, S_IXOTH, S_IXUSR,
 };
+use std::fmt;
 
 #[derive(PartialEq, Debug)]
 pub enum ChmodActionOp {
@@ -31,10 +32,9 @@ pub struct ChmodAction {
     pub write: bool,
     pub execute: bool,
     pub execute_dir: bool,
-    pub setuid: bool,
+    // 's': set-user-ID and/or set-group-ID, depending on the wholist
+    pub set_id: bool,
     pub sticky: bool,
-
-    dirty: bool,
 }
 
 impl ChmodAction {
@@ -48,9 +48,8 @@ impl ChmodAction {
             write: false,
             execute: false,
             execute_dir: false,
-            setuid: false,
+            set_id: false,
             sticky: false,
-            dirty: false,
         }
     }
 }
@@ -62,10 +61,11 @@ pub struct ChmodClause {
     pub group: bool,
     pub others: bool,
 
+    // no wholist was given: acts as 'a', filtered through the umask
+    pub implicit_who: bool,
+
     // actionlist
     pub actions: Vec<ChmodAction>,
-
-    dirty: bool,
 }
 
 impl ChmodClause {
@@ -74,8 +74,8 @@ impl ChmodClause {
             user: false,
             group: false,
             others: false,
+            implicit_who: false,
             actions: Vec::new(),
-            dirty: false,
         }
     }
 }
@@ -99,281 +99,501 @@ pub enum ChmodMode {
     Symbolic(ChmodSymbolic),
 }
 
-#[derive(Debug)]
-enum ParseState {
-    Wholist,
-    Actionlist,
-    ListOrCopy,
+/// A class of token that the mode parser can accept at a given position.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ModeToken {
+    /// One of `u`, `g`, `o` or `a` in a who-list.
+    Who,
+    /// One of the operators `+`, `-` or `=`.
+    Op,
+    /// One of the permission letters `r`, `w`, `x`, `X`, `s` or `t`.
+    Perm,
+    /// One of `u`, `g` or `o` copying existing permissions.
     PermCopy,
-    PermList,
-    NextClause,
+    /// The `,` clause separator.
+    Comma,
+    /// A digit of an absolute (octal) mode.
+    OctalDigit,
+    /// The end of the mode string.
+    End,
 }
 
-pub fn parse(mode: &str) -> Result<ChmodMode, String> {
-    if let Ok(m) = u32::from_str_radix(mode, 8) {
-        return Ok(ChmodMode::Absolute(m));
+impl fmt::Display for ModeToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            ModeToken::Who => "who ('u', 'g', 'o', 'a')",
+            ModeToken::Op => "operator ('+', '-', '=')",
+            ModeToken::Perm => "permission ('r', 'w', 'x', 'X', 's', 't')",
+            ModeToken::PermCopy => "permission copy ('u', 'g', 'o')",
+            ModeToken::Comma => "','",
+            ModeToken::OctalDigit => "octal digit",
+            ModeToken::End => "end of mode",
+        };
+        f.write_str(s)
     }
+}
 
-    let mut state = ParseState::Wholist;
-    let mut done_with_char;
-    let mut symbolic = ChmodSymbolic::new();
-    let mut clause = ChmodClause::new();
-    let mut action = ChmodAction::new();
+/// Error returned by [`parse`] for a malformed mode string.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ParseError {
+    /// Byte offset into the mode string at which parsing failed.
+    pub offset: usize,
 
-    for c in mode.chars() {
-        done_with_char = false;
-        while !done_with_char {
-            match state {
-                ParseState::Wholist => {
-                    done_with_char = true;
-                    clause.dirty = true;
-                    match c {
-                        'u' => clause.user = true,
-                        'g' => clause.group = true,
-                        'o' => clause.others = true,
-                        'a' => {
-                            clause.user = true;
-                            clause.group = true;
-                            clause.others = true;
-                        }
-                        _ => {
-                            state = ParseState::Actionlist;
-                            done_with_char = false;
-                            clause.dirty = false;
-                        }
-                    }
-                }
+    /// The offending character, or `None` if the string ended early.
+    pub found: Option<char>,
 
-                ParseState::Actionlist => {
-                    done_with_char = true;
-                    state = ParseState::ListOrCopy;
-                    action.dirty = true;
-                    match c {
-                        '+' => action.op = ChmodActionOp::Add,
-                        '-' => action.op = ChmodActionOp::Remove,
-                        '=' => action.op = ChmodActionOp::Set,
-                        _ => {
-                            action.dirty = false;
-                            done_with_char = false;
-                            symbolic.clauses.push(clause);
-                            clause = ChmodClause::new();
-                            state = ParseState::NextClause;
-                        }
-                    }
-                }
+    /// The tokens that would have been accepted at `offset`.
+    pub expected: Vec<ModeToken>,
+}
 
-                ParseState::ListOrCopy => match c {
-                    'u' | 'g' | 'o' => state = ParseState::PermCopy,
-                    _ => state = ParseState::PermList,
-                },
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("invalid mode string: ")?;
+        match self.found {
+            Some(c) => write!(f, "unexpected '{}'", c)?,
+            None => f.write_str("unexpected end")?,
+        }
+        write!(f, " at offset {}", self.offset)?;
+
+        for (i, token) in self.expected.iter().enumerate() {
+            if i == 0 {
+                f.write_str(", expected ")?;
+            } else if i == self.expected.len() - 1 {
+                f.write_str(" or ")?;
+            } else {
+                f.write_str(", ")?;
+            }
+            write!(f, "{}", token)?;
+        }
 
-                ParseState::PermCopy => {
-                    done_with_char = true;
-                    match c {
-                        'u' => action.copy_user = true,
-                        'g' => action.copy_group = true,
-                        'o' => action.copy_others = true,
-                        _ => {
-                            done_with_char = false;
-                            clause.actions.push(action);
-                            clause.dirty = true;
-                            action = ChmodAction::new();
-                            state = ParseState::Actionlist;
-                        }
-                    }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+// tokens that may follow an action, depending on how it ended
+const AFTER_OP: &[ModeToken] = &[
+    ModeToken::Perm,
+    ModeToken::PermCopy,
+    ModeToken::Op,
+    ModeToken::Comma,
+    ModeToken::End,
+];
+const AFTER_PERM: &[ModeToken] = &[
+    ModeToken::Perm,
+    ModeToken::Op,
+    ModeToken::Comma,
+    ModeToken::End,
+];
+const AFTER_COPY: &[ModeToken] = &[ModeToken::Op, ModeToken::Comma, ModeToken::End];
+
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::CharIndices<'a>>,
+    len: usize,
+
+    // what could have legally continued the most recent action
+    follow: &'static [ModeToken],
+}
+
+impl<'a> Parser<'a> {
+    fn new(mode: &'a str) -> Parser<'a> {
+        Parser {
+            chars: mode.char_indices().peekable(),
+            len: mode.len(),
+            follow: AFTER_OP,
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().map(|&(_, c)| c)
+    }
+
+    fn bump(&mut self) {
+        self.chars.next();
+    }
+
+    // build an error for the current position
+    fn error(&mut self, expected: &[ModeToken]) -> ParseError {
+        let (offset, found) = match self.chars.peek() {
+            Some(&(offset, c)) => (offset, Some(c)),
+            None => (self.len, None),
+        };
+        ParseError {
+            offset,
+            found,
+            expected: expected.to_vec(),
+        }
+    }
+
+    // absolute_mode : octal digits, no greater than 07777
+    fn absolute(&mut self) -> Result<u32, ParseError> {
+        let mut value: u32 = 0;
+
+        while let Some(c) = self.peek() {
+            let digit = match c.to_digit(8) {
+                Some(digit) => digit,
+                None => return Err(self.error(&[ModeToken::OctalDigit, ModeToken::End])),
+            };
+            value = (value << 3) | digit;
+            if value > MODE_BITS {
+                return Err(self.error(&[ModeToken::End]));
+            }
+            self.bump();
+        }
+
+        Ok(value)
+    }
+
+    // clause : wholist? actionlist
+    fn clause(&mut self) -> Result<ChmodClause, ParseError> {
+        let mut clause = ChmodClause::new();
+
+        while let Some(c) = self.peek() {
+            match c {
+                'u' => clause.user = true,
+                'g' => clause.group = true,
+                'o' => clause.others = true,
+                'a' => {
+                    clause.user = true;
+                    clause.group = true;
+                    clause.others = true;
                 }
+                _ => break,
+            }
+            self.bump();
+        }
+
+        if !(clause.user || clause.group || clause.others) {
+            clause.user = true;
+            clause.group = true;
+            clause.others = true;
+            clause.implicit_who = true;
+        }
+
+        // an actionlist holds at least one action
+        loop {
+            let action = self.action()?;
+            clause.actions.push(action);
 
-                ParseState::PermList => {
-                    done_with_char = true;
+            match self.peek() {
+                Some('+') | Some('-') | Some('=') => continue,
+                _ => break,
+            }
+        }
+
+        Ok(clause)
+    }
+
+    // action : op (permcopy | permlist?)
+    fn action(&mut self) -> Result<ChmodAction, ParseError> {
+        let mut action = ChmodAction::new();
+
+        action.op = match self.peek() {
+            Some('+') => ChmodActionOp::Add,
+            Some('-') => ChmodActionOp::Remove,
+            Some('=') => ChmodActionOp::Set,
+            _ => return Err(self.error(&[ModeToken::Who, ModeToken::Op])),
+        };
+        self.bump();
+
+        // permcopy is a single character
+        match self.peek() {
+            Some('u') => action.copy_user = true,
+            Some('g') => action.copy_group = true,
+            Some('o') => action.copy_others = true,
+            _ => {
+                self.follow = AFTER_OP;
+                while let Some(c) = self.peek() {
                     match c {
                         'r' => action.read = true,
                         'w' => action.write = true,
                         'x' => action.execute = true,
                         'X' => action.execute_dir = true,
-                        's' => action.setuid = true,
+                        's' => action.set_id = true,
                         't' => action.sticky = true,
-                        _ => {
-                            done_with_char = false;
-                            clause.actions.push(action);
-                            clause.dirty = true;
-                            action = ChmodAction::new();
-                            state = ParseState::Actionlist;
-                        }
+                        _ => break,
                     }
+                    self.follow = AFTER_PERM;
+                    self.bump();
                 }
+                return Ok(action);
+            }
+        }
+        self.follow = AFTER_COPY;
+        self.bump();
 
-                ParseState::NextClause => {
-                    if c != ',' {
-                        return Err("invalid mode string".to_string());
-                    }
-                    done_with_char = true;
-                    state = ParseState::Wholist;
-                }
+        Ok(action)
+    }
+
+    // symbolic_mode : clause (',' clause)*
+    fn symbolic(&mut self) -> Result<ChmodSymbolic, ParseError> {
+        let mut symbolic = ChmodSymbolic::new();
+
+        loop {
+            let clause = self.clause()?;
+            symbolic.clauses.push(clause);
+
+            match self.peek() {
+                None => break,
+                Some(',') => self.bump(),
+                Some(_) => return Err(self.error(self.follow)),
             }
         }
+
+        Ok(symbolic)
     }
+}
 
-    if action.dirty {
-        clause.actions.push(action);
-        clause.dirty = true;
+/// Parse a mode string, either an absolute octal mode or a symbolic mode
+/// as described for the POSIX chmod utility.
+pub fn parse(mode: &str) -> Result<ChmodMode, ParseError> {
+    let mut parser = Parser::new(mode);
+
+    match parser.peek() {
+        Some(c) if c.is_ascii_digit() => Ok(ChmodMode::Absolute(parser.absolute()?)),
+        _ => Ok(ChmodMode::Symbolic(parser.symbolic()?)),
     }
-    if clause.dirty {
-        symbolic.clauses.push(clause);
+}
+
+/// All permission bits that a mode string can affect.
+const MODE_BITS: u32 = 0o7777;
+
+impl ChmodAction {
+    // compute the bits named by this action's permlist or permcopy,
+    // replicated across all of user, group and others. `search` tells
+    // whether X grants execute.
+    fn value(&self, mode: u32, search: bool) -> u32 {
+        let mut value = 0;
+
+        if self.copy_user {
+            value |= replicate((mode & S_IRWXU as u32) >> 6);
+        }
+        if self.copy_group {
+            value |= replicate((mode & S_IRWXG as u32) >> 3);
+        }
+        if self.copy_others {
+            value |= replicate(mode & S_IRWXO as u32);
+        }
+
+        if self.read {
+            value |= (S_IRUSR | S_IRGRP | S_IROTH) as u32;
+        }
+        if self.write {
+            value |= (S_IWUSR | S_IWGRP | S_IWOTH) as u32;
+        }
+        if self.execute {
+            value |= (S_IXUSR | S_IXGRP | S_IXOTH) as u32;
+        }
+        if self.execute_dir && search {
+            value |= (S_IXUSR | S_IXGRP | S_IXOTH) as u32;
+        }
+        // left for the who-list to narrow down to S_ISUID (u) and/or
+        // S_ISGID (g)
+        if self.set_id {
+            value |= (S_ISUID | S_ISGID) as u32;
+        }
+        if self.sticky {
+            value |= S_ISVTX as u32;
+        }
+
+        value
     }
 
-    Ok(ChmodMode::Symbolic(symbolic))
+    // apply this action to `mode`, limited to the bits in `who` that
+    // are not masked by `umask`
+    fn apply(&self, mode: u32, who: u32, umask: u32, search: bool) -> u32 {
+        let value = self.value(mode, search) & who & !umask;
+
+        match self.op {
+            ChmodActionOp::Add => mode | value,
+            ChmodActionOp::Remove => mode & !value,
+            ChmodActionOp::Set => (mode & !who) | value,
+        }
+    }
 }
 
-// apply symbolic mutations to the given file at path
-pub fn mutate(cur_mode: u32, symbolic: &ChmodSymbolic) -> u32 {
-    let mut new_mode = cur_mode;
-    let mut user = cur_mode & S_IRWXU as u32;
-    let mut group = cur_mode & S_IRWXG as u32;
-    let mut others = cur_mode & S_IRWXO as u32;
-
-    // apply each clause
-    for clause in &symbolic.clauses {
-        // apply each action
-        for action in &clause.actions {
-            match action.op {
-                // add bits to the mode
-                ChmodActionOp::Add => {
-                    if action.copy_user {
-                        user |= cur_mode & S_IRWXU as u32;
-                    }
-                    if action.copy_group {
-                        group |= cur_mode & S_IRWXG as u32;
-                    }
-                    if action.copy_others {
-                        others |= cur_mode & S_IRWXO as u32;
-                    }
-                    if action.read {
-                        user |= S_IRUSR as u32;
-                        group |= S_IRGRP as u32;
-                        others |= S_IROTH as u32;
-                    }
-                    if action.write {
-                        user |= S_IWUSR as u32;
-                        group |= S_IWGRP as u32;
-                        others |= S_IWOTH as u32;
-                    }
-                    if action.execute {
-                        user |= S_IXUSR as u32;
-                        group |= S_IXGRP as u32;
-                        others |= S_IXOTH as u32;
-                    }
-                    if action.execute_dir {
-                        user |= S_IXUSR as u32;
-                        group |= S_IXGRP as u32;
-                        others |= S_IXOTH as u32;
-                    }
-                    if action.setuid {
-                        user |= S_ISUID as u32;
-                    }
-                    if action.sticky {
-                        others |= S_ISVTX as u32;
-                    }
-                }
+impl ChmodClause {
+    // the mode bits governed by this clause's who-list
+    fn who_mask(&self) -> u32 {
+        let mut who = 0;
+        if self.user {
+            who |= (S_ISUID | S_IRWXU) as u32;
+        }
+        if self.group {
+            who |= (S_ISGID | S_IRWXG) as u32;
+        }
+        if self.others {
+            who |= (S_ISVTX | S_IRWXO) as u32;
+        }
+        who
+    }
+}
 
-                // remove bits from the mode
-                ChmodActionOp::Remove => {
-                    if action.copy_user {
-                        user &= !(cur_mode & S_IRWXU as u32);
-                    }
-                    if action.copy_group {
-                        group &= !(cur_mode & S_IRWXG as u32);
-                    }
-                    if action.copy_others {
-                        others &= !(cur_mode & S_IRWXO as u32);
-                    }
-                    if action.read {
-                        user &= !S_IRUSR as u32;
-                        group &= !S_IRGRP as u32;
-                        others &= !S_IROTH as u32;
-                    }
-                    if action.write {
-                        user &= !S_IWUSR as u32;
-                        group &= !S_IWGRP as u32;
-                        others &= !S_IWOTH as u32;
-                    }
-                    if action.execute {
-                        user &= !S_IXUSR as u32;
-                        group &= !S_IXGRP as u32;
-                        others &= !S_IXOTH as u32;
-                    }
-                    if action.execute_dir {
-                        user &= !S_IXUSR as u32;
-                        group &= !S_IXGRP as u32;
-                        others &= !S_IXOTH as u32;
-                    }
-                    if action.setuid {
-                        user &= !S_ISUID as u32;
-                    }
-                    if action.sticky {
-                        others &= !S_ISVTX as u32;
-                    }
-                }
+impl ChmodSymbolic {
+    /// Apply each clause in order to the mode bits `current`.  See
+    /// [`ChmodMode::apply`].
+    pub fn apply(&self, current: u32, umask: u32, is_dir: bool) -> u32 {
+        let mut mode = current & MODE_BITS;
+
+        // X grants execute to directories, and to files that have at least
+        // one execute bit set in their unmodified mode.  Earlier clauses
+        // removing execute do not change this.
+        let search = is_dir || (current & (S_IXUSR | S_IXGRP | S_IXOTH) as u32) != 0;
+
+        for clause in &self.clauses {
+            let who = clause.who_mask();
+            let umask = if clause.implicit_who { umask } else { 0 };
+            for action in &clause.actions {
+                mode = action.apply(mode, who, umask, search);
+            }
+        }
 
-                // set the mode bits
-                ChmodActionOp::Set => {
-                    if action.copy_user {
-                        user = cur_mode & S_IRWXU as u32;
-                    } else {
-                        user = 0;
-                    }
-                    if action.copy_group {
-                        group = cur_mode & S_IRWXG as u32;
-                    } else {
-                        group = 0;
-                    }
-                    if action.copy_others {
-                        others = cur_mode & S_IRWXO as u32;
-                    } else {
-                        others = 0;
-                    }
-                    if action.read {
-                        user |= S_IRUSR as u32;
-                        group |= S_IRGRP as u32;
-                        others |= S_IROTH as u32;
-                    }
-                    if action.write {
-                        user |= S_IWUSR as u32;
-                        group |= S_IWGRP as u32;
-                        others |= S_IWOTH as u32;
-                    }
-                    if action.execute {
-                        user |= S_IXUSR as u32;
-                        group |= S_IXGRP as u32;
-                        others |= S_IXOTH as u32;
-                    }
-                    if action.execute_dir {
-                        user |= S_IXUSR as u32;
-                        group |= S_IXGRP as u32;
-                        others |= S_IXOTH as u32;
-                    }
-                    if action.setuid {
-                        user |= S_ISUID as u32;
-                    }
-                    if action.sticky {
-                        others |= S_ISVTX as u32;
-                    }
+        (current & !MODE_BITS) | mode
+    }
+}
+
+impl ChmodMode {
+    /// Compute the mode that results from applying this mode to a file
+    /// whose mode is currently `current`.
+    ///
+    /// Clauses without a who-list are filtered through `umask`, as
+    /// required for chmod(1), and conditional execute (`X`) is granted
+    /// only if `is_dir` is set or `current` already has an execute bit.
+    /// Bits of `current` outside the permission bits (such as the file
+    /// type) are preserved.
+    pub fn apply(&self, current: u32, umask: u32, is_dir: bool) -> u32 {
+        match self {
+            ChmodMode::Absolute(m) => (current & !MODE_BITS) | (m & MODE_BITS),
+            ChmodMode::Symbolic(s) => s.apply(current, umask, is_dir),
+        }
+    }
+}
+
+impl fmt::Display for ChmodActionOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let c = match self {
+            ChmodActionOp::Add => '+',
+            ChmodActionOp::Remove => '-',
+            ChmodActionOp::Set => '=',
+        };
+        write!(f, "{}", c)
+    }
+}
+
+impl fmt::Display for ChmodAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.op)?;
+
+        let letters = [
+            (self.copy_user, 'u'),
+            (self.copy_group, 'g'),
+            (self.copy_others, 'o'),
+            (self.read, 'r'),
+            (self.write, 'w'),
+            (self.execute, 'x'),
+            (self.execute_dir, 'X'),
+            (self.set_id, 's'),
+            (self.sticky, 't'),
+        ];
+        for (set, c) in letters {
+            if set {
+                write!(f, "{}", c)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for ChmodClause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.implicit_who {
+            if self.user && self.group && self.others {
+                f.write_str("a")?;
+            } else {
+                if self.user {
+                    f.write_str("u")?;
+                }
+                if self.group {
+                    f.write_str("g")?;
+                }
+                if self.others {
+                    f.write_str("o")?;
                 }
             }
         }
 
-        // apply the clause
-        if clause.user {
-            new_mode = (new_mode & !S_IRWXU as u32) | user;
+        for action in &self.actions {
+            write!(f, "{}", action)?;
         }
-        if clause.group {
-            new_mode = (new_mode & !S_IRWXG as u32) | group;
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for ChmodSymbolic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, clause) in self.clauses.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{}", clause)?;
         }
-        if clause.others {
-            new_mode = (new_mode & !S_IRWXO as u32) | others;
+        Ok(())
+    }
+}
+
+impl fmt::Display for ChmodMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChmodMode::Absolute(m) => write!(f, "{:o}", m),
+            ChmodMode::Symbolic(s) => write!(f, "{}", s),
         }
     }
+}
+
+/// Render the permission bits of `mode` in the nine-character form used
+/// by `ls -l`, such as `rwxr-xr-x`.
+///
+/// Set-user-ID, set-group-ID and sticky bits replace the corresponding
+/// execute position with `s`, `s` and `t`, or with `S`, `S` and `T` if
+/// that execute bit is clear.
+pub fn mode_to_string(mode: u32) -> String {
+    let mut s = String::with_capacity(9);
+
+    let classes = [
+        (S_IRUSR, S_IWUSR, S_IXUSR, S_ISUID, 's'),
+        (S_IRGRP, S_IWGRP, S_IXGRP, S_ISGID, 's'),
+        (S_IROTH, S_IWOTH, S_IXOTH, S_ISVTX, 't'),
+    ];
+    for (read, write, execute, special, special_char) in classes {
+        s.push(if mode & read as u32 != 0 { 'r' } else { '-' });
+        s.push(if mode & write as u32 != 0 { 'w' } else { '-' });
+        s.push(
+            match (mode & execute as u32 != 0, mode & special as u32 != 0) {
+                (true, true) => special_char,
+                (false, true) => special_char.to_ascii_uppercase(),
+                (true, false) => 'x',
+                (false, false) => '-',
+            },
+        );
+    }
 
-    new_mode
+    s
+}
+
+/// Return the file mode creation mask of the calling process.
+pub fn umask() -> u32 {
+    // umask(2) can only be read by setting it, so restore it immediately
+    let mask = unsafe { libc::umask(0) };
+    unsafe { libc::umask(mask) };
+    mask as u32
+}
+
+// copy a 3-bit rwx value into the user, group and others positions
+fn replicate(bits: u32) -> u32 {
+    (bits << 6) | (bits << 3) | bits
 }
 
 #[cfg(test)]
@@ -400,7 +620,7 @@ mod tests {
                 assert_eq!(action.write, true);
                 assert_eq!(action.execute, false);
                 assert_eq!(action.execute_dir, true);
-                assert_eq!(action.setuid, false);
+                assert_eq!(action.set_id, false);
                 assert_eq!(action.sticky, false);
                 let clause = &s.clauses[1];
                 assert_eq!(clause.user, false);
@@ -416,10 +636,195 @@ mod tests {
                 assert_eq!(action.write, false);
                 assert_eq!(action.execute, false);
                 assert_eq!(action.execute_dir, true);
-                assert_eq!(action.setuid, false);
+                assert_eq!(action.set_id, false);
                 assert_eq!(action.sticky, false);
             }
             _ => panic!("unexpected mode"),
         }
     }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = parse("u=rwqz").unwrap_err();
+        assert_eq!(err.offset, 4);
+        assert_eq!(err.found, Some('q'));
+        assert_eq!(
+            err.expected,
+            vec![
+                ModeToken::Perm,
+                ModeToken::Op,
+                ModeToken::Comma,
+                ModeToken::End
+            ]
+        );
+
+        // trailing comma
+        let err = parse("u+x,").unwrap_err();
+        assert_eq!(err.offset, 4);
+        assert_eq!(err.found, None);
+        assert_eq!(err.expected, vec![ModeToken::Who, ModeToken::Op]);
+
+        // empty clauses
+        assert_eq!(parse("").unwrap_err().offset, 0);
+        assert_eq!(parse("ug").unwrap_err().offset, 2);
+        assert_eq!(parse("u+x,,g+x").unwrap_err().offset, 4);
+
+        // permcopy is a single who letter
+        let err = parse("u=go").unwrap_err();
+        assert_eq!(err.offset, 3);
+        assert_eq!(
+            err.expected,
+            vec![ModeToken::Op, ModeToken::Comma, ModeToken::End]
+        );
+
+        // absolute modes
+        assert_eq!(parse("0758").unwrap_err().offset, 3);
+        assert_eq!(parse("17777").unwrap_err().offset, 4);
+        assert!(matches!(parse("7777"), Ok(ChmodMode::Absolute(0o7777))));
+    }
+
+    #[test]
+    fn test_parse_error_display() {
+        let err = parse("a+z").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid mode string: unexpected 'z' at offset 2, expected permission ('r', 'w', \
+             'x', 'X', 's', 't'), permission copy ('u', 'g', 'o'), operator ('+', '-', '='), \
+             ',' or end of mode"
+        );
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        for s in ["u=rwX,go=rX", "a+x", "+w", "ug-s", "o=u", "u+r-w=x", "644"] {
+            assert_eq!(parse(s).unwrap().to_string(), s);
+        }
+
+        // the who-list is normalized
+        assert_eq!(parse("ugo+t").unwrap().to_string(), "a+t");
+        assert_eq!(parse("ogu=").unwrap().to_string(), "a=");
+        assert_eq!(parse("go+xr").unwrap().to_string(), "go+rx");
+    }
+
+    #[test]
+    fn test_mode_to_string() {
+        assert_eq!(mode_to_string(0o755), "rwxr-xr-x");
+        assert_eq!(mode_to_string(0o100644), "rw-r--r--");
+        assert_eq!(mode_to_string(0o000), "---------");
+        assert_eq!(mode_to_string(0o4755), "rwsr-xr-x");
+        assert_eq!(mode_to_string(0o2644), "rw-r-Sr--");
+        assert_eq!(mode_to_string(0o1777), "rwxrwxrwt");
+        assert_eq!(mode_to_string(0o1666), "rw-rw-rwT");
+    }
+
+    #[test]
+    fn test_apply_absolute() {
+        let mode = parse("755").unwrap();
+        assert_eq!(mode.apply(0o100600, 0o022, false), 0o100755);
+    }
+
+    #[test]
+    fn test_apply_symbolic() {
+        let mode = parse("u+x,g-w,o=r").unwrap();
+        assert_eq!(mode.apply(0o666, 0, false), 0o744);
+
+        let mode = parse("go=u").unwrap();
+        assert_eq!(mode.apply(0o750, 0, false), 0o777);
+
+        let mode = parse("u=g").unwrap();
+        assert_eq!(mode.apply(0o650, 0, false), 0o550);
+
+        let mode = parse("a-x,u+s").unwrap();
+        assert_eq!(mode.apply(0o755, 0, false), 0o4644);
+    }
+
+    #[test]
+    fn test_apply_set_id() {
+        let mode = parse("u+s").unwrap();
+        assert_eq!(mode.apply(0o755, 0, false), 0o4755);
+
+        let mode = parse("g+s").unwrap();
+        assert_eq!(mode.apply(0o755, 0, false), 0o2755);
+
+        let mode = parse("u+s,g-s").unwrap();
+        assert_eq!(mode.apply(0o2755, 0, false), 0o4755);
+
+        let mode = parse("ug+s").unwrap();
+        assert_eq!(mode.apply(0o755, 0, false), 0o6755);
+        let mode = parse("+s").unwrap();
+        assert_eq!(mode.apply(0o755, 0o022, false), 0o6755);
+
+        // others have no set-ID bit
+        let mode = parse("o+s").unwrap();
+        assert_eq!(mode.apply(0o755, 0, false), 0o755);
+
+        // = clears the set-ID bit of each class it names
+        let mode = parse("g=rx").unwrap();
+        assert_eq!(mode.apply(0o6755, 0, false), 0o4755);
+        let mode = parse("u=rwxs").unwrap();
+        assert_eq!(mode.apply(0o2755, 0, false), 0o6755);
+    }
+
+    #[test]
+    fn test_apply_umask() {
+        let mode = parse("+w").unwrap();
+        assert_eq!(mode.apply(0o444, 0o022, false), 0o644);
+
+        let mode = parse("a+w").unwrap();
+        assert_eq!(mode.apply(0o444, 0o022, false), 0o666);
+
+        let mode = parse("=rx").unwrap();
+        assert_eq!(mode.apply(0o777, 0o022, false), 0o555);
+        assert_eq!(mode.apply(0o4777, 0o077, false), 0o500);
+
+        let mode = parse("-w").unwrap();
+        assert_eq!(mode.apply(0o666, 0o002, false), 0o446);
+
+        // a who-list, even a partial one, is not filtered
+        let mode = parse("o+w").unwrap();
+        assert_eq!(mode.apply(0o644, 0o022, false), 0o646);
+    }
+
+    #[test]
+    fn test_apply_conditional_execute_file() {
+        // plain files without any execute bit never gain one
+        let mode = parse("a+X").unwrap();
+        assert_eq!(mode.apply(0o100644, 0, false), 0o100644);
+        let mode = parse("u=rwX").unwrap();
+        assert_eq!(mode.apply(0o100644, 0, false), 0o100644);
+        assert_eq!(mode.apply(0o100444, 0, false), 0o100644);
+        let mode = parse("=X").unwrap();
+        assert_eq!(mode.apply(0o100666, 0, false), 0o100000);
+    }
+
+    #[test]
+    fn test_apply_conditional_execute_executable() {
+        // any execute bit counts, not just the one being set
+        let mode = parse("a+X").unwrap();
+        assert_eq!(mode.apply(0o100744, 0, false), 0o100755);
+        assert_eq!(mode.apply(0o100641, 0, false), 0o100751);
+        let mode = parse("u=rwX").unwrap();
+        assert_eq!(mode.apply(0o100654, 0, false), 0o100754);
+
+        // X looks at the unmodified mode, not at the result of earlier
+        // clauses
+        let mode = parse("a-x,a+X").unwrap();
+        assert_eq!(mode.apply(0o100755, 0, false), 0o100755);
+        let mode = parse("a=r,u+X").unwrap();
+        assert_eq!(mode.apply(0o100700, 0, false), 0o100544);
+    }
+
+    #[test]
+    fn test_apply_conditional_execute_dir() {
+        let mode = parse("a+X").unwrap();
+        assert_eq!(mode.apply(0o040644, 0, true), 0o040755);
+        let mode = parse("u=rwX,go=rX").unwrap();
+        assert_eq!(mode.apply(0o040600, 0, true), 0o040755);
+        let mode = parse("go-X").unwrap();
+        assert_eq!(mode.apply(0o040755, 0, true), 0o040744);
+
+        // the umask still applies to a who-less X
+        let mode = parse("+X").unwrap();
+        assert_eq!(mode.apply(0o040600, 0o027, true), 0o040710);
+    }
 }
@@ -0,0 +1,252 @@
+//
+// Copyright (c) 2024 Jeff Garzik
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+//! User and group ID lookups, and parsing of `owner[:group]` specs.
+//!
+//! Lookups go through the user and group databases (`getpwnam`, `getgrnam`,
+//! `getpwuid` and `getgrgid`) and the results, including misses, are cached
+//! for the lifetime of the process. That keeps recursive operations and long
+//! listings from querying NSS once per file.
+
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+use std::fmt;
+use std::sync::Mutex;
+
+#[derive(Default)]
+struct IdCache {
+    uids: HashMap<String, Option<u32>>,
+    gids: HashMap<String, Option<u32>>,
+    user_names: HashMap<u32, Option<String>>,
+    group_names: HashMap<u32, Option<String>>,
+}
+
+static CACHE: Mutex<Option<IdCache>> = Mutex::new(None);
+
+// The lock is held across the libc call as the returned entries point to
+// static storage that the next lookup overwrites.
+fn with_cache<T>(f: impl FnOnce(&mut IdCache) -> T) -> T {
+    let mut cache = CACHE.lock().unwrap_or_else(|e| e.into_inner());
+    f(cache.get_or_insert_with(IdCache::default))
+}
+
+/// Look up the user ID of the user `name`.
+pub fn uid_by_name(name: &str) -> Option<u32> {
+    with_cache(|cache| {
+        if let Some(uid) = cache.uids.get(name) {
+            return *uid;
+        }
+
+        let uid = CString::new(name).ok().and_then(|name| unsafe {
+            let passwd = libc::getpwnam(name.as_ptr());
+            passwd.as_ref().map(|passwd| passwd.pw_uid)
+        });
+        cache.uids.insert(name.to_string(), uid);
+        uid
+    })
+}
+
+/// Look up the group ID of the group `name`.
+pub fn gid_by_name(name: &str) -> Option<u32> {
+    with_cache(|cache| {
+        if let Some(gid) = cache.gids.get(name) {
+            return *gid;
+        }
+
+        let gid = CString::new(name).ok().and_then(|name| unsafe {
+            let group = libc::getgrnam(name.as_ptr());
+            group.as_ref().map(|group| group.gr_gid)
+        });
+        cache.gids.insert(name.to_string(), gid);
+        gid
+    })
+}
+
+/// Look up the name of the user with ID `uid`.
+pub fn user_name(uid: u32) -> Option<String> {
+    with_cache(|cache| {
+        cache
+            .user_names
+            .entry(uid)
+            .or_insert_with(|| unsafe {
+                let passwd = libc::getpwuid(uid);
+                passwd
+                    .as_ref()
+                    .map(|passwd| CStr::from_ptr(passwd.pw_name).to_string_lossy().to_string())
+            })
+            .clone()
+    })
+}
+
+/// Look up the name of the group with ID `gid`.
+pub fn group_name(gid: u32) -> Option<String> {
+    with_cache(|cache| {
+        cache
+            .group_names
+            .entry(gid)
+            .or_insert_with(|| unsafe {
+                let group = libc::getgrgid(gid);
+                group
+                    .as_ref()
+                    .map(|group| CStr::from_ptr(group.gr_name).to_string_lossy().to_string())
+            })
+            .clone()
+    })
+}
+
+/// Error returned when a user or group in a spec cannot be resolved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OwnerError {
+    InvalidUser(String),
+    InvalidGroup(String),
+}
+
+impl fmt::Display for OwnerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OwnerError::InvalidUser(user) => write!(f, "invalid user: '{}'", user),
+            OwnerError::InvalidGroup(group) => write!(f, "invalid group: '{}'", group),
+        }
+    }
+}
+
+impl std::error::Error for OwnerError {}
+
+/// Resolve a user name, or a numeric user ID if no such user exists.
+pub fn parse_user(user: &str) -> Result<u32, OwnerError> {
+    uid_by_name(user)
+        .or_else(|| user.parse().ok())
+        .ok_or_else(|| OwnerError::InvalidUser(user.to_string()))
+}
+
+/// Resolve a group name, or a numeric group ID if no such group exists.
+pub fn parse_group(group: &str) -> Result<u32, OwnerError> {
+    gid_by_name(group)
+        .or_else(|| group.parse().ok())
+        .ok_or_else(|| OwnerError::InvalidGroup(group.to_string()))
+}
+
+/// A parsed `owner[:group]` spec. Parts that were not given are `None`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct OwnerSpec {
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+}
+
+impl OwnerSpec {
+    /// Check whether a file owned by `uid` and `gid` matches the spec. Parts
+    /// that were not given match any ID.
+    pub fn matches(&self, uid: u32, gid: u32) -> bool {
+        self.uid.is_none_or(|u| u == uid) && self.gid.is_none_or(|g| g == gid)
+    }
+}
+
+/// Parse an `owner[:group]` spec. Either part may be empty, as in `owner`,
+/// `owner:` or `:group`.
+pub fn parse_owner_spec(spec: &str) -> Result<OwnerSpec, OwnerError> {
+    let (owner, group) = spec.split_once(':').unwrap_or((spec, ""));
+
+    let uid = if owner.is_empty() {
+        None
+    } else {
+        Some(parse_user(owner)?)
+    };
+    let gid = if group.is_empty() {
+        None
+    } else {
+        Some(parse_group(group)?)
+    };
+
+    Ok(OwnerSpec { uid, gid })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup_root() {
+        assert_eq!(uid_by_name("root"), Some(0));
+        assert_eq!(user_name(0).as_deref(), Some("root"));
+
+        // served from the cache the second time
+        assert_eq!(uid_by_name("root"), Some(0));
+        assert_eq!(user_name(0).as_deref(), Some("root"));
+    }
+
+    #[test]
+    fn test_lookup_missing() {
+        assert_eq!(uid_by_name("no-such-user-posixutils"), None);
+        assert_eq!(gid_by_name("no-such-group-posixutils"), None);
+        assert_eq!(uid_by_name("no-such-user-posixutils"), None);
+        assert_eq!(uid_by_name("nul\0byte"), None);
+    }
+
+    #[test]
+    fn test_parse_numeric_fallback() {
+        assert_eq!(parse_user("123456"), Ok(123456));
+        assert_eq!(parse_group("654321"), Ok(654321));
+        assert_eq!(
+            parse_user("no-such-user-posixutils"),
+            Err(OwnerError::InvalidUser(
+                "no-such-user-posixutils".to_string()
+            ))
+        );
+        assert_eq!(
+            parse_group("-1"),
+            Err(OwnerError::InvalidGroup("-1".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parse_owner_spec() {
+        assert_eq!(
+            parse_owner_spec("root"),
+            Ok(OwnerSpec {
+                uid: Some(0),
+                gid: None
+            })
+        );
+        assert_eq!(
+            parse_owner_spec("123:456"),
+            Ok(OwnerSpec {
+                uid: Some(123),
+                gid: Some(456)
+            })
+        );
+        assert_eq!(
+            parse_owner_spec(":456"),
+            Ok(OwnerSpec {
+                uid: None,
+                gid: Some(456)
+            })
+        );
+        assert_eq!(
+            parse_owner_spec("123:"),
+            Ok(OwnerSpec {
+                uid: Some(123),
+                gid: None
+            })
+        );
+        assert_eq!(
+            parse_owner_spec("123:nogroup-posixutils")
+                .unwrap_err()
+                .to_string(),
+            "invalid group: 'nogroup-posixutils'"
+        );
+    }
+
+    #[test]
+    fn test_owner_spec_matches() {
+        let spec = parse_owner_spec(":456").unwrap();
+        assert!(spec.matches(1, 456));
+        assert!(!spec.matches(1, 457));
+        assert!(OwnerSpec::default().matches(1, 2));
+    }
+}
@@ -0,0 +1,293 @@
+//
+// Copyright (c) 2024 Jeff Garzik
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+//! Progress reports for long-running copies (`cp`, `mv`, `dd`).
+//!
+//! A `Progress` counts the bytes and files processed. A report is written to
+//! standard error every second when periodic reports were requested, and
+//! whenever SIGUSR1 (or SIGINFO, where it exists) arrives once
+//! `install_signal_handler` has been called. Periodic reports on a terminal
+//! keep rewriting a single line; otherwise each report is a line of its own.
+
+use std::cell::Cell;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+// Set by the signal handler, cleared when the report is written
+static REPORT_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn request_report(_signo: libc::c_int) {
+    REPORT_REQUESTED.store(true, Ordering::Relaxed);
+}
+
+/// Write a report whenever SIGUSR1 or SIGINFO is received. Replaces the
+/// default action of these signals, which is to terminate the process
+/// (SIGUSR1) or to do nothing (SIGINFO).
+pub fn install_signal_handler() {
+    let handler = request_report as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    unsafe {
+        libc::signal(libc::SIGUSR1, handler);
+        #[cfg(any(
+            target_os = "macos",
+            target_os = "ios",
+            target_os = "freebsd",
+            target_os = "dragonfly",
+            target_os = "netbsd",
+            target_os = "openbsd"
+        ))]
+        libc::signal(libc::SIGINFO, handler);
+    }
+}
+
+/// An exponentially weighted moving average of a transfer rate, so that the
+/// estimated time remaining follows changes in speed without jumping around
+/// on every sample.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Rate {
+    last_bytes: u64,
+    last_elapsed: Duration,
+    bytes_per_sec: Option<f64>,
+}
+
+impl Rate {
+    // Weight of the newest sample
+    const ALPHA: f64 = 0.3;
+
+    /// Add a sample: `bytes` transferred in total after `elapsed`.
+    pub fn update(&mut self, bytes: u64, elapsed: Duration) {
+        let secs = elapsed.saturating_sub(self.last_elapsed).as_secs_f64();
+        if secs <= 0.0 {
+            return;
+        }
+        let sample = bytes.saturating_sub(self.last_bytes) as f64 / secs;
+        self.bytes_per_sec = Some(match self.bytes_per_sec {
+            Some(rate) => Self::ALPHA * sample + (1.0 - Self::ALPHA) * rate,
+            None => sample,
+        });
+        self.last_bytes = bytes;
+        self.last_elapsed = elapsed;
+    }
+
+    /// The current rate in bytes per second, if any samples were added.
+    pub fn bytes_per_sec(&self) -> Option<f64> {
+        self.bytes_per_sec
+    }
+
+    /// The time needed for `remaining` bytes at the current rate.
+    pub fn eta(&self, remaining: u64) -> Option<Duration> {
+        match self.bytes_per_sec {
+            Some(rate) if rate > 0.0 => Duration::try_from_secs_f64(remaining as f64 / rate).ok(),
+            _ => None,
+        }
+    }
+}
+
+/// Format a byte count with a binary prefix, such as `1.5 MiB`.
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 6] = ["KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+/// Format a duration as `M:SS`, or `H:MM:SS` from an hour up.
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (hours, mins, secs) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, mins, secs)
+    } else {
+        format!("{}:{:02}", mins, secs)
+    }
+}
+
+/// Counters for a copy, reporting on them when asked to.
+///
+/// The counters use interior mutability so a `Progress` can be shared by
+/// reference with code that only gets immutable access to its configuration.
+pub struct Progress {
+    util: String,
+    periodic: bool,
+    rewrite_line: bool,
+    start: Instant,
+    bytes: Cell<u64>,
+    files: Cell<u64>,
+    total_bytes: Cell<Option<u64>>,
+    rate: Cell<Rate>,
+    last_report: Cell<Instant>,
+    line_len: Cell<usize>,
+}
+
+impl Progress {
+    /// Create the counters for `util`, which prefixes the reports. With
+    /// `periodic`, a report is written every second.
+    pub fn new(util: &str, periodic: bool) -> Self {
+        let now = Instant::now();
+        Self {
+            util: util.to_string(),
+            periodic,
+            rewrite_line: periodic && atty::is(atty::Stream::Stderr),
+            start: now,
+            bytes: Cell::new(0),
+            files: Cell::new(0),
+            total_bytes: Cell::new(None),
+            rate: Cell::new(Rate::default()),
+            last_report: Cell::new(now),
+            line_len: Cell::new(0),
+        }
+    }
+
+    /// Set the number of bytes expected in total, enabling the percentage
+    /// and estimated time remaining in the reports.
+    pub fn set_total_bytes(&self, total: u64) {
+        self.total_bytes.set(Some(total));
+    }
+
+    /// Count `n` more bytes as processed.
+    pub fn add_bytes(&self, n: u64) {
+        self.bytes.set(self.bytes.get() + n);
+        self.poll();
+    }
+
+    /// Count one more file as processed.
+    pub fn add_file(&self) {
+        self.files.set(self.files.get() + 1);
+        self.poll();
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.bytes.get()
+    }
+
+    pub fn files(&self) -> u64 {
+        self.files.get()
+    }
+
+    /// Write a report if one is due.
+    pub fn poll(&self) {
+        let signaled = REPORT_REQUESTED.swap(false, Ordering::Relaxed);
+        if signaled || (self.periodic && self.last_report.get().elapsed() >= REPORT_INTERVAL) {
+            self.report();
+        }
+    }
+
+    /// Write the final report if periodic reports were requested.
+    pub fn finish(&self) {
+        if self.periodic {
+            self.report();
+            if self.rewrite_line {
+                let _ = writeln!(io::stderr());
+            }
+        }
+    }
+
+    fn status_line(&self) -> String {
+        let elapsed = self.start.elapsed();
+        let bytes = self.bytes.get();
+
+        let mut rate = self.rate.get();
+        rate.update(bytes, elapsed);
+        self.rate.set(rate);
+
+        let mut line = format!(
+            "{}: {} bytes ({}) copied",
+            self.util,
+            bytes,
+            format_bytes(bytes)
+        );
+        match self.files.get() {
+            0 => (),
+            1 => line.push_str(", 1 file"),
+            files => line.push_str(&format!(", {} files", files)),
+        }
+        line.push_str(&format!(", {:.1} s", elapsed.as_secs_f64()));
+        if let Some(bytes_per_sec) = rate.bytes_per_sec() {
+            line.push_str(&format!(", {}/s", format_bytes(bytes_per_sec as u64)));
+        }
+        if let Some(total) = self.total_bytes.get().filter(|total| *total > 0) {
+            let percent = (bytes.min(total) as f64 * 100.0 / total as f64) as u64;
+            line.push_str(&format!(", {}%", percent));
+            if let Some(eta) = rate.eta(total.saturating_sub(bytes)) {
+                line.push_str(&format!(", ETA {}", format_duration(eta)));
+            }
+        }
+        line
+    }
+
+    /// Write a report now.
+    pub fn report(&self) {
+        let line = self.status_line();
+        let mut stderr = io::stderr().lock();
+        if self.rewrite_line {
+            // Pad with spaces to blank out the rest of a longer, earlier line
+            let width = self.line_len.get().max(line.len());
+            let _ = write!(stderr, "\r{:<width$}", line, width = width);
+            self.line_len.set(line.len());
+        } else {
+            let _ = writeln!(stderr, "{}", line);
+        }
+        let _ = stderr.flush();
+        self.last_report.set(Instant::now());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(0), "0 B");
+        assert_eq!(format_bytes(1023), "1023 B");
+        assert_eq!(format_bytes(1024), "1.0 KiB");
+        assert_eq!(format_bytes(1536 * 1024), "1.5 MiB");
+        assert_eq!(format_bytes(u64::MAX), "16.0 EiB");
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::from_secs(0)), "0:00");
+        assert_eq!(format_duration(Duration::from_secs(75)), "1:15");
+        assert_eq!(
+            format_duration(Duration::from_secs(3 * 3600 + 62)),
+            "3:01:02"
+        );
+    }
+
+    #[test]
+    fn test_rate() {
+        let mut rate = Rate::default();
+        assert_eq!(rate.bytes_per_sec(), None);
+        assert_eq!(rate.eta(100), None);
+
+        rate.update(1000, Duration::from_secs(1));
+        assert_eq!(rate.bytes_per_sec(), Some(1000.0));
+        assert_eq!(rate.eta(5000), Some(Duration::from_secs(5)));
+
+        // A sample at no time since the last one is ignored
+        rate.update(2000, Duration::from_secs(1));
+        assert_eq!(rate.bytes_per_sec(), Some(1000.0));
+
+        // 2000 B/s over the last second, weighted against the earlier rate
+        rate.update(3000, Duration::from_secs(2));
+        let bytes_per_sec = rate.bytes_per_sec().unwrap();
+        assert!((bytes_per_sec - 1300.0).abs() < 1e-9, "{bytes_per_sec}");
+    }
+}
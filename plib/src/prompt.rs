@@ -0,0 +1,96 @@
+//
+// Copyright (c) 2024 Jeff Garzik
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+//! Asking the user for confirmation, as done by `cp -i`, `mv -i` and `rm -i`.
+//!
+//! The prompt is written to standard error and the response is read from
+//! standard input, whether or not it is a terminal. Whether a response is
+//! affirmative is decided by the `yesexpr` of the `LC_MESSAGES` locale.
+
+use std::ffi::{CStr, CString};
+use std::io::{self, Write};
+use std::mem::MaybeUninit;
+
+// Used if the locale does not provide a usable `yesexpr`
+const DEFAULT_YESEXPR: &str = "^[yY]";
+
+fn matches_yesexpr(yesexpr: &CStr, response: &CStr) -> Option<bool> {
+    unsafe {
+        let mut re: MaybeUninit<libc::regex_t> = MaybeUninit::zeroed();
+        let ret = libc::regcomp(
+            re.as_mut_ptr(),
+            yesexpr.as_ptr(),
+            libc::REG_EXTENDED | libc::REG_NOSUB,
+        );
+        if ret != 0 {
+            return None;
+        }
+        let mut re = re.assume_init();
+        let ret = libc::regexec(&re, response.as_ptr(), 0, std::ptr::null_mut(), 0);
+        libc::regfree(&mut re);
+        Some(ret == 0)
+    }
+}
+
+/// Return true if `response`, a line read from the user without its trailing
+/// newline, is affirmative in the current locale.
+pub fn is_affirmative(response: &str) -> bool {
+    let Ok(response) = CString::new(response) else {
+        return false;
+    };
+
+    let yesexpr = unsafe {
+        let ptr = libc::nl_langinfo(libc::YESEXPR);
+        if ptr.is_null() {
+            None
+        } else {
+            Some(CStr::from_ptr(ptr).to_owned())
+        }
+    };
+    if let Some(yesexpr) = yesexpr.filter(|expr| !expr.is_empty()) {
+        if let Some(matched) = matches_yesexpr(&yesexpr, &response) {
+            return matched;
+        }
+    }
+
+    let default = CString::new(DEFAULT_YESEXPR).unwrap();
+    matches_yesexpr(&default, &response).unwrap_or(false)
+}
+
+/// Write `message` to standard error as `util: message `, then read a line
+/// from standard input. Returns true if the response is affirmative. End of
+/// file or a read error counts as a negative response.
+pub fn prompt_user(util: &str, message: &str) -> bool {
+    let mut stderr = io::stderr().lock();
+    let _ = write!(stderr, "{}: {} ", util, message);
+    let _ = stderr.flush();
+    drop(stderr);
+
+    let mut response = String::new();
+    match io::stdin().read_line(&mut response) {
+        Ok(0) | Err(_) => false,
+        Ok(_) => is_affirmative(response.trim_end_matches(['\n', '\r'])),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Tests run in the C locale unless `setlocale` is called
+    #[test]
+    fn test_is_affirmative() {
+        for response in ["y", "Y", "yes", "Yes please", "y "] {
+            assert!(is_affirmative(response), "{response}");
+        }
+        for response in ["", "n", "no", " y", "ok", "1"] {
+            assert!(!is_affirmative(response), "{response}");
+        }
+    }
+}
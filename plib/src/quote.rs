@@ -0,0 +1,131 @@
+//
+// Copyright (c) 2024 Jeff Garzik
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+//! Display of file names that may hold invalid UTF-8 or control characters.
+//!
+//! Writing such names to a terminal as is can corrupt its state, so utilities
+//! pick a `Quoting` style to turn them into printable text.
+
+/// How to write the characters of a name that cannot be shown as is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quoting {
+    /// Write the bytes of the name as they are, even those that are not
+    /// valid UTF-8.
+    Literal,
+
+    /// Write non-printable characters, `<tab>` and invalid UTF-8 as `?`
+    /// (`ls -q`).
+    Hide,
+
+    /// Write non-printable characters as C-style backslash escapes and
+    /// invalid UTF-8 as octal escapes of each byte (`ls -b`). Backslashes and
+    /// spaces are escaped too so the result can be read back unambiguously.
+    Escape,
+}
+
+fn push_octal(res: &mut Vec<u8>, bytes: &[u8]) {
+    for b in bytes {
+        res.extend_from_slice(format!("\\{:03o}", b).as_bytes());
+    }
+}
+
+fn push_escaped(res: &mut Vec<u8>, c: char) {
+    let mut buf = [0u8; 4];
+    match c {
+        '\x07' => res.extend_from_slice(b"\\a"),
+        '\x08' => res.extend_from_slice(b"\\b"),
+        '\t' => res.extend_from_slice(b"\\t"),
+        '\n' => res.extend_from_slice(b"\\n"),
+        '\x0b' => res.extend_from_slice(b"\\v"),
+        '\x0c' => res.extend_from_slice(b"\\f"),
+        '\r' => res.extend_from_slice(b"\\r"),
+        '\\' => res.extend_from_slice(b"\\\\"),
+        ' ' => res.extend_from_slice(b"\\ "),
+        c if c.is_control() => push_octal(res, c.encode_utf8(&mut buf).as_bytes()),
+        c => res.extend_from_slice(c.encode_utf8(&mut buf).as_bytes()),
+    }
+}
+
+/// Convert the raw bytes of `name` to printable bytes using `quoting`.
+///
+/// Only `Quoting::Literal` can return bytes that are not valid UTF-8.
+pub fn quote(name: &[u8], quoting: Quoting) -> Vec<u8> {
+    let mut res = Vec::with_capacity(name.len());
+    let mut buf = [0u8; 4];
+
+    for chunk in name.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match quoting {
+                Quoting::Hide if c.is_control() => res.push(b'?'),
+                Quoting::Literal | Quoting::Hide => {
+                    res.extend_from_slice(c.encode_utf8(&mut buf).as_bytes())
+                }
+                Quoting::Escape => push_escaped(&mut res, c),
+            }
+        }
+
+        let invalid = chunk.invalid();
+        if !invalid.is_empty() {
+            match quoting {
+                Quoting::Literal => res.extend_from_slice(invalid),
+                Quoting::Hide => res.push(b'?'),
+                Quoting::Escape => push_octal(&mut res, invalid),
+            }
+        }
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote_printable() {
+        for quoting in [Quoting::Literal, Quoting::Hide, Quoting::Escape] {
+            assert_eq!(
+                quote(b"plain-name.txt", quoting),
+                "plain-name.txt".as_bytes()
+            );
+            assert_eq!(quote("日本語".as_bytes(), quoting), "日本語".as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_quote_literal() {
+        assert_eq!(quote(b"a\tb\nc", Quoting::Literal), "a\tb\nc".as_bytes());
+        assert_eq!(quote(b"a\xffb", Quoting::Literal), b"a\xffb");
+    }
+
+    #[test]
+    fn test_quote_hide() {
+        assert_eq!(
+            quote(b"a\tb\nc\x1b[0m", Quoting::Hide),
+            "a?b?c?[0m".as_bytes()
+        );
+        assert_eq!(quote(b"a\xff\xfeb", Quoting::Hide), "a??b".as_bytes());
+        assert_eq!(quote(b"a b", Quoting::Hide), "a b".as_bytes());
+    }
+
+    #[test]
+    fn test_quote_escape() {
+        assert_eq!(quote(b"a\tb\nc", Quoting::Escape), "a\\tb\\nc".as_bytes());
+        assert_eq!(quote(b"a b\\c", Quoting::Escape), "a\\ b\\\\c".as_bytes());
+        assert_eq!(quote(b"\x1b\x7f", Quoting::Escape), "\\033\\177".as_bytes());
+        assert_eq!(
+            quote(b"a\xff\xfeb", Quoting::Escape),
+            "a\\377\\376b".as_bytes()
+        );
+        assert_eq!(
+            quote("\u{85}".as_bytes(), Quoting::Escape),
+            "\\302\\205".as_bytes()
+        );
+    }
+}
@@ -7,7 +7,12 @@
 // SPDX-License-Identifier: MIT
 //
 
-use plib::{run_test, TestPlan};
+use plib::{run_test, run_test_u8, TestPlan, TestPlanU8};
+use std::io::Write;
+use std::os::unix::process::ExitStatusExt;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
 
 fn xargs_test(test_data: &str, expected_output: &str, args: Vec<&str>) {
     run_test(TestPlan {
@@ -73,3 +78,294 @@ fn xargs_trace() {
         expected_exit_code: 0,
     });
 }
+
+#[test]
+fn xargs_parallel() {
+    // Both commands run at the same time, and the output of each is written in one piece once it
+    // exits, so the shorter one comes first
+    xargs_test(
+        "2 1\n",
+        "1\n1\n2\n2\n",
+        vec![
+            "-P",
+            "2",
+            "-n",
+            "1",
+            "sh",
+            "-c",
+            "echo $0; sleep $0; echo $0",
+        ],
+    );
+}
+
+#[test]
+fn xargs_parallel_unlimited() {
+    xargs_test(
+        "2 1 0\n",
+        "0\n1\n2\n",
+        vec!["-P", "0", "-n", "1", "sh", "-c", "sleep $0; echo $0"],
+    );
+}
+
+#[test]
+fn xargs_failed_invocation() {
+    for maxprocs in ["1", "2"] {
+        run_test(TestPlan {
+            cmd: String::from("xargs"),
+            args: vec!["-P", maxprocs, "-n", "1", "sh", "-c", "exit $0"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            stdin_data: String::from("0 1 0\n"),
+            expected_out: String::from(""),
+            expected_err: String::from(""),
+            expected_exit_code: 123,
+        });
+    }
+}
+
+#[test]
+fn xargs_parallel_forwards_sigterm() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_xargs"))
+        .args(["-P", "2", "-n", "1", "sleep"])
+        .stdin(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(b"30 31\n").unwrap();
+    thread::sleep(Duration::from_secs(1));
+
+    let start = Instant::now();
+    let ret = unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGTERM) };
+    assert_eq!(ret, 0);
+
+    // The running commands are terminated and waited for, then xargs terminates with the signal
+    let status = child.wait().unwrap();
+    assert_eq!(status.signal(), Some(libc::SIGTERM));
+    assert!(start.elapsed() < Duration::from_secs(10));
+}
+
+#[test]
+fn xargs_quotes_within_argument() {
+    xargs_test(
+        "ab'c d'e \"\" f\\ g\n",
+        "[abc de][][f g]",
+        vec!["printf", "[%s]"],
+    );
+}
+
+#[test]
+fn xargs_null_delimiter_literal() {
+    // Neither quotes, backslashes, blanks nor the end-of-file string are special
+    xargs_test(
+        "a 'b'\0c\\\0STOP\0\0d",
+        "[a 'b'][c\\][STOP][][d]",
+        vec!["-0", "-E", "STOP", "printf", "[%s]"],
+    );
+}
+
+#[test]
+fn xargs_non_utf8_arguments() {
+    // Names that aren't valid UTF-8 are passed on unchanged, as with find -print0
+    for args in [
+        vec!["-0", "printf", "[%s]"],
+        vec!["-d", "\\0", "printf", "[%s]"],
+        vec!["-0", "-I", "{}", "printf", "[%s]", "<{}>"],
+    ] {
+        let expected_out: &[u8] = if args.contains(&"-I") {
+            b"[<bad\xffname>][<ok>]"
+        } else {
+            b"[bad\xffname][ok]"
+        };
+        run_test_u8(TestPlanU8 {
+            cmd: String::from("xargs"),
+            args: args.into_iter().map(String::from).collect(),
+            stdin_data: b"bad\xffname\0ok\0".to_vec(),
+            expected_out: expected_out.to_vec(),
+            expected_err: Vec::new(),
+            expected_exit_code: 0,
+        });
+    }
+}
+
+#[test]
+fn xargs_with_delimiter() {
+    xargs_test(
+        "a b,c \"d\",,e\n",
+        "[a b][c \"d\"][][e\n]",
+        vec!["-d", ",", "printf", "[%s]"],
+    );
+    xargs_test(
+        "a b\nc\\d\n",
+        "[a b][c\\d]",
+        vec!["-d", "\\n", "printf", "[%s]"],
+    );
+}
+
+#[test]
+fn xargs_with_invalid_delimiter() {
+    run_test(TestPlan {
+        cmd: String::from("xargs"),
+        args: vec!["-d", "ab", "echo"]
+            .into_iter()
+            .map(String::from)
+            .collect(),
+        stdin_data: String::new(),
+        expected_out: String::new(),
+        expected_err: String::from(
+            "error: invalid value 'ab' for '--delimiter <DELIMITER>': expected a single \
+             character, or one of \\n, \\t, \\0 and \\\\\n\nFor more information, try '--help'.\n",
+        ),
+        expected_exit_code: 2,
+    });
+}
+
+#[test]
+fn xargs_insert_mode() {
+    // Each line is one argument, with leading blanks and empty lines skipped, and replstr is
+    // replaced wherever it occurs in the arguments
+    xargs_test(
+        "  one two\n\nthree\n",
+        "<xone twoy|one two|{>\n<xthreey|three|{>\n",
+        vec!["-I", "{}", "printf", "<%s|%s|%s>\\n", "x{}y", "{}", "{"],
+    );
+}
+
+#[test]
+fn xargs_insert_mode_quotes() {
+    xargs_test(
+        "'a  b' c\\ d\n",
+        "[a  b c d][a  b c d]\n",
+        vec!["-I", "R", "echo", "[R][R]"],
+    );
+}
+
+#[test]
+fn xargs_insert_mode_ignores_maxnum() {
+    xargs_test(
+        "one\ntwo three\n",
+        "one\ntwo three\n",
+        vec!["-n", "2", "-I", "_", "echo", "_"],
+    );
+}
+
+#[test]
+fn xargs_insert_mode_null_delimiter() {
+    xargs_test(
+        "one\0 two\n\0",
+        "[one]\n[ two\n]\n",
+        vec!["-0", "-I", "{}", "echo", "[{}]"],
+    );
+}
+
+fn xargs_status_test(test_data: &str, args: Vec<&str>, out: &str, err: &str, exit_code: i32) {
+    run_test(TestPlan {
+        cmd: String::from("xargs"),
+        args: args.into_iter().map(String::from).collect(),
+        stdin_data: String::from(test_data),
+        expected_out: String::from(out),
+        expected_err: String::from(err),
+        expected_exit_code: exit_code,
+    });
+}
+
+#[test]
+fn xargs_exit_255_aborts() {
+    xargs_status_test(
+        "a b\n",
+        vec!["-n", "1", "sh", "-c", "echo $0; exit 255"],
+        "a\n",
+        "xargs: sh: exited with status 255; aborting\n",
+        124,
+    );
+}
+
+#[test]
+fn xargs_signal_aborts() {
+    xargs_status_test(
+        "a b\n",
+        vec!["-n", "1", "sh", "-c", "echo $0; kill -9 $$"],
+        "a\n",
+        "xargs: sh: terminated by signal 9\n",
+        125,
+    );
+}
+
+#[test]
+fn xargs_utility_not_found() {
+    xargs_status_test(
+        "a\n",
+        vec!["xargs_test_no_such_utility"],
+        "",
+        "xargs: xargs_test_no_such_utility: No such file or directory (os error 2)\n",
+        127,
+    );
+}
+
+#[test]
+fn xargs_utility_not_executable() {
+    let path = format!("{}/xargs_not_executable", env!("CARGO_TARGET_TMPDIR"));
+    std::fs::write(&path, "").unwrap();
+
+    xargs_status_test(
+        "a\n",
+        vec![&path],
+        "",
+        &format!("xargs: {}: Permission denied (os error 13)\n", path),
+        126,
+    );
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn xargs_argument_too_long() {
+    xargs_status_test(
+        "abcdef\n",
+        vec!["-s", "5", "echo"],
+        "",
+        "xargs: argument line too long\n",
+        1,
+    );
+}
+
+#[test]
+fn xargs_exit_when_maxnum_does_not_fit() {
+    xargs_status_test(
+        "one two\n",
+        vec!["-x", "-n", "2", "-s", "11", "echo"],
+        "",
+        "xargs: argument line too long\n",
+        1,
+    );
+    xargs_test(
+        "one two\n",
+        "one\ntwo\n",
+        vec!["-n", "2", "-s", "11", "echo"],
+    );
+}
+
+#[test]
+fn xargs_batches_within_arg_max() {
+    // More than fits in one argument list on any system
+    let input = "aaaaaaaaaa\n".repeat(1 << 20);
+    let mut child = Command::new(env!("CARGO_BIN_EXE_xargs"))
+        .args(["sh", "-c", "echo $#"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    let writer = thread::spawn(move || stdin.write_all(input.as_bytes()).unwrap());
+    let output = child.wait_with_output().unwrap();
+    writer.join().unwrap();
+
+    assert!(output.status.success());
+    let counts: Vec<usize> = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| line.parse().unwrap())
+        .collect();
+    assert!(counts.len() > 1);
+    // `sh -c` takes the first argument as $0
+    assert_eq!(counts.iter().sum::<usize>() + counts.len(), 1 << 20);
+}
@@ -8,20 +8,22 @@
 //
 // TODO:
 // - prompt mode (-p)
-// - insert mode (-I)
 // - split by lines (-L)
-// - exit feature (-x)
 // - write tests
 //
 
+mod xargs_util;
+
+use self::xargs_util::{parse_delimiter, pool, Delimiter, Pool, Splitter};
 use clap::Parser;
-use gettextrs::{bind_textdomain_codeset, setlocale, textdomain, LocaleCategory};
+use gettextrs::{bind_textdomain_codeset, gettext, setlocale, textdomain, LocaleCategory};
+use plib::argmax::{arg_size, available};
 use plib::PROJECT_NAME;
-use std::io::{self, Read};
-use std::process::{Command, Stdio};
-
-const ARG_MAX: i32 = 131072; // arbitrary.  todo: discover actual value
-const MAX_ARGS_BYTES: usize = ARG_MAX as usize - 2048;
+use std::ffi::{OsStr, OsString};
+use std::io::{self, Read, Write};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::process::ExitStatusExt;
+use std::process::{Command, ExitStatus, Stdio};
 
 /// xargs - construct argument lists and invoke utility
 #[derive(Parser, Debug)]
@@ -43,7 +45,7 @@ struct Args {
     #[arg(short = 'E', long, default_value = "")]
     eofstr: String,
 
-    /// Insert mode.
+    /// Insert mode: execute utility for each line of input, replacing replstr in the arguments with the line
     #[arg(short = 'I', long)]
     replstr: Option<String>,
 
@@ -55,269 +57,315 @@ struct Args {
     #[arg(short, long)]
     trace: bool,
 
-    /// null-based processing
+    /// Arguments are terminated by a null character, with no other characters special
     #[arg(short = '0', long = "null")]
     null_mode: bool,
 
+    /// Arguments are terminated by the delimiter character, with no other characters special
+    #[arg(short = 'd', long, value_parser = parse_delimiter, conflicts_with = "null_mode")]
+    delimiter: Option<u8>,
+
     /// Terminate if a constructed command line will not fit in the implied or specified size
     #[arg(short = 'x', long)]
     exit: bool,
 
+    /// Run up to maxprocs invocations of utility at a time, or as many as possible if 0
+    #[arg(short = 'P', long = "max-procs", default_value_t = 1)]
+    maxprocs: usize,
+
     /// utility to invoke
     util: String,
 
     /// utility arguments
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
     util_args: Vec<String>,
 }
 
-// find a string in a vector of strings
-fn find_str(needle: &str, haystack: &[String]) -> Option<usize> {
-    haystack.iter().position(|s| s == needle)
+impl Args {
+    fn delimiter(&self) -> Delimiter {
+        match self.delimiter {
+            Some(delimiter) => Delimiter::Byte(delimiter),
+            None if self.null_mode => Delimiter::Byte(0),
+            None if self.replstr.is_some() => Delimiter::Line,
+            None => Delimiter::Blank,
+        }
+    }
+
+    // the arguments of an invocation of the utility with `input` from stdin
+    fn util_args(&self, input: Vec<OsString>) -> Vec<OsString> {
+        match &self.replstr {
+            // insert mode takes one argument at a time
+            Some(replstr) => {
+                let line = input.first().map_or(&[][..], |arg| arg.as_bytes());
+                self.util_args
+                    .iter()
+                    .map(|arg| replace(arg, replstr, line))
+                    .collect()
+            }
+            None => self
+                .util_args
+                .iter()
+                .map(OsString::from)
+                .chain(input)
+                .collect(),
+        }
+    }
+}
+
+// replace each occurrence of `replstr` in `arg` with `line`, which need not
+// be UTF-8
+fn replace(arg: &str, replstr: &str, line: &[u8]) -> OsString {
+    let mut replaced = Vec::with_capacity(arg.len());
+    for (i, part) in arg.split(replstr).enumerate() {
+        if i > 0 {
+            replaced.extend_from_slice(line);
+        }
+        replaced.extend_from_slice(part.as_bytes());
+    }
+    OsString::from_vec(replaced)
+}
+
+// how the invocations of the utility went, which decides the exit status
+#[derive(Default)]
+struct Outcome {
+    // an invocation returned a nonzero exit status
+    failed: bool,
+    // the exit status when xargs stops before the end of its input
+    abort: Option<i32>,
 }
 
-// execute the utility
-fn exec_util(util: &str, util_args: Vec<String>, trace: bool) -> io::Result<()> {
+impl Outcome {
+    fn record(&mut self, util: &str, status: ExitStatus) {
+        match status.code() {
+            Some(0) => {}
+            Some(255) => {
+                eprintln!(
+                    "xargs: {}: {}",
+                    util,
+                    gettext("exited with status 255; aborting")
+                );
+                self.abort.get_or_insert(124);
+            }
+            Some(_) => self.failed = true,
+            None => {
+                let signal = status.signal().unwrap_or(0);
+                eprintln!(
+                    "xargs: {}: {}",
+                    util,
+                    gettext!("terminated by signal {}", signal)
+                );
+                self.abort.get_or_insert(125);
+            }
+        }
+    }
+
+    fn spawn_failed(&mut self, util: &str, e: &io::Error) {
+        eprintln!("xargs: {}: {}", util, e);
+        let exit_code = if e.kind() == io::ErrorKind::NotFound {
+            127
+        } else {
+            126
+        };
+        self.abort.get_or_insert(exit_code);
+    }
+
+    // no more invocations are started after an abort or a signal
+    fn stopped(&self) -> bool {
+        self.abort.is_some() || pool::interrupted()
+    }
+
+    fn exit_code(&self) -> i32 {
+        match self.abort {
+            Some(exit_code) => exit_code,
+            None if self.failed => 123,
+            None => 0,
+        }
+    }
+}
+
+// execute the utility, once there is room for it in the pool.
+fn exec_util(
+    pool: &mut Pool,
+    outcome: &mut Outcome,
+    util: &str,
+    util_args: Vec<OsString>,
+    trace: bool,
+) -> io::Result<()> {
+    while pool.is_full() {
+        if let Some(status) = pool.wait()? {
+            outcome.record(util, status);
+        }
+    }
+    if outcome.stopped() {
+        return Ok(());
+    }
+
     // if tracing, Each generated command line shall be written to
     // standard error just prior to invocation.
     if trace {
-        eprintln!("{} {}", util, util_args.join(" "));
+        let mut line = util.as_bytes().to_vec();
+        for arg in &util_args {
+            line.push(b' ');
+            line.extend_from_slice(arg.as_bytes());
+        }
+        line.push(b'\n');
+        io::stderr().write_all(&line)?;
     }
 
-    let _output = Command::new(util)
+    let mut command = Command::new(util);
+    command
         .args(util_args)
         .stdin(Stdio::null())
         .stdout(Stdio::inherit())
-        .stderr(Stdio::inherit())
-        .output()?;
+        .stderr(Stdio::inherit());
+    if let Err(e) = pool.spawn(command) {
+        outcome.spawn_failed(util, &e);
+    }
 
     Ok(())
 }
 
 struct ParseState {
-    // cmdline-related state
+    // cmdline-related state: the command line length of -s, and the space
+    // taken in the argument list, which is limited by ARG_MAX
     util_size: usize,
-    util_n_args: usize,
+    util_space: usize,
 
     // input state
-    tmp_arg: String,
-    in_arg: bool,
-    in_quote: bool,
-    in_escape: bool,
-    quote_char: char,
-    skip_remainder: bool,
-    null_slop: Vec<u8>,
+    input: Splitter,
 
     // output state
     max_bytes: usize,
+    max_space: usize,
     max_args: Option<usize>,
+    exit: bool,
 
     // parsed args, ready for exec
-    args: Vec<String>,
+    args: Vec<OsString>,
 }
 
 impl ParseState {
     fn new(args: &Args) -> ParseState {
         let mut total = args.util.len();
+        let mut space = arg_size(OsStr::new(&args.util));
         for arg in &args.util_args {
             total += arg.len() + 1; // +1 for space
+            space += arg_size(OsStr::new(arg));
         }
 
+        // -s can only lower the limit of the system
+        let max_space = available();
         ParseState {
             util_size: total,
-            util_n_args: args.util_args.len(),
-            tmp_arg: String::new(),
-            in_arg: false,
-            in_quote: false,
-            in_escape: false,
-            quote_char: '"',
-            skip_remainder: false,
-            null_slop: Vec::new(),
-            max_bytes: args.maxsize.unwrap_or(MAX_ARGS_BYTES),
-            max_args: args.maxnum,
+            util_space: space,
+            input: Splitter::new(args.delimiter(), &args.eofstr),
+            max_bytes: args.maxsize.unwrap_or(max_space),
+            max_space,
+            max_args: if args.replstr.is_some() {
+                Some(1)
+            } else {
+                args.maxnum
+            },
+            exit: args.exit,
             args: Vec::new(),
         }
     }
 
     fn full(&self) -> bool {
+        if self.args.is_empty() {
+            return false;
+        }
+
         let mut total = self.util_size;
+        let mut space = self.util_space;
         for arg in &self.args {
             total += arg.len() + 1; // +1 for space
+            space += arg_size(arg);
         }
 
-        if total > self.max_bytes {
+        if total > self.max_bytes || space > self.max_space {
             true
         } else if let Some(max_args) = self.max_args {
-            (self.util_n_args + self.args.len()) >= max_args
+            self.args.len() >= max_args
         } else {
             false
         }
     }
 
-    fn remove_args(&mut self) -> Vec<String> {
+    fn remove_args(&mut self) -> Result<Vec<OsString>, String> {
         let mut total = self.util_size;
-        let mut ret = Vec::new();
-        while !self.args.is_empty() {
-            // stop if adding the next arg would exceed the max size
-            if (total + self.args[0].len() + 1) > self.max_bytes {
+        let mut space = self.util_space;
+        let mut count = 0;
+        for arg in &self.args {
+            // stop if adding the next arg would exceed the max size. that is
+            // an error if not even one arg fits, or with -x, if fewer than
+            // the -n number do.
+            let arg_space = arg_size(arg);
+            if total + arg.len() + 1 > self.max_bytes || space + arg_space > self.max_space {
+                if count == 0 || (self.exit && self.max_args.is_some()) {
+                    return Err(gettext("argument line too long"));
+                }
                 break;
             }
 
             // add the next arg
-            let arg = self.args.remove(0);
             total += arg.len() + 1; // +1 for space
-            ret.push(arg);
+            space += arg_space;
+            count += 1;
 
             // stop if we have reached the max number of args
-            if let Some(max_args) = self.max_args {
-                if (ret.len() + self.util_n_args) == max_args {
-                    break;
-                }
-            }
-        }
-
-        ret
-    }
-
-    // args are null-separated, without any further processing.
-    // if the input data crosses a null boundary, the remainder is
-    // stored as state for the next call to parse_buf_null.
-    fn parse_buf_null(&mut self, in_buf: &[u8]) -> io::Result<()> {
-        if self.skip_remainder {
-            return Ok(());
-        }
-
-        // pull prior state into current buffer
-        let mut buf = Vec::with_capacity(self.null_slop.len() + in_buf.len());
-        buf.extend_from_slice(&self.null_slop);
-        buf.extend_from_slice(in_buf);
-        self.null_slop.clear();
-
-        // divide buffer into null-terminated strings, with remainder
-        let mut start = 0;
-        let mut end = 0;
-        while end < buf.len() {
-            if buf[end] == 0 {
-                let s = String::from_utf8_lossy(&buf[start..end]).to_string();
-                self.args.push(s);
-                start = end + 1;
-            }
-            end += 1;
-        }
-
-        // remember remainder, if any, for next call
-        if start < buf.len() {
-            self.null_slop.extend_from_slice(&buf[start..]);
-        }
-
-        Ok(())
-    }
-
-    fn parse_buf(&mut self, buf: &[u8]) -> io::Result<()> {
-        if self.skip_remainder {
-            return Ok(());
-        }
-
-        for &c8 in buf {
-            let ch = c8 as char;
-
-            if self.in_quote {
-                if ch == self.quote_char {
-                    self.in_quote = false;
-                    self.in_arg = false;
-                    self.args.push(self.tmp_arg.clone());
-                    self.tmp_arg.clear();
-                } else {
-                    self.tmp_arg.push(ch);
-                }
-            } else if self.in_escape {
-                self.in_escape = false;
-                self.tmp_arg.push(ch);
-            } else if self.in_arg && ch.is_whitespace() {
-                self.in_arg = false;
-                self.args.push(self.tmp_arg.clone());
-                self.tmp_arg.clear();
-            } else if ch == '\'' || ch == '"' {
-                self.in_arg = true;
-                self.in_quote = true;
-                self.quote_char = ch;
-            } else if ch == '\\' {
-                self.in_escape = true;
-            } else if ch.is_whitespace() {
-                // ignore whitespace
-            } else {
-                self.in_arg = true;
-                self.tmp_arg.push(ch);
-            }
-        }
-
-        Ok(())
-    }
-
-    fn parse_finalize(&mut self) {
-        if self.in_arg {
-            self.in_arg = false;
-            self.args.push(self.tmp_arg.clone());
-            self.tmp_arg.clear();
-        }
-
-        if !self.null_slop.is_empty() {
-            let s = String::from_utf8_lossy(&self.null_slop).to_string();
-            self.args.push(s);
-            self.null_slop.clear();
-        }
-    }
-
-    fn postprocess(&mut self, args: &Args) -> io::Result<()> {
-        if !args.eofstr.is_empty() {
-            if let Some(pos) = find_str(&args.eofstr, &self.args) {
-                self.args.truncate(pos);
-                self.skip_remainder = true;
+            if self.max_args == Some(count) {
+                break;
             }
         }
 
-        Ok(())
+        Ok(self.args.drain(..count).collect())
     }
 }
 
-fn read_and_spawn(args: &Args) -> io::Result<()> {
+fn read_and_spawn(args: &Args, pool: &mut Pool, outcome: &mut Outcome) -> Result<(), String> {
     let mut state = ParseState::new(args);
 
     let mut buffer = [0; plib::BUFSZ];
 
-    // read stdin until EOF
+    // read stdin until EOF, or until xargs stops early
     loop {
+        if outcome.stopped() {
+            return Ok(());
+        }
+
         // read a chunk of input
-        let n_read = io::stdin().read(&mut buffer)?;
+        let n_read = match io::stdin().read(&mut buffer) {
+            Ok(n_read) => n_read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(gettext!("read error: {}", e)),
+        };
         if n_read == 0 {
             break;
         }
 
-        if args.null_mode {
-            state.parse_buf_null(&buffer[..n_read])?;
-        } else {
-            // parse the line
-            state.parse_buf(&buffer[..n_read])?;
+        state.input.split(&buffer[..n_read], &mut state.args);
 
-            // handle eofstr and other details
-            state.postprocess(args)?;
+        // if enough args, spawn the utility
+        while state.full() && !outcome.stopped() {
+            let util_args = args.util_args(state.remove_args()?);
+            exec_util(pool, outcome, &args.util, util_args, args.trace)
+                .map_err(|e| e.to_string())?;
         }
 
-        // if enough args, spawn the utility
-        while state.full() {
-            let mut util_args = args.util_args.clone();
-            util_args.append(&mut state.remove_args());
-            exec_util(&args.util, util_args, args.trace)?;
+        // the rest of the input is ignored after the logical end-of-file string
+        if state.input.at_eof() {
+            break;
         }
     }
 
     // finalize parsing
-    state.parse_finalize();
+    state.input.finish(&mut state.args);
 
-    // if there are any remaining args, spawn the utility
-    if !state.args.is_empty() {
-        let mut util_args = args.util_args.clone();
-        util_args.append(&mut state.remove_args());
-        exec_util(&args.util, util_args, args.trace)?;
+    // spawn the utility for the remaining args
+    while !state.args.is_empty() && !outcome.stopped() {
+        let util_args = args.util_args(state.remove_args()?);
+        exec_util(pool, outcome, &args.util, util_args, args.trace).map_err(|e| e.to_string())?;
     }
 
     Ok(())
@@ -331,7 +379,23 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     textdomain(PROJECT_NAME)?;
     bind_textdomain_codeset(PROJECT_NAME, "UTF-8")?;
 
-    read_and_spawn(&args)?;
+    let mut pool = Pool::new(args.maxprocs);
+    let mut outcome = Outcome::default();
+    pool::install_signal_handlers();
+    let res = read_and_spawn(&args, &mut pool, &mut outcome);
 
-    Ok(())
+    // wait for the invocations still running, even after an error
+    while let Some(status) = pool.wait()? {
+        outcome.record(&args.util, status);
+    }
+    pool::reraise_signal();
+
+    let exit_code = match res {
+        Ok(()) => outcome.exit_code(),
+        Err(e) => {
+            eprintln!("xargs: {}", e);
+            1
+        }
+    };
+    std::process::exit(exit_code)
 }
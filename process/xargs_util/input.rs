@@ -0,0 +1,148 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+//! Splitting the standard input of xargs into arguments.
+
+use std::ffi::OsString;
+use std::mem;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+
+/// How the input is split into arguments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delimiter {
+    /// Arguments are separated by blanks and newlines, which quotes and
+    /// backslashes can escape, and the logical end-of-file string is
+    /// recognized.
+    Blank,
+    /// As `Blank`, except that only newlines separate arguments and leading
+    /// blanks are ignored, so each line is an argument, as with `-I`.
+    Line,
+    /// Arguments are terminated by a single byte, NUL for `-0`, and all other
+    /// input is taken literally.
+    Byte(u8),
+}
+
+/// Parse the argument of `-d`: a single character, or one of the escapes
+/// `\n`, `\t`, `\0` and `\\`.
+pub fn parse_delimiter(s: &str) -> Result<u8, String> {
+    match s.as_bytes() {
+        [b] if b.is_ascii() => Ok(*b),
+        b"\\n" => Ok(b'\n'),
+        b"\\t" => Ok(b'\t'),
+        b"\\0" => Ok(0),
+        b"\\\\" => Ok(b'\\'),
+        _ => Err(String::from(
+            "expected a single character, or one of \\n, \\t, \\0 and \\\\",
+        )),
+    }
+}
+
+/// Splits the input into arguments as it's read, in chunks that may end in
+/// the middle of an argument.
+#[derive(Debug)]
+pub struct Splitter {
+    delimiter: Delimiter,
+    // the logical end-of-file string, if any
+    eofstr: Option<String>,
+
+    arg: Vec<u8>,
+    in_arg: bool,
+    quote: Option<u8>,
+    in_escape: bool,
+    at_eof: bool,
+}
+
+impl Splitter {
+    /// An empty `eofstr` means there is no logical end-of-file string.
+    pub fn new(delimiter: Delimiter, eofstr: &str) -> Self {
+        Self {
+            delimiter,
+            eofstr: (!eofstr.is_empty()).then(|| eofstr.to_string()),
+            arg: Vec::new(),
+            in_arg: false,
+            quote: None,
+            in_escape: false,
+            at_eof: false,
+        }
+    }
+
+    /// Check if the logical end-of-file string was found, after which the
+    /// rest of the input is ignored.
+    pub fn at_eof(&self) -> bool {
+        self.at_eof
+    }
+
+    /// Split the next chunk of input, adding the arguments completed in it to
+    /// `args`.
+    pub fn split(&mut self, buf: &[u8], args: &mut Vec<OsString>) {
+        for &b in buf {
+            if self.at_eof {
+                return;
+            }
+            match self.delimiter {
+                Delimiter::Blank | Delimiter::Line => self.push_quoted(b, args),
+                Delimiter::Byte(delimiter) if b == delimiter => self.end_arg(args),
+                Delimiter::Byte(_) => {
+                    self.in_arg = true;
+                    self.arg.push(b);
+                }
+            }
+        }
+    }
+
+    /// Add the argument left at the end of the input, if any.
+    pub fn finish(&mut self, args: &mut Vec<OsString>) {
+        if self.in_arg && !self.at_eof {
+            self.end_arg(args);
+        }
+    }
+
+    fn push_quoted(&mut self, b: u8, args: &mut Vec<OsString>) {
+        if let Some(quote) = self.quote {
+            // the argument goes on after the closing quote
+            if b == quote {
+                self.quote = None;
+            } else {
+                self.arg.push(b);
+            }
+        } else if self.in_escape {
+            self.in_escape = false;
+            self.arg.push(b);
+        } else if b.is_ascii_whitespace() {
+            if self.delimiter == Delimiter::Line && b != b'\n' {
+                // blanks within a line are part of the argument
+                if self.in_arg {
+                    self.arg.push(b);
+                }
+            } else if self.in_arg {
+                self.end_arg(args);
+            }
+        } else {
+            self.in_arg = true;
+            match b {
+                b'\'' | b'"' => self.quote = Some(b),
+                b'\\' => self.in_escape = true,
+                _ => self.arg.push(b),
+            }
+        }
+    }
+
+    fn end_arg(&mut self, args: &mut Vec<OsString>) {
+        // the bytes are kept as they are, as they may not be UTF-8
+        let arg = OsString::from_vec(mem::take(&mut self.arg));
+        self.in_arg = false;
+
+        let literal = matches!(self.delimiter, Delimiter::Byte(_));
+        if !literal && self.eofstr.as_ref().map(String::as_bytes) == Some(arg.as_bytes()) {
+            self.at_eof = true;
+            return;
+        }
+        args.push(arg);
+    }
+}
@@ -0,0 +1,14 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+mod input;
+pub mod pool;
+
+pub use input::{parse_delimiter, Delimiter, Splitter};
+pub use pool::Pool;
@@ -0,0 +1,171 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+//! The commands xargs runs at the same time with `-P`.
+//!
+//! Once `install_signal_handlers` has been called, SIGHUP and SIGTERM are
+//! passed on to the running commands, and `interrupted` tells the caller to
+//! stop starting new ones. SIGINT is not passed on, as the terminal already
+//! sends it to the whole process group. After the running commands have been
+//! waited for, `reraise_signal` terminates xargs with the signal it received.
+
+use std::io::{self, Read, Write};
+use std::os::unix::process::ExitStatusExt;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::thread::{self, JoinHandle};
+
+const SIGNALS: [libc::c_int; 3] = [libc::SIGHUP, libc::SIGINT, libc::SIGTERM];
+
+// The last signal received, kept until xargs terminates with it
+static RECEIVED: AtomicI32 = AtomicI32::new(0);
+
+// A signal received but not yet passed on to the running commands
+static PENDING: AtomicI32 = AtomicI32::new(0);
+
+extern "C" fn record_signal(signo: libc::c_int) {
+    RECEIVED.store(signo, Ordering::Relaxed);
+    PENDING.store(signo, Ordering::Relaxed);
+}
+
+/// Catch SIGHUP, SIGINT and SIGTERM instead of terminating right away. The
+/// handler doesn't restart system calls, so waiting for a command is
+/// interrupted when one arrives.
+pub fn install_signal_handlers() {
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = record_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        libc::sigemptyset(&mut action.sa_mask);
+        for signo in SIGNALS {
+            libc::sigaction(signo, &action, std::ptr::null_mut());
+        }
+    }
+}
+
+/// Check if a signal was received, after which no more commands should be
+/// started.
+pub fn interrupted() -> bool {
+    RECEIVED.load(Ordering::Relaxed) != 0
+}
+
+/// Terminate with the signal that was received, if any.
+pub fn reraise_signal() {
+    let signo = RECEIVED.load(Ordering::Relaxed);
+    if signo != 0 {
+        unsafe {
+            libc::signal(signo, libc::SIG_DFL);
+            libc::raise(signo);
+        }
+    }
+}
+
+struct Running {
+    child: Child,
+    // Collects the standard output when it's buffered
+    output: Option<JoinHandle<io::Result<Vec<u8>>>>,
+}
+
+/// Commands running at the same time, up to a limit.
+///
+/// When more than one command may run at a time, the standard output of each
+/// is collected and written out in one piece when it exits, so the output of
+/// different commands isn't interleaved. Standard error is left alone.
+pub struct Pool {
+    max_procs: usize,
+    buffer_output: bool,
+    running: Vec<Running>,
+}
+
+impl Pool {
+    /// A `max_procs` of 0 doesn't limit the number of commands.
+    pub fn new(max_procs: usize) -> Self {
+        Self {
+            max_procs,
+            buffer_output: max_procs != 1,
+            running: Vec::new(),
+        }
+    }
+
+    /// Check if another command can be started without waiting for one of the
+    /// running commands to exit.
+    pub fn is_full(&self) -> bool {
+        self.max_procs != 0 && self.running.len() >= self.max_procs
+    }
+
+    /// Start `command`, which should only be done when the pool isn't full.
+    pub fn spawn(&mut self, mut command: Command) -> io::Result<()> {
+        if self.buffer_output {
+            command.stdout(Stdio::piped());
+        }
+        let mut child = command.spawn()?;
+
+        let output = child.stdout.take().map(|mut stdout| {
+            thread::spawn(move || {
+                let mut output = Vec::new();
+                stdout.read_to_end(&mut output)?;
+                Ok(output)
+            })
+        });
+        self.running.push(Running { child, output });
+        Ok(())
+    }
+
+    /// Wait for one of the running commands to exit, and write its standard
+    /// output if it's buffered. Returns `None` if no commands are running.
+    pub fn wait(&mut self) -> io::Result<Option<ExitStatus>> {
+        loop {
+            if self.running.is_empty() {
+                return Ok(None);
+            }
+            self.forward_signal();
+
+            let mut status = 0;
+            let pid = unsafe { libc::waitpid(-1, &mut status, 0) };
+            if pid < 0 {
+                let e = io::Error::last_os_error();
+                if e.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(e);
+            }
+
+            // Only the commands of the pool are children of xargs
+            let Some(pos) = self
+                .running
+                .iter()
+                .position(|running| running.child.id() == pid as u32)
+            else {
+                continue;
+            };
+            let running = self.running.swap_remove(pos);
+
+            if let Some(output) = running.output {
+                // The thread only ends by returning
+                let output = output.join().unwrap()?;
+                let mut stdout = io::stdout().lock();
+                stdout.write_all(&output)?;
+                stdout.flush()?;
+            }
+            return Ok(Some(ExitStatus::from_raw(status)));
+        }
+    }
+
+    // Pass a signal received since the last call on to the running commands
+    fn forward_signal(&self) {
+        let signo = PENDING.swap(0, Ordering::Relaxed);
+        if signo == 0 || signo == libc::SIGINT {
+            return;
+        }
+        for running in &self.running {
+            unsafe {
+                libc::kill(running.child.id() as libc::pid_t, signo);
+            }
+        }
+    }
+}
@@ -10,9 +10,10 @@
 use clap::Parser;
 use gettextrs::{bind_textdomain_codeset, setlocale, textdomain, LocaleCategory};
 use plib::PROJECT_NAME;
+use std::cmp::Ordering;
 use std::fs;
-use std::io::{self, BufRead, Write};
-use std::path::PathBuf;
+use std::io::{self, BufRead, BufWriter, Write};
+use std::path::{Path, PathBuf};
 
 const NO1: u32 = 1 << 0;
 const NO2: u32 = 1 << 1;
@@ -34,6 +35,22 @@ struct Args {
     #[arg(short = '3', long)]
     no_dup: bool,
 
+    /// Fail if an input isn't sorted, even if all the lines are in both files.
+    #[arg(long = "check-order", overrides_with = "nocheck_order")]
+    check_order: bool,
+
+    /// Don't check that the inputs are sorted.
+    #[arg(long = "nocheck-order", overrides_with = "check_order")]
+    nocheck_order: bool,
+
+    /// Separate the output columns with this string instead of a <tab>.
+    #[arg(long = "output-delimiter", value_name = "STR")]
+    output_delimiter: Option<String>,
+
+    /// Lines are terminated by NUL instead of <newline>, in both the input and the output.
+    #[arg(short = 'z', long = "zero-terminated")]
+    zero_terminated: bool,
+
     /// Comparison file1
     file1: PathBuf,
 
@@ -41,95 +58,217 @@ struct Args {
     file2: PathBuf,
 }
 
-fn line_out(lead_dup: &'static str, outmask: u32, curtype: u32, s: &str) -> io::Result<()> {
-    if (outmask & curtype) != 0 {
+/// How the order of the input lines is checked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CheckOrder {
+    /// Warn about the first line out of order in each file once a line is found in only one
+    /// of the files, and fail at the end. Out of order lines that are in both files don't
+    /// change the output.
+    Default,
+    /// Fail at the first line out of order.
+    Enabled,
+    /// Don't check.
+    Disabled,
+}
+
+/// What is written before the lines of each output column.
+struct Columns {
+    mask: u32,
+    lead_f2: Vec<u8>,
+    lead_dup: Vec<u8>,
+    terminator: u8,
+}
+
+impl Columns {
+    fn new(args: &Args) -> Self {
+        let delimiter = match args.output_delimiter.as_deref() {
+            // An empty delimiter is a NUL, so that the columns can still be told apart
+            Some("") => vec![b'\0'],
+            Some(delimiter) => delimiter.as_bytes().to_vec(),
+            None => vec![b'\t'],
+        };
+        let lead_f2 = if args.no1 { vec![] } else { delimiter.clone() };
+        let lead_dup = match (args.no1, args.no2) {
+            (false, false) => delimiter.repeat(2),
+            (false, true) | (true, false) => delimiter,
+            (true, true) => vec![],
+        };
+
+        Self {
+            mask: args_mask(args),
+            lead_f2,
+            lead_dup,
+            terminator: if args.zero_terminated { b'\0' } else { b'\n' },
+        }
+    }
+}
+
+fn line_out(out: &mut impl Write, columns: &Columns, curtype: u32, s: &[u8]) -> io::Result<()> {
+    if (columns.mask & curtype) != 0 {
         return Ok(());
     }
 
     match curtype {
-        NO1 => {
-            io::stdout().write_all(s.as_bytes())?;
-        }
-        NO2 => {
-            let lead_f2 = {
-                if (outmask & NO1) != 0 {
-                    ""
-                } else {
-                    "\t"
-                }
-            };
-            let newstr = format!("{}{}", lead_f2, s);
-            io::stdout().write_all(newstr.as_bytes())?;
-        }
-        NODUP => {
-            let newstr = format!("{}{}", lead_dup, s);
-            io::stdout().write_all(newstr.as_bytes())?;
-        }
+        NO1 => {}
+        NO2 => out.write_all(&columns.lead_f2)?,
+        NODUP => out.write_all(&columns.lead_dup)?,
         _ => {
             panic!("should never occur");
         }
     }
-
-    Ok(())
+    out.write_all(s)?;
+    out.write_all(&[columns.terminator])
 }
 
-fn open_file(pathname: &PathBuf) -> io::Result<io::BufReader<fs::File>> {
-    Ok(io::BufReader::new(fs::File::open(pathname)?))
+/// One of the input files, with its current line and the line before it.
+struct Input {
+    number: usize,
+    reader: Box<dyn BufRead>,
+    line: Vec<u8>,
+    has_line: bool,
+    previous: Vec<u8>,
+    has_previous: bool,
+    // whether a line out of order has been reported
+    disorder: bool,
 }
 
-fn comm_file(
-    mask: u32,
-    lead_dup: &'static str,
-    file1name: &PathBuf,
-    file2name: &PathBuf,
-) -> io::Result<()> {
-    // open files, or stdin
-    let mut rdr1 = open_file(file1name)?;
-    let mut rdr2 = open_file(file2name)?;
+impl Input {
+    fn open(number: usize, pathname: &Path) -> io::Result<Self> {
+        let reader: Box<dyn BufRead> = if pathname == Path::new("-") {
+            // not locked, which would deadlock if both files are stdin
+            Box::new(io::BufReader::new(io::stdin()))
+        } else {
+            Box::new(io::BufReader::new(fs::File::open(pathname)?))
+        };
+        Ok(Self {
+            number,
+            reader,
+            line: Vec::new(),
+            has_line: false,
+            previous: Vec::new(),
+            has_previous: false,
+            disorder: false,
+        })
+    }
 
-    let mut buf1 = String::new();
-    let mut buf2 = String::new();
-    let mut want1 = true;
-    let mut want2 = true;
+    /// Reads the next line, keeping the current one as the previous line.
+    fn advance(&mut self, terminator: u8) -> io::Result<()> {
+        std::mem::swap(&mut self.line, &mut self.previous);
+        self.has_previous = self.has_line;
+        self.line.clear();
+        self.has_line = self.reader.read_until(terminator, &mut self.line)? > 0;
+        if self.line.last() == Some(&terminator) {
+            self.line.pop();
+        }
+        Ok(())
+    }
 
-    loop {
-        if want1 && buf1.is_empty() {
-            if rdr1.read_line(&mut buf1)? == 0 {
-                want1 = false;
-            }
+    /// Checks that the current line doesn't come before the previous one.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the lines are out of order with `--check-order`.
+    fn check_order(
+        &mut self,
+        check: CheckOrder,
+        seen_unpairable: bool,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let checked = match check {
+            CheckOrder::Default => seen_unpairable,
+            CheckOrder::Enabled => true,
+            CheckOrder::Disabled => false,
+        };
+        if !checked || self.disorder || !self.has_line || !self.has_previous {
+            return Ok(());
         }
-        if want2 && buf2.is_empty() {
-            if rdr2.read_line(&mut buf2)? == 0 {
-                want2 = false;
+
+        if self.previous > self.line {
+            let message = format!("file {} is not in sorted order", self.number);
+            if check == CheckOrder::Enabled {
+                return Err(message.into());
             }
+            eprintln!("{}", message);
+            self.disorder = true;
         }
+        Ok(())
+    }
+}
 
-        if buf1.is_empty() && buf2.is_empty() {
-            break;
-        }
+fn comm_lines(
+    out: &mut impl Write,
+    columns: &Columns,
+    check: CheckOrder,
+    input1: &mut Input,
+    input2: &mut Input,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let terminator = columns.terminator;
+    input1.advance(terminator)?;
+    input2.advance(terminator)?;
+    let mut seen_unpairable = false;
 
-        if buf1.is_empty() {
-            line_out(lead_dup, mask, NO2, &buf2)?;
-            buf2.clear();
-        } else if buf2.is_empty() {
-            line_out(lead_dup, mask, NO1, &buf1)?;
-            buf1.clear();
-        } else if buf1 < buf2 {
-            line_out(lead_dup, mask, NO1, &buf1)?;
-            buf1.clear();
-        } else if buf2 < buf1 {
-            line_out(lead_dup, mask, NO2, &buf2)?;
-            buf2.clear();
-        } else {
-            line_out(lead_dup, mask, NODUP, &buf1)?;
-            buf1.clear();
-            buf2.clear();
+    loop {
+        let order = match (input1.has_line, input2.has_line) {
+            (false, false) => break,
+            (true, false) => Ordering::Less,
+            (false, true) => Ordering::Greater,
+            (true, true) => input1.line.cmp(&input2.line),
+        };
+
+        match order {
+            Ordering::Less => {
+                line_out(out, columns, NO1, &input1.line)?;
+                seen_unpairable = true;
+                input1.advance(terminator)?;
+                input1.check_order(check, seen_unpairable)?;
+            }
+            Ordering::Greater => {
+                line_out(out, columns, NO2, &input2.line)?;
+                seen_unpairable = true;
+                input2.advance(terminator)?;
+                input2.check_order(check, seen_unpairable)?;
+            }
+            Ordering::Equal => {
+                line_out(out, columns, NODUP, &input1.line)?;
+                input1.advance(terminator)?;
+                input2.advance(terminator)?;
+                input1.check_order(check, seen_unpairable)?;
+                input2.check_order(check, seen_unpairable)?;
+            }
         }
     }
 
+    if input1.disorder || input2.disorder {
+        return Err("input is not in sorted order".into());
+    }
     Ok(())
 }
 
+fn comm_file(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    // open files, or stdin
+    let mut input1 = Input::open(1, &args.file1)?;
+    let mut input2 = Input::open(2, &args.file2)?;
+
+    let check = if args.check_order {
+        CheckOrder::Enabled
+    } else if args.nocheck_order {
+        CheckOrder::Disabled
+    } else {
+        CheckOrder::Default
+    };
+
+    // the lines written before a line out of order are still flushed
+    let mut out = BufWriter::new(io::stdout().lock());
+    let result = comm_lines(
+        &mut out,
+        &Columns::new(args),
+        check,
+        &mut input1,
+        &mut input2,
+    );
+    out.flush()?;
+    result
+}
+
 fn args_mask(args: &Args) -> u32 {
     let mut mask = 0;
     if args.no1 {
@@ -153,18 +292,9 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     textdomain(PROJECT_NAME)?;
     bind_textdomain_codeset(PROJECT_NAME, "UTF-8")?;
 
-    let mask = args_mask(&args);
-
-    let lead_dup = match (args.no1, args.no2) {
-        (false, false) => "\t\t",
-        (false, true) => "\t",
-        (true, false) => "\t",
-        (true, true) => "",
-    };
-
     let mut exit_code = 0;
 
-    if let Err(e) = comm_file(mask, lead_dup, &args.file1, &args.file2) {
+    if let Err(e) = comm_file(&args) {
         exit_code = 1;
         eprintln!("{}", e);
     }
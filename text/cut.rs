@@ -7,7 +7,7 @@
 // SPDX-License-Identifier: MIT
 //
 
-use std::io::{self, BufRead, Error, ErrorKind, Read};
+use std::io::{self, BufRead, BufWriter, Error, ErrorKind, Read, Write};
 
 use clap::Parser;
 use gettextrs::{bind_textdomain_codeset, setlocale, textdomain, LocaleCategory};
@@ -19,15 +19,15 @@ use std::path::PathBuf;
 #[command(author, version, about, long_about)]
 struct Args {
     /// Cut based on a list of bytes
-    #[arg(short = 'b', long)]
+    #[arg(short = 'b', long, allow_hyphen_values = true)]
     bytes: Option<String>,
 
     /// Cut based on a list of characters
-    #[arg(short = 'c', long)]
+    #[arg(short = 'c', long, allow_hyphen_values = true)]
     characters: Option<String>,
 
     /// Cut based on a list of fields
-    #[arg(short = 'f', long)]
+    #[arg(short = 'f', long, allow_hyphen_values = true)]
     fields: Option<String>,
 
     /// Set the field delimiter
@@ -42,6 +42,15 @@ struct Args {
     #[structopt(short = 'n')]
     no_split: bool,
 
+    /// Select the bytes, characters or fields that are not in the list
+    #[arg(long)]
+    complement: bool,
+
+    /// Separate the selected parts of a line with this string; the default is the field
+    /// delimiter
+    #[arg(long = "output-delimiter", value_name = "STRING")]
+    output_delimiter: Option<String>,
+
     /// Input files
     filenames: Vec<PathBuf>,
 }
@@ -81,44 +90,54 @@ fn validate_args(args: &Args) -> Result<(), String> {
 
 #[derive(Clone, Debug)]
 enum ParseVariat {
-    Bytes(Vec<(i32, i32)>),
-    Characters(Vec<(i32, i32)>),
-    Fields(Vec<(i32, i32)>),
+    Bytes(Vec<(usize, usize)>),
+    Characters(Vec<(usize, usize)>),
+    Fields(Vec<(usize, usize)>),
 }
 
-/// Helper function to determine if the given bytes form a valid UTF-8 character boundary.
-///
-/// This function checks if the first byte of the provided byte slice `bytes` represents
-/// the start of a valid UTF-8 character. If the bytes are valid UTF-8, it returns true;
-/// otherwise, it returns false.
-///
-/// # Arguments
-///
-/// * `bytes` - A slice of bytes to be checked.
-///
-/// # Returns
-///
-/// A boolean value indicating whether the provided bytes form a valid UTF-8 character boundary.
-///
-fn is_character_boundary(bytes: &[u8]) -> bool {
-    // Check if the first byte of `bytes` is a valid UTF-8 character boundary
-    match std::str::from_utf8(bytes) {
-        Ok(s) => s.chars().next().is_some(),
-        Err(_) => false,
+/// Whether `byte` continues a UTF-8 character rather than starting one.
+fn is_continuation(byte: u8) -> bool {
+    byte & 0xC0 == 0x80
+}
+
+/// Returns the index of the first byte of the character that contains `line[i]`.
+fn character_start(line: &[u8], mut i: usize) -> usize {
+    while i > 0 && is_continuation(line[i]) {
+        i -= 1;
+    }
+    i
+}
+
+/// Returns whether `line[i]` is the last byte of a character.
+fn is_character_end(line: &[u8], i: usize) -> bool {
+    i + 1 == line.len() || !is_continuation(line[i + 1])
+}
+
+/// Appends the selected parts of a line in `parts` to `result`, with the output
+/// delimiter `delim` between them.
+fn push_parts<'a>(result: &mut Vec<u8>, delim: &[u8], parts: impl IntoIterator<Item = &'a [u8]>) {
+    for (i, part) in parts.into_iter().enumerate() {
+        if i > 0 {
+            result.extend_from_slice(delim);
+        }
+        result.extend_from_slice(part);
     }
 }
 
 /// Cuts out selected bytes from the given line based on the specified ranges.
 ///
-/// This function takes a slice of bytes representing the input `line`, along with optional
-/// delimiter character `delim` and a vector of tuples `ranges` representing the start and
+/// This function takes a slice of bytes representing the input `line`, along with the output
+/// delimiter `delim` and a vector of tuples `ranges` representing the start and
 /// end indices of the byte ranges to cut. The boolean `n` flag indicates whether character
 /// boundaries should be respected when selecting byte ranges.
 ///
+/// With `n`, a range that starts inside a character is moved back to the start of that
+/// character, and a range that ends inside a character ends with the character before it.
+///
 /// # Arguments
 ///
 /// * `line` - A slice of bytes representing the input line.
-/// * `delim` - An optional character delimiter.
+/// * `delim` - The output delimiter written between the ranges.
 /// * `ranges` - A vector of tuples representing the start and end indices of the byte ranges to cut.
 /// * `n` - A boolean flag indicating whether character boundaries should be respected.
 ///
@@ -126,153 +145,117 @@ fn is_character_boundary(bytes: &[u8]) -> bool {
 ///
 /// A vector containing the selected bytes from the input line based on the specified ranges.
 ///
-fn cut_bytes(line: &[u8], delim: Option<char>, ranges: &Vec<(i32, i32)>, n: bool) -> Vec<u8> {
-    let mut result = Vec::new();
-
-    for (start, end) in ranges {
-        let mut start = *start as usize;
-        let mut end = *end as usize;
+fn cut_bytes(line: &[u8], delim: &[u8], ranges: &[(usize, usize)], n: bool) -> Vec<u8> {
+    let parts = ranges.iter().filter_map(|&(start, end)| {
+        if start >= line.len() {
+            return None;
+        }
+        let mut start = start;
+        let mut end = end.min(line.len() - 1);
 
         if n {
-            if start != 0 && !is_character_boundary(&line[start..]) {
-                start -= 1;
+            start = character_start(line, start);
+            if !is_character_end(line, end) {
+                end = character_start(line, end).checked_sub(1)?;
             }
-            if end != 0 && !is_character_boundary(&line[end..]) {
-                end -= 1;
+            if end < start {
+                return None;
             }
         }
+        Some(&line[start..=end])
+    });
 
-        if line.get(start).is_some() {
-            if start == end {
-                result.push(line[start]);
-                if let Some(delim) = delim {
-                    for byte in delim.to_string().as_bytes() {
-                        result.push(*byte);
-                    }
-                }
-            } else {
-                for byte in line.iter().take(end + 1).skip(start) {
-                    result.push(*byte);
-                }
-                if let Some(delim) = delim {
-                    for byte in delim.to_string().as_bytes() {
-                        result.push(*byte);
-                    }
-                }
-            }
-        }
-    }
-    if delim.is_some() {
-        result.pop();
-    }
-
+    let mut result = Vec::new();
+    push_parts(&mut result, delim, parts);
     result
 }
 
 /// Cuts out selected characters from the given line based on the specified ranges.
 ///
-/// This function takes a string `line` representing the input line, along with an optional
-/// delimiter character `delim` and a vector of tuples `ranges` representing the start and
-/// end indices of the character ranges to cut.
+/// This function takes a slice of bytes `line` representing the input line, along with the
+/// output delimiter `delim` and a vector of tuples `ranges` representing the
+/// start and end indices of the character ranges to cut. A character is a UTF-8 sequence,
+/// and bytes that aren't part of one are characters of their own.
 ///
 /// # Arguments
 ///
-/// * `line` - A string representing the input line.
-/// * `delim` - An optional character delimiter.
+/// * `line` - A slice of bytes representing the input line.
+/// * `delim` - The output delimiter written between the ranges.
 /// * `ranges` - A vector of tuples representing the start and end indices of the character ranges to cut.
 ///
 /// # Returns
 ///
-/// A string containing the selected characters from the input line based on the specified ranges.
+/// A vector containing the bytes of the selected characters from the input line.
 ///
-fn cut_characters(line: &str, delim: Option<char>, ranges: &Vec<(i32, i32)>) -> String {
-    let mut result = String::new();
-    let chars: Vec<char> = line.chars().collect();
-
-    for (start, end) in ranges {
-        let start = *start as usize;
-        let end = *end as usize;
-
-        if chars.get(start).is_some() {
-            if start == end {
-                result.push(chars[start]);
-                if let Some(delim) = delim {
-                    result.push(delim);
-                }
-            } else {
-                let chars = line.chars();
-                for char in chars.take(end + 1).skip(start) {
-                    result.push(char);
-                }
-                if let Some(delim) = delim {
-                    result.push(delim);
-                }
-            }
-        }
-    }
-    if delim.is_some() {
-        result.pop();
-    }
+fn cut_characters(line: &[u8], delim: &[u8], ranges: &[(usize, usize)]) -> Vec<u8> {
+    // The byte offset of each character, and of the end of the line
+    let mut offsets: Vec<usize> = (0..line.len())
+        .filter(|&i| i == 0 || !is_continuation(line[i]))
+        .collect();
+    let count = offsets.len();
+    offsets.push(line.len());
+
+    let parts = ranges
+        .iter()
+        .filter(|&&(start, _)| start < count)
+        .map(|&(start, end)| &line[offsets[start]..offsets[end.min(count - 1) + 1]]);
+
+    let mut result = Vec::new();
+    push_parts(&mut result, delim, parts);
     result
 }
 
 /// Cuts out selected fields from the given line based on the specified ranges.
 ///
-/// This function takes a string `line` representing the input line, a delimiter character `delim`,
-/// a vector of tuples `ranges` representing the start and end indices of the fields to cut, and
-/// a boolean `suppress` flag indicating whether to suppress output if no fields are found.
+/// This function takes a slice of bytes `line` representing the input line, a delimiter
+/// character `delim`, the output delimiter `out_delim`, a vector of tuples `ranges`
+/// representing the start and end indices of the fields to cut, and a boolean `suppress` flag
+/// indicating whether to suppress output if no fields are found.
 ///
 /// # Arguments
 ///
-/// * `line` - A string representing the input line.
+/// * `line` - A slice of bytes representing the input line.
 /// * `delim` - A character delimiter used to split the line into fields.
+/// * `out_delim` - The delimiter written between the selected fields.
 /// * `ranges` - A vector of tuples representing the start and end indices of the fields to cut.
 /// * `suppress` - A boolean flag indicating whether to suppress output if no fields are found.
 ///
 /// # Returns
 ///
-/// A tuple containing the resulting string with the selected fields and a boolean indicating
-/// whether the output was suppressed.
+/// The selected fields, or `None` if the output is suppressed.
 ///
-fn cut_fields(line: &str, delim: char, ranges: &Vec<(i32, i32)>, suppress: bool) -> (String, bool) {
-    let mut result = String::new();
-    let mut skip = false;
-    let delim_escaped = delim.escape_debug().to_string();
-    let mut fields: Vec<&str>;
-    if delim_escaped.len() > 1 {
-        fields = line.split(&delim_escaped).collect();
-    } else {
-        fields = line.split(delim).collect();
+fn cut_fields(
+    line: &[u8],
+    delim: char,
+    out_delim: &[u8],
+    ranges: &[(usize, usize)],
+    suppress: bool,
+) -> Option<Vec<u8>> {
+    let mut buf = [0; 4];
+    let delim_bytes = delim.encode_utf8(&mut buf).as_bytes();
+
+    let mut fields = Vec::new();
+    let mut field_start = 0;
+    for i in memchr::memmem::find_iter(line, delim_bytes) {
+        fields.push(&line[field_start..i]);
+        field_start = i + delim_bytes.len();
     }
 
-    if fields.len() == 1 {
-        fields = vec![];
+    // A line without a delimiter is written whole, unless it is suppressed
+    if fields.is_empty() {
+        return if suppress { None } else { Some(line.to_vec()) };
     }
+    fields.push(&line[field_start..]);
 
-    for (start, end) in ranges {
-        let start = *start as usize;
-        let end = *end as usize;
-
-        if fields.get(start).is_some() {
-            if start == end {
-                result.push_str(fields[start]);
-                result.push(delim);
-            } else {
-                for i in fields.iter().take(end + 1).skip(start) {
-                    result.push_str(i);
-                    result.push(delim);
-                }
-            }
-        }
-    }
-    result.pop();
-    if result.is_empty() && fields.is_empty() && !suppress {
-        result.push_str(line);
-    }
-    if result.is_empty() && fields.is_empty() && suppress {
-        skip = true;
-    }
-    (result, skip)
+    let selected = ranges
+        .iter()
+        .filter(|&&(start, _)| start < fields.len())
+        .flat_map(|&(start, end)| &fields[start..=end.min(fields.len() - 1)])
+        .copied();
+
+    let mut result = Vec::new();
+    push_parts(&mut result, out_delim, selected);
+    Some(result)
 }
 
 /// Processes files according to the provided arguments, cutting out selected fields, characters, or bytes.
@@ -288,6 +271,24 @@ fn cut_fields(line: &str, delim: char, ranges: &Vec<(i32, i32)>, suppress: bool)
 fn cut_files(args: Args) -> Result<(), Box<dyn std::error::Error>> {
     validate_args(&args).map_err(|err| Box::new(Error::new(ErrorKind::Other, err)))?;
 
+    let ranges = |list: &str| -> Result<Vec<(usize, usize)>, String> {
+        let ranges = read_range(list)?;
+        Ok(if args.complement {
+            complement(&ranges)
+        } else {
+            ranges
+        })
+    };
+    let parse_option = if let Some(bytes_list) = &args.bytes {
+        ParseVariat::Bytes(ranges(bytes_list)?)
+    } else if let Some(characters_list) = &args.characters {
+        ParseVariat::Characters(ranges(characters_list)?)
+    } else if let Some(fields_list) = &args.fields {
+        ParseVariat::Fields(ranges(fields_list)?)
+    } else {
+        return Err(Box::new(Error::new(ErrorKind::Other, "Invalid arguments")));
+    };
+
     // open files, or stdin
 
     let filenames = args.filenames;
@@ -303,103 +304,97 @@ fn cut_files(args: Args) -> Result<(), Box<dyn std::error::Error>> {
             bufs
         };
 
-    // Process each file
-    for file in readers {
-        let reader = io::BufReader::new(file);
-
-        let parse_option;
+    // Fields are split on <tab> by default, and without an output delimiter the selected
+    // parts are separated by the delimiter
+    let delim = args
+        .delimiter
+        .or(args.fields.as_ref().map(|_| '\t'))
+        .map(String::from);
+    let out_delim = args.output_delimiter.clone().or(delim).unwrap_or_default();
+    let out_delim = out_delim.as_bytes();
 
-        if let Some(bytes_list) = &args.bytes {
-            let ranges: Vec<(i32, i32)> = read_range(bytes_list)
-                .map_err(|err| Box::new(Error::new(ErrorKind::Other, err)))?;
+    let mut out = BufWriter::new(io::stdout().lock());
 
-            parse_option = ParseVariat::Bytes(ranges);
-        } else if let Some(characters_list) = &args.characters {
-            let ranges: Vec<(i32, i32)> = read_range(characters_list)
-                .map_err(|err| Box::new(Error::new(ErrorKind::Other, err)))?;
+    // Process each file
+    for file in readers {
+        let mut reader = io::BufReader::new(file);
+        let mut line = Vec::new();
 
-            parse_option = ParseVariat::Characters(ranges);
-        } else if let Some(fields_list) = &args.fields {
-            let ranges: Vec<(i32, i32)> = read_range(fields_list)
-                .map_err(|err| Box::new(Error::new(ErrorKind::Other, err)))?;
-            parse_option = ParseVariat::Fields(ranges);
-        } else {
-            return Err(Box::new(Error::new(ErrorKind::Other, "Invalid arguments")));
-        }
+        loop {
+            line.clear();
+            if reader.read_until(b'\n', &mut line)? == 0 {
+                break;
+            }
+            if line.last() == Some(&b'\n') {
+                line.pop();
+            }
 
-        for line in reader.lines() {
-            let line = line?;
-            match parse_option.clone() {
-                ParseVariat::Bytes(ranges) => {
-                    let bytes = cut_bytes(line.as_bytes(), args.delimiter, &ranges, args.no_split);
-                    match String::from_utf8(bytes) {
-                        Ok(string) => println!("{}", string),
-                        Err(e) => eprintln!("Conversion error to string: {}", e),
-                    }
-                }
-                ParseVariat::Characters(ranges) => {
-                    println!("{}", cut_characters(&line, args.delimiter, &ranges))
-                }
+            let result = match &parse_option {
+                ParseVariat::Bytes(ranges) => cut_bytes(&line, out_delim, ranges, args.no_split),
+                ParseVariat::Characters(ranges) => cut_characters(&line, out_delim, ranges),
                 ParseVariat::Fields(ranges) => {
-                    if let Some(delim) = args.delimiter {
-                        let result = cut_fields(&line, delim, &ranges, args.suppress);
-                        if !result.1 {
-                            println!("{}", result.0)
-                        }
-                    } else {
-                        println!("{}", line);
+                    let delim = args.delimiter.unwrap_or('\t');
+                    match cut_fields(&line, delim, out_delim, ranges, args.suppress) {
+                        Some(result) => result,
+                        None => continue,
                     }
                 }
-            }
+            };
+            out.write_all(&result)?;
+            out.write_all(b"\n")?;
         }
     }
+
+    out.flush()?;
     Ok(())
 }
 
-fn read_range(line: &str) -> Result<Vec<(i32, i32)>, String> {
-    let ranges: Vec<&str> = line.split(',').collect();
-    for range in &ranges {
-        if *range == "-" {
-            return Err("Invalid range, no endpopint".to_string());
+/// Parses a list of positions, which are numbered from 1: a position, a range `N-M`, or
+/// an open range `-M` from the first position or `N-` to the end of the line, separated by
+/// commas or blanks.
+///
+/// The returned ranges count from 0 and are sorted, with overlapping ranges merged.
+///
+/// # Errors
+///
+/// Returns an error if a position isn't a positive number, or a range is decreasing.
+fn read_range(list: &str) -> Result<Vec<(usize, usize)>, String> {
+    let position = |pos: &str| -> Result<usize, String> {
+        if pos.is_empty() || !pos.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(format!("invalid byte, character or field list: '{}'", list));
         }
-    }
-    let res: Result<Vec<(i32, i32)>, String> = ranges
-        .iter()
-        .map(|range| {
-            let nums: Vec<&str> = range.split('-').collect();
-
-            let start = if nums[0].is_empty() {
-                0
-            } else {
-                match nums[0].parse::<i32>() {
-                    Err(err) => return Err(err.to_string()),
-                    Ok(num) => num - 1,
-                }
-            };
-
-            let end = if range.len() == 1 {
-                start
-            } else if nums[1].is_empty() {
-                i32::MAX - 1
-            } else {
-                match nums[1].parse::<i32>() {
-                    Err(err) => return Err(err.to_string()),
-                    Ok(num) => num - 1,
-                }
-            };
-            Ok((start, end))
-        })
-        .collect();
-    let mut ranges = res?;
-    for (start, end) in &ranges {
-        if start > end {
-            // Invalid range
-            return Err("Invalid decreasing range".to_string());
+        match pos.parse::<usize>() {
+            Ok(0) => Err("positions are numbered from 1".to_string()),
+            Ok(pos) => Ok(pos - 1),
+            // a position past any line selects nothing
+            Err(_) => Ok(usize::MAX - 1),
         }
-        if *start < 0 {
-            return Err("Invalid start position (from 0)".to_string());
+    };
+
+    let mut ranges = Vec::new();
+    for range in list
+        .split([',', ' ', '\t'])
+        .filter(|range| !range.is_empty())
+    {
+        let (start, end) = match range.split_once('-') {
+            None => {
+                let pos = position(range)?;
+                (pos, pos)
+            }
+            Some(("", "")) => return Err("invalid range with no endpoint: -".to_string()),
+            Some(("", end)) => (0, position(end)?),
+            Some((start, "")) => (position(start)?, usize::MAX),
+            Some((start, end)) => (position(start)?, position(end)?),
+        };
+        if start > end {
+            return Err(format!("invalid decreasing range: '{}'", range));
         }
+        ranges.push((start, end));
     }
+    if ranges.is_empty() {
+        return Err(format!("invalid byte, character or field list: '{}'", list));
+    }
+
     ranges.sort_by(|a, b| a.0.cmp(&b.0));
     let mut filtered_ranges = Vec::new();
 
@@ -433,6 +428,23 @@ fn read_range(line: &str) -> Result<Vec<(i32, i32)>, String> {
     Ok(merged_ranges)
 }
 
+/// Returns the positions that aren't in the sorted and merged `ranges`, as ranges of the
+/// same form.
+fn complement(ranges: &[(usize, usize)]) -> Vec<(usize, usize)> {
+    let mut result = Vec::new();
+    let mut next = 0;
+    for &(start, end) in ranges {
+        if start > next {
+            result.push((next, start - 1));
+        }
+        next = end.saturating_add(1);
+    }
+    if next < usize::MAX {
+        result.push((next, usize::MAX));
+    }
+    result
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     // parse command line arguments
     let args = Args::parse();
@@ -7,16 +7,20 @@
 // SPDX-License-Identifier: MIT
 //
 
+mod grep_util;
+
+use self::grep_util::{
+    new_matcher, BinaryFiles, ColorWhen, Colors, Input, MatchOptions, Matcher, Matches, Syntax,
+};
 use clap::Parser;
 use gettextrs::{bind_textdomain_codeset, textdomain};
-use libc::{regcomp, regex_t, regexec, regfree, REG_EXTENDED, REG_ICASE, REG_NOMATCH};
 use plib::PROJECT_NAME;
 use std::{
-    ffi::CString,
+    collections::VecDeque,
     fs::File,
-    io::{self, BufRead, BufReader},
+    io::{self, BufRead, BufReader, Write},
+    os::unix::io::FromRawFd,
     path::{Path, PathBuf},
-    ptr,
 };
 
 /// grep - search a file for a pattern.
@@ -31,6 +35,50 @@ struct Args {
     #[arg(short = 'F', long)]
     fixed_strings: bool,
 
+    /// Search binary files as if they were text, the same as --binary-files=text.
+    #[arg(short = 'a', long)]
+    text: bool,
+
+    /// Treat binary files as not matching, the same as --binary-files=without-match.
+    #[arg(short = 'I')]
+    without_match: bool,
+
+    /// How to search files that contain binary data, which by default only get a message that they
+    /// match written instead of the matching lines.
+    #[arg(long, value_enum, default_value_t = BinaryFiles::Binary)]
+    binary_files: BinaryFiles,
+
+    /// Highlight the matching text, file names and line numbers in the output: never, always or,
+    /// with no argument, only when writing to a terminal (auto). The colors can be set in the
+    /// GREP_COLORS environment variable.
+    #[arg(
+        long,
+        value_enum,
+        value_name = "WHEN",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "auto",
+        alias = "colour"
+    )]
+    color: Option<ColorWhen>,
+
+    /// Write NUM lines of context after each selected line.
+    #[arg(short = 'A', long, value_name = "NUM")]
+    after_context: Option<usize>,
+
+    /// Write NUM lines of context before each selected line.
+    #[arg(short = 'B', long, value_name = "NUM")]
+    before_context: Option<usize>,
+
+    /// Write NUM lines of context before and after each selected line. Groups of lines that
+    /// aren't adjacent are separated by a line of "--".
+    #[arg(short = 'C', long, value_name = "NUM")]
+    context: Option<usize>,
+
+    /// Write only the matching parts of the selected lines, each on a line of its own.
+    #[arg(short = 'o', long)]
+    only_matching: bool,
+
     /// Write only a count of selected lines to standard output.
     #[arg(short, long)]
     count: bool,
@@ -51,6 +99,21 @@ struct Args {
     #[arg(short = 'l', long)]
     files_with_matches: bool,
 
+    /// Treat input and output data as lines terminated by NUL bytes instead of newlines.
+    #[arg(short = 'z', long)]
+    null_data: bool,
+
+    /// Write a NUL byte after each file name in the output, instead of the character that would
+    /// normally follow it.
+    #[arg(short = 'Z', long)]
+    null: bool,
+
+    /// Map regular files into memory instead of reading them, which can be faster for large files.
+    /// The files must not be changed while they're searched: a file that is truncated terminates
+    /// grep.
+    #[arg(long)]
+    mmap: bool,
+
     /// Precede each output line by its relative line number in the file, each file starting at line 1.
     #[arg(short = 'n', long)]
     line_number: bool,
@@ -63,6 +126,15 @@ struct Args {
     #[arg(short = 's', long)]
     no_messages: bool,
 
+    /// Search the files in directories and their subdirectories, following symbolic links only
+    /// if they are operands.
+    #[arg(short = 'r', long)]
+    recursive: bool,
+
+    /// Search the files in directories and their subdirectories, following all symbolic links.
+    #[arg(short = 'R', long)]
+    dereference_recursive: bool,
+
     /// Select lines not matching any of the specified patterns.
     #[arg(short = 'v', long)]
     invert_match: bool,
@@ -145,7 +217,12 @@ impl Args {
         self.regexp.dedup();
 
         if self.input_files.is_empty() {
-            self.input_files.push(String::from("-"))
+            // A recursive search defaults to the working directory
+            if self.recursive || self.dereference_recursive {
+                self.input_files.push(String::from("."))
+            } else {
+                self.input_files.push(String::from("-"))
+            }
         }
     }
 
@@ -180,164 +257,78 @@ impl Args {
             OutputMode::Default
         };
 
-        let patterns = Patterns::new(
-            self.regexp,
-            self.extended_regexp,
-            self.fixed_strings,
-            self.ignore_case,
-            self.line_regexp,
-        )?;
+        let syntax = if self.fixed_strings {
+            Syntax::Fixed
+        } else if self.extended_regexp {
+            Syntax::Extended
+        } else {
+            Syntax::Basic
+        };
+        let options = MatchOptions {
+            ignore_case: self.ignore_case,
+            line_regexp: self.line_regexp,
+            null_data: self.null_data,
+        };
+        let matcher = new_matcher(self.regexp, syntax, options)?;
+
+        let binary_files = if self.text {
+            BinaryFiles::Text
+        } else if self.without_match {
+            BinaryFiles::WithoutMatch
+        } else {
+            self.binary_files
+        };
+
+        let recursion = if self.dereference_recursive {
+            Recursion::Logical
+        } else if self.recursive {
+            Recursion::Physical
+        } else {
+            Recursion::None
+        };
+        // Files found in a directory are named in the output like multiple inputs
+        let multiple_inputs = self.input_files.len() > 1
+            || (recursion != Recursion::None && Path::new(&self.input_files[0]).is_dir());
+
+        // Context lines contain nothing to write with `-o`
+        let (before_context, after_context) = if self.only_matching {
+            (0, 0)
+        } else {
+            (
+                self.before_context.or(self.context).unwrap_or(0),
+                self.after_context.or(self.context).unwrap_or(0),
+            )
+        };
+
+        let colors = self
+            .color
+            .is_some_and(ColorWhen::enabled)
+            .then(Colors::from_env);
 
         Ok(GrepModel {
             any_matches: false,
             any_errors: self.any_errors,
             line_number: self.line_number,
+            terminator: if self.null_data { b'\0' } else { b'\n' },
+            mmap: self.mmap,
+            null: self.null,
             no_messages: self.no_messages,
             invert_match: self.invert_match,
-            multiple_inputs: self.input_files.len() > 1,
+            only_matching: self.only_matching,
+            before_context,
+            after_context,
+            any_groups: false,
+            multiple_inputs,
+            recursion,
+            binary_files,
+            colors,
             output_mode,
-            patterns,
+            matcher,
             input_files: self.input_files,
         })
     }
 }
 
-/// Newtype over `Vec[libc::regex_t]`. Provides functionality for matching input data.
-enum Patterns {
-    Fixed(Vec<String>, bool, bool),
-    Regex(Vec<regex_t>),
-}
-
-impl Patterns {
-    /// Creates a new `Patterns` object with regex patterns.
-    ///
-    /// # Arguments
-    ///
-    /// * `patterns` - `Vec<String>` containing the patterns.
-    /// * `extended_regexp` - `bool` indicating whether to use extended regular expressions.
-    /// * `fixed_string` - `bool` indicating whether pattern is fixed string or regex.
-    /// * `ignore_case` - `bool` indicating whether to ignore case.
-    /// * `line_regexp` - `bool` indicating whether to match the entire input.
-    ///
-    /// # Errors
-    ///
-    /// Returns an error if passed invalid regex.
-    ///
-    /// # Returns
-    ///
-    /// Returns [Patterns](Patterns).
-    fn new(
-        patterns: Vec<String>,
-        extended_regexp: bool,
-        fixed_string: bool,
-        ignore_case: bool,
-        line_regexp: bool,
-    ) -> Result<Self, String> {
-        if fixed_string {
-            Ok(Self::Fixed(
-                patterns
-                    .into_iter()
-                    .map(|p| if ignore_case { p.to_lowercase() } else { p })
-                    .collect(),
-                ignore_case,
-                line_regexp,
-            ))
-        } else {
-            let mut ps = vec![];
-
-            let mut cflags = 0;
-            if extended_regexp {
-                cflags |= REG_EXTENDED;
-            }
-            if ignore_case {
-                cflags |= REG_ICASE;
-            }
-            for mut pattern in patterns {
-                // macOS version of [regcomp](regcomp) from `libc`
-                // provides additional check for empty regex. In this case,
-                // an error [REG_EMPTY](https://developer.apple.com/library/archive/documentation/System/Conceptual/ManPages_iPhoneOS/man3/regcomp.3.html)
-                // will be returned.
-
-                // Therefore, an empty pattern is replaced with ".*".
-                #[cfg(target_os = "macos")]
-                {
-                    pattern = if pattern.is_empty() {
-                        String::from(".*")
-                    } else {
-                        pattern
-                    };
-                }
-                pattern = if line_regexp {
-                    format!("^{pattern}$")
-                } else {
-                    pattern
-                };
-
-                let c_pattern = CString::new(pattern).map_err(|err| err.to_string())?;
-                let mut regex = unsafe { std::mem::zeroed::<regex_t>() };
-
-                let result = unsafe { regcomp(&mut regex, c_pattern.as_ptr(), cflags) };
-                if result != 0 {
-                    return Err(format!(
-                        "Error compiling regex '{}'",
-                        c_pattern.to_string_lossy()
-                    ));
-                }
-                ps.push(regex);
-            }
-            Ok(Self::Regex(ps))
-        }
-    }
-
-    /// Checks if input string matches the present patterns.
-    ///
-    /// # Arguments
-    ///
-    /// * `input` - object that implements [AsRef](AsRef) for [str](str) and describes line.
-    ///
-    /// # Returns
-    ///
-    /// Returns [bool](bool) - `true` if input matches present patterns, else `false`.
-    fn matches(&self, input: impl AsRef<str>) -> bool {
-        let input = input.as_ref();
-        match self {
-            Patterns::Fixed(patterns, ignore_case, line_regexp) => {
-                let input = if *ignore_case {
-                    input.to_lowercase()
-                } else {
-                    input.to_string()
-                };
-                patterns.iter().any(|p| {
-                    if *line_regexp {
-                        input == *p
-                    } else {
-                        input.contains(p)
-                    }
-                })
-            }
-            Patterns::Regex(patterns) => {
-                let c_input = CString::new(input).unwrap();
-                patterns.iter().any(|p| unsafe {
-                    regexec(p, c_input.as_ptr(), 0, ptr::null_mut(), 0) != REG_NOMATCH
-                })
-            }
-        }
-    }
-}
-
-impl Drop for Patterns {
-    fn drop(&mut self) {
-        match &self {
-            Patterns::Fixed(_, _, _) => {}
-            Patterns::Regex(regexes) => {
-                for regex in regexes {
-                    unsafe { regfree(regex as *const regex_t as *mut regex_t) }
-                }
-            }
-        }
-    }
-}
-
 /// Represents possible `grep` output modes.
 #[derive(Eq, PartialEq)]
 enum OutputMode {
@@ -347,16 +338,51 @@ enum OutputMode {
     Default,
 }
 
+/// Represents whether and how `grep` searches directories.
+#[derive(Eq, PartialEq)]
+enum Recursion {
+    None,
+    /// `-r`: Symbolic links are only followed if they are operands.
+    Physical,
+    /// `-R`: All symbolic links are followed.
+    Logical,
+}
+
+/// The lines of context around the selected lines of an input.
+#[derive(Default)]
+struct Context {
+    /// The lines before the next selected line, with their numbers, up to the `-B` count.
+    before: VecDeque<(u64, Vec<u8>)>,
+    /// The number of lines after the last selected line that are still to be written.
+    after_left: usize,
+    /// The number of the line written last.
+    last_written: Option<u64>,
+}
+
 /// Structure that contains all necessary information for `grep` utility processing.
 struct GrepModel {
     any_matches: bool,
     any_errors: bool,
     line_number: bool,
+    /// The byte that terminates the lines of input and output.
+    terminator: u8,
+    /// `-Z`: File names are followed by a NUL byte.
+    null: bool,
+    mmap: bool,
     no_messages: bool,
     invert_match: bool,
+    only_matching: bool,
+    before_context: usize,
+    after_context: usize,
+    /// Set once a group of lines with context has been written, so the next one is separated
+    /// from it.
+    any_groups: bool,
     multiple_inputs: bool,
+    recursion: Recursion,
+    binary_files: BinaryFiles,
+    colors: Option<Colors>,
     output_mode: OutputMode,
-    patterns: Patterns,
+    matcher: Box<dyn Matcher>,
     input_files: Vec<String>,
 }
 
@@ -369,13 +395,15 @@ impl GrepModel {
     fn grep(&mut self) -> i32 {
         for input_name in self.input_files.drain(..).collect::<Vec<_>>() {
             if input_name == "-" {
-                let reader = Box::new(BufReader::new(io::stdin()));
-                self.process_input("(standard input)", reader);
+                let input = Input::new(Box::new(io::stdin()), self.terminator);
+                self.process_input("(standard input)", input);
+            } else if self.recursion != Recursion::None {
+                self.process_tree(&input_name);
             } else {
                 match File::open(&input_name) {
                     Ok(file) => {
-                        let reader = Box::new(BufReader::new(file));
-                        self.process_input(&input_name, reader)
+                        let input = self.open_input(file);
+                        self.process_input(&input_name, input)
                     }
                     Err(err) => {
                         self.any_errors = true;
@@ -399,86 +427,330 @@ impl GrepModel {
         }
     }
 
-    /// Reads lines from buffer and processes them.
+    /// Processes the files in the tree at `path`, for `-r` and `-R`. Directories are searched in
+    /// file name order. Devices, FIFOs, sockets and symbolic links that aren't followed are skipped,
+    /// unless `path` itself is one. An error on one file or directory is reported, and the search
+    /// goes on with the rest.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - [str](str) that represents the operand to search.
+    fn process_tree(&mut self, path: &str) {
+        let walk = ftw::Walker::new(path)
+            .follow_symlinks_on_args(true)
+            .follow_symlinks(self.recursion == Recursion::Logical)
+            .sort_by_file_name(true)
+            .walk();
+
+        for entry in walk {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(err) => {
+                    let path = err.path().to_path_buf();
+                    self.report_error(&path.to_string_lossy(), &err.into_error().inner());
+                    continue;
+                }
+            };
+            let input_name = entry.path().to_string_lossy().to_string();
+
+            match entry.file_type() {
+                Ok(ftw::FileType::RegularFile) => {}
+                Ok(ftw::FileType::Directory) => continue,
+                Ok(_) if entry.depth() == 0 => {}
+                Ok(_) => continue,
+                Err(err) => {
+                    self.report_error(&input_name, &err);
+                    continue;
+                }
+            }
+
+            // Opened relative to the directory, so the path can be of any length
+            let fd = unsafe {
+                libc::openat(
+                    entry.dir_fd(),
+                    entry.file_name().as_ptr(),
+                    libc::O_RDONLY | libc::O_CLOEXEC,
+                )
+            };
+            if fd < 0 {
+                self.report_error(&input_name, &io::Error::last_os_error());
+                continue;
+            }
+            let file = unsafe { File::from_raw_fd(fd) };
+
+            let input = self.open_input(file);
+            self.process_input(&input_name, input);
+            if self.any_matches && self.output_mode == OutputMode::Quiet {
+                return;
+            }
+        }
+    }
+
+    /// Reports an error on an input file, unless messages are suppressed with `-s`.
+    fn report_error(&mut self, input_name: &str, err: &io::Error) {
+        self.any_errors = true;
+        if !self.no_messages {
+            eprintln!("{}: {}", input_name, err);
+        }
+    }
+
+    /// Prepares to search an open file, mapping it into memory with `--mmap` if it's a regular
+    /// file. It's read instead if it can't be mapped.
+    fn open_input(&self, file: File) -> Input {
+        if self.mmap && file.metadata().is_ok_and(|metadata| metadata.is_file()) {
+            // # Safety
+            // With `--mmap`, the user vouches that the files aren't changed while they're
+            // searched, as the option's help says.
+            if let Ok(input) = unsafe { Input::mapped(&file, self.terminator) } {
+                return input;
+            }
+        }
+        Input::new(Box::new(file), self.terminator)
+    }
+
+    /// Reads lines from input and processes them.
     ///
     /// # Arguments
     ///
     /// * `input_name` - [str](str) that represents content source name.
-    /// * `reader` - [Box](Box) that contains object that implements [BufRead] and reads lines.
-    fn process_input(&mut self, input_name: &str, mut reader: Box<dyn BufRead>) {
-        let mut line_number: u64 = 0;
+    /// * `input` - [Input](Input) that reads the lines.
+    fn process_input(&mut self, input_name: &str, mut input: Input) {
+        let mut context = Context::default();
+        // Unless lines that don't match are selected or written as context, they can be skipped
+        // without looking at each of them
+        let skip_lines = !self.invert_match && self.before_context == 0;
         loop {
-            let mut line = String::new();
-            line_number += 1;
-            match reader.read_line(&mut line) {
-                Ok(n_read) => {
-                    if n_read == 0 {
-                        break;
-                    }
-                    let trimmed = if line.ends_with('\n') {
-                        &line[..line.len() - 1]
-                    } else {
-                        &line
-                    };
-
-                    let init_matches = self.patterns.matches(trimmed);
-                    let matches = if self.invert_match {
-                        !init_matches
-                    } else {
-                        init_matches
-                    };
-                    if matches {
-                        self.any_matches = true;
-                        match &mut self.output_mode {
-                            OutputMode::Count(count) => {
-                                *count += 1;
-                            }
-                            OutputMode::FilesWithMatches => {
-                                println!("{input_name}");
-                                break;
-                            }
-                            OutputMode::Quiet => {
-                                return;
-                            }
-                            OutputMode::Default => {
-                                let result = format!(
-                                    "{}{}{}",
-                                    if self.multiple_inputs {
-                                        format!("{input_name}:")
-                                    } else {
-                                        String::new()
-                                    },
-                                    if self.line_number {
-                                        format!("{line_number}:")
-                                    } else {
-                                        String::new()
-                                    },
-                                    trimmed
-                                );
-                                println!("{result}");
-                            }
-                        }
-                    }
-                    line.clear();
-                }
+            let next = if skip_lines && context.after_left == 0 {
+                input.next_match(self.matcher.as_ref())
+            } else {
+                input.next_line()
+            };
+            match next {
+                Ok(true) => {}
+                Ok(false) => break,
                 Err(err) => {
                     self.any_errors = true;
                     if !self.no_messages {
                         eprintln!(
                             "{}: Error reading line {} ({})",
-                            input_name, line_number, err
+                            input_name,
+                            input.line_number() + 1,
+                            err
                         );
                     }
+                    break;
+                }
+            }
+            let line = input.line();
+
+            let binary = input.is_binary() && self.binary_files != BinaryFiles::Text;
+            if binary && self.binary_files == BinaryFiles::WithoutMatch {
+                break;
+            }
+
+            let init_matches = self.matcher.is_match(line);
+            let matches = if self.invert_match {
+                !init_matches
+            } else {
+                init_matches
+            };
+            if matches {
+                self.any_matches = true;
+                match &mut self.output_mode {
+                    OutputMode::Count(count) => {
+                        *count += 1;
+                    }
+                    OutputMode::FilesWithMatches => {
+                        let mut out = Vec::new();
+                        self.write_file_name(&mut out, input_name);
+                        out.push(if self.null { b'\0' } else { b'\n' });
+                        Self::write_out(&out);
+                        break;
+                    }
+                    OutputMode::Quiet => {
+                        return;
+                    }
+                    // The lines of binary data are not written, only that there was a match
+                    OutputMode::Default if binary => {
+                        println!("{input_name}: binary file matches");
+                        break;
+                    }
+                    OutputMode::Default => {
+                        let line_number = input.line_number();
+                        self.write_before_context(input_name, &mut context, line_number);
+                        let out = if self.only_matching {
+                            self.format_matches(input_name, line_number, line)
+                        } else {
+                            self.format_line(input_name, line_number, line, true)
+                        };
+                        Self::write_out(&out);
+                        context.last_written = Some(line_number);
+                        context.after_left = self.after_context;
+                    }
+                }
+            } else if self.output_mode == OutputMode::Default && !binary {
+                let line_number = input.line_number();
+                if context.after_left > 0 {
+                    context.after_left -= 1;
+                    let out = self.format_line(input_name, line_number, line, false);
+                    Self::write_out(&out);
+                    context.last_written = Some(line_number);
+                } else if self.before_context > 0 {
+                    if context.before.len() == self.before_context {
+                        context.before.pop_front();
+                    }
+                    context.before.push_back((line_number, line.to_vec()));
                 }
             }
         }
-        if let OutputMode::Count(count) = &mut self.output_mode {
+        if let OutputMode::Count(count) = self.output_mode {
+            let mut out = Vec::new();
             if self.multiple_inputs {
-                println!("{input_name}:{count}");
-            } else {
-                println!("{count}");
+                self.write_file_name(&mut out, input_name);
+                self.write_file_name_separator(&mut out, b':');
+            }
+            out.extend_from_slice(format!("{count}\n").as_bytes());
+            Self::write_out(&out);
+            self.output_mode = OutputMode::Count(0);
+        }
+    }
+
+    /// Writes the lines of context kept before the selected line `line_number`, and the
+    /// separator before them if they start a new group. With context lines, the group separator
+    /// is also written before a selected line that doesn't follow the lines written last.
+    fn write_before_context(&mut self, input_name: &str, context: &mut Context, line_number: u64) {
+        if self.before_context == 0 && self.after_context == 0 {
+            return;
+        }
+
+        let first = context.before.front().map_or(line_number, |(n, _)| *n);
+        let adjacent = context.last_written.is_some_and(|last| first == last + 1);
+        let mut out = Vec::new();
+        if self.any_groups && !adjacent {
+            match &self.colors {
+                Some(colors) => colors.paint(&mut out, &colors.separator, b"--"),
+                None => out.extend_from_slice(b"--"),
+            }
+            out.push(b'\n');
+        }
+        self.any_groups = true;
+
+        for (number, line) in context.before.drain(..) {
+            out.extend(self.format_line(input_name, number, &line, false));
+        }
+        Self::write_out(&out);
+    }
+
+    /// Writes the file name and line number that precede a line of output, if they are written.
+    /// They are followed by ':' for selected lines, and by '-' for context lines.
+    fn write_prefix(&self, out: &mut Vec<u8>, input_name: &str, line_number: u64, selected: bool) {
+        let separator = if selected { b':' } else { b'-' };
+        if self.multiple_inputs {
+            self.write_file_name(out, input_name);
+            self.write_file_name_separator(out, separator);
+        }
+        if self.line_number {
+            let line_number = line_number.to_string();
+            match &self.colors {
+                Some(colors) => colors.paint(out, &colors.line_number, line_number.as_bytes()),
+                None => out.extend_from_slice(line_number.as_bytes()),
+            }
+            self.write_separator(out, separator);
+        }
+    }
+
+    /// Formats a selected or context line for the output, preceded by the file name and line
+    /// number if they are written, and highlighted with `--color`.
+    ///
+    /// # Arguments
+    ///
+    /// * `input_name` - [str](str) that represents content source name.
+    /// * `line_number` - the number of the line in the input.
+    /// * `line` - the line without its terminator.
+    /// * `selected` - whether the line is selected, rather than written as context.
+    fn format_line(
+        &self,
+        input_name: &str,
+        line_number: u64,
+        line: &[u8],
+        selected: bool,
+    ) -> Vec<u8> {
+        let mut out = Vec::with_capacity(line.len() + 1);
+        self.write_prefix(&mut out, input_name, line_number, selected);
+
+        match &self.colors {
+            Some(colors) => {
+                let (line_color, match_color) = if selected {
+                    (&colors.selected_line, &colors.selected_match)
+                } else {
+                    (&colors.context_line, &colors.context_match)
+                };
+                // Only the lines that match have matches to highlight, which with `-v` are the
+                // context lines
+                let mut end = 0;
+                if selected != self.invert_match {
+                    for m in Matches::new(self.matcher.as_ref(), line) {
+                        colors.paint(&mut out, line_color, &line[end..m.start]);
+                        colors.paint(&mut out, match_color, &line[m.clone()]);
+                        end = m.end;
+                    }
+                }
+                colors.paint(&mut out, line_color, &line[end..]);
+            }
+            None => out.extend_from_slice(line),
+        }
+        out.push(self.terminator);
+        out
+    }
+
+    /// Formats the matches in a selected line for the output of `-o`, each on a line of its own.
+    /// Lines selected with `-v` have no matches, and nothing is written for them.
+    fn format_matches(&self, input_name: &str, line_number: u64, line: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        if self.invert_match {
+            return out;
+        }
+        for m in Matches::new(self.matcher.as_ref(), line) {
+            self.write_prefix(&mut out, input_name, line_number, true);
+            match &self.colors {
+                Some(colors) => colors.paint(&mut out, &colors.selected_match, &line[m]),
+                None => out.extend_from_slice(&line[m]),
+            }
+            out.push(self.terminator);
+        }
+        out
+    }
+
+    fn write_file_name(&self, out: &mut Vec<u8>, input_name: &str) {
+        match &self.colors {
+            Some(colors) => colors.paint(out, &colors.file_name, input_name.as_bytes()),
+            None => out.extend_from_slice(input_name.as_bytes()),
+        }
+    }
+
+    fn write_file_name_separator(&self, out: &mut Vec<u8>, separator: u8) {
+        if self.null {
+            out.push(b'\0');
+        } else {
+            self.write_separator(out, separator);
+        }
+    }
+
+    fn write_separator(&self, out: &mut Vec<u8>, separator: u8) {
+        match &self.colors {
+            Some(colors) => colors.paint(out, &colors.separator, &[separator]),
+            None => out.push(separator),
+        }
+    }
+
+    /// Writes output to STDOUT, exiting if that fails, as there is no point in going on. The
+    /// reader of a pipe going away, as with `grep ... | head`, is not reported.
+    fn write_out(out: &[u8]) {
+        if let Err(err) = io::stdout().write_all(out) {
+            if err.kind() != io::ErrorKind::BrokenPipe {
+                eprintln!("{}", err);
             }
-            *count = 0;
+            std::process::exit(2);
         }
     }
 }
@@ -488,6 +760,9 @@ impl GrepModel {
 //     1 - No lines were selected.
 //     >1 - An error occurred.
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Bracket expressions, character classes and case in `regcomp`, and the encoding, are those
+    // of the C library's locale, which is set directly
+    unsafe { libc::setlocale(libc::LC_ALL, c"".as_ptr()) };
     textdomain(PROJECT_NAME)?;
     bind_textdomain_codeset(PROJECT_NAME, "UTF-8")?;
     // Parse command line arguments
@@ -0,0 +1,142 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+//! Highlighting of the output with `--color`, using SGR escape sequences.
+//!
+//! The colors can be set in the `GREP_COLORS` environment variable, as a
+//! colon-separated list of capabilities such as `ms=01;31:fn=35`:
+//!
+//! * `mt` - both `ms` and `mc`.
+//! * `ms` - the matching text in selected lines.
+//! * `mc` - the matching text in context lines, which only has matches with
+//!   `-v`.
+//! * `sl` - the rest of the selected lines.
+//! * `cx` - the rest of the context lines.
+//! * `fn` - file names.
+//! * `ln` - line numbers.
+//! * `se` - separators between the fields, and between groups of context
+//!   lines.
+//! * `ne` - a boolean that leaves out the Erase in Line sequence after each
+//!   colored piece of text.
+//!
+//! Unknown capabilities are ignored, like malformed ones.
+
+use std::env;
+
+/// When the output is colored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ColorWhen {
+    Never,
+    Always,
+    /// Only when writing to a terminal.
+    Auto,
+}
+
+impl ColorWhen {
+    /// Checks if the standard output should be colored.
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorWhen::Never => false,
+            ColorWhen::Always => true,
+            ColorWhen::Auto => {
+                atty::is(atty::Stream::Stdout)
+                    && env::var_os("TERM").is_some_and(|term| term != "dumb")
+            }
+        }
+    }
+}
+
+/// The SGR parameters of each part of the output. An empty string leaves the
+/// part uncolored.
+#[derive(Debug, Clone)]
+pub struct Colors {
+    pub selected_match: String,
+    pub context_match: String,
+    pub selected_line: String,
+    pub context_line: String,
+    pub file_name: String,
+    pub line_number: String,
+    pub separator: String,
+    // Whether each colored piece is followed by Erase in Line, which keeps
+    // the background color from spreading to the end of the line
+    erase: bool,
+}
+
+impl Default for Colors {
+    fn default() -> Self {
+        Self {
+            selected_match: String::from("01;31"),
+            context_match: String::from("01;31"),
+            selected_line: String::new(),
+            context_line: String::new(),
+            file_name: String::from("35"),
+            line_number: String::from("32"),
+            separator: String::from("36"),
+            erase: true,
+        }
+    }
+}
+
+impl Colors {
+    /// The default colors, changed by the `GREP_COLORS` environment variable.
+    pub fn from_env() -> Self {
+        let mut colors = Self::default();
+        if let Ok(spec) = env::var("GREP_COLORS") {
+            colors.parse(&spec);
+        }
+        colors
+    }
+
+    fn parse(&mut self, spec: &str) {
+        for capability in spec.split(':') {
+            let (name, value) = match capability.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (capability, None),
+            };
+            // SGR parameters are numbers separated by semicolons
+            if let Some(value) = value {
+                if !value.bytes().all(|b| b.is_ascii_digit() || b == b';') {
+                    continue;
+                }
+            }
+
+            match (name, value) {
+                ("mt", Some(value)) => {
+                    self.selected_match = value.to_string();
+                    self.context_match = value.to_string();
+                }
+                ("ms", Some(value)) => self.selected_match = value.to_string(),
+                ("mc", Some(value)) => self.context_match = value.to_string(),
+                ("sl", Some(value)) => self.selected_line = value.to_string(),
+                ("cx", Some(value)) => self.context_line = value.to_string(),
+                ("fn", Some(value)) => self.file_name = value.to_string(),
+                ("ln", Some(value)) => self.line_number = value.to_string(),
+                ("se", Some(value)) => self.separator = value.to_string(),
+                ("ne", None) => self.erase = false,
+                _ => {}
+            }
+        }
+    }
+
+    /// Writes `text` to `out` in the color of `sgr`.
+    pub fn paint(&self, out: &mut Vec<u8>, sgr: &str, text: &[u8]) {
+        if sgr.is_empty() || text.is_empty() {
+            out.extend_from_slice(text);
+            return;
+        }
+        let erase: &[u8] = if self.erase { b"\x1b[K" } else { b"" };
+        out.extend_from_slice(b"\x1b[");
+        out.extend_from_slice(sgr.as_bytes());
+        out.push(b'm');
+        out.extend_from_slice(erase);
+        out.extend_from_slice(text);
+        out.extend_from_slice(b"\x1b[m");
+        out.extend_from_slice(erase);
+    }
+}
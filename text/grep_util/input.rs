@@ -0,0 +1,306 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+use super::Matcher;
+use memchr::{memchr, memchr_iter, memrchr};
+use std::{
+    fs::File,
+    io::{self, Read},
+    os::unix::io::AsRawFd,
+    ptr, slice,
+};
+
+/// How files that contain binary data are searched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum BinaryFiles {
+    /// Report that the file matches, instead of writing the matching lines.
+    Binary,
+    /// Search the file as if it were text (`-a`).
+    Text,
+    /// Treat the file as not matching (`-I`).
+    WithoutMatch,
+}
+
+// The size of the reads from the input. A line that doesn't fit grows the
+// buffer to hold it.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+// As much of the start of the input as is looked at to classify it
+const CLASSIFY_SIZE: usize = 8 * 1024;
+
+/// A regular file mapped into memory, read-only.
+///
+/// The mapping is private, but changes to the file can still show through it,
+/// and accessing it after the file is truncated raises SIGBUS. Creating one is
+/// therefore unsafe: the file must not be modified or truncated while the
+/// mapping exists.
+struct Mapping {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+impl Mapping {
+    /// # Safety
+    /// The caller has to ensure that `file` isn't modified or truncated while the mapping exists
+    unsafe fn new(file: &File, len: usize) -> io::Result<Self> {
+        let ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self { ptr, len })
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.ptr as *const u8, self.len) }
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.ptr, self.len) };
+    }
+}
+
+enum Source {
+    Reader(Box<dyn Read>),
+    Mapped(Mapping),
+}
+
+/// Reads the lines of an input file, which are terminated by newlines, or by
+/// NUL bytes with `-z`.
+///
+/// The input is read in large chunks rather than line by line, so that
+/// `next_match` can have the matcher skip over the lines that don't match all
+/// at once.
+///
+/// The input is classified as binary data if the start of it, as far as the
+/// first read goes, contains a NUL byte. A NUL byte in a later line makes it
+/// binary from that line on. When NUL bytes terminate the lines, the input is
+/// never binary.
+///
+/// Lines are not required to be valid in the encoding of the locale. Bytes
+/// that don't form a character are matched and written as they are.
+pub struct Input {
+    source: Source,
+    terminator: u8,
+    // Read but not yet consumed input is `buf[pos..]`, or the rest of the
+    // mapping from `pos` on
+    buf: Vec<u8>,
+    pos: usize,
+    eof: bool,
+    // Set once the start of the input has been looked at
+    classified: bool,
+    binary: bool,
+    line: (usize, usize),
+    line_number: u64,
+}
+
+impl Input {
+    pub fn new(reader: Box<dyn Read>, terminator: u8) -> Self {
+        Self::with_source(Source::Reader(reader), terminator)
+    }
+
+    /// Maps the regular file `file` into memory instead of reading it, which saves copying the
+    /// data. A file with a size of 0 is read instead, as files in procfs and sysfs have content
+    /// although their size is 0.
+    ///
+    /// # Safety
+    ///
+    /// The caller has to ensure that `file` isn't modified or truncated while the returned
+    /// `Input` exists. Lines that were returned could change, and reading past the end of a
+    /// truncated file terminates the process with SIGBUS.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can't be mapped.
+    pub unsafe fn mapped(file: &File, terminator: u8) -> io::Result<Self> {
+        let len = file.metadata()?.len() as usize;
+        if len == 0 {
+            // mmap doesn't take an empty length
+            return Ok(Self::new(Box::new(file.try_clone()?), terminator));
+        }
+        Ok(Self::with_source(
+            Source::Mapped(unsafe { Mapping::new(file, len)? }),
+            terminator,
+        ))
+    }
+
+    fn with_source(source: Source, terminator: u8) -> Self {
+        let eof = matches!(source, Source::Mapped(_));
+        Self {
+            source,
+            terminator,
+            buf: Vec::new(),
+            pos: 0,
+            eof,
+            classified: false,
+            binary: false,
+            line: (0, 0),
+            line_number: 0,
+        }
+    }
+
+    /// Checks if binary data was found in the input read so far.
+    pub fn is_binary(&self) -> bool {
+        self.binary
+    }
+
+    /// Returns the number of the line last read, counting from 1.
+    pub fn line_number(&self) -> u64 {
+        self.line_number
+    }
+
+    /// Returns the line last read, without its terminator.
+    pub fn line(&self) -> &[u8] {
+        &self.data()[self.line.0..self.line.1]
+    }
+
+    /// Reads the next line, which `line` then returns.
+    ///
+    /// # Returns
+    ///
+    /// Returns `false` at the end of the input.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading fails.
+    pub fn next_line(&mut self) -> io::Result<bool> {
+        self.classify()?;
+        loop {
+            let rest = &self.data()[self.pos..];
+            let end = match memchr(self.terminator, rest) {
+                Some(i) => i,
+                None if !self.eof => {
+                    self.fill()?;
+                    continue;
+                }
+                None if rest.is_empty() => return Ok(false),
+                // The last line lacks a terminator
+                None => rest.len(),
+            };
+
+            if !self.binary && self.terminator != 0 && memchr(0, &rest[..end]).is_some() {
+                self.binary = true;
+            }
+            self.line = (self.pos, self.pos + end);
+            self.pos = (self.pos + end + 1).min(self.data().len());
+            self.line_number += 1;
+            return Ok(true);
+        }
+    }
+
+    /// Reads the next line that `matcher` may match, skipping over the lines before it. The line
+    /// still has to be checked with [Matcher::is_match], as the matcher can find lines that
+    /// don't match.
+    ///
+    /// # Returns
+    ///
+    /// Returns `false` at the end of the input.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading fails.
+    pub fn next_match(&mut self, matcher: &dyn Matcher) -> io::Result<bool> {
+        self.classify()?;
+        loop {
+            let rest = &self.data()[self.pos..];
+            // Only whole lines are searched, so a line is never split across reads
+            let lines = if self.eof {
+                rest.len()
+            } else {
+                memrchr(self.terminator, rest).map_or(0, |i| i + 1)
+            };
+            if lines == 0 {
+                if self.eof {
+                    return Ok(false);
+                }
+                self.fill()?;
+                continue;
+            }
+
+            let skipped = match matcher.find_candidate(&rest[..lines]) {
+                Some(start) => start,
+                None => lines,
+            };
+            self.skip(skipped);
+            if skipped < lines {
+                return self.next_line();
+            }
+        }
+    }
+
+    // Consumes `len` bytes of whole lines without returning them
+    fn skip(&mut self, len: usize) {
+        let skipped = &self.data()[self.pos..self.pos + len];
+        let lines = memchr_iter(self.terminator, skipped).count() as u64;
+        if !self.binary && self.terminator != 0 && memchr(0, skipped).is_some() {
+            self.binary = true;
+        }
+
+        self.line_number += lines;
+        self.pos += len;
+    }
+
+    fn data(&self) -> &[u8] {
+        match &self.source {
+            Source::Reader(_) => &self.buf,
+            Source::Mapped(mapping) => mapping.as_slice(),
+        }
+    }
+
+    fn classify(&mut self) -> io::Result<()> {
+        if !self.classified {
+            if !self.eof && self.buf.is_empty() {
+                self.fill()?;
+            }
+            let start = &self.data()[..self.data().len().min(CLASSIFY_SIZE)];
+            self.binary = self.terminator != 0 && memchr(0, start).is_some();
+            self.classified = true;
+        }
+        Ok(())
+    }
+
+    // Reads the next chunk of the input, after the part that isn't consumed yet
+    fn fill(&mut self) -> io::Result<()> {
+        let Source::Reader(reader) = &mut self.source else {
+            self.eof = true;
+            return Ok(());
+        };
+
+        self.buf.drain(..self.pos);
+        self.line = (0, 0);
+        self.pos = 0;
+
+        let len = self.buf.len();
+        self.buf.resize(len + CHUNK_SIZE, 0);
+        loop {
+            match reader.read(&mut self.buf[len..]) {
+                Ok(n) => {
+                    self.buf.truncate(len + n);
+                    self.eof = n == 0;
+                    return Ok(());
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => {
+                    self.buf.truncate(len);
+                    return Err(err);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,407 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+use aho_corasick::{AhoCorasick, AhoCorasickBuilder, Input, MatchKind};
+use libc::{
+    regcomp, regex_t, regexec, regfree, regmatch_t, REG_EXTENDED, REG_ICASE, REG_NEWLINE,
+    REG_NOTBOL, REG_STARTEND,
+};
+use memchr::{memchr, memrchr};
+use std::{
+    ffi::{CStr, CString},
+    ops::Range,
+};
+
+/// The syntax of the patterns, selected by `-E` and `-F`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Syntax {
+    /// Basic regular expressions, the default.
+    Basic,
+    /// Extended regular expressions (`-E`).
+    Extended,
+    /// Strings matched literally (`-F`).
+    Fixed,
+}
+
+/// Options that change how lines are matched, independent of the syntax.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MatchOptions {
+    /// `-i`: Match without regard to case.
+    pub ignore_case: bool,
+    /// `-x`: Only match the whole line.
+    pub line_regexp: bool,
+    /// `-z`: Lines are terminated by NUL bytes, and may contain newlines.
+    pub null_data: bool,
+}
+
+impl MatchOptions {
+    fn terminator(&self) -> u8 {
+        if self.null_data {
+            b'\0'
+        } else {
+            b'\n'
+        }
+    }
+}
+
+/// A matching engine for a set of patterns.
+pub trait Matcher {
+    /// Finds the leftmost match of any of the patterns in `line` that starts at or after `start`.
+    /// Of the matches starting at the same offset, the longest is returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `line` - the line without its terminator.
+    /// * `start` - the offset in `line` to start searching from. The search still sees the
+    ///   line before it, so it's not the start of the line for `^`.
+    ///
+    /// # Returns
+    ///
+    /// Returns the range of the match in `line`, or `None` if there is no match.
+    fn find_at(&self, line: &[u8], start: usize) -> Option<Range<usize>>;
+
+    /// Checks if any of the patterns matches `line`.
+    fn is_match(&self, line: &[u8]) -> bool {
+        self.find_at(line, 0).is_some()
+    }
+
+    /// Finds the first of `lines` that any of the patterns may match, so the lines before it can
+    /// be skipped. Matchers that can search many lines at once do so, and may then find a line
+    /// that the patterns don't match, as a match can span lines.
+    ///
+    /// # Arguments
+    ///
+    /// * `lines` - whole lines, each with its terminator, except possibly the last one.
+    ///
+    /// # Returns
+    ///
+    /// Returns the offset of the start of the line in `lines`, or `None` if none of the lines
+    /// match.
+    fn find_candidate(&self, lines: &[u8]) -> Option<usize>;
+}
+
+/// Finds the first of `lines` that `matcher` matches by trying each line in turn, for the
+/// matchers that can't search many lines at once.
+fn find_matching_line(matcher: &dyn Matcher, lines: &[u8], terminator: u8) -> Option<usize> {
+    let mut start = 0;
+    while start < lines.len() {
+        let end = memchr(terminator, &lines[start..]).map_or(lines.len(), |i| start + i);
+        if matcher.is_match(&lines[start..end]) {
+            return Some(start);
+        }
+        start = end + 1;
+    }
+    None
+}
+
+/// Finds the start of the line in `lines` that contains the byte at `offset`.
+fn line_start(lines: &[u8], offset: usize, terminator: u8) -> usize {
+    memrchr(terminator, &lines[..offset]).map_or(0, |i| i + 1)
+}
+
+/// An iterator over the non-empty matches in a line, which don't overlap.
+pub struct Matches<'a> {
+    matcher: &'a dyn Matcher,
+    line: &'a [u8],
+    start: usize,
+}
+
+impl<'a> Matches<'a> {
+    pub fn new(matcher: &'a dyn Matcher, line: &'a [u8]) -> Self {
+        Self {
+            matcher,
+            line,
+            start: 0,
+        }
+    }
+}
+
+impl Iterator for Matches<'_> {
+    type Item = Range<usize>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.start <= self.line.len() {
+            let m = self.matcher.find_at(self.line, self.start)?;
+            if m.is_empty() {
+                // Empty matches are skipped, as there is nothing to show of them
+                self.start = m.end + 1;
+                continue;
+            }
+            self.start = m.end;
+            return Some(m);
+        }
+        None
+    }
+}
+
+/// Creates the engine for `patterns` in the given syntax.
+///
+/// # Errors
+///
+/// Returns an error if one of the patterns is not a valid regular expression.
+pub fn new_matcher(
+    patterns: Vec<String>,
+    syntax: Syntax,
+    options: MatchOptions,
+) -> Result<Box<dyn Matcher>, String> {
+    match syntax {
+        Syntax::Fixed => Ok(Box::new(FixedMatcher::new(patterns, options))),
+        Syntax::Basic | Syntax::Extended => Ok(Box::new(RegexMatcher::new(
+            patterns,
+            syntax == Syntax::Extended,
+            options,
+        )?)),
+    }
+}
+
+/// Checks if the character encoding of the locale is UTF-8. In other locales,
+/// only ASCII letters are taken to have a case.
+fn utf8_locale() -> bool {
+    let codeset = unsafe { CStr::from_ptr(libc::nl_langinfo(libc::CODESET)) };
+    let codeset = codeset.to_bytes();
+    codeset.eq_ignore_ascii_case(b"UTF-8") || codeset.eq_ignore_ascii_case(b"UTF8")
+}
+
+/// Lowercases the characters of `line`, leaving the bytes that aren't part of
+/// a UTF-8 character as they are.
+fn lowercase(line: &[u8]) -> Vec<u8> {
+    let mut lowercased = Vec::with_capacity(line.len());
+    for chunk in line.utf8_chunks() {
+        lowercased.extend_from_slice(chunk.valid().to_lowercase().as_bytes());
+        lowercased.extend_from_slice(chunk.invalid());
+    }
+    lowercased
+}
+
+/// Matches all of the strings of `-F` at once with an Aho-Corasick automaton.
+struct FixedMatcher {
+    automaton: AhoCorasick,
+    // Set when case is ignored for patterns that aren't all ASCII in a UTF-8
+    // locale, which the automaton can't do by itself. Lines are then
+    // lowercased before matching.
+    lowercase: bool,
+    line_regexp: bool,
+    terminator: u8,
+}
+
+impl FixedMatcher {
+    fn new(patterns: Vec<String>, options: MatchOptions) -> Self {
+        let lowercase =
+            options.ignore_case && !patterns.iter().all(|p| p.is_ascii()) && utf8_locale();
+        let patterns: Vec<String> = if lowercase {
+            patterns.iter().map(|p| p.to_lowercase()).collect()
+        } else {
+            patterns
+        };
+
+        // The longest of the patterns starting at the leftmost position is
+        // found, so with `-x` it's the one that can span the whole line
+        let automaton = AhoCorasickBuilder::new()
+            .match_kind(MatchKind::LeftmostLongest)
+            .ascii_case_insensitive(options.ignore_case && !lowercase)
+            .build(patterns)
+            // Only fails if the automaton would be too large for its
+            // representation of state identifiers
+            .expect("too many fixed strings");
+
+        Self {
+            automaton,
+            lowercase,
+            line_regexp: options.line_regexp,
+            terminator: options.terminator(),
+        }
+    }
+}
+
+impl Matcher for FixedMatcher {
+    fn find_at(&self, line: &[u8], start: usize) -> Option<Range<usize>> {
+        if self.lowercase {
+            // The offsets in the lowercased line may not be those in `line`, so they're only
+            // reported for lines that lowercasing doesn't change the length of
+            let lowercased = lowercase(line);
+            let m = self.find_in(&lowercased, start)?;
+            return Some(if lowercased.len() == line.len() {
+                m
+            } else {
+                0..line.len()
+            });
+        }
+        self.find_in(line, start)
+    }
+
+    fn find_candidate(&self, lines: &[u8]) -> Option<usize> {
+        if self.lowercase {
+            return find_matching_line(self, lines, self.terminator);
+        }
+        // The patterns contain no terminators, so a match is always within a line. It only
+        // doesn't match the line with `-x`.
+        let m = self.automaton.find(lines)?;
+        Some(line_start(lines, m.start(), self.terminator))
+    }
+}
+
+impl FixedMatcher {
+    fn find_in(&self, line: &[u8], start: usize) -> Option<Range<usize>> {
+        if start > line.len() {
+            return None;
+        }
+        let m = self
+            .automaton
+            .find(Input::new(line).span(start..line.len()))?;
+        if self.line_regexp && (m.start() != 0 || m.end() != line.len()) {
+            return None;
+        }
+        Some(m.range())
+    }
+}
+
+/// Matches basic or extended regular expressions with `regcomp` and
+/// `regexec`, trying each of the patterns in turn.
+///
+/// Unless lines are terminated by NUL bytes, the regular expressions are
+/// compiled with `REG_NEWLINE`, which doesn't change how they match a single
+/// line, but lets them search many lines at once.
+struct RegexMatcher {
+    regexes: Vec<regex_t>,
+    null_data: bool,
+}
+
+impl RegexMatcher {
+    fn new(patterns: Vec<String>, extended: bool, options: MatchOptions) -> Result<Self, String> {
+        let mut cflags = 0;
+        if extended {
+            cflags |= REG_EXTENDED;
+        }
+        if options.ignore_case {
+            cflags |= REG_ICASE;
+        }
+        if !options.null_data {
+            cflags |= REG_NEWLINE;
+        }
+
+        // Compiled regexes are freed on drop, also if a later one fails
+        let mut matcher = Self {
+            regexes: Vec::new(),
+            null_data: options.null_data,
+        };
+        for mut pattern in patterns {
+            // macOS version of [regcomp](regcomp) from `libc`
+            // provides additional check for empty regex. In this case,
+            // an error [REG_EMPTY](https://developer.apple.com/library/archive/documentation/System/Conceptual/ManPages_iPhoneOS/man3/regcomp.3.html)
+            // will be returned.
+
+            // Therefore, an empty pattern is replaced with ".*".
+            #[cfg(target_os = "macos")]
+            {
+                pattern = if pattern.is_empty() {
+                    String::from(".*")
+                } else {
+                    pattern
+                };
+            }
+            pattern = if options.line_regexp {
+                format!("^{pattern}$")
+            } else {
+                pattern
+            };
+
+            let c_pattern = CString::new(pattern).map_err(|err| err.to_string())?;
+            let mut regex = unsafe { std::mem::zeroed::<regex_t>() };
+
+            let result = unsafe { regcomp(&mut regex, c_pattern.as_ptr(), cflags) };
+            if result != 0 {
+                return Err(format!(
+                    "Error compiling regex '{}'",
+                    c_pattern.to_string_lossy()
+                ));
+            }
+            matcher.regexes.push(regex);
+        }
+        Ok(matcher)
+    }
+}
+
+impl RegexMatcher {
+    fn exec(regex: &regex_t, line: &[u8], start: usize) -> Option<Range<usize>> {
+        Self::exec_until(regex, line, start, line.len())
+    }
+
+    // Only the input up to `end` is searched
+    fn exec_until(regex: &regex_t, line: &[u8], start: usize, end: usize) -> Option<Range<usize>> {
+        // With REG_STARTEND, the line ends where the first match says, rather than at a NUL,
+        // so it needs no terminator and can contain NULs
+        let mut pmatch = [regmatch_t {
+            rm_so: start as libc::regoff_t,
+            rm_eo: end as libc::regoff_t,
+        }];
+        let eflags = if start > 0 {
+            REG_STARTEND | REG_NOTBOL
+        } else {
+            REG_STARTEND
+        };
+        let result = unsafe {
+            regexec(
+                regex,
+                line.as_ptr() as *const libc::c_char,
+                1,
+                pmatch.as_mut_ptr(),
+                eflags,
+            )
+        };
+        (result == 0).then(|| pmatch[0].rm_so as usize..pmatch[0].rm_eo as usize)
+    }
+}
+
+impl Matcher for RegexMatcher {
+    fn find_at(&self, line: &[u8], start: usize) -> Option<Range<usize>> {
+        if start > line.len() {
+            return None;
+        }
+        self.regexes
+            .iter()
+            .filter_map(|regex| Self::exec(regex, line, start))
+            .min_by_key(|m| (m.start, usize::MAX - m.end))
+    }
+
+    // Any match will do, so the patterns after the first that matches aren't tried
+    fn is_match(&self, line: &[u8]) -> bool {
+        self.regexes
+            .iter()
+            .any(|regex| Self::exec(regex, line, 0).is_some())
+    }
+
+    fn find_candidate(&self, lines: &[u8]) -> Option<usize> {
+        // Without REG_NEWLINE, `^` and `$` only match at the ends of all the lines
+        if self.null_data {
+            return find_matching_line(self, lines, b'\0');
+        }
+
+        // A match can only span lines if a bracket expression matches a newline, so the line
+        // where it starts is taken to match. Each pattern after the first only needs to be
+        // searched for up to the end of the line found so far.
+        let mut candidate: Option<(usize, usize)> = None;
+        for regex in &self.regexes {
+            let end = candidate.map_or(lines.len(), |(_, end)| end);
+            if let Some(m) = Self::exec_until(regex, lines, 0, end) {
+                let start = line_start(lines, m.start, b'\n');
+                let end = memchr(b'\n', &lines[m.start..]).map_or(lines.len(), |i| m.start + i);
+                candidate = Some((start, end));
+            }
+        }
+        candidate.map(|(start, _)| start)
+    }
+}
+
+impl Drop for RegexMatcher {
+    fn drop(&mut self) {
+        for regex in &mut self.regexes {
+            unsafe { regfree(regex) }
+        }
+    }
+}
@@ -0,0 +1,16 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+mod color;
+mod input;
+mod matcher;
+
+pub use color::{ColorWhen, Colors};
+pub use input::{BinaryFiles, Input};
+pub use matcher::{new_matcher, MatchOptions, Matcher, Matches, Syntax};
@@ -21,10 +21,49 @@ struct Args {
     #[arg(short, default_value_t = 10, value_parser = clap::value_parser!(u64).range(1..))]
     n: u64,
 
+    /// The first <N> bytes of each input file shall be copied to standard output. <N> may
+    /// have a multiplier suffix: b for 512, K, M, G, T, P or E for powers of 1024, which
+    /// may be followed by iB, or followed by B for powers of 1000.
+    #[arg(short = 'c', value_parser = parse_size, conflicts_with = "n")]
+    bytes: Option<u64>,
+
     /// Files to read as input.
     files: Vec<PathBuf>,
 }
 
+/// Parses a number of bytes with an optional multiplier suffix; `k` and `m` are the
+/// same as `K` and `M`.
+fn parse_size(s: &str) -> Result<u64, String> {
+    let invalid = || format!("invalid number of bytes: '{}'", s);
+    let digits = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, suffix) = s.split_at(digits);
+    let number: u64 = number.parse().map_err(|_| invalid())?;
+
+    let multiplier = if suffix.is_empty() {
+        1
+    } else if suffix == "b" {
+        512
+    } else {
+        let mut chars = suffix.chars();
+        let exponent = match chars.next() {
+            Some('k' | 'K') => 1,
+            Some('m' | 'M') => 2,
+            Some('G') => 3,
+            Some('T') => 4,
+            Some('P') => 5,
+            Some('E') => 6,
+            _ => return Err(invalid()),
+        };
+        let base: u64 = match chars.as_str() {
+            "" | "iB" => 1024,
+            "B" => 1000,
+            _ => return Err(invalid()),
+        };
+        base.pow(exponent)
+    };
+    number.checked_mul(multiplier).ok_or_else(invalid)
+}
+
 fn head_file(args: &Args, pathname: &PathBuf, first: bool, want_header: bool) -> io::Result<()> {
     // print file header
     if want_header {
@@ -38,6 +77,11 @@ fn head_file(args: &Args, pathname: &PathBuf, first: bool, want_header: bool) ->
     // open file, or stdin
     let mut file = plib::io::input_stream(pathname, false)?;
 
+    if let Some(bytes) = args.bytes {
+        io::copy(&mut file.take(bytes), &mut io::stdout())?;
+        return Ok(());
+    }
+
     let mut raw_buffer = [0; plib::BUFSZ];
     let mut nl = 0;
 
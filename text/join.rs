@@ -10,42 +10,46 @@
 use clap::Parser;
 use gettextrs::{bind_textdomain_codeset, setlocale, textdomain, LocaleCategory};
 use plib::PROJECT_NAME;
-use std::collections::HashMap;
+use std::cmp::Ordering;
 use std::fs::File;
-use std::io::{self, BufRead, BufReader};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
 use std::path::PathBuf;
 
 /// join - relational database operator
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
-    /// Additional lines to include when there are no matches
-    #[arg(short, default_value_t = 0)]
-    additional: u8,
+    /// Additional lines to include when there are no matches: the unpairable lines of file_number, which can be given for both files
+    #[arg(short, value_parser = clap::value_parser!(u8).range(1..=2))]
+    additional: Vec<u8>,
 
     /// Replace empty output fields with the specified string
     #[arg(short)]
     empty: Option<String>,
 
-    /// Output fields in specified order
-    #[arg(short, value_delimiter = ',')]
-    order: Option<Vec<String>>,
+    /// Output fields in specified order: a list of file_number.field, or 0 for the join field, separated by commas or blanks
+    #[arg(short)]
+    order: Vec<String>,
 
     /// Field separator character
-    #[arg(short = 't', default_value_t = ' ')]
-    separator: char,
+    #[arg(short = 't')]
+    separator: Option<char>,
 
-    /// Output only unpairable lines from file_number
-    #[arg(short = 'v', default_value_t = 0)]
-    unpairable: u8,
+    /// Output only unpairable lines from file_number, which can be given for both files
+    #[arg(short = 'v', value_parser = clap::value_parser!(u8).range(1..=2))]
+    unpairable: Vec<u8>,
 
     /// Join on the specified field of file 1
-    #[arg(short = '1', default_value_t = 1)]
-    field1: usize,
+    #[arg(short = '1', default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..))]
+    field1: u64,
 
     /// Join on the specified field of file 2
-    #[arg(short = '2', default_value_t = 1)]
-    field2: usize,
+    #[arg(short = '2', default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..))]
+    field2: u64,
+
+    /// Ignore differences in case when comparing the join fields
+    #[arg(short = 'i', long = "ignore-case")]
+    ignore_case: bool,
 
     /// File 1
     file1: PathBuf,
@@ -54,126 +58,263 @@ struct Args {
     file2: PathBuf,
 }
 
-fn parse_fields(line: &str, sep: char) -> Vec<String> {
-    line.split(sep).map(|s| s.to_string()).collect()
+/// A field of the output given with `-o`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OutputField {
+    /// `0`: the join field.
+    Key,
+    /// `file_number.field`, counting the files and fields from 0.
+    Field(usize, usize),
 }
 
-fn process_files2(
-    file1_path: &PathBuf,
-    file2_path: &PathBuf,
-    sep: char,
-    field1: usize,
-    field2: usize,
-    a: u8,
-    e: Option<String>,
-    o: Option<Vec<String>>,
-    v: u8,
-) -> Result<(), Box<dyn std::error::Error>> {
-    // code to support stdin
-    let stdin = io::stdin();
-    let file1: Box<dyn BufRead> = if file1_path.to_str() == Some("-") {
-        Box::new(stdin.lock())
-    } else {
-        Box::new(BufReader::new(File::open(file1_path)?))
-    };
+/// Parses the lists of output fields given with `-o`.
+///
+/// # Errors
+///
+/// Returns an error if a field isn't `0` or `file_number.field`.
+fn parse_output_fields(lists: &[String]) -> Result<Vec<OutputField>, String> {
+    let mut fields = Vec::new();
+    for spec in lists
+        .iter()
+        .flat_map(|list| list.split([',', ' ', '\t']))
+        .filter(|spec| !spec.is_empty())
+    {
+        let invalid = || format!("invalid field specifier: '{}'", spec);
+        if spec == "0" {
+            fields.push(OutputField::Key);
+            continue;
+        }
+        let (file, field) = spec.split_once('.').ok_or_else(invalid)?;
+        let file = match file {
+            "1" => 0,
+            "2" => 1,
+            _ => return Err(invalid()),
+        };
+        let field: usize = field.parse().map_err(|_| invalid())?;
+        if field == 0 {
+            return Err(invalid());
+        }
+        fields.push(OutputField::Field(file, field - 1));
+    }
+    Ok(fields)
+}
 
-    let mut matched_keys = HashMap::new();
-    for line1 in file1.lines() {
-        let line1 = line1?;
-        let fields1 = parse_fields(&line1, sep);
-        let key1 = &fields1[field1 - 1];
+/// Splits a line into fields. Without a separator, fields are separated by runs of blanks,
+/// and leading blanks are ignored.
+fn parse_fields(line: &str, sep: Option<char>) -> Vec<String> {
+    match sep {
+        Some(sep) => line.split(sep).map(|s| s.to_string()).collect(),
+        None => line
+            .split([' ', '\t'])
+            .filter(|s| !s.is_empty())
+            .map(|s| s.to_string())
+            .collect(),
+    }
+}
 
-        let mut found_match = false;
+/// One of the input files, read a group of lines with the same join field at a time.
+struct Input {
+    reader: Box<dyn BufRead>,
+    // the join field, counting from 0
+    field: usize,
+    // the first line of the next group
+    next: Option<Vec<String>>,
+}
 
-        let file2: Box<dyn BufRead> = if file2_path.to_str() == Some("-") {
-            Box::new(stdin.lock())
+impl Input {
+    fn open(path: &PathBuf, field: u64) -> io::Result<Self> {
+        let reader: Box<dyn BufRead> = if path.to_str() == Some("-") {
+            Box::new(io::stdin().lock())
         } else {
-            Box::new(BufReader::new(File::open(file2_path)?))
+            Box::new(BufReader::new(File::open(path)?))
         };
-        for line2 in file2.lines() {
-            let line = line2?;
-            let fields2 = parse_fields(&line, sep);
-            let key2 = &fields2[field2 - 1];
-
-            if key1 == key2 {
-                found_match = true;
-                matched_keys.insert(key2.clone(), true);
-
-                if let Some(order) = &o {
-                    let mut res: Vec<String> = Vec::new();
-                    for num in order {
-                        let f_num: Vec<&str> = num.split('.').collect();
-                        if f_num[0] == "1" {
-                            if fields1.len() <= f_num[1].parse::<usize>()? - 1 {
-                                if let Some(e) = &e {
-                                    res.push(e.to_string());
-                                }
-                            } else {
-                                res.push(fields1[f_num[1].parse::<usize>()? - 1].clone());
-                            }
-                        } else if f_num[0] == "2" {
-                            if fields2.len() <= f_num[1].parse::<usize>()? - 1 {
-                                if let Some(e) = &e {
-                                    res.push(e.to_string());
-                                }
-                            } else {
-                                res.push(fields2[f_num[1].parse::<usize>()? - 1].clone());
-                            }
-                        }
-                    }
-                    if v == 0 {
-                        println!("{}", res.join(" "));
-                    }
-                } else {
-                    if v == 0 {
-                        println!("{} {}", fields1.join(" "), fields2[1..].join(" "));
-                    }
+        Ok(Self {
+            reader,
+            field: field as usize - 1,
+            next: None,
+        })
+    }
+
+    fn key<'a>(&self, fields: &'a [String]) -> &'a str {
+        fields.get(self.field).map_or("", String::as_str)
+    }
+
+    fn read_line(&mut self, sep: Option<char>) -> io::Result<Option<Vec<String>>> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        if line.ends_with('\n') {
+            line.pop();
+        }
+        Ok(Some(parse_fields(&line, sep)))
+    }
+
+    /// Reads the next group of lines with the same join field, which is empty at the end of
+    /// the input.
+    fn read_group(&mut self, sep: Option<char>, ignore_case: bool) -> io::Result<Vec<Vec<String>>> {
+        let first = match self.next.take() {
+            Some(first) => first,
+            None => match self.read_line(sep)? {
+                Some(first) => first,
+                None => return Ok(Vec::new()),
+            },
+        };
+
+        let mut group = vec![first];
+        while let Some(line) = self.read_line(sep)? {
+            if compare_keys(self.key(&group[0]), self.key(&line), ignore_case) != Ordering::Equal {
+                self.next = Some(line);
+                break;
+            }
+            group.push(line);
+        }
+        Ok(group)
+    }
+}
+
+fn compare_keys(key1: &str, key2: &str, ignore_case: bool) -> Ordering {
+    if ignore_case {
+        key1.chars()
+            .flat_map(char::to_lowercase)
+            .cmp(key2.chars().flat_map(char::to_lowercase))
+    } else {
+        key1.cmp(key2)
+    }
+}
+
+/// Writes the joined lines in the format of the options.
+struct Output<'a> {
+    writer: BufWriter<io::StdoutLock<'static>>,
+    sep: char,
+    empty: Option<&'a str>,
+    fields: &'a [OutputField],
+}
+
+impl Output<'_> {
+    /// Writes a line made of `line1` and `line2`, either of which is missing for an
+    /// unpairable line. `key` is the join field of the line.
+    fn write(
+        &mut self,
+        inputs: &[Input; 2],
+        key: &str,
+        line1: Option<&[String]>,
+        line2: Option<&[String]>,
+    ) -> io::Result<()> {
+        let mut out: Vec<&str> = Vec::new();
+        if self.fields.is_empty() {
+            // The join field, then the other fields of each line
+            out.push(key);
+            for (input, line) in inputs.iter().zip([line1, line2]) {
+                if let Some(line) = line {
+                    out.extend(
+                        line.iter()
+                            .enumerate()
+                            .filter(|(i, _)| *i != input.field)
+                            .map(|(_, field)| field.as_str()),
+                    );
                 }
             }
+        } else {
+            let lines = [line1, line2];
+            for field in self.fields {
+                out.push(match *field {
+                    OutputField::Key => key,
+                    OutputField::Field(file, i) => lines[file]
+                        .and_then(|line| line.get(i))
+                        .map_or("", String::as_str),
+                });
+            }
         }
 
-        if !found_match && a == 1 {
-            println!("{}", fields1.join(" "));
+        for (i, field) in out.iter().enumerate() {
+            if i > 0 {
+                write!(self.writer, "{}", self.sep)?;
+            }
+            match self.empty {
+                Some(empty) if field.is_empty() => self.writer.write_all(empty.as_bytes())?,
+                _ => self.writer.write_all(field.as_bytes())?,
+            }
         }
+        self.writer.write_all(b"\n")
     }
+}
+
+fn process_files2(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    if args.file1.to_str() == Some("-") && args.file2.to_str() == Some("-") {
+        return Err("both files cannot be standard input".into());
+    }
+
+    let output_fields = parse_output_fields(&args.order)?;
+    let mut inputs = [
+        Input::open(&args.file1, args.field1)?,
+        Input::open(&args.file2, args.field2)?,
+    ];
 
-    let file1 = BufReader::new(File::open(file1_path)?);
-    let file2 = BufReader::new(File::open(file2_path)?);
-    if v == 1 {
-        for line1 in file1.lines() {
-            let line1 = line1?;
-            let fields1 = parse_fields(&line1, sep);
-            let key1 = &fields1[field1 - 1];
-            if !matched_keys.contains_key(key1) {
-                println!("{}", fields1.join(" "));
+    // -a adds the unpairable lines to the joined ones, -v replaces them
+    let print_pairs = args.unpairable.is_empty();
+    let print_unpairable =
+        [1, 2].map(|file: u8| args.unpairable.contains(&file) || args.additional.contains(&file));
+
+    let mut output = Output {
+        writer: BufWriter::new(io::stdout().lock()),
+        sep: args.separator.unwrap_or(' '),
+        empty: args.empty.as_deref(),
+        fields: &output_fields,
+    };
+
+    let sep = args.separator;
+    let mut group1 = inputs[0].read_group(sep, args.ignore_case)?;
+    let mut group2 = inputs[1].read_group(sep, args.ignore_case)?;
+    while !group1.is_empty() || !group2.is_empty() {
+        let order = match (group1.first(), group2.first()) {
+            (Some(line1), Some(line2)) => {
+                compare_keys(inputs[0].key(line1), inputs[1].key(line2), args.ignore_case)
             }
-        }
-    } else if v == 2 {
-        for line2 in file2.lines() {
-            let line2 = line2?;
-            let fields2 = parse_fields(&line2, sep);
-            let key2 = &fields2[field2 - 1];
-            if !matched_keys.contains_key(key2) {
-                println!("{}", fields2.join(" "));
+            (Some(_), None) => Ordering::Less,
+            _ => Ordering::Greater,
+        };
+
+        match order {
+            Ordering::Less => {
+                if print_unpairable[0] {
+                    for line1 in &group1 {
+                        output.write(&inputs, inputs[0].key(line1), Some(line1), None)?;
+                    }
+                }
+                group1 = inputs[0].read_group(sep, args.ignore_case)?;
+            }
+            Ordering::Greater => {
+                if print_unpairable[1] {
+                    for line2 in &group2 {
+                        output.write(&inputs, inputs[1].key(line2), None, Some(line2))?;
+                    }
+                }
+                group2 = inputs[1].read_group(sep, args.ignore_case)?;
+            }
+            Ordering::Equal => {
+                // Each line of file 1 is joined with each line of file 2 that has the
+                // same join field
+                if print_pairs {
+                    for line1 in &group1 {
+                        for line2 in &group2 {
+                            let key = inputs[0].key(line1);
+                            output.write(&inputs, key, Some(line1), Some(line2))?;
+                        }
+                    }
+                }
+                group1 = inputs[0].read_group(sep, args.ignore_case)?;
+                group2 = inputs[1].read_group(sep, args.ignore_case)?;
             }
         }
     }
+    output.writer.flush()?;
 
     Ok(())
 }
 
 fn join(args: Args) -> Result<(), Box<dyn std::error::Error>> {
-    process_files2(
-        &args.file1,
-        &args.file2,
-        args.separator,
-        args.field1,
-        args.field2,
-        args.additional,
-        args.empty,
-        args.order,
-        args.unpairable,
-    )?;
+    process_files2(&args)?;
 
     Ok(())
 }
@@ -189,7 +330,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     if let Err(err) = join(args) {
         exit_code = 1;
-        eprint!("{}", err);
+        eprintln!("{}", err);
     }
 
     std::process::exit(exit_code)
@@ -9,13 +9,16 @@
 
 use clap::{Parser, ValueEnum};
 use gettextrs::{bind_textdomain_codeset, setlocale, textdomain, LocaleCategory};
+use libc::{regcomp, regex_t, regexec, regfree, REG_NOSUB};
 use plib::PROJECT_NAME;
-use regex::Regex;
+use std::ffi::CString;
 use std::fs;
-use std::io::{self, BufRead, Read};
+use std::io::{self, BufRead, BufWriter, Read, Write};
 use std::path::PathBuf;
 use std::process::ExitCode;
+use std::ptr;
 use std::str::FromStr;
+use std::sync::Arc;
 
 /// nl - line numbering filter
 #[derive(Parser, Debug)]
@@ -53,7 +56,7 @@ struct Args {
     header_numbering: LineNumberingStyle,
 
     /// Specify the increment value used to number logical page lines.
-    #[arg(short = 'i', long, default_value_t = 1)]
+    #[arg(short = 'i', long, default_value_t = 1, allow_hyphen_values = true)]
     line_increment: i64,
 
     /// Specify the number of blank lines to be considered as one. For example,
@@ -79,7 +82,7 @@ struct Args {
     number_separator: String,
 
     /// Specify the initial value used to number logical page lines.
-    #[arg(short = 'v', long, default_value_t = 1)]
+    #[arg(short = 'v', long, default_value_t = 1, allow_hyphen_values = true)]
     starting_line_number: i64,
 
     /// Specify the number of characters to be used for the line number.
@@ -93,12 +96,66 @@ struct Args {
     file: Option<PathBuf>,
 }
 
+/// A basic regular expression, compiled with `regcomp`.
+struct Bre {
+    pattern: String,
+    regex: regex_t,
+}
+
+// `regexec` doesn't change the compiled expression, which can be used from any thread
+unsafe impl Send for Bre {}
+unsafe impl Sync for Bre {}
+
+impl Bre {
+    fn new(pattern: &str) -> Result<Self, String> {
+        let invalid = || format!("invalid regular expression: {pattern}");
+
+        // The `regcomp` of macOS fails with REG_EMPTY for an empty expression, which
+        // matches any line
+        let c_pattern = if pattern.is_empty() {
+            CString::new(".*")
+        } else {
+            CString::new(pattern)
+        }
+        .map_err(|_| invalid())?;
+
+        let mut regex = unsafe { std::mem::zeroed::<regex_t>() };
+        if unsafe { regcomp(&mut regex, c_pattern.as_ptr(), REG_NOSUB) } != 0 {
+            return Err(invalid());
+        }
+        Ok(Self {
+            pattern: pattern.to_string(),
+            regex,
+        })
+    }
+
+    /// Whether the line, without its newline, matches. A line with a null byte is
+    /// matched up to it.
+    fn is_match(&self, line: &[u8]) -> bool {
+        let end = line.iter().position(|&b| b == 0).unwrap_or(line.len());
+        let c_line = CString::new(&line[..end]).unwrap();
+        unsafe { regexec(&self.regex, c_line.as_ptr(), 0, ptr::null_mut(), 0) == 0 }
+    }
+}
+
+impl Drop for Bre {
+    fn drop(&mut self) {
+        unsafe { regfree(&mut self.regex) };
+    }
+}
+
+impl std::fmt::Debug for Bre {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Bre").field(&self.pattern).finish()
+    }
+}
+
 #[derive(Debug, Clone)]
 enum LineNumberingStyle {
     All,
     NonEmpty,
     None,
-    Regex(Regex),
+    Regex(Arc<Bre>),
 }
 
 impl FromStr for LineNumberingStyle {
@@ -111,11 +168,7 @@ impl FromStr for LineNumberingStyle {
             "n" => Ok(LineNumberingStyle::None),
             s => {
                 if let Some(re) = s.strip_prefix('p') {
-                    if let Ok(regexp) = Regex::new(re) {
-                        Ok(LineNumberingStyle::Regex(regexp))
-                    } else {
-                        Err(format!("invalid regular expression: {re}"))
-                    }
+                    Ok(LineNumberingStyle::Regex(Arc::new(Bre::new(re)?)))
                 } else {
                     Err(format!("invalid variant: {s}"))
                 }
@@ -131,7 +184,7 @@ impl std::fmt::Display for LineNumberingStyle {
             LineNumberingStyle::NonEmpty => write!(f, "t"),
             LineNumberingStyle::None => write!(f, "n"),
             LineNumberingStyle::Regex(re) => {
-                write!(f, "p{}", re)
+                write!(f, "p{}", re.pattern)
             }
         }
     }
@@ -154,6 +207,94 @@ impl std::fmt::Display for NumberFormat {
     }
 }
 
+/// The sections of a logical page, each with its own numbering style.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Section {
+    Header,
+    Body,
+    Footer,
+}
+
+/// Numbers the lines in the format of the options.
+struct LineNumberer<'a> {
+    args: &'a Args,
+    line_number: i64,
+    overflowed: bool,
+    // the adjacent empty lines since the last numbered line, with -l
+    blank_lines: i64,
+}
+
+impl LineNumberer<'_> {
+    /// Whether the line is numbered in the numbering style.
+    fn is_numbered(&mut self, style: &LineNumberingStyle, line: &[u8]) -> bool {
+        match style {
+            LineNumberingStyle::All => {
+                // Only the last of `-l` adjacent empty lines is numbered
+                if !line.is_empty() {
+                    self.blank_lines = 0;
+                    return true;
+                }
+                self.blank_lines += 1;
+                if self.blank_lines == self.args.join_blank_lines {
+                    self.blank_lines = 0;
+                    true
+                } else {
+                    false
+                }
+            }
+            LineNumberingStyle::NonEmpty => !line.is_empty(),
+            LineNumberingStyle::None => false,
+            LineNumberingStyle::Regex(re) => re.is_match(line),
+        }
+    }
+
+    /// Writes the number of the next line, and the separator.
+    fn write_number(&mut self, out: &mut impl Write) -> io::Result<()> {
+        if self.overflowed {
+            return Err(io::Error::other("line number overflow"));
+        }
+
+        let n = self.line_number;
+        let width = self.args.number_width as usize;
+        let sep = &self.args.number_separator;
+        match self.args.number_format {
+            NumberFormat::Ln => write!(out, "{:<width$}{}", n, sep)?,
+            NumberFormat::Rn => write!(out, "{:>width$}{}", n, sep)?,
+            // The sign comes before the zeros
+            NumberFormat::Rz if n < 0 => write!(
+                out,
+                "-{:0>width$}{}",
+                n.unsigned_abs(),
+                sep,
+                width = width.saturating_sub(1)
+            )?,
+            NumberFormat::Rz => write!(out, "{:0>width$}{}", n, sep)?,
+        }
+
+        match n.checked_add(self.args.line_increment) {
+            Some(next) => self.line_number = next,
+            None => self.overflowed = true,
+        }
+        Ok(())
+    }
+
+    /// Writes the blanks in place of the number and the separator of a line that isn't
+    /// numbered.
+    fn write_spacer(&self, out: &mut impl Write) -> io::Result<()> {
+        let width = self.args.number_width as usize + self.args.number_separator.len();
+        write!(out, "{:width$}", "")
+    }
+
+    /// Starts numbering a section, from the starting number unless `-p` is given.
+    fn start_section(&mut self) {
+        self.blank_lines = 0;
+        if !self.args.no_renumber {
+            self.line_number = self.args.starting_line_number;
+            self.overflowed = false;
+        }
+    }
+}
+
 fn nl_main(args: &Args) -> io::Result<()> {
     let readable: Box<dyn Read> = if let Some(path) = &args.file {
         if path.as_os_str() == "-" {
@@ -165,171 +306,103 @@ fn nl_main(args: &Args) -> io::Result<()> {
         Box::new(io::stdin().lock())
     };
     let mut reader = io::BufReader::new(readable);
+    let mut out = BufWriter::new(io::stdout().lock());
+
+    // A line made of the delimiter three times starts the header of a logical page,
+    // twice the body and once the footer. An empty delimiter doesn't start any section.
+    let delimiter = args.section_delimiter.as_bytes();
+    let section_of = |line: &[u8]| -> Option<Section> {
+        if delimiter.is_empty() || !line.len().is_multiple_of(delimiter.len()) {
+            return None;
+        }
+        if !line.chunks(delimiter.len()).all(|chunk| chunk == delimiter) {
+            return None;
+        }
+        match line.len() / delimiter.len() {
+            3 => Some(Section::Header),
+            2 => Some(Section::Body),
+            1 => Some(Section::Footer),
+            _ => None,
+        }
+    };
 
-    let delimiter_header: String = (0..3).map(|_| args.section_delimiter.as_str()).collect();
-    let delimiter_body: String = (0..2).map(|_| args.section_delimiter.as_str()).collect();
-    let delimiter_footer: String = args.section_delimiter.clone();
-
-    let mut line_buffer = String::new();
-    let mut line_number = args.starting_line_number;
-    let mut line_number_overflowed = false;
-    let mut current_numbering_style = &args.body_numbering;
-    let mut consecutive_blank_lines = 0;
-
-    // Need to pass `line_number` and `line_number_overflowed` as arguments since
-    // they are also being mutated outside the closure.
-    let print_line_number =
-        |line_number: &mut i64, line_number_overflowed: &mut bool| -> io::Result<()> {
-            if *line_number_overflowed {
-                return Err(io::Error::other("line number overflowed"));
-            }
-
-            match args.number_format {
-                NumberFormat::Ln => print!(
-                    "{:<width$}{}",
-                    line_number,
-                    args.number_separator,
-                    width = args.number_width as usize
-                ),
-                NumberFormat::Rn => print!(
-                    "{:>width$}{}",
-                    line_number,
-                    args.number_separator,
-                    width = args.number_width as usize
-                ),
-                NumberFormat::Rz => print!(
-                    "{:0>width$}{}",
-                    line_number,
-                    args.number_separator,
-                    width = args.number_width as usize
-                ),
-            }
-            if let Some(sum) = line_number.checked_add(args.line_increment) {
-                *line_number = sum;
-            } else {
-                *line_number_overflowed = true;
-            }
-
-            Ok(())
-        };
-
-    let print_spacer = || {
-        print!(
-            "{:width$}",
-            "",
-            width = args.number_separator.len() + args.number_width as usize
-        );
+    let mut numberer = LineNumberer {
+        args,
+        line_number: args.starting_line_number,
+        overflowed: false,
+        blank_lines: 0,
     };
+    let mut section = Section::Body;
+    let mut line = Vec::new();
 
     loop {
-        line_buffer.clear();
-
-        if let Ok(read_bytes) = reader.read_line(&mut line_buffer) {
-            // EOF
-            if read_bytes == 0 {
-                break;
-            }
-
-            // Removing the newline makes for easier checks but it has to be
-            // added back later
-            if line_buffer.ends_with('\n') {
-                line_buffer.pop();
-            }
-
-            // If we are in a header, body or footer
-            let mut non_text = false;
-
-            if line_buffer == delimiter_header {
-                non_text = true;
-                current_numbering_style = &args.header_numbering;
-            } else if line_buffer == delimiter_body {
-                non_text = true;
-                current_numbering_style = &args.body_numbering;
-            } else if line_buffer == delimiter_footer {
-                non_text = true;
-                current_numbering_style = &args.footer_numbering;
-            } else {
-                match current_numbering_style {
-                    LineNumberingStyle::All => {
-                        if args.join_blank_lines > 1 {
-                            if !line_buffer.is_empty() {
-                                print_line_number(&mut line_number, &mut line_number_overflowed)?;
-                                consecutive_blank_lines = 0;
-                            } else {
-                                consecutive_blank_lines += 1;
-                                if consecutive_blank_lines == args.join_blank_lines {
-                                    print_line_number(
-                                        &mut line_number,
-                                        &mut line_number_overflowed,
-                                    )?;
-                                    consecutive_blank_lines = 0;
-                                } else {
-                                    print_spacer();
-                                }
-                            }
-                        } else {
-                            print_line_number(&mut line_number, &mut line_number_overflowed)?;
-                        }
-                    }
-                    LineNumberingStyle::NonEmpty => {
-                        if !line_buffer.is_empty() {
-                            print_line_number(&mut line_number, &mut line_number_overflowed)?;
-                        } else {
-                            print_spacer();
-                        }
-                    }
-                    LineNumberingStyle::None => print_spacer(),
-                    LineNumberingStyle::Regex(regexp) => {
-                        if regexp.is_match(&line_buffer) {
-                            print_line_number(&mut line_number, &mut line_number_overflowed)?;
-                        } else {
-                            print_spacer();
-                        }
-                    }
-                }
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+        // Reference `nl` unconditionally adds a newline even on files not ending on a
+        // newline
+        if line.last() == Some(&b'\n') {
+            line.pop();
+        }
 
-                // Reference `nl` unconditionally adds a newline even on files
-                // not ending on a newline
-                line_buffer.push('\n');
-                print!("{}", &line_buffer);
-            }
+        // A delimiter line is output as an empty line
+        if let Some(next) = section_of(&line) {
+            section = next;
+            numberer.start_section();
+            out.write_all(b"\n")?;
+            continue;
+        }
 
-            if non_text {
-                if !args.no_renumber {
-                    line_number = args.starting_line_number;
-                    line_number_overflowed = false;
-                }
-                println!();
-            }
+        let style = match section {
+            Section::Header => &args.header_numbering,
+            Section::Body => &args.body_numbering,
+            Section::Footer => &args.footer_numbering,
+        };
+        if numberer.is_numbered(style, &line) {
+            numberer.write_number(&mut out)?;
         } else {
-            break;
+            numberer.write_spacer(&mut out)?;
         }
+        out.write_all(&line)?;
+        out.write_all(b"\n")?;
     }
 
-    Ok(())
+    out.flush()
 }
 
 fn main() -> ExitCode {
     let mut args = Args::parse();
 
-    match args.section_delimiter.len() {
+    match args.section_delimiter.chars().count() {
         1 => {
             args.section_delimiter.push(':');
         }
-        2 => (),
+        0 | 2 => (),
         _ => {
             // Delimiter should be at most 2 characters.
+            eprintln!(
+                "nl: invalid section delimiter: '{}'",
+                args.section_delimiter
+            );
             return ExitCode::from(1);
         }
     }
 
     // Initialize translation system
     setlocale(LocaleCategory::LcAll, "");
+    // The regular expressions are matched in the C library's locale
+    unsafe {
+        libc::setlocale(libc::LC_ALL, c"".as_ptr());
+    }
     textdomain(PROJECT_NAME).unwrap();
     bind_textdomain_codeset(PROJECT_NAME, "UTF-8").unwrap();
 
     match nl_main(&args) {
         Ok(_) => ExitCode::from(0),
-        Err(_) => ExitCode::from(1),
+        Err(err) => {
+            eprintln!("nl: {}", err);
+            ExitCode::from(1)
+        }
     }
 }
@@ -7,8 +7,6 @@
 // SPDX-License-Identifier: MIT
 //
 // TODO:
-// - stdin ("-")
-// - fix:  empty-string delimiters \0
 // - improve:  don't open all files at once in --serial mode
 //
 
@@ -16,7 +14,7 @@ use clap::Parser;
 use gettextrs::{bind_textdomain_codeset, setlocale, textdomain, LocaleCategory};
 use plib::PROJECT_NAME;
 use std::fs::{self, File};
-use std::io::{self, BufRead, BufReader, Write};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
 
 /// paste - merge corresponding or subsequent lines of files
 #[derive(Parser, Debug)]
@@ -34,98 +32,141 @@ struct Args {
     files: Vec<String>,
 }
 
+/// Where the lines of an input come from. Every `-` operand reads from the same
+/// standard input, so that `paste - -` takes its lines in turn.
+enum Source {
+    Stdin,
+    File(BufReader<File>),
+}
+
 struct PasteFile {
     filename: String,
-    rdr: BufReader<File>,
+    src: Source,
     eof: bool,
     last: bool,
 }
 
+impl PasteFile {
+    /// Reads the next line into `buffer` without its line terminator, returning `false` at
+    /// the end of the input.
+    fn read_line(&mut self, stdin: &mut io::StdinLock, buffer: &mut Vec<u8>) -> io::Result<bool> {
+        buffer.clear();
+        let n_read = match &mut self.src {
+            Source::Stdin => stdin.read_until(b'\n', buffer),
+            Source::File(rdr) => rdr.read_until(b'\n', buffer),
+        }
+        .inspect_err(|e| eprintln!("{}: {}", self.filename, e))?;
+
+        if buffer.last() == Some(&b'\n') {
+            buffer.pop();
+        }
+        Ok(n_read > 0)
+    }
+}
+
 struct PasteInfo {
     inputs: Vec<PasteFile>,
+    stdin: io::StdinLock<'static>,
 }
 
 impl PasteInfo {
     fn new() -> PasteInfo {
-        PasteInfo { inputs: Vec::new() }
+        PasteInfo {
+            inputs: Vec::new(),
+            stdin: io::stdin().lock(),
+        }
     }
 }
 
+/// The delimiter list, each of which is a character or, for `\0`, empty.
 struct DelimInfo {
-    cur_delim: usize,
-    delims: String,
+    delims: Vec<String>,
 }
 
 impl DelimInfo {
     fn new() -> DelimInfo {
         DelimInfo {
-            cur_delim: 0,
-            delims: String::from("\t"),
+            delims: vec![String::from("\t")],
         }
     }
 
-    fn delim(&mut self) -> char {
-        let ch = self.delims.chars().nth(self.cur_delim).unwrap();
-
-        self.advance();
-
-        ch
-    }
-
-    fn advance(&mut self) {
-        if self.delims.len() > 1 {
-            self.cur_delim = self.cur_delim + 1;
-            if self.cur_delim >= self.delims.len() {
-                self.cur_delim = 0;
-            }
-        }
+    /// Returns the delimiter used after column `n`, cycling through the list.
+    fn delim(&self, n: usize) -> &[u8] {
+        self.delims[n % self.delims.len()].as_bytes()
     }
 }
 
-fn xlat_delim_str(s: &str) -> String {
-    let mut output = String::with_capacity(s.len() + 10);
+/// Translates the delimiter list given with `-d`, with the escapes `\n`, `\t`, `\\` and
+/// `\0` for an empty delimiter.
+///
+/// # Errors
+///
+/// Returns an error if the list ends with an unescaped backslash.
+fn xlat_delim_str(s: &str) -> Result<Vec<String>, String> {
+    let mut output = Vec::with_capacity(s.len());
 
     let mut in_escape = false;
     for ch in s.chars() {
         if in_escape {
-            let out_ch = match ch {
-                'n' => '\n',
-                't' => '\t',
-                '0' => '\0',
-                _ => ch,
+            let delim = match ch {
+                'n' => String::from("\n"),
+                't' => String::from("\t"),
+                '0' => String::new(),
+                _ => String::from(ch),
             };
 
-            output.push(out_ch);
+            output.push(delim);
             in_escape = false;
         } else if ch == '\\' {
             in_escape = true;
         } else {
-            output.push(ch);
+            output.push(String::from(ch));
         }
     }
 
-    output
+    if in_escape {
+        return Err(format!(
+            "delimiter list ends with an unescaped backslash: {}",
+            s
+        ));
+    }
+    // An empty list is a single empty delimiter
+    if output.is_empty() {
+        output.push(String::new());
+    }
+
+    Ok(output)
 }
 
 fn open_inputs(args: &Args, info: &mut PasteInfo) -> io::Result<()> {
-    // open each input
-    for filename in &args.files {
-        let f_res = fs::File::open(filename);
+    // without operands, read stdin
+    let stdin = [String::from("-")];
+    let filenames = if args.files.is_empty() {
+        &stdin[..]
+    } else {
+        &args.files[..]
+    };
 
-        match f_res {
-            Err(e) => {
-                eprintln!("{}: {}", filename, e);
-                return Err(e);
-            }
-            Ok(f) => {
-                info.inputs.push(PasteFile {
-                    filename: filename.to_string(),
-                    rdr: BufReader::new(f),
-                    eof: false,
-                    last: false,
-                });
+    // open each input
+    for filename in filenames {
+        let src = if filename == "-" {
+            Source::Stdin
+        } else {
+            match fs::File::open(filename) {
+                Err(e) => {
+                    eprintln!("{}: {}", filename, e);
+                    return Err(e);
+                }
+                Ok(f) => Source::File(BufReader::new(f)),
             }
-        }
+        };
+
+        info.inputs.push(PasteFile {
+            filename: filename.to_string(),
+            src,
+            eof: false,
+            last: false,
+        });
     }
 
     // mark final input
@@ -135,84 +176,60 @@ fn open_inputs(args: &Args, info: &mut PasteInfo) -> io::Result<()> {
     Ok(())
 }
 
-fn paste_files_serial(mut info: PasteInfo, mut dinfo: DelimInfo) -> io::Result<()> {
+fn paste_files_serial(mut info: PasteInfo, dinfo: DelimInfo) -> io::Result<()> {
+    let mut out = BufWriter::new(io::stdout().lock());
+    let mut buffer = Vec::new();
+
     // loop serially for each input file
     for input in &mut info.inputs {
-        let mut first_line = true;
-
-        // for each input line
-        loop {
-            // read line
-            let mut buffer = String::new();
-            let n_read_res = input.rdr.read_line(&mut buffer);
-            if let Err(e) = n_read_res {
-                eprintln!("{}: {}", input.filename, e);
-                return Err(e);
-            }
-            let n_read = n_read_res.unwrap();
+        // the delimiter list starts over for each file
+        let mut n_lines = 0;
 
-            // if EOF, output line terminator and end inner loop
-            if n_read == 0 {
-                println!();
-                break;
-
-            // output line segment
-            } else {
-                let slice = &buffer[0..buffer.len() - 1];
-
-                if first_line {
-                    print!("{}", slice);
-                } else {
-                    print!("{}{}", dinfo.delim(), slice);
-                }
-            }
-
-            if first_line {
-                first_line = false;
+        // for each input line, output line segment
+        while input.read_line(&mut info.stdin, &mut buffer)? {
+            if n_lines > 0 {
+                out.write_all(dinfo.delim(n_lines - 1))?;
             }
+            out.write_all(&buffer)?;
+            n_lines += 1;
         }
+
+        // at EOF, output line terminator
+        out.write_all(b"\n")?;
     }
 
-    Ok(())
+    out.flush()
 }
 
-fn paste_files(mut info: PasteInfo, mut dinfo: DelimInfo) -> io::Result<()> {
+fn paste_files(mut info: PasteInfo, dinfo: DelimInfo) -> io::Result<()> {
+    let mut out = BufWriter::new(io::stdout().lock());
+    let mut buffer = Vec::new();
+
     // for each input line, across N files
     loop {
-        let mut output = String::new();
+        let mut output = Vec::new();
         let mut have_data = false;
 
-        // for each input line
-        for input in &mut info.inputs {
-            // if not already at EOF, read and process a line
+        // for each input line; the delimiter list starts over for each output line
+        for (i, input) in info.inputs.iter_mut().enumerate() {
+            // if not already at EOF, read and process a line; a file that is shorter
+            // than the others leaves its columns empty
             if !input.eof {
-                // read input line
-                let mut buffer = String::new();
-                let n_read_res = input.rdr.read_line(&mut buffer);
-                if let Err(e) = n_read_res {
-                    eprintln!("{}: {}", input.filename, e);
-                    return Err(e);
-                }
-                let n_read = n_read_res.unwrap();
-
-                // if at EOF, note and continue
-                if n_read == 0 {
-                    input.eof = true;
-
-                // otherwise add to output line, sans trailing NL
-                } else {
+                if input.read_line(&mut info.stdin, &mut buffer)? {
                     have_data = true;
-                    output.push_str(&buffer[0..buffer.len() - 1]);
+                    output.extend_from_slice(&buffer);
+                } else {
+                    input.eof = true;
                 }
             }
 
             // final record, output line end
             if input.last {
-                output.push('\n');
+                output.push(b'\n');
 
             // next delimiter
             } else {
-                output.push(dinfo.delim());
+                output.extend_from_slice(dinfo.delim(i));
             }
         }
 
@@ -220,17 +237,13 @@ fn paste_files(mut info: PasteInfo, mut dinfo: DelimInfo) -> io::Result<()> {
             break;
         }
 
-        // output all segments to stdout at once (one write per line)
-        match io::stdout().write_all(output.as_bytes()) {
-            Ok(()) => {}
-            Err(e) => {
-                eprintln!("stdout: {}", e);
-                return Err(e);
-            }
+        if let Err(e) = out.write_all(&output) {
+            eprintln!("stdout: {}", e);
+            return Err(e);
         }
     }
 
-    Ok(())
+    out.flush()
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -245,9 +258,13 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut delim_state = DelimInfo::new();
     match &args.delims {
         None => {}
-        Some(dlm) => {
-            delim_state.delims = xlat_delim_str(dlm);
-        }
+        Some(dlm) => match xlat_delim_str(dlm) {
+            Ok(delims) => delim_state.delims = delims,
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        },
     }
 
     open_inputs(&args, &mut state)?;
@@ -0,0 +1,116 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+//! The collation of text in the order of the `LC_COLLATE` locale.
+//!
+//! The C and POSIX locales, and their UTF-8 variants, order text by its
+//! bytes, which is also the order of the code points of UTF-8 text, so it is
+//! compared directly. Other locales are compared with `strcoll`.
+
+use std::cmp::Ordering;
+use std::env;
+use std::ffi::{CStr, CString};
+use std::sync::OnceLock;
+
+/// Sets the locale of the C library from the environment, which `strcoll`
+/// and `localeconv` use.
+///
+/// # Returns
+///
+/// Returns `false` if the collation locale is unknown, and the text is then
+/// compared by bytes.
+pub fn set_locale_from_env() -> bool {
+    unsafe {
+        libc::setlocale(libc::LC_ALL, c"".as_ptr());
+        // LC_ALL fails as a whole if any category is unknown, so the one
+        // that matters here is checked on its own
+        !libc::setlocale(libc::LC_COLLATE, c"".as_ptr()).is_null()
+    }
+}
+
+/// Checks if the collation of the locale is the order of the bytes.
+pub fn is_byte_order() -> bool {
+    static BYTE_ORDER: OnceLock<bool> = OnceLock::new();
+    *BYTE_ORDER.get_or_init(|| {
+        let name = unsafe { libc::setlocale(libc::LC_COLLATE, std::ptr::null()) };
+        if name.is_null() {
+            return true;
+        }
+        is_byte_order_locale(&unsafe { CStr::from_ptr(name) }.to_string_lossy())
+    })
+}
+
+fn is_byte_order_locale(name: &str) -> bool {
+    name == "C" || name == "POSIX" || name.starts_with("C.")
+}
+
+/// The name of the collation locale given in the environment, in `LC_ALL`,
+/// `LC_COLLATE` or `LANG`.
+pub fn requested_locale() -> String {
+    ["LC_ALL", "LC_COLLATE", "LANG"]
+        .iter()
+        .filter_map(|var| env::var(var).ok())
+        .find(|name| !name.is_empty())
+        .unwrap_or_else(|| String::from("C"))
+}
+
+/// Compares two strings in the order of the locale.
+pub fn compare(s1: &[u8], s2: &[u8]) -> Ordering {
+    if is_byte_order() {
+        return s1.cmp(s2);
+    }
+
+    // strcoll stops at NUL, so the text on either side of each one is
+    // compared on its own
+    let mut parts1 = s1.split(|&b| b == 0);
+    let mut parts2 = s2.split(|&b| b == 0);
+    loop {
+        match (parts1.next(), parts2.next()) {
+            (Some(part1), Some(part2)) => match strcoll(part1, part2) {
+                Ordering::Equal => {}
+                ordering => return ordering,
+            },
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+        }
+    }
+}
+
+fn strcoll(s1: &[u8], s2: &[u8]) -> Ordering {
+    // the parts were split at NUL, so they can't have any
+    let s1 = CString::new(s1).expect("text without NUL");
+    let s2 = CString::new(s2).expect("text without NUL");
+    unsafe { libc::strcoll(s1.as_ptr(), s2.as_ptr()) }.cmp(&0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_byte_order_locales() {
+        for name in ["C", "POSIX", "C.UTF-8", "C.utf8"] {
+            assert!(is_byte_order_locale(name), "{}", name);
+        }
+        for name in ["en_US.UTF-8", "de_DE", "Czech"] {
+            assert!(!is_byte_order_locale(name), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_strcoll_with_nul() {
+        // the tests run in the C locale, where strcoll compares bytes
+        assert_eq!(strcoll(b"a", b"b"), Ordering::Less);
+        assert_eq!(strcoll(b"b", b"a"), Ordering::Greater);
+        assert_eq!(strcoll(b"a", b"a"), Ordering::Equal);
+        assert_eq!(compare(b"a\0b", b"a\0c"), Ordering::Less);
+        assert_eq!(compare(b"a\0b", b"a"), Ordering::Greater);
+    }
+}
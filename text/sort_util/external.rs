@@ -0,0 +1,399 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+//! Sorting of input that may not fit in memory.
+//!
+//! Lines are read into memory until they take up the memory budget. If the
+//! input ends before that, it is sorted in memory. Otherwise sorted runs are
+//! written to temporary files with replacement selection: the lines in memory
+//! are kept in a heap, and the smallest one that can still follow the last
+//! line of the current run is written, then replaced with the next line of
+//! the input. Lines that come before the last line written wait for the next
+//! run. On random input this makes the runs about twice as long as the budget.
+//!
+//! The runs are then merged, at most [MERGE_FAN_IN] of them at a time.
+
+use super::parallel;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Seek, Write};
+use std::path::Path;
+use std::vec;
+
+/// Compares two lines in the order of the output.
+pub type Compare = dyn Fn(&[u8], &[u8]) -> Ordering + Sync;
+
+/// The number of runs merged at once. Merging more runs takes more open
+/// files, so if there are more, they are merged in several passes.
+pub const MERGE_FAN_IN: usize = 16;
+
+// The memory a line takes up in addition to its text, roughly
+const LINE_OVERHEAD: usize = std::mem::size_of::<RunEntry>() + 16;
+
+fn line_size(line: &[u8]) -> usize {
+    line.len() + LINE_OVERHEAD
+}
+
+/// Parses the size of the memory budget given with `-S`: a number, followed
+/// by `b` for bytes, `K`, `M`, `G` or `T` for their multiples of 1024 bytes,
+/// or `%` for a percentage of the physical memory. A number alone is in
+/// kilobytes.
+pub fn parse_buffer_size(size: &str) -> Result<usize, String> {
+    let invalid = || format!("invalid buffer size '{}'", size);
+    let digits = size.bytes().take_while(u8::is_ascii_digit).count();
+    let number: usize = size[..digits].parse().map_err(|_| invalid())?;
+    let multiplier = match &size[digits..] {
+        "b" => 1,
+        "" | "k" | "K" => 1 << 10,
+        "m" | "M" => 1 << 20,
+        "g" | "G" => 1 << 30,
+        "t" | "T" => 1 << 40,
+        "%" if number <= 100 => return Ok(physical_memory().unwrap_or(0) / 100 * number),
+        _ => return Err(invalid()),
+    };
+    number.checked_mul(multiplier).ok_or_else(invalid)
+}
+
+/// The memory budget when none is given: a quarter of the physical memory.
+pub fn default_buffer_size() -> usize {
+    // for systems that don't tell
+    const FALLBACK: usize = 256 << 20;
+    physical_memory().map_or(FALLBACK, |memory| memory / 4)
+}
+
+fn physical_memory() -> Option<usize> {
+    let pages = unsafe { libc::sysconf(libc::_SC_PHYS_PAGES) };
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    if pages > 0 && page_size > 0 {
+        (pages as usize).checked_mul(page_size as usize)
+    } else {
+        None
+    }
+}
+
+/// Reads a line without its terminating newline.
+///
+/// # Returns
+///
+/// Returns `None` at the end of the input.
+pub fn read_line(reader: &mut dyn BufRead) -> io::Result<Option<Vec<u8>>> {
+    let mut line = Vec::new();
+    if reader.read_until(b'\n', &mut line)? == 0 {
+        return Ok(None);
+    }
+    if line.ends_with(b"\n") {
+        line.pop();
+    }
+    Ok(Some(line))
+}
+
+/// The lines of `reader`, without their terminating newlines.
+pub fn lines(mut reader: Box<dyn BufRead>) -> impl Iterator<Item = io::Result<Vec<u8>>> {
+    let mut failed = false;
+    std::iter::from_fn(move || {
+        if failed {
+            return None;
+        }
+        let line = read_line(reader.as_mut()).transpose();
+        failed = matches!(line, Some(Err(_)));
+        line
+    })
+}
+
+/// Sorts the lines of `input`, using temporary files in `tmp_dir` if they
+/// take up more than `memory` bytes. Lines that compare equal are kept in the
+/// order of the input. Lines sorted in memory are sorted on up to `threads`
+/// threads.
+///
+/// # Errors
+///
+/// Returns an error if reading the input fails, or if the temporary files
+/// can't be created or written.
+pub fn sort_lines<'a, I>(
+    mut input: I,
+    compare: &'a Compare,
+    memory: usize,
+    tmp_dir: &Path,
+    threads: usize,
+) -> io::Result<Sorted<'a>>
+where
+    I: Iterator<Item = io::Result<Vec<u8>>>,
+{
+    let mut lines = Vec::new();
+    let mut size = 0;
+    while size <= memory {
+        match input.next() {
+            Some(line) => {
+                let line = line?;
+                size += line_size(&line);
+                lines.push(line);
+            }
+            None => {
+                let lines = parallel::sort(lines, compare, threads);
+                return Ok(Sorted::Memory(lines.into_iter()));
+            }
+        }
+    }
+
+    let runs = write_runs(lines, size, input, compare, memory, tmp_dir)?;
+    Ok(Sorted::Merged(merge_runs(runs, compare, tmp_dir)?))
+}
+
+/// The sorted lines, from memory or merged from temporary files.
+pub enum Sorted<'a> {
+    Memory(vec::IntoIter<Vec<u8>>),
+    Merged(Merge<'a>),
+}
+
+impl Iterator for Sorted<'_> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Sorted::Memory(lines) => lines.next().map(Ok),
+            Sorted::Merged(merge) => merge.next(),
+        }
+    }
+}
+
+// A line waiting in memory to be written to a run. The heap is a max-heap, so
+// the order is reversed: the next line to write is the greatest entry
+struct RunEntry<'a> {
+    run: usize,
+    // the position in the input, which keeps equal lines in order
+    seq: usize,
+    line: Vec<u8>,
+    compare: &'a Compare,
+}
+
+impl Ord for RunEntry<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .run
+            .cmp(&self.run)
+            .then_with(|| (self.compare)(&other.line, &self.line))
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+impl PartialOrd for RunEntry<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for RunEntry<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for RunEntry<'_> {}
+
+// Writes the sorted runs of `lines`, which take up `size` bytes, followed by
+// the rest of `input`
+fn write_runs<I>(
+    lines: Vec<Vec<u8>>,
+    mut size: usize,
+    mut input: I,
+    compare: &Compare,
+    memory: usize,
+    tmp_dir: &Path,
+) -> io::Result<Vec<File>>
+where
+    I: Iterator<Item = io::Result<Vec<u8>>>,
+{
+    let mut seq = lines.len();
+    let mut heap: BinaryHeap<RunEntry> = lines
+        .into_iter()
+        .enumerate()
+        .map(|(seq, line)| RunEntry {
+            run: 0,
+            seq,
+            line,
+            compare,
+        })
+        .collect();
+
+    let mut runs = Vec::new();
+    let mut writer = BufWriter::new(temporary_file(tmp_dir)?);
+    let mut current_run = 0;
+    let mut input_ended = false;
+    while let Some(entry) = heap.pop() {
+        if entry.run != current_run {
+            runs.push(finish_run(writer)?);
+            writer = BufWriter::new(temporary_file(tmp_dir)?);
+            current_run = entry.run;
+        }
+        writer.write_all(&entry.line)?;
+        writer.write_all(b"\n")?;
+        size -= line_size(&entry.line);
+
+        let last = entry.line;
+        while !input_ended && size <= memory {
+            let Some(line) = input.next() else {
+                input_ended = true;
+                break;
+            };
+            let line = line?;
+            let run = if compare(&line, &last) == Ordering::Less {
+                current_run + 1
+            } else {
+                current_run
+            };
+            size += line_size(&line);
+            heap.push(RunEntry {
+                run,
+                seq,
+                line,
+                compare,
+            });
+            seq += 1;
+        }
+    }
+    runs.push(finish_run(writer)?);
+    Ok(runs)
+}
+
+/// Creates a temporary file in `tmp_dir`, which is removed once it's closed.
+///
+/// # Errors
+///
+/// Returns an error naming the directory if the file can't be created.
+pub fn temporary_file(tmp_dir: &Path) -> io::Result<File> {
+    tempfile::tempfile_in(tmp_dir).map_err(|err| {
+        io::Error::new(
+            err.kind(),
+            format!(
+                "can't create a temporary file in '{}': {}",
+                tmp_dir.display(),
+                err
+            ),
+        )
+    })
+}
+
+// Flushes a run and rewinds it for reading
+fn finish_run(writer: BufWriter<File>) -> io::Result<File> {
+    let mut file = writer.into_inner().map_err(|err| err.into_error())?;
+    file.rewind()?;
+    Ok(file)
+}
+
+fn readers(files: Vec<File>) -> Vec<Box<dyn BufRead>> {
+    files
+        .into_iter()
+        .map(|file| Box::new(BufReader::new(file)) as Box<dyn BufRead>)
+        .collect()
+}
+
+// Merges the runs in passes until at most `MERGE_FAN_IN` are left, and
+// returns the merge of those
+fn merge_runs<'a>(
+    mut runs: Vec<File>,
+    compare: &'a Compare,
+    tmp_dir: &Path,
+) -> io::Result<Merge<'a>> {
+    while runs.len() > MERGE_FAN_IN {
+        let mut merged = Vec::new();
+        let mut rest = runs.into_iter().peekable();
+        while rest.peek().is_some() {
+            let group: Vec<File> = rest.by_ref().take(MERGE_FAN_IN).collect();
+            let mut writer = BufWriter::new(temporary_file(tmp_dir)?);
+            for line in Merge::new(readers(group), compare)? {
+                writer.write_all(&line?)?;
+                writer.write_all(b"\n")?;
+            }
+            merged.push(finish_run(writer)?);
+        }
+        runs = merged;
+    }
+    Merge::new(readers(runs), compare)
+}
+
+// The next line of an input of a merge. The order is reversed for the
+// max-heap, like that of `RunEntry`
+struct MergeEntry<'a> {
+    line: Vec<u8>,
+    // inputs that come first win ties, which keeps the merge stable
+    source: usize,
+    compare: &'a Compare,
+}
+
+impl Ord for MergeEntry<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.compare)(&other.line, &self.line).then_with(|| other.source.cmp(&self.source))
+    }
+}
+
+impl PartialOrd for MergeEntry<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for MergeEntry<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for MergeEntry<'_> {}
+
+/// A k-way merge of sorted inputs. Lines that compare equal come in the order
+/// of the inputs.
+pub struct Merge<'a> {
+    sources: Vec<Box<dyn BufRead>>,
+    heap: BinaryHeap<MergeEntry<'a>>,
+    compare: &'a Compare,
+}
+
+impl<'a> Merge<'a> {
+    /// Starts merging `sources`, reading the first line of each.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading fails.
+    pub fn new(mut sources: Vec<Box<dyn BufRead>>, compare: &'a Compare) -> io::Result<Self> {
+        let mut heap = BinaryHeap::with_capacity(sources.len());
+        for (source, reader) in sources.iter_mut().enumerate() {
+            if let Some(line) = read_line(reader.as_mut())? {
+                heap.push(MergeEntry {
+                    line,
+                    source,
+                    compare,
+                });
+            }
+        }
+        Ok(Self {
+            sources,
+            heap,
+            compare,
+        })
+    }
+}
+
+impl Iterator for Merge<'_> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = self.heap.pop()?;
+        match read_line(self.sources[entry.source].as_mut()) {
+            Ok(Some(line)) => self.heap.push(MergeEntry {
+                line,
+                source: entry.source,
+                compare: self.compare,
+            }),
+            Ok(None) => {}
+            Err(err) => return Some(Err(err)),
+        }
+        Some(Ok(entry.line))
+    }
+}
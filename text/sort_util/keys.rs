@@ -0,0 +1,485 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+//! Sort keys, the parts of the lines that are compared, as given with `-k`.
+//!
+//! A key is found in a line as a range of byte offsets, and compared where it
+//! is, without copying it out of the line.
+//!
+//! Without `-t`, a field is a run of non-blank characters together with the
+//! blanks before it, so the character positions of a key count the blanks
+//! unless `b` is given. With `-t`, each separator character ends a field, and
+//! fields can be empty.
+//!
+//! The lines are bytes, which need not be UTF-8. Character positions count
+//! UTF-8 characters, and each byte that isn't part of one as a character.
+
+use super::{collate, version, NumericOrder};
+use memchr::memmem;
+use std::cmp::Ordering;
+use std::hash::{BuildHasher, Hasher, RandomState};
+use std::ops::Range;
+use std::sync::OnceLock;
+
+/// How the text of a key is compared.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KeyOptions {
+    /// `-n`, `-g` or `-h`: compare the numbers at the start of the keys.
+    pub numeric: Option<NumericOrder>,
+    /// `-r`: reverse the comparison.
+    pub reverse: bool,
+    /// `-f`: compare lowercase letters as uppercase.
+    pub fold_case: bool,
+    /// `-d`: only compare blanks and alphanumeric characters.
+    pub dictionary_order: bool,
+    /// `-i`: only compare printable characters.
+    pub ignore_nonprintable: bool,
+    /// `-R`: compare keyed hashes of the keys, which shuffles the lines but
+    /// keeps equal keys together.
+    pub random: bool,
+    /// `-V`: compare the keys as version numbers.
+    pub version: bool,
+}
+
+// A position of `-k`, the start or the end of a key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Position {
+    // Counting from 0
+    field: usize,
+    // For the start, the characters before the key in the field. For the end,
+    // the characters of the field in the key, or 0 for the whole field
+    character: usize,
+    // `b`: the leading blanks of the field aren't counted
+    skip_blanks: bool,
+}
+
+/// A key of `-k`, or the whole line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Key {
+    start: Position,
+    // `None` for the end of the line
+    end: Option<Position>,
+    options: KeyOptions,
+}
+
+fn is_blank(b: u8) -> bool {
+    b == b' ' || b == b'\t'
+}
+
+impl Key {
+    /// The key covering the whole line, compared with the global options.
+    /// `skip_blanks` is `-b`, which ignores the leading blanks of the line.
+    pub fn whole_line(options: KeyOptions, skip_blanks: bool) -> Self {
+        Self {
+            start: Position {
+                field: 0,
+                character: 0,
+                skip_blanks,
+            },
+            end: None,
+            options,
+        }
+    }
+
+    /// Parses the definition of a key given with `-k`:
+    /// `field_start[.first_character][type][,field_end[.last_character][type]]`,
+    /// where `type` is any of the modifiers `b`, `d`, `f`, `g`, `h`, `i`, `n`, `r`, `R`
+    /// and `V`.
+    ///
+    /// A key without modifiers of its own is compared with the `global`
+    /// options, and ignores leading blanks at both positions if
+    /// `global_skip_blanks` is set. A modifier other than `b` applies to the
+    /// whole key, whichever position it is given with.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the definition is malformed. A key that ends
+    /// before it starts isn't an error, it is empty.
+    pub fn parse(
+        definition: &str,
+        global: KeyOptions,
+        global_skip_blanks: bool,
+    ) -> Result<Self, String> {
+        if definition.is_empty() {
+            return Err("key must be non-empty".to_string());
+        }
+
+        let mut options = KeyOptions::default();
+        let mut has_modifiers = false;
+        let (start, end) = match definition.split_once(',') {
+            Some((start, end)) => (start, Some(end)),
+            None => (definition, None),
+        };
+
+        let mut parse_position = |position: &str, is_start: bool| {
+            let field_digits = position.bytes().take_while(u8::is_ascii_digit).count();
+            let field: usize = position[..field_digits]
+                .parse()
+                .map_err(|err| format!("{}", err))?;
+            if field == 0 {
+                return Err("the key can't be zero.".to_string());
+            }
+
+            let mut rest = &position[field_digits..];
+            let mut character = if is_start { 1 } else { 0 };
+            if let Some(after_dot) = rest.strip_prefix('.') {
+                let digits = after_dot.bytes().take_while(u8::is_ascii_digit).count();
+                character = after_dot[..digits]
+                    .parse()
+                    .map_err(|err| format!("{}", err))?;
+                if is_start && character == 0 {
+                    return Err("the key can't start at character zero.".to_string());
+                }
+                rest = &after_dot[digits..];
+            }
+
+            let mut skip_blanks = false;
+            for modifier in rest.chars() {
+                match modifier {
+                    'b' => skip_blanks = true,
+                    'd' => options.dictionary_order = true,
+                    'f' => options.fold_case = true,
+                    'i' => options.ignore_nonprintable = true,
+                    'g' => options.numeric = Some(NumericOrder::General),
+                    'h' => options.numeric = Some(NumericOrder::Human),
+                    'n' => options.numeric = Some(NumericOrder::Numeric),
+                    'r' => options.reverse = true,
+                    'R' => options.random = true,
+                    'V' => options.version = true,
+                    _ => return Err(format!("invalid key '{}'", definition)),
+                }
+                has_modifiers = true;
+            }
+
+            Ok(Position {
+                field: field - 1,
+                character: if is_start { character - 1 } else { character },
+                skip_blanks,
+            })
+        };
+
+        let mut start = parse_position(start, true)?;
+        let mut end = end.map(|end| parse_position(end, false)).transpose()?;
+        if !has_modifiers {
+            options = global;
+            start.skip_blanks = global_skip_blanks;
+            if let Some(end) = &mut end {
+                end.skip_blanks = global_skip_blanks;
+            }
+        }
+
+        Ok(Self {
+            start,
+            end,
+            options,
+        })
+    }
+
+    /// Finds the key in `line`, as a range of byte offsets, which is empty if
+    /// the line is too short to have the key, or if the key ends before it
+    /// starts. `separator` is the field separator of `-t`, encoded as UTF-8.
+    pub fn find(&self, line: &[u8], separator: Option<&[u8]>) -> Range<usize> {
+        let start = {
+            let field = field_start(line, self.start.field, separator);
+            let field = if self.start.skip_blanks {
+                skip_blanks(line, field)
+            } else {
+                field
+            };
+            advance(line, field, self.start.character)
+        };
+
+        let end = match &self.end {
+            None => line.len(),
+            Some(end) => {
+                let field = field_start(line, end.field, separator);
+                if end.character == 0 {
+                    field_end(line, field, separator)
+                } else {
+                    let field = if end.skip_blanks {
+                        skip_blanks(line, field)
+                    } else {
+                        field
+                    };
+                    advance(line, field, end.character)
+                }
+            }
+        };
+
+        start..end.max(start)
+    }
+
+    /// Compares the keys of two lines.
+    pub fn compare(&self, line1: &[u8], line2: &[u8], separator: Option<&[u8]>) -> Ordering {
+        let key1 = &line1[self.find(line1, separator)];
+        let key2 = &line2[self.find(line2, separator)];
+        let ordering = compare_text(key1, key2, &self.options);
+        if self.options.reverse {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    }
+}
+
+fn skip_blanks(line: &[u8], offset: usize) -> usize {
+    line[offset..]
+        .iter()
+        .position(|b| !is_blank(*b))
+        .map_or(line.len(), |i| offset + i)
+}
+
+// The length of the character at the start of `text`: a UTF-8 character, or
+// a byte that isn't part of one
+fn char_len(text: &[u8]) -> usize {
+    let len = match text.first() {
+        None => return 0,
+        Some(0xc0..=0xdf) => 2,
+        Some(0xe0..=0xef) => 3,
+        Some(0xf0..=0xf7) => 4,
+        Some(_) => 1,
+    };
+    match text.get(..len) {
+        Some(c) if std::str::from_utf8(c).is_ok() => len,
+        _ => 1,
+    }
+}
+
+// The offset `characters` characters after `offset`, or the end of the line
+fn advance(line: &[u8], mut offset: usize, characters: usize) -> usize {
+    for _ in 0..characters {
+        if offset == line.len() {
+            break;
+        }
+        offset += char_len(&line[offset..]);
+    }
+    offset
+}
+
+// The offset where the field `field` starts, or the end of the line if there
+// are fewer fields
+fn field_start(line: &[u8], field: usize, separator: Option<&[u8]>) -> usize {
+    let mut offset = 0;
+    for _ in 0..field {
+        if offset == line.len() {
+            break;
+        }
+        offset = match separator {
+            Some(separator) => memmem::find(&line[offset..], separator)
+                .map_or(line.len(), |i| offset + i + separator.len()),
+            None => field_end(line, offset, None),
+        };
+    }
+    offset
+}
+
+// The offset where the field starting at `offset` ends
+fn field_end(line: &[u8], offset: usize, separator: Option<&[u8]>) -> usize {
+    match separator {
+        Some(separator) => {
+            memmem::find(&line[offset..], separator).map_or(line.len(), |i| offset + i)
+        }
+        None => {
+            let word = skip_blanks(line, offset);
+            line[word..]
+                .iter()
+                .position(|b| is_blank(*b))
+                .map_or(line.len(), |i| word + i)
+        }
+    }
+}
+
+// Compares the text of two keys, except for reversing the comparison
+fn compare_text(key1: &[u8], key2: &[u8], options: &KeyOptions) -> Ordering {
+    if options.random {
+        // Keys with the same hash, which are almost always equal, are
+        // ordered as they would be without -R
+        let ordering = random_hash(key1, options).cmp(&random_hash(key2, options));
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    if let Some(order) = options.numeric {
+        return order.compare(key1, key2);
+    }
+    if options.version {
+        return version::compare(key1, key2);
+    }
+
+    let compared1 = compared_bytes(key1, options);
+    let compared2 = compared_bytes(key2, options);
+    if !collate::is_byte_order() {
+        // The locale collates whole strings, so the filtered and folded text
+        // is collected first
+        let compared1: Vec<u8> = compared1.collect();
+        let compared2: Vec<u8> = compared2.collect();
+        return collate::compare(&compared1, &compared2);
+    }
+    if options.fold_case || options.dictionary_order || options.ignore_nonprintable {
+        // With -f, keys that only differ in case are equal, for -u and -s;
+        // the whole lines are compared as a last resort otherwise
+        compared1.cmp(compared2)
+    } else {
+        key1.cmp(key2)
+    }
+}
+
+// The bytes of a key that are compared: the characters left after `-d` and
+// `-i`, as uppercase with `-f`. Bytes that aren't part of a UTF-8 character
+// are compared as they are, but left out by `-d` and `-i`
+fn compared_bytes<'a>(key: &'a [u8], options: &'a KeyOptions) -> impl Iterator<Item = u8> + 'a {
+    key.utf8_chunks().flat_map(move |chunk| {
+        let chars = chunk
+            .valid()
+            .chars()
+            .filter(move |c| is_compared(*c, options))
+            .flat_map(move |c| {
+                let upper = options.fold_case.then(|| c.to_uppercase());
+                upper
+                    .into_iter()
+                    .flatten()
+                    .chain((!options.fold_case).then_some(c))
+            })
+            .flat_map(|c| {
+                let mut buf = [0; 4];
+                let len = c.encode_utf8(&mut buf).len();
+                buf.into_iter().take(len)
+            });
+        let invalid = if options.dictionary_order || options.ignore_nonprintable {
+            &[][..]
+        } else {
+            chunk.invalid()
+        };
+        chars.chain(invalid.iter().copied())
+    })
+}
+
+// Checks if `c` is compared, or left out with `-d` or `-i`
+fn is_compared(c: char, options: &KeyOptions) -> bool {
+    if options.dictionary_order {
+        c == ' ' || c == '\t' || c.is_alphanumeric()
+    } else if options.ignore_nonprintable {
+        !c.is_control()
+    } else {
+        true
+    }
+}
+
+// The hash of the text of a key that `-R` compares, keyed the same for all
+// the lines but differently in each run
+fn random_hash(key: &[u8], options: &KeyOptions) -> u64 {
+    static STATE: OnceLock<RandomState> = OnceLock::new();
+    let mut hasher = STATE.get_or_init(RandomState::new).build_hasher();
+    compared_bytes(key, options).for_each(|b| hasher.write_u8(b));
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(definition: &str) -> Key {
+        Key::parse(definition, KeyOptions::default(), false).unwrap()
+    }
+
+    fn find<'a>(definition: &str, line: &'a str, separator: Option<&str>) -> &'a str {
+        &line[key(definition).find(line.as_bytes(), separator.map(str::as_bytes))]
+    }
+
+    #[test]
+    fn test_fields_include_leading_blanks() {
+        assert_eq!(find("2,2", "a  bc d", None), "  bc");
+        assert_eq!(find("2", "a  bc d", None), "  bc d");
+        assert_eq!(find("2b,2", "a  bc d", None), "bc");
+        assert_eq!(find("1,1", "  a b", None), "  a");
+        assert_eq!(find("3,3", "a b", None), "");
+    }
+
+    #[test]
+    fn test_character_positions() {
+        assert_eq!(find("2.2,2.3", "a  bc d", None), " b");
+        assert_eq!(find("2.2b,2.3b", "a  bc d", None), "c ");
+        assert_eq!(find("1.2,1.2", "héllo", None), "é");
+        assert_eq!(find("2,3.0", "a b c d", None), " b c");
+        assert_eq!(find("1.9", "abc", None), "");
+
+        // A byte that isn't part of a UTF-8 character is a character
+        let key = key("1.2,1.2");
+        assert_eq!(key.find(b"h\xe9llo", None), 1..2);
+        assert_eq!(key.find("h\u{e9}llo".as_bytes(), None), 1..3);
+    }
+
+    #[test]
+    fn test_separator() {
+        assert_eq!(find("2,2", ":ab:c", Some(":")), "ab");
+        assert_eq!(find("2,3", "a::c:d", Some(":")), ":c");
+        assert_eq!(find("4,4", "a:b", Some(":")), "");
+        assert_eq!(find("2.2,2.2", "x: ab", Some(":")), "a");
+        assert_eq!(find("2b,2", "x: ab", Some(":")), "ab");
+    }
+
+    #[test]
+    fn test_modifiers() {
+        let key = key("2n,3r");
+        assert_eq!(
+            key.options,
+            KeyOptions {
+                numeric: Some(NumericOrder::Numeric),
+                reverse: true,
+                ..Default::default()
+            }
+        );
+        assert!(!key.start.skip_blanks);
+
+        // Global options only apply to keys without modifiers
+        let global = KeyOptions {
+            fold_case: true,
+            ..Default::default()
+        };
+        let inherited = Key::parse("1,2", global, true).unwrap();
+        assert_eq!(inherited.options, global);
+        assert!(inherited.start.skip_blanks && inherited.end.unwrap().skip_blanks);
+        let own = Key::parse("1b,2", global, false).unwrap();
+        assert_eq!(own.options, KeyOptions::default());
+        assert!(own.start.skip_blanks && !own.end.unwrap().skip_blanks);
+    }
+
+    #[test]
+    fn test_invalid_keys() {
+        let parse = |definition| Key::parse(definition, KeyOptions::default(), false);
+        assert_eq!(parse(""), Err("key must be non-empty".to_string()));
+        assert_eq!(parse("0"), Err("the key can't be zero.".to_string()));
+        assert_eq!(parse("1,0"), Err("the key can't be zero.".to_string()));
+        assert!(parse("1.0").is_err());
+        assert!(parse("2.,3").is_err());
+        assert!(parse("1x").is_err());
+        // A key that ends before it starts is empty
+        assert!(parse("2,1").is_ok());
+        assert!(parse("1.3,1.2").is_ok());
+        assert!(parse("1.3,1.3").is_ok());
+    }
+
+    #[test]
+    fn test_compare() {
+        let fold = KeyOptions {
+            fold_case: true,
+            ..Default::default()
+        };
+        let dictionary = KeyOptions {
+            dictionary_order: true,
+            ..Default::default()
+        };
+        assert_eq!(compare_text(b"abc", b"ABD", &fold), Ordering::Less);
+        assert_eq!(compare_text(b"a", b"A", &fold), Ordering::Equal);
+        assert_eq!(compare_text(b"\xe9a", b"\xe9A", &fold), Ordering::Equal);
+        assert_eq!(compare_text(b"a-b", b"ab", &dictionary), Ordering::Equal);
+        assert_eq!(compare_text(b"a\xe9b", b"ab", &dictionary), Ordering::Equal);
+        assert_eq!(key("1,1r").compare(b"a x", b"b y", None), Ordering::Greater);
+    }
+}
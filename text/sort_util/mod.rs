@@ -0,0 +1,24 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+mod collate;
+mod external;
+mod keys;
+mod numeric;
+mod parallel;
+mod version;
+
+pub use collate::{compare as collate, requested_locale, set_locale_from_env};
+pub use external::{
+    default_buffer_size, lines, parse_buffer_size, sort_lines, temporary_file, Compare, Merge,
+    Sorted,
+};
+pub use keys::{Key, KeyOptions};
+pub use numeric::NumericOrder;
+pub use parallel::default_threads;
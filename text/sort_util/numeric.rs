@@ -0,0 +1,415 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+//! The numeric orders of `-n`, `-g` and `-h`.
+//!
+//! `-n` and `-h` compare the decimal digits of the numbers rather than
+//! converting them to floating point, so numbers of any length and precision
+//! are ordered exactly. The radix character and the thousands separator are
+//! those of the locale.
+
+use std::cmp::Ordering;
+use std::sync::OnceLock;
+
+/// Which numeric order a key is compared in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumericOrder {
+    /// `-n`: the initial numeric strings, of blanks, an optional minus sign,
+    /// and digits with an optional radix character and thousands separators.
+    /// Text that doesn't start with a number counts as zero.
+    Numeric,
+    /// `-g`: the initial floating point numbers, with exponents, infinities
+    /// and NaNs. Text that doesn't start with a number comes first, then
+    /// NaNs, then the numbers from minus to plus infinity.
+    General,
+    /// `-h`: numbers like `-n`, followed by an optional SI suffix such as `K`
+    /// or `M`, as in the output of `du -h`. Numbers with a larger suffix are
+    /// larger, whatever the number before the suffix.
+    Human,
+}
+
+impl NumericOrder {
+    /// Compares the text of two keys in this order.
+    pub fn compare(self, key1: &[u8], key2: &[u8]) -> Ordering {
+        match self {
+            NumericOrder::Numeric => {
+                let separators = Separators::get();
+                Decimal::parse(key1, separators)
+                    .0
+                    .cmp(&Decimal::parse(key2, separators).0)
+            }
+            NumericOrder::General => compare_general(key1, key2),
+            NumericOrder::Human => compare_human(key1, key2, Separators::get()),
+        }
+    }
+}
+
+fn is_blank(b: u8) -> bool {
+    b == b' ' || b == b'\t'
+}
+
+// The radix character and thousands separator of the locale
+#[derive(Debug, Clone, Copy)]
+struct Separators {
+    decimal_point: u8,
+    thousands: Option<u8>,
+}
+
+impl Separators {
+    fn get() -> Self {
+        static SEPARATORS: OnceLock<Separators> = OnceLock::new();
+        *SEPARATORS.get_or_init(|| {
+            // Only single byte separators are supported
+            let single_byte = |s: *const libc::c_char| {
+                if s.is_null() {
+                    return None;
+                }
+                let s = unsafe { std::ffi::CStr::from_ptr(s) }.to_bytes();
+                match s {
+                    [b] if b.is_ascii() => Some(*b),
+                    _ => None,
+                }
+            };
+            let conv = unsafe { &*libc::localeconv() };
+            Separators {
+                decimal_point: single_byte(conv.decimal_point).unwrap_or(b'.'),
+                thousands: single_byte(conv.thousands_sep),
+            }
+        })
+    }
+}
+
+// A number of `-n`, as the digits of the text
+#[derive(Debug, Clone, Copy)]
+struct Decimal<'a> {
+    negative: bool,
+    // The integer digits, which can include leading zeros and thousands
+    // separators
+    integer: &'a [u8],
+    fraction: &'a [u8],
+}
+
+impl<'a> Decimal<'a> {
+    // Parses the number at the start of `text`, and returns it with the rest
+    // of the text
+    fn parse(bytes: &'a [u8], separators: Separators) -> (Self, &'a [u8]) {
+        let mut i = bytes.iter().take_while(|b| is_blank(**b)).count();
+        let negative = bytes.get(i) == Some(&b'-');
+        if negative {
+            i += 1;
+        }
+
+        let integer_start = i;
+        while let Some(&b) = bytes.get(i) {
+            let is_separator = Some(b) == separators.thousands
+                && i > integer_start
+                && bytes[i - 1].is_ascii_digit()
+                && bytes.get(i + 1).is_some_and(u8::is_ascii_digit);
+            if !b.is_ascii_digit() && !is_separator {
+                break;
+            }
+            i += 1;
+        }
+        let integer = &bytes[integer_start..i];
+
+        let mut fraction: &[u8] = &[];
+        if bytes.get(i) == Some(&separators.decimal_point) {
+            let digits = bytes[i + 1..]
+                .iter()
+                .take_while(|b| b.is_ascii_digit())
+                .count();
+            fraction = &bytes[i + 1..i + 1 + digits];
+            i += 1 + digits;
+        }
+
+        let decimal = Decimal {
+            negative,
+            integer,
+            fraction,
+        };
+        // Text without digits isn't a number, and counts as zero
+        let rest = if integer.is_empty() && fraction.is_empty() {
+            &bytes[integer_start.min(bytes.len())..]
+        } else {
+            &bytes[i..]
+        };
+        (decimal, rest)
+    }
+
+    fn is_zero(&self) -> bool {
+        self.integer
+            .iter()
+            .chain(self.fraction)
+            .all(|b| !(b'1'..=b'9').contains(b))
+    }
+
+    // The digits of the integer part without leading zeros
+    fn integer_digits(&self) -> impl Iterator<Item = u8> + Clone + 'a {
+        self.integer
+            .iter()
+            .copied()
+            .filter(u8::is_ascii_digit)
+            .skip_while(|b| *b == b'0')
+    }
+
+    fn compare_magnitude(&self, other: &Self) -> Ordering {
+        let digits1 = self.integer_digits();
+        let digits2 = other.integer_digits();
+        digits1
+            .clone()
+            .count()
+            .cmp(&digits2.clone().count())
+            .then_with(|| digits1.cmp(digits2))
+            .then_with(|| {
+                // Missing digits of the fraction are zeros
+                let len = self.fraction.len().max(other.fraction.len());
+                let digit = |fraction: &[u8], i| fraction.get(i).copied().unwrap_or(b'0');
+                (0..len)
+                    .map(|i| digit(self.fraction, i).cmp(&digit(other.fraction, i)))
+                    .find(|ordering| *ordering != Ordering::Equal)
+                    .unwrap_or(Ordering::Equal)
+            })
+    }
+}
+
+impl Ord for Decimal<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Minus zero is zero
+        let negative1 = self.negative && !self.is_zero();
+        let negative2 = other.negative && !other.is_zero();
+        match (negative1, negative2) {
+            (false, false) => self.compare_magnitude(other),
+            (true, true) => other.compare_magnitude(self),
+            (true, false) => Ordering::Less,
+            (false, true) => Ordering::Greater,
+        }
+    }
+}
+
+impl PartialOrd for Decimal<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Decimal<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Decimal<'_> {}
+
+// The SI suffixes of `-h`, from the smallest
+const SUFFIXES: &[u8] = b"KMGTPEZYRQ";
+
+fn compare_human(key1: &[u8], key2: &[u8], separators: Separators) -> Ordering {
+    let parse = |key| {
+        let (decimal, rest) = Decimal::parse(key, separators);
+        let has_digits = !decimal.integer.is_empty() || !decimal.fraction.is_empty();
+        let suffix = match rest.first() {
+            // `k` is also used for kilo
+            Some(b'k') if has_digits => 1,
+            Some(b) if has_digits => SUFFIXES
+                .iter()
+                .position(|suffix| suffix == b)
+                .map_or(0, |i| i + 1),
+            _ => 0,
+        };
+        (decimal, suffix)
+    };
+    let (decimal1, suffix1) = parse(key1);
+    let (decimal2, suffix2) = parse(key2);
+
+    let sign = |decimal: &Decimal| {
+        if decimal.is_zero() {
+            0
+        } else if decimal.negative {
+            -1
+        } else {
+            1
+        }
+    };
+    let (sign1, sign2) = (sign(&decimal1), sign(&decimal2));
+    sign1.cmp(&sign2).then_with(|| {
+        // Zero is zero, whatever the suffix
+        if sign1 == 0 {
+            return Ordering::Equal;
+        }
+        let ordering = suffix1.cmp(&suffix2);
+        // A larger suffix makes a negative number smaller
+        let ordering = if sign1 < 0 {
+            ordering.reverse()
+        } else {
+            ordering
+        };
+        ordering.then_with(|| decimal1.cmp(&decimal2))
+    })
+}
+
+// A number of `-g`
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum General {
+    NotNumber,
+    Nan,
+    Number(f64),
+}
+
+impl General {
+    // Parses the floating point number at the start of `text`, as `strtod`
+    // does
+    fn parse(text: &[u8]) -> Self {
+        // the number is all ASCII, so nothing from the first other byte on
+        // can be part of it
+        let ascii_len = text.iter().take_while(|b| b.is_ascii()).count();
+        let text = std::str::from_utf8(&text[..ascii_len]).expect("ASCII text");
+        let text = text.trim_start_matches([' ', '\t']);
+        let unsigned = text.strip_prefix(['+', '-']).unwrap_or(text);
+        let negative = text.starts_with('-');
+
+        let starts_with = |word: &str| {
+            unsigned
+                .get(..word.len())
+                .is_some_and(|start| start.eq_ignore_ascii_case(word))
+        };
+        if starts_with("nan") {
+            return General::Nan;
+        }
+        if starts_with("inf") {
+            return General::Number(if negative {
+                f64::NEG_INFINITY
+            } else {
+                f64::INFINITY
+            });
+        }
+
+        let bytes = unsigned.as_bytes();
+        let digits_from = |i: usize| bytes[i..].iter().take_while(|b| b.is_ascii_digit()).count();
+        let integer = digits_from(0);
+        let mut end = integer;
+        let mut fraction = 0;
+        if bytes.get(end) == Some(&b'.') {
+            fraction = digits_from(end + 1);
+            end += 1 + fraction;
+        }
+        if integer == 0 && fraction == 0 {
+            return General::NotNumber;
+        }
+        if matches!(bytes.get(end), Some(b'e' | b'E')) {
+            let mut exponent = end + 1;
+            if matches!(bytes.get(exponent), Some(b'+' | b'-')) {
+                exponent += 1;
+            }
+            let digits = digits_from(exponent);
+            if digits > 0 {
+                end = exponent + digits;
+            }
+        }
+
+        let sign_len = text.len() - unsigned.len();
+        match text[..sign_len + end].parse::<f64>() {
+            Ok(number) => General::Number(number),
+            Err(_) => General::NotNumber,
+        }
+    }
+}
+
+fn compare_general(key1: &[u8], key2: &[u8]) -> Ordering {
+    match (General::parse(key1), General::parse(key2)) {
+        (General::Number(number1), General::Number(number2)) => {
+            number1.partial_cmp(&number2).unwrap_or(Ordering::Equal)
+        }
+        (General::NotNumber, General::NotNumber) | (General::Nan, General::Nan) => Ordering::Equal,
+        (General::NotNumber, _) | (General::Nan, General::Number(_)) => Ordering::Less,
+        (_, General::NotNumber) | (General::Number(_), General::Nan) => Ordering::Greater,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const C_LOCALE: Separators = Separators {
+        decimal_point: b'.',
+        thousands: None,
+    };
+
+    fn numeric(key1: &str, key2: &str, separators: Separators) -> Ordering {
+        Decimal::parse(key1.as_bytes(), separators)
+            .0
+            .cmp(&Decimal::parse(key2.as_bytes(), separators).0)
+    }
+
+    #[test]
+    fn test_numeric() {
+        assert_eq!(numeric("2", "11", C_LOCALE), Ordering::Less);
+        assert_eq!(numeric("  -3", "-20", C_LOCALE), Ordering::Greater);
+        assert_eq!(numeric("-0", "0.000", C_LOCALE), Ordering::Equal);
+        assert_eq!(numeric("007", "7.0", C_LOCALE), Ordering::Equal);
+        assert_eq!(numeric(".5", "0.49", C_LOCALE), Ordering::Greater);
+        assert_eq!(numeric("abc", "0", C_LOCALE), Ordering::Equal);
+        assert_eq!(numeric("-abc", "-0", C_LOCALE), Ordering::Equal);
+        assert_eq!(numeric("+1", "0", C_LOCALE), Ordering::Equal);
+        assert_eq!(numeric("1e3", "2", C_LOCALE), Ordering::Less);
+        assert_eq!(
+            numeric(
+                "123456789012345678901234567890",
+                "123456789012345678901234567891",
+                C_LOCALE
+            ),
+            Ordering::Less
+        );
+    }
+
+    #[test]
+    fn test_numeric_thousands_separator() {
+        let separators = Separators {
+            decimal_point: b',',
+            thousands: Some(b'.'),
+        };
+        assert_eq!(numeric("1.000", "999", separators), Ordering::Greater);
+        assert_eq!(numeric("1.000,5", "1000,4", separators), Ordering::Greater);
+        // A separator has to be between digits
+        assert_eq!(numeric("1..000", "1", separators), Ordering::Equal);
+        assert_eq!(numeric("1.000", "1000", C_LOCALE), Ordering::Less);
+    }
+
+    #[test]
+    fn test_general() {
+        let order = |keys: &[&str]| {
+            keys.windows(2).all(|pair| {
+                compare_general(pair[0].as_bytes(), pair[1].as_bytes()) == Ordering::Less
+            })
+        };
+        assert!(order(&[
+            "abc", "nan", "-inf", "-1e10", "-2", "0", "1e-3", "2.5", "1e10", "inf"
+        ]));
+        assert_eq!(compare_general(b"1E2", b"100"), Ordering::Equal);
+        assert_eq!(compare_general(b"-0", b"+0"), Ordering::Equal);
+        assert_eq!(compare_general(b"1e", b"1"), Ordering::Equal);
+        assert_eq!(compare_general(b"x", b""), Ordering::Equal);
+        assert_eq!(compare_general(b"-NaN", b"nan"), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_human() {
+        let order = |keys: &[&str]| {
+            keys.windows(2).all(|pair| {
+                compare_human(pair[0].as_bytes(), pair[1].as_bytes(), C_LOCALE) == Ordering::Less
+            })
+        };
+        assert!(order(&[
+            "-1G", "-900M", "-1", "0", "5", "1023", "1k", "1.5K", "900K", "2M", "1G"
+        ]));
+        assert_eq!(compare_human(b"1K", b"1k", C_LOCALE), Ordering::Equal);
+        assert_eq!(compare_human(b"0K", b"0", C_LOCALE), Ordering::Equal);
+        assert_eq!(compare_human(b"K", b"abc", C_LOCALE), Ordering::Equal);
+        assert_eq!(compare_human(b"-0K", b"0", C_LOCALE), Ordering::Equal);
+    }
+}
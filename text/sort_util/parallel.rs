@@ -0,0 +1,125 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+//! Sorting of the lines in memory on several threads.
+//!
+//! The lines are split into a chunk for each thread, and the chunks are
+//! sorted at the same time. The sorted chunks are then merged in pairs, each
+//! pair on a thread of its own, until one is left.
+
+use super::external::Compare;
+use std::cmp::Ordering;
+use std::num::NonZeroUsize;
+use std::thread;
+
+/// The most threads used by default. Beyond this the merges, which read all
+/// the lines once per round, take up more of the time than the threads save.
+pub const MAX_DEFAULT_THREADS: usize = 8;
+
+// Fewer lines than this on a thread cost more to start the thread than to
+// sort them on the one at hand
+const MIN_CHUNK_LEN: usize = 1 << 13;
+
+/// The number of threads used when `--parallel` isn't given: one for each
+/// processor, up to [MAX_DEFAULT_THREADS].
+pub fn default_threads() -> usize {
+    thread::available_parallelism()
+        .map_or(1, NonZeroUsize::get)
+        .min(MAX_DEFAULT_THREADS)
+}
+
+/// Sorts `lines` on up to `threads` threads. Lines that compare equal are
+/// kept in their order.
+pub fn sort(mut lines: Vec<Vec<u8>>, compare: &Compare, threads: usize) -> Vec<Vec<u8>> {
+    let threads = threads.min(lines.len() / MIN_CHUNK_LEN);
+    if threads <= 1 {
+        lines.sort_by(|a, b| compare(a, b));
+        return lines;
+    }
+
+    let chunk_len = lines.len().div_ceil(threads);
+    let mut input = lines.into_iter();
+    let mut chunks: Vec<Vec<Vec<u8>>> = Vec::with_capacity(threads);
+    loop {
+        let chunk: Vec<Vec<u8>> = input.by_ref().take(chunk_len).collect();
+        if chunk.is_empty() {
+            break;
+        }
+        chunks.push(chunk);
+    }
+
+    thread::scope(|scope| {
+        for chunk in &mut chunks {
+            scope.spawn(move || chunk.sort_by(|a, b| compare(a, b)));
+        }
+    });
+
+    while chunks.len() > 1 {
+        chunks = thread::scope(|scope| {
+            let mut rest = chunks.into_iter();
+            let mut merges = Vec::new();
+            while let Some(first) = rest.next() {
+                merges.push(match rest.next() {
+                    Some(second) => scope.spawn(move || merge(first, second, compare)),
+                    None => scope.spawn(move || first),
+                });
+            }
+            merges
+                .into_iter()
+                .map(|merge| merge.join().expect("sorting thread panicked"))
+                .collect()
+        });
+    }
+    chunks.pop().unwrap_or_default()
+}
+
+// Merges two sorted chunks, the lines of `first` winning ties
+fn merge(first: Vec<Vec<u8>>, second: Vec<Vec<u8>>, compare: &Compare) -> Vec<Vec<u8>> {
+    let mut merged = Vec::with_capacity(first.len() + second.len());
+    let mut first = first.into_iter().peekable();
+    let mut second = second.into_iter().peekable();
+    while let (Some(a), Some(b)) = (first.peek(), second.peek()) {
+        let next = if compare(b, a) == Ordering::Less {
+            second.next()
+        } else {
+            first.next()
+        };
+        merged.extend(next);
+    }
+    merged.extend(first);
+    merged.extend(second);
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parallel_sort_is_stable() {
+        // sorts by the first character only, so the order of the rest shows
+        // whether equal lines kept their places
+        let compare = |a: &[u8], b: &[u8]| a[..1].cmp(&b[..1]);
+        let lines: Vec<Vec<u8>> = (0..100_000)
+            .map(|i| format!("{}{:06}", (b'a' + (i * 7 % 26) as u8) as char, i).into_bytes())
+            .collect();
+
+        let mut expected = lines.clone();
+        expected.sort_by(|a, b| compare(a, b));
+        for threads in [1, 2, 3, 8] {
+            assert_eq!(sort(lines.clone(), &compare, threads), expected);
+        }
+    }
+
+    #[test]
+    fn test_parallel_sort_small_input() {
+        let lines = vec![b"b".to_vec(), b"a".to_vec()];
+        assert_eq!(sort(lines, &|a, b| a.cmp(b), 4), [b"a", b"b"]);
+    }
+}
@@ -0,0 +1,177 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+//! The version order of `-V`, in which `file-1.9` comes before `file-1.10`.
+//!
+//! This is the order of GNU `filevercmp`: the text is split into runs of
+//! digits, which are compared as numbers, and runs of other characters, in
+//! which letters come before other characters, and `~` before anything, even
+//! the end of the text. A file suffix such as `.tar.gz` is only compared if
+//! the rest is equal. `.` and `..` come first, then other names starting with
+//! a dot.
+
+use std::cmp::Ordering;
+
+/// Compares two strings in the version order.
+pub fn compare(a: &[u8], b: &[u8]) -> Ordering {
+    match (a.is_empty(), b.is_empty()) {
+        (true, true) => return Ordering::Equal,
+        (true, false) => return Ordering::Less,
+        (false, true) => return Ordering::Greater,
+        (false, false) => {}
+    }
+
+    match (a[0] == b'.', b[0] == b'.') {
+        (true, false) => return Ordering::Less,
+        (false, true) => return Ordering::Greater,
+        (true, true) => {
+            for special in [&b"."[..], b".."] {
+                match (a == special, b == special) {
+                    (true, true) => return Ordering::Equal,
+                    (true, false) => return Ordering::Less,
+                    (false, true) => return Ordering::Greater,
+                    (false, false) => {}
+                }
+            }
+        }
+        (false, false) => {}
+    }
+
+    let prefix1 = &a[..prefix_len(a)];
+    let prefix2 = &b[..prefix_len(b)];
+    match compare_versions(prefix1, prefix2) {
+        Ordering::Equal if prefix1.len() < a.len() || prefix2.len() < b.len() => {
+            compare_versions(a, b)
+        }
+        ordering => ordering,
+    }
+}
+
+// The length of `s` without its file suffix: a dot followed by a letter or
+// `~`, then letters, digits and `~`, repeated any number of times. The first
+// character is never part of the suffix
+fn prefix_len(s: &[u8]) -> usize {
+    let is_suffix_char = |c: u8| c.is_ascii_alphanumeric() || c == b'~';
+    let mut prefix_len = 0;
+    let mut i = 0;
+    while i < s.len() {
+        i += 1;
+        prefix_len = i;
+        while i + 1 < s.len()
+            && s[i] == b'.'
+            && (s[i + 1].is_ascii_alphabetic() || s[i + 1] == b'~')
+        {
+            i += 2;
+            while i < s.len() && is_suffix_char(s[i]) {
+                i += 1;
+            }
+        }
+    }
+    prefix_len
+}
+
+// The order of a character in a run of non-digits. The end of the text
+// counts as 0, which is also the order of a digit
+fn char_order(c: Option<&u8>) -> i32 {
+    match c {
+        None => 0,
+        Some(c) if c.is_ascii_digit() => 0,
+        Some(c) if c.is_ascii_alphabetic() => *c as i32,
+        Some(b'~') => -1,
+        Some(c) => *c as i32 + 256,
+    }
+}
+
+fn compare_versions(s1: &[u8], s2: &[u8]) -> Ordering {
+    let is_digit = |s: &[u8], i: usize| s.get(i).is_some_and(u8::is_ascii_digit);
+    let (mut i, mut j) = (0, 0);
+    while i < s1.len() || j < s2.len() {
+        while (i < s1.len() && !is_digit(s1, i)) || (j < s2.len() && !is_digit(s2, j)) {
+            let ordering = char_order(s1.get(i)).cmp(&char_order(s2.get(j)));
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+            i += 1;
+            j += 1;
+        }
+
+        while s1.get(i) == Some(&b'0') {
+            i += 1;
+        }
+        while s2.get(j) == Some(&b'0') {
+            j += 1;
+        }
+        let mut first_difference = Ordering::Equal;
+        while is_digit(s1, i) && is_digit(s2, j) {
+            if first_difference == Ordering::Equal {
+                first_difference = s1[i].cmp(&s2[j]);
+            }
+            i += 1;
+            j += 1;
+        }
+        // the longer number is the larger
+        if is_digit(s1, i) {
+            return Ordering::Greater;
+        }
+        if is_digit(s2, j) {
+            return Ordering::Less;
+        }
+        if first_difference != Ordering::Equal {
+            return first_difference;
+        }
+    }
+    Ordering::Equal
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_ordered(versions: &[&str]) {
+        for pair in versions.windows(2) {
+            assert_eq!(
+                compare(pair[0].as_bytes(), pair[1].as_bytes()),
+                Ordering::Less,
+                "{:?}",
+                pair
+            );
+            assert_eq!(
+                compare(pair[1].as_bytes(), pair[0].as_bytes()),
+                Ordering::Greater,
+                "{:?}",
+                pair
+            );
+        }
+    }
+
+    #[test]
+    fn test_numbers_in_versions() {
+        assert_ordered(&["file-1.2", "file-1.9", "file-1.10", "file-2.0"]);
+        assert_ordered(&["a1", "a01b", "a2"]);
+        assert_eq!(compare(b"a01", b"a1"), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_tilde_and_letters() {
+        assert_ordered(&["1.0~rc1", "1.0", "1.0a", "1.0+", "1.0-1"]);
+    }
+
+    #[test]
+    fn test_file_suffixes() {
+        assert_ordered(&["foo-1.2.tar.gz", "foo-1.10.tar.gz"]);
+        assert_ordered(&["foo.tar", "foo.tar.gz"]);
+        assert_eq!(prefix_len(b"foo-1.2.tar.gz"), 7);
+        assert_eq!(prefix_len(b".bashrc"), 7);
+    }
+
+    #[test]
+    fn test_dot_files() {
+        assert_ordered(&["", ".", "..", ".a", "a"]);
+    }
+}
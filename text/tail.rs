@@ -1,42 +1,35 @@
 use clap::Parser;
 use gettextrs::{bind_textdomain_codeset, setlocale, textdomain, LocaleCategory};
-use notify_debouncer_full::new_debouncer;
-use notify_debouncer_full::notify::event::{ModifyKind, RemoveKind};
-use notify_debouncer_full::notify::{EventKind, RecursiveMode, Watcher};
+use notify_debouncer_full::notify::{self, Config, EventKind, PollWatcher, RecursiveMode, Watcher};
 use plib::PROJECT_NAME;
-use std::fs::File;
-use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{self, BufRead, Read, Seek, SeekFrom, Write};
+use std::os::fd::AsFd;
+use std::os::unix::fs::MetadataExt;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::mpsc;
 use std::time::Duration;
 
-/// A wrapper type for `isize` that defaults to negative values if no sign is provided.
-///
-/// The `SignedIsize` struct is a simple wrapper around the `isize` type, designed to parse
-/// a string into an `isize` value that defaults to negative if no explicit sign is provided.
-#[derive(Debug, Clone)]
-struct SignedIsize(isize);
+/// A number of lines or bytes: the last ones of the input with `N` or `-N`, or the ones
+/// from the `N`th on, counting from 1, with `+N`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Count {
+    Last(u64),
+    From(u64),
+}
 
-impl FromStr for SignedIsize {
-    type Err = <isize as FromStr>::Err;
+impl FromStr for Count {
+    type Err = <u64 as FromStr>::Err;
 
-    /// Parses a string slice into a `SignedIsize`.
-    ///
-    /// If the string starts with a '-' or '+' sign, it parses it as is. If no sign is provided,
-    /// it defaults to a negative value.
-    ///
-    /// # Arguments
-    /// * `s` - A string slice to parse into a `SignedIsize`.
-    ///
-    /// # Returns
-    /// * `Ok(SignedIsize)` - If parsing is successful.
-    /// * `Err(Self::Err)` - If parsing fails (e.g., due to an invalid integer string).
-    ///
+    /// Parses a string slice into a `Count`, which counts from the end if no sign is
+    /// provided.
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s.starts_with('-') || s.starts_with('+') {
-            Ok(SignedIsize(s.parse()?))
+        if let Some(n) = s.strip_prefix('+') {
+            Ok(Count::From(n.parse()?))
         } else {
-            Ok(SignedIsize(-s.parse::<isize>()?))
+            Ok(Count::Last(s.strip_prefix('-').unwrap_or(s).parse()?))
         }
     }
 }
@@ -45,20 +38,33 @@ impl FromStr for SignedIsize {
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
-    /// The number of lines to print from the end of the file
-    #[arg(short = 'n')]
-    lines: Option<SignedIsize>,
+    /// The number of lines to print from the end of the file, or with +N, the line to
+    /// start printing from
+    #[arg(short = 'n', allow_hyphen_values = true)]
+    lines: Option<Count>,
 
-    /// The number of bytes to print from the end of the file
-    #[arg(short = 'c')]
-    bytes: Option<SignedIsize>,
+    /// The number of bytes to print from the end of the file, or with +N, the byte to
+    /// start printing from
+    #[arg(short = 'c', allow_hyphen_values = true)]
+    bytes: Option<Count>,
 
     /// Output appended data as the file grows
     #[arg(short = 'f')]
     follow: bool,
 
-    /// The file to read
-    file: Option<PathBuf>,
+    /// Output appended data as the file grows, following the file by its name: a file that
+    /// is renamed or removed and created again is opened again, and a file that can't be
+    /// opened is retried
+    #[arg(short = 'F')]
+    follow_name: bool,
+
+    /// How often, in seconds, the followed files are checked when there is no notification
+    /// of their changes, because the platform doesn't have any or one was missed
+    #[arg(short = 's', long = "sleep-interval", default_value_t = 1.0)]
+    sleep_interval: f64,
+
+    /// The files to read, with a header before each one if there is more than one
+    files: Vec<PathBuf>,
 }
 
 impl Args {
@@ -74,337 +80,420 @@ impl Args {
         }
 
         if self.bytes.is_none() && self.lines.is_none() {
-            self.lines = Some(SignedIsize(-10));
+            self.lines = Some(Count::Last(10));
+        }
+
+        if !(self.sleep_interval.is_finite() && self.sleep_interval > 0.0) {
+            return Err(format!(
+                "invalid number of seconds: '{}'",
+                self.sleep_interval
+            ));
         }
 
         Ok(())
     }
 }
 
-/// Prints the last `n` lines from the given reader.
-///
-/// This function reads from a reader and prints the last `n` lines to standard output.
-/// If `n` is negative, it treats `n` as counting from the end of the file. If `n` is
-/// non-negative, it counts lines from the start of the file until `n` lines are skipped,
-/// then it prints the rest of the file.
-///
-/// # Type Parameters
-/// * `R` - A type that implements `Read` and `BufRead`.
-///
-/// # Arguments
-/// * `reader` - A mutable reference to a reader to read bytes from.
-/// * `n` - The number of lines to print from the end. Negative values indicate counting from the end.
-///
-/// # Returns
-/// * `Ok(())` - If the operation completes successfully.
-/// * `Err(Box<dyn std::error::Error>)` - If an error occurs during reading.
-///
-fn print_last_n_lines<R: Read + BufRead>(
-    reader: &mut R,
-    n: isize,
-) -> Result<(), Box<dyn std::error::Error>> {
-    // Check if `n` is negative. If so, treat `n` as counting from the end.
-    if n < 0 {
-        // Convert `n` to an unsigned integer.
-        let n = n.unsigned_abs();
-
-        // Create a vector to store the last `n` lines. Preallocate memory for efficiency.
-        let mut lines = Vec::with_capacity(n);
-
-        // Temporary storage for each line read from the reader.
-        let mut line = String::new();
-
-        // Read each line and store the last `n` lines.
-        while reader.read_line(&mut line).map_err(|e| e.to_string())? != 0 {
-            // If the vector is full, remove the first (oldest) line.
-            if lines.len() == n {
-                lines.remove(0);
-            }
-
-            // Add the current line to the vector.
-            lines.push(line.clone());
+/// How the last part of an input is found. A regular file can be read from any offset,
+/// so its last part is found by going back from its end; anything else, like a pipe or
+/// a terminal, has to be read up to its end, keeping the last part in memory.
+enum InputKind {
+    /// A regular file, read from `start`, its offset when it was opened, up to `end`.
+    Seekable {
+        start: u64,
+        end: u64,
+    },
+    Stream,
+}
 
-            // Clear the temporary storage for the next line.
-            line.clear();
+impl InputKind {
+    fn of(file: &mut File) -> io::Result<InputKind> {
+        let metadata = file.metadata()?;
+        if !metadata.is_file() {
+            return Ok(InputKind::Stream);
         }
+        let start = file.stream_position()?;
+        Ok(InputKind::Seekable {
+            start,
+            end: metadata.len().max(start),
+        })
+    }
+}
 
-        // Print the collected lines, removing any trailing newline characters.
-        for line in lines {
-            println!("{}", line.trim_end());
-        }
-    } else {
-        // If `n` is non-negative, count lines from the start of the file.
-        let mut n = n as usize;
+/// Finds the offset of the last `n` lines of a regular file, from `start` to `end`, by
+/// reading it backwards a block at a time, so that only the blocks with those lines are
+/// read however large the file is. A newline at the end of the file ends its last line.
+fn find_last_lines(file: &mut File, start: u64, end: u64, n: u64) -> io::Result<u64> {
+    if n == 0 {
+        return Ok(end);
+    }
 
-        // Ensure `n` is at least 1 to avoid unnecessary work when `n` is 0.
-        if n == 0 {
-            n = 1;
+    let mut buffer = vec![0; plib::BUFSZ];
+    let block_size = buffer.len() as u64;
+    let mut pos = end;
+    let mut newlines = 0;
+    while pos > start {
+        // The last block is read up to the end, and the blocks before it are aligned
+        let block_start = ((pos - 1) / block_size * block_size).max(start);
+        let block = &mut buffer[..(pos - block_start) as usize];
+        file.seek(SeekFrom::Start(block_start))?;
+        file.read_exact(block)?;
+
+        let mut block = &block[..];
+        if pos == end {
+            block = block.strip_suffix(b"\n").unwrap_or(block);
         }
+        for i in memchr::memrchr_iter(b'\n', block) {
+            newlines += 1;
+            if newlines == n {
+                return Ok(block_start + i as u64 + 1);
+            }
+        }
+        pos = block_start;
+    }
+    Ok(start)
+}
 
-        // Buffer to read a single byte at a time.
-        let mut empty_buffer = [0; 1];
-        let mut line_count = 0;
-
-        // Skip the first `n` lines.
-        if n != 1 {
-            'skip_lines: loop {
-                // Read a single byte from the reader.
-                let bytes_read = reader.read(&mut empty_buffer)?;
-                if bytes_read == 0 {
-                    break;
-                }
-
-                // Check if the byte is a newline character.
-                if empty_buffer[0] == b'\n' {
-                    line_count += 1;
-
-                    // If the required number of lines are skipped, exit the loop.
-                    if line_count == n - 1 {
-                        break 'skip_lines;
-                    }
+/// Prints the lines selected by `count` from the file.
+fn print_lines(file: &mut File, count: Count, out: &mut impl Write) -> io::Result<()> {
+    match (count, InputKind::of(file)?) {
+        (Count::Last(n), InputKind::Seekable { start, end }) => {
+            let offset = find_last_lines(file, start, end, n)?;
+            file.seek(SeekFrom::Start(offset))?;
+            io::copy(file, out)?;
+        }
+        (Count::Last(n), InputKind::Stream) => {
+            // The last `n` lines read so far
+            let mut reader = io::BufReader::new(file);
+            let mut lines = VecDeque::new();
+            let mut line = Vec::new();
+            while reader.read_until(b'\n', &mut line)? != 0 {
+                lines.push_back(std::mem::take(&mut line));
+                if lines.len() as u64 > n {
+                    lines.pop_front();
                 }
             }
+            for line in &lines {
+                out.write_all(line)?;
+            }
         }
-
-        // Buffer to read chunks of data from the reader.
-        let mut buffer = [0; 1024];
-
-        // Read and print the remaining lines.
-        loop {
-            // Read up to 1024 bytes into the buffer.
-            let bytes_read = reader
-                .read(&mut buffer)
-                .map_err(|e| format!("Failed to read: {}", e))?;
-
-            // If no more bytes are read, exit the loop.
-            if bytes_read == 0 {
-                break;
+        (Count::From(n), _) => {
+            let mut reader = io::BufReader::new(file);
+            let mut line = Vec::new();
+            for _ in 1..n {
+                line.clear();
+                if reader.read_until(b'\n', &mut line)? == 0 {
+                    break;
+                }
             }
-
-            // Print the bytes read as a string.
-            print_bytes(&buffer[..bytes_read]);
+            io::copy(&mut reader, out)?;
         }
     }
-
-    // Return `Ok(())` to indicate successful execution.
     Ok(())
 }
 
-/// Prints the last `n` bytes from the given reader.
-///
-/// This function reads from a reader and prints the last `n` bytes to standard output.
-/// If `n` is negative, it treats `n` as counting from the end of the file. If `n` is
-/// non-negative, it skips `n` bytes from the start of the file and then prints the rest
-/// of the file.
-///
-/// # Type Parameters
-/// * `R` - A type that implements `Read`.
-///
-/// # Arguments
-/// * `buf_reader` - A mutable reference to a reader to read bytes from.
-/// * `n` - The number of bytes to print from the end. Negative values indicate counting from the end.
-///
-/// # Returns
-/// * `Ok(())` - If the operation completes successfully.
-/// * `Err(Box<dyn std::error::Error>)` - If an error occurs during reading.
-///
-fn print_last_n_bytes<R: Read>(
-    buf_reader: &mut R,
-    n: isize,
-) -> Result<(), Box<dyn std::error::Error>> {
-    // Check if `n` is negative. If so, treat `n` as counting from the end.
-    if n < 0 {
-        // Convert `n` to an unsigned integer.
-        let n = n.unsigned_abs();
-
-        // Buffer to read `n` bytes at a time.
-        let mut buffer_1 = vec![0; n];
-
-        // Buffer to store the last `n` bytes read.
-        let mut buffer_2 = vec![];
-
-        // Continuously read bytes into buffer_1.
-        loop {
-            let bytes_read = buf_reader
-                .read(&mut buffer_1)
-                .map_err(|e| format!("Failed to read: {}", e))?;
-
-            // If the number of bytes read is less than the buffer size, we're at the end of the file.
-            if bytes_read < buffer_1.len() {
-                // Add the bytes read to buffer_2.
-                buffer_2.extend(&buffer_1[..bytes_read]);
-
-                // If buffer_2 contains fewer than `n` bytes, print all of them.
-                if buffer_2.len() < n {
-                    print_bytes(&buffer_2);
-                } else {
-                    // Otherwise, print the last `n` bytes from buffer_2.
-                    let start = buffer_2.len() - n;
-                    print_bytes(&buffer_2[start..]);
+/// Prints the bytes selected by `count` from the file.
+fn print_bytes(file: &mut File, count: Count, out: &mut impl Write) -> io::Result<()> {
+    match (count, InputKind::of(file)?) {
+        (Count::Last(n), InputKind::Seekable { start, end }) => {
+            file.seek(SeekFrom::Start(end.saturating_sub(n).max(start)))?;
+            io::copy(file, out)?;
+        }
+        (Count::Last(n), InputKind::Stream) => {
+            // The last `n` bytes read so far
+            let mut bytes = VecDeque::new();
+            let mut buffer = vec![0; plib::BUFSZ];
+            loop {
+                let len = file.read(&mut buffer)?;
+                if len == 0 {
+                    break;
                 }
-
-                // Exit the loop since we've reached the end of the file.
-                break;
+                bytes.extend(&buffer[..len]);
+                let excess = (bytes.len() as u64).saturating_sub(n) as usize;
+                bytes.drain(..excess);
             }
-
-            // Clone buffer_1 into buffer_2 for the next iteration.
-            buffer_2.clone_from(&buffer_1);
+            let (front, back) = bytes.as_slices();
+            out.write_all(front)?;
+            out.write_all(back)?;
         }
-    } else {
-        // If `n` is non-negative, skip the first `n` bytes.
-
-        // Convert `n` to an unsigned integer.
-        let mut skip = n as usize;
+        (Count::From(n), InputKind::Seekable { start, end }) => {
+            let offset = start.saturating_add(n.saturating_sub(1)).min(end);
+            file.seek(SeekFrom::Start(offset))?;
+            io::copy(file, out)?;
+        }
+        (Count::From(n), InputKind::Stream) => {
+            io::copy(&mut file.take(n.saturating_sub(1)), &mut io::sink())?;
+            io::copy(file, out)?;
+        }
+    }
+    Ok(())
+}
 
-        // Buffer to read one byte at a time.
-        let mut empty_buffer = [0; 1];
+/// A file followed with `-f` or `-F`, read from where its output stopped.
+struct Followed {
+    path: PathBuf,
+    // None while the file can't be opened, with `-F`
+    file: Option<File>,
+    pos: u64,
+}
 
-        // Skip the first `n` bytes.
-        while skip > 1 {
-            let bytes_read = buf_reader.read(&mut empty_buffer)?;
-            if bytes_read == 0 {
-                break;
-            }
-            skip -= 1;
+impl Followed {
+    /// Reads the data appended to the file since the last read. A file that became
+    /// shorter than what was read was truncated, and is read again from the start.
+    fn read_file(&mut self) -> io::Result<Vec<u8>> {
+        let mut new_data = vec![];
+        let Some(file) = &mut self.file else {
+            return Ok(new_data);
+        };
+
+        let len = file.metadata()?.len();
+        if len < self.pos {
+            eprintln!("tail: {}: file truncated", self.path.display());
+            self.pos = 0;
         }
 
-        // Buffer to read chunks of data from the reader.
-        let mut buffer = [0; 1024];
+        file.seek(SeekFrom::Start(self.pos))?;
+        self.pos += file.read_to_end(&mut new_data)? as u64;
+        Ok(new_data)
+    }
 
-        // Read and print the remaining bytes.
-        loop {
-            let bytes_read = buf_reader
-                .read(&mut buffer)
-                .map_err(|e| format!("Failed to read: {}", e))?;
+    /// Reads the data appended to the file since the last read. Following by name, the
+    /// rest of a file that was renamed or removed is read, and then the file that has the
+    /// name now, from its start.
+    fn read_new(&mut self, by_name: bool) -> io::Result<Vec<u8>> {
+        let mut new_data = self.read_file()?;
+        if !by_name {
+            return Ok(new_data);
+        }
+
+        let name = self.path.display();
+        match fs::metadata(&self.path) {
+            Err(e) => {
+                if self.file.take().is_some() {
+                    eprintln!("tail: '{}' has become inaccessible: {}", name, e);
+                }
+            }
+            Ok(metadata) => {
+                let same_file = self
+                    .file
+                    .as_ref()
+                    .and_then(|file| file.metadata().ok())
+                    .is_some_and(|m| m.dev() == metadata.dev() && m.ino() == metadata.ino());
+                if same_file {
+                    return Ok(new_data);
+                }
 
-            // If no more bytes are read, exit the loop.
-            if bytes_read == 0 {
-                break;
+                // A file that can't be opened yet is retried the next time
+                if let Ok(file) = File::open(&self.path) {
+                    if self.file.is_some() {
+                        eprintln!("tail: '{}' has been replaced;  following new file", name);
+                    } else {
+                        eprintln!("tail: '{}' has appeared;  following new file", name);
+                    }
+                    self.file = Some(file);
+                    self.pos = 0;
+                    new_data.extend(self.read_file()?);
+                }
             }
+        }
+        Ok(new_data)
+    }
+}
 
-            // Print the bytes read as a string.
-            print_bytes(&buffer[..bytes_read]);
+/// Watches the files for changes with the notification of the platform, inotify or
+/// kqueue, or by polling them every `interval` if that isn't available. Following by
+/// name, the directories of the files are watched, which shows files that are created
+/// and renamed.
+fn watch_files(
+    files: &[Followed],
+    by_name: bool,
+    interval: Duration,
+    tx: mpsc::Sender<notify::Result<notify::Event>>,
+) -> notify::Result<Box<dyn Watcher>> {
+    let mut paths: Vec<&Path> = files
+        .iter()
+        .map(|f| match f.path.parent() {
+            Some(dir) if by_name && dir.as_os_str().is_empty() => Path::new("."),
+            Some(dir) if by_name => dir,
+            _ => &f.path,
+        })
+        .collect();
+    paths.sort();
+    paths.dedup();
+    let watch = |watcher: &mut dyn Watcher| {
+        paths
+            .iter()
+            .try_for_each(|path| watcher.watch(path, RecursiveMode::NonRecursive))
+    };
+
+    if let Ok(mut watcher) = notify::recommended_watcher(tx.clone()) {
+        if watch(&mut watcher).is_ok() {
+            return Ok(Box::new(watcher));
         }
     }
 
-    // Return `Ok(())` to indicate successful execution.
-    Ok(())
+    let mut watcher = PollWatcher::new(tx, Config::default().with_poll_interval(interval))?;
+    watch(&mut watcher)?;
+    Ok(Box::new(watcher))
 }
 
-/// Prints a slice of bytes to standard output.
+/// Outputs the data appended to the files as they grow, with a header when the data
+/// comes from a different file than the last data, if there is more than one file.
+/// `current` is the file whose data was output last, if it is followed.
 ///
-/// This function attempts to interpret the byte slice as a UTF-8 string and prints it.
-/// If the byte slice is not valid UTF-8, it prints each byte as a character.
+/// The files are checked when there is a notification of a change, and every `interval`
+/// in case there is none.
 ///
-/// # Arguments
-/// * `bytes` - A slice of bytes to print.
+/// # Errors
+/// This function will return an error if the files can't be watched when following them
+/// by descriptor, or an error occurs while reading them.
 ///
-fn print_bytes(bytes: &[u8]) {
-    match std::str::from_utf8(bytes) {
-        Ok(valid_str) => print!("{}", valid_str),
-        Err(_) => {
-            for byte in bytes {
-                print!("{}", *byte as char);
+fn follow(
+    mut files: Vec<Followed>,
+    by_name: bool,
+    interval: Duration,
+    headers: bool,
+    mut current: Option<usize>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (tx, rx) = mpsc::channel();
+    // Following by name, the files are still checked every interval if they can't be
+    // watched, for example because their directory doesn't exist yet
+    let _watcher = match watch_files(&files, by_name, interval, tx.clone()) {
+        Ok(watcher) => Some(watcher),
+        Err(_) if by_name => None,
+        Err(e) => return Err(e.into()),
+    };
+
+    loop {
+        match rx.recv_timeout(interval) {
+            Ok(Ok(event)) if matches!(event.kind, EventKind::Access(_)) => continue,
+            Ok(Err(e)) => eprintln!("tail: watch error: {}", e),
+            _ => {}
+        }
+
+        let mut stdout = io::stdout().lock();
+        for (i, followed) in files.iter_mut().enumerate() {
+            let new_data = followed.read_new(by_name)?;
+            if new_data.is_empty() {
+                continue;
+            }
+            if headers && current != Some(i) {
+                writeln!(stdout, "\n==> {} <==", followed.path.display())?;
             }
+            current = Some(i);
+            stdout.write_all(&new_data)?;
         }
+        stdout.flush()?;
+    }
+}
+
+/// Prints the last part of the file or stdin, selected by the number of lines or bytes.
+fn print_tail(args: &Args, file: &mut File) -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+    match args.bytes {
+        Some(bytes) => print_bytes(file, bytes, &mut stdout)?,
+        None => print_lines(file, args.lines.unwrap(), &mut stdout)?,
     }
+    stdout.flush()
 }
 
 /// The main logic for the `tail` command.
 ///
 /// This function processes the command-line arguments to determine how many lines or bytes
-/// to print from the end of a specified file or standard input. It supports an option to
-/// follow the file, printing new data as it is appended to the file.
+/// to print from the end of the specified files or standard input. It supports an option to
+/// follow the files, printing new data as it is appended to them.
 ///
 /// # Arguments
 /// * `args` - The command-line arguments parsed into an `Args` struct.
 ///
 /// # Returns
-/// * `Ok(())` - If the operation completes successfully.
-/// * `Err(Box<dyn std::error::Error>)` - If an error occurs during the operation.
+/// * `Ok(true)` - If all the files were read.
+/// * `Ok(false)` - If a file couldn't be opened or read, which is reported.
+/// * `Err(Box<dyn std::error::Error>)` - If an error occurs while following the files.
 ///
 /// # Errors
-/// This function will return an error if:
-/// - The specified file cannot be opened.
-/// - An error occurs while reading from the file or stdin.
-/// - An error occurs while watching the file for changes.
+/// This function will return an error if an error occurs while watching the files for
+/// changes.
 ///
-fn tail(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
-    // open file, or stdin
-    let file: Box<dyn Read> = {
-        if args.file == Some(PathBuf::from("-")) || args.file.is_none() {
-            Box::new(io::stdin().lock())
-        } else {
-            Box::new(File::open(args.file.as_ref().unwrap())?)
-        }
-    };
-
-    let mut reader = io::BufReader::new(file);
-
-    if let Some(bytes) = &args.bytes {
-        print_last_n_bytes(&mut reader, bytes.0)?;
+fn tail(args: &Args) -> Result<bool, Box<dyn std::error::Error>> {
+    let stdin = [PathBuf::from("-")];
+    let paths = if args.files.is_empty() {
+        &stdin[..]
     } else {
-        print_last_n_lines(&mut reader, args.lines.as_ref().unwrap().0)?;
-    }
+        &args.files[..]
+    };
+    let headers = paths.len() > 1;
+    let mut success = true;
+    let mut followed = Vec::new();
+    // The followed file whose tail was printed last
+    let mut current = None;
+
+    for (i, path) in paths.iter().enumerate() {
+        let is_stdin = path == Path::new("-");
+        let name = if is_stdin {
+            String::from("standard input")
+        } else {
+            path.display().to_string()
+        };
 
-    // If follow option is specified, continue monitoring the file
-    if args.follow && !(args.file == Some(PathBuf::from("-")) || args.file.is_none()) {
-        let file_path = args.file.as_ref().unwrap();
-
-        // Opening a file and placing the cursor at the end of the file
-        let mut file = File::open(file_path)?;
-        file.seek(SeekFrom::End(0))?;
-        let mut reader = BufReader::new(&file);
-
-        let (tx, rx) = std::sync::mpsc::channel();
-        // Automatically select the best implementation for your platform.
-        let mut debouncer = new_debouncer(Duration::from_millis(1), None, tx).unwrap();
-
-        // Add a path to be watched.
-        // below will be monitored for changes.
-        debouncer
-            .watcher()
-            .watch(Path::new(file_path), RecursiveMode::NonRecursive)?;
-
-        for res in rx {
-            match res {
-                Ok(events) => {
-                    let event = events.first().unwrap();
-                    match event.kind {
-                        EventKind::Modify(ModifyKind::Any)
-                        | EventKind::Modify(ModifyKind::Data(_))
-                        | EventKind::Modify(ModifyKind::Other) => {
-                            // If the file has been modified, check if the file was truncated
-                            let metadata = file.metadata()?;
-                            let current_size = metadata.len();
-
-                            if current_size < reader.stream_position()? {
-                                eprintln!("\ntail: {}: file truncated", file_path.display());
-                                reader.seek(SeekFrom::Start(0))?;
-                            }
-
-                            // Read the new lines and output them
-                            let mut new_data = vec![];
-                            let bytes_read = reader.read_to_end(&mut new_data)?;
-                            if bytes_read > 0 {
-                                print_bytes(&new_data);
-                                io::stdout().flush()?;
-                            }
-                        }
-                        EventKind::Remove(RemoveKind::File) => {
-                            debouncer.watcher().unwatch(Path::new(file_path))?
-                        }
-                        _ => {}
-                    }
-                }
-                Err(e) => {
-                    eprintln!("watch error: {:?}", e);
+        // open file, or stdin, which is read through its own descriptor so that a
+        // regular file redirected to it can be read from its end
+        let opened = if is_stdin {
+            io::stdin().as_fd().try_clone_to_owned().map(File::from)
+        } else {
+            File::open(path)
+        };
+        let mut file = match opened {
+            Ok(file) => file,
+            Err(e) => {
+                eprintln!("tail: cannot open '{}' for reading: {}", name, e);
+                success = false;
+                // Following by name, the file is read from its start once it appears
+                if args.follow_name && !is_stdin {
+                    followed.push(Followed {
+                        path: path.clone(),
+                        file: None,
+                        pos: 0,
+                    });
                 }
+                continue;
             }
+        };
+
+        if headers {
+            if i > 0 {
+                println!();
+            }
+            println!("==> {} <==", name);
+        }
+        if let Err(e) = print_tail(args, &mut file) {
+            eprintln!("tail: {}: {}", name, e);
+            success = false;
+            continue;
+        }
+
+        // Standard input isn't followed; a followed file is followed from where the
+        // reading stopped
+        if (args.follow || args.follow_name) && !is_stdin {
+            current = Some(followed.len());
+            let pos = file.stream_position()?;
+            followed.push(Followed {
+                path: path.clone(),
+                file: Some(file),
+                pos,
+            });
+        } else {
+            current = None;
         }
     }
+    io::stdout().flush()?;
 
-    Ok(())
+    if !followed.is_empty() {
+        let interval = Duration::from_secs_f64(args.sleep_interval);
+        follow(followed, args.follow_name, interval, headers, current)?;
+    }
+
+    Ok(success)
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -415,9 +504,13 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     args.validate_args()?;
     let mut exit_code = 0;
 
-    if let Err(err) = tail(&args) {
-        exit_code = 1;
-        eprint!("{}", err);
+    match tail(&args) {
+        Ok(true) => {}
+        Ok(false) => exit_code = 1,
+        Err(err) => {
+            exit_code = 1;
+            eprintln!("tail: {}", err);
+        }
     }
 
     std::process::exit(exit_code)
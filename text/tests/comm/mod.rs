@@ -65,3 +65,92 @@ fn comm_suppress_third_column() {
         "comm.3",
     );
 }
+
+fn comm_test(args: &[&str], stdin: &str, expected_out: &str, expected_err: &str, exit_code: i32) {
+    run_test(TestPlan {
+        cmd: String::from("comm"),
+        args: args.iter().map(|&s| s.into()).collect(),
+        expected_out: String::from(expected_out),
+        expected_err: String::from(expected_err),
+        stdin_data: String::from(stdin),
+        expected_exit_code: exit_code,
+    });
+}
+
+#[test]
+fn comm_unsorted_input_warns() {
+    comm_test(
+        &["-", "tests/comm/comm.file1"],
+        "banana\napple\n",
+        "\tapple\n\t\tbanana\napple\n\tcarrot\n",
+        "file 1 is not in sorted order\ninput is not in sorted order\n",
+        1,
+    );
+}
+
+#[test]
+fn comm_check_order() {
+    // Unsorted lines that are all in both files only fail with --check-order
+    let input = "banana\napple\n";
+    let unsorted = "tests/comm/comm.unsorted";
+    comm_test(&["-", unsorted], input, "\t\tbanana\n\t\tapple\n", "", 0);
+    comm_test(
+        &["--check-order", "-", unsorted],
+        input,
+        "\t\tbanana\n",
+        "file 1 is not in sorted order\n",
+        1,
+    );
+    comm_test(
+        &["--nocheck-order", "-", "tests/comm/comm.file1"],
+        input,
+        "\tapple\n\t\tbanana\napple\n\tcarrot\n",
+        "",
+        0,
+    );
+}
+
+#[test]
+fn comm_output_delimiter() {
+    comm_test(
+        &[
+            "--output-delimiter=::",
+            "tests/comm/comm.file1",
+            "tests/comm/comm.file2",
+        ],
+        "",
+        "apple\n::::banana\n::::carrot\n::date\n",
+        "",
+        0,
+    );
+    comm_test(
+        &[
+            "-1",
+            "--output-delimiter=|",
+            "tests/comm/comm.file1",
+            "tests/comm/comm.file2",
+        ],
+        "",
+        "|banana\n|carrot\ndate\n",
+        "",
+        0,
+    );
+}
+
+#[test]
+fn comm_zero_terminated() {
+    comm_test(
+        &["-z", "-12", "-", "tests/comm/comm.zero"],
+        "a b\0c\nd\0",
+        "a b\0c\nd\0",
+        "",
+        0,
+    );
+    comm_test(
+        &["-z", "-3", "-", "tests/comm/comm.file1"],
+        "x\0",
+        "\tapple\nbanana\ncarrot\n\0x\0",
+        "",
+        0,
+    );
+}
@@ -354,3 +354,93 @@ fn test_stdin_no_hyphen() {
         "081ca869c86b 41 truefalse/src/false.rs\n",
     );
 }
+
+#[test]
+fn test_characters_multibyte() {
+    cut_test(&["-c", "2-3"], "äöüß\n", "öü\n");
+    cut_test(&["-c", "1,4"], "äöüß\n", "äß\n");
+}
+
+#[test]
+fn test_bytes_no_split() {
+    // "ä" and "ö" are two bytes each
+    cut_test(&["-b", "1-2,5"], "äöx\n", "äx\n");
+    cut_test(&["-n", "-b", "1-3"], "äöx\n", "ä\n");
+    cut_test(&["-n", "-b", "2-5"], "äöx\n", "äöx\n");
+    cut_test(&["-n", "-b", "1"], "äöx\n", "\n");
+}
+
+#[test]
+fn test_open_ranges() {
+    cut_test(&["-c", "-3"], "abcdef\n", "abc\n");
+    cut_test(&["-c", "4-"], "abcdef\n", "def\n");
+    cut_test(&["-b", "-2,5-"], "abcdef\n", "abef\n");
+    cut_test(&["-d", ":", "-f", "-2"], "a:b:c\n", "a:b\n");
+}
+
+#[test]
+fn test_fields_default_tab() {
+    cut_test(&["-f", "2"], "a\tb\tc\n", "b\n");
+}
+
+#[test]
+fn test_invalid_ranges() {
+    for list in ["0", "3-2", "-", "a"] {
+        run_test(TestPlan {
+            cmd: String::from("cut"),
+            args: vec![String::from("-c"), String::from(list)],
+            stdin_data: String::from("abc\n"),
+            expected_out: String::new(),
+            expected_err: match list {
+                "0" => String::from("positions are numbered from 1\n"),
+                "3-2" => String::from("invalid decreasing range: '3-2'\n"),
+                "-" => String::from("invalid range with no endpoint: -\n"),
+                _ => String::from("invalid byte, character or field list: 'a'\n"),
+            },
+            expected_exit_code: 1,
+        });
+    }
+}
+
+#[test]
+fn test_complement() {
+    cut_test(&["--complement", "-b", "2-3,5"], "abcdef\n", "adf\n");
+    cut_test(&["--complement", "-c", "1"], "äöü\n", "öü\n");
+    cut_test(
+        &["--complement", "-d", ",", "-f", "2"],
+        "a,b,c,d\n",
+        "a,c,d\n",
+    );
+    cut_test(&["--complement", "-c", "1-"], "abc\n", "\n");
+}
+
+#[test]
+fn test_output_delimiter() {
+    cut_test(
+        &["-d", ",", "-f", "1,3", "--output-delimiter", ";"],
+        "a,b,c\n",
+        "a;c\n",
+    );
+    cut_test(&["-f", "1-", "--output-delimiter= | "], "a\tb\n", "a | b\n");
+    cut_test(
+        &["-b", "1-2,5-", "--output-delimiter", ":"],
+        "abcdef\n",
+        "ab:ef\n",
+    );
+    cut_test(
+        &["--complement", "-b", "3", "--output-delimiter", ":"],
+        "abcde\n",
+        "ab:de\n",
+    );
+}
+
+#[test]
+fn test_suppress_without_delimiter() {
+    cut_test(&["-d", ",", "-f", "2"], "a,b\nnone\n", "b\nnone\n");
+    cut_test(&["-s", "-d", ",", "-f", "2"], "a,b\nnone\n", "b\n");
+    cut_test(
+        &["-s", "--complement", "-d", ",", "-f", "2"],
+        "a,b\nnone\n",
+        "a\n",
+    );
+}
@@ -9,6 +9,8 @@
 //
 
 use plib::testing::{run_test, TestPlan};
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
 
 const LINES_INPUT: &str =
     "line_{1}\np_line_{2}_s\n  line_{3}  \nLINE_{4}\np_LINE_{5}_s\nl_{6}\nline_{70}\n";
@@ -50,6 +52,33 @@ fn grep_test(
     });
 }
 
+/// Runs grep in `locale`, which the tests otherwise inherit.
+fn run_grep_in_locale(locale: &str, args: &[&str], input: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_grep"))
+        .args(args)
+        .env("LC_ALL", locale)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(input).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn grep_locale_test(
+    locale: &str,
+    args: &[&str],
+    test_data: &str,
+    expected_output: &str,
+    expected_exit_code: i32,
+) {
+    let output = run_grep_in_locale(locale, args, test_data.as_bytes());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), expected_output);
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+    assert_eq!(output.status.code(), Some(expected_exit_code));
+}
+
 #[test]
 fn test_incompatible_options() {
     grep_test(
@@ -130,8 +159,8 @@ fn test_basic_regexp_03() {
         &[BRE, INVALID_LINE_INPUT_FILE],
         "",
         "line_{1}\np_line_{2}_s\n",
-        "tests/grep/invalid_line: Error reading line 2 (stream did not contain valid UTF-8)\n",
-        2,
+        "",
+        0,
     );
 }
 
@@ -230,8 +259,8 @@ fn test_basic_regexp_line_number_03() {
         &["-n", BRE, INVALID_LINE_INPUT_FILE],
         "",
         "1:line_{1}\n3:p_line_{2}_s\n",
-        "tests/grep/invalid_line: Error reading line 2 (stream did not contain valid UTF-8)\n",
-        2,
+        "",
+        0,
     );
 }
 
@@ -290,7 +319,7 @@ fn test_basic_regexp_no_messages_with_error_05() {
         "",
         "line_{1}\np_line_{2}_s\n",
         "",
-        2,
+        0,
     );
 }
 
@@ -353,8 +382,8 @@ fn test_extended_regexp_03() {
         &["-E", ERE, INVALID_LINE_INPUT_FILE],
         "",
         "line_{1}\np_line_{2}_s\n",
-        "tests/grep/invalid_line: Error reading line 2 (stream did not contain valid UTF-8)\n",
-        2,
+        "",
+        0,
     );
 }
 
@@ -459,8 +488,8 @@ fn test_extended_regexp_line_number_03() {
         &["-E", "-n", ERE, INVALID_LINE_INPUT_FILE],
         "",
         "1:line_{1}\n3:p_line_{2}_s\n",
-        "tests/grep/invalid_line: Error reading line 2 (stream did not contain valid UTF-8)\n",
-        2,
+        "",
+        0,
     );
 }
 
@@ -525,7 +554,7 @@ fn test_extended_regexp_no_messages_with_error_05() {
         "",
         "line_{1}\np_line_{2}_s\n",
         "",
-        2,
+        0,
     );
 }
 
@@ -594,8 +623,8 @@ fn test_fixed_strings_03() {
         &["-F", FIXED, INVALID_LINE_INPUT_FILE],
         "",
         "line_{1}\np_line_{2}_s\n",
-        "tests/grep/invalid_line: Error reading line 2 (stream did not contain valid UTF-8)\n",
-        2,
+        "",
+        0,
     );
 }
 
@@ -706,8 +735,8 @@ fn test_fixed_strings_line_number_03() {
         &["-F", "-n", FIXED, INVALID_LINE_INPUT_FILE],
         "",
         "1:line_{1}\n3:p_line_{2}_s\n",
-        "tests/grep/invalid_line: Error reading line 2 (stream did not contain valid UTF-8)\n",
-        2,
+        "",
+        0,
     );
 }
 
@@ -772,7 +801,7 @@ fn test_fixed_strings_no_messages_with_error_05() {
         "",
         "line_{1}\np_line_{2}_s\n",
         "",
-        2,
+        0,
     );
 }
 
@@ -1389,3 +1418,529 @@ fn test_long_names_files() {
             0,
         );
 }
+
+#[test]
+fn test_extended_regexp_alternation() {
+    grep_test(
+        &["-E", "^(l_|LINE)"],
+        LINES_INPUT,
+        "LINE_{4}\nl_{6}\n",
+        "",
+        0,
+    );
+    // Without -E, the parentheses and bar are literal
+    grep_test(&["^(l_|LINE)"], LINES_INPUT, "", "", 1);
+}
+
+#[test]
+fn test_fixed_strings_multiple_patterns() {
+    grep_test(
+        &["-F", "-e", "{4}", "-e", "{6}", "-e", "line_{7"],
+        LINES_INPUT,
+        "LINE_{4}\nl_{6}\nline_{70}\n",
+        "",
+        0,
+    );
+}
+
+#[test]
+fn test_fixed_strings_line_regexp_overlapping_patterns() {
+    // The shorter pattern matches at the start of the line too, but only the longer one matches
+    // all of it
+    grep_test(
+        &["-F", "-x", "-e", "l_", "-e", "l_{6}"],
+        LINES_INPUT,
+        "l_{6}\n",
+        "",
+        0,
+    );
+}
+
+#[test]
+fn test_fixed_strings_ignore_case_non_ascii() {
+    grep_locale_test(
+        "C.UTF-8",
+        &["-F", "-i", "ÉTÉ"],
+        "été\nete\nÉté\n",
+        "été\nÉté\n",
+        0,
+    );
+    // Only ASCII letters have a case in the C locale
+    grep_locale_test("C", &["-F", "-i", "ÉTé"], "été\nÉTé\n", "ÉTé\n", 0);
+}
+
+// a/b/y.txt, a/x.txt, c.txt, fifo, link -> a/x.txt
+fn create_recursive_tree(name: &str) -> String {
+    let test_dir = format!("{}/{}", env!("CARGO_TARGET_TMPDIR"), name);
+    std::fs::create_dir_all(format!("{}/a/b", test_dir)).unwrap();
+    std::fs::write(format!("{}/a/b/y.txt", test_dir), "no\nmatch here\n").unwrap();
+    std::fs::write(format!("{}/a/x.txt", test_dir), "match\n").unwrap();
+    std::fs::write(format!("{}/c.txt", test_dir), "none\n").unwrap();
+    let fifo = std::ffi::CString::new(format!("{}/fifo", test_dir)).unwrap();
+    assert_eq!(unsafe { libc::mkfifo(fifo.as_ptr(), 0o644) }, 0);
+    std::os::unix::fs::symlink("a/x.txt", format!("{}/link", test_dir)).unwrap();
+    test_dir
+}
+
+#[test]
+fn test_recursive() {
+    let test_dir = create_recursive_tree("grep_recursive");
+
+    // The FIFO would block if it were read, and the symlink isn't followed
+    grep_test(
+        &["-r", "match", &test_dir],
+        "",
+        &format!("{0}/a/b/y.txt:match here\n{0}/a/x.txt:match\n", test_dir),
+        "",
+        0,
+    );
+    grep_test(
+        &["-rn", "match", &format!("{}/a", test_dir)],
+        "",
+        &format!(
+            "{0}/a/b/y.txt:2:match here\n{0}/a/x.txt:1:match\n",
+            test_dir
+        ),
+        "",
+        0,
+    );
+    grep_test(
+        &["-rc", "match", &test_dir],
+        "",
+        &format!("{0}/a/b/y.txt:1\n{0}/a/x.txt:1\n{0}/c.txt:0\n", test_dir),
+        "",
+        0,
+    );
+
+    std::fs::remove_dir_all(&test_dir).unwrap();
+}
+
+#[test]
+fn test_dereference_recursive() {
+    let test_dir = create_recursive_tree("grep_dereference_recursive");
+
+    grep_test(
+        &["-R", "match", &test_dir],
+        "",
+        &format!(
+            "{0}/a/b/y.txt:match here\n{0}/a/x.txt:match\n{0}/link:match\n",
+            test_dir
+        ),
+        "",
+        0,
+    );
+    // A symlink operand is followed with -r too, and a single file isn't named in the output
+    grep_test(
+        &["-r", "match", &format!("{}/link", test_dir)],
+        "",
+        "match\n",
+        "",
+        0,
+    );
+
+    std::fs::remove_dir_all(&test_dir).unwrap();
+}
+
+#[test]
+fn test_recursive_error_continues() {
+    let test_dir = create_recursive_tree("grep_recursive_error");
+    let missing = format!("{}/missing", test_dir);
+    let dir = format!("{}/a", test_dir);
+
+    grep_test(
+        &["-r", "match", &missing, &dir],
+        "",
+        &format!("{0}/a/b/y.txt:match here\n{0}/a/x.txt:match\n", test_dir),
+        &format!("{}: No such file or directory (os error 2)\n", missing),
+        2,
+    );
+    grep_test(
+        &["-rs", "match", &missing, &dir],
+        "",
+        &format!("{0}/a/b/y.txt:match here\n{0}/a/x.txt:match\n", test_dir),
+        "",
+        2,
+    );
+
+    std::fs::remove_dir_all(&test_dir).unwrap();
+}
+
+const BINARY_INPUT: &str = "abc\0def\nmatch\n";
+
+#[test]
+fn test_binary_file_matches() {
+    grep_test(
+        &["match"],
+        BINARY_INPUT,
+        "(standard input): binary file matches\n",
+        "",
+        0,
+    );
+    grep_test(&["-c", "match"], BINARY_INPUT, "1\n", "", 0);
+    grep_test(&["nothing"], BINARY_INPUT, "", "", 1);
+}
+
+#[test]
+fn test_binary_files_text() {
+    grep_test(&["-a", "match"], BINARY_INPUT, "match\n", "", 0);
+    grep_test(
+        &["--binary-files=text", "-n", "def"],
+        BINARY_INPUT,
+        "1:abc\0def\n",
+        "",
+        0,
+    );
+}
+
+#[test]
+fn test_binary_files_without_match() {
+    grep_test(&["-I", "match"], BINARY_INPUT, "", "", 1);
+    grep_test(
+        &["--binary-files=without-match", "-c", "match"],
+        BINARY_INPUT,
+        "0\n",
+        "",
+        1,
+    );
+}
+
+#[test]
+fn test_binary_data_after_text() {
+    // The NUL comes after the first buffer of input, so the lines before it are written as text
+    let path = format!(
+        "{}/grep_binary_data_after_text",
+        env!("CARGO_TARGET_TMPDIR")
+    );
+    let text = format!("match 1\n{}\n", "-".repeat(16384));
+    std::fs::write(&path, format!("{}\0\nmatch 2\nmatch 3\n", text)).unwrap();
+
+    grep_test(
+        &["match", &path],
+        "",
+        &format!("match 1\n{}: binary file matches\n", path),
+        "",
+        0,
+    );
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_color_always() {
+    grep_test(
+        &["--color=always", "-n", "o"],
+        "foo bar\nbaz\n",
+        "\x1b[32m\x1b[K1\x1b[m\x1b[K\x1b[36m\x1b[K:\x1b[m\x1b[K\
+         f\x1b[01;31m\x1b[Ko\x1b[m\x1b[K\x1b[01;31m\x1b[Ko\x1b[m\x1b[K bar\n",
+        "",
+        0,
+    );
+    // Only the first match of an anchored pattern is at the start of the line
+    grep_test(
+        &["--color=always", "^a"],
+        "aaa\n",
+        "\x1b[01;31m\x1b[Ka\x1b[m\x1b[Kaa\n",
+        "",
+        0,
+    );
+}
+
+#[test]
+fn test_color_file_names() {
+    let path = format!("{}/grep_color_file_names", env!("CARGO_TARGET_TMPDIR"));
+    std::fs::write(&path, "match\n").unwrap();
+
+    grep_test(
+        &["--color=always", "-F", "at", &path, "-"],
+        "other\n",
+        &format!(
+            "\x1b[35m\x1b[K{}\x1b[m\x1b[K\x1b[36m\x1b[K:\x1b[m\x1b[K\
+             m\x1b[01;31m\x1b[Kat\x1b[m\x1b[Kch\n",
+            path
+        ),
+        "",
+        0,
+    );
+    grep_test(
+        &["--color=always", "-l", "at", &path],
+        "",
+        &format!("\x1b[35m\x1b[K{}\x1b[m\x1b[K\n", path),
+        "",
+        0,
+    );
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_color_never() {
+    grep_test(&["--color=never", "o"], "foo\n", "foo\n", "", 0);
+    // Not a terminal
+    grep_test(&["--colour", "o"], "foo\n", "foo\n", "", 0);
+    // No matches to highlight
+    grep_test(&["--color=always", "-v", "o"], "foo\nbar\n", "bar\n", "", 0);
+}
+
+#[test]
+fn test_color_grep_colors() {
+    let output = Command::new(env!("CARGO_BIN_EXE_grep"))
+        .args(["--color=always", "-n", "b"])
+        .env("GREP_COLORS", "ms=04:ln=:ne:xx=1")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .and_then(|mut child| {
+            child.stdin.take().unwrap().write_all(b"abc\n")?;
+            child.wait_with_output()
+        })
+        .unwrap();
+
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "1\x1b[36m:\x1b[ma\x1b[04mb\x1b[mc\n"
+    );
+    assert!(output.status.success());
+}
+
+#[test]
+fn test_null_data() {
+    // Records may contain newlines, which `.` and `-x` treat as any other character
+    grep_test(&["-z", "b"], "a\nb\0c\0ab\0", "a\nb\0ab\0", "", 0);
+    grep_test(&["-zx", "a.b"], "a\nb\0a\n", "a\nb\0", "", 0);
+    grep_test(&["-z", "-n", "-v", "b"], "ab\0c", "2:c\0", "", 0);
+    // A NUL doesn't make the input binary
+    grep_test(&["--null-data", "-c", "d"], "abc\0def\0", "1\n", "", 0);
+}
+
+#[test]
+fn test_null_file_names() {
+    let path = format!("{}/grep_null_file_names", env!("CARGO_TARGET_TMPDIR"));
+    std::fs::write(&path, "match\n").unwrap();
+
+    grep_test(
+        &["-lZ", "match", &path, "-"],
+        "other\n",
+        &format!("{}\0", path),
+        "",
+        0,
+    );
+    grep_test(
+        &["--null", "match", &path, "-"],
+        "match\n",
+        &format!("{}\0match\n(standard input)\0match\n", path),
+        "",
+        0,
+    );
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_locale_bracket_expressions() {
+    // A multibyte character is one character in a UTF-8 locale
+    grep_locale_test("C.UTF-8", &["-x", "[[:alpha:]]"], "é\na\n1\n", "é\na\n", 0);
+    grep_locale_test("C.UTF-8", &["-x", "[é]"], "é\ne\n", "é\n", 0);
+    grep_locale_test("C", &["-x", "[[:alpha:]]"], "é\na\n", "a\n", 0);
+    grep_locale_test("C", &["-x", ".."], "é\n", "é\n", 0);
+    grep_locale_test("C.UTF-8", &["-i", "-x", "É"], "é\n", "é\n", 0);
+}
+
+#[test]
+fn test_invalid_byte_sequences() {
+    // Bytes that aren't characters are selected and written as they are, without an error
+    let input = b"x\x80y\nxy\n";
+    for locale in ["C", "C.UTF-8"] {
+        let output = run_grep_in_locale(locale, &["y"], input);
+        assert_eq!(output.stdout, input);
+        assert!(output.stderr.is_empty());
+        assert!(output.status.success());
+    }
+    // Only in the C locale is the byte a character that `.` matches
+    let output = run_grep_in_locale("C", &["-c", "x.y"], input);
+    assert_eq!(output.stdout, b"1\n");
+    let output = run_grep_in_locale("C.UTF-8", &["-c", "x.y"], input);
+    assert_eq!(output.stdout, b"0\n");
+}
+
+#[test]
+fn test_large_input() {
+    // Lines longer than a read, and matches on both sides of the reads. The input is a file, as
+    // the output would fill the pipe before all of the input is written to it.
+    let path = format!("{}/grep_large_input", env!("CARGO_TARGET_TMPDIR"));
+    let long_line = format!("{}X", "a".repeat(200000));
+    let lines: String = (0..100000).map(|i| format!("l{i}\n")).collect();
+    std::fs::write(&path, format!("{long_line}\n{lines}endX")).unwrap();
+
+    grep_test(
+        &["-n", "X", &path],
+        "",
+        &format!("1:{long_line}\n100002:endX\n"),
+        "",
+        0,
+    );
+    grep_test(&["-c", "-F", "l9999", &path], "", "11\n", "", 0);
+    grep_test(&["-n", "^l50000$", &path], "", "50002:l50000\n", "", 0);
+    grep_test(&["-c", "-v", "l", &path], "", "2\n", "", 0);
+    grep_test(
+        &["--mmap", "-n", "-F", "dX", &path],
+        "",
+        "100002:endX\n",
+        "",
+        0,
+    );
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_matches_spanning_lines() {
+    // Searching many lines at once finds matches that span them, which aren't selected
+    grep_test(&["-n", "a[[:space:]]*b"], "a\nb\nab\n", "3:ab\n", "", 0);
+    grep_test(&["-n", "-x", "-F", "abc"], "ab\nabc\n", "2:abc\n", "", 0);
+    grep_test(
+        &["-n", "-e", "c$", "-e", "^a"],
+        "xc\nb\na\n",
+        "1:xc\n3:a\n",
+        "",
+        0,
+    );
+}
+
+#[test]
+fn test_mmap() {
+    let path = format!("{}/grep_mmap", env!("CARGO_TARGET_TMPDIR"));
+    let empty = format!("{}/grep_mmap_empty", env!("CARGO_TARGET_TMPDIR"));
+    std::fs::write(&path, "one\ntwo\nthree").unwrap();
+    std::fs::write(&empty, "").unwrap();
+
+    grep_test(
+        &["--mmap", "-n", "t", &path, &empty],
+        "",
+        &format!("{path}:2:two\n{path}:3:three\n"),
+        "",
+        0,
+    );
+    // Input that isn't a regular file is read
+    grep_test(&["--mmap", "o", "-"], "foo\n", "foo\n", "", 0);
+    // A file with a size of 0 is read, as it may still have content
+    #[cfg(target_os = "linux")]
+    grep_test(
+        &["--mmap", "-c", "^Name:", "/proc/self/status"],
+        "",
+        "1\n",
+        "",
+        0,
+    );
+
+    std::fs::remove_file(&path).unwrap();
+    std::fs::remove_file(&empty).unwrap();
+}
+
+const CONTEXT_INPUT: &str = "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n11\n12\n13\n";
+
+#[test]
+fn test_context() {
+    // Overlapping and adjacent groups are merged, others are separated
+    grep_test(
+        &["-n", "-C", "1", "-e", "^3$", "-e", "^5$", "-e", "^11$"],
+        CONTEXT_INPUT,
+        "2-2\n3:3\n4-4\n5:5\n6-6\n--\n10-10\n11:11\n12-12\n",
+        "",
+        0,
+    );
+    grep_test(
+        &["-A", "1", "-e", "^2$", "-e", "^4$"],
+        CONTEXT_INPUT,
+        "2\n3\n4\n5\n",
+        "",
+        0,
+    );
+    grep_test(
+        &["--before-context=2", "^1[13]$"],
+        CONTEXT_INPUT,
+        "9\n10\n11\n12\n13\n",
+        "",
+        0,
+    );
+    // -A and -B take precedence over -C
+    grep_test(
+        &["-C", "2", "-A", "0", "-n", "^7$"],
+        CONTEXT_INPUT,
+        "5-5\n6-6\n7:7\n",
+        "",
+        0,
+    );
+    grep_test(&["-c", "-C", "3", "^7$"], CONTEXT_INPUT, "1\n", "", 0);
+}
+
+#[test]
+fn test_context_multiple_files() {
+    let path = format!(
+        "{}/grep_context_multiple_files",
+        env!("CARGO_TARGET_TMPDIR")
+    );
+    std::fs::write(&path, "a\nb\nc\n").unwrap();
+
+    grep_test(
+        &["-B", "1", "-n", "[ac]", &path, "-"],
+        "x\nc\n",
+        &format!(
+            "{path}:1:a\n{path}-2-b\n{path}:3:c\n--\n(standard input)-1-x\n(standard input):2:c\n"
+        ),
+        "",
+        0,
+    );
+    grep_test(&["-v", "-A", "1", "b", &path], "", "a\nb\nc\n", "", 0);
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_only_matching() {
+    grep_test(
+        &["-o", "-n", "ab*c*"],
+        "ab abc xab\nnone\nac\n",
+        "1:ab\n1:abc\n1:ab\n3:ac\n",
+        "",
+        0,
+    );
+    grep_test(
+        &["-o", "-i", "-F", "-e", "ab", "-e", "bc"],
+        "ABC abc\n",
+        "AB\nab\n",
+        "",
+        0,
+    );
+    // No context and no matches with -v, but the lines are still selected
+    grep_test(&["-o", "-C", "1", "b"], "a\nb\nc\n", "b\n", "", 0);
+    grep_test(&["-o", "-v", "b"], "a\nb\n", "", "", 0);
+    grep_test(
+        &["--color=always", "-o", "b"],
+        "abc\n",
+        "\x1b[01;31m\x1b[Kb\x1b[m\x1b[K\n",
+        "",
+        0,
+    );
+}
+
+#[test]
+fn test_context_color() {
+    grep_test(
+        &["--color=always", "-n", "-A", "1", "-e", "^1$", "-e", "^4$"],
+        "1\n2\n3\n4\n",
+        "\x1b[32m\x1b[K1\x1b[m\x1b[K\x1b[36m\x1b[K:\x1b[m\x1b[K\x1b[01;31m\x1b[K1\x1b[m\x1b[K\n\
+         \x1b[32m\x1b[K2\x1b[m\x1b[K\x1b[36m\x1b[K-\x1b[m\x1b[K2\n\
+         \x1b[36m\x1b[K--\x1b[m\x1b[K\n\
+         \x1b[32m\x1b[K4\x1b[m\x1b[K\x1b[36m\x1b[K:\x1b[m\x1b[K\x1b[01;31m\x1b[K4\x1b[m\x1b[K\n",
+        "",
+        0,
+    );
+    // With -v, the context lines are the ones with matches
+    grep_test(
+        &["--color=always", "-v", "-B", "1", "a"],
+        "a\nb\n",
+        "\x1b[01;31m\x1b[Ka\x1b[m\x1b[K\nb\n",
+        "",
+        0,
+    );
+}
@@ -33,3 +33,30 @@ fn test_head_basic() {
         "1\n2\n3\n4\n5\n6\n7\n8\n9\n0\n",
     );
 }
+
+fn head_args_test(args: &[&str], test_data: &str, expected_output: &str) {
+    run_test(TestPlan {
+        cmd: String::from("head"),
+        args: args.iter().map(|s| String::from(*s)).collect(),
+        stdin_data: String::from(test_data),
+        expected_out: String::from(expected_output),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    });
+}
+
+#[test]
+fn test_head_bytes() {
+    head_args_test(&["-c", "3"], "abcdef\n", "abc");
+    head_args_test(&["-c", "0"], "abcdef\n", "");
+    head_args_test(&["-c", "10"], "ab\ncd\n", "ab\ncd\n");
+}
+
+#[test]
+fn test_head_bytes_multiplier() {
+    let input = "x".repeat(3000);
+    head_args_test(&["-c", "1b"], &input, &input[..512]);
+    head_args_test(&["-c", "2k"], &input, &input[..2048]);
+    head_args_test(&["-c", "2KiB"], &input, &input[..2048]);
+    head_args_test(&["-c", "2kB"], &input, &input[..2000]);
+}
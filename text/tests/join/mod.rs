@@ -138,7 +138,91 @@ fn third_field_join_test() {
     let file2 = format!("{}/tests/join/file4.txt", project_root);
     let args = ["-1", "3", "-2", "3", file1.as_str(), file2.as_str()];
 
-    let expected_output = "1 Bob HR Director HR\n2 Charlie Finance Analyst Finance\n";
+    // The join field comes first, then the other fields of each file
+    let expected_output = "HR 1 Bob 1 Director\nFinance 2 Charlie 2 Analyst\n";
 
     run_test_join(&args, &expected_output, "", 0)
 }
+
+fn join_asset(name: &str) -> String {
+    format!("{}/tests/join/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+#[test]
+fn multiple_matches_test() {
+    // Each line of a group in file 1 is joined with each line of the group in file 2
+    let (file1, file2) = (join_asset("groups1.txt"), join_asset("groups2.txt"));
+    let expected_output = "a 1 x\na 1 y\na 2 x\na 2 y\nc 4 z\n";
+
+    run_test_join(&[&file1, &file2], expected_output, "", 0)
+}
+
+#[test]
+fn unpairable_both_files_test() {
+    let (file1, file2) = (join_asset("groups1.txt"), join_asset("groups2.txt"));
+
+    run_test_join(&["-v", "1", "-v", "2", &file1, &file2], "b 3\nd w\n", "", 0);
+    run_test_join(
+        &[
+            "-a",
+            "1",
+            "-a",
+            "2",
+            "-o",
+            "0,1.2,2.2",
+            "-e",
+            "-",
+            &file1,
+            &file2,
+        ],
+        "a 1 x\na 1 y\na 2 x\na 2 y\nb 3 -\nc 4 z\nd - w\n",
+        "",
+        0,
+    );
+}
+
+#[test]
+fn o_blank_separated_test() {
+    let (file1, file2) = (join_asset("groups1.txt"), join_asset("groups2.txt"));
+
+    run_test_join(
+        &["-o", "2.2 0", "-o", "1.2", &file1, &file2],
+        "x a 1\ny a 1\nx a 2\ny a 2\nz c 4\n",
+        "",
+        0,
+    );
+    run_test_join(
+        &["-o", "3.1", &file1, &file2],
+        "",
+        "invalid field specifier: '3.1'\n",
+        1,
+    );
+}
+
+#[test]
+fn different_join_fields_test() {
+    let file1 = join_asset("keyed.txt");
+    let file2 = join_asset("fields.txt");
+
+    run_test_join(
+        &["-1", "2", "-2", "3", &file1, &file2],
+        "x a 1 2\nz c 3 4\n",
+        "",
+        0,
+    );
+    run_test_join(
+        &["-1", "2", "-2", "3", "-o", "2.1,0,1.1", &file1, &file2],
+        "1 x a\n3 z c\n",
+        "",
+        0,
+    );
+}
+
+#[test]
+fn ignore_case_test() {
+    let file1 = join_asset("upper.txt");
+    let file2 = join_asset("groups1.txt");
+
+    run_test_join(&["-i", &file1, &file2], "A up 1\nA up 2\nC up 4\n", "", 0);
+    run_test_join(&[&file1, &file2], "", "", 0);
+}
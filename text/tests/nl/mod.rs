@@ -83,13 +83,57 @@ fn test_nl_delimiter() {
 
 #[test]
 fn test_nl_regex() {
-    // NOTE: The implementation has better regex support than the reference.
-    // `nl -b p.+ng` would fail to match the words ending with "ng" in the
-    // original whereas it would in this Rust implementation. Might be
-    // considered a bug?
     nl_test(
         &["-b", "p.*ng"],
         "something\nanything\neverything\ncat\ndog",
         "     1\tsomething\n     2\tanything\n     3\teverything\n       cat\n       dog\n",
     );
 }
+
+#[test]
+fn test_nl_regex_basic() {
+    // The expression is a basic regular expression, where + is an ordinary character
+    nl_test(
+        &["-b", "p.+ng"],
+        "something\na+ng\n",
+        "       something\n     1\ta+ng\n",
+    );
+    nl_test(
+        &["-b", "p^\\(a\\)\\1"],
+        "aa\nab\n",
+        "     1\taa\n       ab\n",
+    );
+}
+
+#[test]
+fn test_nl_sections_styles() {
+    let input = "\\:\\:\\:\nh\n\n\\:\\:\nb\n\n\\:\nf\n";
+    nl_test(
+        &[],
+        input,
+        "\n       h\n       \n\n     1\tb\n       \n\n       f\n",
+    );
+    nl_test(
+        &["-h", "t", "-b", "a", "-f", "pf"],
+        input,
+        "\n     1\th\n       \n\n     1\tb\n     2\t\n\n     1\tf\n",
+    );
+}
+
+#[test]
+fn test_nl_join_blank_lines() {
+    // Only each second adjacent empty line is numbered
+    nl_test(
+        &["-b", "a", "-l", "2"],
+        "a\n\n\n\n\nb\n",
+        "     1\ta\n       \n     2\t\n       \n     3\t\n     4\tb\n",
+    );
+}
+
+#[test]
+fn test_nl_width_separator() {
+    nl_test(&["-w", "2", "-s", ": "], "a\nb", " 1: a\n 2: b\n");
+    nl_test(&["-w", "1", "-v", "99"], "a\nb", "99\ta\n100\tb\n");
+    nl_test(&["-n", "rz", "-w", "3", "-v", "-5"], "a", "-05\ta\n");
+    nl_test(&["-v", "0", "-i", "-1"], "a\nb", "     0\ta\n    -1\tb\n");
+}
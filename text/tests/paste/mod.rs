@@ -82,3 +82,81 @@ fn paste_serial_custom_delimiter() {
         "output_serial_custom_delim.txt",
     );
 }
+
+fn run_paste_str(args: &[&str], stdin_data: &str, expected_out: &str) {
+    run_test(TestPlan {
+        cmd: String::from("paste"),
+        args: args.iter().map(|s| s.to_string()).collect(),
+        expected_out: String::from(expected_out),
+        expected_err: String::new(),
+        stdin_data: String::from(stdin_data),
+        expected_exit_code: 0,
+    });
+}
+
+#[test]
+fn paste_delimiter_list_cycles() {
+    run_paste_str(
+        &[
+            "-d",
+            ",;",
+            "tests/paste/input1.txt",
+            "tests/paste/input2.txt",
+            "tests/paste/input3.txt",
+        ],
+        "",
+        "apple,1;one\nbanana,2;two\ncarrot,3;three\n",
+    );
+    // the list starts over for each file
+    run_paste_str(
+        &[
+            "-s",
+            "-d",
+            ",;",
+            "tests/paste/input1.txt",
+            "tests/paste/input2.txt",
+        ],
+        "",
+        "apple,banana;carrot\n1,2;3\n",
+    );
+}
+
+#[test]
+fn paste_delimiter_escapes() {
+    run_paste_str(
+        &["-d", "\\0\\t", "-", "-", "-"],
+        "a\nb\nc\nd\n",
+        "ab\tc\nd\t\n",
+    );
+    run_paste_str(&["-s", "-d", "\\n", "-"], "a\nb\n", "a\nb\n");
+    run_paste_str(&["-s", "-d", "\\\\", "-"], "a\nb\n", "a\\b\n");
+    run_test(TestPlan {
+        cmd: String::from("paste"),
+        args: vec![String::from("-d"), String::from("a\\"), String::from("-")],
+        expected_out: String::new(),
+        expected_err: String::from("delimiter list ends with an unescaped backslash: a\\\n"),
+        stdin_data: String::new(),
+        expected_exit_code: 1,
+    });
+}
+
+#[test]
+fn paste_different_lengths() {
+    run_paste_str(
+        &[
+            "-d",
+            ",",
+            "tests/paste/short.txt",
+            "tests/paste/input1.txt",
+            "tests/paste/short.txt",
+        ],
+        "",
+        "x,apple,x\ny,banana,y\n,carrot,\n",
+    );
+}
+
+#[test]
+fn paste_stdin_lines_in_turn() {
+    run_paste_str(&["-", "-"], "a\nb\nc\n", "a\tb\nc\t\n");
+    run_paste_str(&["-s"], "a\nb\nc", "a\tb\tc\n");
+}
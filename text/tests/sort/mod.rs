@@ -8,7 +8,7 @@
 // SPDX-License-Identifier: MIT
 //
 
-use plib::{run_test, TestPlan};
+use plib::{run_test, run_test_u8, run_test_with_checker, TestPlan, TestPlanU8};
 
 fn sort_test(
     args: &[&str],
@@ -66,42 +66,78 @@ fn test_n7() {
 
 #[test]
 fn test_n8a() {
-    sort_test(&["-n", "-k1,1"], ".0a\n.0b\n", ".0a\n.0b\n", 0, "");
+    sort_test(&["-s", "-n", "-k1,1"], ".0a\n.0b\n", ".0a\n.0b\n", 0, "");
 }
 
 #[test]
 fn test_n8b() {
-    sort_test(&["-n", "-k1,1"], ".0b\n.0a\n", ".0b\n.0a\n", 0, "");
+    sort_test(&["-s", "-n", "-k1,1"], ".0b\n.0a\n", ".0b\n.0a\n", 0, "");
 }
 
 #[test]
 fn test_n9a() {
-    sort_test(&["-n", "-k1,1"], ".000a\n.000b\n", ".000a\n.000b\n", 0, "");
+    sort_test(
+        &["-s", "-n", "-k1,1"],
+        ".000a\n.000b\n",
+        ".000a\n.000b\n",
+        0,
+        "",
+    );
 }
 
 #[test]
 fn test_n9b() {
-    sort_test(&["-n", "-k1,1"], ".000b\n.000a\n", ".000b\n.000a\n", 0, "");
+    sort_test(
+        &["-s", "-n", "-k1,1"],
+        ".000b\n.000a\n",
+        ".000b\n.000a\n",
+        0,
+        "",
+    );
 }
 
 #[test]
 fn test_n10a() {
-    sort_test(&["-n", "-k1,1"], ".00a\n.000b\n", ".00a\n.000b\n", 0, "");
+    sort_test(
+        &["-s", "-n", "-k1,1"],
+        ".00a\n.000b\n",
+        ".00a\n.000b\n",
+        0,
+        "",
+    );
 }
 
 #[test]
 fn test_n10b() {
-    sort_test(&["-n", "-k1,1"], ".00b\n.000a\n", ".00b\n.000a\n", 0, "");
+    sort_test(
+        &["-s", "-n", "-k1,1"],
+        ".00b\n.000a\n",
+        ".00b\n.000a\n",
+        0,
+        "",
+    );
 }
 
 #[test]
 fn test_n11a() {
-    sort_test(&["-n", "-k1,1"], ".01a\n.010\n", ".01a\n.010\n", 0, "");
+    sort_test(
+        &["-s", "-n", "-k1,1"],
+        ".01a\n.010\n",
+        ".01a\n.010\n",
+        0,
+        "",
+    );
 }
 
 #[test]
 fn test_n11b() {
-    sort_test(&["-n", "-k1,1"], ".010\n.01a\n", ".010\n.01a\n", 0, "");
+    sort_test(
+        &["-s", "-n", "-k1,1"],
+        ".010\n.01a\n",
+        ".010\n.01a\n",
+        0,
+        "",
+    );
 }
 
 #[test]
@@ -111,13 +147,7 @@ fn test_02a() {
 
 #[test]
 fn test_02b() {
-    sort_test(
-        &["-c"],
-        "A\nC\nB\n",
-        "",
-        1,
-        "The order of the lines is not correct on line 2:`C`\n",
-    );
+    sort_test(&["-c"], "A\nC\nB\n", "", 1, "sort: -:3: disorder: B\n");
 }
 
 #[test]
@@ -132,18 +162,12 @@ fn test_02d() {
 
 #[test]
 fn test_02e() {
-    sort_test(
-        &["-C"],
-        "A\nC\nB\n",
-        "",
-        1,
-        "The order of the lines is not correct\n",
-    );
+    sort_test(&["-C"], "A\nC\nB\n", "", 1, "");
 }
 
 #[test]
 fn test_02m() {
-    sort_test(&["-cu"], "A\nA\n", "", 1, "Duplicate key was found! `A`\n");
+    sort_test(&["-cu"], "A\nA\n", "", 1, "sort: -:2: disorder: A\n");
 }
 
 #[test]
@@ -153,24 +177,12 @@ fn test_02n() {
 
 #[test]
 fn test_02o() {
-    sort_test(
-        &["-cu"],
-        "A\nB\nB\n",
-        "",
-        1,
-        "Duplicate key was found! `B`\n",
-    );
+    sort_test(&["-cu"], "A\nB\nB\n", "", 1, "sort: -:3: disorder: B\n");
 }
 
 #[test]
 fn test_02p() {
-    sort_test(
-        &["-cu"],
-        "B\nA\nB\n",
-        "",
-        1,
-        "Duplicate key was found! `B`\n",
-    );
+    sort_test(&["-cu"], "B\nA\nB\n", "", 1, "sort: -:2: disorder: A\n");
 }
 
 #[test]
@@ -191,7 +203,7 @@ fn test_03c() {
 #[test]
 fn test_03d() {
     // Fail with a diagnostic when -k specifies field == 0.
-    sort_test(&["-k0", "-"], "", "", 1, "the key can't be zero.\n");
+    sort_test(&["-k0", "-"], "", "", 2, "sort: the key can't be zero.\n");
 }
 
 #[test]
@@ -284,14 +296,11 @@ fn test_07e() {
 
 #[test]
 fn test_07f() {
-    // ensure fields with end position before start are error
-    sort_test(
-        &["-n", "-k1.3,1.1", "-"],
-        "a 2\nb 1\n",
-        "",
-        1,
-        "keys fields with end position before start!\n",
-    );
+    // ensure fields with end position before start are empty keys, which leaves the
+    // lines to the whole-line comparison
+    sort_test(&["-n", "-k1.3,1.1", "-"], "b 1\na 2\n", "a 2\nb 1\n", 0, "");
+    sort_test(&["-k1.2,1.1", "-"], "ba\nab\n", "ab\nba\n", 0, "");
+    sort_test(&["-k2,1", "-"], "b 1\na 2\n", "a 2\nb 1\n", 0, "");
 }
 
 #[test]
@@ -301,8 +310,8 @@ fn test_08a() {
         &["-k", "2.,3", "-"],
         "",
         "",
-        1,
-        "cannot parse integer from empty string\n",
+        2,
+        "sort: cannot parse integer from empty string\n",
     );
 }
 
@@ -313,8 +322,8 @@ fn test_08b() {
         &["-k", "2,", "-"],
         "",
         "",
-        1,
-        "cannot parse integer from empty string\n",
+        2,
+        "sort: cannot parse integer from empty string\n",
     );
 }
 
@@ -613,3 +622,442 @@ fn test_files_sort_2() {
             "",
         );
 }
+
+#[test]
+fn test_reverse_with_key() {
+    sort_test(&["-r", "-k1", "-"], "a\nc\nb\n", "c\nb\na\n", 0, "");
+}
+
+#[test]
+fn test_empty_input() {
+    sort_test(&["-"], "", "", 0, "");
+}
+
+// Lines in a shuffled order, which is the same on every run
+fn shuffled_numbers(count: usize) -> Vec<usize> {
+    let mut numbers: Vec<usize> = (0..count).collect();
+    let mut state: usize = 12345;
+    for i in (1..count).rev() {
+        state = state
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        numbers.swap(i, (state >> 33) % (i + 1));
+    }
+    numbers
+}
+
+#[test]
+fn test_external_sort() {
+    let numbers = shuffled_numbers(5000);
+    let input: String = numbers.iter().map(|n| format!("{}\n", n)).collect();
+    let mut sorted: Vec<String> = numbers.iter().map(|n| n.to_string()).collect();
+    sorted.sort();
+    let expected: String = sorted.iter().map(|line| format!("{}\n", line)).collect();
+
+    // The runs are short enough to need several merge passes
+    sort_test(&["-S", "1b", "-"], &input, &expected, 0, "");
+    sort_test(&["-S", "2K", "-"], &input, &expected, 0, "");
+}
+
+#[test]
+fn test_external_sort_numeric_reverse() {
+    let numbers = shuffled_numbers(3000);
+    let input: String = numbers.iter().map(|n| format!("{}\n", n)).collect();
+    let expected: String = (0..3000).rev().map(|n| format!("{}\n", n)).collect();
+    sort_test(&["-n", "-r", "-S", "1K", "-"], &input, &expected, 0, "");
+}
+
+#[test]
+fn test_external_sort_is_stable() {
+    // Lines with equal keys stay in the order of the input across runs
+    let input: String = shuffled_numbers(2000)
+        .iter()
+        .map(|n| format!("{} {}\n", n % 10, n))
+        .collect();
+    let mut expected: Vec<&str> = input.lines().collect();
+    expected.sort_by_key(|line| line.split(' ').next().unwrap().to_string());
+    let expected: String = expected.iter().map(|line| format!("{}\n", line)).collect();
+    sort_test(&["-s", "-k1,1", "-S", "1K", "-"], &input, &expected, 0, "");
+}
+
+#[test]
+fn test_external_sort_unique() {
+    let input: String = shuffled_numbers(2000)
+        .iter()
+        .map(|n| format!("{}\n", n % 100))
+        .collect();
+    let expected: String = (0..100).map(|n| format!("{}\n", n)).collect();
+    sort_test(&["-n", "-u", "-S", "1K", "-"], &input, &expected, 0, "");
+}
+
+#[test]
+fn test_invalid_buffer_size() {
+    sort_test(
+        &["-S", "10Q", "-"],
+        "",
+        "",
+        2,
+        "sort: invalid buffer size '10Q'\n",
+    );
+}
+
+#[test]
+fn test_error_exit_status() {
+    // 1 is only for the disorder found by -c or -C
+    sort_test(
+        &["/nonexistent/file"],
+        "",
+        "",
+        2,
+        "sort: /nonexistent/file: No such file or directory (os error 2)\n",
+    );
+    sort_test(
+        &["-c", "/nonexistent/file"],
+        "",
+        "",
+        2,
+        "sort: /nonexistent/file: No such file or directory (os error 2)\n",
+    );
+    sort_test(&["-k", "0"], "", "", 2, "sort: the key can't be zero.\n");
+}
+
+#[test]
+fn test_temporary_directory_error() {
+    sort_test(
+        &["-S", "1b", "-T", "/nonexistent/directory", "-"],
+        "b\na\n",
+        "",
+        2,
+        "sort: can't create a temporary file in '/nonexistent/directory': No such file or directory (os error 2)\n",
+    );
+}
+
+#[test]
+fn test_more_than_two_keys() {
+    sort_test(
+        &["-k1,1", "-k2,2", "-k3,3n", "-"],
+        "a x 10\na x 9\na w 5\n",
+        "a w 5\na x 9\na x 10\n",
+        0,
+        "",
+    );
+}
+
+#[test]
+fn test_key_empty_fields_with_separator() {
+    sort_test(
+        &["-t", ":", "-k3,3", "-"],
+        "a::c\nb:x:b\nc:\n",
+        "c:\nb:x:b\na::c\n",
+        0,
+        "",
+    );
+}
+
+#[test]
+fn test_key_past_end_of_field() {
+    // The end character can reach into the next field
+    sort_test(
+        &["-k1.2,1.4", "-"],
+        "ab d\nab c\nab\n",
+        "ab\nab c\nab d\n",
+        0,
+        "",
+    );
+}
+
+#[test]
+fn test_global_options_without_keys() {
+    sort_test(&["-b", "-"], "  b\na\n c\n", "a\n  b\n c\n", 0, "");
+    sort_test(&["-t", ":", "-"], "b:1\na:2\n", "a:2\nb:1\n", 0, "");
+}
+
+#[test]
+fn test_key_modifiers_override_global_options() {
+    sort_test(&["-r", "-k1n", "-"], "1\n2\n", "1\n2\n", 0, "");
+    sort_test(&["-r", "-k1", "-"], "1\n2\n", "2\n1\n", 0, "");
+}
+
+#[test]
+fn test_invalid_key_modifier() {
+    sort_test(&["-k1x", "-"], "", "", 2, "sort: invalid key '1x'\n");
+}
+
+#[test]
+fn test_numeric_blanks_and_sign() {
+    sort_test(
+        &["-n", "-"],
+        " 10\n-5\n  2\n-00\n3.14\n-3.2\n",
+        "-5\n-3.2\n-00\n  2\n3.14\n 10\n",
+        0,
+        "",
+    );
+}
+
+#[test]
+fn test_numeric_long_numbers() {
+    sort_test(
+        &["-n", "-"],
+        "100000000000000000000001\n100000000000000000000000\n",
+        "100000000000000000000000\n100000000000000000000001\n",
+        0,
+        "",
+    );
+}
+
+#[test]
+fn test_general_numeric() {
+    sort_test(
+        &["-g", "-"],
+        "inf\nnan\n1e3\n-inf\nx\n2\n-1.5e-2\n",
+        "x\nnan\n-inf\n-1.5e-2\n2\n1e3\ninf\n",
+        0,
+        "",
+    );
+}
+
+#[test]
+fn test_human_numeric() {
+    sort_test(
+        &["-h", "-"],
+        "1.5K\t./b\n2M\t./c\n10\t./a\n900K\t.\n",
+        "10\t./a\n1.5K\t./b\n900K\t.\n2M\t./c\n",
+        0,
+        "",
+    );
+    sort_test(
+        &["-k2h", "-"],
+        "a 1G\nb -2K\nc 3\n",
+        "b -2K\nc 3\na 1G\n",
+        0,
+        "",
+    );
+}
+
+#[test]
+fn test_numeric_orders_conflict() {
+    sort_test(
+        &["-n", "-g", "-"],
+        "",
+        "",
+        2,
+        "sort: Options '-n' and '-g' cannot be used together\n",
+    );
+}
+
+fn sort_asset(name: &str) -> String {
+    format!("{}/tests/sort/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+#[test]
+fn test_merge() {
+    let (file1, file2) = (sort_asset("merge1.txt"), sort_asset("merge2.txt"));
+    sort_test(
+        &["-m", "-n", &file1, "-", &file2],
+        "4 fig\n",
+        "1 apple\n2 banana\n3 cherry\n3 date\n4 fig\n5 grape\n6 kiwi\n",
+        0,
+        "",
+    );
+}
+
+#[test]
+fn test_merge_unique_by_key() {
+    let (file1, file2) = (sort_asset("merge1.txt"), sort_asset("merge2.txt"));
+    sort_test(
+        &["-m", "-u", "-k1,1n", &file2, &file1],
+        "",
+        "1 apple\n2 banana\n3 date\n5 grape\n6 kiwi\n",
+        0,
+        "",
+    );
+}
+
+#[test]
+fn test_check_reports_file_and_line() {
+    let file2 = sort_asset("merge2.txt");
+    sort_test(
+        &["-c", "-k2r", &file2],
+        "",
+        "",
+        1,
+        &format!("sort: {}:2: disorder: 3 date\n", file2),
+    );
+    sort_test(&["-C", "-u", "-k1,1n", &file2], "", "", 0, "");
+}
+
+#[test]
+fn test_check_more_than_one_file() {
+    let (file1, file2) = (sort_asset("merge1.txt"), sort_asset("merge2.txt"));
+    sort_test(
+        &["-c", &file1, &file2],
+        "",
+        "",
+        2,
+        &format!("sort: extra operand '{}' not allowed with -c\n", file2),
+    );
+}
+
+#[test]
+fn test_parallel_sort() {
+    // Enough lines for each thread to sort a chunk, with equal keys that
+    // keep the order of the input across the chunks
+    let input: String = shuffled_numbers(50000)
+        .iter()
+        .map(|n| format!("{} {}\n", n % 100, n))
+        .collect();
+    let mut expected: Vec<&str> = input.lines().collect();
+    expected.sort_by_key(|line| line.split(' ').next().unwrap().parse::<u32>().unwrap());
+    let expected: String = expected.iter().map(|line| format!("{}\n", line)).collect();
+    for threads in ["1", "3"] {
+        sort_test(
+            &["-s", "-k1,1n", "--parallel", threads, "-"],
+            &input,
+            &expected,
+            0,
+            "",
+        );
+    }
+}
+
+#[test]
+fn test_version_sort() {
+    sort_test(
+        &["-V", "-"],
+        "file-1.10\nfile-1.9\nfile-1.2.tar.gz\nfile-1.0~rc1\n",
+        "file-1.0~rc1\nfile-1.2.tar.gz\nfile-1.9\nfile-1.10\n",
+        0,
+        "",
+    );
+    sort_test(
+        &["-k2V", "-"],
+        "b 2.10\na 2.9\nc 2.9.1\n",
+        "a 2.9\nc 2.9.1\nb 2.10\n",
+        0,
+        "",
+    );
+}
+
+#[test]
+fn test_random_sort_keeps_equal_keys_together() {
+    let input: String = shuffled_numbers(300)
+        .iter()
+        .map(|n| format!("{} {}\n", n % 30, n))
+        .collect();
+    let plan = TestPlan {
+        cmd: String::from("sort"),
+        args: vec![String::from("-R"), String::from("-k1,1"), String::from("-")],
+        stdin_data: input.clone(),
+        expected_out: String::new(),
+        expected_err: String::new(),
+        expected_exit_code: 0,
+    };
+    run_test_with_checker(plan, |_, output| {
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let mut lines: Vec<&str> = stdout.lines().collect();
+
+        // Each key is in one group of adjacent lines
+        let mut keys: Vec<&str> = lines
+            .iter()
+            .map(|line| &line[..line.find(' ').unwrap()])
+            .collect();
+        keys.dedup();
+        assert_eq!(keys.len(), 30);
+
+        let mut expected: Vec<&str> = input.lines().collect();
+        expected.sort();
+        lines.sort();
+        assert_eq!(lines, expected);
+    });
+}
+
+#[test]
+fn test_random_and_numeric_conflict() {
+    sort_test(
+        &["-n", "-R", "-"],
+        "",
+        "",
+        2,
+        "sort: Options '-n' and '-R' cannot be used together\n",
+    );
+}
+
+#[test]
+fn test_last_resort_comparison() {
+    let input = "c 2\nb 2\na 10\n";
+
+    // Lines with equal keys are compared as whole lines
+    sort_test(&["-k2n", "-"], input, "b 2\nc 2\na 10\n", 0, "");
+    sort_test(&["-k2,2", "-"], input, "a 10\nb 2\nc 2\n", 0, "");
+
+    // The global -r reverses the whole-line comparison, also after a key like 2n that
+    // has modifiers of its own and isn't reversed
+    sort_test(&["-r", "-k2n", "-"], input, "c 2\nb 2\na 10\n", 0, "");
+    sort_test(&["-r", "-k2,2", "-"], input, "c 2\nb 2\na 10\n", 0, "");
+
+    // Except with -s, which keeps their order, or -u, which outputs the first of them
+    sort_test(&["-s", "-k2n", "-"], input, input, 0, "");
+    sort_test(&["-u", "-k2n", "-"], input, "c 2\na 10\n", 0, "");
+}
+
+#[test]
+fn test_fold_case_equal_keys() {
+    // Keys that only differ in case are equal with -f
+    sort_test(&["-f", "-u", "-"], "a\nA\nb\n", "a\nb\n", 0, "");
+    sort_test(&["-s", "-f", "-"], "a\nA\n", "a\nA\n", 0, "");
+    sort_test(
+        &["-c", "-u", "-f", "-"],
+        "A\na\n",
+        "",
+        1,
+        "sort: -:2: disorder: a\n",
+    );
+
+    // Otherwise they are ordered by the whole lines
+    sort_test(&["-f", "-"], "a\nA\nb\n", "A\na\nb\n", 0, "");
+}
+
+fn sort_test_u8(
+    args: &[&str],
+    test_data: &[u8],
+    expected_output: &[u8],
+    expected_exit_code: i32,
+    expected_err: &[u8],
+) {
+    run_test_u8(TestPlanU8 {
+        cmd: String::from("sort"),
+        args: args.iter().map(|s| String::from(*s)).collect(),
+        stdin_data: test_data.to_vec(),
+        expected_out: expected_output.to_vec(),
+        expected_err: expected_err.to_vec(),
+        expected_exit_code,
+    });
+}
+
+#[test]
+fn test_input_not_utf8() {
+    // Lines are sorted by their bytes, whatever their encoding
+    sort_test_u8(&["-"], b"b\n\xe9t\xe9\na\n", b"a\nb\n\xe9t\xe9\n", 0, b"");
+    sort_test_u8(
+        &["-f", "-k1.2", "-"],
+        b"x\xe9B\nxa\ny\xe9a\n",
+        b"xa\ny\xe9a\nx\xe9B\n",
+        0,
+        b"",
+    );
+    sort_test_u8(
+        &["-c", "-"],
+        b"b\n\xe9t\xe9\na\n",
+        b"",
+        1,
+        b"sort: -:3: disorder: a\n",
+    );
+    sort_test_u8(
+        &["-c", "-"],
+        b"b\n\xe9\n\xe8\n",
+        b"",
+        1,
+        b"sort: -:3: disorder: \xe8\n",
+    );
+}
@@ -9,6 +9,13 @@
 //
 
 use plib::{run_test, TestPlan};
+use std::ffi::OsStr;
+use std::fs::{self, OpenOptions};
+use std::io::{Read, Write};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
 
 fn tail_test(args: &[&str], test_data: &str, expected_output: &str) {
     let str_args: Vec<String> = args.iter().map(|s| String::from(*s)).collect();
@@ -153,3 +160,219 @@ fn test_tail_19() {
 fn test_tail_20() {
     tail_test(&["-n-1"], &("y\n".repeat(5)), "y\n");
 }
+
+#[test]
+fn test_tail_zero() {
+    tail_test(&["-n", "0"], "x\ny\n", "");
+    tail_test(&["-c", "0"], "xy", "");
+    tail_test(&["-c", "+0"], "xy", "xy");
+}
+
+#[test]
+fn test_tail_no_trailing_newline() {
+    // The last line counts even without a newline, which isn't added
+    tail_test(&["-n", "2"], "x\ny\nz", "y\nz");
+    tail_test(&["-n", "+3"], "x\ny\nz", "z");
+    tail_test(&["-n", "1"], "x  \n", "x  \n");
+}
+
+#[test]
+fn test_tail_regular_file() {
+    // A regular file is read from its end
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("lines");
+    let lines: String = (1..=5000).map(|i| format!("{i}\n")).collect();
+    fs::write(&path, &lines).unwrap();
+    let path = path.to_str().unwrap();
+
+    tail_test(&["-n", "3", path], "", "4998\n4999\n5000\n");
+    tail_test(&["-n", "6000", path], "", &lines);
+    tail_test(&["-n", "+4999", path], "", "4999\n5000\n");
+    tail_test(&["-c", "5", path], "", "5000\n");
+    tail_test(&["-c", "+23889", path], "", "5000\n");
+    tail_test(&["-c", "100000", path], "", &lines);
+}
+
+#[test]
+fn test_tail_block_boundaries() {
+    // Lines that end at the end of the blocks that the file is read backwards in
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("blocks");
+    let (x, y) = ("x".repeat(8191), "y".repeat(8191));
+    fs::write(&path, format!("{x}\n{y}\nz\n")).unwrap();
+    let path = path.to_str().unwrap();
+
+    tail_test(&["-n", "1", path], "", "z\n");
+    tail_test(&["-n", "2", path], "", &format!("{y}\nz\n"));
+    tail_test(&["-n", "3", path], "", &format!("{x}\n{y}\nz\n"));
+    tail_test(&["-n", "4", path], "", &format!("{x}\n{y}\nz\n"));
+}
+
+#[test]
+fn test_tail_huge_file() {
+    // Only the end of a file is read: this one is mostly a hole, which would take a
+    // long time to read through
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("huge");
+    let file = fs::File::create(&path).unwrap();
+    file.set_len(16 << 30).unwrap();
+    drop(file);
+    append(&path, "a\nb\nc\n");
+    let path = path.to_str().unwrap();
+
+    tail_test(&["-n", "2", path], "", "b\nc\n");
+    tail_test(&["-c", "5", path], "", "\nb\nc\n");
+}
+
+#[test]
+fn test_tail_multiple_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let a = dir.path().join("a");
+    let b = dir.path().join("b");
+    fs::write(&a, "1\n2\n").unwrap();
+    fs::write(&b, "x\ny\n").unwrap();
+    let (a, b) = (a.to_str().unwrap(), b.to_str().unwrap());
+
+    tail_test(
+        &["-n", "1", a, "-", b],
+        "s\n",
+        &format!("==> {a} <==\n2\n\n==> standard input <==\ns\n\n==> {b} <==\ny\n"),
+    );
+}
+
+/// Appends `data` to the file at `path`.
+fn append(path: &std::path::Path, data: &str) {
+    let mut file = OpenOptions::new().append(true).open(path).unwrap();
+    file.write_all(data.as_bytes()).unwrap();
+}
+
+/// A `tail` that follows files, whose output is read on its own thread so that waiting
+/// for it can time out.
+struct Follower {
+    child: Child,
+    rx: mpsc::Receiver<Vec<u8>>,
+    output: String,
+}
+
+impl Follower {
+    fn spawn(args: &[&OsStr]) -> Follower {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_tail"))
+            .args(args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+
+        let (tx, rx) = mpsc::channel();
+        let mut stdout = child.stdout.take().unwrap();
+        thread::spawn(move || {
+            let mut buf = [0; 1024];
+            while let Ok(n) = stdout.read(&mut buf) {
+                if n == 0 || tx.send(buf[..n].to_vec()).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Follower {
+            child,
+            rx,
+            output: String::new(),
+        }
+    }
+
+    /// Waits until the output ends with `expected`.
+    fn wait_for(&mut self, expected: &str) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !self.output.ends_with(expected) {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            match self.rx.recv_timeout(timeout) {
+                Ok(data) => self.output.push_str(&String::from_utf8_lossy(&data)),
+                Err(_) => panic!("timed out waiting for {:?} in {:?}", expected, self.output),
+            }
+        }
+    }
+
+    /// Stops following, and returns the diagnostics.
+    fn stop(mut self) -> String {
+        self.child.kill().unwrap();
+        let mut stderr = String::new();
+        self.child
+            .stderr
+            .take()
+            .unwrap()
+            .read_to_string(&mut stderr)
+            .unwrap();
+        self.child.wait().unwrap();
+        stderr
+    }
+}
+
+#[test]
+fn test_tail_follow() {
+    let dir = tempfile::tempdir().unwrap();
+    let a = dir.path().join("a");
+    let b = dir.path().join("b");
+    fs::write(&a, "1\n2\n").unwrap();
+    fs::write(&b, "x\n").unwrap();
+
+    let mut tail = Follower::spawn(&["-f".as_ref(), "-n1".as_ref(), a.as_ref(), b.as_ref()]);
+
+    let (name_a, name_b) = (a.display(), b.display());
+    tail.wait_for(&format!("==> {name_a} <==\n2\n\n==> {name_b} <==\nx\n"));
+    append(&b, "more b\n");
+    tail.wait_for("x\nmore b\n");
+    append(&a, "more a\n");
+    tail.wait_for(&format!("more b\n\n==> {name_a} <==\nmore a\n"));
+
+    // A truncated file is read again from its start
+    fs::write(&a, "").unwrap();
+    thread::sleep(Duration::from_millis(200));
+    append(&a, "new\n");
+    tail.wait_for("more a\nnew\n");
+
+    assert_eq!(tail.stop(), format!("tail: {name_a}: file truncated\n"));
+}
+
+#[test]
+fn test_tail_follow_name() {
+    let dir = tempfile::tempdir().unwrap();
+    let log = dir.path().join("app.log");
+    let rotated = dir.path().join("app.log.1");
+    let missing = dir.path().join("missing.log");
+    fs::write(&log, "first\n").unwrap();
+
+    let mut tail = Follower::spawn(&[
+        "-F".as_ref(),
+        "-s".as_ref(),
+        "0.1".as_ref(),
+        log.as_ref(),
+        missing.as_ref(),
+    ]);
+    let (name_log, name_missing) = (log.display(), missing.display());
+    tail.wait_for(&format!("==> {name_log} <==\nfirst\n"));
+
+    // The file is rotated: renamed, and created again
+    fs::rename(&log, &rotated).unwrap();
+    fs::write(&log, "second\n").unwrap();
+    tail.wait_for("first\nsecond\n");
+
+    // A file that couldn't be opened is followed once it appears
+    fs::write(&missing, "appeared\n").unwrap();
+    tail.wait_for(&format!("second\n\n==> {name_missing} <==\nappeared\n"));
+
+    fs::remove_file(&log).unwrap();
+    thread::sleep(Duration::from_millis(500));
+    fs::write(&log, "third\n").unwrap();
+    tail.wait_for(&format!("appeared\n\n==> {name_log} <==\nthird\n"));
+
+    let stderr = tail.stop();
+    for notice in [
+        format!("cannot open '{name_missing}' for reading"),
+        format!("'{name_missing}' has appeared;  following new file"),
+        format!("'{name_log}' has become inaccessible"),
+        format!("'{name_log}' has appeared;  following new file"),
+    ] {
+        assert!(stderr.contains(&notice), "{:?} not in {:?}", notice, stderr);
+    }
+}
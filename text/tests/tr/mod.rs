@@ -9,6 +9,8 @@
 //
 
 use plib::{run_test, TestPlan};
+use std::io::Write;
+use std::process::{Command, Stdio};
 
 fn tr_test(args: &[&str], test_data: &str, expected_output: &str) {
     let str_args: Vec<String> = args.iter().map(|s| String::from(*s)).collect();
@@ -23,6 +25,22 @@ fn tr_test(args: &[&str], test_data: &str, expected_output: &str) {
     });
 }
 
+/// Runs `tr` in `locale` with the bytes as input, checking its output.
+fn tr_locale_test(locale: &str, args: &[&str], input: &[u8], expected_output: &[u8]) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_tr"))
+        .args(args)
+        .env("LC_ALL", locale)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(input).unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(output.stdout, expected_output);
+}
+
 #[test]
 fn test_tr_1() {
     tr_test(&["abcd", "[]*]"], "abcd", "]]]]");
@@ -201,10 +219,11 @@ fn tr_v() {
 
 #[test]
 fn tr_w() {
-    tr_test(
+    tr_locale_test(
+        "C.UTF-8",
         &["-ds", "\u{350}", "\u{345}"],
-        "\u{300}\u{301}\u{377}\u{345}\u{345}\u{350}\u{345}",
-        "\u{300}\u{301}\u{377}\u{345}",
+        "\u{300}\u{301}\u{377}\u{345}\u{345}\u{350}\u{345}".as_bytes(),
+        "\u{300}\u{301}\u{377}\u{345}".as_bytes(),
     );
 }
 
@@ -269,7 +288,11 @@ fn tr_ross_1b() {
 
 #[test]
 fn tr_ross_2() {
-    tr_test(&["-dcs", "[:lower:]", "n-rs-z"], "amzAMZ123.-+amz", "amzam");
+    tr_test(
+        &["-dcs", "[:lower:]", "n-rs-z"],
+        "amzAMZ123.-+amz",
+        "amzamz",
+    );
 }
 
 #[test]
@@ -351,3 +374,176 @@ fn tr_ignored_backslash() {
 fn tr_escaped_backslash() {
     tr_test(&["-d", r#"\\"#], r#"a\b\c"#, "abc");
 }
+
+fn tr_error_test(args: &[&str], expected_error: &str) {
+    // No input, as tr may exit before reading it
+    run_test(TestPlan {
+        cmd: String::from("tr"),
+        args: args.iter().map(|s| String::from(*s)).collect(),
+        stdin_data: String::new(),
+        expected_out: String::new(),
+        expected_err: String::from(expected_error),
+        expected_exit_code: 1,
+    });
+}
+
+#[test]
+fn tr_class_among_chars() {
+    tr_test(&["-d", "x[:digit:]-"], "a1-b2x", "ab");
+    tr_test(&["[:digit:]a", "[x*10]A"], "a1b2", "Axbx");
+}
+
+#[test]
+fn tr_case_mapping_in_position() {
+    tr_test(
+        &["a-z[:upper:]", "x[a*25][:lower:]"],
+        "Hello World",
+        "haaaa waaaa",
+    );
+    tr_test(&["[:upper:]0", "[:lower:]-"], "AB0c", "ab-c");
+}
+
+#[test]
+fn tr_equiv_translation() {
+    tr_test(&["[=e=]", "X"], "resume", "rXsumX");
+
+    // Without collation data, a character is only equivalent to itself
+    for locale in ["C", "C.UTF-8"] {
+        tr_locale_test(
+            locale,
+            &["[=e=]", "X"],
+            "\u{e9} e E\n".as_bytes(),
+            "\u{e9} X E\n".as_bytes(),
+        );
+        tr_locale_test(
+            locale,
+            &["-d", "[=e=]"],
+            "\u{e9}e\n".as_bytes(),
+            "\u{e9}\n".as_bytes(),
+        );
+    }
+}
+
+#[test]
+fn tr_misaligned_case_classes() {
+    for args in [
+        ["a-z", "[:upper:]"],
+        ["[:digit:]", "[:upper:]"],
+        ["x[:lower:]", "[:upper:]"],
+        ["[:lower:]", "a[:upper:]"],
+        ["a-z", "[:upper:][:lower:]"],
+    ] {
+        tr_error_test(
+            &args,
+            "Error: misaligned [:upper:] and/or [:lower:] construct\n",
+        );
+    }
+
+    tr_test(&["x[:lower:]", "y[:upper:]"], "xyz", "XYZ");
+    tr_test(&["[:upper:]", "[:upper:]"], "aB", "aB");
+}
+
+#[test]
+fn tr_class_at_end_of_string2() {
+    let error = "Error: When translating with string1 longer than string2, the latter string must not end with a character class\n";
+    tr_error_test(&["[:upper:]0-9", "[:lower:]"], error);
+    tr_error_test(&["-c", "a", "[:upper:]"], error);
+    tr_error_test(&["-c", "a", "x[:lower:]"], error);
+
+    tr_test(&["-c", "a", "[:upper:]x"], "ab.", "axx");
+}
+
+#[test]
+fn tr_repeat_octal_count() {
+    tr_test(&["a-f", "[x*02]y"], "abcdef", "xxyyyy");
+    tr_test(&["a-f", "[x*10]"], "abcdef", "xxxxxx");
+}
+
+#[test]
+fn tr_octal_escapes() {
+    tr_test(&["\\141-\\143", "x"], "abcd", "xxxd");
+}
+
+#[test]
+fn tr_invalid_sets() {
+    tr_error_test(&["[:foo:]", "x"], "Error: Invalid class name 'foo'\n");
+    tr_error_test(
+        &["a", "[:digit:]"],
+        "Error: When translating, the only character classes that may appear in string2 are 'upper' and 'lower'\n",
+    );
+    tr_error_test(
+        &["z-a", "x"],
+        "Error: Range-endpoints of 'z-a' are in reverse collating sequence order\n",
+    );
+}
+
+#[test]
+fn tr_squeeze_runs_only() {
+    // only sequences are squeezed, not every repeated character
+    tr_test(&["-s", "a"], "aabaa", "aba");
+    tr_test(&["-cs", "a-z"], "ab  ..cd", "ab .cd");
+    tr_test(&["-s", "a-z", "A-Z"], "aabbA", "ABA");
+}
+
+#[test]
+fn tr_complement_combinations() {
+    tr_test(&["-cd", "a-z\\n"], "a1b2 c\n", "abc\n");
+    tr_test(&["-cds", "a-z", "z"], "zz1zb", "zb");
+    tr_test(&["-c", "a-z", "AB[X*]YZ"], "abc  xyz\n", "abcXXxyzX");
+    // the complement starts at the space
+    tr_test(&["-C", "\\000-\\037a-z", "01"], "a !b\n", "a01b\n");
+}
+
+#[test]
+fn tr_control_escapes() {
+    tr_test(&["\\t\\n", "\\a\\b"], "a\tb\n", "a\u{7}b\u{8}");
+    tr_test(&["-d", "\\v\\f\\r"], "a\u{b}b\u{c}c\rd", "abcd");
+    tr_test(&["-s", "\\040"], "a   b", "a b");
+}
+
+#[test]
+fn tr_invalid_combinations() {
+    tr_error_test(
+        &["-d", "a", "b"],
+        "Error: \"Only one string may be given when deleting without squeezing repeats\"\n",
+    );
+    tr_error_test(
+        &["-ds", "a"],
+        "Error: \"Two strings must be given when both deleting and squeezing repeats\"\n",
+    );
+}
+
+#[test]
+fn tr_invalid_utf8() {
+    // Without UTF-8, characters are bytes
+    tr_locale_test("C", &["a", "b"], b"a\xffb\n", b"b\xffb\n");
+    tr_locale_test("C", &["-d", "\\377"], b"a\xffb\xfe\n", b"ab\xfe\n");
+    tr_locale_test("C", &["-s", "\\377"], b"a\xff\xffb\n", b"a\xffb\n");
+    tr_locale_test("C", &["-cd", "a-z"], b"a\xffb\n", b"ab");
+    tr_locale_test("C", &["-c", "a-z", "_"], b"a\xffb\n", b"a_b_");
+    // Both bytes of e-acute are deleted
+    tr_locale_test("C", &["-d", "\u{e9}"], "h\u{e9}!".as_bytes(), b"h!");
+
+    // In UTF-8 locales, the bytes that aren't part of a character are left as they are
+    tr_locale_test("C.UTF-8", &["-d", "b"], b"a\xffb\n", b"a\xff\n");
+    tr_locale_test("C.UTF-8", &["-s", "a"], b"aa\xffaa\n", b"a\xffa\n");
+    tr_locale_test("C.UTF-8", &["-cd", "a-z"], b"a\xffb\n", b"a\xffb");
+    tr_locale_test(
+        "C.UTF-8",
+        &["-c", "a-z", "_"],
+        b"a\xff\xc3\n",
+        b"a\xff\xc3_",
+    );
+}
+
+#[test]
+fn tr_read_error() {
+    let output = Command::new(env!("CARGO_BIN_EXE_tr"))
+        .args(["a", "b"])
+        .stdin(std::fs::File::open("/").unwrap())
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("tr: read error: "));
+}
@@ -202,3 +202,49 @@ fn uniq_42() {
 fn uniq_43() {
     uniq_test(&["-c"], "a\na\n", "2 a\n");
 }
+
+#[test]
+fn uniq_skip_past_end_of_line() {
+    uniq_test(&["-s", "5"], "ab\ncd\n", "ab\n");
+    uniq_test(&["-f", "3"], "a b\nc\n", "a b\n");
+}
+
+#[test]
+fn uniq_width() {
+    uniq_test(&["-w", "2"], "abc\nabd\nacd\n", "abc\nacd\n");
+    uniq_test(&["-w", "0"], "a\nb\n", "a\n");
+    uniq_test(&["-s", "1", "-w", "1"], "xab\nyac\nzbc\n", "xab\nzbc\n");
+    uniq_test(&["-f", "1", "-w", "2"], "1 ab\n2 ac\n3  b\n", "1 ab\n3  b\n");
+}
+
+#[test]
+fn uniq_width_counts_characters() {
+    uniq_test(&["-w", "1"], "äa\näb\n", "äa\n");
+    uniq_test(&["-s", "1"], "äa\nba\n", "äa\n");
+}
+
+#[test]
+fn uniq_ignore_case() {
+    uniq_test(&["-i"], "ab\nAB\naC\nac\n", "ab\naC\n");
+    uniq_test(&["-i", "-c"], "Äb\näB\nb\n", "2 Äb\n1 b\n");
+    uniq_test(&["-i", "-d", "-f", "1"], "1 x\n2 X\n3 y\n", "1 x\n");
+}
+
+#[test]
+fn uniq_all_repeated() {
+    let input = "a\na\nb\nc\nc\nc\nd\n";
+    uniq_test(&["-D"], input, "a\na\nc\nc\nc\n");
+    uniq_test(&["--all-repeated=prepend"], input, "\na\na\n\nc\nc\nc\n");
+    uniq_test(&["--all-repeated=separate"], input, "a\na\n\nc\nc\nc\n");
+    uniq_test(&["-D", "-i"], "x\nX\ny\n", "x\nX\n");
+}
+
+#[test]
+fn uniq_group() {
+    let input = "a\na\nb\nc\n";
+    uniq_test(&["--group"], input, "a\na\n\nb\n\nc\n");
+    uniq_test(&["--group=prepend"], input, "\na\na\n\nb\n\nc\n");
+    uniq_test(&["--group=append"], input, "a\na\n\nb\n\nc\n\n");
+    uniq_test(&["--group=both"], input, "\na\na\n\nb\n\nc\n\n");
+    uniq_test(&["--group=both"], "", "");
+}
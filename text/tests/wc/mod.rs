@@ -9,6 +9,9 @@
 //
 
 use plib::{run_test, TestPlan};
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Stdio};
 
 fn wc_test(args: &[&str], test_data: &str, expected_output: &str) {
     let str_args: Vec<String> = args.iter().map(|s| String::from(*s)).collect();
@@ -43,3 +46,69 @@ fn wc_two() {
     wc_test(&["-l"], "x y\n", "1\n");
     wc_test(&["-w"], "x y\n", "2\n");
 }
+
+/// Runs `wc` in `locale` with the bytes as input, checking its output.
+fn wc_locale_test(locale: &str, args: &[&str], input: &[u8], expected_output: &str) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_wc"))
+        .args(args)
+        .env("LC_ALL", locale)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(input).unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), expected_output);
+}
+
+#[test]
+fn wc_chars_multibyte() {
+    let input = "h\u{e9}llo w\u{f6}rld \u{1f600}\n".as_bytes();
+    wc_locale_test("C.UTF-8", &["-m"], input, "14\n");
+    wc_locale_test("C.UTF-8", &["-c"], input, "19\n");
+    wc_locale_test(
+        "C.UTF-8",
+        &["-lwmc"],
+        input,
+        "       1        3       14       19\n",
+    );
+
+    // Without a multibyte locale, characters are bytes
+    wc_locale_test("C", &["-m"], input, "19\n");
+}
+
+#[test]
+fn wc_words_multibyte_space() {
+    // U+3000 IDEOGRAPHIC SPACE and U+2003 EM SPACE delimit words
+    let input = "a\u{3000}b\u{2003}c\n".as_bytes();
+    wc_locale_test("C.UTF-8", &["-w"], input, "3\n");
+    wc_locale_test("C", &["-w"], input, "1\n");
+}
+
+#[test]
+fn wc_invalid_multibyte() {
+    // Bytes that aren't valid characters aren't counted as characters, or as words
+    wc_locale_test(
+        "C.UTF-8",
+        &["-mw"],
+        b"a\xff b \xe2\x80",
+        "       2        4\n",
+    );
+    wc_locale_test("C", &["-mw"], b"a\xff b \xe2\x80", "       3        7\n");
+}
+
+#[test]
+fn wc_lines_bytes_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("lines");
+    let lines: String = (0..100_000).map(|i| format!("{i}\n")).collect();
+    fs::write(&path, &lines).unwrap();
+    let path = path.to_str().unwrap();
+
+    wc_test(&["-l", path], "", "100000\n");
+    wc_test(&["-c", path], "", "588890\n");
+    wc_test(&["-lc", path], "", "  100000   588890\n");
+    wc_test(&["-c"], &lines, "588890\n");
+}
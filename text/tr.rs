@@ -1,10 +1,9 @@
 use clap::Parser;
-use deunicode::deunicode_char;
 use gettextrs::{bind_textdomain_codeset, setlocale, textdomain, LocaleCategory};
 use plib::PROJECT_NAME;
-use regex::Regex;
-use std::collections::{HashMap, HashSet};
-use std::io::{self, Read};
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+use std::io::{self, Read, Write};
 
 /// tr - translate or delete characters
 #[derive(Parser, Debug)]
@@ -39,12 +38,15 @@ impl Args {
         if self.complement_char && self.complement_val {
             return Err("Options '-c' and '-C' cannot be used together".to_string());
         }
-        if self.squeeze_repeats
-            && self.string2.is_none()
-            && (self.complement_char || self.complement_val)
-            && !self.delete
-        {
-            return Err("Option '-c' or '-C' may only be used with 2 strings".to_string());
+        if self.delete && self.squeeze_repeats && self.string2.is_none() {
+            return Err(
+                "Two strings must be given when both deleting and squeezing repeats".to_string(),
+            );
+        }
+        if self.delete && !self.squeeze_repeats && self.string2.is_some() {
+            return Err(
+                "Only one string may be given when deleting without squeezing repeats".to_string(),
+            );
         }
 
         if !self.squeeze_repeats && !self.delete && self.string2.is_none() {
@@ -59,12 +61,133 @@ impl Args {
     }
 }
 
+extern "C" {
+    // Not exported by the `libc` crate
+    fn iswalnum(wc: libc::c_uint) -> libc::c_int;
+    fn iswalpha(wc: libc::c_uint) -> libc::c_int;
+    fn iswblank(wc: libc::c_uint) -> libc::c_int;
+    fn iswcntrl(wc: libc::c_uint) -> libc::c_int;
+    fn iswdigit(wc: libc::c_uint) -> libc::c_int;
+    fn iswgraph(wc: libc::c_uint) -> libc::c_int;
+    fn iswlower(wc: libc::c_uint) -> libc::c_int;
+    fn iswprint(wc: libc::c_uint) -> libc::c_int;
+    fn iswpunct(wc: libc::c_uint) -> libc::c_int;
+    fn iswspace(wc: libc::c_uint) -> libc::c_int;
+    fn iswupper(wc: libc::c_uint) -> libc::c_int;
+    fn iswxdigit(wc: libc::c_uint) -> libc::c_int;
+    fn towlower(wc: libc::c_uint) -> libc::c_uint;
+    fn towupper(wc: libc::c_uint) -> libc::c_uint;
+}
+
+/// Checks if the character encoding of the locale is UTF-8. In other locales, the
+/// characters are bytes.
+fn utf8_locale() -> bool {
+    let codeset = unsafe { CStr::from_ptr(libc::nl_langinfo(libc::CODESET)) };
+    let codeset = codeset.to_bytes();
+    codeset.eq_ignore_ascii_case(b"UTF-8") || codeset.eq_ignore_ascii_case(b"UTF8")
+}
+
+/// The last character of the locale, `char::MAX` in UTF-8 locales, and the byte 0xFF
+/// otherwise.
+fn max_char() -> char {
+    if utf8_locale() {
+        char::MAX
+    } else {
+        '\u{ff}'
+    }
+}
+
+/// The characters of a string in the locale. Without UTF-8, each byte is a character,
+/// whose code point is the value of the byte.
+fn locale_chars(s: &str, utf8: bool) -> String {
+    if utf8 {
+        s.to_string()
+    } else {
+        s.bytes().map(char::from).collect()
+    }
+}
+
+/// The characters in the equivalence class of `c` in the collation of the locale, in the
+/// order of their code points. These are the characters that `[[=c=]]` matches with
+/// `regcomp`, which in the C locale and in locales without collation data is only `c`.
+fn equivalents(c: char, utf8: bool) -> Vec<char> {
+    let mut pattern = b"[[=".to_vec();
+    encode(c, utf8, &mut pattern);
+    pattern.extend_from_slice(b"=]]");
+    let Ok(pattern) = CString::new(pattern) else {
+        return vec![c];
+    };
+    let mut regex = unsafe { std::mem::zeroed::<libc::regex_t>() };
+    if unsafe { libc::regcomp(&mut regex, pattern.as_ptr(), 0) } != 0 {
+        return vec![c];
+    }
+
+    // Search the characters of the locale, other than NUL, as a single string
+    let mut candidates = Vec::new();
+    for candidate in '\u{1}'..=max_char() {
+        encode(candidate, utf8, &mut candidates);
+    }
+    let candidates = CString::new(candidates).unwrap();
+    let candidates = candidates.as_bytes();
+
+    let mut chars = Vec::new();
+    let mut offset = 0;
+    while offset < candidates.len() {
+        let mut found = libc::regmatch_t { rm_so: 0, rm_eo: 0 };
+        let result = unsafe {
+            libc::regexec(
+                &regex,
+                candidates[offset..].as_ptr().cast(),
+                1,
+                &mut found,
+                0,
+            )
+        };
+        if result != 0 || found.rm_eo <= found.rm_so {
+            break;
+        }
+        let start = offset + found.rm_so as usize;
+        offset += found.rm_eo as usize;
+        if let Some(Ok(found)) = decode(&candidates[start..offset], utf8).next() {
+            chars.push(found);
+        }
+    }
+    unsafe { libc::regfree(&mut regex) };
+
+    if chars.is_empty() {
+        chars.push(c);
+    }
+    chars
+}
+
+/// Decodes the characters of the input. In UTF-8 locales, the bytes that aren't part of a
+/// character are `Err`, to be left as they are. Otherwise, each byte is a character.
+fn decode(input: &[u8], utf8: bool) -> Box<dyn Iterator<Item = Result<char, u8>> + '_> {
+    if utf8 {
+        Box::new(input.utf8_chunks().flat_map(|chunk| {
+            let invalid = chunk.invalid().iter().map(|&byte| Err(byte));
+            chunk.valid().chars().map(Ok).chain(invalid)
+        }))
+    } else {
+        Box::new(input.iter().map(|&byte| Ok(char::from(byte))))
+    }
+}
+
+/// Encodes a character of the output, the reverse of `decode`.
+fn encode(c: char, utf8: bool, output: &mut Vec<u8>) {
+    match u8::try_from(c) {
+        Ok(byte) if !utf8 => output.push(byte),
+        _ => output.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+    }
+}
+
 // The Char struct represents a character along with its repetition count.
 #[derive(Debug, Clone)]
 struct Char {
     // The character.
     char: char,
-    // The number of times the character is repeated
+    // The number of times the character is repeated, `usize::MAX` for `[x*]`, which fills
+    // STRING2 to the length of STRING1
     repeated: usize,
 }
 
@@ -73,13 +196,101 @@ struct Char {
 struct Equiv {
     // The character equivalent.
     char: char,
+    // The characters of its equivalence class, in the order of their code points
+    chars: Vec<char>,
 }
 
-// The Operand enum can be either a Char or an Equiv
+/// A character class `[:class:]`, whose characters are those of the class in the
+/// `LC_CTYPE` locale.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Class {
+    Alnum,
+    Alpha,
+    Blank,
+    Cntrl,
+    Digit,
+    Graph,
+    Lower,
+    Print,
+    Punct,
+    Space,
+    Upper,
+    Xdigit,
+}
+
+impl Class {
+    fn from_name(name: &str) -> Option<Class> {
+        Some(match name {
+            "alnum" => Class::Alnum,
+            "alpha" => Class::Alpha,
+            "blank" => Class::Blank,
+            "cntrl" => Class::Cntrl,
+            "digit" => Class::Digit,
+            "graph" => Class::Graph,
+            "lower" => Class::Lower,
+            "print" => Class::Print,
+            "punct" => Class::Punct,
+            "space" => Class::Space,
+            "upper" => Class::Upper,
+            "xdigit" => Class::Xdigit,
+            _ => return None,
+        })
+    }
+
+    /// Checks if a character is in the class.
+    fn contains(self, c: char) -> bool {
+        let wc = c as libc::c_uint;
+        let result = unsafe {
+            match self {
+                Class::Alnum => iswalnum(wc),
+                Class::Alpha => iswalpha(wc),
+                Class::Blank => iswblank(wc),
+                Class::Cntrl => iswcntrl(wc),
+                Class::Digit => iswdigit(wc),
+                Class::Graph => iswgraph(wc),
+                Class::Lower => iswlower(wc),
+                Class::Print => iswprint(wc),
+                Class::Punct => iswpunct(wc),
+                Class::Space => iswspace(wc),
+                Class::Upper => iswupper(wc),
+                Class::Xdigit => iswxdigit(wc),
+            }
+        };
+        result != 0
+    }
+
+    /// The characters of the class, in the order of their code points.
+    fn chars(self) -> impl Iterator<Item = char> {
+        ('\0'..=max_char()).filter(move |&c| self.contains(c))
+    }
+
+    fn case(self) -> CaseSensitive {
+        match self {
+            Class::Upper => CaseSensitive::UpperCase,
+            Class::Lower => CaseSensitive::LowerCase,
+            _ => CaseSensitive::None,
+        }
+    }
+
+    /// Converts a character to the case of the class, for `[:upper:]` and `[:lower:]`.
+    fn convert_case(self, c: char) -> char {
+        let converted = unsafe {
+            match self {
+                Class::Upper => towupper(c as libc::c_uint),
+                Class::Lower => towlower(c as libc::c_uint),
+                _ => c as libc::c_uint,
+            }
+        };
+        char::from_u32(converted).unwrap_or(c)
+    }
+}
+
+// The Operand enum can be either a Char, an Equiv or a Class
 #[derive(Debug, Clone)]
 enum Operand {
     Char(Char),
     Equiv(Equiv),
+    Class(Class),
 }
 
 impl Operand {
@@ -97,7 +308,7 @@ impl Operand {
         for operand in operands {
             match operand {
                 Operand::Equiv(e) => {
-                    if compare_deunicoded_chars(e.char, *target) {
+                    if e.chars.binary_search(target).is_ok() {
                         return true;
                     }
                 }
@@ -106,211 +317,183 @@ impl Operand {
                         return true;
                     }
                 }
+                Operand::Class(class) => {
+                    if class.contains(*target) {
+                        return true;
+                    }
+                }
             }
         }
         false
     }
-}
 
-/// Filters out `Char` elements from a vector of `Operand` elements.
-///
-/// # Arguments
-///
-/// * `operands` - A vector of `Operand` elements.
-///
-/// # Returns
-///
-/// A vector of `Char` elements.
-fn filter_chars(operands: Vec<Operand>) -> Vec<Char> {
-    operands
-        .into_iter()
-        .filter_map(|operand| {
-            if let Operand::Char(c) = operand {
-                Some(c)
-            } else {
-                None
-            }
-        })
-        .collect()
+    /// The character of a `Char` or an `Equiv`.
+    fn char(&self) -> char {
+        match self {
+            Operand::Char(c) => c.char,
+            Operand::Equiv(e) => e.char,
+            Operand::Class(_) => unreachable!("classes are expanded into their characters"),
+        }
+    }
 }
 
-/// Creates a minimal string with a given size using a vector of `Char` elements.
-///
-/// # Arguments
-///
-/// * `chars` - A vector of `Char` elements.
-/// * `size` - The desired size of the resulting string.
+/// Parses a character of a string, which is either a plain character or an escape
+/// sequence: `\\`, `\a`, `\b`, `\f`, `\n`, `\r`, `\t`, `\v` or one to three octal digits.
 ///
 /// # Returns
 ///
-/// A vector of characters representing the minimal string.
-fn create_minimal_string(chars: Vec<Char>, size: usize) -> Vec<char> {
-    let mut result = vec![];
-    let mut remaining_space = size;
-    let mut overflow_chars: Vec<(usize, Char)> = vec![];
-
-    // Add chars with repeated == 1 to the result
-    for ch in &chars {
-        if ch.repeated == 1 {
-            if remaining_space > 0 {
-                result.push(ch.char);
-                remaining_space -= 1;
-            }
-        } else if remaining_space >= ch.repeated {
-            for _ in 0..ch.repeated {
-                result.push(ch.char);
-            }
+/// The character and the number of characters of the string that it takes up.
+///
+fn parse_char(chars: &[char]) -> (char, usize) {
+    // A single backslash character (0x5C)
+    // https://pubs.opengroup.org/onlinepubs/9799919799/basedefs/V1_chap05.html#tagtcjh_2
+    // https://www.unicode.org/Public/UCD/latest/ucd/NameAliases.txt
+    if chars[0] != '\\' {
+        return (chars[0], 1);
+    }
 
-            remaining_space -= ch.repeated;
-        } else {
-            overflow_chars.push((result.len(), ch.clone()));
+    let octal_len = chars[1..]
+        .iter()
+        .take(3)
+        .take_while(|c| ('0'..='7').contains(*c))
+        .count();
+    if octal_len > 0 {
+        let octal: String = chars[1..=octal_len].iter().collect();
+        if let Some(c) = parse_octal(&octal) {
+            return (c, 1 + octal_len);
         }
     }
 
-    // Add remaining chars from overflow_chars if there's still space
-    if !overflow_chars.is_empty() {
-        for (insert_position, char) in overflow_chars.iter().rev() {
-            if remaining_space > 0 {
-                let chars_to_add = remaining_space.min(char.repeated);
-                let replace_with = vec![char.char; chars_to_add];
-                result.splice(insert_position..insert_position, replace_with);
-
-                remaining_space -= chars_to_add;
-            }
+    let char_for_operand = match chars.get(1) {
+        // <alert>
+        // Code point 0007
+        Some('a') => '\u{0007}',
+        // <backspace>
+        // Code point 0008
+        Some('b') => '\u{0008}',
+        // <tab>
+        // Code point 0009
+        Some('t') => '\u{0009}',
+        // <newline>
+        // Code point 000A
+        Some('n') => '\u{000A}',
+        // <vertical-tab>
+        // Code point 000B
+        Some('v') => '\u{000B}',
+        // <form-feed>
+        // Code point 000C
+        Some('f') => '\u{000C}',
+        // <carriage-return>
+        // Code point 000D
+        Some('r') => '\u{000D}',
+        // <backslash>
+        // Code point 005C
+        Some('\\') => {
+            // An escaped backslash
+            '\u{005C}'
         }
-    }
+        Some(&cha) => {
+            // If a backslash is not at the end of the string, and is not followed by one of the valid
+            // escape characters (including another backslash), the backslash is basically just ignored:
+            // the following character is the character added to the set.
+            cha
+        }
+        None => {
+            eprintln!("tr: warning: an unescaped backslash at end of string is not portable");
 
-    if result.len() < size {
-        let last = *result.last().unwrap();
-        for _ in 0..size - result.len() {
-            result.push(last);
+            // If an unescaped backslash is the last character of the string, treat it as though it were
+            // escaped (backslash is added to the set)
+            return ('\u{005C}', 1);
         }
-    }
+    };
 
-    result
+    (char_for_operand, 2)
 }
 
-/// Parses a sequence in the format `[=equiv=]` from the given character iterator.
-///
-/// The function expects the iterator to be positioned just before the first `=`
-/// character. It reads the equivalent characters between the `=` symbols and
-/// creates a list of `Operand::Equiv` entries, one for each character.
-///
-/// # Arguments
+/// Parses a sequence in the format `[:class:]`, `[=equiv=]` or `[x*n]` at the start of
+/// the given characters.
 ///
-/// * `chars` - A mutable reference to a peekable character iterator.
+/// In `[x*n]`, `n` is an octal number if it starts with `0`, and a missing or zero `n`
+/// repeats `x` as many times as are needed to fill STRING2.
 ///
 /// # Returns
 ///
-/// A `Result` containing a vector of `Operand::Equiv` entries if successful, or a
-/// `String` describing the error if parsing fails.
+/// A `Result` containing the `Operand` and the number of characters that it takes up, or
+/// `None` if the characters don't start with one of the sequences, in which case the `[`
+/// is a plain character.
 ///
 /// # Errors
 ///
 /// This function will return an error if:
-/// - The sequence does not contain a closing `=` before `]`.
-/// - The sequence does not contain a closing `]`.
-/// - The sequence contains no characters between the `=` symbols.
-///
-fn parse_equiv(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<Vec<Operand>, String> {
-    chars.next(); // Skip '='
-    let mut equiv = String::new();
-
-    while let Some(&next_ch) = chars.peek() {
-        if next_ch == '=' {
-            break;
+/// - The class name of `[:class:]` is invalid.
+/// - The repetition count `n` is not a valid number.
+///
+fn parse_bracket(chars: &[char]) -> Result<Option<(Operand, usize)>, String> {
+    let rest: String = chars[1..].iter().collect();
+
+    // [:class:]
+    if let Some(name) = rest.strip_prefix(':').and_then(|s| s.split_once(":]")) {
+        let name = name.0;
+        if !name.is_empty() && !name.contains([']', '[']) {
+            let class = Class::from_name(name)
+                .ok_or_else(|| format!("Error: Invalid class name '{}'", name))?;
+            return Ok(Some((Operand::Class(class), name.chars().count() + 4)));
         }
-        equiv.push(next_ch);
-        chars.next();
     }
 
-    if equiv.is_empty() {
-        return Err("Error: Missing equiv symbol after '[='".to_string());
+    // [=equiv=]
+    if chars.len() >= 5 && chars[1] == '=' && chars[3] == '=' && chars[4] == ']' {
+        let equiv = Equiv {
+            char: chars[2],
+            chars: equivalents(chars[2], utf8_locale()),
+        };
+        return Ok(Some((Operand::Equiv(equiv), 5)));
     }
 
-    // Skip '='
-    let Some('=') = chars.next() else {
-        return Err("Error: Missing '=' before ']' for '[=equiv=]'".to_string());
-    };
-
-    // Skip ']'
-    let Some(']') = chars.next() else {
-        return Err("Error: Missing closing ']' for '[=equiv=]'".to_string());
-    };
-
-    let mut operands = Vec::new();
-    for equiv_char in equiv.chars() {
-        operands.push(Operand::Equiv(Equiv { char: equiv_char }));
+    // [x*n]
+    if chars.len() < 2 {
+        return Ok(None);
     }
-
-    Ok(operands)
-}
-
-/// Parses a sequence in the format `[x*n]` from the given character iterator.
-///
-/// The function expects the iterator to be positioned just before the symbol `x`.
-/// It reads the symbol, the repetition count `n`, and creates an `Operand::Char`
-/// entry with the specified number of repetitions.
-///
-/// # Arguments
-///
-/// * `chars` - A mutable reference to a peekable character iterator.
-/// * `symbol` - The symbol that is being repeated.
-///
-/// # Returns
-///
-/// A `Result` containing an `Operand::Char` entry if successful, or a `String`
-/// describing the error if parsing fails.
-///
-/// # Errors
-///
-/// This function will return an error if:
-/// - The sequence does not contain a `*` after the symbol.
-/// - The sequence does not contain a closing `]`.
-/// - The repetition count `n` is not a valid positive integer.
-///
-fn parse_repeated_char(
-    chars: &mut std::iter::Peekable<std::str::Chars>,
-    symbol: char,
-) -> Result<Operand, String> {
-    // Skip '*'
-    let Some('*') = chars.next() else {
-        return Err(format!(
-            "Error: Missing '*' after '[' for symbol '{}'",
-            symbol
-        ));
-    };
-
-    let mut repeat_str = String::new();
-    while let Some(&digit) = chars.peek() {
-        if !digit.is_ascii_digit() {
-            break;
-        }
-        repeat_str.push(digit);
-        chars.next();
+    let (symbol, symbol_len) = parse_char(&chars[1..]);
+    let mut i = 1 + symbol_len;
+    if chars.get(i) != Some(&'*') {
+        return Ok(None);
     }
-
-    // Skip ']'
-    let Some(']') = chars.next() else {
-        return Err("Error: Missing closing ']'".to_string());
-    };
-
-    let repeated = match repeat_str.parse::<usize>() {
-        Ok(n) if n > 0 => n,
-        _ => usize::MAX,
+    i += 1;
+    let digits_len = chars[i..].iter().take_while(|c| c.is_ascii_digit()).count();
+    if chars.get(i + digits_len) != Some(&']') {
+        return Ok(None);
+    }
+    let digits: String = chars[i..i + digits_len].iter().collect();
+
+    let radix = if digits.starts_with('0') { 8 } else { 10 };
+    let repeated = match usize::from_str_radix(&digits, radix) {
+        Ok(0) => usize::MAX,
+        Ok(n) => n,
+        Err(_) if digits.is_empty() => usize::MAX,
+        Err(_) => {
+            return Err(format!(
+                "Error: Invalid repeat count '{}' in [c*n] construct",
+                digits
+            ))
+        }
     };
 
-    Ok(Operand::Char(Char {
-        char: symbol,
-        repeated,
-    }))
+    Ok(Some((
+        Operand::Char(Char {
+            char: symbol,
+            repeated,
+        }),
+        i + digits_len + 1,
+    )))
 }
 
 /// Parses an input string and converts it into a vector of `Operand` entries.
 ///
 /// This function processes the input string, looking for sequences in the formats
-/// `[=equiv=]` and `[x*n]`, as well as regular characters. It delegates the parsing
-/// of the specific formats to helper functions `parse_equiv` and `parse_repeated_char`.
+/// `[:class:]`, `[=equiv=]` and `[x*n]`, ranges `c-c`, and regular characters and escape
+/// sequences. It delegates the parsing of the specific formats to the helper functions
+/// `parse_bracket` and `parse_char`.
 ///
 /// # Arguments
 ///
@@ -324,239 +507,63 @@ fn parse_repeated_char(
 /// # Errors
 ///
 /// This function will return an error if:
-/// - It encounters an invalid format.
-/// - It encounters any specific error from `parse_equiv` or `parse_repeated_char`.
+/// - The end of a range comes before its start.
+/// - It encounters any specific error from `parse_bracket`.
 ///
 fn parse_symbols(input: &str) -> Result<Vec<Operand>, String> {
     let mut operands: Vec<Operand> = Vec::new();
-    let mut chars = input.chars().peekable();
-
-    while let Some(&ch) = chars.peek() {
-        match ch {
-            '[' => {
-                // Skip '['
-                chars.next();
-                let Some('=') = chars.peek() else {
-                    let symbol = chars
-                        .next()
-                        .ok_or("Error: Missing symbol after '['".to_string())?;
-                    operands.push(parse_repeated_char(&mut chars, symbol)?);
-                    continue;
-                };
-
-                operands.extend(parse_equiv(&mut chars)?);
+    let chars: Vec<char> = input.chars().collect();
+    let mut i = 0;
+
+    while i < chars.len() {
+        if chars[i] == '[' {
+            if let Some((operand, len)) = parse_bracket(&chars[i..])? {
+                operands.push(operand);
+                i += len;
+                continue;
             }
-            // A single backslash character (0x5C)
-            // https://pubs.opengroup.org/onlinepubs/9799919799/basedefs/V1_chap05.html#tagtcjh_2
-            // https://www.unicode.org/Public/UCD/latest/ucd/NameAliases.txt
-            '\\' => {
-                // Skip '\'
-                chars.next();
-
-                let char_after_initial_backslash = chars.next();
-
-                let char_for_operand = match char_after_initial_backslash {
-                    // <alert>
-                    // Code point 0007
-                    Some('a') => '\u{0007}',
-                    // <backspace>
-                    // Code point 0008
-                    Some('b') => '\u{0008}',
-                    // <tab>
-                    // Code point 0009
-                    Some('t') => '\u{0009}',
-                    // <newline>
-                    // Code point 000A
-                    Some('n') => '\u{000A}',
-                    // <vertical-tab>
-                    // Code point 000B
-                    Some('v') => '\u{000B}',
-                    // <form-feed>
-                    // Code point 000C
-                    Some('f') => '\u{000C}',
-                    // <carriage-return>
-                    // Code point 000D
-                    Some('r') => '\u{000D}',
-                    // <backslash>
-                    // Code point 005C
-                    Some('\\') => {
-                        // An escaped backslash
-                        '\u{005C}'
-                    }
-                    Some(cha) => {
-                        // If a backslash is not at the end of the string, and is not followed by one of the valid
-                        // escape characters (including another backslash), the backslash is basically just ignored:
-                        // the following character is the character added to the set.
-                        cha
-                    }
-                    None => {
-                        eprintln!(
-                            "tr: warning: an unescaped backslash at end of string is not portable"
-                        );
-
-                        // If an unescaped backslash is the last character of the string, treat it as though it were
-                        // escaped (backslash is added to the set)
-                        '\u{005C}'
-                    }
-                };
+        }
 
-                operands.push(Operand::Char(Char {
-                    char: char_for_operand,
-                    repeated: 1,
-                }));
+        let (start, len) = parse_char(&chars[i..]);
+        i += len;
+
+        // A range of characters, unless the '-' is the last character
+        if chars.get(i) == Some(&'-') && i + 1 < chars.len() {
+            let (end, len) = parse_char(&chars[i + 1..]);
+            if end < start {
+                return Err(format!(
+                    "Error: Range-endpoints of '{}-{}' are in reverse collating sequence order",
+                    start, end
+                ));
             }
-            _ => {
-                // Add a regular character with a repetition of 1
-                operands.push(Operand::Char(Char {
-                    char: ch,
+            operands.extend((start..=end).map(|c| {
+                Operand::Char(Char {
+                    char: c,
                     repeated: 1,
-                }));
-                chars.next();
-            }
+                })
+            }));
+            i += 1 + len;
+            continue;
         }
+
+        // Add a regular character with a repetition of 1
+        operands.push(Operand::Char(Char {
+            char: start,
+            repeated: 1,
+        }));
     }
 
     Ok(operands)
 }
 
 /// Represents the case sensitivity of character classes.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 enum CaseSensitive {
     UpperCase,
     LowerCase,
     None,
 }
 
-/// Compares two characters after normalizing them.
-/// This function uses the hypothetical `deunicode_char` function to normalize
-/// the input characters and then compares them for equality.
-/// # Arguments
-///
-/// * `char1` - The first character to compare.
-/// * `char2` - The second character to compare.
-///
-/// # Returns
-///
-/// * `true` if the normalized characters are equal.
-/// * `false` otherwise.
-fn compare_deunicoded_chars(char1: char, char2: char) -> bool {
-    let normalized_char1 = deunicode_char(char1);
-    let normalized_char2 = deunicode_char(char2);
-    normalized_char1 == normalized_char2
-}
-
-/// Expands a character class name into a vector of `Operand` elements and determines the case sensitivity.
-/// This function expands the given character class name into its corresponding characters
-/// and wraps them in `Operand::Char`. It also determines the case sensitivity of the class
-/// based on the class name. If the class name is invalid, it returns an error message.
-/// # Arguments
-///
-/// * `class` - A string slice representing the character class name.
-///
-/// # Returns
-///
-/// * `Result<(Vec<Operand>, CaseSensitive), String>` - On success, returns a tuple containing a vector of `Operand` elements and a `CaseSensitive` value.
-///   On failure, returns an error message.
-fn expand_character_class(class: &str) -> Result<(Vec<Operand>, CaseSensitive), String> {
-    let mut case_sensitive = CaseSensitive::None;
-    let result = match class {
-        "alnum" => ('0'..='9').chain('A'..='Z').chain('a'..='z').collect(),
-        "alpha" => ('A'..='Z').chain('a'..='z').collect(),
-        "digit" => ('0'..='9').collect(),
-        "lower" => {
-            case_sensitive = CaseSensitive::LowerCase;
-            ('a'..='z').collect()
-        }
-        "upper" => {
-            case_sensitive = CaseSensitive::UpperCase;
-            ('A'..='Z').collect()
-        }
-        "space" => vec![' ', '\t', '\n', '\r', '\x0b', '\x0c'],
-        "blank" => vec![' ', '\t'],
-        "cntrl" => (0..=31)
-            .chain(std::iter::once(127))
-            .map(|c| c as u8 as char)
-            .collect(),
-        "graph" => (33..=126).map(|c| c as u8 as char).collect(),
-        "print" => (32..=126).map(|c| c as u8 as char).collect(),
-        "punct" => (33..=47)
-            .chain(58..=64)
-            .chain(91..=96)
-            .chain(123..=126)
-            .map(|c| c as u8 as char)
-            .collect(),
-        "xdigit" => ('0'..='9').chain('A'..='F').chain('a'..='f').collect(),
-        _ => return Err("Error: Invalid class name ".to_string()),
-    };
-    Ok((
-        result
-            .into_iter()
-            .map(|c| {
-                Operand::Char(Char {
-                    char: c,
-                    repeated: 1,
-                })
-            })
-            .collect(),
-        case_sensitive,
-    ))
-}
-
-/// Parses character classes from a string input and expands them into a vector of `Operand` elements.
-///
-/// This function parses the input string for character classes in the `[:class:]` format and expands them
-/// using the `expand_character_class` function. It collects the resulting `Operand` elements and determines
-/// the case sensitivity of the character classes encountered.
-///
-/// If an invalid format is detected, the function returns an error message.
-/// # Arguments
-///
-/// * `input` - A string slice containing the input to parse.
-///
-/// # Returns
-///
-/// * `Result<(Vec<Operand>, CaseSensitive), String>` - On success, returns a tuple containing a vector of `Operand` elements and a `CaseSensitive` value.
-///   On failure, returns an error message.
-fn parse_classes(input: &str) -> Result<(Vec<Operand>, CaseSensitive), String> {
-    let mut classes: Vec<Operand> = Vec::new();
-    let mut chars = input.chars().peekable();
-    let mut case_sensitive = CaseSensitive::None;
-    while let Some(ch) = chars.next() {
-        if ch == '[' {
-            let Some(':') = chars.next() else {
-                continue;
-            };
-            // Processing the [:class:] format
-
-            let mut class = String::new();
-            while let Some(&next_ch) = chars.peek() {
-                if next_ch == ':' {
-                    break;
-                }
-                class.push(next_ch);
-                chars.next();
-            }
-            if class.is_empty() {
-                return Err("Error: Missing class name after '[:'".to_string());
-            }
-            // Skip ':'
-            let Some(':') = chars.next() else {
-                return Err("Error: Missing ':' before ']' for '[:class:]'".to_string());
-            };
-            // Skip ']'
-            let Some(']') = chars.next() else {
-                return Err("Error: Missing closing ']' for '[:class:]'".to_string());
-            };
-
-            let res = expand_character_class(&class)?;
-            case_sensitive = res.1;
-            classes.extend(res.0);
-        }
-    }
-
-    Ok((classes, case_sensitive))
-}
-
 /// Parses an octal string and returns the corresponding character, if valid.
 ///
 /// # Arguments
@@ -574,233 +581,247 @@ fn parse_octal(s: &str) -> Option<char> {
     u32::from_str_radix(s, 8).ok().and_then(char::from_u32)
 }
 
-/// Parses a string representing a range of characters or octal values and returns a vector of `Operand`s.
-///
-/// This function handles ranges specified in square brackets, such as `[a-z]` or `[\\141-\\172]`.
-/// It supports ranges of plain characters and ranges of octal-encoded characters. The function
-/// trims the square brackets from the input string, splits the range into start and end parts,
-/// and then expands the range into a list of `Operand`s.
+/// Parses a set expression and returns a vector of `Operand`s.
 ///
 /// # Arguments
 ///
-/// * `input` - A string slice containing the range to be parsed. The range can be in the form of
-///   `[a-z]`, `[\\141-\\172]`, etc.
+/// * `set` - A string slice representing the set expression to be parsed.
 ///
 /// # Returns
 ///
-/// * `Result<Vec<Operand>, String>` - Returns `Ok(Vec<Operand>)` if the input string represents
-///   a valid range. Returns `Err(String)` with an error message if the input is invalid.
-///
-/// # Errors
+/// * `Result<Vec<Operand>, String>` - Returns `Ok(Vec<Operand>)` on successful parsing.
+///   Returns `Err(String)` with an error message if the input is invalid.
 ///
-/// This function returns an error if:
-/// - The input string does not contain a valid range.
-/// - The octal values in the range cannot be parsed into valid characters.
-///
-fn parse_ranges(input: &str) -> Result<Vec<Operand>, String> {
-    let mut chars = Vec::new();
-    let s = input.trim_matches(|c| c == '[' || c == ']'); // Remove square brackets
-    let parts: Vec<&str> = s.split('-').collect();
-
-    let start = parts[0];
-    let end = parts[1];
-
-    if start.starts_with('\\') && end.starts_with('\\') {
-        // Processing the \octal-\octal range
-        if let (Some(start_char), Some(end_char)) =
-            (parse_octal(&start[1..]), parse_octal(&end[1..]))
-        {
-            let start_u32 = start_char as u32;
-            let end_u32 = end_char as u32;
+fn parse_set(set: &str) -> Result<Vec<Operand>, String> {
+    parse_symbols(set)
+}
 
-            for code in start_u32..=end_u32 {
-                if let Some(c) = char::from_u32(code) {
-                    chars.push(c);
-                }
+/// Expands STRING1 into its characters in order, for translation. Each character comes
+/// with the case of the `[:upper:]` or `[:lower:]` class that it is part of, and whether
+/// it is the first character of a class.
+fn expand_string1(set1: &[Operand]) -> Vec<(Operand, CaseSensitive, bool)> {
+    let mut expanded = Vec::new();
+    for operand in set1 {
+        match operand {
+            Operand::Char(c) => {
+                // There is nothing to fill in STRING1
+                let repeated = if c.repeated == usize::MAX {
+                    1
+                } else {
+                    c.repeated
+                };
+                let single = Operand::Char(Char {
+                    char: c.char,
+                    repeated: 1,
+                });
+                expanded.extend(std::iter::repeat_n(
+                    (single, CaseSensitive::None, false),
+                    repeated,
+                ));
             }
-        }
-    } else if !start.starts_with('\\') && !end.starts_with('\\') {
-        // Processing the c-c range
-        let start_char = start.chars().next().unwrap();
-        let end_char = end.chars().next().unwrap();
-
-        for c in start_char..=end_char {
-            chars.push(c);
+            Operand::Equiv(_) => expanded.push((operand.clone(), CaseSensitive::None, false)),
+            Operand::Class(class) => expanded.extend(class.chars().enumerate().map(|(i, c)| {
+                let single = Operand::Char(Char {
+                    char: c,
+                    repeated: 1,
+                });
+                (single, class.case(), i == 0)
+            })),
         }
     }
-
-    Ok(chars
-        .into_iter()
-        .map(|c| {
-            Operand::Char(Char {
-                char: c,
-                repeated: 1,
-            })
-        })
-        .collect())
+    expanded
 }
 
-/// Parses a set expression and returns a vector of `Operand`s and a `CaseSensitive` flag.
+/// Expands STRING2 into the characters that the characters of the expanded STRING1 are
+/// translated to, with `fill` characters for `[x*]`.
 ///
-/// The function determines the type of set based on its format and delegates
-/// the parsing to the appropriate helper function. The supported formats are:
-/// - Character classes in the form of `[:class:]`
-/// - Single ranges such as `[a-z]` or `[\\141-\\172]`
-/// - Symbols and characters not fitting the above categories
+/// `[:upper:]` or `[:lower:]` must be in the same position as either of them in STRING1,
+/// and converts the characters of the class in STRING1 to its case. Without STRING1, when
+/// translating its complement, the classes are their characters.
 ///
-/// # Arguments
-///
-/// * `set` - A string slice representing the set expression to be parsed.
-///
-/// # Returns
-///
-/// * `Result<(Vec<Operand>, CaseSensitive), String>` - Returns `Ok((Vec<Operand>, CaseSensitive))`
-///   on successful parsing. Returns `Err(String)` with an error message if the input is invalid.
+/// # Errors
 ///
-fn parse_set(set: &str) -> Result<(Vec<Operand>, CaseSensitive), String> {
-    if set.starts_with("[:") && set.ends_with(":]") {
-        Ok(parse_classes(set)?)
-    } else if contains_single_range(set) {
-        Ok((parse_ranges(set)?, CaseSensitive::None))
-    } else {
-        Ok((parse_symbols(set)?, CaseSensitive::None))
+/// Returns an error if a `[:upper:]` or `[:lower:]` isn't aligned with one in STRING1.
+fn expand_string2_with_fill(
+    set2: &[Operand],
+    set1: Option<&[(Operand, CaseSensitive, bool)]>,
+    fill: usize,
+) -> Result<Vec<char>, String> {
+    let mut expanded = Vec::new();
+    for operand in set2 {
+        match operand {
+            Operand::Char(c) => {
+                let repeated = if c.repeated == usize::MAX {
+                    fill
+                } else {
+                    c.repeated
+                };
+                expanded.extend(std::iter::repeat_n(c.char, repeated));
+            }
+            Operand::Equiv(e) => expanded.push(e.char),
+            Operand::Class(class) => {
+                let Some(set1) = set1 else {
+                    expanded.extend(class.chars());
+                    continue;
+                };
+                let case = match set1.get(expanded.len()) {
+                    Some((_, case, true)) if *case != CaseSensitive::None => *case,
+                    _ => {
+                        return Err(
+                            "Error: misaligned [:upper:] and/or [:lower:] construct".to_string()
+                        )
+                    }
+                };
+                let aligned = set1[expanded.len()..]
+                    .iter()
+                    .enumerate()
+                    .take_while(|(i, (_, c, start))| *c == case && (*i == 0 || !start))
+                    .map(|(_, (operand, _, _))| class.convert_case(operand.char()))
+                    .collect::<Vec<_>>();
+                expanded.extend(aligned);
+            }
+        }
     }
+    Ok(expanded)
 }
 
-/// Determines if a string contains a single valid range expression.
-///
-/// This function uses a regular expression to check if the input string matches any
-/// of the following range formats:
-/// - `[a-z]` or `[A-Z]` or `[0-9]`: Character ranges enclosed in square brackets
-/// - `\\octal-\\octal`: Ranges of octal-encoded characters
-/// - `a-z` or `A-Z` or `0-9`: Simple character-symbol ranges
-///
-/// # Arguments
-///
-/// * `s` - A string slice to be checked for containing a single valid range.
-///
-/// # Returns
-///
-/// * `bool` - Returns `true` if the input string matches any of the valid range formats.
-///   Returns `false` otherwise.
-///
-fn contains_single_range(s: &str) -> bool {
-    // Regular expression for a range of characters or \octal
-    let re = Regex::new(
-        r"(?x)
-        ^ \[ [a-zA-Z0-9\\]+ - [a-zA-Z0-9\\]+ \] $ |   # Range in square brackets
-        ^ \\ [0-7]{1,3} - \\ [0-7]{1,3} $ |           # Range \octal-\octal
-        ^ [a-zA-Z0-9] - [a-zA-Z0-9] $                 # Character-symbol range
-    ",
-    )
-    .unwrap();
-
-    re.is_match(s)
+/// Checks that the only classes of STRING2 are `[:upper:]` and `[:lower:]`.
+fn check_string2_classes(set2: &[Operand]) -> Result<(), String> {
+    if set2.iter().any(
+        |operand| matches!(operand, Operand::Class(class) if class.case() == CaseSensitive::None),
+    ) {
+        return Err("Error: When translating, the only character classes that may appear in string2 are 'upper' and 'lower'".to_string());
+    }
+    Ok(())
 }
 
-/// Computes the complement of a string with respect to two sets of characters.
-///
-/// This function takes an input string and two sets of characters (`chars1` and `chars2`)
-/// and computes the complement of the input string with respect to the characters in `chars1`.
-/// For each character in the input string:
-/// - If the character is present in `chars1`, it remains unchanged in the result.
-/// - If the character is not present in `chars1`, it is replaced with characters from `chars2`
-///   sequentially until all characters in `chars2` are exhausted, and then the process repeats.
-///
-/// # Arguments
-///
-/// * `input` - A string slice representing the input string.
-/// * `chars1` - A vector of `Operand` representing the first set of characters.
-/// * `chars2` - A vector of `Operand` representing the second set of characters.
-///
-/// # Returns
-///
-/// * `String` - Returns a string representing the complement of the input string.
-///
-fn complement_chars(input: &str, chars1: Vec<Operand>, mut chars2: Vec<Operand>) -> String {
-    // Create a variable to store the result
-    let mut result = String::new();
-    let chars_2_const = chars2.clone();
-
-    // Initialize the index for the chars2 vector
-    let mut chars2_index = 0;
+/// The error for a STRING2 that is shorter than STRING1 and ends with a class, which
+/// can't be padded with its last character.
+fn class_at_end_error() -> String {
+    "Error: When translating with string1 longer than string2, the latter string must not end with a character class".to_string()
+}
 
-    // Convert the input string to a character vector for easy processing
-    let input_chars: Vec<char> = input.chars().collect();
+/// The translation of the characters of STRING1, or of its complement, to those of STRING2.
+struct Translation {
+    chars: HashMap<char, char>,
+    // With `-c` or `-C`, STRING1, whose characters are left as they are, and the
+    // translation of the characters of the complement that aren't in `chars`
+    complement: Option<(Vec<Operand>, char)>,
+}
 
-    // Go through each character in the input string
-    for &ch in &input_chars {
-        // Check if the character is in the chars1 vector
-        if Operand::contains(&chars1, &ch) {
-            // If the character is in the chars1 vector, add it to the result without changing it
-            result.push(ch);
-            continue;
+impl Translation {
+    /// Builds the translation of the characters of STRING1 to those of STRING2. A STRING2
+    /// shorter than STRING1 is padded with its last character.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if STRING2 is empty, has a class other than `[:upper:]` and
+    /// `[:lower:]`, or one that isn't aligned with either of them in STRING1, or is shorter
+    /// than STRING1 and ends with a class.
+    fn new(set1: &[Operand], set2: &[Operand]) -> Result<Translation, String> {
+        check_string2_classes(set2)?;
+
+        let set1 = expand_string1(set1);
+        let fill = set1
+            .len()
+            .saturating_sub(expand_string2_with_fill(set2, Some(&set1), 0)?.len());
+        let ends_with_class = matches!(set2.last(), Some(Operand::Class(_)));
+        let mut set2 = expand_string2_with_fill(set2, Some(&set1), fill)?;
+        match set2.last() {
+            Some(_) if set2.len() < set1.len() && ends_with_class => {
+                return Err(class_at_end_error())
+            }
+            Some(&last) if set2.len() < set1.len() => set2.resize(set1.len(), last),
+            None if !set1.is_empty() => {
+                return Err("Error: When not truncating set1, string2 must be non-empty".to_string())
+            }
+            _ => {}
         }
-        // If the character is not in the chars1 vector, replace it with a character from the chars2 vector
-        // Add the character from the chars2 vector to the result
-        let operand = &mut chars2[chars2_index];
-        match operand {
-            Operand::Char(char) => {
-                result.push(char.char);
-                char.repeated -= 1;
 
-                if char.repeated > 0 {
-                    continue;
+        // A character that is in STRING1 more than once is translated by its last position
+        let mut chars = HashMap::new();
+        for ((operand, _, _), to) in set1.iter().zip(set2) {
+            match operand {
+                Operand::Equiv(e) => chars.extend(e.chars.iter().map(|&c| (c, to))),
+                _ => {
+                    chars.insert(operand.char(), to);
                 }
             }
-            Operand::Equiv(equiv) => {
-                result.push(equiv.char);
-            }
         }
+        Ok(Translation {
+            chars,
+            complement: None,
+        })
+    }
 
-        // Increase the index for the chars2 vector
-        chars2_index += 1;
-
-        // If the index has reached the end of the chars2 vector, reset it to zero
-        if chars2_index >= chars2.len() {
-            chars2_index = 0;
-            chars2.clone_from(&chars_2_const);
+    /// Builds the translation of the complement of STRING1 to the characters of STRING2.
+    ///
+    /// The complement is in the order of the code points, which is the collation order of
+    /// the POSIX locale, for both `-c` and `-C`. The characters of STRING2 before a `[x*]`
+    /// are the translations of the first characters of the complement, those after it of
+    /// the last ones, and `x` of everything in between. Without `[x*]`, STRING2 is padded
+    /// with its last character.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if STRING2 is empty, has a class other than `[:upper:]` and
+    /// `[:lower:]`, or ends with a class without a `[x*]`.
+    fn complement(set1: Vec<Operand>, set2: &[Operand]) -> Result<Translation, String> {
+        check_string2_classes(set2)?;
+
+        let fill = set2
+            .iter()
+            .position(|operand| matches!(operand, Operand::Char(c) if c.repeated == usize::MAX));
+        let (first, last, rest) = match fill {
+            Some(i) => (
+                expand_string2_with_fill(&set2[..i], None, 0)?,
+                expand_string2_with_fill(&set2[i + 1..], None, 0)?,
+                set2[i].char(),
+            ),
+            // The complement is longer than STRING2, which is padded with its last character
+            None if matches!(set2.last(), Some(Operand::Class(_))) => {
+                return Err(class_at_end_error())
+            }
+            None => {
+                let first = expand_string2_with_fill(set2, None, 0)?;
+                let Some(&rest) = first.last() else {
+                    return Err(
+                        "Error: When not truncating set1, string2 must be non-empty".to_string()
+                    );
+                };
+                (first, Vec::new(), rest)
+            }
+        };
+
+        let mut chars = HashMap::new();
+        let in_complement = |c: &char| !Operand::contains(&set1, c);
+        let max_char = max_char();
+        for (c, to) in ('\0'..=max_char)
+            .rev()
+            .filter(in_complement)
+            .zip(last.into_iter().rev())
+        {
+            chars.insert(c, to);
+        }
+        for (c, to) in ('\0'..=max_char).filter(in_complement).zip(first) {
+            chars.insert(c, to);
         }
-    }
 
-    result
-}
+        Ok(Translation {
+            chars,
+            complement: Some((set1, rest)),
+        })
+    }
 
-/// Checks if a character is repeatable based on certain conditions.
-///
-/// This function determines if a character `c` is repeatable based on the following conditions:
-/// - The character occurs more than once in the input string.
-/// - The character is present in the set `set2`.
-///
-/// If the conditions are met and the character has not been seen before, it is considered repeatable.
-///
-/// # Arguments
-///
-/// * `c` - A character to be checked for repeatability.
-/// * `char_counts` - A reference to a hashmap containing character counts in the input string.
-/// * `seen` - A mutable reference to a hash set to keep track of characters seen so far.
-/// * `set2` - A reference to a vector of `Operand` representing the second set of characters.
-///
-/// # Returns
-///
-/// * `bool` - Returns `true` if the character is repeatable based on the conditions specified above.
-///            Returns `false` otherwise.
-///
-fn check_repeatable(
-    c: char,
-    char_counts: &HashMap<char, usize>,
-    seen: &mut HashSet<char>,
-    set2: &Vec<Operand>,
-) -> bool {
-    if char_counts[&c] > 1 && Operand::contains(set2, &c) {
-        if seen.contains(&c) {
-            false
-        } else {
-            seen.insert(c);
-            true
+    fn translate(&self, c: char) -> char {
+        if let Some((set1, rest)) = &self.complement {
+            if Operand::contains(set1, &c) {
+                return c;
+            }
+            return self.chars.get(&c).copied().unwrap_or(*rest);
         }
-    } else {
-        true
+
+        self.chars.get(&c).copied().unwrap_or(c)
     }
 }
 
@@ -820,182 +841,74 @@ fn check_repeatable(
 ///   if there is an error reading from standard input or processing the input string.
 ///
 fn tr(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
-    let mut input = String::new();
-    io::stdin()
-        .read_to_string(&mut input)
-        .expect("Failed to read input");
+    let mut input = Vec::new();
+    if let Err(err) = io::stdin().lock().read_to_end(&mut input) {
+        return Err(format!("tr: read error: {}", err).into());
+    }
 
-    let (set1, set_1_collection) = parse_set(&args.string1)?;
-    let (mut set2, mut set_2_collection) = (None, CaseSensitive::None);
+    let utf8 = utf8_locale();
+    let set1 = parse_set(&locale_chars(&args.string1, utf8))?;
+    let mut set2 = None;
     if let Some(string2) = &args.string2 {
-        let result = parse_set(string2)?;
-        set2 = Some(result.0);
-        set_2_collection = result.1;
+        set2 = Some(parse_set(&locale_chars(string2, utf8))?);
     }
+    let complement = args.complement_char || args.complement_val;
 
-    if args.delete {
-        let mut filtered_string: String;
-
-        if args.complement_char || args.complement_val {
-            filtered_string = input
-                .chars()
-                .filter(|c| Operand::contains(&set1, c))
-                .collect();
-        } else {
-            filtered_string = input
-                .chars()
-                .filter(|c| !Operand::contains(&set1, c))
-                .collect();
-        }
-
-        if args.squeeze_repeats && set2.is_some() {
-            // Counting the frequency of characters in the chars vector
-            let mut char_counts = HashMap::new();
-            for c in filtered_string.chars() {
-                *char_counts.entry(c).or_insert(0) += 1;
-            }
-
-            let mut seen = HashSet::new();
-            filtered_string = filtered_string
-                .chars()
-                .filter(|&c| check_repeatable(c, &char_counts, &mut seen, set2.as_ref().unwrap()))
-                .collect();
-        }
-
-        print!("{filtered_string}");
-        Ok(())
-    } else if args.squeeze_repeats && set2.is_none() {
-        let mut char_counts = HashMap::new();
-        for c in input.chars() {
-            *char_counts.entry(c).or_insert(0) += 1;
-        }
-
-        let mut seen = HashSet::new();
-        let filtered_string: String = input
-            .chars()
-            .filter(|&c| {
-                if char_counts[&c] > 1 && Operand::contains(&set1, &c) {
-                    if seen.contains(&c) {
-                        false
-                    } else {
-                        seen.insert(c);
-                        true
-                    }
-                } else {
-                    true
-                }
-            })
-            .collect();
-        print!("{filtered_string}");
-        return Ok(());
-    } else {
-        let mut result_string: String;
-
-        if args.complement_char || args.complement_val {
-            if args.complement_char {
-                result_string = complement_chars(&input, set1, set2.clone().unwrap());
-            } else {
-                let mut set2 = set2.clone().unwrap();
-                set2.sort_by(|a, b| match (a, b) {
-                    (Operand::Char(char1), Operand::Char(char2)) => char1.char.cmp(&char2.char),
-                    (Operand::Equiv(equiv1), Operand::Equiv(equiv2)) => {
-                        equiv1.char.cmp(&equiv2.char)
-                    }
-                    (Operand::Char(char1), Operand::Equiv(equiv2)) => char1.char.cmp(&equiv2.char),
-                    (Operand::Equiv(equiv1), Operand::Char(char2)) => equiv1.char.cmp(&char2.char),
-                });
-
-                result_string = complement_chars(&input, set1, set2);
-            }
+    let translation = match &set2 {
+        Some(set2) if !args.delete => Some(if complement {
+            Translation::complement(set1.clone(), set2)?
         } else {
-            if set_1_collection != CaseSensitive::None
-                && set_2_collection != CaseSensitive::None
-                && set_1_collection != set_2_collection
-            {
-                match set_1_collection {
-                    CaseSensitive::UpperCase => print!("{}", input.to_lowercase()),
-
-                    CaseSensitive::LowerCase => print!("{}", input.to_uppercase()),
-                    _ => (),
-                }
-                return Ok(());
-            }
-
-            let set_2 = set2.clone().unwrap();
-            let input_chars: Vec<char> = input.chars().collect();
-
-            let mut result_chars = input_chars.clone();
-            let input_len = input_chars.len();
-
-            let mut start = 0;
-            let end_loop = input_len;
-
-            while start < end_loop {
-                let mut match_len = 0;
-                let mut j = 0;
-                let mut end = start;
-
-                while j < set1.len() && end < input_len {
-                    let mut count = 0;
-
-                    if let Operand::Equiv(equiv) = &set1[j] {
-                        if end < input_len && compare_deunicoded_chars(equiv.char, input_chars[end])
-                        {
-                            j += 1;
-                            end += 1;
-                            match_len = end - start;
-                        }
-                    } else if let Operand::Char(char_struct) = &set1[j] {
-                        while end < input_len && input_chars[end] == char_struct.char {
-                            count += 1;
-                            end += 1;
-                        }
-                        if count != 0 && count <= char_struct.repeated {
-                            j += 1;
-                            match_len = end - start;
-                        } else {
-                            break;
-                        }
-                    }
-                }
-
-                if match_len > 0 {
-                    let set_2_chars = filter_chars(set_2.clone());
-                    let string_for_replace = create_minimal_string(set_2_chars, match_len);
-
-                    result_chars.splice(start..start + match_len, string_for_replace);
+            Translation::new(&set1, set2)?
+        }),
+        _ => None,
+    };
 
-                    start += match_len;
-                    continue;
-                }
+    // The squeezed set is the last string: STRING2 if it is given, otherwise STRING1,
+    // or its complement
+    let squeezed = |c: char| match &set2 {
+        Some(set2) => Operand::contains(set2, &c),
+        None => Operand::contains(&set1, &c) != complement,
+    };
 
-                start += 1;
+    let mut output = Vec::with_capacity(input.len());
+    let mut last = None;
+    for decoded in decode(&input, utf8) {
+        let c = match decoded {
+            Ok(c) => c,
+            Err(byte) => {
+                output.push(byte);
+                last = None;
+                continue;
             }
-
-            result_string = result_chars.into_iter().collect();
-        }
-
-        if args.squeeze_repeats {
-            // Counting the frequency of characters in the chars vector
-            let mut char_counts = HashMap::new();
-            for c in result_string.chars() {
-                *char_counts.entry(c).or_insert(0) += 1;
+        };
+        let c = if args.delete {
+            if Operand::contains(&set1, &c) != complement {
+                continue;
             }
+            c
+        } else {
+            translation.as_ref().map_or(c, |t| t.translate(c))
+        };
 
-            let mut seen = HashSet::new();
-            result_string = result_string
-                .chars()
-                .filter(|&c| check_repeatable(c, &char_counts, &mut seen, set2.as_ref().unwrap()))
-                .collect();
+        // Replace each sequence of a repeated character in the squeezed set with a single
+        // occurrence of that character
+        if args.squeeze_repeats && last == Some(c) && squeezed(c) {
+            continue;
         }
-
-        print!("{result_string}");
-        return Ok(());
+        last = Some(c);
+        encode(c, utf8, &mut output);
     }
+
+    io::stdout().write_all(&output)?;
+    Ok(())
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     setlocale(LocaleCategory::LcAll, "");
+    // The character classes and case conversions are those of the C library's locale
+    unsafe {
+        libc::setlocale(libc::LC_ALL, c"".as_ptr());
+    }
     textdomain(PROJECT_NAME)?;
     bind_textdomain_codeset(PROJECT_NAME, "UTF-8")?;
 
@@ -1005,7 +918,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     if let Err(err) = tr(&args) {
         exit_code = 1;
-        eprint!("{}", err);
+        eprintln!("{}", err);
     }
 
     std::process::exit(exit_code)
@@ -1,8 +1,8 @@
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use gettextrs::{bind_textdomain_codeset, setlocale, textdomain, LocaleCategory};
 use plib::PROJECT_NAME;
 use std::fs::File;
-use std::io::{self, BufRead, BufReader, Write};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
 use std::path::PathBuf;
 
 /// The uniq utility - filters out duplicate lines in a file
@@ -29,6 +29,35 @@ struct Args {
     #[arg(short = 's')]
     chars: Option<usize>,
 
+    /// Compare at most this many characters of each line, after the skipped fields and characters
+    #[arg(short = 'w')]
+    width: Option<usize>,
+
+    /// Ignore differences in case when comparing lines
+    #[arg(short = 'i')]
+    ignore_case: bool,
+
+    /// Print all the repeated lines, with groups of them delimited as given
+    #[arg(
+        short = 'D',
+        long = "all-repeated",
+        value_enum,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "none"
+    )]
+    all_repeated: Option<Delimit>,
+
+    /// Print all the lines, with a blank line between, before, or after each group of equal lines
+    #[arg(
+        long = "group",
+        value_enum,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "separate"
+    )]
+    group: Option<GroupMethod>,
+
     /// Input file (if not specified, use stdin)
     input_file: Option<PathBuf>,
 
@@ -36,6 +65,30 @@ struct Args {
     output_file: Option<PathBuf>,
 }
 
+/// Where the groups of `-D` are delimited with blank lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Delimit {
+    /// Not at all.
+    None,
+    /// Before each group.
+    Prepend,
+    /// Between the groups.
+    Separate,
+}
+
+/// Where the groups of `--group` are delimited with blank lines, in addition to between them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum GroupMethod {
+    /// Only between the groups.
+    Separate,
+    /// Before the first group.
+    Prepend,
+    /// After the last group.
+    Append,
+    /// Before the first group and after the last one.
+    Both,
+}
+
 impl Args {
     /// Validates the arguments to ensure no conflicting options are used together.
     ///
@@ -53,6 +106,28 @@ impl Args {
         if self.count && self.unique {
             return Err("Options '-c' and '-u' cannot be used together".to_string());
         }
+        if self.all_repeated.is_some() {
+            if self.count {
+                return Err("Options '-c' and '-D' cannot be used together".to_string());
+            }
+            if self.unique {
+                return Err("Options '-u' and '-D' cannot be used together".to_string());
+            }
+        }
+        if self.group.is_some() {
+            let others = [
+                (self.count, 'c'),
+                (self.repeated, 'd'),
+                (self.all_repeated.is_some(), 'D'),
+                (self.unique, 'u'),
+            ];
+            if let Some((_, option)) = others.iter().find(|(given, _)| *given) {
+                return Err(format!(
+                    "Options '--group' and '-{}' cannot be used together",
+                    option
+                ));
+            }
+        }
         Ok(())
     }
 }
@@ -67,7 +142,7 @@ impl Args {
 ///
 /// Returns an error if there is an issue reading the input or writing the output.
 fn uniq(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
-    let input: Box<dyn BufRead> = match &args.input_file {
+    let mut input: Box<dyn BufRead> = match &args.input_file {
         Some(file) => {
             if *file == PathBuf::from("-") {
                 Box::new(BufReader::new(io::stdin()))
@@ -78,85 +153,133 @@ fn uniq(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
         None => Box::new(BufReader::new(io::stdin())),
     };
 
-    let mut output: Box<dyn Write> = match &args.output_file {
+    let output: Box<dyn Write> = match &args.output_file {
         Some(file) => Box::new(File::create(file)?),
         None => Box::new(io::stdout()),
     };
+    let mut output = BufWriter::new(output);
 
-    let lines: Vec<String> = input.lines().collect::<Result<_, _>>()?;
-
-    let mut last_line: Option<String> = None;
+    // -D and --group write each line as it is read, the other modes each
+    // group of lines once it has ended
+    let prints_all_lines = args.all_repeated.is_some() || args.group.is_some();
+    let mut last_line: Vec<u8> = Vec::new();
+    let mut line: Vec<u8> = Vec::new();
     let mut current_count = 0;
+    let mut groups = 0;
 
-    for line in &lines {
-        let processed_line = process_line(line, args.fields, args.chars);
+    loop {
+        line.clear();
+        if input.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+        if line.last() == Some(&b'\n') {
+            line.pop();
+        }
 
-        if let Some(last_line) = &last_line {
-            let processed_last_line = process_line(last_line, args.fields, args.chars);
-            if processed_line == processed_last_line {
+        if current_count > 0 {
+            let key = comparison_key(&line, args);
+            let last_key = comparison_key(&last_line, args);
+            if keys_equal(key, last_key, args.ignore_case) {
                 current_count += 1;
+                if let Some(delimit) = args.all_repeated {
+                    // The first line of a group is only known to be
+                    // repeated once the second one is read
+                    if current_count == 2 {
+                        if delimit == Delimit::Prepend
+                            || (delimit == Delimit::Separate && groups > 0)
+                        {
+                            output.write_all(b"\n")?;
+                        }
+                        groups += 1;
+                        write_line(&mut output, &last_line)?;
+                    }
+                    write_line(&mut output, &line)?;
+                } else if args.group.is_some() {
+                    write_line(&mut output, &line)?;
+                }
                 continue;
-            } else {
-                output_result(&mut output, last_line, current_count, args)?;
             }
+            if !prints_all_lines {
+                output_result(&mut output, &last_line, current_count, args)?;
+            }
+        }
+
+        if let Some(method) = args.group {
+            if groups > 0 || matches!(method, GroupMethod::Prepend | GroupMethod::Both) {
+                output.write_all(b"\n")?;
+            }
+            groups += 1;
+            write_line(&mut output, &line)?;
         }
-        last_line = Some(line.to_string());
+        std::mem::swap(&mut last_line, &mut line);
         current_count = 1;
     }
 
-    if let Some(last) = last_line {
-        output_result(&mut output, &last, current_count, args)?;
+    if current_count > 0 {
+        if !prints_all_lines {
+            output_result(&mut output, &last_line, current_count, args)?;
+        }
+        if matches!(args.group, Some(GroupMethod::Append | GroupMethod::Both)) {
+            output.write_all(b"\n")?;
+        }
     }
+    output.flush()?;
     Ok(())
 }
 
-/// Processes a line according to the specified field and character options.
+fn is_blank(b: u8) -> bool {
+    b == b' ' || b == b'\t'
+}
+
+/// Finds the byte offset after the first `chars` characters of `s`, or the end of `s` if it is
+/// shorter. Bytes that aren't part of valid UTF-8 count as characters of their own.
+fn char_offset(s: &[u8], chars: usize) -> usize {
+    // UTF-8 continuation bytes are the only ones of the form 10xxxxxx
+    s.iter()
+        .enumerate()
+        .filter(|(_, b)| (**b & 0xc0) != 0x80)
+        .nth(chars)
+        .map_or(s.len(), |(i, _)| i)
+}
+
+/// Finds the part of a line that is compared with `-f`, `-s` and `-w`.
 ///
 /// # Arguments
 ///
-/// * `line` - The line to be processed.
-/// * `fields` - The number of fields to skip.
-/// * `chars` - The number of characters to skip.
+/// * `line` - The line, without its newline.
+/// * `args` - A reference to the `Args` struct containing the command line arguments.
 ///
 /// # Returns
 ///
-/// Returns the processed line as a `String`.
-fn process_line(line: &str, fields: Option<usize>, chars: Option<usize>) -> String {
-    let mut processed_line = line.to_string();
-    if line.is_empty() {
-        return line.to_string();
-    }
-    if let Some(f) = fields {
-        if f == 0 {
-            processed_line = line.to_string();
-        } else {
-            let mut field_count = 0;
-
-            let chars = line.chars().skip_while(|c| {
-                if c.is_whitespace() {
-                    if field_count >= f - 1 {
-                        return false;
-                    }
-                    field_count += 1;
-                }
-                true
-            });
-            processed_line = chars.collect::<String>();
-        }
+/// Returns the compared part of `line`, which is empty if the line is too short.
+fn comparison_key<'a>(line: &'a [u8], args: &Args) -> &'a [u8] {
+    let mut key = line;
+
+    // A field is a run of blanks followed by a run of non-blanks
+    for _ in 0..args.fields.unwrap_or(0) {
+        let blanks = key.iter().take_while(|b| is_blank(**b)).count();
+        let field = key[blanks..].iter().take_while(|b| !is_blank(**b)).count();
+        key = &key[blanks + field..];
     }
 
-    if let Some(c) = chars {
-        if c < processed_line.len() {
-            processed_line = processed_line[c..].to_string();
-        } else {
-            processed_line.clear();
-        }
+    key = &key[char_offset(key, args.chars.unwrap_or(0))..];
+    if let Some(width) = args.width {
+        key = &key[..char_offset(key, width)];
     }
+    key
+}
 
-    if processed_line.is_empty() {
-        line.to_string()
-    } else {
-        processed_line
+/// Compares the keys of two lines, ignoring case with `-i`.
+fn keys_equal(key1: &[u8], key2: &[u8], ignore_case: bool) -> bool {
+    if !ignore_case {
+        return key1 == key2;
+    }
+    match (std::str::from_utf8(key1), std::str::from_utf8(key2)) {
+        (Ok(key1), Ok(key2)) => key1
+            .chars()
+            .flat_map(char::to_lowercase)
+            .eq(key2.chars().flat_map(char::to_lowercase)),
+        _ => key1.eq_ignore_ascii_case(key2),
     }
 }
 
@@ -174,22 +297,32 @@ fn process_line(line: &str, fields: Option<usize>, chars: Option<usize>) -> Stri
 /// Returns an error if there is an issue writing to the output.
 fn output_result<W: Write>(
     output: &mut W,
-    line: &str,
+    line: &[u8],
     count: usize,
     args: &Args,
 ) -> Result<(), io::Error> {
-    if args.count {
-        writeln!(output, "{} {}", count, line)?;
-    } else if args.repeated && count > 1 {
-        writeln!(output, "{}", line)?;
-    } else if args.unique && count == 1 {
-        writeln!(output, "{}", line)?;
-    } else if !args.repeated && !args.unique {
-        writeln!(output, "{}", line)?;
+    let selected = if args.repeated {
+        count > 1
+    } else if args.unique {
+        count == 1
+    } else {
+        true
+    };
+    if selected {
+        if args.count {
+            write!(output, "{} ", count)?;
+        }
+        write_line(output, line)?;
     }
     Ok(())
 }
 
+/// Writes a line followed by a newline.
+fn write_line<W: Write>(output: &mut W, line: &[u8]) -> Result<(), io::Error> {
+    output.write_all(line)?;
+    output.write_all(b"\n")
+}
+
 /// The main function that initializes the application, parses the arguments, and runs the uniq function.
 ///
 /// # Errors
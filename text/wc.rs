@@ -11,8 +11,11 @@ use clap::Parser;
 use gettextrs::{bind_textdomain_codeset, setlocale, textdomain, LocaleCategory};
 use plib::PROJECT_NAME;
 use std::ffi::OsStr;
-use std::io::{self, BufRead, Read};
-use std::path::PathBuf;
+use std::fs::File;
+use std::io::{self, Read, Seek};
+use std::os::fd::AsFd;
+use std::path::{Path, PathBuf};
+use std::ptr;
 
 /// wc - word, line, and byte or character count
 #[derive(Parser, Debug)]
@@ -30,7 +33,7 @@ struct Args {
     #[arg(short = 'm', long)]
     chars: bool,
 
-    /// Count number of lines in each file
+    /// Count number of words in each file
     #[arg(short, long)]
     words: bool,
 
@@ -38,9 +41,50 @@ struct Args {
     files: Vec<PathBuf>,
 }
 
+/// The size of the buffer the input is read in.
+const BUFSZ: usize = 64 * 1024;
+
+/// The maximum length of a character, in bytes, in any locale.
+const MB_LEN_MAX: usize = 16;
+
+extern "C" {
+    // Not exported by the `libc` crate
+    fn mbtowc(pwc: *mut libc::wchar_t, s: *const libc::c_char, n: libc::size_t) -> libc::c_int;
+    fn iswspace(wc: libc::c_uint) -> libc::c_int;
+    #[cfg(target_os = "macos")]
+    fn ___mb_cur_max() -> libc::c_int;
+    #[cfg(not(target_os = "macos"))]
+    fn __ctype_get_mb_cur_max() -> libc::size_t;
+}
+
+/// Whether a character can be longer than a byte in the locale of `LC_CTYPE`, which is
+/// `MB_CUR_MAX > 1` in C.
+fn multibyte_locale() -> bool {
+    #[cfg(target_os = "macos")]
+    let mb_cur_max = unsafe { ___mb_cur_max() } as usize;
+    #[cfg(not(target_os = "macos"))]
+    let mb_cur_max = unsafe { __ctype_get_mb_cur_max() };
+    mb_cur_max > 1
+}
+
+/// Decodes the character at the start of `bytes`, returning it and its length, or `None`
+/// if the bytes aren't a valid character, or not a complete one.
+fn decode_char(bytes: &[u8]) -> Option<(u32, usize)> {
+    let mut wc: libc::wchar_t = 0;
+    let len = unsafe { mbtowc(&mut wc, bytes.as_ptr().cast(), bytes.len()) };
+    if len < 0 {
+        // Reset the conversion state after an error
+        unsafe { mbtowc(ptr::null_mut(), ptr::null(), 0) };
+        return None;
+    }
+    // The null character has a length of 0
+    Some((wc as u32, (len as usize).max(1)))
+}
+
 struct CountInfo {
     words: usize,
     chars: usize,
+    bytes: usize,
     nl: usize,
 }
 
@@ -49,49 +93,39 @@ impl CountInfo {
         CountInfo {
             words: 0,
             chars: 0,
+            bytes: 0,
             nl: 0,
         }
     }
 
     fn accum(&mut self, count: &CountInfo) {
-        self.words = self.words + count.words;
-        self.chars = self.chars + count.chars;
-        self.nl = self.nl + count.nl;
+        self.words += count.words;
+        self.chars += count.chars;
+        self.bytes += count.bytes;
+        self.nl += count.nl;
     }
 }
 
 fn build_display_str(args: &Args, count: &CountInfo, filename: &OsStr) -> String {
-    let mut output = String::with_capacity(filename.len() + (3 * 10));
+    let mut output = String::with_capacity(filename.len() + (4 * 10));
 
     let multi_file = args.files.len() > 1;
-    let only_lines = (args.words == false) && (args.bytes == false) && (args.chars == false);
-    let only_words = (args.lines == false) && (args.bytes == false) && (args.chars == false);
-    let only_bytechars = (args.lines == false) && (args.words == false);
-
-    if args.lines {
-        let numstr = match only_lines {
-            true => format!("{}", count.nl),
-            false => format!("{:>8}", count.nl),
-        };
-        output.push_str(&numstr);
-    }
-    if args.words {
-        if output.len() > 0 {
+    let counts = [
+        (args.lines, count.nl),
+        (args.words, count.words),
+        (args.chars, count.chars),
+        (args.bytes, count.bytes),
+    ];
+    let n_counts = counts.iter().filter(|(selected, _)| *selected).count();
+
+    // A single count isn't aligned
+    for (_, n) in counts.iter().filter(|(selected, _)| *selected) {
+        if !output.is_empty() {
             output.push(' ');
         }
-        let numstr = match only_words {
-            true => format!("{}", count.words),
-            false => format!("{:>8}", count.words),
-        };
-        output.push_str(&numstr);
-    }
-    if args.bytes || args.chars {
-        if output.len() > 0 {
-            output.push(' ');
-        }
-        let numstr = match only_bytechars {
-            true => format!("{}", count.chars),
-            false => format!("{:>8}", count.chars),
+        let numstr = match n_counts {
+            1 => format!("{}", n),
+            _ => format!("{:>8}", n),
         };
         output.push_str(&numstr);
     }
@@ -109,83 +143,99 @@ fn build_display_str(args: &Args, count: &CountInfo, filename: &OsStr) -> String
     output
 }
 
-fn wc_file_bytes(count: &mut CountInfo, pathname: &PathBuf) -> io::Result<()> {
-    let mut file = plib::io::input_stream(pathname, false)?;
+/// Opens the file, or stdin, which is read through its own descriptor so that the size
+/// of a regular file redirected to it can be used.
+fn open_input(pathname: &Path) -> io::Result<File> {
+    if pathname.as_os_str().is_empty() {
+        io::stdin().as_fd().try_clone_to_owned().map(File::from)
+    } else {
+        File::open(pathname)
+    }
+}
 
-    let mut buffer = [0; plib::BUFSZ];
-    let mut in_word = false;
+/// Counts only the lines and bytes, which doesn't need looking at the characters: the
+/// newlines are searched for, and the size of a regular file is used if the lines aren't
+/// counted.
+fn wc_file_lines(args: &Args, count: &mut CountInfo, file: &mut File) -> io::Result<()> {
+    if !args.lines {
+        let metadata = file.metadata()?;
+        if metadata.is_file() {
+            let pos = file.stream_position()?;
+            count.bytes = metadata.len().saturating_sub(pos) as usize;
+            return Ok(());
+        }
+    }
 
+    let mut buffer = vec![0; BUFSZ];
     loop {
-        let n_read = file.read(&mut buffer[..])?;
+        let n_read = file.read(&mut buffer)?;
         if n_read == 0 {
             break;
         }
 
-        count.chars = count.chars + n_read;
-
-        let bufslice = &buffer[0..n_read];
-
-        for ch_u8 in bufslice {
-            let ch = *ch_u8 as char;
-
-            if ch == '\n' {
-                count.nl = count.nl + 1;
-                if in_word {
-                    in_word = false;
-                    count.words = count.words + 1;
-                }
-            } else if ch.is_whitespace() {
-                if in_word {
-                    in_word = false;
-                    count.words = count.words + 1;
-                }
-            } else {
-                if !in_word {
-                    in_word = true;
-                }
-            }
+        count.bytes += n_read;
+        if args.lines {
+            count.nl += memchr::memchr_iter(b'\n', &buffer[..n_read]).count();
         }
     }
 
-    if in_word {
-        count.words = count.words + 1;
-    }
-
     Ok(())
 }
 
-fn wc_file_chars(args: &Args, count: &mut CountInfo, pathname: &PathBuf) -> io::Result<()> {
-    let mut reader = plib::io::input_reader(pathname, false)?;
+/// Counts the lines, words, characters and bytes. In a multibyte locale, the characters
+/// are decoded, and the words are delimited by the characters that are white space in
+/// the locale; bytes that aren't part of a valid character are neither characters nor
+/// white space.
+fn wc_file_words(count: &mut CountInfo, file: &mut File, multibyte: bool) -> io::Result<()> {
+    let mut buffer = vec![0; BUFSZ];
+    // The bytes of a character that was incomplete at the end of the last read
+    let mut kept = 0;
+    let mut in_word = false;
 
     loop {
-        let mut buffer = String::new();
-        let n_read = reader.read_line(&mut buffer)?;
-        if n_read == 0 {
-            break;
-        }
-
-        count.nl = count.nl + 1;
-        count.chars = count.chars + n_read;
-
-        if args.words {
-            let mut in_word = false;
-
-            for ch in buffer.chars() {
-                if ch.is_whitespace() {
-                    if in_word {
-                        in_word = false;
-                        count.words = count.words + 1;
+        let n_read = file.read(&mut buffer[kept..])?;
+        count.bytes += n_read;
+        let data = &buffer[..kept + n_read];
+
+        let mut pos = 0;
+        while pos < data.len() {
+            let byte = data[pos];
+            let is_space = if !multibyte || byte.is_ascii() {
+                pos += 1;
+                count.chars += 1;
+                unsafe { libc::isspace(byte.into()) != 0 }
+            } else {
+                match decode_char(&data[pos..]) {
+                    Some((ch, len)) => {
+                        pos += len;
+                        count.chars += 1;
+                        unsafe { iswspace(ch) != 0 }
                     }
-                } else {
-                    if !in_word {
-                        in_word = true;
+                    // The rest of the character may be in the next read
+                    None if n_read != 0 && data.len() - pos < MB_LEN_MAX => break,
+                    None => {
+                        pos += 1;
+                        continue;
                     }
                 }
+            };
+
+            if byte == b'\n' {
+                count.nl += 1;
             }
-            if in_word {
-                count.words = count.words + 1;
+            if is_space {
+                in_word = false;
+            } else if !in_word {
+                in_word = true;
+                count.words += 1;
             }
         }
+
+        if n_read == 0 {
+            break;
+        }
+        kept = data.len() - pos;
+        buffer.copy_within(pos..pos + kept, 0);
     }
 
     Ok(())
@@ -193,14 +243,18 @@ fn wc_file_chars(args: &Args, count: &mut CountInfo, pathname: &PathBuf) -> io::
 
 fn wc_file(
     args: &Args,
-    chars_mode: bool,
-    pathname: &PathBuf,
+    multibyte: bool,
+    pathname: &Path,
     count: &mut CountInfo,
 ) -> io::Result<()> {
-    if chars_mode {
-        wc_file_chars(args, count, pathname)?;
+    let mut file = open_input(pathname)?;
+
+    // Without a multibyte locale, characters are bytes
+    if args.words || (args.chars && multibyte) {
+        wc_file_words(count, &mut file, multibyte)?;
     } else {
-        wc_file_bytes(count, pathname)?;
+        wc_file_lines(args, count, &mut file)?;
+        count.chars = count.bytes;
     }
 
     let output = build_display_str(args, count, pathname.as_os_str());
@@ -214,30 +268,31 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     // parse command line arguments
     let mut args = Args::parse();
 
-    let mut chars_mode = false;
-
     // Assign defaults, per POSIX
     if !args.bytes && !args.lines && !args.chars && !args.words {
         args.bytes = true;
         args.lines = true;
         args.words = true;
-    } else if args.chars {
-        args.bytes = false;
-        chars_mode = true;
     }
 
     setlocale(LocaleCategory::LcAll, "");
+    // The characters, and which of them are white space, are those of the C library's
+    // locale
+    unsafe {
+        libc::setlocale(libc::LC_ALL, c"".as_ptr());
+    }
     textdomain(PROJECT_NAME)?;
     bind_textdomain_codeset(PROJECT_NAME, "UTF-8")?;
 
     let mut exit_code = 0;
     let mut totals = CountInfo::new();
+    let multibyte = multibyte_locale();
 
     // input via stdin
     if args.files.is_empty() {
         let mut count = CountInfo::new();
 
-        if let Err(e) = wc_file(&args, chars_mode, &PathBuf::new(), &mut count) {
+        if let Err(e) = wc_file(&args, multibyte, &PathBuf::new(), &mut count) {
             exit_code = 1;
             eprintln!("stdin: {}", e);
         }
@@ -247,7 +302,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         for filename in &args.files {
             let mut count = CountInfo::new();
 
-            if let Err(e) = wc_file(&args, chars_mode, filename, &mut count) {
+            if let Err(e) = wc_file(&args, multibyte, filename, &mut count) {
                 exit_code = 1;
                 eprintln!("{}: {}", filename.display(), e);
             }
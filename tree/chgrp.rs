@@ -6,36 +6,59 @@
 // file in the root directory of this project.
 // SPDX-License-Identifier: MIT
 //
-// TODO:
-// - implement -h, -H, -L, -P
-//
 
+mod common;
+
+use self::common::{chown_file, chown_recursive, ChownConfig};
 use clap::Parser;
 use gettextrs::{bind_textdomain_codeset, setlocale, textdomain, LocaleCategory};
+use plib::owner::{self, OwnerSpec};
 use plib::PROJECT_NAME;
-use std::ffi::CString;
-use std::path::Path;
-use std::{fs, io};
+use std::path::PathBuf;
 
 /// chgrp - change file group ownership
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about)]
+#[command(author, version, about, long_about, disable_help_flag = true)]
 struct Args {
+    #[arg(long, action = clap::ArgAction::HelpLong)]
+    help: Option<bool>,
+
     /// Change symbolic links, rather than the files they point to
     #[arg(short = 'h', long)]
-    no_derereference: bool,
+    no_dereference: bool,
 
     /// Follow command line symlinks during -R recursion
-    #[arg(short = 'H', long)]
+    #[arg(
+        short = 'H',
+        overrides_with_all = [
+            "follow_cli",
+            "dereference",
+            "physical"
+        ]
+    )]
     follow_cli: bool,
 
     /// Follow symlinks during -R recursion
-    #[arg(short = 'L', group = "deref")]
+    #[arg(
+        short = 'L',
+        overrides_with_all = [
+            "follow_cli",
+            "dereference",
+            "physical"
+        ]
+    )]
     dereference: bool,
 
-    /// Never follow symlinks during -R recursion
-    #[arg(short = 'P', group = "deref")]
-    no_dereference2: bool,
+    /// Never follow symlinks during -R recursion, changing the symlinks themselves (the default)
+    #[arg(
+        short = 'P',
+        overrides_with_all = [
+            "follow_cli",
+            "dereference",
+            "physical"
+        ]
+    )]
+    physical: bool,
 
     /// Recursively change groups of directories and their contents
     #[arg(short, short_alias = 'R', long)]
@@ -45,50 +68,7 @@ struct Args {
     group: String,
 
     /// The files to change
-    files: Vec<String>,
-}
-
-fn chgrp_file(filename: &str, gid: u32, recurse: bool) -> Result<(), io::Error> {
-    let path = Path::new(filename);
-    let metadata = fs::metadata(path)?;
-
-    // recurse into directories
-    if metadata.is_dir() && recurse {
-        for entry in fs::read_dir(path)? {
-            let entry = entry?;
-            let entry_path = entry.path();
-            let entry_filename = entry_path.to_str().unwrap();
-            chgrp_file(entry_filename, gid, recurse)?;
-        }
-    }
-
-    // change the group
-    let pathstr = CString::new(filename).unwrap();
-    unsafe {
-        if libc::chown(pathstr.as_ptr(), libc::geteuid(), gid) != 0 {
-            return Err(io::Error::last_os_error());
-        }
-    }
-
-    Ok(())
-}
-
-// lookup string group by name, or parse numeric group ID
-fn parse_group(group: &str) -> Result<u32, &'static str> {
-    match group.parse::<u32>() {
-        Ok(gid) => Ok(gid),
-        Err(_) => {
-            // lookup group by name
-            let group_cstr = CString::new(group).unwrap();
-            let group = unsafe { libc::getgrnam(group_cstr.as_ptr()) };
-            if group.is_null() {
-                return Err("group not found");
-            }
-
-            let gid = unsafe { (*group).gr_gid };
-            Ok(gid)
-        }
-    }
+    files: Vec<PathBuf>,
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -103,13 +83,36 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut exit_code = 0;
 
     // lookup string group by name, or parse numeric group ID
-    let gid = parse_group(&args.group)?;
+    let gid = match owner::parse_group(&args.group) {
+        Ok(gid) => gid,
+        Err(e) => {
+            eprintln!("chgrp: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // chgrp is chown with only the group given
+    let cfg = ChownConfig {
+        util: "chgrp",
+        owner: OwnerSpec {
+            uid: None,
+            gid: Some(gid),
+        },
+        from: OwnerSpec::default(),
+        no_dereference: args.no_dereference,
+        follow_cli: args.follow_cli,
+        dereference: args.dereference,
+    };
 
     // apply the group to each file
-    for filename in &args.files {
-        if let Err(e) = chgrp_file(filename, gid, args.recurse) {
+    for path in &args.files {
+        let success = if args.recurse {
+            chown_recursive(&cfg, path)
+        } else {
+            chown_file(&cfg, path)
+        };
+        if !success {
             exit_code = 1;
-            eprintln!("{}: {}", filename, e);
         }
     }
 
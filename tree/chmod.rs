@@ -7,76 +7,374 @@
 // SPDX-License-Identifier: MIT
 //
 
+mod common;
+
+use self::common::error_string;
 use clap::Parser;
-use gettextrs::{bind_textdomain_codeset, setlocale, textdomain, LocaleCategory};
-use modestr::{ChmodMode, ChmodSymbolic};
+use ftw::{self, traverse_directory};
+use gettextrs::{bind_textdomain_codeset, gettext, setlocale, textdomain, LocaleCategory};
+use modestr::ChmodMode;
 use plib::{modestr, PROJECT_NAME};
-use std::os::unix::fs::PermissionsExt;
-use std::path::Path;
-use std::{fs, io};
+use std::cell::Cell;
+use std::ffi::{CStr, CString};
+use std::num::NonZeroUsize;
+use std::os::unix::{ffi::OsStrExt, fs::MetadataExt};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::{fs, io, thread};
 
 /// chmod - change the file modes
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about)]
 struct Args {
+    /// Follow command line symlinks during -R recursion
+    #[arg(
+        short = 'H',
+        overrides_with_all = [
+            "follow_cli",
+            "dereference",
+            "no_dereference"
+        ]
+    )]
+    follow_cli: bool,
+
+    /// Follow symlinks during -R recursion
+    #[arg(
+        short = 'L',
+        overrides_with_all = [
+            "follow_cli",
+            "dereference",
+            "no_dereference"
+        ]
+    )]
+    dereference: bool,
+
+    /// Never follow symlinks during -R recursion (the default)
+    #[arg(
+        short = 'P',
+        overrides_with_all = [
+            "follow_cli",
+            "dereference",
+            "no_dereference"
+        ]
+    )]
+    no_dereference: bool,
+
     /// Recursively change file mode bits.
     #[arg(short = 'R', long)]
     recurse: bool,
 
+    /// Report every file processed, with its old and new mode.
+    #[arg(short, long)]
+    verbose: bool,
+
+    /// Like verbose, but report only files whose mode changed.
+    #[arg(short, long)]
+    changes: bool,
+
+    /// Do not report errors, but still exit with a non-zero status.
+    #[arg(short = 'f', long, visible_alias = "quiet")]
+    silent: bool,
+
+    /// With -R, change modes using N threads (default: number of CPUs).
+    #[arg(long, value_name = "N", requires = "recurse")]
+    jobs: Option<Option<NonZeroUsize>>,
+
+    /// Use the mode of RFILE instead of a mode operand.
+    #[arg(long, value_name = "RFILE")]
+    reference: Option<PathBuf>,
+
     /// Represents the change to be made to the file mode bits of each file named by one of the file operands.
+    /// With --reference, this is the first file to change.
     mode: String,
 
     /// The files to change
-    files: Vec<String>,
+    files: Vec<PathBuf>,
 }
 
-// apply symbolic mutations to the given file at path
-fn set_permissions_symbolic(path: &Path, symbolic: &ChmodSymbolic) -> Result<(), io::Error> {
-    // query the current mode bits
-    let metadata = fs::metadata(path)?;
-    let mut perms = metadata.permissions();
+// report a failure, unless -f was given
+fn report_error(args: &Args, err_str: String) {
+    if !args.silent {
+        eprintln!("chmod: {}", err_str);
+    }
+}
 
-    // perform mutations on the mode bits
-    let new_mode = modestr::mutate(perms.mode(), symbolic);
+// describe the mode change of `path` as requested by -v or -c
+fn change_message(args: &Args, path: &str, old_mode: u32, new_mode: u32) -> Option<String> {
+    let old_mode = old_mode & 0o7777;
+    let new_mode = new_mode & 0o7777;
 
-    // update path in filesystem
-    perms.set_mode(new_mode);
-    fs::set_permissions(path, perms)?;
+    if old_mode != new_mode {
+        if args.verbose || args.changes {
+            return Some(gettext!(
+                "mode of '{}' changed from {:04o} ({}) to {:04o} ({})",
+                path,
+                old_mode,
+                modestr::mode_to_string(old_mode),
+                new_mode,
+                modestr::mode_to_string(new_mode)
+            ));
+        }
+    } else if args.verbose {
+        return Some(gettext!(
+            "mode of '{}' retained as {:04o} ({})",
+            path,
+            old_mode,
+            modestr::mode_to_string(old_mode)
+        ));
+    }
 
-    Ok(())
+    None
 }
 
-fn chmod_file(filename: &str, mode: &ChmodMode, recurse: bool) -> Result<(), io::Error> {
-    let path = Path::new(filename);
-    let metadata = fs::metadata(path)?;
+// apply the mode to the file `filename` relative to `dirfd`, whose current
+// metadata is `md`. `path` is used for reporting.
+//
+// On success, returns the message to print for -v or -c, if any. On
+// failure, returns the error message.
+fn chmod_at(
+    args: &Args,
+    dirfd: libc::c_int,
+    filename: &CStr,
+    path: &str,
+    mode: &ChmodMode,
+    umask: u32,
+    md: &ftw::Metadata,
+) -> Result<Option<String>, String> {
+    let cur_mode = md.mode();
+    let is_dir = md.file_type() == ftw::FileType::Directory;
+
+    // the new mode is computed up front so that it can be compared
+    // against the current one
+    let new_mode = mode.apply(cur_mode, umask, is_dir);
+
+    let ret = unsafe { libc::fchmodat(dirfd, filename.as_ptr(), new_mode as libc::mode_t, 0) };
+    if ret != 0 {
+        let e = io::Error::last_os_error();
+        return Err(gettext!(
+            "changing permissions of '{}': {}",
+            path,
+            error_string(&e)
+        ));
+    }
 
-    if metadata.is_dir() && recurse {
-        for entry in fs::read_dir(path)? {
-            let entry = entry?;
-            let entry_path = entry.path();
-            let entry_filename = entry_path.to_str().unwrap();
-            chmod_file(entry_filename, mode, recurse)?;
+    Ok(change_message(args, path, cur_mode, new_mode))
+}
+
+// print the outcome of `chmod_at`, returning whether it succeeded
+fn report(args: &Args, result: Result<Option<String>, String>) -> bool {
+    match result {
+        Ok(Some(msg)) => {
+            println!("{}", msg);
+            true
+        }
+        Ok(None) => true,
+        Err(err_str) => {
+            report_error(args, err_str);
+            false
         }
     }
+}
+
+fn chmod_file(args: &Args, path: &Path, mode: &ChmodMode, umask: u32) -> bool {
+    let filename = CString::new(path.as_os_str().as_bytes()).unwrap();
 
-    match mode {
-        // set the mode bits to the given value
-        ChmodMode::Absolute(m) => {
-            fs::set_permissions(path, fs::Permissions::from_mode(*m))?;
+    // operands are always dereferenced when not recursing
+    let metadata = match ftw::Metadata::new(libc::AT_FDCWD, filename.as_ptr(), true) {
+        Ok(md) => md,
+        Err(e) => {
+            report_error(
+                args,
+                gettext!("cannot access '{}': {}", path.display(), error_string(&e)),
+            );
+            return false;
         }
+    };
+    let result = chmod_at(
+        args,
+        libc::AT_FDCWD,
+        &filename,
+        &path.display().to_string(),
+        mode,
+        umask,
+        &metadata,
+    );
+    report(args, result)
+}
 
-        // apply symbolic mutations to the mode bits
-        ChmodMode::Symbolic(s) => {
-            set_permissions_symbolic(path, s)?;
+// describe an error found while walking the tree under `path`
+fn walk_error_message(path: &str, kind: ftw::ErrorKind, e: &io::Error) -> String {
+    match kind {
+        ftw::ErrorKind::OpenDir | ftw::ErrorKind::DirNotSearchable => {
+            gettext!("cannot access directory '{}': {}", path, error_string(e))
+        }
+        ftw::ErrorKind::ReadDir => {
+            gettext!("cannot read directory '{}': {}", path, error_string(e))
         }
+        ftw::ErrorKind::Open
+        | ftw::ErrorKind::Stat
+        | ftw::ErrorKind::ReadLink
+        | ftw::ErrorKind::Cycle => gettext!("cannot access '{}': {}", path, error_string(e)),
     }
+}
+
+fn chmod_recursive(args: &Args, path: &Path, mode: &ChmodMode, umask: u32) -> bool {
+    let success = Cell::new(true);
+
+    // The return value is not used because `traverse_directory` also returns
+    // `false` for operands that are not directories. Errors are tracked in
+    // `success` instead.
+    let _ = traverse_directory(
+        path,
+        |entry| {
+            let md = entry.metadata().unwrap();
 
-    Ok(())
+            // A symlink that is not followed has no mode bits of its own to
+            // change
+            if md.file_type() == ftw::FileType::SymbolicLink {
+                return Ok(false);
+            }
+            let result = chmod_at(
+                args,
+                entry.dir_fd(),
+                entry.file_name(),
+                &entry.path().clean_trailing_slashes(),
+                mode,
+                umask,
+                md,
+            );
+            if !report(args, result) {
+                success.set(false);
+            }
+
+            // Descend into directories even if their mode could not be
+            // changed
+            Ok(true)
+        },
+        |_| Ok(()),
+        |entry, error| {
+            success.set(false);
+
+            let err_str = walk_error_message(
+                &entry.path().clean_trailing_slashes(),
+                error.kind(),
+                &error.inner(),
+            );
+            report_error(args, err_str);
+            ftw::ErrorAction::Continue
+        },
+        args.follow_cli,
+        args.dereference,
+        false,
+    );
+
+    success.get()
+}
+
+// Messages produced while walking with `chmod_parallel`, keyed by path
+type Messages = Mutex<Vec<(PathBuf, Result<Option<String>, String>)>>;
+
+// change the mode of a file found by the walk of `chmod_parallel`, keeping
+// the message to print, if any
+fn chmod_walk_entry(
+    args: &Args,
+    entry: &ftw::WalkEntry,
+    mode: &ChmodMode,
+    umask: u32,
+    messages: &Messages,
+) {
+    let md = entry.metadata();
+
+    // A symlink that is not followed has no mode bits of its own to change
+    if md.file_type() == ftw::FileType::SymbolicLink {
+        return;
+    }
+    let result = chmod_at(
+        args,
+        entry.dir_fd(),
+        entry.file_name_cstr(),
+        &entry.path().display().to_string(),
+        mode,
+        umask,
+        md,
+    );
+    if !matches!(result, Ok(None)) {
+        messages
+            .lock()
+            .unwrap()
+            .push((entry.path().to_path_buf(), result));
+    }
+}
+
+// Like `chmod_recursive`, but with `jobs` threads reading directories
+// concurrently. Directories are changed on the walking threads before they
+// are read, the other files as they are found. Reports and errors are
+// printed in path order once the walk is done, so the output does not
+// depend on scheduling.
+fn chmod_parallel(
+    args: &Arc<Args>,
+    path: &Path,
+    mode: &Arc<ChmodMode>,
+    umask: u32,
+    jobs: NonZeroUsize,
+) -> bool {
+    let messages = Arc::new(Messages::default());
+
+    let walk = {
+        let (args, mode, messages) = (args.clone(), mode.clone(), messages.clone());
+        ftw::ParallelWalker::new([path])
+            .threads(jobs.get())
+            .follow_symlinks_on_args(args.follow_cli)
+            .follow_symlinks(args.dereference)
+            .descend_filter(move |entry| {
+                chmod_walk_entry(&args, entry, &mode, umask, &messages);
+
+                // Descend into directories even if their mode could not be
+                // changed
+                true
+            })
+            .walk()
+    };
+
+    for entry_or_err in walk {
+        match entry_or_err {
+            // Directories were changed by `descend_filter`
+            Ok(entry) if entry.metadata().file_type() == ftw::FileType::Directory => {}
+            Ok(entry) => chmod_walk_entry(args, &entry, mode, umask, &messages),
+            Err(error) => {
+                let path = error.path().to_path_buf();
+                let err_str = walk_error_message(
+                    &path.display().to_string(),
+                    error.kind(),
+                    &error.into_error().inner(),
+                );
+                messages.lock().unwrap().push((path, Err(err_str)));
+            }
+        }
+    }
+
+    let mut messages = std::mem::take(&mut *messages.lock().unwrap());
+    // stable, so that messages about the same path keep their order
+    messages.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut success = true;
+    for (_, result) in messages {
+        if !report(args, result) {
+            success = false;
+        }
+    }
+    success
+}
+
+// use the mode bits of `path`, without its file type, as an absolute mode
+fn reference_mode(path: &Path) -> io::Result<ChmodMode> {
+    let metadata = fs::metadata(path)?;
+    Ok(ChmodMode::Absolute(metadata.mode() & 0o7777))
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     // parse command line arguments
-    let args = Args::parse();
+    let args = Arc::new(Args::parse());
 
     // initialize translations
     setlocale(LocaleCategory::LcAll, "");
@@ -85,14 +383,59 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let mut exit_code = 0;
 
-    // parse the mode string
-    let mode = modestr::parse(&args.mode)?;
+    let mut files = Vec::with_capacity(args.files.len() + 1);
+
+    let mode = Arc::new(match &args.reference {
+        // take the mode from the reference file, and treat the mode
+        // operand as the first file to change
+        Some(reference) => {
+            files.push(PathBuf::from(&args.mode));
+            match reference_mode(reference) {
+                Ok(mode) => mode,
+                Err(e) => {
+                    eprintln!(
+                        "chmod: {}",
+                        gettext!(
+                            "failed to get attributes of '{}': {}",
+                            reference.display(),
+                            error_string(&e)
+                        )
+                    );
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        // parse the mode string
+        None => match modestr::parse(&args.mode) {
+            Ok(mode) => mode,
+            Err(e) => {
+                eprintln!("chmod: {}: {}", args.mode, e);
+                std::process::exit(1);
+            }
+        },
+    });
+    files.extend_from_slice(&args.files);
+
+    // clauses without a who-list are filtered through the umask
+    let umask = modestr::umask();
+
+    // -R --jobs without a value uses every CPU
+    let jobs = args.jobs.map(|jobs| {
+        jobs.unwrap_or_else(|| thread::available_parallelism().unwrap_or(NonZeroUsize::MIN))
+    });
 
     // apply the mode to each file
-    for filename in &args.files {
-        if let Err(e) = chmod_file(filename, &mode, args.recurse) {
+    for path in &files {
+        let success = if let Some(jobs) = jobs {
+            chmod_parallel(&args, path, &mode, umask, jobs)
+        } else if args.recurse {
+            chmod_recursive(&args, path, &mode, umask)
+        } else {
+            chmod_file(&args, path, &mode, umask)
+        };
+        if !success {
             exit_code = 1;
-            eprintln!("{}: {}", filename, e);
         }
     }
 
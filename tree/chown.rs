@@ -6,129 +6,74 @@
 // file in the root directory of this project.
 // SPDX-License-Identifier: MIT
 //
-// TODO:
-// - implement -h, -H, -L, -P
-//
 
+mod common;
+
+use self::common::{chown_file, chown_recursive, ChownConfig};
 use clap::Parser;
 use gettextrs::{bind_textdomain_codeset, setlocale, textdomain, LocaleCategory};
+use plib::owner::{self, OwnerSpec};
 use plib::PROJECT_NAME;
-use std::ffi::CString;
-use std::os::unix::fs::MetadataExt;
-use std::path::Path;
-use std::{fs, io};
+use std::path::PathBuf;
 
 /// chown - change the file ownership
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about)]
+#[command(author, version, about, long_about, disable_help_flag = true)]
 struct Args {
+    #[arg(long, action = clap::ArgAction::HelpLong)]
+    help: Option<bool>,
+
     /// Change symbolic links, rather than the files they point to
     #[arg(short = 'h', long)]
-    no_derereference: bool,
+    no_dereference: bool,
 
     /// Follow command line symlinks during -R recursion
-    #[arg(short = 'H', long)]
+    #[arg(
+        short = 'H',
+        overrides_with_all = [
+            "follow_cli",
+            "dereference",
+            "physical"
+        ]
+    )]
     follow_cli: bool,
 
     /// Follow symlinks during -R recursion
-    #[arg(short = 'L', group = "deref")]
+    #[arg(
+        short = 'L',
+        overrides_with_all = [
+            "follow_cli",
+            "dereference",
+            "physical"
+        ]
+    )]
     dereference: bool,
 
-    /// Never follow symlinks during -R recursion
-    #[arg(short = 'P', group = "deref")]
-    no_dereference2: bool,
+    /// Never follow symlinks during -R recursion, changing the symlinks themselves (the default)
+    #[arg(
+        short = 'P',
+        overrides_with_all = [
+            "follow_cli",
+            "dereference",
+            "physical"
+        ]
+    )]
+    physical: bool,
 
     /// Recursively change groups of directories and their contents
     #[arg(short, short_alias = 'R', long)]
     recurse: bool,
 
+    /// Only change files currently owned by CURRENT_OWNER and/or CURRENT_GROUP.
+    /// Either may be omitted, in which case it is not checked.
+    #[arg(long, value_name = "CURRENT_OWNER:CURRENT_GROUP")]
+    from: Option<String>,
+
     /// Owner and group are changed to OWNER[:GROUP]
     owner_group: String,
 
     /// The files to change
-    files: Vec<String>,
-}
-
-fn chown_file(filename: &str, uid: u32, gid: Option<u32>, recurse: bool) -> Result<(), io::Error> {
-    let path = Path::new(filename);
-    let metadata = fs::metadata(path)?;
-
-    // recurse into directories
-    if metadata.is_dir() && recurse {
-        for entry in fs::read_dir(path)? {
-            let entry = entry?;
-            let entry_path = entry.path();
-            let entry_filename = entry_path.to_str().unwrap();
-            chown_file(entry_filename, uid, gid, recurse)?;
-        }
-    }
-
-    // change the user, and optionally, the group
-    let pathstr = CString::new(filename).unwrap();
-    let gid = {
-        if let Some(gid) = gid {
-            gid
-        } else {
-            metadata.gid()
-        }
-    };
-    unsafe {
-        if libc::chown(pathstr.as_ptr(), uid, gid) != 0 {
-            return Err(io::Error::last_os_error());
-        }
-    }
-
-    Ok(())
-}
-
-// lookup string group by name, or parse numeric group ID
-fn parse_group(group: &str) -> Result<u32, &'static str> {
-    match group.parse::<u32>() {
-        Ok(gid) => Ok(gid),
-        Err(_) => {
-            // lookup group by name
-            let group_cstr = CString::new(group).unwrap();
-            let group = unsafe { libc::getgrnam(group_cstr.as_ptr()) };
-            if group.is_null() {
-                return Err("group not found");
-            }
-
-            let gid = unsafe { (*group).gr_gid };
-            Ok(gid)
-        }
-    }
-}
-
-// lookup string user by name, or parse numeric user ID
-fn parse_user(user: &str) -> Result<u32, &'static str> {
-    match user.parse::<u32>() {
-        Ok(uid) => Ok(uid),
-        Err(_) => {
-            // lookup user by name
-            let user_cstr = CString::new(user).unwrap();
-            let user = unsafe { libc::getpwnam(user_cstr.as_ptr()) };
-            if user.is_null() {
-                return Err("user not found");
-            }
-
-            let uid = unsafe { (*user).pw_uid };
-            Ok(uid)
-        }
-    }
-}
-
-fn parse_owner_group(owner_group: &str) -> Result<(u32, Option<u32>), &'static str> {
-    match owner_group.split_once(':') {
-        None => {
-            let uid = parse_user(owner_group)?;
-            Ok((uid, None))
-        }
-        Some((owner, group)) => {
-            let uid = parse_user(owner)?;
-            let gid = parse_group(group)?;
-            Ok((uid, Some(gid)))
-        }
-    }
+    files: Vec<PathBuf>,
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -143,13 +88,40 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut exit_code = 0;
 
     // lookup the owner and group
-    let (uid, gid) = parse_owner_group(&args.owner_group)?;
+    let owner = match owner::parse_owner_spec(&args.owner_group) {
+        Ok(owner) => owner,
+        Err(e) => {
+            eprintln!("chown: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let from = match args.from.as_deref().map(owner::parse_owner_spec) {
+        Some(Ok(from)) => from,
+        Some(Err(e)) => {
+            eprintln!("chown: {}", e);
+            std::process::exit(1);
+        }
+        None => OwnerSpec::default(),
+    };
+
+    let cfg = ChownConfig {
+        util: "chown",
+        owner,
+        from,
+        no_dereference: args.no_dereference,
+        follow_cli: args.follow_cli,
+        dereference: args.dereference,
+    };
 
-    // apply the group to each file
-    for filename in &args.files {
-        if let Err(e) = chown_file(filename, uid, gid, args.recurse) {
+    // apply the owner and group to each file
+    for path in &args.files {
+        let success = if args.recurse {
+            chown_recursive(&cfg, path)
+        } else {
+            chown_file(&cfg, path)
+        };
+        if !success {
             exit_code = 1;
-            eprintln!("{}: {}", filename, e);
         }
     }
 
@@ -1,20 +1,26 @@
-// This module is shared between `cp`, `mv` and `rm` but is considered as three
-// separated modules due to the project structure. The `#![allow(unused)]` is
-// to remove warnings when, say, `rm` doesn't use all the the functions in this
-// module (but is used in `cp` or `mv`).
+// This module is shared between `chgrp`, `chmod`, `chown`, `cp`, `mv` and `rm`
+// but is considered as separate modules due to the project structure. The
+// `#![allow(unused)]` is to remove warnings when, say, `rm` doesn't use all
+// the functions in this module (but is used in `cp` or `mv`).
 #![allow(unused)]
 
 use ftw::{self, traverse_directory};
 use gettextrs::gettext;
+use plib::owner::OwnerSpec;
+use plib::progress::Progress;
 use std::{
-    cell::RefCell,
+    cell::{Cell, RefCell},
     collections::{HashMap, HashSet},
     ffi::{CStr, CString, OsStr},
-    fs, io,
+    fs,
+    io::{self, Read},
     mem::MaybeUninit,
     os::{
         fd::{AsRawFd, FromRawFd},
-        unix::{ffi::OsStrExt, fs::MetadataExt},
+        unix::{
+            ffi::OsStrExt,
+            fs::{FileExt, MetadataExt},
+        },
     },
     path::{Path, PathBuf},
 };
@@ -48,13 +54,97 @@ pub fn error_string(e: &io::Error) -> String {
     gettext(s)
 }
 
+/// The characteristics of the source that are duplicated on the copy.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PreserveAttrs {
+    pub mode: bool,
+    pub ownership: bool,
+    pub timestamps: bool,
+    pub xattrs: bool,
+}
+
+impl PreserveAttrs {
+    /// The characteristics POSIX requires for `cp -p`.
+    pub fn posix() -> Self {
+        PreserveAttrs {
+            mode: true,
+            ownership: true,
+            timestamps: true,
+            xattrs: false,
+        }
+    }
+
+    pub fn any(&self) -> bool {
+        self.mode || self.ownership || self.timestamps || self.xattrs
+    }
+}
+
+/// How holes in the source file are handled when copying its contents.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Sparse {
+    /// Write every byte, holes are filled with zeros
+    Never,
+
+    /// Skip the holes of source files that have them
+    #[default]
+    Auto,
+
+    /// Also turn blocks of zero bytes into holes
+    Always,
+}
+
+/// Whether file contents are cloned (sharing the data blocks between the source
+/// and the copy) on file systems that support it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Reflink {
+    /// Always copy the data
+    Never,
+
+    /// Clone if possible, otherwise copy the data
+    #[default]
+    Auto,
+
+    /// Fail if the file cannot be cloned
+    Always,
+}
+
 pub struct CopyConfig {
     pub force: bool,
     pub follow_cli: bool,
     pub dereference: bool,
     pub interactive: bool,
-    pub preserve: bool,
+    pub no_clobber: bool,
+    pub preserve: PreserveAttrs,
     pub recursive: bool,
+    pub sparse: Sparse,
+    pub reflink: Reflink,
+    pub progress: Progress,
+}
+
+/// Return the permission bits of `mode` as 4 octal digits and in the
+/// `rwxrwxrwx` form, for prompts about unwritable files.
+pub fn mode_strings(mode: u32) -> (String, String) {
+    let mut mode_str = String::new();
+    let bit_loc = 0o400;
+    for i in 0..9 {
+        let mask = bit_loc >> i;
+        if mode & mask != 0 {
+            match i % 3 {
+                0 => mode_str.push('r'),
+                1 => mode_str.push('w'),
+                2 => mode_str.push('x'),
+                _ => (),
+            }
+        } else {
+            mode_str.push('-');
+        }
+    }
+
+    // This needs to be formatted separately because `gettext!` does not
+    // accept a format spec (just plain curly braces, `{}`).
+    let mode_octal = format!("{:04o}", mode & 0o7777);
+
+    (mode_octal, mode_str)
 }
 
 enum CopyResult {
@@ -79,8 +169,12 @@ where
     F: Fn(&str) -> bool,
 {
     let source_md = source.metadata().unwrap();
-    let source_is_symlink = source.is_symlink().unwrap_or(false);
     let source_file_type = source_md.file_type();
+
+    // Symlinks that are followed have the metadata of the file they point to
+    // and are copied like that file. The others (including dangling symlinks)
+    // are copied as symlinks.
+    let source_is_symlink = source_file_type == ftw::FileType::SymbolicLink;
     let source_is_dir = source_file_type == ftw::FileType::Directory;
 
     let source_is_special_file = match source_file_type {
@@ -101,6 +195,7 @@ where
             created_files,
             source_file_type == ftw::FileType::Fifo,
         )?;
+        cfg.progress.add_file();
         return Ok(CopyResult::CopiedFile);
     }
 
@@ -248,7 +343,7 @@ where
             let mut target_file = unsafe { fs::File::from_raw_fd(target_fd) };
 
             // 3.d
-            io::copy(&mut source_file, &mut target_file)?;
+            copy_data(cfg, &mut source_file, &mut target_file, target)?;
 
             Ok(())
         };
@@ -264,36 +359,21 @@ where
                 return Err(io::Error::other(err_str));
             }
 
+            if cfg.no_clobber {
+                return Ok(CopyResult::Skipped);
+            }
+
             // 3.a.i
             let target_is_writable = target_symlink_md
                 .as_ref()
                 .map(|md| md.is_writable())
                 .unwrap_or(false);
 
-            // Different prompt if the target is not writable
-            if !target_is_writable && (cfg.interactive || cfg.force) {
+            // Different prompt if the target is not writable. With -f too, it
+            // is about to be replaced rather than written to.
+            if !target_is_writable && cfg.interactive {
                 let mode = target_symlink_md.as_ref().unwrap().mode();
-
-                let mut mode_str = String::new();
-                let bit_loc = 0o400;
-                for i in 0..9 {
-                    let mask = bit_loc >> i;
-                    if mode & mask != 0 {
-                        match i % 3 {
-                            0 => mode_str.push('r'),
-                            1 => mode_str.push('w'),
-                            2 => mode_str.push('x'),
-                            _ => (),
-                        }
-                    } else {
-                        mode_str.push('-');
-                    }
-                }
-
-                // 4 octal digits
-                // This needs to be formatted separately because `gettext!` does
-                // not accept a format spec (just plain curly braces, `{}`).
-                let mode_octal = format!("{:04o}", mode & 0o7777);
+                let (mode_octal, mode_str) = mode_strings(mode);
 
                 if cfg.force {
                     let is_affirm = prompt_fn(&gettext!(
@@ -305,7 +385,7 @@ where
                     if !is_affirm {
                         return Ok(CopyResult::Skipped);
                     }
-                } else if cfg.interactive {
+                } else {
                     let is_affirm = prompt_fn(&gettext!(
                         "unwritable '{}' (mode {}, {}); try anyway?",
                         target.display(),
@@ -325,29 +405,24 @@ where
                 }
             }
 
+            // Checked after prompting, a declined prompt is not an error
+            if target_is_dir {
+                let err_str = gettext!(
+                    "cannot overwrite directory '{}' with non-directory '{}'",
+                    target.display(),
+                    source.path()
+                );
+                return Err(io::Error::other(err_str));
+            }
+
             // 4.c
             if source_is_symlink {
-                let ret = unsafe {
-                    libc::unlinkat(
-                        target_dirfd,
-                        target_filename,
-                        if target_is_dir { libc::AT_REMOVEDIR } else { 0 },
-                    )
-                };
+                let ret = unsafe { libc::unlinkat(target_dirfd, target_filename, 0) };
                 if ret != 0 {
                     return Err(io::Error::last_os_error());
                 }
 
-                let ret = unsafe {
-                    libc::symlinkat(
-                        source.read_link().unwrap().as_ptr(),
-                        target_dirfd,
-                        target_filename,
-                    )
-                };
-                if ret != 0 {
-                    return Err(io::Error::last_os_error());
-                }
+                copy_symlink(source, target, target_dirfd, target_filename)?;
             } else {
                 // 3.a.ii
                 let target_fd = unsafe {
@@ -374,17 +449,11 @@ where
                     }
                     let mut source_file = unsafe { fs::File::from_raw_fd(source_fd) };
 
-                    io::copy(&mut source_file, &mut target_file)?;
+                    copy_data(cfg, &mut source_file, &mut target_file, target)?;
                 } else {
                     // 3.a.iii
                     if cfg.force {
-                        let ret = unsafe {
-                            libc::unlinkat(
-                                target_dirfd,
-                                target_filename,
-                                if target_is_dir { libc::AT_REMOVEDIR } else { 0 },
-                            )
-                        };
+                        let ret = unsafe { libc::unlinkat(target_dirfd, target_filename, 0) };
                         if ret != 0 {
                             return Err(io::Error::last_os_error());
                         }
@@ -394,7 +463,7 @@ where
                     } else {
                         let e = io::Error::last_os_error();
                         let err_str = gettext!(
-                            "cannot open '{}' for reading: {}",
+                            "cannot open '{}' for writing: {}",
                             target.display(),
                             error_string(&e)
                         );
@@ -407,16 +476,14 @@ where
         } else {
             // 4.c
             if source_is_symlink {
-                let ret = unsafe {
-                    libc::symlinkat(
-                        source.read_link().unwrap().as_ptr(),
-                        target_dirfd,
-                        target_filename,
-                    )
-                };
-                if ret != 0 {
-                    return Err(io::Error::last_os_error());
+                // Replace a dangling symlink rather than writing through it
+                if target_exists {
+                    let ret = unsafe { libc::unlinkat(target_dirfd, target_filename, 0) };
+                    if ret != 0 {
+                        return Err(io::Error::last_os_error());
+                    }
                 }
+                copy_symlink(source, target, target_dirfd, target_filename)?;
             } else {
                 create_target_then_copy()?;
             }
@@ -425,6 +492,7 @@ where
         created_files.insert(target.to_path_buf());
     }
 
+    cfg.progress.add_file();
     Ok(CopyResult::CopiedFile)
 }
 
@@ -470,6 +538,22 @@ where
             let source_md = source.metadata().unwrap();
             let identifier = (source_md.dev(), source_md.ino());
 
+            // A symlink that is still a symlink after being dereferenced is dangling, which
+            // only skips that file, like a cycle
+            let dereference =
+                cfg.dereference || (cfg.follow_cli && target_dirfd.as_raw_fd() == libc::AT_FDCWD);
+            if dereference && source_md.file_type() == ftw::FileType::SymbolicLink {
+                let e = ftw::Metadata::new(source.dir_fd(), source.file_name().as_ptr(), true)
+                    .err()
+                    .unwrap_or_else(|| io::Error::from_raw_os_error(libc::ENOENT));
+                *last_error.borrow_mut() = Some(io::Error::other(gettext!(
+                    "cannot stat '{}': {}",
+                    source.path().clean_trailing_slashes(),
+                    error_string(&e)
+                )));
+                return Ok(false);
+            }
+
             // Hard-link preserving behavior of `mv`. `cp` does not maintain the hard-link structure
             // of the hierarchy according to the standard
             if let Some(inode_map) = inode_map.as_deref_mut() {
@@ -553,8 +637,9 @@ where
                         CopyResult::CopiedFile => {
                             // Immediately copy the metadata if copying a file. Directories are
                             // handled on the `postprocess_dir` closure below.
-                            if cfg.preserve {
+                            if cfg.preserve.any() {
                                 if let Err(e) = copy_characteristics(
+                                    &cfg.preserve,
                                     &source,
                                     &target,
                                     target_dirfd.as_raw_fd(),
@@ -592,7 +677,7 @@ where
 
             // Preserve metadata for directories. Must do this inside this closure to ensure no
             // further last access time changes to the source will be made.
-            if cfg.preserve {
+            if cfg.preserve.any() {
                 let target_dirfd = target_dirfd_stack_borrowed.last().unwrap();
 
                 let target_filename = if target_dirfd.as_raw_fd() == libc::AT_FDCWD {
@@ -603,8 +688,9 @@ where
                 let target_filename_cstr = CString::new(target_filename.as_bytes()).unwrap();
 
                 if let Err(e) = copy_characteristics(
+                    &cfg.preserve,
                     &source,
-                    &target_dir_path_borrowed,
+                    &target_dir_path_borrowed.join(target_filename),
                     target_dirfd.as_raw_fd(),
                     target_filename_cstr.as_ptr(),
                 ) {
@@ -615,12 +701,33 @@ where
 
             Ok(())
         },
-        |_entry, error| {
+        |entry, error| {
+            // A cycle only skips that directory, the rest of the hierarchy is still copied
+            if error.kind() == ftw::ErrorKind::Cycle {
+                *last_error.borrow_mut() = Some(io::Error::other(gettext!(
+                    "cannot copy cyclic symbolic link '{}'",
+                    entry.path().clean_trailing_slashes()
+                )));
+                return ftw::ErrorAction::Continue;
+            }
+            // So does a file that can't be stat'ed, such as a symlink loop under -L
+            if error.kind() == ftw::ErrorKind::Stat {
+                *last_error.borrow_mut() = Some(io::Error::other(gettext!(
+                    "cannot stat '{}': {}",
+                    entry.path().clean_trailing_slashes(),
+                    error_string(&error.inner())
+                )));
+                return ftw::ErrorAction::Continue;
+            }
+            // `file_handler` skips everything after this, but the directories already copied
+            // still get their characteristics from `postprocess_dir`
             *last_error.borrow_mut() = Some(error.inner());
             *terminate.borrow_mut() = true;
+            ftw::ErrorAction::Continue
         },
         cfg.follow_cli,
         cfg.dereference,
+        false,
     );
 
     match last_error.into_inner() {
@@ -690,6 +797,294 @@ where
     result
 }
 
+// Data is copied in chunks of this size between progress updates
+const COPY_CHUNK_SIZE: u64 = 16 * 1024 * 1024;
+
+// Copy the contents of `source` to the empty file `target`, leaving holes
+// where `cfg.sparse` says so. `target_path` is used for reporting.
+//
+// The fastest method that works is used: cloning the file, then copying in
+// the kernel with `copy_file_range` and finally reading and writing.
+fn copy_data(
+    cfg: &CopyConfig,
+    source: &mut fs::File,
+    target: &mut fs::File,
+    target_path: &Path,
+) -> io::Result<()> {
+    let source_md = source.metadata()?;
+    let target_md = target.metadata()?;
+    let sparse = cfg.sparse;
+
+    // Holes can only be made by seeking in regular files. A source with fewer
+    // allocated 512-byte blocks than its size has holes.
+    let seekable = source_md.is_file() && target_md.is_file();
+
+    // A clone has the same holes as the source and shares its blocks, so it is
+    // only done automatically when no particular --sparse is requested
+    let try_clone = match cfg.reflink {
+        Reflink::Never => false,
+        Reflink::Auto => seekable && sparse == Sparse::Auto,
+        Reflink::Always => true,
+    };
+    if try_clone {
+        match clone_file(source, target) {
+            Ok(()) => {
+                cfg.progress.add_bytes(source_md.size());
+                return Ok(());
+            }
+            Err(e) if cfg.reflink == Reflink::Always => {
+                let err_str = gettext!(
+                    "failed to clone '{}': {}",
+                    target_path.display(),
+                    error_string(&e)
+                );
+                return Err(io::Error::other(err_str));
+            }
+            Err(_) => (),
+        }
+    }
+
+    let has_holes = source_md.blocks() * 512 < source_md.size();
+    let skip_holes = match sparse {
+        Sparse::Never => false,
+        Sparse::Auto => seekable && has_holes,
+        Sparse::Always => seekable,
+    };
+    if !skip_holes {
+        // `std` already uses `copy_file_range` for this where possible. The
+        // copy is done in chunks to keep the progress up to date.
+        loop {
+            let n = io::copy(&mut (&*source).take(COPY_CHUNK_SIZE), target)?;
+            cfg.progress.add_bytes(n);
+            if n < COPY_CHUNK_SIZE {
+                return Ok(());
+            }
+        }
+    }
+
+    let block_size = target_md.blksize().max(512) as usize;
+    let zero_block_size = (sparse == Sparse::Always).then_some(block_size);
+    let mut buf = vec![0u8; block_size.max(128 * 1024) / block_size * block_size];
+    let progress = &cfg.progress;
+
+    // Only the data regions need to be copied. File systems that can't tell
+    // where those are fail with EINVAL, in which case the whole file is read.
+    let fd = source.as_raw_fd();
+    let mut offset = 0;
+    let end = loop {
+        let data_start = unsafe { libc::lseek(fd, offset as libc::off_t, libc::SEEK_DATA) };
+        if data_start < 0 {
+            let e = io::Error::last_os_error();
+            match e.raw_os_error() {
+                // The rest of the file is a hole
+                Some(libc::ENXIO) => break source_md.size(),
+                Some(libc::EINVAL) if offset == 0 => {
+                    break copy_range(source, target, 0, None, zero_block_size, &mut buf, progress)?
+                }
+                _ => return Err(e),
+            }
+        }
+
+        let data_end = unsafe { libc::lseek(fd, data_start, libc::SEEK_HOLE) };
+        if data_end < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        offset = copy_range(
+            source,
+            target,
+            data_start as u64,
+            Some(data_end as u64),
+            zero_block_size,
+            &mut buf,
+            progress,
+        )?;
+    };
+
+    // A hole at the end is not written so the size has to be set explicitly
+    target.set_len(end)
+}
+
+// Make `target` share the data blocks of `source` (a reflink).
+#[cfg(target_os = "linux")]
+fn clone_file(source: &fs::File, target: &fs::File) -> io::Result<()> {
+    let ret = unsafe { libc::ioctl(target.as_raw_fd(), libc::FICLONE, source.as_raw_fd()) };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn clone_file(_source: &fs::File, _target: &fs::File) -> io::Result<()> {
+    Err(io::Error::from_raw_os_error(libc::ENOTSUP))
+}
+
+// Copy the bytes of `source` from `start` up to `end` (or the end of the file)
+// to the same offsets in `target`, returning the offset reached. Blocks of
+// zero bytes of `zero_block_size`, if given, are skipped.
+fn copy_range(
+    source: &fs::File,
+    target: &fs::File,
+    start: u64,
+    end: Option<u64>,
+    zero_block_size: Option<usize>,
+    buf: &mut [u8],
+    progress: &Progress,
+) -> io::Result<u64> {
+    let mut offset = start;
+
+    // Zero detection needs to look at the data
+    if zero_block_size.is_none() {
+        offset = copy_range_in_kernel(source, target, offset, end, progress)?;
+    }
+
+    while end.is_none_or(|end| offset < end) {
+        let len = match end {
+            Some(end) => buf.len().min((end - offset) as usize),
+            None => buf.len(),
+        };
+        let n = match source.read_at(&mut buf[..len], offset) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+
+        match zero_block_size {
+            Some(block_size) => {
+                let mut chunk_offset = offset;
+                for chunk in buf[..n].chunks(block_size) {
+                    if !chunk.iter().all(|b| *b == 0) {
+                        target.write_all_at(chunk, chunk_offset)?;
+                    }
+                    chunk_offset += chunk.len() as u64;
+                }
+            }
+            None => target.write_all_at(&buf[..n], offset)?,
+        }
+        offset += n as u64;
+        progress.add_bytes(n as u64);
+    }
+    Ok(offset)
+}
+
+// Copy with `copy_file_range` for as long as it works, returning the offset
+// reached. The caller copies the rest, if any, by reading and writing: the
+// call is not supported across file systems on older kernels, nor by every
+// file system.
+#[cfg(target_os = "linux")]
+fn copy_range_in_kernel(
+    source: &fs::File,
+    target: &fs::File,
+    start: u64,
+    end: Option<u64>,
+    progress: &Progress,
+) -> io::Result<u64> {
+    let mut offset = start as libc::loff_t;
+    while end.is_none_or(|end| (offset as u64) < end) {
+        let len = match end {
+            Some(end) => (end - offset as u64).min(COPY_CHUNK_SIZE),
+            None => COPY_CHUNK_SIZE,
+        } as usize;
+
+        // Both offsets are updated by the kernel
+        let mut off_in = offset;
+        let mut off_out = offset;
+        let n = unsafe {
+            libc::copy_file_range(
+                source.as_raw_fd(),
+                &mut off_in,
+                target.as_raw_fd(),
+                &mut off_out,
+                len,
+                0,
+            )
+        };
+        match n {
+            0 => break,
+            n if n > 0 => {
+                offset += n as libc::loff_t;
+                progress.add_bytes(n as u64);
+            }
+            _ => {
+                let e = io::Error::last_os_error();
+                match e.raw_os_error() {
+                    Some(libc::EINTR) => continue,
+                    Some(
+                        libc::ENOSYS | libc::EXDEV | libc::EINVAL | libc::EOPNOTSUPP | libc::EPERM,
+                    ) => break,
+                    _ => return Err(e),
+                }
+            }
+        }
+    }
+    Ok(offset as u64)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn copy_range_in_kernel(
+    _source: &fs::File,
+    _target: &fs::File,
+    start: u64,
+    _end: Option<u64>,
+    _progress: &Progress,
+) -> io::Result<u64> {
+    Ok(start)
+}
+
+// Create a symlink at the target with the same contents as the symlink
+// `source`.
+fn copy_symlink(
+    source: &ftw::Entry,
+    target: &Path,
+    target_dirfd: libc::c_int,
+    target_filename: *const libc::c_char,
+) -> io::Result<()> {
+    // `ftw` only reads the link for symlinks that it was asked to follow
+    let link_target = match source.read_link() {
+        Some(link_target) => link_target.to_owned(),
+        None => read_link_at(source.dir_fd(), source.file_name()).map_err(|e| {
+            io::Error::other(gettext!(
+                "cannot read symbolic link '{}': {}",
+                source.path(),
+                error_string(&e)
+            ))
+        })?,
+    };
+
+    let ret = unsafe { libc::symlinkat(link_target.as_ptr(), target_dirfd, target_filename) };
+    if ret != 0 {
+        let e = io::Error::last_os_error();
+        let err_str = gettext!(
+            "cannot create symbolic link '{}': {}",
+            target.display(),
+            error_string(&e)
+        );
+        return Err(io::Error::other(err_str));
+    }
+    Ok(())
+}
+
+fn read_link_at(dirfd: libc::c_int, filename: &CStr) -> io::Result<CString> {
+    let mut buf = vec![0u8; libc::PATH_MAX as usize];
+    let len = unsafe {
+        libc::readlinkat(
+            dirfd,
+            filename.as_ptr(),
+            buf.as_mut_ptr() as *mut libc::c_char,
+            buf.len(),
+        )
+    };
+    if len < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    buf.truncate(len as usize);
+
+    // Symlink contents cannot hold NUL bytes
+    Ok(CString::new(buf).unwrap())
+}
+
 fn copy_special_file(
     cfg: &CopyConfig,
     source_md: &ftw::Metadata,
@@ -706,9 +1101,9 @@ fn copy_special_file(
     let dev = source_md.rdev();
 
     // 4.b
-    let mode = if is_fifo {
+    let permissions = if is_fifo {
         // Mandatory to be the same as source for FIFO
-        source_md.mode()
+        source_md.mode() & 0o7777
     } else {
         // Under Rationale:
         // "In general, it is strongly suggested that the permissions,
@@ -717,6 +1112,10 @@ fn copy_special_file(
         0o644
     };
 
+    // Recreate a node of the same type, `mknodat` would make a regular file
+    // without it
+    let mode = (source_md.mode() & libc::S_IFMT as u32) | permissions;
+
     let mut stat_buf = MaybeUninit::uninit();
 
     // Using `fstatat` to check for the existence of the target file
@@ -751,7 +1150,7 @@ fn copy_special_file(
     } else {
         let e = io::Error::last_os_error();
         let err_str = gettext!(
-            "cannot create regular file '{}': {}",
+            "cannot create special file '{}': {}",
             target.display(),
             error_string(&e)
         );
@@ -759,8 +1158,10 @@ fn copy_special_file(
     }
 }
 
-// Copy the metadata in `source_md` to the target.
+// Copy the characteristics selected in `preserve` from `source` to the
+// target. `target` is also used to copy the extended attributes.
 fn copy_characteristics(
+    preserve: &PreserveAttrs,
     source: &ftw::Entry,
     target: &Path,
     target_dirfd: libc::c_int,
@@ -772,6 +1173,12 @@ fn copy_characteristics(
     // access time than `d2`.
     let source_md = ftw::Metadata::new(source.dir_fd(), source.file_name().as_ptr(), false)?;
 
+    // Extended attributes go first, the mode may be changed to one that no
+    // longer allows writing them
+    if preserve.xattrs {
+        copy_xattrs(&source.path(), target)?;
+    }
+
     // [last_access_time, last_modified_time]
     let times = [
         libc::timespec {
@@ -784,41 +1191,63 @@ fn copy_characteristics(
         },
     ];
 
+    let mut mode = source_md.mode() as libc::mode_t;
+
     unsafe {
         // Copy last access and last modified times
-        let ret = libc::utimensat(
-            target_dirfd,
-            target_filename,
-            times.as_ptr(),
-            libc::AT_SYMLINK_NOFOLLOW, // Update the file itself if a symlink
-        );
-        if ret != 0 {
-            let err_str = gettext!(
-                "failed to preserve times for '{}': {}",
-                target.display(),
-                io::Error::last_os_error()
+        if preserve.timestamps {
+            let ret = libc::utimensat(
+                target_dirfd,
+                target_filename,
+                times.as_ptr(),
+                libc::AT_SYMLINK_NOFOLLOW, // Update the file itself if a symlink
             );
-            return Err(io::Error::other(err_str));
+            if ret != 0 {
+                let err_str = gettext!(
+                    "failed to preserve times for '{}': {}",
+                    target.display(),
+                    io::Error::last_os_error()
+                );
+                return Err(io::Error::other(err_str));
+            }
         }
 
-        // Copy user and group
-        let ret = libc::fchownat(
-            target_dirfd,
-            target_filename,
-            source_md.uid(),
-            source_md.gid(),
-            libc::AT_SYMLINK_NOFOLLOW,
-        );
-        if ret != 0 {
-            // Ignore errors
-            errno::set_errno(errno::Errno(0));
+        // Copy user and group. Users other than root can usually only change
+        // the group, and only to one they are a member of. This is not an
+        // error, but then the set-user-ID and set-group-ID bits must not be
+        // copied.
+        if preserve.ownership {
+            let ret = libc::fchownat(
+                target_dirfd,
+                target_filename,
+                source_md.uid(),
+                source_md.gid(),
+                libc::AT_SYMLINK_NOFOLLOW,
+            );
+            if ret != 0 {
+                libc::fchownat(
+                    target_dirfd,
+                    target_filename,
+                    libc::uid_t::MAX,
+                    source_md.gid(),
+                    libc::AT_SYMLINK_NOFOLLOW,
+                );
+                mode &= !(libc::S_ISUID | libc::S_ISGID);
+
+                // Ignore errors
+                errno::set_errno(errno::Errno(0));
+            }
+        }
+
+        if !preserve.mode {
+            return Ok(());
         }
 
         // Copy permissions
         let ret = libc::fchmodat(
             target_dirfd,
             target_filename,
-            source_md.mode() as libc::mode_t,
+            mode,
             libc::AT_SYMLINK_NOFOLLOW,
         );
         if ret != 0 {
@@ -846,3 +1275,233 @@ fn copy_characteristics(
     }
     Ok(())
 }
+
+// Copy the extended attributes of `source` to `target`, without following
+// symlinks. On Linux, this includes POSIX ACLs which are stored as the
+// `system.posix_acl_access` and `system.posix_acl_default` attributes.
+#[cfg(target_os = "linux")]
+fn copy_xattrs(source: &Path, target: &Path) -> io::Result<()> {
+    let source_cstr = CString::new(source.as_os_str().as_bytes()).unwrap();
+    let target_cstr = CString::new(target.as_os_str().as_bytes()).unwrap();
+
+    let err = |e: io::Error| {
+        io::Error::other(gettext!(
+            "failed to preserve extended attributes for '{}': {}",
+            target.display(),
+            error_string(&e)
+        ))
+    };
+
+    // Query the size first, the list is a sequence of NUL terminated names
+    let size = unsafe { libc::llistxattr(source_cstr.as_ptr(), std::ptr::null_mut(), 0) };
+    if size < 0 {
+        let e = io::Error::last_os_error();
+        return match e.raw_os_error() {
+            // The file system of the source doesn't support them so there is
+            // nothing to copy
+            Some(libc::ENOTSUP) => Ok(()),
+            _ => Err(err(e)),
+        };
+    }
+    let mut names = vec![0u8; size as usize];
+    let size = unsafe {
+        libc::llistxattr(
+            source_cstr.as_ptr(),
+            names.as_mut_ptr() as *mut libc::c_char,
+            names.len(),
+        )
+    };
+    if size < 0 {
+        return Err(err(io::Error::last_os_error()));
+    }
+    names.truncate(size as usize);
+
+    for name in names.split(|b| *b == 0).filter(|name| !name.is_empty()) {
+        let name = CString::new(name).unwrap();
+
+        let size = unsafe {
+            libc::lgetxattr(source_cstr.as_ptr(), name.as_ptr(), std::ptr::null_mut(), 0)
+        };
+        if size < 0 {
+            return Err(err(io::Error::last_os_error()));
+        }
+        let mut value = vec![0u8; size as usize];
+        let size = unsafe {
+            libc::lgetxattr(
+                source_cstr.as_ptr(),
+                name.as_ptr(),
+                value.as_mut_ptr() as *mut libc::c_void,
+                value.len(),
+            )
+        };
+        if size < 0 {
+            return Err(err(io::Error::last_os_error()));
+        }
+
+        let ret = unsafe {
+            libc::lsetxattr(
+                target_cstr.as_ptr(),
+                name.as_ptr(),
+                value.as_ptr() as *const libc::c_void,
+                size as usize,
+                0,
+            )
+        };
+        if ret != 0 {
+            return Err(err(io::Error::last_os_error()));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn copy_xattrs(_source: &Path, target: &Path) -> io::Result<()> {
+    Err(io::Error::other(gettext!(
+        "failed to preserve extended attributes for '{}': {}",
+        target.display(),
+        error_string(&io::Error::from_raw_os_error(libc::ENOTSUP))
+    )))
+}
+
+/// The options of `chown` and `chgrp`, which changes only the group.
+pub struct ChownConfig {
+    /// The utility, which prefixes the error messages
+    pub util: &'static str,
+    pub owner: OwnerSpec,
+    /// `--from`: files not matching this are left alone
+    pub from: OwnerSpec,
+    pub no_dereference: bool,
+    pub follow_cli: bool,
+    pub dereference: bool,
+}
+
+// change the ownership of the file `filename` relative to `dirfd`. The
+// symlink itself is changed if `follow` is not set. `path` is used for
+// reporting. Files not matching `cfg.from` are left alone.
+fn chown_at(
+    cfg: &ChownConfig,
+    dirfd: libc::c_int,
+    filename: &CStr,
+    path: &str,
+    follow: bool,
+) -> bool {
+    if cfg.from != OwnerSpec::default() {
+        match ftw::Metadata::new(dirfd, filename.as_ptr(), follow) {
+            Ok(md) => {
+                if !cfg.from.matches(md.uid(), md.gid()) {
+                    return true;
+                }
+            }
+            Err(e) => {
+                eprintln!(
+                    "{}: {}",
+                    cfg.util,
+                    gettext!("cannot access '{}': {}", path, error_string(&e))
+                );
+                return false;
+            }
+        }
+    }
+
+    // -1 leaves the owner or group unchanged
+    let uid = cfg.owner.uid.unwrap_or(libc::uid_t::MAX);
+    let gid = cfg.owner.gid.unwrap_or(libc::gid_t::MAX);
+    let flags = if follow { 0 } else { libc::AT_SYMLINK_NOFOLLOW };
+
+    let ret = unsafe { libc::fchownat(dirfd, filename.as_ptr(), uid, gid, flags) };
+    if ret != 0 {
+        let e = io::Error::last_os_error();
+        let msg = if cfg.owner.uid.is_none() {
+            gettext!("changing group of '{}': {}", path, error_string(&e))
+        } else {
+            gettext!("changing ownership of '{}': {}", path, error_string(&e))
+        };
+        eprintln!("{}: {}", cfg.util, msg);
+        return false;
+    }
+
+    true
+}
+
+/// Change the ownership of the operand `path`, which is dereferenced unless
+/// `-h` is given.
+pub fn chown_file(cfg: &ChownConfig, path: &Path) -> bool {
+    let filename = CString::new(path.as_os_str().as_bytes()).unwrap();
+
+    chown_at(
+        cfg,
+        libc::AT_FDCWD,
+        &filename,
+        &path.display().to_string(),
+        !cfg.no_dereference,
+    )
+}
+
+/// Change the ownership of `path` and, if it's a directory, of everything in
+/// it, following symlinks as `-H` and `-L` direct.
+pub fn chown_recursive(cfg: &ChownConfig, path: &Path) -> bool {
+    let success = Cell::new(true);
+
+    // The return value is not used because `traverse_directory` also returns
+    // `false` for operands that are not directories. Errors are tracked in
+    // `success` instead.
+    let _ = traverse_directory(
+        path,
+        |entry| {
+            let md = entry.metadata().unwrap();
+
+            // Symlinks that are not followed have the metadata of the symlink
+            // itself. Those are changed rather than the files they point to.
+            let follow = md.file_type() != ftw::FileType::SymbolicLink;
+
+            if !chown_at(
+                cfg,
+                entry.dir_fd(),
+                entry.file_name(),
+                &entry.path().clean_trailing_slashes(),
+                follow,
+            ) {
+                success.set(false);
+            }
+
+            // Descend into directories even if their ownership could not be
+            // changed
+            Ok(true)
+        },
+        |_| Ok(()),
+        |entry, error| {
+            success.set(false);
+
+            let err_str = match error.kind() {
+                ftw::ErrorKind::OpenDir | ftw::ErrorKind::DirNotSearchable => gettext!(
+                    "cannot access directory '{}': {}",
+                    entry.path().clean_trailing_slashes(),
+                    error_string(&error.inner())
+                ),
+                ftw::ErrorKind::ReadDir => gettext!(
+                    "cannot read directory '{}': {}",
+                    entry.path().clean_trailing_slashes(),
+                    error_string(&error.inner())
+                ),
+                ftw::ErrorKind::Open
+                | ftw::ErrorKind::Stat
+                | ftw::ErrorKind::ReadLink
+                | ftw::ErrorKind::Cycle => {
+                    gettext!(
+                        "cannot access '{}': {}",
+                        entry.path().clean_trailing_slashes(),
+                        error_string(&error.inner())
+                    )
+                }
+            };
+            eprintln!("{}: {}", cfg.util, err_str);
+            ftw::ErrorAction::Continue
+        },
+        cfg.follow_cli,
+        cfg.dereference,
+        false,
+    );
+
+    success.get()
+}
@@ -9,9 +9,12 @@
 
 mod common;
 
-use self::common::{copy_file, copy_files, error_string, CopyConfig};
+use self::common::{
+    copy_file, copy_files, error_string, CopyConfig, PreserveAttrs, Reflink, Sparse,
+};
 use clap::Parser;
 use gettextrs::{bind_textdomain_codeset, gettext, setlocale, textdomain, LocaleCategory};
+use plib::progress::{self, Progress};
 use plib::PROJECT_NAME;
 use std::collections::HashSet;
 use std::path::PathBuf;
@@ -21,7 +24,7 @@ use std::{fs, io};
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about)]
 struct Args {
-    /// Do not prompt for confirmation if the destination path exists
+    /// If a destination file cannot be opened, remove it and try again
     #[arg(short, long)]
     force: bool,
 
@@ -47,12 +50,11 @@ struct Args {
             "dereference",
             "no_dereference"
         ],
-        requires = "recursive",
-        default_value_t = true,
+        requires = "recursive"
     )]
     dereference: bool,
 
-    /// Never follow symlinks in source
+    /// Never follow symlinks in source (the default with -R)
     #[arg(
         short = 'P',
         long,
@@ -65,41 +67,114 @@ struct Args {
     no_dereference: bool,
 
     /// Prompt for confirmation if the destination path exists.
-    #[arg(short, long)]
+    #[arg(short, long, overrides_with = "no_clobber")]
     interactive: bool,
 
+    /// Do not overwrite an existing destination file
+    #[arg(short, long, overrides_with = "interactive")]
+    no_clobber: bool,
+
     /// Duplicate the characteristics of each source file in the corresponding destination file.
-    #[arg(short, long)]
+    #[arg(short = 'p')]
     preserve: bool,
 
+    /// Duplicate the characteristics in ATTR_LIST, a comma separated list of
+    /// mode, ownership, timestamps, xattr (extended attributes, including
+    /// ACLs) and all. Without ATTR_LIST, the same as -p.
+    #[arg(
+        long = "preserve",
+        value_name = "ATTR_LIST",
+        num_args = 0..=1,
+        require_equals = true,
+        value_delimiter = ',',
+        default_missing_values = ["mode", "ownership", "timestamps"]
+    )]
+    preserve_attrs: Vec<PreserveAttr>,
+
     /// Copy file hierarchies.
     #[arg(short = 'R', visible_short_alias = 'r', long)]
     recursive: bool,
 
+    /// Control creation of sparse files. WHEN is 'auto' (the default) to
+    /// keep the holes of sparse source files, 'always' to also turn blocks of
+    /// zero bytes into holes, or 'never' to write every byte.
+    #[arg(long, value_name = "WHEN", default_value = "auto")]
+    sparse: Sparse,
+
+    /// Clone the contents of files, sharing their data blocks, on file systems
+    /// that support it. WHEN is 'auto' (the default) to fall back to copying
+    /// the data, 'always' (when omitted) to fail instead, or 'never'.
+    #[arg(
+        long,
+        value_name = "WHEN",
+        num_args = 0..=1,
+        require_equals = true,
+        default_value = "auto",
+        default_missing_value = "always"
+    )]
+    reflink: Reflink,
+
+    /// Report the progress of the copy every second. A report is also written
+    /// on receipt of SIGUSR1 (or SIGINFO).
+    #[arg(long)]
+    progress: bool,
+
     /// Source(s) and target of move(s)
     files: Vec<PathBuf>,
 }
 
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+enum PreserveAttr {
+    Mode,
+    Ownership,
+    Timestamps,
+    Xattr,
+    All,
+}
+
 impl CopyConfig {
     fn new(args: &Args) -> Self {
         // `args.no_dereference` serves only to disable `args.dereference` or
-        // `follow_cli`
+        // `follow_cli`. Without -R, symlinks are followed unless -P is given.
+        let follow_by_default = !args.recursive && !args.no_dereference;
+
+        let mut preserve = if args.preserve {
+            PreserveAttrs::posix()
+        } else {
+            PreserveAttrs::default()
+        };
+        for attr in &args.preserve_attrs {
+            match attr {
+                PreserveAttr::Mode => preserve.mode = true,
+                PreserveAttr::Ownership => preserve.ownership = true,
+                PreserveAttr::Timestamps => preserve.timestamps = true,
+                PreserveAttr::Xattr => preserve.xattrs = true,
+                PreserveAttr::All => {
+                    preserve = PreserveAttrs {
+                        xattrs: true,
+                        ..PreserveAttrs::posix()
+                    }
+                }
+            }
+        }
+
         CopyConfig {
             force: args.force,
             follow_cli: args.follow_cli,
-            dereference: args.dereference,
+            dereference: args.dereference || follow_by_default,
             interactive: args.interactive,
-            preserve: args.preserve,
+            no_clobber: args.no_clobber,
+            preserve,
             recursive: args.recursive,
+            sparse: args.sparse,
+            reflink: args.reflink,
+            progress: Progress::new("cp", args.progress),
         }
     }
 }
 
 fn prompt_user(prompt: &str) -> bool {
-    eprint!("cp: {} ", prompt);
-    let mut response = String::new();
-    io::stdin().read_line(&mut response).unwrap();
-    response.to_lowercase().starts_with('y')
+    plib::prompt::prompt_user("cp", prompt)
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -137,12 +212,28 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     };
 
+    if !dir_exists && sources.len() > 1 {
+        let err_str = gettext!("target '{}' is not a directory", target.display());
+        eprintln!("cp: {}", err_str);
+        std::process::exit(1);
+    }
+
     let cfg = CopyConfig::new(&args);
-    if dir_exists {
-        match copy_files(&cfg, sources, target, None, prompt_user) {
-            Some(_) => Ok(()),
-            None => std::process::exit(1),
-        }
+    progress::install_signal_handler();
+
+    // The total is only known up front if no directories are copied
+    let total_bytes = sources.iter().try_fold(0, |total, source| {
+        fs::metadata(source)
+            .ok()
+            .filter(|md| md.is_file())
+            .map(|md| total + md.len())
+    });
+    if let Some(total_bytes) = total_bytes {
+        cfg.progress.set_total_bytes(total_bytes);
+    }
+
+    let success = if dir_exists {
+        copy_files(&cfg, sources, target, None, prompt_user).is_some()
     } else {
         let mut created_files = HashSet::new();
 
@@ -154,11 +245,17 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             None,
             prompt_user,
         ) {
-            Ok(_) => Ok(()),
+            Ok(_) => true,
             Err(e) => {
                 eprintln!("cp: {}", error_string(&e));
-                std::process::exit(1);
+                false
             }
         }
+    };
+    cfg.progress.finish();
+
+    if !success {
+        std::process::exit(1);
     }
+    Ok(())
 }
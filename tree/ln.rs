@@ -10,8 +10,10 @@
 use clap::Parser;
 use gettextrs::{bind_textdomain_codeset, gettext, setlocale, textdomain, LocaleCategory};
 use plib::PROJECT_NAME;
-use std::path::{Path, PathBuf};
-use std::{fs, io};
+use std::ffi::CString;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Component, Path, PathBuf};
+use std::{env, fs, io};
 
 /// ln - link files
 #[derive(Parser, Debug)]
@@ -25,16 +27,143 @@ struct Args {
     #[arg(short, long)]
     symlink: bool,
 
+    /// Make symbolic links relative to the directory containing the link.
+    #[arg(short, long, requires = "symlink")]
+    relative: bool,
+
+    /// For a source that is a symbolic link, create a hard link to the file it refers to.
+    #[arg(short = 'L', long, overrides_with = "physical")]
+    logical: bool,
+
+    /// For a source that is a symbolic link, create a hard link to the symbolic link itself
+    /// (default).
+    #[arg(short = 'P', long, overrides_with = "logical")]
+    physical: bool,
+
     /// Source(s) and target of link(s).
     files: Vec<String>,
 }
 
+// Resolve `.` and `..` components of an absolute path without consulting the filesystem
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            _ => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Compute the contents of a symbolic link at `link` pointing to `target`, as a path relative to
+/// the directory containing the link.
+///
+/// Symlinks in the directory of the link are resolved, because that is where the relative path
+/// will be looked up from. The target is only made absolute, so a link to a file through a
+/// symlinked directory stays that way.
+fn relative_target(target: &str, link: &Path) -> io::Result<PathBuf> {
+    let link_dir = match link.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let link_dir = fs::canonicalize(link_dir)?;
+    let target = normalize(&env::current_dir()?.join(target));
+
+    let mut link_components = link_dir.components().peekable();
+    let mut target_components = target.components().peekable();
+    while let (Some(a), Some(b)) = (link_components.peek(), target_components.peek()) {
+        if a != b {
+            break;
+        }
+        link_components.next();
+        target_components.next();
+    }
+
+    let mut relative = PathBuf::new();
+    for _ in link_components {
+        relative.push("..");
+    }
+    relative.extend(target_components);
+    if relative.as_os_str().is_empty() {
+        relative.push(".");
+    }
+    Ok(relative)
+}
+
+// Create a hard link with `linkat`, which unlike `link` specifies whether a symlink as `file1` is
+// followed
+fn hard_link(args: &Args, file1: &str, file2: &str) -> io::Result<()> {
+    let file1 = CString::new(file1)?;
+    let file2 = CString::new(file2)?;
+    let flags = if args.logical {
+        libc::AT_SYMLINK_FOLLOW
+    } else {
+        0
+    };
+
+    let ret = unsafe {
+        libc::linkat(
+            libc::AT_FDCWD,
+            file1.as_ptr(),
+            libc::AT_FDCWD,
+            file2.as_ptr(),
+            flags,
+        )
+    };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+// With -f, remove an existing destination so the link can take its place
+fn remove_destination(args: &Args, file1: &str, file2: &str) -> io::Result<()> {
+    let dest_md = match fs::symlink_metadata(file2) {
+        Ok(md) => md,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+
+    if dest_md.is_dir() {
+        return Err(io::Error::other(gettext("cannot overwrite directory")));
+    }
+
+    // Removing the destination of a hard link to itself would remove the file
+    if !args.symlink {
+        let source_md = if args.logical {
+            fs::metadata(file1)
+        } else {
+            fs::symlink_metadata(file1)
+        };
+        if let Ok(source_md) = source_md {
+            if source_md.dev() == dest_md.dev() && source_md.ino() == dest_md.ino() {
+                return Err(io::Error::other(gettext(
+                    "source and destination are the same file",
+                )));
+            }
+        }
+    }
+
+    fs::remove_file(file2)
+}
+
 #[allow(deprecated)] // for soft_link()
 fn do_link(args: &Args, file1: &str, file2: &str) -> io::Result<()> {
-    if args.symlink {
+    if args.force {
+        remove_destination(args, file1, file2)?;
+    }
+
+    if args.relative {
+        let target = relative_target(file1, Path::new(file2))?;
+        fs::soft_link(target, file2)
+    } else if args.symlink {
         fs::soft_link(file1, file2)
     } else {
-        fs::hard_link(file1, file2)
+        hard_link(args, file1, file2)
     }
 }
 
@@ -65,7 +194,8 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let mut exit_code = 0;
 
-    if sources.len() == 1 {
+    // A single source is linked into the target if it's an existing directory
+    if sources.len() == 1 && !Path::new(target).is_dir() {
         let src = &sources[0];
         if let Err(e) = do_link(&args, src, target) {
             exit_code = 1;
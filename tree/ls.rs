@@ -11,17 +11,20 @@ mod ls_util;
 
 use clap::{CommandFactory, FromArgMatches, Parser};
 use gettextrs::{bind_textdomain_codeset, gettext, setlocale, textdomain, LocaleCategory};
+use plib::quote::{quote, Quoting};
 use plib::PROJECT_NAME;
+use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::ffi::{CStr, CString, OsStr, OsString};
 use std::fs;
-use std::io;
+use std::io::{self, Write};
 use std::mem::MaybeUninit;
 use std::os::unix::ffi::OsStrExt;
-use std::path::PathBuf;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
 use std::process::ExitCode;
 
-use self::ls_util::{ls_from_utf8_lossy, Entry, LongFormatPadding, MultiColumnPadding};
+use self::ls_util::{Entry, LongFormatPadding, LsColors, MultiColumnPadding, TimeStyle};
 
 /// ls - list directory contents
 #[derive(Parser, Debug)]
@@ -124,7 +127,8 @@ struct Args {
 
     /// Set the block size for the -s option and the per-directory block count
     /// written for the -l, -n, -s, -g, and -o options to 1024
-    /// bytes.
+    /// bytes. Otherwise the block size is taken from the BLOCKSIZE environment
+    /// variable (such as 4096 or 1M), falling back to 512 bytes.
     #[arg(short = 'k', long)]
     kibibytes: bool,
 
@@ -185,9 +189,30 @@ struct Args {
     /// characters to be written as the <question-mark> ( '?' ) character.
     /// Implementations may provide this option by default if the output is to a
     /// terminal device.
-    #[arg(short = 'q', long)]
+    #[arg(
+        short = 'q',
+        long,
+        overrides_with_all = ["hide_control_chars", "escape", "literal"]
+    )]
     hide_control_chars: bool,
 
+    /// Write non-printable filename characters as C-style backslash escapes,
+    /// and bytes that are not valid UTF-8 as octal escapes.
+    #[arg(
+        short = 'b',
+        long,
+        overrides_with_all = ["hide_control_chars", "escape", "literal"]
+    )]
+    escape: bool,
+
+    /// Write filename characters as they are, even to a terminal device.
+    #[arg(
+        short = 'N',
+        long,
+        overrides_with_all = ["hide_control_chars", "escape", "literal"]
+    )]
+    literal: bool,
+
     /// Reverse the order of the sort to get reverse collating sequence oldest
     /// first, or smallest file size first depending on the other options given.
     #[arg(short = 'r', long = "reverse")]
@@ -259,6 +284,25 @@ struct Args {
     )]
     muti_column_across: bool,
 
+    /// Color file names by their type. WHEN is 'always' (the default when
+    /// omitted), 'auto' to only color when writing to a terminal, or 'never'.
+    /// The colors are taken from the LS_COLORS environment variable.
+    #[arg(
+        long,
+        value_name = "WHEN",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "always"
+    )]
+    color: Option<ColorWhen>,
+
+    /// Format timestamps in long format with STYLE: 'locale' (the default),
+    /// 'full-iso', 'long-iso', 'iso', or '+FORMAT' as in strftime(3). A FORMAT
+    /// with a newline formats recent files with the part before it and the
+    /// others with the part after it.
+    #[arg(long, value_name = "STYLE", value_parser = TimeStyle::parse)]
+    time_style: Option<TimeStyle>,
+
     /// (The numeric digit one.) Force output to be one entry per line. This
     /// option does not disable long format output. (Long format output is
     /// enabled by -g, -l (ell), -n, and -o; and disabled by -C, -m, and -x.)
@@ -279,8 +323,13 @@ struct Args {
     file: Vec<PathBuf>,
 }
 
-const DATE_TIME_FORMAT_RECENT: &str = "%b %d %H:%M";
-const DATE_TIME_FORMAT_OLD_OR_FUTURE: &str = "%b %d  %Y"; // Two spaces between %d and %Y
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+enum ColorWhen {
+    Always,
+    Auto,
+    Never,
+}
+
 const BLOCK_SIZE: u64 = 512;
 const BLOCK_SIZE_KIBIBYTES: u64 = 1024;
 const COLUMN_SPACING: usize = 2; // How many spaces in the column separator
@@ -338,13 +387,16 @@ struct Config {
     dereference_symbolic_link: DereferenceSymbolicLink,
     file_time_option: FileTimeOption,
     file_inclusion: FileInclusion,
+    directory: bool,
     inode: bool,
-    kibibytes: bool,
-    hide_control_chars: bool,
+    block_size: u64,
+    quoting: Quoting,
     reverse_sorting: bool,
     display_size: bool,
     recursive: bool,
     terminal_width: usize,
+    colors: Option<LsColors>,
+    time_style: TimeStyle,
 }
 
 impl Config {
@@ -459,7 +511,11 @@ impl Config {
                     // format is implementation-defined.
                     //
                     // coreutils uses -C by default.
-                    OutputFormat::OneEntryPerLine
+                    if atty::is(atty::Stream::Stdout) {
+                        OutputFormat::MultiColumn
+                    } else {
+                        OutputFormat::OneEntryPerLine
+                    }
                 }
             }
             (true, false, false, false) => OutputFormat::MultiColumn,
@@ -523,6 +579,41 @@ impl Config {
             }
         }
 
+        let color = match args.color.unwrap_or(ColorWhen::Never) {
+            ColorWhen::Always => true,
+            ColorWhen::Auto => atty::is(atty::Stream::Stdout),
+            ColorWhen::Never => false,
+        };
+        let colors = if color {
+            Some(LsColors::from_env())
+        } else {
+            None
+        };
+
+        let quoting = match (args.hide_control_chars, args.escape, args.literal) {
+            (false, false, false) => {
+                // -q may be the default when writing to a terminal
+                if atty::is(atty::Stream::Stdout) {
+                    Quoting::Hide
+                } else {
+                    Quoting::Literal
+                }
+            }
+            (true, false, false) => Quoting::Hide,
+            (false, true, false) => Quoting::Escape,
+            (false, false, true) => Quoting::Literal,
+            _ => unreachable!(), // -q, -b and -N are mutually exclusive
+        };
+
+        let block_size = if args.kibibytes {
+            BLOCK_SIZE_KIBIBYTES
+        } else {
+            std::env::var("BLOCKSIZE")
+                .ok()
+                .and_then(|s| parse_block_size(&s))
+                .unwrap_or(BLOCK_SIZE)
+        };
+
         let mut file = args.file;
         if file.is_empty() {
             file.push(PathBuf::from("."));
@@ -536,63 +627,71 @@ impl Config {
             file_time_option,
             file_inclusion,
 
+            directory: args.directory,
             inode: args.inode,
-            kibibytes: args.kibibytes,
-            hide_control_chars: args.hide_control_chars,
+            block_size,
+            quoting,
             reverse_sorting: args.reverse_sorting,
             display_size: args.display_size,
             recursive: args.recursive,
 
             terminal_width: get_terminal_width(),
+            colors,
+            time_style: args.time_style.unwrap_or_default(),
         };
 
         (config, file)
     }
 }
 
-fn get_terminal_width() -> usize {
-    // Constants taken from:
-    // https://docs.rs/term_size/0.3.2/src/term_size/platform/unix.rs.html#5-19
-    const fn winsize_request_code() -> std::ffi::c_ulong {
-        #[cfg(any(target_os = "linux", target_os = "android"))]
-        return 0x5413;
-
-        #[cfg(any(
-            target_os = "macos",
-            target_os = "ios",
-            target_os = "dragonfly",
-            target_os = "freebsd",
-            target_os = "netbsd",
-            target_os = "openbsd"
-        ))]
-        return 0x40087468;
-
-        #[cfg(target_os = "solaris")]
-        0x5468
-    }
+/// Parse a BLOCKSIZE value: a number of bytes with an optional K, M or G
+/// multiplier, or just the multiplier. Invalid values are ignored.
+fn parse_block_size(s: &str) -> Option<u64> {
+    let (digits, multiplier) = match s.char_indices().last()? {
+        (i, 'k' | 'K') => (&s[..i], 1 << 10),
+        (i, 'm' | 'M') => (&s[..i], 1 << 20),
+        (i, 'g' | 'G') => (&s[..i], 1 << 30),
+        _ => (s, 1),
+    };
+    let count: u64 = if digits.is_empty() {
+        1
+    } else {
+        digits.parse().ok()?
+    };
+    count.checked_mul(multiplier).filter(|size| *size > 0)
+}
+
+/// Convert a count of 512-byte blocks to units of `block_size` bytes, rounding
+/// up.
+fn scale_blocks(blocks: u64, block_size: u64) -> u64 {
+    (blocks * BLOCK_SIZE).div_ceil(block_size)
+}
 
+fn get_terminal_width() -> usize {
     // COLUMNS is usually automatically set and it even changes when the
-    // terminal window is resized.
+    // terminal window is resized. It also overrides the terminal width
+    // according to the specification.
     if let Ok(s) = std::env::var("COLUMNS") {
         if let Ok(num_columns) = s.parse() {
-            return num_columns;
+            if num_columns > 0 {
+                return num_columns;
+            }
         }
     }
 
     // Fallback to manually querying via `ioctl`.
     unsafe {
         let mut winsize: MaybeUninit<libc::winsize> = MaybeUninit::zeroed();
-        let ret = libc::ioctl(
-            libc::STDOUT_FILENO,
-            winsize_request_code(),
-            winsize.as_mut_ptr(),
-        );
+        let ret = libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, winsize.as_mut_ptr());
 
         // We're only interested in stdout here unlike `term_size::dimensions`
-        // so we won't query further if the first `ioctl` call fails.
+        // so we won't query further if the first `ioctl` call fails. Some
+        // pseudo-terminals report a width of zero.
         if ret == 0 {
             let winsize = winsize.assume_init();
-            return winsize.ws_col as usize;
+            if winsize.ws_col > 0 {
+                return winsize.ws_col as usize;
+            }
         }
     }
 
@@ -718,10 +817,10 @@ fn calc_optimal_padding(
 
 // Used by the -f option. This had to be done through `libc` because it needs to
 // output the entries `.` and `..` which `std::fs::read_dir` does not do.
-fn get_file_names_in_directory_order(directory: &str) -> io::Result<Vec<OsString>> {
+fn get_file_names_in_directory_order(directory: &Path) -> io::Result<Vec<OsString>> {
     unsafe {
         // Convert `directory` to a C-style string
-        let path = CString::new(directory)?;
+        let path = CString::new(directory.as_os_str().as_bytes())?;
 
         let dirp = libc::opendir(path.as_ptr());
         if dirp.is_null() {
@@ -778,7 +877,61 @@ fn get_file_names_in_directory_order(directory: &str) -> io::Result<Vec<OsString
     }
 }
 
-fn display_entries(entries: &mut [Entry], config: &Config, dir_path: Option<&str>) {
+// Ordering of entries for -S, -t and -r. The directory order of -f has to be
+// handled by the caller.
+fn compare_entries(a: &Entry, b: &Entry, config: &Config) -> Ordering {
+    let sort_fn = match &config.sort_by {
+        SortBy::Lexicographical => Entry::sorting_cmp_lexicographic,
+        SortBy::FileSize => Entry::sorting_cmp_size,
+        SortBy::Time => Entry::sorting_cmp_time,
+        SortBy::DirectoryOrder => unreachable!(),
+    };
+    if config.reverse_sorting {
+        sort_fn(a, b).reverse()
+    } else {
+        sort_fn(a, b)
+    }
+}
+
+// Sort the directory operands the same way as the entries in a directory.
+// With -f they are listed in the order given.
+fn sort_directory_operands(directories: &mut Vec<PathBuf>, config: &Config) {
+    if let SortBy::DirectoryOrder = &config.sort_by {
+        return;
+    }
+
+    let mut sortable = Vec::new();
+    let mut unsortable = Vec::new();
+    for path in directories.drain(..) {
+        let entry = fs::metadata(&path).and_then(|metadata| {
+            Entry::new(
+                &path,
+                path.as_os_str().to_os_string(),
+                &metadata,
+                config,
+                true,
+            )
+        });
+        match entry {
+            Ok(entry) => sortable.push((entry, path)),
+            // Reported when the directory is listed
+            Err(_) => unsortable.push(path),
+        }
+    }
+
+    sortable.sort_by(|(a, _), (b, _)| compare_entries(a, b, config));
+    directories.extend(sortable.into_iter().map(|(_, path)| path));
+    directories.extend(unsortable);
+}
+
+// Like `print!`, for file names that might not be valid UTF-8
+fn print_bytes(bytes: &[u8]) {
+    io::stdout()
+        .write_all(bytes)
+        .expect("failed printing to stdout");
+}
+
+fn display_entries(entries: &mut [Entry], config: &Config, dir_path: Option<&Path>) {
     match &config.sort_by {
         SortBy::DirectoryOrder => {
             if let Some(dir_path) = dir_path {
@@ -806,21 +959,7 @@ fn display_entries(entries: &mut [Entry], config: &Config, dir_path: Option<&str
                 }
             }
         }
-        other_sorting => {
-            entries.sort_by(|a, b| {
-                let sort_fn = match other_sorting {
-                    SortBy::Lexicographical => Entry::sorting_cmp_lexicographic,
-                    SortBy::FileSize => Entry::sorting_cmp_size,
-                    SortBy::Time => Entry::sorting_cmp_time,
-                    SortBy::DirectoryOrder => unreachable!(), // Already handled
-                };
-                if config.reverse_sorting {
-                    sort_fn(a, b).reverse()
-                } else {
-                    sort_fn(a, b)
-                }
-            });
-        }
+        _ => entries.sort_by(|a, b| compare_entries(a, b, config)),
     }
 
     let mut display_total_size = config.display_size;
@@ -830,21 +969,13 @@ fn display_entries(entries: &mut [Entry], config: &Config, dir_path: Option<&str
 
     // `dir_path.is_some()` to only display the total on directories.
     if display_total_size && dir_path.is_some() {
-        let mut total_block_size = 0;
-        for entry in entries.iter() {
-            total_block_size += BLOCK_SIZE * entry.blocks();
-        }
+        let total_blocks: u64 = entries.iter().map(|entry| entry.blocks()).sum();
 
         // The specification seems contradictory here. On the -s flag
         // it says it is implementation-defined. But on the STDOUT
         // section, it mandates it to be 512 when -k is not specified
-        // and 1024 when it is.
-        // coreutils seems to always have it as 1024 with or without -k.
-        if config.kibibytes {
-            total_block_size /= BLOCK_SIZE_KIBIBYTES;
-        } else {
-            total_block_size /= BLOCK_SIZE;
-        }
+        // and 1024 when it is. The same block size is used for both.
+        let total_block_size = scale_blocks(total_blocks, config.block_size);
         println!("{} {}", gettext("total"), total_block_size);
     }
 
@@ -938,9 +1069,13 @@ fn display_entries(entries: &mut [Entry], config: &Config, dir_path: Option<&str
         OutputFormat::StreamOutputFormat => {
             let stream_outputs: Vec<_> = entries
                 .iter()
-                .map(|entry| entry.build_stream_mode_string())
+                .map(|entry| entry.build_stream_mode_output())
+                .collect();
+            // The escape sequences of --color have no width
+            let char_counts: Vec<_> = entries
+                .iter()
+                .map(|entry| entry.get_multi_column_padding().total_width)
                 .collect();
-            let char_counts: Vec<_> = stream_outputs.iter().map(|s| s.chars().count()).collect();
             let mut start = 0;
 
             'outer: loop {
@@ -961,24 +1096,29 @@ fn display_entries(entries: &mut [Entry], config: &Config, dir_path: Option<&str
                             assert_ne!(start, i);
 
                             for output in &stream_outputs[start..i] {
-                                print!("{}, ", output);
+                                print_bytes(output);
+                                print!(", ");
                             }
-                            println!("{},", &stream_outputs[i]);
+                            print_bytes(&stream_outputs[i]);
+                            println!(",");
 
                             start = i + 1;
                         } else {
                             // Long file name that exceeds
                             // `terminal_width` by itself
                             if start == i {
-                                println!("{}", &stream_outputs[i]);
+                                print_bytes(&stream_outputs[i]);
+                                println!();
                                 start = i + 1;
 
                             // `start..i` fits in `terminal_width`
                             } else {
                                 for output in &stream_outputs[start..(i - 1)] {
-                                    print!("{}, ", output);
+                                    print_bytes(output);
+                                    print!(", ");
                                 }
-                                println!("{},", &stream_outputs[i - 1]);
+                                print_bytes(&stream_outputs[i - 1]);
+                                println!(",");
 
                                 start = i;
                             }
@@ -988,10 +1128,12 @@ fn display_entries(entries: &mut [Entry], config: &Config, dir_path: Option<&str
                     }
                 }
                 for output in &stream_outputs[start..(stream_outputs.len() - 1)] {
-                    print!("{}, ", output);
+                    print_bytes(output);
+                    print!(", ");
                 }
                 // No comma on the very last file name
-                println!("{}", &stream_outputs[stream_outputs.len() - 1]);
+                print_bytes(&stream_outputs[stream_outputs.len() - 1]);
+                println!();
 
                 break;
             }
@@ -1016,15 +1158,34 @@ fn ls(paths: Vec<PathBuf>, config: &Config) -> io::Result<u8> {
     let mut directories = Vec::new();
     let mut files = Vec::new();
 
-    // Categorize into directories/files
+    // Symbolic links named as operands are not followed with -d, -F or -l
+    // unless -H or -L is given
+    let follow_operands = match config.dereference_symbolic_link {
+        DereferenceSymbolicLink::CommandLine | DereferenceSymbolicLink::All => true,
+        DereferenceSymbolicLink::None => {
+            !(config.directory
+                || matches!(config.classify_files, ClassifyFiles::Complete)
+                || matches!(config.output_format, OutputFormat::LongFormat(_)))
+        }
+    };
+
+    // Categorize into directories/files. With -d, directories are listed
+    // like any other file.
     for path in paths {
-        if path.is_dir() {
+        let is_dir = if follow_operands {
+            path.is_dir()
+        } else {
+            fs::symlink_metadata(&path).is_ok_and(|m| m.is_dir())
+        };
+        if is_dir && !config.directory {
             directories.push(path);
         } else {
             files.push(path);
         }
     }
 
+    sort_directory_operands(&mut directories, config);
+
     let num_directory_args = directories.len();
     let num_file_args = files.len();
     let num_args = num_file_args + num_directory_args;
@@ -1062,27 +1223,73 @@ fn ls(paths: Vec<PathBuf>, config: &Config) -> io::Result<u8> {
 
     let mut is_first_dir_arg = true;
     for path in directories.into_iter() {
-        // Stack for depth-first directory traversal
-        let mut subdirectories = vec![path];
-
-        // Stores visited paths to prevent infinite loops due to symbolic links
-        // Map of canonical path -> path
-        let mut visited: HashMap<PathBuf, PathBuf> = HashMap::new();
-
-        while let Some(dir) = subdirectories.pop() {
-            let canonical_dir_path = fs::canonicalize(&dir)?;
-            if let Some(noncanonical_dir_path) = visited.get(&canonical_dir_path) {
+        // Stack for depth-first directory traversal, with the depth of each
+        // directory
+        let mut subdirectories = vec![(path, 0)];
+
+        // The device and inode numbers of the directories from the operand
+        // down to the one being listed. Entering one of them again, through
+        // a symbolic link or a bind mount, would loop forever.
+        let mut ancestors: Vec<(u64, u64)> = Vec::new();
+
+        while let Some((dir, depth)) = subdirectories.pop() {
+            let dir_path = quote(dir.as_os_str().as_bytes(), config.quoting);
+            let dir_name = String::from_utf8_lossy(&dir_path);
+
+            let metadata = match fs::metadata(&dir) {
+                Ok(m) => m,
+                Err(e) => {
+                    eprintln!("ls: {} '{dir_name}': {e}", gettext("cannot access"));
+                    exit_code = exit_code.max(1);
+                    continue;
+                }
+            };
+            // The directories as deep as this one or deeper were left
+            ancestors.truncate(depth);
+            let id = (metadata.dev(), metadata.ino());
+
+            // The directory being entered is reported, not the one it was
+            // first listed as
+            if ancestors.contains(&id) {
                 eprintln!(
-                    "ls: {}: {}",
-                    ls_from_utf8_lossy(noncanonical_dir_path.as_os_str().as_bytes()),
+                    "ls: {dir_name}: {}",
                     gettext("not listing already-listed directory")
                 );
                 // This is the only error that has exit code 2 for now.
                 exit_code = exit_code.max(2);
                 continue;
             }
+            ancestors.push(id);
+
+            // If more than one directory, or a combination of non-directory
+            // files and directories are written, either as a result of
+            // specifying multiple operands, or the -R option
+            let display_directory_header = num_args > 1 || config.recursive;
+
+            if display_directory_header {
+                if is_first_dir_arg && num_file_args == 0 {
+                    // Trimming the newline on the first directory isn't
+                    // strictly required by the specification
+                    print_bytes(&dir_path);
+                    println!(":");
+                    is_first_dir_arg = false;
+                } else {
+                    println!();
+                    print_bytes(&dir_path);
+                    println!(":");
+                }
+            }
 
-            visited.insert(canonical_dir_path, dir.clone());
+            // An unreadable directory is reported and skipped, the rest of the
+            // hierarchy is still listed
+            let read_dir = match fs::read_dir(&dir) {
+                Ok(read_dir) => read_dir,
+                Err(e) => {
+                    eprintln!("ls: {} '{dir_name}': {e}", gettext("cannot open directory"));
+                    exit_code = exit_code.max(1);
+                    continue;
+                }
+            };
 
             let mut entries = Vec::new();
             let mut errors = Vec::new();
@@ -1090,13 +1297,14 @@ fn ls(paths: Vec<PathBuf>, config: &Config) -> io::Result<u8> {
             // For sorting the subdirectories on recursion
             let mut new_subdirectories = Vec::new();
 
-            for dir_entry in fs::read_dir(&dir)? {
+            for dir_entry in read_dir {
                 // Helper closure to easily catch the `io::Error` for printing
                 let process_dir_entry = || -> io::Result<()> {
                     let dir_entry = dir_entry?;
 
-                    let mut path = dir_entry.path();
-                    let path_str = ls_from_utf8_lossy(path.as_os_str().as_bytes());
+                    let path = dir_entry.path();
+                    let path_str = quote(path.as_os_str().as_bytes(), config.quoting);
+                    let path_str = String::from_utf8_lossy(&path_str);
 
                     let mut metadata = dir_entry.metadata().map_err(|e| {
                         io::Error::other(format!("{} '{path_str}': {e}", gettext("cannot access")))
@@ -1139,10 +1347,12 @@ fn ls(paths: Vec<PathBuf>, config: &Config) -> io::Result<u8> {
                                 // encountered, the directory shall not be
                                 // recursively listed unless the -L option is
                                 // specified
+                                //
+                                // The directory is listed under the name of
+                                // the link.
                                 if let DereferenceSymbolicLink::All =
                                     config.dereference_symbolic_link
                                 {
-                                    path = fs::read_link(&path)?;
                                     metadata = path.metadata()?;
                                 }
                             }
@@ -1163,7 +1373,7 @@ fn ls(paths: Vec<PathBuf>, config: &Config) -> io::Result<u8> {
 
             new_subdirectories.sort();
             while let Some(subdir) = new_subdirectories.pop() {
-                subdirectories.push(subdir);
+                subdirectories.push((subdir, depth + 1));
             }
 
             // `.` and `..` are excluded from `fs::read_dir` so it's guaranteed
@@ -1188,30 +1398,13 @@ fn ls(paths: Vec<PathBuf>, config: &Config) -> io::Result<u8> {
                 }
             }
 
-            // If more than one directory, or a combination of non-directory
-            // files and directories are written, either as a result of
-            // specifying multiple operands, or the -R option
-            let display_directory_header = num_args > 1 || config.recursive;
-
-            let dir_path = ls_from_utf8_lossy(dir.as_os_str().as_bytes());
-            if display_directory_header {
-                if is_first_dir_arg && num_file_args == 0 {
-                    // Trimming the newline on the first directory isn't
-                    // strictly required by the specification
-                    println!("{}:", dir_path);
-                    is_first_dir_arg = false;
-                } else {
-                    println!("\n{}:", dir_path);
-                }
-            }
-
             for e in errors {
                 eprintln!("ls: {e}");
                 exit_code = exit_code.max(1);
             }
 
             if !entries.is_empty() {
-                display_entries(&mut entries, config, Some(&dir_path));
+                display_entries(&mut entries, config, Some(&dir));
             }
         }
     }
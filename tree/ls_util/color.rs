@@ -0,0 +1,200 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+//! File name coloring for `--color`, configured through the `LS_COLORS`
+//! environment variable.
+//!
+//! `LS_COLORS` is a `:` separated list of `key=value` pairs where the value is
+//! an SGR sequence such as `01;34`. The key is either a two letter file type
+//! indicator (`di`, `ln`, `ex`, ...) or a `*suffix` glob matched against the
+//! end of regular file names.
+
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::path::Path;
+
+// Used when `LS_COLORS` is not set, and for keys it doesn't mention
+const DEFAULT_COLORS: &[(&str, &str)] = &[
+    ("di", "01;34"),
+    ("ln", "01;36"),
+    ("pi", "33"),
+    ("so", "01;35"),
+    ("bd", "01;33"),
+    ("cd", "01;33"),
+    ("ex", "01;32"),
+    ("su", "37;41"),
+    ("sg", "30;43"),
+    ("st", "37;44"),
+    ("ow", "34;42"),
+    ("tw", "30;42"),
+];
+
+pub struct LsColors {
+    // Two letter indicator -> SGR sequence
+    indicators: HashMap<String, String>,
+
+    // Suffix -> SGR sequence, in the order they appear in `LS_COLORS`
+    suffixes: Vec<(Vec<u8>, String)>,
+
+    // `ln=target` colors symbolic links as the file they point to
+    link_as_target: bool,
+}
+
+impl LsColors {
+    /// Build the color table from the `LS_COLORS` environment variable.
+    pub fn from_env() -> Self {
+        Self::parse(&std::env::var("LS_COLORS").unwrap_or_default())
+    }
+
+    /// Build the color table from an `LS_COLORS` string. Malformed entries
+    /// are ignored.
+    pub fn parse(ls_colors: &str) -> Self {
+        let mut colors = Self {
+            indicators: DEFAULT_COLORS
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            suffixes: Vec::new(),
+            link_as_target: false,
+        };
+
+        for item in ls_colors.split(':') {
+            let Some((key, value)) = item.split_once('=') else {
+                continue;
+            };
+
+            if let Some(suffix) = key.strip_prefix('*') {
+                colors
+                    .suffixes
+                    .push((suffix.as_bytes().to_vec(), value.to_string()));
+            } else if key == "ln" && value == "target" {
+                colors.link_as_target = true;
+            } else if key.len() == 2 {
+                colors.indicators.insert(key.to_string(), value.to_string());
+            }
+        }
+
+        colors
+    }
+
+    fn indicator(&self, key: &str) -> Option<&str> {
+        self.indicators
+            .get(key)
+            .map(|s| s.as_str())
+            .filter(|s| !is_reset(s))
+    }
+
+    /// Return the SGR sequence for the file `file_name` at `path` having
+    /// `metadata`, or `None` if it should not be colored.
+    ///
+    /// `metadata` is not dereferenced for symbolic links. `path` is used to
+    /// find out whether a symbolic link is dangling.
+    pub fn color_for(
+        &self,
+        path: &Path,
+        file_name: &OsStr,
+        metadata: &fs::Metadata,
+    ) -> Option<&str> {
+        let file_type = metadata.file_type();
+        let mode = metadata.mode();
+
+        if file_type.is_symlink() {
+            return match fs::metadata(path) {
+                Ok(target_metadata) => {
+                    if self.link_as_target {
+                        self.color_for(path, file_name, &target_metadata)
+                    } else {
+                        self.indicator("ln")
+                    }
+                }
+                Err(_) => self.indicator("or").or_else(|| self.indicator("ln")),
+            };
+        }
+
+        if file_type.is_dir() {
+            let sticky = mode & libc::S_ISVTX as u32 != 0;
+            let other_writable = mode & libc::S_IWOTH as u32 != 0;
+            let key = match (sticky, other_writable) {
+                (true, true) => "tw",
+                (false, true) => "ow",
+                (true, false) => "st",
+                (false, false) => "di",
+            };
+            return self.indicator(key).or_else(|| self.indicator("di"));
+        }
+
+        if file_type.is_fifo() {
+            return self.indicator("pi");
+        }
+        if file_type.is_socket() {
+            return self.indicator("so");
+        }
+        if file_type.is_block_device() {
+            return self.indicator("bd");
+        }
+        if file_type.is_char_device() {
+            return self.indicator("cd");
+        }
+
+        // Regular files
+        if mode & libc::S_ISUID as u32 != 0 {
+            if let Some(color) = self.indicator("su") {
+                return Some(color);
+            }
+        }
+        if mode & libc::S_ISGID as u32 != 0 {
+            if let Some(color) = self.indicator("sg") {
+                return Some(color);
+            }
+        }
+        if mode & (libc::S_IXUSR | libc::S_IXGRP | libc::S_IXOTH) as u32 != 0 {
+            if let Some(color) = self.indicator("ex") {
+                return Some(color);
+            }
+        }
+
+        let name = file_name.as_bytes();
+        self.suffixes
+            .iter()
+            .find(|(suffix, _)| name.ends_with(suffix))
+            .map(|(_, color)| color.as_str())
+            .filter(|s| !is_reset(s))
+            .or_else(|| self.indicator("fi"))
+    }
+
+    /// Return the SGR sequence for the target `target` of the symbolic link at
+    /// `path` in long format.
+    pub fn target_color_for(&self, path: &Path, target: &OsStr) -> Option<&str> {
+        match fs::metadata(path) {
+            Ok(metadata) => self.color_for(path, target, &metadata),
+            Err(_) => self.indicator("mi"),
+        }
+    }
+}
+
+// An empty sequence (or one that only resets) means "no color"
+fn is_reset(sgr: &str) -> bool {
+    sgr.bytes().all(|b| b == b'0')
+}
+
+/// Append `s` to `out`, wrapped in the escape sequences for the SGR sequence
+/// `color`.
+pub fn paint(out: &mut Vec<u8>, s: &[u8], color: Option<&str>) {
+    match color {
+        Some(color) => {
+            out.extend_from_slice(format!("\x1b[{}m", color).as_bytes());
+            out.extend_from_slice(s);
+            out.extend_from_slice(b"\x1b[0m");
+        }
+        None => out.extend_from_slice(s),
+    }
+}
@@ -7,20 +7,23 @@
 // SPDX-License-Identifier: MIT
 //
 
-use super::ls_from_utf8_lossy;
+use super::color::paint;
+use super::time::TimeStyle;
 use crate::{
-    ClassifyFiles, Config, DereferenceSymbolicLink, FileTimeOption, LongFormatOptions,
-    OutputFormat, DATE_TIME_FORMAT_OLD_OR_FUTURE, DATE_TIME_FORMAT_RECENT,
+    print_bytes, scale_blocks, ClassifyFiles, Config, DereferenceSymbolicLink, FileTimeOption,
+    LongFormatOptions, OutputFormat,
 };
-use chrono::{DateTime, Local};
+use plib::quote::quote;
+use plib::{modestr, owner};
 use std::cmp::Ordering;
-use std::ffi::{CStr, OsStr, OsString};
+use std::ffi::{OsStr, OsString};
 use std::fs;
 use std::io;
 use std::os::unix::ffi::OsStrExt;
 use std::os::unix::fs::{FileTypeExt, MetadataExt};
 use std::path::Path;
 use std::time::{Duration, SystemTime};
+use unicode_width::UnicodeWidthStr;
 
 enum FileInfo {
     Size(u64),
@@ -34,13 +37,17 @@ pub struct Entry {
     time: SystemTime,
     time_string: String,
 
-    file_name_raw: OsString,   // Actual file name, might not be valid UTF-8
-    file_name_display: String, // File name to be displayed
+    file_name_raw: OsString,    // Actual file name, might not be valid UTF-8
+    file_name_display: Vec<u8>, // File name to be displayed
 
     blocks_str: Option<String>,
     inode_str: Option<String>,
     suffix: Option<char>,
-    target_path: Option<String>,
+    target_path: Option<Vec<u8>>,
+
+    // SGR sequences for --color
+    file_name_color: Option<String>,
+    target_path_color: Option<String>,
 
     multi_column_padding: MultiColumnPadding,
 
@@ -78,25 +85,35 @@ impl Entry {
         };
 
         let mut target_path = None;
+        let mut target_path_color = None;
         if metadata.is_symlink() && !dereference_symlink {
             if let OutputFormat::LongFormat(_) = &config.output_format {
                 let target = fs::read_link(path)?;
                 let os_str = target.as_os_str();
-                target_path = Some(ls_from_utf8_lossy(os_str.as_bytes()));
+                target_path = Some(quote(os_str.as_bytes(), config.quoting));
+
+                if let Some(colors) = &config.colors {
+                    target_path_color =
+                        colors.target_color_for(path, os_str).map(|s| s.to_string());
+                }
             }
         }
 
+        let file_name_color = config.colors.as_ref().and_then(|colors| {
+            colors
+                .color_for(path, &file_name_raw, metadata)
+                .map(|s| s.to_string())
+        });
+
         let file_info = get_file_info(metadata);
 
         // This `SystemTime` *is* affected by -c or -u
-        let (time, time_string) = get_time_and_time_string(metadata, &config.file_time_option)?;
+        let (time, time_string) =
+            get_time_and_time_string(metadata, &config.file_time_option, &config.time_style)?;
 
         let blocks = metadata.blocks();
         let blocks_str = if config.display_size {
-            // The 2 is because `crate::BLOCK_SIZE_KIBIBYTES` is double that of
-            // `crate::BLOCK_SIZE`
-            let b = if config.kibibytes { blocks / 2 } else { blocks };
-            Some(format!("{}", b))
+            Some(scale_blocks(blocks, config.block_size).to_string())
         } else {
             None
         };
@@ -159,20 +176,12 @@ impl Entry {
         //         .map(|s| s.to_os_string())
         //         .unwrap_or(OsString::from(".."))
         // };
-        let file_name_display = {
-            let tmp = ls_from_utf8_lossy(file_name_raw.as_bytes());
-
-            // -q
-            if config.hide_control_chars {
-                tmp.chars()
-                    .map(|c| if c.is_control() || c == '\t' { '?' } else { c })
-                    .collect()
-            } else {
-                tmp
-            }
-        };
+        // -q, -b or neither
+        let file_name_display = quote(file_name_raw.as_bytes(), config.quoting);
 
-        let mut file_name_width = file_name_display.chars().count();
+        // Columns taken up on the terminal, e.g. CJK characters are two
+        // columns wide and combining characters take up none
+        let mut file_name_width = String::from_utf8_lossy(&file_name_display).width();
         if suffix.is_some() {
             file_name_width += 1;
         }
@@ -213,6 +222,8 @@ impl Entry {
             inode_str,
             suffix,
             target_path,
+            file_name_color,
+            target_path_color,
             multi_column_padding,
             terminal_width: config.terminal_width,
             long_format_data,
@@ -228,10 +239,6 @@ impl Entry {
         &self.file_name_raw
     }
 
-    pub fn file_name_str(&self) -> &str {
-        &self.file_name_display
-    }
-
     /// Sets the width of the inode and blocks to be equal to of the `padding`.
     ///
     /// This is for coreutils compatibility. coreutils sets the column widths
@@ -250,29 +257,40 @@ impl Entry {
         self.multi_column_padding.total_width += delta;
     }
 
-    /// Return the `[inode] [blocks] filename` string.
-    pub fn build_stream_mode_string(&self) -> String {
-        let mut output = String::new();
+    /// Return the `[inode] [blocks] filename` output.
+    pub fn build_stream_mode_output(&self) -> Vec<u8> {
+        let mut output = Vec::new();
 
         if let Some(inode_str) = &self.inode_str {
-            output.push_str(inode_str);
-            output.push(' ');
+            output.extend_from_slice(inode_str.as_bytes());
+            output.push(b' ');
         }
 
         if let Some(blocks_str) = &self.blocks_str {
-            output.push_str(blocks_str);
-            output.push(' ');
+            output.extend_from_slice(blocks_str.as_bytes());
+            output.push(b' ');
         }
 
-        output.push_str(self.file_name_str());
-
-        if let Some(suffix) = &self.suffix {
-            output.push(*suffix);
-        }
+        self.push_file_name(&mut output);
 
         output
     }
 
+    // The file name wrapped in the escape sequences for --color, if enabled,
+    // and its -F or -p suffix
+    fn push_file_name(&self, output: &mut Vec<u8>) {
+        paint(
+            output,
+            &self.file_name_display,
+            self.file_name_color.as_deref(),
+        );
+
+        if let Some(suffix) = self.suffix {
+            let mut buf = [0u8; 4];
+            output.extend_from_slice(suffix.encode_utf8(&mut buf).as_bytes());
+        }
+    }
+
     /// Print a single grid cell in multi-column format.
     pub fn print_multi_column(&self, padding: &MultiColumnPadding) {
         let MultiColumnPadding {
@@ -294,11 +312,6 @@ impl Entry {
             String::from("")
         };
 
-        let mut file_name = self.file_name_str().to_string();
-        if let Some(suffix) = &self.suffix {
-            file_name.push(*suffix);
-        }
-
         let mut file_name_width = *file_name_width;
 
         // This implies that this will be printed in a single column. Don't
@@ -308,7 +321,13 @@ impl Entry {
             file_name_width = 0;
         }
 
-        print!("{}{}{:<file_name_width$}", inode_str, blocks_str, file_name,);
+        // Padded by hand as the escape sequences of --color have no width
+        let padding = file_name_width.saturating_sub(self.multi_column_padding.file_name_width);
+
+        let mut output = format!("{}{}", inode_str, blocks_str).into_bytes();
+        self.push_file_name(&mut output);
+        output.resize(output.len() + padding, b' ');
+        print_bytes(&output);
     }
 
     /// Print one row in long format (-l).
@@ -364,15 +383,6 @@ impl Entry {
             }
         };
 
-        let mut file_name = self.file_name_str().to_string();
-        if let Some(suffix) = &self.suffix {
-            file_name.push(*suffix);
-        }
-        if let Some(target_path) = &self.target_path {
-            file_name.push_str(" -> ");
-            file_name.push_str(target_path);
-        }
-
         // Alignment and padding is not mentioned in the specification however
         // the example given there does have alignment and padding.
         //
@@ -381,8 +391,8 @@ impl Entry {
         //
         // As for the alignment, <number of links>, <size> or <device info>,
         // <date and time> are right-aligned and the rest are left-aligned.
-        println!(
-            "{}{}{} {:>num_links_width$}{}{} {:>file_size_width$} {:>time_width$} {}",
+        let mut output = format!(
+            "{}{}{} {:>num_links_width$}{}{} {:>file_size_width$} {:>time_width$} ",
             inode_str,
             blocks_str,
             long_format_data.file_mode,
@@ -391,8 +401,16 @@ impl Entry {
             group_name,
             file_info,
             self.time_string,
-            file_name
-        );
+        )
+        .into_bytes();
+
+        self.push_file_name(&mut output);
+        if let Some(target_path) = &self.target_path {
+            output.extend_from_slice(b" -> ");
+            paint(&mut output, target_path, self.target_path_color.as_deref());
+        }
+        output.push(b'\n');
+        print_bytes(&output);
     }
 
     /// Comparison key for sorting based on just the file name.
@@ -620,7 +638,7 @@ impl LongFormatData {
             Some(get_owner_name(
                 metadata,
                 long_format_options.numeric_uid_gid,
-            )?)
+            ))
         };
 
         let group_name = if long_format_options.without_group {
@@ -629,7 +647,7 @@ impl LongFormatData {
             Some(get_group_name(
                 metadata,
                 long_format_options.numeric_uid_gid,
-            )?)
+            ))
         };
 
         Ok(Self {
@@ -657,115 +675,35 @@ fn get_file_mode_string(metadata: &fs::Metadata) -> String {
         'l'
     } else if file_type.is_fifo() {
         'p'
+    } else if file_type.is_socket() {
+        's'
     } else {
         '-'
     });
 
-    let mode = metadata.mode();
-
-    // Owner permissions
-    file_mode.push(if mode & (libc::S_IRUSR as u32) != 0 {
-        'r'
-    } else {
-        '-'
-    });
-    file_mode.push(if mode & (libc::S_IWUSR as u32) != 0 {
-        'w'
-    } else {
-        '-'
-    });
-    file_mode.push({
-        let executable = mode & (libc::S_IXUSR as u32) != 0;
-        let set_user_id = mode & (libc::S_ISUID as u32) != 0;
-        match (executable, set_user_id) {
-            (true, true) => 's',
-            (true, false) => 'x',
-            (false, true) => 'S',
-            (false, false) => '-',
-        }
-    });
-
-    // Group permissions
-    file_mode.push(if mode & (libc::S_IRGRP as u32) != 0 {
-        'r'
-    } else {
-        '-'
-    });
-    file_mode.push(if mode & (libc::S_IWGRP as u32) != 0 {
-        'w'
-    } else {
-        '-'
-    });
-    file_mode.push(if mode & (libc::S_IXGRP as u32) != 0 {
-        'x'
-    } else {
-        '-'
-    });
-
-    // Other permissions
-    file_mode.push(if mode & (libc::S_IROTH as u32) != 0 {
-        'r'
-    } else {
-        '-'
-    });
-    file_mode.push(if mode & (libc::S_IWOTH as u32) != 0 {
-        'w'
-    } else {
-        '-'
-    });
-    file_mode.push({
-        if file_type.is_dir() {
-            let searchable = mode & (libc::S_IXOTH as u32) != 0;
-            let restricted_deletion = mode & (libc::S_ISVTX as u32) != 0;
-            match (searchable, restricted_deletion) {
-                (true, true) => 't',
-                (true, false) => 'x',
-                (false, true) => 'T',
-                (false, false) => '-',
-            }
-        } else {
-            if mode & (libc::S_IXOTH as u32) != 0 {
-                'x'
-            } else {
-                '-'
-            }
-        }
-    });
+    // Permissions
+    file_mode.push_str(&modestr::mode_to_string(metadata.mode()));
 
     file_mode
 }
 
-fn get_owner_name(metadata: &fs::Metadata, numeric: bool) -> io::Result<String> {
+// The numeric user ID is printed if the user name cannot be determined
+fn get_owner_name(metadata: &fs::Metadata, numeric: bool) -> String {
     let uid = metadata.uid();
     if numeric {
-        Ok(uid.to_string())
+        uid.to_string()
     } else {
-        unsafe {
-            let passwd = libc::getpwuid(uid);
-            if passwd.is_null() {
-                return Err(io::Error::last_os_error());
-            }
-            let passwd_ref = &*passwd;
-            let name = CStr::from_ptr(passwd_ref.pw_name);
-            Ok(ls_from_utf8_lossy(name.to_bytes()))
-        }
+        owner::user_name(uid).unwrap_or_else(|| uid.to_string())
     }
 }
 
-fn get_group_name(metadata: &fs::Metadata, numeric: bool) -> io::Result<String> {
+// The numeric group ID is printed if the group name cannot be determined
+fn get_group_name(metadata: &fs::Metadata, numeric: bool) -> String {
     let gid = metadata.gid();
     if numeric {
-        Ok(gid.to_string())
+        gid.to_string()
     } else {
-        unsafe {
-            let group = libc::getgrgid(gid);
-            if group.is_null() {
-                return Err(io::Error::last_os_error());
-            }
-            let group_ref = &*group;
-            let name = CStr::from_ptr(group_ref.gr_name);
-            Ok(ls_from_utf8_lossy(name.to_bytes()))
-        }
+        owner::group_name(gid).unwrap_or_else(|| gid.to_string())
     }
 }
 
@@ -799,8 +737,12 @@ fn get_system_time(
         FileTimeOption::LastStatusChangeTime => {
             let seconds_since_epoch =
                 u64::try_from(metadata.ctime()).map_err(|_| io::Error::other("negative ctime"))?;
+
+            // Nanoseconds are needed to order files changed within the same
+            // second
+            let nanoseconds = metadata.ctime_nsec() as u32;
             SystemTime::UNIX_EPOCH
-                .checked_add(Duration::from_secs(seconds_since_epoch))
+                .checked_add(Duration::new(seconds_since_epoch, nanoseconds))
                 .ok_or(io::Error::other("`SystemTime` overflow"))?
         }
     };
@@ -810,32 +752,12 @@ fn get_system_time(
 fn get_time_and_time_string(
     metadata: &fs::Metadata,
     file_time_option: &FileTimeOption,
+    time_style: &TimeStyle,
 ) -> io::Result<(SystemTime, String)> {
     let time = get_system_time(metadata, file_time_option)?;
 
-    let dt_format = {
-        // The specification says to base it of "last modification time"
-        // without any mention of -c or -u
-        let last_modified_time = get_system_time(metadata, &FileTimeOption::LastModificationTime)?;
-        let now = SystemTime::now();
-
-        match now.duration_since(last_modified_time) {
-            Ok(duration) => {
-                const SIX_MONTHS: Duration = Duration::from_secs(3600 * 24 * 30 * 6);
-                if duration > SIX_MONTHS {
-                    DATE_TIME_FORMAT_OLD_OR_FUTURE // Old
-                } else {
-                    DATE_TIME_FORMAT_RECENT
-                }
-            }
-            Err(_) => DATE_TIME_FORMAT_OLD_OR_FUTURE, // Future
-        }
-    };
-
-    let time_string = {
-        // chrono parses the TZ environment variable in this conversion
-        let dt: DateTime<Local> = time.into();
-        dt.format(dt_format).to_string()
-    };
+    // Whether the year is shown depends on the same timestamp that is
+    // written, so -c and -u also apply to the six months rule
+    let time_string = time_style.format(time, SystemTime::now());
     Ok((time, time_string))
 }
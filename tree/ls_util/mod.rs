@@ -7,8 +7,10 @@
 // SPDX-License-Identifier: MIT
 //
 
+mod color;
 mod entry;
-mod utf8_lossy;
+mod time;
 
+pub use color::LsColors;
 pub use entry::{Entry, LongFormatPadding, MultiColumnPadding};
-pub use utf8_lossy::ls_from_utf8_lossy;
+pub use time::TimeStyle;
@@ -0,0 +1,165 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+//! Timestamp formatting for the long format (-l).
+//!
+//! Timestamps are formatted with `strftime(3)` so that month names and the
+//! like follow `LC_TIME`. The TZ environment variable is honored through
+//! `localtime_r(3)`.
+
+use std::ffi::CString;
+use std::mem::MaybeUninit;
+use std::sync::Once;
+use std::time::{Duration, SystemTime};
+
+extern "C" {
+    // Not exported by the `libc` crate
+    fn tzset();
+}
+
+// The POSIX locale formats
+const DATE_TIME_FORMAT_RECENT: &str = "%b %e %H:%M";
+const DATE_TIME_FORMAT_OLD_OR_FUTURE: &str = "%b %e  %Y"; // Two spaces between %e and %Y
+
+// Half of an average Gregorian year
+const SIX_MONTHS: Duration = Duration::from_secs(31_556_952 / 2);
+
+/// The `strftime` formats used for timestamps, selected with `--time-style`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TimeStyle {
+    // Used for timestamps within the past six months
+    recent: String,
+
+    // Used for timestamps older than six months or in the future
+    old_or_future: String,
+}
+
+impl Default for TimeStyle {
+    fn default() -> Self {
+        Self {
+            recent: DATE_TIME_FORMAT_RECENT.to_string(),
+            old_or_future: DATE_TIME_FORMAT_OLD_OR_FUTURE.to_string(),
+        }
+    }
+}
+
+impl TimeStyle {
+    /// Parse a `--time-style` argument. `STYLE` is one of `locale` (the
+    /// default), `full-iso`, `long-iso`, `iso`, or `+FORMAT`. A `FORMAT`
+    /// holding a newline uses the part before it for recent timestamps and
+    /// the part after it for the others. `%N` expands to nanoseconds.
+    pub fn parse(style: &str) -> Result<Self, String> {
+        let (recent, old_or_future) = match style {
+            "locale" => return Ok(Self::default()),
+            "full-iso" => ("%Y-%m-%d %H:%M:%S.%N %z", "%Y-%m-%d %H:%M:%S.%N %z"),
+            "long-iso" => ("%Y-%m-%d %H:%M", "%Y-%m-%d %H:%M"),
+            "iso" => ("%m-%d %H:%M", "%Y-%m-%d "),
+            _ => match style.strip_prefix('+') {
+                Some(format) => format.split_once('\n').unwrap_or((format, format)),
+                None => return Err(format!("invalid time style format '{}'", style)),
+            },
+        };
+
+        Ok(Self {
+            recent: recent.to_string(),
+            old_or_future: old_or_future.to_string(),
+        })
+    }
+
+    /// Format `time`, picking the format by how far `time` is from `now`.
+    pub fn format(&self, time: SystemTime, now: SystemTime) -> String {
+        // The year is shown instead of the time of day for timestamps that
+        // are older than six months or in the future
+        let format = match now.duration_since(time) {
+            Ok(age) if age <= SIX_MONTHS => &self.recent,
+            _ => &self.old_or_future,
+        };
+        strftime(format, time)
+    }
+}
+
+// Split `time` into seconds and nanoseconds since the epoch, rounding the
+// seconds down for times before it
+fn epoch_parts(time: SystemTime) -> (i64, u32) {
+    match time.duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(d) => (d.as_secs() as i64, d.subsec_nanos()),
+        Err(e) => {
+            let d = e.duration();
+            match d.subsec_nanos() {
+                0 => (-(d.as_secs() as i64), 0),
+                nanos => (-(d.as_secs() as i64) - 1, 1_000_000_000 - nanos),
+            }
+        }
+    }
+}
+
+// `strftime` knows nothing of sub-second precision. Replace `%N` with the
+// nanoseconds beforehand, leaving `%%N` alone.
+fn expand_nanoseconds(format: &str, nanos: u32) -> String {
+    let mut expanded = String::with_capacity(format.len());
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            expanded.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('N') => expanded.push_str(&format!("{:09}", nanos)),
+            Some(next) => {
+                expanded.push('%');
+                expanded.push(next);
+            }
+            None => expanded.push('%'),
+        }
+    }
+    expanded
+}
+
+/// Format `time` in the local time zone with `strftime(3)`.
+pub fn strftime(format: &str, time: SystemTime) -> String {
+    // `localtime_r` is not required to pick up changes to TZ by itself
+    static TZSET: Once = Once::new();
+    TZSET.call_once(|| unsafe { tzset() });
+
+    let (secs, nanos) = epoch_parts(time);
+    let Ok(format) = CString::new(expand_nanoseconds(format, nanos)) else {
+        return String::new();
+    };
+    if format.as_bytes().is_empty() {
+        return String::new();
+    }
+
+    let secs = secs as libc::time_t;
+    let tm = unsafe {
+        let mut tm: MaybeUninit<libc::tm> = MaybeUninit::zeroed();
+        if libc::localtime_r(&secs, tm.as_mut_ptr()).is_null() {
+            return secs.to_string();
+        }
+        tm.assume_init()
+    };
+
+    // A return value of 0 means the buffer was too small (or that the output
+    // is empty, which can only end the loop through the size limit)
+    let mut buf = vec![0u8; 64];
+    loop {
+        let len = unsafe {
+            libc::strftime(
+                buf.as_mut_ptr() as *mut libc::c_char,
+                buf.len(),
+                format.as_ptr(),
+                &tm,
+            )
+        };
+        if len > 0 || buf.len() >= 4096 {
+            buf.truncate(len);
+            return String::from_utf8_lossy(&buf).into_owned();
+        }
+        buf.resize(buf.len() * 2, 0);
+    }
+}
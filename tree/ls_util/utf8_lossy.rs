@@ -1,189 +0,0 @@
-//
-// Copyright (c) 2024 Hemi Labs, Inc.
-//
-// This file is part of the posixutils-rs project covered under
-// the MIT License.  For the full license text, please see the LICENSE
-// file in the root directory of this project.
-// SPDX-License-Identifier: MIT
-//
-
-//! Straight copy of experimental API `std::str::Utf8Chunks` for usage in stable
-//! Rust.
-//!
-//! https://doc.rust-lang.org/std/str/struct.Utf8Chunks.html
-//!
-//! Alternative is to use the `bstr` crate:
-//!
-//! https://docs.rs/bstr/latest/bstr/struct.Utf8Chunks.html
-
-const UTF8_CHAR_WIDTH: &[u8; 256] = &[
-    // 1  2  3  4  5  6  7  8  9  A  B  C  D  E  F
-    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, // 0
-    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, // 1
-    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, // 2
-    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, // 3
-    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, // 4
-    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, // 5
-    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, // 6
-    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, // 7
-    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // 8
-    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // 9
-    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // A
-    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // B
-    0, 0, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, // C
-    2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, // D
-    3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, // E
-    4, 4, 4, 4, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // F
-];
-
-const fn utf8_char_width(b: u8) -> usize {
-    UTF8_CHAR_WIDTH[b as usize] as usize
-}
-
-pub struct Utf8Chunk<'a> {
-    valid: &'a str,
-    invalid: &'a [u8],
-}
-
-impl<'a> Utf8Chunk<'a> {
-    pub fn valid(&self) -> &'a str {
-        self.valid
-    }
-
-    pub fn invalid(&self) -> &'a [u8] {
-        self.invalid
-    }
-}
-
-#[derive(Clone)]
-pub struct Utf8Chunks<'a> {
-    source: &'a [u8],
-}
-
-impl<'a> Utf8Chunks<'a> {
-    pub fn new(bytes: &'a [u8]) -> Self {
-        Self { source: bytes }
-    }
-}
-
-impl<'a> Iterator for Utf8Chunks<'a> {
-    type Item = Utf8Chunk<'a>;
-
-    fn next(&mut self) -> Option<Utf8Chunk<'a>> {
-        if self.source.is_empty() {
-            return None;
-        }
-
-        const TAG_CONT_U8: u8 = 128;
-        fn safe_get(xs: &[u8], i: usize) -> u8 {
-            *xs.get(i).unwrap_or(&0)
-        }
-
-        let mut i = 0;
-        let mut valid_up_to = 0;
-        while i < self.source.len() {
-            // SAFETY: `i < self.source.len()` per previous line.
-            // For some reason the following are both significantly slower:
-            // while let Some(&byte) = self.source.get(i) {
-            // while let Some(byte) = self.source.get(i).copied() {
-            let byte = unsafe { *self.source.get_unchecked(i) };
-            i += 1;
-
-            if byte < 128 {
-                // This could be a `1 => ...` case in the match below, but for
-                // the common case of all-ASCII inputs, we bypass loading the
-                // sizeable UTF8_CHAR_WIDTH table into cache.
-            } else {
-                let w = utf8_char_width(byte);
-
-                match w {
-                    2 => {
-                        if safe_get(self.source, i) & 192 != TAG_CONT_U8 {
-                            break;
-                        }
-                        i += 1;
-                    }
-                    3 => {
-                        match (byte, safe_get(self.source, i)) {
-                            (0xE0, 0xA0..=0xBF) => (),
-                            (0xE1..=0xEC, 0x80..=0xBF) => (),
-                            (0xED, 0x80..=0x9F) => (),
-                            (0xEE..=0xEF, 0x80..=0xBF) => (),
-                            _ => break,
-                        }
-                        i += 1;
-                        if safe_get(self.source, i) & 192 != TAG_CONT_U8 {
-                            break;
-                        }
-                        i += 1;
-                    }
-                    4 => {
-                        match (byte, safe_get(self.source, i)) {
-                            (0xF0, 0x90..=0xBF) => (),
-                            (0xF1..=0xF3, 0x80..=0xBF) => (),
-                            (0xF4, 0x80..=0x8F) => (),
-                            _ => break,
-                        }
-                        i += 1;
-                        if safe_get(self.source, i) & 192 != TAG_CONT_U8 {
-                            break;
-                        }
-                        i += 1;
-                        if safe_get(self.source, i) & 192 != TAG_CONT_U8 {
-                            break;
-                        }
-                        i += 1;
-                    }
-                    _ => break,
-                }
-            }
-
-            valid_up_to = i;
-        }
-
-        let (inspected, remaining) = self.source.split_at(i);
-        self.source = remaining;
-
-        let (valid, invalid) = inspected.split_at(valid_up_to);
-
-        Some(Utf8Chunk {
-            // SAFETY: All bytes up to `valid_up_to` are valid UTF-8.
-            valid: unsafe { std::str::from_utf8_unchecked(valid) },
-            invalid,
-        })
-    }
-}
-
-/// `String::from_utf8_lossy` modified to use "?" as the replacement character.
-///
-/// This only replaces invalid UTF-8 characters with "?". -q still needs to be
-/// enabled to replace other non-printable characters and <tab>.
-pub fn ls_from_utf8_lossy(v: &[u8]) -> String {
-    let mut iter = Utf8Chunks::new(v);
-
-    let first_valid = if let Some(chunk) = iter.next() {
-        let valid = chunk.valid();
-        if chunk.invalid().is_empty() {
-            debug_assert_eq!(valid.len(), v.len());
-            return valid.to_string();
-        }
-        valid
-    } else {
-        return String::from("");
-    };
-
-    const REPLACEMENT: &str = "?";
-
-    let mut res = String::with_capacity(v.len());
-    res.push_str(first_valid);
-    res.push_str(REPLACEMENT);
-
-    for chunk in iter {
-        res.push_str(chunk.valid());
-        if !chunk.invalid().is_empty() {
-            res.push_str(REPLACEMENT);
-        }
-    }
-
-    res
-}
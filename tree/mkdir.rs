@@ -9,11 +9,11 @@
 
 use clap::Parser;
 use gettextrs::{bind_textdomain_codeset, setlocale, textdomain, LocaleCategory};
-use modestr::ChmodMode;
 use plib::{modestr, PROJECT_NAME};
 use std::ffi::CString;
-use std::io;
-use std::path::PathBuf;
+use std::os::unix::{ffi::OsStrExt, fs::PermissionsExt};
+use std::path::{Component, Path, PathBuf};
+use std::{fs, io};
 
 /// mkdir - make directories
 #[derive(Parser, Debug)]
@@ -24,42 +24,63 @@ struct Args {
     parents: bool,
 
     /// Set the file permission bits of the newly-created directory to the specified mode value.
-    #[arg(short, long)]
+    #[arg(short, long, allow_hyphen_values = true)]
     mode: Option<String>,
 
     /// A pathname of a directory to be created.
     dirs: Vec<String>,
 }
 
-fn create_dir_with_mode(path: &str, mode: u32) -> io::Result<()> {
-    let c_path = CString::new(path).expect("CString::new failed");
-    let c_mode = mode as libc::mode_t;
+// Bits of a directory mode that `mkdir` doesn't set or that the umask can clear
+const MKDIR_MASKED_BITS: u32 = 0o6000;
+
+/// Create a directory with exactly the permissions `mode`. `mkdir` filters the mode through the
+/// umask, so the permissions are set again afterwards if that cleared any of them.
+fn create_dir_with_mode(path: &Path, mode: u32, umask: u32) -> io::Result<()> {
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+
+    let result = unsafe { libc::mkdir(c_path.as_ptr(), mode as libc::mode_t) };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
 
-    let result = unsafe { libc::mkdir(c_path.as_ptr(), c_mode) };
-    if result == 0 {
-        Ok(())
-    } else {
-        Err(io::Error::last_os_error())
+    if mode & (umask | MKDIR_MASKED_BITS) != 0 {
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
     }
+
+    Ok(())
 }
 
-fn do_mkdir(dirname: &str, mode: &ChmodMode, parents: bool) -> io::Result<()> {
-    let mode_val = match mode {
-        ChmodMode::Absolute(mode) => *mode,
-        ChmodMode::Symbolic(sym) => modestr::mutate(0o777, sym),
-    };
+fn do_mkdir(dirname: &str, mode: u32, parents: bool, umask: u32) -> io::Result<()> {
+    if !parents {
+        return create_dir_with_mode(Path::new(dirname), mode, umask);
+    }
+
+    // Intermediate directories get the default permissions, plus write and search for the owner
+    // so that their subdirectories can be created
+    let intermediate_mode = (0o777 & !umask) | 0o300;
+
+    let components: Vec<_> = Path::new(dirname).components().collect();
+    let mut path = PathBuf::new();
+    for (i, component) in components.iter().enumerate() {
+        path.push(component);
+        if matches!(component, Component::RootDir | Component::CurDir) {
+            continue;
+        }
 
-    if parents {
-        let mut path = PathBuf::new();
-        for part in dirname.split('/') {
-            path.push(part);
-            if path.is_dir() {
-                continue;
+        let is_last = i == components.len() - 1;
+        let dir_mode = if is_last { mode } else { intermediate_mode };
+        if let Err(e) = create_dir_with_mode(&path, dir_mode, umask) {
+            // Existing directories are not an error, including the last one
+            if e.kind() == io::ErrorKind::AlreadyExists {
+                if path.is_dir() {
+                    continue;
+                } else if !is_last {
+                    return Err(io::Error::from_raw_os_error(libc::ENOTDIR));
+                }
             }
-            create_dir_with_mode(&path.to_string_lossy(), mode_val)?;
+            return Err(e);
         }
-    } else {
-        create_dir_with_mode(dirname, mode_val)?;
     }
 
     Ok(())
@@ -75,15 +96,24 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let mut exit_code = 0;
 
-    // parse the mode string
+    let umask = modestr::umask();
+
+    // parse the mode string. Symbolic modes start from a=rwx and their clauses without a who-list
+    // are filtered through the umask, as for chmod.
     let mode = match args.mode {
-        Some(mode) => modestr::parse(&mode)?,
-        None => ChmodMode::Absolute(0o777),
+        Some(mode) => match modestr::parse(&mode) {
+            Ok(mode) => mode.apply(0o777, umask, true),
+            Err(e) => {
+                eprintln!("mkdir: {}: {}", mode, e);
+                std::process::exit(1);
+            }
+        },
+        None => 0o777 & !umask,
     };
 
-    // apply the mode to each file
+    // create each directory
     for dirname in &args.dirs {
-        if let Err(e) = do_mkdir(dirname, &mode, args.parents) {
+        if let Err(e) = do_mkdir(dirname, mode, args.parents, umask) {
             exit_code = 1;
             eprintln!("{}: {}", dirname, e);
         }
@@ -26,10 +26,7 @@ struct Args {
 }
 
 fn do_mkfifo(filename: &str, mode: &ChmodMode) -> io::Result<()> {
-    let mode_val = match mode {
-        ChmodMode::Absolute(mode) => *mode,
-        ChmodMode::Symbolic(sym) => modestr::mutate(0o666, sym),
-    };
+    let mode_val = mode.apply(0o666, 0, false);
 
     let res = unsafe { libc::mkfifo(filename.as_ptr() as *const i8, mode_val as libc::mode_t) };
     if res < 0 {
@@ -51,7 +48,13 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // parse the mode string
     let mode = match args.mode {
-        Some(mode) => modestr::parse(&mode)?,
+        Some(mode) => match modestr::parse(&mode) {
+            Ok(mode) => mode,
+            Err(e) => {
+                eprintln!("mkfifo: {}: {}", mode, e);
+                std::process::exit(1);
+            }
+        },
         None => ChmodMode::Absolute(0o666),
     };
 
@@ -10,10 +10,11 @@
 
 mod common;
 
-use self::common::{copy_file, error_string};
+use self::common::{copy_file, error_string, mode_strings};
 use clap::Parser;
-use common::CopyConfig;
+use common::{CopyConfig, PreserveAttrs, Reflink, Sparse};
 use gettextrs::{bind_textdomain_codeset, gettext, setlocale, textdomain, LocaleCategory};
+use plib::progress::{self, Progress};
 use plib::PROJECT_NAME;
 use std::{
     collections::{HashMap, HashSet},
@@ -28,13 +29,22 @@ use std::{
 #[command(author, version, about, long_about)]
 struct Args {
     /// Do not prompt for confirmation if the destination path exists
-    #[arg(short, long, overrides_with_all = ["force", "interactive"])]
+    #[arg(short, long, overrides_with_all = ["force", "interactive", "no_clobber"])]
     force: bool,
 
     /// Prompt for confirmation if the destination path exists.
-    #[arg(short, long, overrides_with_all = ["force", "interactive"])]
+    #[arg(short, long, overrides_with_all = ["force", "interactive", "no_clobber"])]
     interactive: bool,
 
+    /// Do not overwrite an existing destination path
+    #[arg(short, long, overrides_with_all = ["force", "interactive", "no_clobber"])]
+    no_clobber: bool,
+
+    /// Report the progress of copies between file systems every second. A
+    /// report is also written on receipt of SIGUSR1 (or SIGINFO).
+    #[arg(long)]
+    progress: bool,
+
     /// Source(s) and target of move(s)
     // `PathBuf` instead of `String` avoids the inefficient reconverting of a
     // `String` to a `&Path` when calling the `std::fs` functions. It also
@@ -46,7 +56,10 @@ struct Args {
 struct MvConfig {
     force: bool,
     interactive: bool,
+    no_clobber: bool,
     is_terminal: bool,
+    // For moves between file systems
+    copy: CopyConfig,
 }
 
 impl MvConfig {
@@ -54,16 +67,31 @@ impl MvConfig {
         MvConfig {
             force: args.force,
             interactive: args.interactive,
+            no_clobber: args.no_clobber,
             is_terminal: atty::is(atty::Stream::Stdin),
+            copy: CopyConfig {
+                force: args.force,
+                follow_cli: false,  // Move symlink operands themselves
+                dereference: false, // Don't follow symlinks
+                // The target is removed before copying, after prompting
+                interactive: false,
+                no_clobber: false,
+                // Always copy file attributes
+                preserve: PreserveAttrs {
+                    xattrs: cfg!(target_os = "linux"),
+                    ..PreserveAttrs::posix()
+                },
+                recursive: true, // Recursively copy
+                sparse: Sparse::Auto,
+                reflink: Reflink::Auto,
+                progress: Progress::new("mv", args.progress),
+            },
         }
     }
 }
 
 fn prompt_user(prompt: &str) -> bool {
-    eprint!("mv: {} ", prompt);
-    let mut response = String::new();
-    io::stdin().read_line(&mut response).unwrap();
-    response.to_lowercase().starts_with('y')
+    plib::prompt::prompt_user("mv", prompt)
 }
 
 // Copy the file or directory hierarchy from `src` to `dst`.
@@ -74,17 +102,8 @@ fn copy_hierarchy(
     inode_map: &mut HashMap<(u64, u64), (ftw::FileDescriptor, CString)>,
     created_files: &mut HashSet<PathBuf>,
 ) -> io::Result<()> {
-    let copy_cfg = CopyConfig {
-        force: cfg.force,
-        follow_cli: true,   // Follow symlink if passed as an argument
-        dereference: false, // Don't follow symlinks
-        interactive: cfg.interactive,
-        preserve: true,  // Always copy file attributes
-        recursive: true, // Recursively copy
-    };
-
     copy_file(
-        &copy_cfg,
+        &cfg.copy,
         src,
         dst,
         created_files,
@@ -93,6 +112,40 @@ fn copy_hierarchy(
     )
 }
 
+// Replacing an existing target is atomic with rename(2): there is no moment
+// at which the target doesn't exist. With `no_replace`, renameat2(2) makes
+// sure that no target is replaced, not even one created after it was checked
+// for. File systems without RENAME_NOREPLACE fall back to rename(2).
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+fn rename(source: &Path, target: &Path, no_replace: bool) -> io::Result<()> {
+    if no_replace {
+        let source_cstr = CString::new(source.as_os_str().as_bytes()).unwrap();
+        let target_cstr = CString::new(target.as_os_str().as_bytes()).unwrap();
+        let ret = unsafe {
+            libc::renameat2(
+                libc::AT_FDCWD,
+                source_cstr.as_ptr(),
+                libc::AT_FDCWD,
+                target_cstr.as_ptr(),
+                libc::RENAME_NOREPLACE,
+            )
+        };
+        if ret == 0 {
+            return Ok(());
+        }
+        let e = io::Error::last_os_error();
+        if !matches!(e.raw_os_error(), Some(libc::EINVAL | libc::ENOSYS)) {
+            return Err(e);
+        }
+    }
+    fs::rename(source, target)
+}
+
+#[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+fn rename(source: &Path, target: &Path, _no_replace: bool) -> io::Result<()> {
+    fs::rename(source, target)
+}
+
 /// Handles moving the file.
 ///
 /// Returns `Ok(true)` if the source was deleted and `Ok(false)` if it's not.
@@ -122,7 +175,10 @@ fn move_file(
         Some(md) => md.file_type() == ftw::FileType::Directory,
         None => false,
     };
-    let target_is_writable = target_md.map(|md| md.is_writable()).unwrap_or(false);
+    let target_is_writable = target_md
+        .as_ref()
+        .map(|md| md.is_writable())
+        .unwrap_or(false);
 
     let source_md = match ftw::Metadata::new(libc::AT_FDCWD, source_filename.as_ptr(), true) {
         Ok(md) => Some(md),
@@ -141,11 +197,27 @@ fn move_file(
         None => false,
     };
 
+    // With -n, an existing destination path is silently left alone. Returning
+    // `Ok(true)` keeps the source as is.
+    if target_exists && cfg.no_clobber {
+        return Ok(true);
+    }
+
     // 1. If the destination path exists, conditionally prompt user
     if target_exists && !cfg.force && ((!target_is_writable && cfg.is_terminal) || cfg.interactive)
     {
-        let is_affirm = prompt_user(&gettext!("overwrite '{}'?", target.display()));
-        if !is_affirm {
+        let prompt = if target_is_writable {
+            gettext!("overwrite '{}'?", target.display())
+        } else {
+            let (mode_octal, mode_str) = mode_strings(target_md.as_ref().unwrap().mode());
+            gettext!(
+                "replace '{}', overriding mode {} ({})?",
+                target.display(),
+                mode_octal,
+                mode_str
+            )
+        };
+        if !prompt_user(&prompt) {
             return Ok(true);
         }
     }
@@ -227,11 +299,16 @@ fn move_file(
     }
 
     // 3. call rename(2) to move source to target
-    match fs::rename(source, target) {
-        Ok(_) => return Ok(true),
+    match rename(source, target, cfg.no_clobber) {
+        Ok(_) => {
+            cfg.copy.progress.add_file();
+            return Ok(true);
+        }
+        // Created after the check above
+        Err(e) if cfg.no_clobber && e.kind() == io::ErrorKind::AlreadyExists => return Ok(true),
         Err(e) => {
             // use ErrorKind::CrossesDevices in the future, when it is stable
-            let errno = std::io::Error::last_os_error().raw_os_error().unwrap();
+            let errno = e.raw_os_error().unwrap_or(0);
             if errno != libc::EXDEV {
                 let err_str = match errno {
                     // The new directory pathname contains a path prefix that
@@ -291,11 +368,41 @@ fn move_file(
         Some(set) => set,
         None => &mut HashSet::new(),
     };
-    copy_hierarchy(cfg, source, target, inode_map, created_files).map_err(err_inter_device)?;
+    if let Err(e) = copy_hierarchy(cfg, source, target, inode_map, created_files) {
+        // Nothing else was at the target after (5). Leave the source intact
+        // and don't keep a partial copy of it around.
+        let _ = remove_hierarchy(target);
+        return Err(err_inter_device(e));
+    }
 
     Ok(false)
 }
 
+// Remove `path` and, if it is a directory, everything under it. Symlinks are
+// removed, not followed.
+fn remove_hierarchy(path: &Path) -> io::Result<()> {
+    if fs::symlink_metadata(path)?.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+// 7. Remove source file hierarchy after copying it
+fn remove_source(source: &Path) -> bool {
+    match remove_hierarchy(source) {
+        Ok(()) => true,
+        Err(e) => {
+            eprintln!(
+                "mv: {}: {}",
+                gettext!("cannot remove '{}'", source.display()),
+                error_string(&e)
+            );
+            false
+        }
+    }
+}
+
 fn move_files(cfg: &MvConfig, sources: &[PathBuf], target: &Path) -> Option<()> {
     let mut result = Some(());
 
@@ -349,19 +456,8 @@ fn move_files(cfg: &MvConfig, sources: &[PathBuf], target: &Path) -> Option<()>
         }
     }
 
-    // 7. Remove source file hierarchy
     for source in sources_to_delete {
-        let remove_result = if source.is_dir() {
-            fs::remove_dir_all(source)
-        } else {
-            fs::remove_file(source)
-        };
-        if let Err(e) = remove_result {
-            eprintln!(
-                "mv: {}: {}",
-                gettext!("cannot remove '{}'", source.display()),
-                error_string(&e)
-            );
+        if !remove_source(source) {
             result = None;
         }
     }
@@ -406,33 +502,26 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     };
 
     let cfg = MvConfig::new(&args);
-    if dir_exists {
-        match move_files(&cfg, sources, target) {
-            Some(_) => Ok(()),
-            None => {
-                // Already eprintln'd the errors
-                std::process::exit(1);
-            }
-        }
+    progress::install_signal_handler();
+
+    let success = if dir_exists {
+        // Already eprintln'd the errors
+        move_files(&cfg, sources, target).is_some()
     } else {
         let source = &sources[0];
         let mut dummy = HashMap::new();
         match move_file(&cfg, source, target, &mut dummy, None) {
-            Ok(is_source_deleted) => {
-                // 7. Remove source file hierarchy
-                if !is_source_deleted {
-                    if source.is_dir() {
-                        fs::remove_dir_all(source)?;
-                    } else {
-                        fs::remove_file(source)?;
-                    }
-                }
-                Ok(())
-            }
+            Ok(is_source_deleted) => is_source_deleted || remove_source(source),
             Err(e) => {
                 eprintln!("mv: {}", e);
-                std::process::exit(1);
+                false
             }
         }
+    };
+    cfg.copy.progress.finish();
+
+    if !success {
+        std::process::exit(1);
     }
+    Ok(())
 }
@@ -15,7 +15,8 @@ use ftw::{self, traverse_directory};
 use gettextrs::{bind_textdomain_codeset, gettext, setlocale, textdomain, LocaleCategory};
 use plib::PROJECT_NAME;
 use std::{
-    ffi::CString,
+    cell::Cell,
+    ffi::{CStr, CString},
     fs, io,
     os::unix::{ffi::OsStrExt, fs::MetadataExt},
     path::{Path, PathBuf},
@@ -26,17 +27,29 @@ use std::{
 #[command(author, version, about, long_about)]
 struct Args {
     /// Do not prompt for confirmation.
-    #[arg(short, long, overrides_with_all = ["force", "interactive"])]
+    #[arg(short, long, overrides_with_all = ["force", "interactive", "interactive_once"])]
     force: bool,
 
     /// Prompt for confirmation.
-    #[arg(short, long, overrides_with_all = ["force", "interactive"])]
+    #[arg(short, long, overrides_with_all = ["force", "interactive", "interactive_once"])]
     interactive: bool,
 
+    /// Prompt once before removing more than three files or when removing recursively.
+    #[arg(short = 'I', overrides_with_all = ["force", "interactive", "interactive_once"])]
+    interactive_once: bool,
+
     /// Remove file hierarchies.
     #[arg(short, visible_short_alias = 'R', long)]
     recurse: bool,
 
+    /// Do not treat '/' specially when removing recursively.
+    #[arg(long, overrides_with = "preserve_root")]
+    no_preserve_root: bool,
+
+    /// Refuse to remove '/' recursively (default).
+    #[arg(long, overrides_with = "no_preserve_root")]
+    preserve_root: bool,
+
     /// Filepaths to remove
     #[arg(value_parser = parse_pathbuf)]
     files: Vec<PathBuf>,
@@ -53,10 +66,7 @@ struct RmConfig {
 }
 
 fn prompt_user(prompt: &str) -> bool {
-    eprint!("rm: {} ", prompt);
-    let mut response = String::new();
-    io::stdin().read_line(&mut response).unwrap();
-    response.to_lowercase().starts_with('y')
+    plib::prompt::prompt_user("rm", prompt)
 }
 
 // Simplifies trailing slashes
@@ -72,8 +82,31 @@ fn ask_for_prompt(cfg: &RmConfig, writable: bool) -> bool {
     !cfg.args.force && ((!writable && cfg.is_tty) || cfg.args.interactive)
 }
 
+/// Check if the permissions of `filename`, relative to `dirfd`, allow writing to it. A file that
+/// doesn't is write-protected and gets a prompt when standard input is a terminal.
+///
+/// Symlinks are never write-protected as their permissions are not used. The check is skipped
+/// when there would be no prompt either way.
+fn is_writable(
+    cfg: &RmConfig,
+    dirfd: libc::c_int,
+    filename: &CStr,
+    metadata: &ftw::Metadata,
+) -> bool {
+    if cfg.args.force || !(cfg.is_tty || cfg.args.interactive) {
+        return true;
+    }
+    if metadata.file_type() == ftw::FileType::SymbolicLink {
+        return true;
+    }
+
+    // Unlike the permission bits, `faccessat` accounts for supplementary groups, ACLs and root
+    let ret = unsafe { libc::faccessat(dirfd, filename.as_ptr(), libc::W_OK, 0) };
+    ret == 0 || io::Error::last_os_error().raw_os_error() != Some(libc::EACCES)
+}
+
 fn descend_into_directory(cfg: &RmConfig, entry: &ftw::Entry, metadata: &ftw::Metadata) -> bool {
-    let writable = metadata.is_writable();
+    let writable = is_writable(cfg, entry.dir_fd(), entry.file_name(), metadata);
     if ask_for_prompt(cfg, writable) {
         let prompt = if writable {
             gettext!(
@@ -94,7 +127,7 @@ fn descend_into_directory(cfg: &RmConfig, entry: &ftw::Entry, metadata: &ftw::Me
 }
 
 fn should_remove_directory(cfg: &RmConfig, entry: &ftw::Entry, metadata: &ftw::Metadata) -> bool {
-    let writable = metadata.is_writable();
+    let writable = is_writable(cfg, entry.dir_fd(), entry.file_name(), metadata);
     if ask_for_prompt(cfg, writable) {
         let prompt = if writable {
             gettext!(
@@ -116,11 +149,15 @@ fn should_remove_directory(cfg: &RmConfig, entry: &ftw::Entry, metadata: &ftw::M
 
 // The signature of `filename_fn` is to prevent unnecessarily building the filename when a prompt
 // is not required.
-fn should_remove_file<F>(cfg: &RmConfig, metadata: &ftw::Metadata, filename_fn: F) -> bool
+fn should_remove_file<F>(
+    cfg: &RmConfig,
+    metadata: &ftw::Metadata,
+    writable: bool,
+    filename_fn: F,
+) -> bool
 where
     F: Fn() -> String,
 {
-    let writable = metadata.is_writable();
     if ask_for_prompt(cfg, writable) {
         let file_type = metadata.file_type();
         let prompt = match file_type {
@@ -176,24 +213,26 @@ enum DirAction {
 }
 
 /// Directly remove a directory or enter it.
+///
+/// Without prompts, a directory is always entered and removed after its contents, by
+/// `postprocess_dir` of `traverse_directory`. Only when prompting is an empty directory detected
+/// upfront, to ask "remove directory" instead of "descend into directory".
 fn process_directory(
     cfg: &RmConfig,
     entry: &ftw::Entry,
     metadata: &ftw::Metadata,
 ) -> io::Result<DirAction> {
+    let writable = is_writable(cfg, entry.dir_fd(), entry.file_name(), metadata);
+    if !ask_for_prompt(cfg, writable) {
+        return Ok(DirAction::Entered);
+    }
+
     let dir_is_empty = entry.is_empty_dir();
 
     // If directory is empty or the directory is inaccessible, try to remove it directly
     if (dir_is_empty.is_ok() && dir_is_empty.as_ref().unwrap() == &true) || dir_is_empty.is_err() {
         if should_remove_directory(cfg, entry, metadata) {
-            let ret = unsafe {
-                libc::unlinkat(
-                    entry.dir_fd(),
-                    entry.file_name().as_ptr(),
-                    libc::AT_REMOVEDIR,
-                )
-            };
-            if ret != 0 {
+            if let Err(e2) = remove_dir_at(entry) {
                 let err_str = if let Err(e1) = dir_is_empty {
                     gettext!(
                         "cannot remove '{}': {}",
@@ -201,7 +240,6 @@ fn process_directory(
                         error_string(&e1)
                     )
                 } else {
-                    let e2 = io::Error::last_os_error();
                     gettext!(
                         "cannot remove directory '{}': {}",
                         entry.path().clean_trailing_slashes(),
@@ -226,11 +264,31 @@ fn process_directory(
     }
 }
 
+// Remove the directory `entry` relative to its parent's file descriptor
+fn remove_dir_at(entry: &ftw::Entry) -> io::Result<()> {
+    let ret = unsafe {
+        libc::unlinkat(
+            entry.dir_fd(),
+            entry.file_name().as_ptr(),
+            libc::AT_REMOVEDIR,
+        )
+    };
+    if ret != 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
 /// Recursively removes a directory.
 ///
 /// This function returns `Ok(true)` on success. The return value of `Ok(false)`
 /// denotes that the error message is already printed to stderr to is used to
 /// change the exit code in `main`.
+///
+/// The hierarchy is removed depth-first, each directory after its contents. Entries are removed
+/// relative to the file descriptor of the directory containing them and symlinks are never
+/// followed, so neither long paths nor directories renamed during the removal are a problem.
 fn rm_directory(cfg: &RmConfig, filepath: &Path) -> io::Result<bool> {
     if !cfg.args.recurse {
         let err_str = gettext!(
@@ -250,23 +308,34 @@ fn rm_directory(cfg: &RmConfig, filepath: &Path) -> io::Result<bool> {
         return Err(io::Error::other(err_str));
     }
 
-    // Also forbidden to `rm` the root directory
-    if let Ok(abspath) = fs::canonicalize(filepath) {
-        if abspath.as_os_str() == "/" {
-            // If the arg is verbatim "/"
-            let err_str = if filepath.as_os_str() == "/" {
-                gettext("it is dangerous to operate recursively on '/'")
-            } else {
-                gettext!(
-                    "it is dangerous to operate recursively on '{}' (same as '/')",
-                    filepath.display()
-                )
-            };
-            return Err(io::Error::other(err_str));
+    // Also forbidden to `rm` the root directory, unless overridden
+    if !cfg.args.no_preserve_root {
+        if let Ok(abspath) = fs::canonicalize(filepath) {
+            if abspath.as_os_str() == "/" {
+                // If the arg is verbatim "/"
+                let err_str = if filepath.as_os_str() == "/" {
+                    gettext("it is dangerous to operate recursively on '/'")
+                } else {
+                    gettext!(
+                        "it is dangerous to operate recursively on '{}' (same as '/')",
+                        filepath.display()
+                    )
+                };
+                eprintln!("rm: {}", err_str);
+                eprintln!(
+                    "rm: {}",
+                    gettext("use --no-preserve-root to override this failsafe")
+                );
+                return Ok(false);
+            }
         }
     }
 
-    let success = traverse_directory(
+    // Errors are tracked here instead of using the return value of `traverse_directory`, because
+    // a directory that can't be entered is not an error if it can be removed anyway
+    let success = Cell::new(true);
+
+    traverse_directory(
         filepath,
         |entry| {
             let md = entry.metadata().unwrap();
@@ -279,11 +348,13 @@ fn rm_directory(cfg: &RmConfig, filepath: &Path) -> io::Result<bool> {
                     },
                     Err(e) => {
                         eprintln!("rm: {}", error_string(&e));
+                        success.set(false);
                         Err(())
                     }
                 }
             } else {
-                if should_remove_file(cfg, md, || entry.path().clean_trailing_slashes()) {
+                let writable = is_writable(cfg, entry.dir_fd(), entry.file_name(), md);
+                if should_remove_file(cfg, md, writable, || entry.path().clean_trailing_slashes()) {
                     // Remove the file
                     let ret =
                         unsafe { libc::unlinkat(entry.dir_fd(), entry.file_name().as_ptr(), 0) };
@@ -298,6 +369,7 @@ fn rm_directory(cfg: &RmConfig, filepath: &Path) -> io::Result<bool> {
                                 error_string(&e)
                             )
                         );
+                        success.set(false);
                         return Err(());
                     }
                 }
@@ -307,18 +379,7 @@ fn rm_directory(cfg: &RmConfig, filepath: &Path) -> io::Result<bool> {
         |entry| {
             let md = entry.metadata().unwrap();
             if should_remove_directory(cfg, &entry, md) {
-                // Remove the directory
-                let ret = unsafe {
-                    libc::unlinkat(
-                        entry.dir_fd(),
-                        entry.file_name().as_ptr(),
-                        libc::AT_REMOVEDIR,
-                    )
-                };
-
-                if ret != 0 {
-                    let e = io::Error::last_os_error();
-
+                if let Err(e) = remove_dir_at(&entry) {
                     // `ENOTEMPTY` means one or more subdirectories were not
                     // removed. Do not flood the output by recursively
                     // printing `Directory not empty` errors.
@@ -329,6 +390,7 @@ fn rm_directory(cfg: &RmConfig, filepath: &Path) -> io::Result<bool> {
                             error_string(&e)
                         );
                         eprintln!("rm: {}", err_str);
+                        success.set(false);
                         return Err(());
                     }
                 }
@@ -336,53 +398,76 @@ fn rm_directory(cfg: &RmConfig, filepath: &Path) -> io::Result<bool> {
 
             Ok(())
         },
-        |entry, error| match error.kind() {
-            ftw::ErrorKind::OpenDir => {
-                eprintln!(
-                    "rm: {}",
-                    gettext!(
-                        "cannot access directory '{}': {}",
-                        entry.path().clean_trailing_slashes(),
-                        error_string(&error.inner())
-                    )
-                );
-            }
-            ftw::ErrorKind::ReadDir => {
-                eprintln!(
-                    "rm: {}",
-                    gettext!(
-                        "error accessing directory entry: {}",
-                        entry.path().clean_trailing_slashes(),
-                    )
-                );
-            }
-            ftw::ErrorKind::Stat => {
-                eprintln!(
-                    "rm: {}",
-                    gettext!(
-                        "cannot stat '{}': {}",
-                        entry.path().clean_trailing_slashes(),
-                        error_string(&error.inner())
-                    )
+        |entry, error| {
+            // A directory that can't be read or searched can still be removed if it's empty. When
+            // prompting, `process_directory` has already tried that.
+            if let Some(md) = entry.metadata() {
+                let unreadable = matches!(
+                    error.kind(),
+                    ftw::ErrorKind::Open
+                        | ftw::ErrorKind::OpenDir
+                        | ftw::ErrorKind::DirNotSearchable
                 );
+                if unreadable
+                    && md.file_type() == ftw::FileType::Directory
+                    && !ask_for_prompt(cfg, is_writable(cfg, entry.dir_fd(), entry.file_name(), md))
+                    && remove_dir_at(&entry).is_ok()
+                {
+                    return ftw::ErrorAction::Continue;
+                }
             }
-            ftw::ErrorKind::Open | ftw::ErrorKind::DirNotSearchable => {
-                eprintln!(
-                    "rm: {}",
-                    gettext!(
-                        "cannot remove '{}': {}",
-                        entry.path().clean_trailing_slashes(),
-                        error_string(&error.inner())
-                    )
-                );
+
+            success.set(false);
+            match error.kind() {
+                ftw::ErrorKind::OpenDir => {
+                    eprintln!(
+                        "rm: {}",
+                        gettext!(
+                            "cannot access directory '{}': {}",
+                            entry.path().clean_trailing_slashes(),
+                            error_string(&error.inner())
+                        )
+                    );
+                }
+                ftw::ErrorKind::ReadDir => {
+                    eprintln!(
+                        "rm: {}",
+                        gettext!(
+                            "error accessing directory entry: {}",
+                            entry.path().clean_trailing_slashes(),
+                        )
+                    );
+                }
+                ftw::ErrorKind::Stat => {
+                    eprintln!(
+                        "rm: {}",
+                        gettext!(
+                            "cannot stat '{}': {}",
+                            entry.path().clean_trailing_slashes(),
+                            error_string(&error.inner())
+                        )
+                    );
+                }
+                ftw::ErrorKind::Open | ftw::ErrorKind::DirNotSearchable | ftw::ErrorKind::Cycle => {
+                    eprintln!(
+                        "rm: {}",
+                        gettext!(
+                            "cannot remove '{}': {}",
+                            entry.path().clean_trailing_slashes(),
+                            error_string(&error.inner())
+                        )
+                    );
+                }
+                ftw::ErrorKind::ReadLink => unreachable!(), // rm doesn't follow symlinks
             }
-            ftw::ErrorKind::ReadLink => unreachable!(), // rm doesn't follow symlinks
+            ftw::ErrorAction::Continue
         },
         false, // Don't follow symlinks on `filepath`
         false, // Don't follow any encountered symlinks
+        false, // Descend into other file systems
     );
 
-    Ok(success)
+    Ok(success.get())
 }
 
 /// Removes a file.
@@ -393,7 +478,8 @@ fn rm_file(cfg: &RmConfig, filepath: &Path) -> io::Result<bool> {
     let filename_cstr = CString::new(filepath.as_os_str().as_bytes())?;
     let metadata = ftw::Metadata::new(libc::AT_FDCWD, filename_cstr.as_ptr(), false)?;
 
-    if should_remove_file(cfg, &metadata, || display_cleaned(filepath)) {
+    let writable = is_writable(cfg, libc::AT_FDCWD, &filename_cstr, &metadata);
+    if should_remove_file(cfg, &metadata, writable, || display_cleaned(filepath)) {
         fs::remove_file(filepath).map_err(|e| {
             let err_str = gettext!(
                 "cannot remove '{}': {}",
@@ -443,6 +529,19 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let is_tty = atty::is(atty::Stream::Stdin);
     let cfg = RmConfig { args, is_tty };
 
+    // -I asks once upfront instead of for each file
+    let num_files = cfg.args.files.len();
+    if cfg.args.interactive_once && num_files > 0 && (cfg.args.recurse || num_files > 3) {
+        let prompt = match (cfg.args.recurse, num_files) {
+            (true, 1) => gettext("remove 1 argument recursively?"),
+            (true, n) => gettext!("remove {} arguments recursively?", n),
+            (false, n) => gettext!("remove {} arguments?", n),
+        };
+        if !prompt_user(&prompt) {
+            std::process::exit(0);
+        }
+    }
+
     let mut exit_code = 0;
 
     for filepath in &cfg.args.files {
@@ -8,10 +8,10 @@
 //
 
 use clap::Parser;
-use gettextrs::{bind_textdomain_codeset, gettext, setlocale, textdomain, LocaleCategory};
+use gettextrs::{bind_textdomain_codeset, setlocale, textdomain, LocaleCategory};
 use plib::PROJECT_NAME;
 use std::fs;
-use std::io::{self, Error, ErrorKind};
+use std::io;
 use std::path::Path;
 
 /// rmdir - remove directories
@@ -22,27 +22,39 @@ struct Args {
     #[arg(short, long)]
     parents: bool,
 
+    /// Ignore failures due solely to a directory not being empty
+    #[arg(long)]
+    ignore_fail_on_non_empty: bool,
+
     /// Directories to remove
     dirs: Vec<String>,
 }
 
-fn remove_dir(dirname: &str, rm_parents: bool) -> io::Result<()> {
-    fs::remove_dir(dirname)?;
+// POSIX allows either error for a directory that is not empty
+fn is_not_empty(e: &io::Error) -> bool {
+    matches!(e.raw_os_error(), Some(libc::ENOTEMPTY) | Some(libc::EEXIST))
+}
 
-    if rm_parents {
-        let parent = Path::new(dirname).parent();
-        if parent.is_some() && parent != Some(Path::new("")) {
-            match parent.unwrap().to_str() {
-                Some(parent_name) => return remove_dir(parent_name, rm_parents),
-                None => {
-                    eprintln!("{}", gettext("Non-unicode directory name rejected"));
-                    return Err(Error::new(ErrorKind::Other, "Non-unicode dir name"));
-                }
+/// Remove `dirname` and with -p, each of its parents from the deepest up, stopping at the first
+/// directory that can't be removed. On failure, returns that directory along with the error.
+fn remove_dir<'a>(args: &Args, dirname: &'a str) -> Result<(), (&'a Path, io::Error)> {
+    let mut path = Path::new(dirname);
+    loop {
+        if let Err(e) = fs::remove_dir(path) {
+            if args.ignore_fail_on_non_empty && is_not_empty(&e) {
+                return Ok(());
             }
+            return Err((path, e));
         }
-    }
 
-    Ok(())
+        if !args.parents {
+            return Ok(());
+        }
+        match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => path = parent,
+            _ => return Ok(()),
+        }
+    }
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -56,9 +68,9 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut exit_code = 0;
 
     for dirname in &args.dirs {
-        if let Err(e) = remove_dir(dirname, args.parents) {
+        if let Err((path, e)) = remove_dir(&args, dirname) {
             exit_code = 1;
-            eprintln!("{}: {}", dirname, e);
+            eprintln!("{}: {}", path.display(), e);
         }
     }
 
@@ -0,0 +1,82 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+use crate::common::{gid_of, setup_tree, uid_of};
+use plib::{run_test, TestPlan};
+use tempfile::tempdir;
+
+fn chgrp_test(args: &[&str], expected_output: &str, expected_error: &str, expected_exit_code: i32) {
+    let str_args: Vec<String> = args.iter().map(|s| String::from(*s)).collect();
+
+    run_test(TestPlan {
+        cmd: String::from("chgrp"),
+        args: str_args,
+        stdin_data: String::new(),
+        expected_out: String::from(expected_output),
+        expected_err: String::from(expected_error),
+        expected_exit_code,
+    });
+}
+
+#[test]
+fn test_chgrp_errors() {
+    let test_dir = tempdir().unwrap();
+    let missing = test_dir.path().join("missing");
+    let missing = missing.to_str().unwrap();
+
+    let gid = unsafe { libc::getegid() }.to_string();
+    chgrp_test(
+        &[&gid, missing],
+        "",
+        &format!("chgrp: changing group of '{missing}': No such file or directory\n"),
+        1,
+    );
+    chgrp_test(
+        &["-R", &gid, missing],
+        "",
+        &format!("chgrp: cannot access '{missing}': No such file or directory\n"),
+        1,
+    );
+    chgrp_test(
+        &["no-such-group-posixutils", missing],
+        "",
+        "chgrp: invalid group: 'no-such-group-posixutils'\n",
+        1,
+    );
+}
+
+#[test]
+#[cfg_attr(
+    not(all(
+        target_os = "linux",
+        feature = "posixutils_test_all",
+        feature = "requires_root"
+    )),
+    ignore
+)]
+fn test_chgrp_recursive() {
+    let test_dir = tempdir().unwrap();
+    let base = test_dir.path();
+    setup_tree(base);
+
+    let top = base.join("top");
+    chgrp_test(&["-R", "12345", top.to_str().unwrap()], "", "", 0);
+
+    assert_eq!(gid_of(base.join("top")), 12345);
+    assert_eq!(gid_of(base.join("top/a")), 12345);
+    assert_eq!(gid_of(base.join("top/sub/b")), 12345);
+
+    // The symlink itself is changed, not what it points to
+    assert_eq!(gid_of(base.join("top/link")), 12345);
+    assert_eq!(gid_of(base.join("outside")), 0);
+    assert_eq!(gid_of(base.join("outside/c")), 0);
+
+    // The owner is left alone
+    assert_eq!(uid_of(base.join("top/a")), 0);
+}
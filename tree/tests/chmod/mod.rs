@@ -0,0 +1,354 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+use crate::common;
+use plib::{run_test, TestPlan};
+use std::fs;
+use std::os::unix::fs::{symlink, PermissionsExt};
+use std::path::Path;
+use tempfile::tempdir;
+
+fn chmod_test(args: &[&str], expected_output: &str, expected_error: &str, expected_exit_code: i32) {
+    let str_args: Vec<String> = args.iter().map(|s| String::from(*s)).collect();
+
+    run_test(TestPlan {
+        cmd: String::from("chmod"),
+        args: str_args,
+        stdin_data: String::new(),
+        expected_out: String::from(expected_output),
+        expected_err: String::from(expected_error),
+        expected_exit_code,
+    });
+}
+
+fn mode_of<P: AsRef<Path>>(path: P) -> u32 {
+    fs::symlink_metadata(path).unwrap().permissions().mode() & 0o7777
+}
+
+fn set_mode<P: AsRef<Path>>(path: P, mode: u32) {
+    fs::set_permissions(path, fs::Permissions::from_mode(mode)).unwrap();
+}
+
+// The shared tree, with files of mode 600 and directories of mode 700
+fn setup_tree(base: &Path) {
+    common::setup_tree(base);
+
+    for path in ["top/a", "top/sub/b", "outside/c"] {
+        set_mode(base.join(path), 0o600);
+    }
+    for path in ["top", "top/sub", "outside"] {
+        set_mode(base.join(path), 0o700);
+    }
+}
+
+#[test]
+fn test_chmod_recursive() {
+    let test_dir = tempdir().unwrap();
+    let base = test_dir.path();
+    setup_tree(base);
+
+    let top = base.join("top");
+    chmod_test(&["-R", "go+rX", top.to_str().unwrap()], "", "", 0);
+
+    assert_eq!(mode_of(base.join("top")), 0o755);
+    assert_eq!(mode_of(base.join("top/sub")), 0o755);
+    assert_eq!(mode_of(base.join("top/a")), 0o644);
+    assert_eq!(mode_of(base.join("top/sub/b")), 0o644);
+
+    // Symlinks are not followed by default
+    assert_eq!(mode_of(base.join("outside")), 0o700);
+    assert_eq!(mode_of(base.join("outside/c")), 0o600);
+}
+
+#[test]
+fn test_chmod_recursive_follow_all() {
+    let test_dir = tempdir().unwrap();
+    let base = test_dir.path();
+    setup_tree(base);
+
+    let top = base.join("top");
+    chmod_test(&["-R", "-L", "go+rX", top.to_str().unwrap()], "", "", 0);
+
+    assert_eq!(mode_of(base.join("top/sub/b")), 0o644);
+    assert_eq!(mode_of(base.join("outside")), 0o755);
+    assert_eq!(mode_of(base.join("outside/c")), 0o644);
+}
+
+#[test]
+fn test_chmod_recursive_follow_cli() {
+    let test_dir = tempdir().unwrap();
+    let base = test_dir.path();
+    setup_tree(base);
+    symlink("top", base.join("toplink")).unwrap();
+
+    // -H follows the operand but not the symlinks found under it
+    let toplink = base.join("toplink");
+    chmod_test(&["-R", "-H", "go+rX", toplink.to_str().unwrap()], "", "", 0);
+
+    assert_eq!(mode_of(base.join("top")), 0o755);
+    assert_eq!(mode_of(base.join("top/sub/b")), 0o644);
+    assert_eq!(mode_of(base.join("outside")), 0o700);
+    assert_eq!(mode_of(base.join("outside/c")), 0o600);
+}
+
+#[test]
+fn test_chmod_recursive_no_follow_cli() {
+    let test_dir = tempdir().unwrap();
+    let base = test_dir.path();
+    setup_tree(base);
+    symlink("top", base.join("toplink")).unwrap();
+
+    // The last of -H/-L/-P wins
+    let toplink = base.join("toplink");
+    chmod_test(
+        &["-R", "-H", "-P", "go+rX", toplink.to_str().unwrap()],
+        "",
+        "",
+        0,
+    );
+
+    assert_eq!(mode_of(base.join("top")), 0o700);
+    assert_eq!(mode_of(base.join("top/a")), 0o600);
+}
+
+#[test]
+fn test_chmod_non_recursive_follows_operand() {
+    let test_dir = tempdir().unwrap();
+    let base = test_dir.path();
+    setup_tree(base);
+
+    let link = base.join("top/link");
+    chmod_test(&["750", link.to_str().unwrap()], "", "", 0);
+
+    assert_eq!(mode_of(base.join("outside")), 0o750);
+    assert_eq!(mode_of(base.join("outside/c")), 0o600);
+}
+
+#[test]
+fn test_chmod_missing_file() {
+    let test_dir = tempdir().unwrap();
+    let missing = test_dir.path().join("missing");
+    let missing = missing.to_str().unwrap();
+
+    chmod_test(
+        &["-R", "644", missing],
+        "",
+        &format!("chmod: cannot access '{missing}': No such file or directory\n"),
+        1,
+    );
+}
+
+#[test]
+fn test_chmod_verbose() {
+    let test_dir = tempdir().unwrap();
+    let file = test_dir.path().join("f");
+    fs::write(&file, b"").unwrap();
+    set_mode(&file, 0o644);
+    let file = file.to_str().unwrap();
+
+    chmod_test(
+        &["-v", "u+x", file],
+        &format!("mode of '{file}' changed from 0644 (rw-r--r--) to 0744 (rwxr--r--)\n"),
+        "",
+        0,
+    );
+    chmod_test(
+        &["-v", "u+x", file],
+        &format!("mode of '{file}' retained as 0744 (rwxr--r--)\n"),
+        "",
+        0,
+    );
+}
+
+#[test]
+fn test_chmod_changes() {
+    let test_dir = tempdir().unwrap();
+    let base = test_dir.path();
+    fs::create_dir(base.join("d")).unwrap();
+    fs::write(base.join("d/f"), b"").unwrap();
+    set_mode(base.join("d"), 0o755);
+    set_mode(base.join("d/f"), 0o600);
+
+    // Only the file is reported as the directory already has the mode
+    let dir = base.join("d");
+    chmod_test(
+        &["-R", "-c", "go+rX", dir.to_str().unwrap()],
+        &format!(
+            "mode of '{}' changed from 0600 (rw-------) to 0644 (rw-r--r--)\n",
+            base.join("d/f").display()
+        ),
+        "",
+        0,
+    );
+}
+
+#[test]
+fn test_chmod_silent() {
+    let test_dir = tempdir().unwrap();
+    let missing = test_dir.path().join("missing");
+
+    chmod_test(&["-f", "644", missing.to_str().unwrap()], "", "", 1);
+}
+
+#[test]
+fn test_chmod_reference() {
+    let test_dir = tempdir().unwrap();
+    let base = test_dir.path();
+    for name in ["ref", "f1", "f2"] {
+        fs::write(base.join(name), b"").unwrap();
+        set_mode(base.join(name), 0o600);
+    }
+    set_mode(base.join("ref"), 0o4751);
+
+    let reference = format!("--reference={}", base.join("ref").display());
+    let f1 = base.join("f1");
+    let f2 = base.join("f2");
+    chmod_test(
+        &[&reference, f1.to_str().unwrap(), f2.to_str().unwrap()],
+        "",
+        "",
+        0,
+    );
+
+    assert_eq!(mode_of(&f1), 0o4751);
+    assert_eq!(mode_of(&f2), 0o4751);
+}
+
+#[test]
+fn test_chmod_reference_missing() {
+    let test_dir = tempdir().unwrap();
+    let base = test_dir.path();
+    fs::write(base.join("f"), b"").unwrap();
+    set_mode(base.join("f"), 0o600);
+
+    let missing = base.join("missing");
+    let reference = format!("--reference={}", missing.display());
+    let f = base.join("f");
+    chmod_test(
+        &[&reference, f.to_str().unwrap()],
+        "",
+        &format!(
+            "chmod: failed to get attributes of '{}': No such file or directory\n",
+            missing.display()
+        ),
+        1,
+    );
+
+    assert_eq!(mode_of(&f), 0o600);
+}
+
+#[test]
+fn test_chmod_parallel() {
+    let test_dir = tempdir().unwrap();
+    let base = test_dir.path();
+    setup_tree(base);
+    for i in 0..20 {
+        let dir = base.join(format!("top/sub/d{i:02}"));
+        fs::create_dir(&dir).unwrap();
+        set_mode(&dir, 0o700);
+        for j in 0..5 {
+            let file = dir.join(format!("f{j}"));
+            fs::write(&file, b"").unwrap();
+            set_mode(&file, 0o600);
+        }
+    }
+
+    let top = base.join("top");
+    chmod_test(
+        &["-R", "--jobs=4", "go+rX", top.to_str().unwrap()],
+        "",
+        "",
+        0,
+    );
+
+    assert_eq!(mode_of(base.join("top")), 0o755);
+    assert_eq!(mode_of(base.join("top/a")), 0o644);
+    for i in 0..20 {
+        let dir = base.join(format!("top/sub/d{i:02}"));
+        assert_eq!(mode_of(&dir), 0o755);
+        for j in 0..5 {
+            assert_eq!(mode_of(dir.join(format!("f{j}"))), 0o644);
+        }
+    }
+
+    // Symlinks are not followed by default
+    assert_eq!(mode_of(base.join("outside")), 0o700);
+    assert_eq!(mode_of(base.join("outside/c")), 0o600);
+}
+
+#[test]
+fn test_chmod_parallel_ordered_output() {
+    let test_dir = tempdir().unwrap();
+    let base = test_dir.path();
+    setup_tree(base);
+
+    // Reports are sorted by path regardless of which thread made them
+    let top = base.join("top");
+    let dir_change = "0700 (rwx------) to 0750 (rwxr-x---)";
+    let file_change = "0600 (rw-------) to 0640 (rw-r-----)";
+    let expected: String = [
+        ("top", dir_change),
+        ("top/a", file_change),
+        ("top/sub", dir_change),
+        ("top/sub/b", file_change),
+    ]
+    .iter()
+    .map(|(path, change)| {
+        format!(
+            "mode of '{}' changed from {change}\n",
+            base.join(path).display()
+        )
+    })
+    .collect();
+    chmod_test(
+        &["-R", "-c", "--jobs=3", "g+rX", top.to_str().unwrap()],
+        &expected,
+        "",
+        0,
+    );
+}
+
+#[test]
+fn test_chmod_parallel_follow_loop() {
+    let test_dir = tempdir().unwrap();
+    let base = test_dir.path();
+    fs::create_dir_all(base.join("a/b")).unwrap();
+    symlink("../..", base.join("a/b/up")).unwrap();
+
+    // a/b/up is the parent of a, so a/b/up/a is a again
+    let a = base.join("a");
+    let expected_error = format!(
+        "chmod: cannot access '{}': File system loop detected\n",
+        base.join("a/b/up/a").display()
+    );
+    for jobs in [None, Some("--jobs=2")] {
+        let mut args = vec!["-RL", "u+w", a.to_str().unwrap()];
+        args.extend(jobs);
+        chmod_test(&args, "", &expected_error, 1);
+    }
+}
+
+#[test]
+fn test_chmod_conditional_execute() {
+    let test_dir = tempdir().unwrap();
+    let base = test_dir.path();
+    fs::create_dir(base.join("d")).unwrap();
+    fs::write(base.join("d/plain"), b"").unwrap();
+    fs::write(base.join("d/exec"), b"").unwrap();
+    set_mode(base.join("d"), 0o700);
+    set_mode(base.join("d/plain"), 0o600);
+    set_mode(base.join("d/exec"), 0o700);
+
+    let dir = base.join("d");
+    chmod_test(&["-R", "u=rwX,go=rX", dir.to_str().unwrap()], "", "", 0);
+
+    assert_eq!(mode_of(base.join("d")), 0o755);
+    assert_eq!(mode_of(base.join("d/plain")), 0o644);
+    assert_eq!(mode_of(base.join("d/exec")), 0o755);
+}
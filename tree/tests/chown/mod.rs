@@ -0,0 +1,266 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+use crate::common::{gid_of, setup_tree, uid_of};
+use plib::{run_test, TestPlan};
+use std::fs;
+use std::os::unix::fs::symlink;
+use tempfile::tempdir;
+
+fn chown_test(args: &[&str], expected_output: &str, expected_error: &str, expected_exit_code: i32) {
+    let str_args: Vec<String> = args.iter().map(|s| String::from(*s)).collect();
+
+    run_test(TestPlan {
+        cmd: String::from("chown"),
+        args: str_args,
+        stdin_data: String::new(),
+        expected_out: String::from(expected_output),
+        expected_err: String::from(expected_error),
+        expected_exit_code,
+    });
+}
+
+#[test]
+fn test_chown_recursive_same_owner() {
+    let test_dir = tempdir().unwrap();
+    let base = test_dir.path();
+    setup_tree(base);
+
+    let owner = format!("{}:{}", unsafe { libc::geteuid() }, unsafe {
+        libc::getegid()
+    });
+    let top = base.join("top");
+    chown_test(&["-R", &owner, top.to_str().unwrap()], "", "", 0);
+}
+
+#[test]
+fn test_chown_missing_file() {
+    let test_dir = tempdir().unwrap();
+    let missing = test_dir.path().join("missing");
+    let missing = missing.to_str().unwrap();
+
+    let owner = unsafe { libc::geteuid() }.to_string();
+    chown_test(
+        &[&owner, missing],
+        "",
+        &format!("chown: changing ownership of '{missing}': No such file or directory\n"),
+        1,
+    );
+    chown_test(
+        &["-R", &owner, missing],
+        "",
+        &format!("chown: cannot access '{missing}': No such file or directory\n"),
+        1,
+    );
+}
+
+#[test]
+#[cfg_attr(
+    not(all(
+        target_os = "linux",
+        feature = "posixutils_test_all",
+        feature = "requires_root"
+    )),
+    ignore
+)]
+fn test_chown_recursive_physical() {
+    let test_dir = tempdir().unwrap();
+    let base = test_dir.path();
+    setup_tree(base);
+
+    let top = base.join("top");
+    chown_test(&["-R", "12345", top.to_str().unwrap()], "", "", 0);
+
+    assert_eq!(uid_of(base.join("top")), 12345);
+    assert_eq!(uid_of(base.join("top/a")), 12345);
+    assert_eq!(uid_of(base.join("top/sub/b")), 12345);
+
+    // The symlink itself is changed, not what it points to
+    assert_eq!(uid_of(base.join("top/link")), 12345);
+    assert_eq!(uid_of(base.join("outside")), 0);
+    assert_eq!(uid_of(base.join("outside/c")), 0);
+}
+
+#[test]
+#[cfg_attr(
+    not(all(
+        target_os = "linux",
+        feature = "posixutils_test_all",
+        feature = "requires_root"
+    )),
+    ignore
+)]
+fn test_chown_recursive_follow_all() {
+    let test_dir = tempdir().unwrap();
+    let base = test_dir.path();
+    setup_tree(base);
+
+    let top = base.join("top");
+    chown_test(&["-R", "-L", "12345", top.to_str().unwrap()], "", "", 0);
+
+    assert_eq!(uid_of(base.join("top/sub/b")), 12345);
+    assert_eq!(uid_of(base.join("top/link")), 0);
+    assert_eq!(uid_of(base.join("outside")), 12345);
+    assert_eq!(uid_of(base.join("outside/c")), 12345);
+}
+
+#[test]
+#[cfg_attr(
+    not(all(
+        target_os = "linux",
+        feature = "posixutils_test_all",
+        feature = "requires_root"
+    )),
+    ignore
+)]
+fn test_chown_recursive_follow_cli() {
+    let test_dir = tempdir().unwrap();
+    let base = test_dir.path();
+    setup_tree(base);
+    symlink("top", base.join("toplink")).unwrap();
+
+    let toplink = base.join("toplink");
+    chown_test(&["-R", "-H", "12345", toplink.to_str().unwrap()], "", "", 0);
+
+    assert_eq!(uid_of(base.join("toplink")), 0);
+    assert_eq!(uid_of(base.join("top")), 12345);
+    assert_eq!(uid_of(base.join("top/a")), 12345);
+    assert_eq!(uid_of(base.join("top/link")), 12345);
+    assert_eq!(uid_of(base.join("outside")), 0);
+}
+
+#[test]
+#[cfg_attr(
+    not(all(
+        target_os = "linux",
+        feature = "posixutils_test_all",
+        feature = "requires_root"
+    )),
+    ignore
+)]
+fn test_chown_no_dereference() {
+    let test_dir = tempdir().unwrap();
+    let base = test_dir.path();
+    setup_tree(base);
+
+    let link = base.join("top/link");
+    chown_test(&["-h", "12345:54321", link.to_str().unwrap()], "", "", 0);
+    assert_eq!(uid_of(&link), 12345);
+    assert_eq!(gid_of(&link), 54321);
+    assert_eq!(uid_of(base.join("outside")), 0);
+
+    // Without -h, the operand is dereferenced
+    chown_test(&["12345", link.to_str().unwrap()], "", "", 0);
+    assert_eq!(uid_of(base.join("outside")), 12345);
+}
+
+#[test]
+fn test_chown_from_no_match() {
+    let test_dir = tempdir().unwrap();
+    let file = test_dir.path().join("file");
+    fs::write(&file, b"").unwrap();
+
+    // No file is owned by the filter's owner, so nothing is changed and the
+    // requested owner is never tried
+    let uid = unsafe { libc::geteuid() };
+    let from = format!("{}", uid.wrapping_add(1));
+    chown_test(
+        &["--from", &from, "12345", file.to_str().unwrap()],
+        "",
+        "",
+        0,
+    );
+    assert_eq!(uid_of(&file), uid);
+}
+
+#[test]
+#[cfg_attr(
+    not(all(
+        target_os = "linux",
+        feature = "posixutils_test_all",
+        feature = "requires_root"
+    )),
+    ignore
+)]
+fn test_chown_from() {
+    let test_dir = tempdir().unwrap();
+    let base = test_dir.path();
+    setup_tree(base);
+    fs::write(base.join("top/d"), b"").unwrap();
+
+    let a = base.join("top/a");
+    let b = base.join("top/sub/b");
+    let c = base.join("outside/c");
+    let d = base.join("top/d");
+    chown_test(&["1000:2000", a.to_str().unwrap()], "", "", 0);
+    chown_test(&["1000:3000", b.to_str().unwrap()], "", "", 0);
+    chown_test(&["1001:2000", d.to_str().unwrap()], "", "", 0);
+
+    // Only the owner is checked
+    let top = base.join("top");
+    chown_test(
+        &["-R", "--from=1000", "5000", top.to_str().unwrap()],
+        "",
+        "",
+        0,
+    );
+    assert_eq!(uid_of(&a), 5000);
+    assert_eq!(uid_of(&b), 5000);
+    assert_eq!(uid_of(&d), 1001);
+    assert_eq!(uid_of(&top), 0);
+
+    // Only the group is checked
+    chown_test(
+        &["-R", "--from=:2000", "6000", top.to_str().unwrap()],
+        "",
+        "",
+        0,
+    );
+    assert_eq!(uid_of(&a), 6000);
+    assert_eq!(uid_of(&b), 5000);
+    assert_eq!(uid_of(&d), 6000);
+
+    // Both must match
+    chown_test(
+        &[
+            "--from=5000:3000",
+            "7000",
+            a.to_str().unwrap(),
+            b.to_str().unwrap(),
+            c.to_str().unwrap(),
+        ],
+        "",
+        "",
+        0,
+    );
+    assert_eq!(uid_of(&a), 6000);
+    assert_eq!(uid_of(&b), 7000);
+    assert_eq!(uid_of(&c), 0);
+}
+
+#[test]
+fn test_chown_invalid_owner() {
+    let test_dir = tempdir().unwrap();
+    let file = test_dir.path().join("file");
+    fs::write(&file, b"").unwrap();
+    let file = file.to_str().unwrap();
+
+    chown_test(
+        &["no-such-user-posixutils", file],
+        "",
+        "chown: invalid user: 'no-such-user-posixutils'\n",
+        1,
+    );
+    chown_test(
+        &["0:no-such-group-posixutils", file],
+        "",
+        "chown: invalid group: 'no-such-group-posixutils'\n",
+        1,
+    );
+}
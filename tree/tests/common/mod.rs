@@ -0,0 +1,36 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+//! Fixtures shared by the tests of `chmod`, `chown` and `chgrp`.
+
+use std::fs;
+use std::os::unix::fs::{symlink, MetadataExt};
+use std::path::Path;
+
+pub fn uid_of<P: AsRef<Path>>(path: P) -> u32 {
+    fs::symlink_metadata(path).unwrap().uid()
+}
+
+pub fn gid_of<P: AsRef<Path>>(path: P) -> u32 {
+    fs::symlink_metadata(path).unwrap().gid()
+}
+
+// Creates:
+//   top/a
+//   top/sub/b
+//   outside/c
+//   top/link -> ../outside
+pub fn setup_tree(base: &Path) {
+    fs::create_dir_all(base.join("top/sub")).unwrap();
+    fs::create_dir(base.join("outside")).unwrap();
+    fs::write(base.join("top/a"), b"").unwrap();
+    fs::write(base.join("top/sub/b"), b"").unwrap();
+    fs::write(base.join("outside/c"), b"").unwrap();
+    symlink("../outside", base.join("top/link")).unwrap();
+}
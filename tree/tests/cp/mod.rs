@@ -7,7 +7,7 @@
 // SPDX-License-Identifier: MIT
 //
 
-use plib::{run_test, TestPlan};
+use plib::{run_test, run_test_with_checker, TestPlan};
 use std::ffi::CString;
 use std::io::{Read, Write};
 use std::os::unix::fs::FileTypeExt;
@@ -970,3 +970,455 @@ fn test_cp_issue199() {
 
     fs::remove_dir_all(test_dir).unwrap();
 }
+
+#[test]
+fn test_cp_r_hierarchy() {
+    let test_dir = &format!("{}/test_cp_r_hierarchy", env!("CARGO_TARGET_TMPDIR"));
+    let src = &format!("{test_dir}/src");
+    let sub = &format!("{test_dir}/src/sub");
+    let file = &format!("{test_dir}/src/sub/file");
+    let slink = &format!("{test_dir}/src/slink");
+    let dangle = &format!("{test_dir}/src/dangle");
+    let fifo = &format!("{test_dir}/src/fifo");
+    let physical = &format!("{test_dir}/physical");
+    let logical = &format!("{test_dir}/logical");
+
+    fs::create_dir(test_dir).unwrap();
+    fs::create_dir_all(sub).unwrap();
+    fs::write(file, "abc\n").unwrap();
+    unix::fs::symlink("sub/file", slink).unwrap();
+    unix::fs::symlink("no-such-file", dangle).unwrap();
+    unsafe {
+        let fifo_cstr = CString::new(fifo.as_bytes()).unwrap();
+        let ret = libc::mkfifo(fifo_cstr.as_ptr(), 0o640);
+        if ret != 0 {
+            panic!("{}", io::Error::last_os_error());
+        }
+    }
+
+    // Symlinks are copied as symlinks by default
+    cp_test(&["-R", src, physical], "", "", 0);
+    assert_eq!(
+        fs::read_to_string(format!("{physical}/sub/file")).unwrap(),
+        "abc\n"
+    );
+    for name in ["slink", "dangle"] {
+        let path = format!("{physical}/{name}");
+        assert!(fs::symlink_metadata(&path).unwrap().is_symlink());
+    }
+    assert_eq!(
+        fs::read_link(format!("{physical}/slink")).unwrap(),
+        Path::new("sub/file")
+    );
+    let fifo_md = fs::symlink_metadata(format!("{physical}/fifo")).unwrap();
+    assert!(fifo_md.file_type().is_fifo());
+    assert_eq!(fifo_md.mode() & 0o777, 0o640);
+
+    // -L copies the files they point to, and dangling symlinks can't be copied
+    cp_test(
+        &["-RL", src, logical],
+        "",
+        &format!("cp: cannot stat '{dangle}': No such file or directory\n"),
+        1,
+    );
+    let slink_md = fs::symlink_metadata(format!("{logical}/slink")).unwrap();
+    assert!(slink_md.is_file());
+    assert!(fs::symlink_metadata(format!("{logical}/dangle")).is_err());
+
+    // Copying again onto the existing hierarchy replaces the files
+    fs::write(file, "def\n").unwrap();
+    cp_test(&["-R", &format!("{src}/."), physical], "", "", 0);
+    assert_eq!(
+        fs::read_to_string(format!("{physical}/sub/file")).unwrap(),
+        "def\n"
+    );
+
+    // Without -R, symlinks are followed
+    let copied = &format!("{test_dir}/copied");
+    cp_test(&[slink, copied], "", "", 0);
+    assert!(fs::symlink_metadata(copied).unwrap().is_file());
+
+    fs::remove_dir_all(test_dir).unwrap();
+}
+
+#[test]
+fn test_cp_existing_target() {
+    let test_dir = &format!("{}/test_cp_existing_target", env!("CARGO_TARGET_TMPDIR"));
+    let file = &format!("{test_dir}/file");
+    let other = &format!("{test_dir}/other");
+    let target = &format!("{test_dir}/target");
+    let target_file = &format!("{test_dir}/target/file");
+
+    fs::create_dir(test_dir).unwrap();
+    fs::write(file, "abc\n").unwrap();
+    fs::write(other, "def\n").unwrap();
+
+    // Multiple sources need a directory to copy into
+    cp_test(
+        &[file, other, target],
+        "",
+        &format!("cp: target '{target}' is not a directory\n"),
+        1,
+    );
+
+    fs::create_dir_all(target_file).unwrap();
+    cp_test(
+        &[file, target],
+        "",
+        &format!("cp: cannot overwrite directory '{target_file}' with non-directory '{file}'\n"),
+        1,
+    );
+    assert!(Path::new(target_file).is_dir());
+
+    fs::remove_dir_all(test_dir).unwrap();
+}
+
+#[test]
+#[cfg_attr(
+    not(all(
+        target_os = "linux",
+        feature = "posixutils_test_all",
+        feature = "requires_root"
+    )),
+    ignore
+)]
+fn test_cp_r_device() {
+    let test_dir = &format!("{}/test_cp_r_device", env!("CARGO_TARGET_TMPDIR"));
+    let src = &format!("{test_dir}/src");
+    let dst = &format!("{test_dir}/dst");
+
+    fs::create_dir(test_dir).unwrap();
+    fs::create_dir(src).unwrap();
+    unsafe {
+        let null_cstr = CString::new(format!("{src}/null")).unwrap();
+        let ret = libc::mknod(
+            null_cstr.as_ptr(),
+            libc::S_IFCHR | 0o666,
+            libc::makedev(1, 3),
+        );
+        if ret != 0 {
+            panic!("{}", io::Error::last_os_error());
+        }
+    }
+
+    cp_test(&["-R", src, dst], "", "", 0);
+
+    let md = fs::symlink_metadata(format!("{dst}/null")).unwrap();
+    assert!(md.file_type().is_char_device());
+    assert_eq!(
+        md.rdev(),
+        fs::metadata(format!("{src}/null")).unwrap().rdev()
+    );
+
+    fs::remove_dir_all(test_dir).unwrap();
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_cp_preserve_attrs() {
+    let test_dir = &format!("{}/test_cp_preserve_attrs", env!("CARGO_TARGET_TMPDIR"));
+    let src = &format!("{test_dir}/src");
+    let times_copy = &format!("{test_dir}/times_copy");
+    let posix_copy = &format!("{test_dir}/posix_copy");
+    let xattr_copy = &format!("{test_dir}/xattr_copy");
+
+    fs::create_dir(test_dir).unwrap();
+    fs::write(src, "abc\n").unwrap();
+
+    // A modification time with nanoseconds
+    let src_cstr = CString::new(src.as_bytes()).unwrap();
+    unsafe {
+        let times = [
+            libc::timespec {
+                tv_sec: 1_000_000_000,
+                tv_nsec: 123_456_789,
+            },
+            libc::timespec {
+                tv_sec: 1_000_000_000,
+                tv_nsec: 987_654_321,
+            },
+        ];
+        let ret = libc::utimensat(libc::AT_FDCWD, src_cstr.as_ptr(), times.as_ptr(), 0);
+        if ret != 0 {
+            panic!("{}", io::Error::last_os_error());
+        }
+    }
+
+    // Not every file system supports user extended attributes
+    let name = CString::new("user.posixutils").unwrap();
+    let has_xattrs = unsafe {
+        libc::setxattr(
+            src_cstr.as_ptr(),
+            name.as_ptr(),
+            b"value".as_ptr() as *const libc::c_void,
+            5,
+            0,
+        ) == 0
+    };
+
+    let get_xattr = |path: &str| -> Option<Vec<u8>> {
+        let path = CString::new(path.as_bytes()).unwrap();
+        let mut buf = [0u8; 64];
+        let size = unsafe {
+            libc::getxattr(
+                path.as_ptr(),
+                name.as_ptr(),
+                buf.as_mut_ptr() as *mut libc::c_void,
+                buf.len(),
+            )
+        };
+        (size >= 0).then(|| buf[..size as usize].to_vec())
+    };
+
+    cp_test(&["--preserve=timestamps", src, times_copy], "", "", 0);
+    let md = fs::metadata(times_copy).unwrap();
+    assert_eq!(md.mtime(), 1_000_000_000);
+    assert_eq!(md.mtime_nsec(), 987_654_321);
+
+    // -p does not include extended attributes
+    cp_test(&["-p", src, posix_copy], "", "", 0);
+    assert_eq!(fs::metadata(posix_copy).unwrap().mtime_nsec(), 987_654_321);
+    if has_xattrs {
+        assert_eq!(get_xattr(posix_copy), None);
+    }
+
+    if has_xattrs {
+        cp_test(&["--preserve=xattr", src, xattr_copy], "", "", 0);
+        assert_eq!(get_xattr(xattr_copy).as_deref(), Some(&b"value"[..]));
+    }
+
+    fs::remove_dir_all(test_dir).unwrap();
+}
+
+#[test]
+fn test_cp_sparse() {
+    let test_dir = &format!("{}/test_cp_sparse", env!("CARGO_TARGET_TMPDIR"));
+    let sparse = &format!("{test_dir}/sparse");
+    let zeros = &format!("{test_dir}/zeros");
+    let sparse_auto = &format!("{test_dir}/sparse_auto");
+    let sparse_never = &format!("{test_dir}/sparse_never");
+    let zeros_auto = &format!("{test_dir}/zeros_auto");
+    let zeros_always = &format!("{test_dir}/zeros_always");
+
+    const SIZE: u64 = 16 * 1024 * 1024;
+
+    fs::create_dir(test_dir).unwrap();
+    {
+        let file = fs::File::create(sparse).unwrap();
+        file.set_len(SIZE).unwrap();
+        unix::fs::FileExt::write_all_at(&file, b"data\n", SIZE / 2).unwrap();
+    }
+    {
+        let mut data = vec![0u8; SIZE as usize];
+        data.extend_from_slice(b"data\n");
+        fs::write(zeros, data).unwrap();
+    }
+
+    cp_test(&[sparse, sparse_auto], "", "", 0);
+    cp_test(&["--sparse=never", sparse, sparse_never], "", "", 0);
+    cp_test(&[zeros, zeros_auto], "", "", 0);
+    cp_test(&["--sparse=always", zeros, zeros_always], "", "", 0);
+
+    for (source, copy) in [
+        (sparse, sparse_auto),
+        (sparse, sparse_never),
+        (zeros, zeros_auto),
+        (zeros, zeros_always),
+    ] {
+        assert_eq!(fs::read(source).unwrap(), fs::read(copy).unwrap());
+    }
+
+    // Allocated bytes, skipping the checks if the file system has no holes
+    let allocated = |path: &str| fs::metadata(path).unwrap().blocks() * 512;
+    if allocated(sparse) < SIZE {
+        assert!(allocated(sparse_auto) < SIZE);
+        assert!(allocated(sparse_never) >= SIZE);
+        assert!(allocated(zeros_auto) >= SIZE);
+        assert!(allocated(zeros_always) < SIZE);
+    }
+
+    fs::remove_dir_all(test_dir).unwrap();
+}
+
+#[test]
+fn test_cp_clobber_control() {
+    let test_dir = &format!("{}/test_cp_clobber_control", env!("CARGO_TARGET_TMPDIR"));
+    let a = &format!("{test_dir}/a");
+    let b = &format!("{test_dir}/b");
+    let c = &format!("{test_dir}/c");
+
+    fs::create_dir(test_dir).unwrap();
+    fs::write(a, "a\n").unwrap();
+    fs::write(b, "b\n").unwrap();
+
+    // No prompt and no error, the target is left alone
+    cp_test(&["-n", a, b], "", "", 0);
+    assert_eq!(fs::read_to_string(b).unwrap(), "b\n");
+
+    // The last of -i and -n wins; cp -n doesn't read its input, which may be closed
+    // before anything is written to it
+    cp_test(&["-i", "-n", a, b], "", "", 0);
+    assert_eq!(fs::read_to_string(b).unwrap(), "b\n");
+    cp_test_with_stdin(
+        &["-n", "-i", a, b],
+        "y\n",
+        "",
+        &format!("cp: overwrite '{b}'? "),
+        0,
+    );
+    assert_eq!(fs::read_to_string(b).unwrap(), "a\n");
+
+    // End of input is not an affirmative response
+    fs::write(b, "b\n").unwrap();
+    cp_test_with_stdin(&["-i", a, b], "", "", &format!("cp: overwrite '{b}'? "), 0);
+    assert_eq!(fs::read_to_string(b).unwrap(), "b\n");
+
+    // -f replaces a target it cannot open without asking
+    fs::write(c, "c\n").unwrap();
+    fs::set_permissions(c, fs::Permissions::from_mode(0o444)).unwrap();
+    cp_test(&["-f", a, c], "", "", 0);
+    assert_eq!(fs::read_to_string(c).unwrap(), "a\n");
+
+    fs::remove_dir_all(test_dir).unwrap();
+}
+
+#[test]
+fn test_cp_progress() {
+    let test_dir = &format!("{}/test_cp_progress", env!("CARGO_TARGET_TMPDIR"));
+    let a = &format!("{test_dir}/a");
+    let b = &format!("{test_dir}/b");
+
+    fs::create_dir(test_dir).unwrap();
+    fs::write(a, vec![b'a'; 100_000]).unwrap();
+
+    // Standard error is not a terminal, so the final report is a plain line
+    run_test_with_checker(
+        TestPlan {
+            cmd: String::from("cp"),
+            args: vec!["--progress".to_string(), a.clone(), b.clone()],
+            stdin_data: String::new(),
+            expected_out: String::new(),
+            expected_err: String::new(),
+            expected_exit_code: 0,
+        },
+        |_, output| {
+            assert!(output.status.success());
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            assert!(
+                stderr.starts_with("cp: 100000 bytes (97.7 KiB) copied, 1 file, "),
+                "{stderr}"
+            );
+            assert!(stderr.ends_with(", 100%, ETA 0:00\n"), "{stderr}");
+            assert!(!stderr.contains('\r'), "{stderr}");
+        },
+    );
+    assert_eq!(fs::read(a).unwrap(), fs::read(b).unwrap());
+
+    fs::remove_dir_all(test_dir).unwrap();
+}
+
+#[test]
+fn test_cp_reflink() {
+    let test_dir = &format!("{}/test_cp_reflink", env!("CARGO_TARGET_TMPDIR"));
+    let source = &format!("{test_dir}/source");
+    let never = &format!("{test_dir}/never");
+    let auto = &format!("{test_dir}/auto");
+    let always = &format!("{test_dir}/always");
+
+    fs::create_dir(test_dir).unwrap();
+    let data: Vec<u8> = (0..1024 * 1024).map(|i| (i % 251) as u8).collect();
+    fs::write(source, &data).unwrap();
+
+    cp_test(&["--reflink=never", source, never], "", "", 0);
+    cp_test(&["--reflink=auto", source, auto], "", "", 0);
+    assert_eq!(fs::read(never).unwrap(), data);
+    assert_eq!(fs::read(auto).unwrap(), data);
+
+    // Whether cloning works depends on the file system
+    run_test_with_checker(
+        TestPlan {
+            cmd: String::from("cp"),
+            args: vec!["--reflink".to_string(), source.clone(), always.clone()],
+            stdin_data: String::new(),
+            expected_out: String::new(),
+            expected_err: String::new(),
+            expected_exit_code: 0,
+        },
+        |_, output| {
+            if output.status.success() {
+                assert_eq!(fs::read(always).unwrap(), data);
+            } else {
+                assert_eq!(output.status.code(), Some(1));
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                assert!(stderr.contains("failed to clone"), "{stderr}");
+            }
+        },
+    );
+
+    fs::remove_dir_all(test_dir).unwrap();
+}
+
+#[test]
+fn test_cp_deref_cycle() {
+    let test_dir = &format!("{}/test_cp_deref_cycle", env!("CARGO_TARGET_TMPDIR"));
+    let src = &format!("{test_dir}/src");
+    let dst = &format!("{test_dir}/dst");
+    let src_sub_up = &format!("{test_dir}/src/sub/up");
+
+    fs::create_dir_all(format!("{src}/sub")).unwrap();
+    fs::write(format!("{src}/sub/file"), "data").unwrap();
+    unix::fs::symlink("..", src_sub_up).unwrap();
+
+    // The cycle is skipped but the rest is still copied
+    cp_test(
+        &["-RL", src, dst],
+        "",
+        &format!("cp: cannot copy cyclic symbolic link '{src_sub_up}'\n"),
+        1,
+    );
+    assert_eq!(
+        fs::read_to_string(format!("{dst}/sub/file")).unwrap(),
+        "data"
+    );
+    assert!(!Path::new(&format!("{dst}/sub/up")).exists());
+
+    fs::remove_dir_all(test_dir).unwrap();
+}
+
+#[test]
+fn test_cp_deref_dangling() {
+    let test_dir = &format!("{}/test_cp_deref_dangling", env!("CARGO_TARGET_TMPDIR"));
+    let src = &format!("{test_dir}/src");
+    let dst = &format!("{test_dir}/dst");
+    let dangle = &format!("{src}/dangle");
+
+    fs::create_dir_all(src).unwrap();
+    fs::write(format!("{src}/file"), "data").unwrap();
+    unix::fs::symlink("no-such", dangle).unwrap();
+
+    // -L dereferences every symlink, so a dangling one can't be copied, but the rest is
+    cp_test(
+        &["-RL", src, dst],
+        "",
+        &format!("cp: cannot stat '{dangle}': No such file or directory\n"),
+        1,
+    );
+    assert_eq!(fs::read_to_string(format!("{dst}/file")).unwrap(), "data");
+    assert!(fs::symlink_metadata(format!("{dst}/dangle")).is_err());
+
+    // So does -H for an operand
+    let dst_dangle = &format!("{test_dir}/dst_dangle");
+    cp_test(
+        &["-RH", dangle, dst_dangle],
+        "",
+        &format!("cp: cannot stat '{dangle}': No such file or directory\n"),
+        1,
+    );
+    assert!(fs::symlink_metadata(dst_dangle).is_err());
+
+    // Without -L, the symlink itself is copied
+    cp_test(&["-R", dangle, dst_dangle], "", "", 0);
+    assert_eq!(fs::read_link(dst_dangle).unwrap(), Path::new("no-such"));
+
+    fs::remove_dir_all(test_dir).unwrap();
+}
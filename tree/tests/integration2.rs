@@ -60,6 +60,19 @@ fn rm_test(args: &[&str], expected_output: &str, expected_error: &str, expected_
     });
 }
 
+fn mkdir_test(args: &[&str], expected_error: &str, expected_exit_code: i32) {
+    let str_args: Vec<String> = args.iter().map(|s| String::from(*s)).collect();
+
+    run_test(TestPlan {
+        cmd: String::from("mkdir"),
+        args: str_args,
+        stdin_data: String::new(),
+        expected_out: String::new(),
+        expected_err: String::from(expected_error),
+        expected_exit_code,
+    });
+}
+
 // Port of coreutils/tests/cp/existing-perm-dir.sh
 #[test]
 fn test_cp_existing_perm_dir() {
@@ -195,3 +208,69 @@ fn test_rm_deep_1() {
     umask_setter.umask(original_umask);
     fs::remove_dir_all(test_dir).unwrap();
 }
+
+fn mode_of(path: &str) -> u32 {
+    fs::metadata(path).unwrap().mode() & 0o7777
+}
+
+#[test]
+fn test_mkdir_mode_umask() {
+    let test_dir = &format!("{}/test_mkdir_mode_umask", env!("CARGO_TARGET_TMPDIR"));
+    fs::create_dir(test_dir).unwrap();
+
+    let umask_setter = UMASK_SETTER.lock().unwrap();
+    let original_umask = umask_setter.umask(0o022);
+
+    for (mode, expected) in [
+        // The umask doesn't apply to the mode given explicitly
+        ("777", 0o777),
+        ("u=rwx,g=rx,o=", 0o750),
+        ("go-w", 0o755),
+        // ...except to clauses without a who-list
+        ("-w", 0o577),
+        ("+t", 0o1777),
+        ("=rx", 0o555),
+    ] {
+        let d = &format!("{test_dir}/{expected:o}");
+        mkdir_test(&["-m", mode, d], "", 0);
+        assert_eq!(mode_of(d), expected, "{mode}");
+    }
+
+    // Without -m, the default mode is filtered through the umask
+    let d = &format!("{test_dir}/default");
+    mkdir_test(&[d], "", 0);
+    assert_eq!(mode_of(d), 0o755);
+
+    umask_setter.umask(original_umask);
+    fs::remove_dir_all(test_dir).unwrap();
+}
+
+#[test]
+fn test_mkdir_parents_mode() {
+    let test_dir = &format!("{}/test_mkdir_parents_mode", env!("CARGO_TARGET_TMPDIR"));
+    let a = &format!("{test_dir}/a");
+    let a_b = &format!("{test_dir}/a/b");
+    let a_b_c = &format!("{test_dir}/a/b/c");
+    let a_b_c_d = &format!("{test_dir}/a/b/c/d");
+    fs::create_dir(test_dir).unwrap();
+
+    let umask_setter = UMASK_SETTER.lock().unwrap();
+    let original_umask = umask_setter.umask(0o277);
+
+    // Intermediate directories are always writable and searchable by the owner
+    mkdir_test(&["-p", a_b_c], "", 0);
+    assert_eq!(mode_of(a), 0o700);
+    assert_eq!(mode_of(a_b), 0o700);
+    assert_eq!(mode_of(a_b_c), 0o500);
+
+    // -m only applies to the last component, and not to existing directories
+    fs::set_permissions(a_b_c, fs::Permissions::from_mode(0o700)).unwrap();
+    mkdir_test(&["-p", "-m", "750", a_b_c_d], "", 0);
+    assert_eq!(mode_of(a_b_c), 0o700);
+    assert_eq!(mode_of(a_b_c_d), 0o750);
+    mkdir_test(&["-p", "-m", "700", a_b_c_d], "", 0);
+    assert_eq!(mode_of(a_b_c_d), 0o750);
+
+    umask_setter.umask(original_umask);
+    fs::remove_dir_all(test_dir).unwrap();
+}
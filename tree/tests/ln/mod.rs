@@ -0,0 +1,201 @@
+//
+// Copyright (c) 2024 Jeff Garzik
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+use plib::testing::{run_test, TestPlan};
+use std::fs;
+use std::os::unix::fs::{symlink, MetadataExt};
+use std::path::Path;
+use tempfile::tempdir;
+
+fn ln_test(args: &[&str], expected_err: &str, expected_exit_code: i32) {
+    run_test(TestPlan {
+        cmd: String::from("ln"),
+        args: args.iter().map(|s| s.to_string()).collect(),
+        stdin_data: String::new(),
+        expected_out: String::new(),
+        expected_err: String::from(expected_err),
+        expected_exit_code,
+    });
+}
+
+fn read_link(path: &str) -> String {
+    fs::read_link(path).unwrap().to_str().unwrap().to_string()
+}
+
+#[test]
+fn test_ln_relative() {
+    let dir = tempdir().unwrap();
+    let dir = fs::canonicalize(dir.path()).unwrap();
+    let dir = dir.to_str().unwrap();
+
+    let file = &format!("{dir}/a/b/file");
+    let file2 = &format!("{dir}/a/b/file2");
+    fs::create_dir_all(format!("{dir}/a/b")).unwrap();
+    fs::create_dir_all(format!("{dir}/c/d")).unwrap();
+    fs::write(file, "data").unwrap();
+    fs::write(file2, "data2").unwrap();
+
+    let link = &format!("{dir}/c/d/link");
+    ln_test(&["-s", "-r", file, link], "", 0);
+    assert_eq!(read_link(link), "../../a/b/file");
+    assert_eq!(fs::read_to_string(link).unwrap(), "data");
+
+    // Linking into a directory
+    ln_test(&["-sr", file, file2, &format!("{dir}/a")], "", 0);
+    assert_eq!(read_link(&format!("{dir}/a/file")), "b/file");
+    assert_eq!(read_link(&format!("{dir}/a/file2")), "b/file2");
+
+    // A link next to its target
+    let sibling = &format!("{dir}/a/b/sibling");
+    ln_test(&["-sr", file, sibling], "", 0);
+    assert_eq!(read_link(sibling), "file");
+
+    // Symlinks in the directory of the link are resolved
+    symlink(format!("{dir}/c/d"), format!("{dir}/s")).unwrap();
+    ln_test(&["-sr", file, &format!("{dir}/s/link2")], "", 0);
+    assert_eq!(read_link(&format!("{dir}/c/d/link2")), "../../a/b/file");
+
+    // ... but symlinks in the target are kept as given
+    symlink(format!("{dir}/a"), format!("{dir}/alias")).unwrap();
+    let link3 = &format!("{dir}/c/link3");
+    ln_test(&["-sr", &format!("{dir}/alias/b/../b/file"), link3], "", 0);
+    assert_eq!(read_link(link3), "../alias/b/file");
+    assert_eq!(fs::read_to_string(link3).unwrap(), "data");
+}
+
+#[test]
+fn test_ln_relative_requires_symbolic() {
+    let dir = tempdir().unwrap();
+    let file = dir.path().join("file");
+    let link = dir.path().join("link");
+    fs::write(&file, "").unwrap();
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_ln"))
+        .args(["-r", file.to_str().unwrap(), link.to_str().unwrap()])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(!Path::new(&link).exists());
+}
+
+#[test]
+fn test_ln_logical_physical() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path().to_str().unwrap();
+
+    let file = &format!("{dir}/file");
+    let sym = &format!("{dir}/sym");
+    fs::write(file, "data").unwrap();
+    symlink(file, sym).unwrap();
+    let file_ino = fs::metadata(file).unwrap().ino();
+
+    // -P links to the symlink itself, which is also the default
+    for (args, link) in [
+        (vec!["-P"], "p"),
+        (vec![], "default"),
+        (vec!["-L", "-P"], "lp"),
+    ] {
+        let link = &format!("{dir}/{link}");
+        let mut args = args.clone();
+        args.extend([sym.as_str(), link.as_str()]);
+        ln_test(&args, "", 0);
+        assert!(fs::symlink_metadata(link).unwrap().is_symlink());
+        assert_eq!(read_link(link), *file);
+    }
+
+    // -L links to the file the symlink refers to
+    for (args, link) in [(vec!["-L"], "l"), (vec!["-P", "-L"], "pl")] {
+        let link = &format!("{dir}/{link}");
+        let mut args = args.clone();
+        args.extend([sym.as_str(), link.as_str()]);
+        ln_test(&args, "", 0);
+        let md = fs::symlink_metadata(link).unwrap();
+        assert!(md.is_file());
+        assert_eq!(md.ino(), file_ino);
+    }
+}
+
+#[test]
+fn test_ln_force() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path().to_str().unwrap();
+
+    let file = &format!("{dir}/file");
+    let other = &format!("{dir}/other");
+    let dest = &format!("{dir}/dest");
+    fs::write(file, "data").unwrap();
+    fs::write(other, "other").unwrap();
+    fs::write(dest, "dest").unwrap();
+
+    // Without -f an existing destination is an error
+    ln_test(
+        &[file, dest],
+        &format!("{file} -> {dest}: File exists (os error 17)\n"),
+        1,
+    );
+    assert_eq!(fs::read_to_string(dest).unwrap(), "dest");
+
+    ln_test(&["-f", file, dest], "", 0);
+    assert_eq!(
+        fs::metadata(dest).unwrap().ino(),
+        fs::metadata(file).unwrap().ino()
+    );
+
+    // Replacing a hard link with a symlink and a symlink with another one
+    ln_test(&["-sf", other, dest], "", 0);
+    assert_eq!(read_link(dest), *other);
+    ln_test(&["-sf", file, dest], "", 0);
+    assert_eq!(read_link(dest), *file);
+
+    // A file is not removed to link it to itself
+    let hard = &format!("{dir}/hard");
+    fs::hard_link(file, hard).unwrap();
+    for (src, dst) in [(file, file), (file, hard)] {
+        ln_test(
+            &["-f", src, dst],
+            &format!("{src} -> {dst}: source and destination are the same file\n"),
+            1,
+        );
+    }
+    assert_eq!(fs::read_to_string(file).unwrap(), "data");
+    assert_eq!(fs::read_to_string(hard).unwrap(), "data");
+
+    // A directory is not replaced
+    let subdir = &format!("{dir}/subdir");
+    let subdir_file = &format!("{dir}/subdir/file");
+    fs::create_dir(subdir).unwrap();
+    fs::create_dir(subdir_file).unwrap();
+    ln_test(
+        &["-f", file, subdir],
+        &format!("{file} -> {subdir}: cannot overwrite directory\n"),
+        1,
+    );
+    assert!(Path::new(subdir_file).is_dir());
+}
+
+#[test]
+fn test_ln_into_directory() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path().to_str().unwrap();
+
+    let file = &format!("{dir}/file");
+    let subdir = &format!("{dir}/subdir");
+    fs::write(file, "data").unwrap();
+    fs::create_dir(subdir).unwrap();
+
+    // A single source is linked into an existing directory
+    ln_test(&[file, subdir], "", 0);
+    assert_eq!(
+        fs::read_to_string(format!("{subdir}/file")).unwrap(),
+        "data"
+    );
+
+    ln_test(&["-sf", file, subdir], "", 0);
+    assert_eq!(read_link(&format!("{subdir}/file")), *file);
+}
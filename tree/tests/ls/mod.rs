@@ -12,7 +12,7 @@ use regex::Regex;
 use std::ffi::CString;
 use std::fs;
 use std::io::{self, Write};
-use std::os::unix::fs::MetadataExt;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
 use std::path::Path;
 use std::thread;
 use std::time::Duration;
@@ -292,10 +292,34 @@ fn test_ls_infloop() {
     ls_test(
         &["-RL", loop_sub],
         &format!("{loop_sub}:\nsub\n"),
-        &format!("ls: {loop_sub}: not listing already-listed directory\n"),
+        &format!("ls: {loop_sub}/sub: not listing already-listed directory\n"),
         2,
     );
 
+    // The directory being entered is reported, also below the operand
+    let a = &format!("{test_dir}/a");
+    let a_b = &format!("{test_dir}/a/b");
+    fs::create_dir_all(a_b).unwrap();
+    std::os::unix::fs::symlink("..", format!("{a_b}/up")).unwrap();
+    ls_test(
+        &["-RL", a],
+        &format!("{a}:\nb\n\n{a_b}:\nup\n"),
+        &format!("ls: {a_b}/up: not listing already-listed directory\n"),
+        2,
+    );
+
+    // A directory reached twice without a loop is listed both times
+    let c = &format!("{test_dir}/c");
+    fs::create_dir_all(format!("{c}/d")).unwrap();
+    std::os::unix::fs::symlink("d", format!("{c}/l1")).unwrap();
+    std::os::unix::fs::symlink("d", format!("{c}/l2")).unwrap();
+    ls_test(
+        &["-RL", c],
+        &format!("{c}:\nd\nl1\nl2\n\n{c}/d:\n\n{c}/l1:\n\n{c}/l2:\n"),
+        "",
+        0,
+    );
+
     fs::remove_dir_all(test_dir).unwrap();
 }
 
@@ -663,3 +687,420 @@ fn test_ls_time() {
 
     fs::remove_dir_all(test_dir).unwrap();
 }
+
+#[test]
+fn test_ls_long_format() {
+    std::env::set_var("TZ", "UTC0");
+
+    let test_dir = &format!("{}/test_ls_long_format", env!("CARGO_TARGET_TMPDIR"));
+    let dir = &format!("{test_dir}/dir");
+    let file = &format!("{test_dir}/dir/file");
+    let link = &format!("{test_dir}/link");
+    let socket = &format!("{test_dir}/socket");
+
+    fs::create_dir(test_dir).unwrap();
+    fs::create_dir(dir).unwrap();
+    fs::write(file, b"hello").unwrap();
+    std::os::unix::fs::symlink("dir", link).unwrap();
+    let _listener = std::os::unix::net::UnixListener::bind(socket).unwrap();
+
+    unsafe {
+        let file_cstr = CString::new(file.as_bytes()).unwrap();
+        let ret = libc::chmod(file_cstr.as_ptr(), 0o4754);
+        if ret != 0 {
+            panic!("{}", io::Error::last_os_error());
+        }
+    }
+    change_file_time(file, TimeToChange::Modified("2001-02-03 04:05:06"));
+
+    let uid = fs::metadata(file).unwrap().uid();
+    let gid = fs::metadata(file).unwrap().gid();
+
+    // The day of the month is padded with a space rather than a zero
+    ls_test(
+        &["-n", file],
+        &format!("-rwsr-xr-- 1 {uid} {gid} 5 Feb  3  2001 {file}\n"),
+        "",
+        0,
+    );
+    ls_test(
+        &["-go", file],
+        &format!("-rwsr-xr-- 1 5 Feb  3  2001 {file}\n"),
+        "",
+        0,
+    );
+
+    // A symbolic link operand is not followed with -l
+    ls_test_with_checker(&["-l", link], |_, output| {
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.starts_with("lrwxrwxrwx 1 "));
+        assert!(stdout.ends_with(&format!(" {link} -> dir\n")));
+        assert_eq!(output.status.code(), Some(0));
+    });
+
+    // ... but it is with -H
+    ls_test_with_checker(&["-l", "-H", "-n", link], |_, output| {
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.starts_with("total "));
+        assert!(stdout.ends_with(&format!("\n-rwsr-xr-- 1 {uid} {gid} 5 Feb  3  2001 file\n")));
+        assert_eq!(output.status.code(), Some(0));
+    });
+
+    // -d lists directories themselves
+    ls_test(&["-d", dir, link], &format!("{dir}\n{link}\n"), "", 0);
+
+    ls_test_with_checker(&["-l", socket], |_, output| {
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.starts_with('s'));
+        assert_eq!(output.status.code(), Some(0));
+    });
+
+    fs::remove_dir_all(test_dir).unwrap();
+}
+
+#[test]
+fn test_ls_time_style() {
+    std::env::set_var("TZ", "UTC0");
+
+    let test_dir = &format!("{}/test_ls_time_style", env!("CARGO_TARGET_TMPDIR"));
+    let file = &format!("{test_dir}/file");
+
+    fs::create_dir(test_dir).unwrap();
+    fs::File::create(file).unwrap();
+    fs::set_permissions(file, fs::Permissions::from_mode(0o644)).unwrap();
+    change_file_time(file, TimeToChange::Modified("2001-02-03 04:05:06"));
+
+    let uid = fs::metadata(file).unwrap().uid();
+    let gid = fs::metadata(file).unwrap().gid();
+    let style_test = |style: &str, time: &str| {
+        ls_test(
+            &["-n", &format!("--time-style={style}"), file],
+            &format!("-rw-r--r-- 1 {uid} {gid} 0 {time} {file}\n"),
+            "",
+            0,
+        );
+    };
+
+    style_test("locale", "Feb  3  2001");
+    style_test("full-iso", "2001-02-03 04:05:06.000000000 +0000");
+    style_test("long-iso", "2001-02-03 04:05");
+    style_test("iso", "2001-02-03 ");
+    style_test("+%Y/%m/%d %%N", "2001/02/03 %N");
+    style_test("+%H:%M\n[%Y]", "[2001]");
+
+    // -u shows a recent access time instead of the year
+    let now = chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    change_file_time(file, TimeToChange::Accessed(&now));
+    ls_test_with_checker(&["-lu", "--time-style=+%H:%M\n[%Y]", file], |_, output| {
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(Regex::new(r" \d\d:\d\d ").unwrap().is_match(&stdout));
+        assert_eq!(output.status.code(), Some(0));
+    });
+
+    ls_test_with_checker(&["-l", "--time-style=bogus", file], |_, output| {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("invalid time style format 'bogus'"));
+        assert_eq!(output.status.code(), Some(2));
+    });
+
+    fs::remove_dir_all(test_dir).unwrap();
+}
+
+// `ls_test` but with `LS_COLORS` set for the child process. The terminal is
+// made wide enough to fit the test paths in columns.
+fn ls_colors_test(ls_colors: &str, args: &[&str], expected_out: &str) {
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_ls"))
+        .env("LS_COLORS", ls_colors)
+        .env("COLUMNS", "250")
+        .args(args)
+        .output()
+        .unwrap();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout, expected_out);
+
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn test_ls_color() {
+    let test_dir = &format!("{}/test_ls_color", env!("CARGO_TARGET_TMPDIR"));
+    let dir = &format!("{test_dir}/dir");
+    let exe = &format!("{test_dir}/exe");
+    let tarball = &format!("{test_dir}/file.tar");
+    let plain = &format!("{test_dir}/plain");
+    let link = &format!("{test_dir}/link");
+    let dangle = &format!("{test_dir}/dangle");
+
+    fs::create_dir(test_dir).unwrap();
+    fs::create_dir(dir).unwrap();
+    fs::File::create(exe).unwrap();
+    fs::File::create(tarball).unwrap();
+    fs::File::create(plain).unwrap();
+    std::os::unix::fs::symlink("plain", link).unwrap();
+    std::os::unix::fs::symlink("nowhere", dangle).unwrap();
+
+    unsafe {
+        let exe_cstr = CString::new(exe.as_bytes()).unwrap();
+        let ret = libc::chmod(exe_cstr.as_ptr(), 0o755);
+        if ret != 0 {
+            panic!("{}", io::Error::last_os_error());
+        }
+    }
+
+    let ls_colors = "di=01;34:ln=01;36:or=31:ex=01;32:*.tar=01;31";
+    ls_colors_test(
+        ls_colors,
+        &["--color=always", "-1", test_dir],
+        "\x1b[31mdangle\x1b[0m\n\
+         \x1b[01;34mdir\x1b[0m\n\
+         \x1b[01;32mexe\x1b[0m\n\
+         \x1b[01;31mfile.tar\x1b[0m\n\
+         \x1b[01;36mlink\x1b[0m\n\
+         plain\n",
+    );
+
+    // The -F indicator is not colored
+    ls_colors_test(
+        ls_colors,
+        &["--color", "-d", "-F", dir],
+        &format!("\x1b[01;34m{dir}\x1b[0m/\n"),
+    );
+
+    // ln=target colors links as the file they point to, an empty value
+    // disables the color of that type
+    ls_colors_test(
+        "ln=target:di=:ex=01;32",
+        &["--color", "-d", dir, link],
+        &format!("{dir}\n{link}\n"),
+    );
+
+    // Not a terminal
+    ls_colors_test(ls_colors, &["--color=auto", "-d", dir], &format!("{dir}\n"));
+    ls_colors_test(
+        ls_colors,
+        &["--color=never", "-d", dir],
+        &format!("{dir}\n"),
+    );
+
+    // Padding in columns is not affected by the escape sequences
+    ls_colors_test(
+        ls_colors,
+        &["--color", "-d", "-x", dir, plain],
+        &format!("\x1b[01;34m{dir}\x1b[0m  {plain}\n"),
+    );
+
+    fs::remove_dir_all(test_dir).unwrap();
+}
+
+#[test]
+fn test_ls_multibyte_columns() {
+    let test_dir = &format!("{}/test_ls_multibyte_columns", env!("CARGO_TARGET_TMPDIR"));
+
+    fs::create_dir(test_dir).unwrap();
+    for name in ["a", "bb", "日本"] {
+        fs::File::create(format!("{test_dir}/{name}")).unwrap();
+    }
+
+    let columns_test = |columns: &str, args: &[&str], expected_out: &str| {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_ls"))
+            .current_dir(test_dir)
+            .env("COLUMNS", columns)
+            .args(args)
+            .output()
+            .unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), expected_out);
+        assert_eq!(output.status.code(), Some(0));
+    };
+
+    // "日本" takes up 4 columns on the terminal, so a single row needs 11
+    columns_test("11", &["-x"], "a  bb  日本\n");
+    columns_test("10", &["-x"], "a     bb\n日本  \n");
+    columns_test("10", &["-C"], "a   日本\nbb  \n");
+
+    // Cannot fit in any number of columns
+    columns_test("3", &["-C"], "a\nbb\n日本\n");
+
+    // Not a terminal, so a single column is the default
+    columns_test("80", &[], "a\nbb\n日本\n");
+
+    fs::remove_dir_all(test_dir).unwrap();
+}
+
+#[test]
+fn test_ls_sort_operands() {
+    std::env::set_var("TZ", "UTC0");
+
+    let test_dir = &format!("{}/test_ls_sort_operands", env!("CARGO_TARGET_TMPDIR"));
+    let a = &format!("{test_dir}/a");
+    let b = &format!("{test_dir}/b");
+    let x = &format!("{test_dir}/x");
+    let y = &format!("{test_dir}/y");
+    let z = &format!("{test_dir}/z");
+
+    fs::create_dir(test_dir).unwrap();
+    fs::create_dir(a).unwrap();
+    fs::create_dir(b).unwrap();
+    fs::write(x, b"x").unwrap();
+    fs::write(y, b"yyy").unwrap();
+    fs::write(z, b"zz").unwrap();
+
+    change_file_time(a, TimeToChange::Modified("2000-01-01 00:00:00"));
+    change_file_time(b, TimeToChange::Modified("2010-01-01 00:00:00"));
+
+    // Directory operands are sorted like the files in a directory
+    ls_test(&[b, a], &format!("{a}:\n\n{b}:\n"), "", 0);
+    ls_test(&["-r", a, b], &format!("{b}:\n\n{a}:\n"), "", 0);
+    ls_test(&["-t", a, b], &format!("{b}:\n\n{a}:\n"), "", 0);
+    ls_test(&["-tr", b, a], &format!("{a}:\n\n{b}:\n"), "", 0);
+
+    // Files come first
+    ls_test(&["-t", a, z], &format!("{z}\n\n{a}:\n"), "", 0);
+
+    ls_test(&["-S", x, y, z], &format!("{y}\n{z}\n{x}\n"), "", 0);
+    ls_test(&["-Sr", x, y, z], &format!("{x}\n{z}\n{y}\n"), "", 0);
+
+    // -f lists operands in the order given and ignores -r
+    ls_test(&["-f", z, x, y], &format!("{z}\n{x}\n{y}\n"), "", 0);
+    ls_test(&["-f", "-r", z, x, y], &format!("{z}\n{x}\n{y}\n"), "", 0);
+
+    fs::remove_dir_all(test_dir).unwrap();
+}
+
+#[test]
+fn test_ls_recursive_dereference() {
+    let test_dir = &format!(
+        "{}/test_ls_recursive_dereference",
+        env!("CARGO_TARGET_TMPDIR")
+    );
+    let top = &format!("{test_dir}/top");
+    let outside = &format!("{test_dir}/outside");
+
+    fs::create_dir(test_dir).unwrap();
+    fs::create_dir(top).unwrap();
+    fs::create_dir(outside).unwrap();
+    fs::File::create(format!("{outside}/f")).unwrap();
+
+    // Relative to the directory containing the link
+    std::os::unix::fs::symlink("../outside", format!("{top}/link")).unwrap();
+
+    ls_test(&["-R1", top], &format!("{top}:\nlink\n"), "", 0);
+    ls_test(
+        &["-R1L", top],
+        &format!("{top}:\nlink\n\n{top}/link:\nf\n"),
+        "",
+        0,
+    );
+
+    fs::remove_dir_all(test_dir).unwrap();
+}
+
+#[test]
+fn test_ls_recursive_unreadable() {
+    // Permissions are not checked for root
+    if unsafe { libc::geteuid() } == 0 {
+        return;
+    }
+
+    let test_dir = &format!(
+        "{}/test_ls_recursive_unreadable",
+        env!("CARGO_TARGET_TMPDIR")
+    );
+    let a = &format!("{test_dir}/a");
+    let b = &format!("{test_dir}/b");
+
+    fs::create_dir(test_dir).unwrap();
+    fs::create_dir(a).unwrap();
+    fs::create_dir(b).unwrap();
+    fs::File::create(format!("{b}/f")).unwrap();
+    fs::set_permissions(a, fs::Permissions::from_mode(0o000)).unwrap();
+
+    // `a` is reported and `b` is still listed
+    ls_test(
+        &["-R1", test_dir],
+        &format!("{test_dir}:\na\nb\n\n{a}:\n\n{b}:\nf\n"),
+        &format!("ls: cannot open directory '{a}': Permission denied (os error 13)\n"),
+        1,
+    );
+
+    fs::set_permissions(a, fs::Permissions::from_mode(0o755)).unwrap();
+    fs::remove_dir_all(test_dir).unwrap();
+}
+
+#[test]
+fn test_ls_quoting() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let test_dir = &format!("{}/test_ls_quoting", env!("CARGO_TARGET_TMPDIR"));
+
+    fs::create_dir(test_dir).unwrap();
+    for name in [&b"a\tb"[..], b"c\nd", b"e\xfff", b"g h"] {
+        let mut path = test_dir.as_bytes().to_vec();
+        path.push(b'/');
+        path.extend_from_slice(name);
+        fs::File::create(OsStr::from_bytes(&path)).unwrap();
+    }
+
+    // TestPlan only compares strings, the raw bytes are checked here
+    let literal_test = |args: &[&str]| {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_ls"))
+            .args(args)
+            .output()
+            .unwrap();
+
+        assert_eq!(output.stdout, b"a\tb\nc\nd\ne\xfff\ng h\n");
+        assert_eq!(output.status.code(), Some(0));
+    };
+
+    // Not a terminal so names are written as is by default, even invalid
+    // UTF-8
+    literal_test(&[test_dir]);
+    literal_test(&["-N", test_dir]);
+
+    ls_test(&["-q", test_dir], "a?b\nc?d\ne?f\ng h\n", "", 0);
+    ls_test(&["-b", test_dir], "a\\tb\nc\\nd\ne\\377f\ng\\ h\n", "", 0);
+
+    // The last of -q, -b and -N wins
+    ls_test(&["-bq", test_dir], "a?b\nc?d\ne?f\ng h\n", "", 0);
+    literal_test(&["-qN", test_dir]);
+
+    fs::remove_dir_all(test_dir).unwrap();
+}
+
+#[test]
+fn test_ls_block_size() {
+    let test_dir = &format!("{}/test_ls_block_size", env!("CARGO_TARGET_TMPDIR"));
+    let file = &format!("{test_dir}/file");
+
+    fs::create_dir(test_dir).unwrap();
+    fs::write(file, vec![b'\n'; 123456]).unwrap();
+
+    // In 512-byte units
+    let blocks = fs::metadata(file).unwrap().blocks();
+
+    // Run with BLOCKSIZE set only for the child process
+    let block_size_test = |blocksize: Option<&str>, args: &[&str], expected_blocks: u64| {
+        let mut command = std::process::Command::new(env!("CARGO_BIN_EXE_ls"));
+        command.env_remove("BLOCKSIZE");
+        if let Some(blocksize) = blocksize {
+            command.env("BLOCKSIZE", blocksize);
+        }
+        let output = command.args(args).arg(file).output().unwrap();
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert_eq!(stdout, format!("{expected_blocks} {file}\n"));
+        assert_eq!(output.status.code(), Some(0));
+    };
+
+    block_size_test(None, &["-s"], blocks);
+    block_size_test(None, &["-sk"], blocks.div_ceil(2));
+    block_size_test(Some("4096"), &["-s"], blocks.div_ceil(8));
+    block_size_test(Some("1M"), &["-s"], 1);
+
+    // -k takes precedence over BLOCKSIZE, and invalid values are ignored
+    block_size_test(Some("1M"), &["-sk"], blocks.div_ceil(2));
+    block_size_test(Some("bogus"), &["-s"], blocks);
+
+    fs::remove_dir_all(test_dir).unwrap();
+}
@@ -98,3 +98,28 @@ fn test_set_directory_mode() {
     // Clean up
     fs::remove_dir(&dir_path).expect("Unable to remove test directory");
 }
+
+#[test]
+fn test_parents_existing() {
+    let (temp_dir, dir_path) = setup_test_env();
+    let nested = format!("{dir_path}/a/b");
+
+    run_mkdir_test(vec!["-p", &nested], 0, "");
+    assert!(Path::new(&nested).is_dir());
+
+    // Existing directories are not an error with -p
+    run_mkdir_test(vec!["-p", &nested], 0, "");
+    run_mkdir_test(
+        vec!["-p", &dir_path, &format!("{dir_path}/./a/../c")],
+        0,
+        "",
+    );
+    assert!(Path::new(&format!("{dir_path}/c")).is_dir());
+
+    // ... but other files are
+    let file = temp_dir.path().join("file");
+    fs::write(&file, "").unwrap();
+    let under_file = format!("{}/d", file.to_str().unwrap());
+    run_mkdir_test(vec!["-p", &under_file], 1, "Not a directory");
+    run_mkdir_test(vec!["-p", file.to_str().unwrap()], 1, "File exists");
+}
@@ -404,8 +404,7 @@ fn test_mv_i_1() {
 }
 
 // Partial port of coreutils/tests/mv/i-2.sh
-// This only includes the tests for `mv` and not `cp`. This also does not
-// consider the writability of the destination when generating the prompt.
+// This only includes the tests for `mv` and not `cp`.
 #[test]
 fn test_mv_i_2() {
     let test_dir = &format!("{}/test_mv_i_2", env!("CARGO_TARGET_TMPDIR"));
@@ -433,14 +432,11 @@ fn test_mv_i_2() {
     mv_test_with_stdin(&["-if", a, b], "", "", "", 0);
     assert!(!Path::new(a).exists());
 
-    // coreutils mv changes the prompt depending on whether the destination is
-    // writeable. For this case it's:
-    // "mv: replace 'd', overriding mode 0000 (---------)?"
     mv_test_with_stdin(
         &["-fi", c, d],
         "y\n",
         "",
-        &format!("mv: overwrite '{d}'? "),
+        &format!("mv: replace '{d}', overriding mode 0000 (---------)? "),
         0,
     );
     assert!(!Path::new(c).exists());
@@ -1447,3 +1443,121 @@ fn test_mv_sticky_to_xpart() {
     fs::remove_dir_all(test_dir).unwrap();
     fs::remove_dir_all(other_dir).unwrap();
 }
+
+#[test]
+#[cfg_attr(not(target_os = "linux"), ignore)]
+fn test_mv_xpart_dir() {
+    let test_name = "test_mv_xpart_dir";
+    let test_dir = &format!("{}/{test_name}", env!("CARGO_TARGET_TMPDIR"));
+    let d = &format!("{test_dir}/d");
+    let d_f = &format!("{test_dir}/d/f");
+    let d_link = &format!("{test_dir}/d/link");
+    fs::create_dir(test_dir).unwrap();
+    fs::create_dir(d).unwrap();
+    fs::write(d_f, b"f\n").unwrap();
+    fs::set_permissions(d_f, Permissions::from_mode(0o640)).unwrap();
+    unix::fs::symlink("f", d_link).unwrap();
+
+    let other_dir = &format!(
+        "{}/{test_name}",
+        option_env!("OTHER_PARTITION_TMPDIR").unwrap_or("/dev/shm")
+    );
+    let other_d = &format!("{other_dir}/d");
+    let other_d_f = &format!("{other_dir}/d/f");
+    let other_d_link = &format!("{other_dir}/d/link");
+    fs::create_dir(other_dir).unwrap();
+
+    let mtime = fs::metadata(d_f).unwrap().mtime();
+    mv_test(&[d, other_d], "", "", 0);
+
+    assert!(!Path::new(d).exists());
+    assert_eq!(fs::read(other_d_f).unwrap(), b"f\n");
+    let md = fs::metadata(other_d_f).unwrap();
+    assert_eq!(md.mode() & 0o777, 0o640);
+    assert_eq!(md.mtime(), mtime);
+    assert_eq!(fs::read_link(other_d_link).unwrap(), Path::new("f"));
+
+    fs::remove_dir_all(test_dir).unwrap();
+    fs::remove_dir_all(other_dir).unwrap();
+}
+
+#[test]
+#[cfg_attr(not(target_os = "linux"), ignore)]
+fn test_mv_xpart_cleanup() {
+    // Permissions are not checked for root
+    if unsafe { libc::geteuid() } == 0 {
+        return;
+    }
+
+    let test_name = "test_mv_xpart_cleanup";
+    let test_dir = &format!("{}/{test_name}", env!("CARGO_TARGET_TMPDIR"));
+    let d = &format!("{test_dir}/d");
+    let d_a = &format!("{test_dir}/d/a");
+    let d_b = &format!("{test_dir}/d/b");
+    fs::create_dir(test_dir).unwrap();
+    fs::create_dir(d).unwrap();
+    fs::write(d_a, b"a\n").unwrap();
+    fs::write(d_b, b"b\n").unwrap();
+    fs::set_permissions(d_b, Permissions::from_mode(0o000)).unwrap();
+
+    let other_dir = &format!(
+        "{}/{test_name}",
+        option_env!("OTHER_PARTITION_TMPDIR").unwrap_or("/dev/shm")
+    );
+    let other_d = &format!("{other_dir}/d");
+    fs::create_dir(other_dir).unwrap();
+
+    mv_test(
+        &[d, other_dir],
+        "",
+        &format!(
+            "mv: inter-device move failed: cannot open '{d_b}' for reading: Permission denied\n"
+        ),
+        1,
+    );
+
+    // The source is kept and the partial copy removed
+    assert_eq!(fs::read(d_a).unwrap(), b"a\n");
+    assert!(Path::new(d_b).exists());
+    assert!(!Path::new(other_d).exists());
+
+    fs::set_permissions(d_b, Permissions::from_mode(0o644)).unwrap();
+    fs::remove_dir_all(test_dir).unwrap();
+    fs::remove_dir_all(other_dir).unwrap();
+}
+
+#[test]
+fn test_mv_no_clobber() {
+    let test_dir = &format!("{}/test_mv_no_clobber", env!("CARGO_TARGET_TMPDIR"));
+    let a = &format!("{test_dir}/a");
+    let b = &format!("{test_dir}/b");
+    let d = &format!("{test_dir}/d");
+    let d_a = &format!("{test_dir}/d/a");
+
+    fs::create_dir(test_dir).unwrap();
+    fs::create_dir(d).unwrap();
+    fs::write(a, b"a\n").unwrap();
+    fs::write(b, b"b\n").unwrap();
+    fs::write(d_a, b"d/a\n").unwrap();
+
+    // Neither file is touched and it's not an error
+    mv_test(&["-n", a, b], "", "", 0);
+    mv_test(&["-n", a, d], "", "", 0);
+    assert_eq!(fs::read(a).unwrap(), b"a\n");
+    assert_eq!(fs::read(b).unwrap(), b"b\n");
+    assert_eq!(fs::read(d_a).unwrap(), b"d/a\n");
+
+    // The last of -f, -i and -n wins
+    mv_test_with_stdin(&["-i", "-n", a, b], "y\n", "", "", 0);
+    assert_eq!(fs::read(b).unwrap(), b"b\n");
+    mv_test(&["-n", "-f", a, b], "", "", 0);
+    assert!(!Path::new(a).exists());
+    assert_eq!(fs::read(b).unwrap(), b"a\n");
+
+    // Moving to a path that doesn't exist is not affected
+    mv_test(&["-n", b, a], "", "", 0);
+    assert!(!Path::new(b).exists());
+    assert_eq!(fs::read(a).unwrap(), b"a\n");
+
+    fs::remove_dir_all(test_dir).unwrap();
+}
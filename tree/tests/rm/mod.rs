@@ -440,6 +440,11 @@ fn test_rm_i_1() {
 // Port of coreutils/tests/rm/isatty.sh
 #[test]
 fn test_rm_isatty() {
+    // Permissions are not checked for root
+    if unsafe { libc::geteuid() } == 0 {
+        return;
+    }
+
     let test_dir = &format!("{}/test_rm_isatty", env!("CARGO_TARGET_TMPDIR"));
     let f = &format!("{test_dir}/f");
 
@@ -713,9 +718,19 @@ fn test_rm_r_root() {
         } else {
             format!("rm: it is dangerous to operate recursively on '{arg}' (same as '/')\n")
         };
+        let err = format!("{err}rm: use --no-preserve-root to override this failsafe\n");
         rm_test(&["-r", &arg], "", &err, 1);
     }
 
+    // The last of --preserve-root and --no-preserve-root wins
+    rm_test(
+        &["-r", "--no-preserve-root", "--preserve-root", "/"],
+        "",
+        "rm: it is dangerous to operate recursively on '/'\n\
+         rm: use --no-preserve-root to override this failsafe\n",
+        1,
+    );
+
     for arg in [
         String::from("//."),
         String::from("/./"),
@@ -843,6 +858,11 @@ fn test_rm_rm2() {
 // Port of coreutils/tests/rm/rm3.sh
 #[test]
 fn test_rm_rm3() {
+    // Permissions are not checked for root
+    if unsafe { libc::geteuid() } == 0 {
+        return;
+    }
+
     let test_dir = &format!("{}/test_rm_rm3", env!("CARGO_TARGET_TMPDIR"));
     let z = &format!("{test_dir}/z");
     let empty = &format!("{test_dir}/z/empty");
@@ -1204,3 +1224,100 @@ fn test_rm_no_give_up() {
 
     fs::remove_dir_all(test_dir).unwrap();
 }
+
+#[test]
+fn test_rm_r_no_follow_symlinks() {
+    let test_dir = &format!(
+        "{}/test_rm_r_no_follow_symlinks",
+        env!("CARGO_TARGET_TMPDIR")
+    );
+    let outside = &format!("{test_dir}/outside");
+    let outside_f = &format!("{test_dir}/outside/f");
+    let d = &format!("{test_dir}/d");
+    let d_sub = &format!("{test_dir}/d/sub");
+
+    fs::create_dir(test_dir).unwrap();
+    fs::create_dir(outside).unwrap();
+    fs::File::create(outside_f).unwrap();
+    fs::create_dir_all(d_sub).unwrap();
+    unix::fs::symlink(outside, format!("{d}/link")).unwrap();
+    unix::fs::symlink(outside, format!("{d_sub}/link")).unwrap();
+
+    rm_test(&["-r", d], "", "", 0);
+
+    // Only the symlinks are removed, not what they point to
+    assert!(!Path::new(d).exists());
+    assert!(Path::new(outside_f).exists());
+
+    // A symlink to a directory given as the operand is also not followed
+    let link = &format!("{test_dir}/link");
+    unix::fs::symlink(outside, link).unwrap();
+    rm_test(&["-r", link], "", "", 0);
+    assert!(fs::symlink_metadata(link).is_err());
+    assert!(Path::new(outside_f).exists());
+
+    fs::remove_dir_all(test_dir).unwrap();
+}
+
+#[test]
+fn test_rm_interactive_once() {
+    let test_dir = &format!("{}/test_rm_interactive_once", env!("CARGO_TARGET_TMPDIR"));
+    let files: Vec<String> = (1..=4).map(|i| format!("{test_dir}/f{i}")).collect();
+    let files: Vec<&str> = files.iter().map(String::as_str).collect();
+    let d = &format!("{test_dir}/d");
+    let d_f = &format!("{test_dir}/d/f");
+
+    fs::create_dir(test_dir).unwrap();
+    for f in &files {
+        fs::File::create(f).unwrap();
+    }
+
+    // No prompt for up to three files
+    rm_test(&["-I", files[0], files[1], files[2]], "", "", 0);
+    for f in &files[..3] {
+        assert!(!Path::new(f).exists());
+    }
+    for f in &files[..3] {
+        fs::File::create(f).unwrap();
+    }
+
+    // A single prompt for more than three files
+    let mut args = vec!["-I"];
+    args.extend(&files);
+    rm_test_with_stdin(&args, "n\n", "", "rm: remove 4 arguments? ", 0);
+    for f in &files {
+        assert!(Path::new(f).exists());
+    }
+    rm_test_with_stdin(&args, "y\n", "", "rm: remove 4 arguments? ", 0);
+    for f in &files {
+        assert!(!Path::new(f).exists());
+    }
+
+    // Always prompt when removing recursively, but only once
+    fs::create_dir(d).unwrap();
+    fs::File::create(d_f).unwrap();
+    rm_test_with_stdin(
+        &["-I", "-r", d],
+        "n\n",
+        "",
+        "rm: remove 1 argument recursively? ",
+        0,
+    );
+    assert!(Path::new(d_f).exists());
+    rm_test_with_stdin(
+        &["-I", "-r", d],
+        "y\n",
+        "",
+        "rm: remove 1 argument recursively? ",
+        0,
+    );
+    assert!(!Path::new(d).exists());
+
+    // -f overrides an earlier -I
+    fs::create_dir(d).unwrap();
+    fs::File::create(d_f).unwrap();
+    rm_test(&["-I", "-f", "-r", d], "", "", 0);
+    assert!(!Path::new(d).exists());
+
+    fs::remove_dir_all(test_dir).unwrap();
+}
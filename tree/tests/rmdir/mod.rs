@@ -92,3 +92,67 @@ fn rmdir_remove_directory_with_parents() {
     assert!(!dir_path.exists());
     assert!(!parent_dir.exists());
 }
+
+#[test]
+fn rmdir_parents_stop_at_non_empty() {
+    let temp_dir = tempdir().expect("Unable to create temporary directory");
+    let a = temp_dir.path().join("a");
+    let a_b = a.join("b");
+    let a_b_c = a_b.join("c");
+    fs::create_dir_all(&a_b_c).expect("Unable to create test directories");
+    fs::write(a.join("file.txt"), b"test").expect("Unable to create test file");
+
+    // The first directory that can't be removed is reported, and the ones above it are kept
+    let expected_err = format!("{}: Directory not empty", a.display());
+    let plan = TestPlan {
+        cmd: String::from("rmdir"),
+        args: vec![String::from("-p"), a_b_c.to_str().unwrap().to_string()],
+        stdin_data: String::new(),
+        expected_out: String::new(),
+        expected_err: String::new(),
+        expected_exit_code: 1,
+    };
+    run_test_with_checker(plan, |_, output: &Output| {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.starts_with(&expected_err), "{stderr}");
+        assert_eq!(output.status.code(), Some(1));
+    });
+    assert!(!a_b.exists());
+    assert!(a.exists());
+}
+
+#[test]
+fn rmdir_ignore_fail_on_non_empty() {
+    let temp_dir = tempdir().expect("Unable to create temporary directory");
+    let a = temp_dir.path().join("a");
+    let a_b = a.join("b");
+    fs::create_dir_all(&a_b).expect("Unable to create test directories");
+    fs::write(a.join("file.txt"), b"test").expect("Unable to create test file");
+
+    for args in [
+        vec!["--ignore-fail-on-non-empty", a.to_str().unwrap()],
+        vec!["--ignore-fail-on-non-empty", "-p", a_b.to_str().unwrap()],
+    ] {
+        let plan = TestPlan {
+            cmd: String::from("rmdir"),
+            args: args.iter().map(|&s| s.into()).collect(),
+            stdin_data: String::new(),
+            expected_out: String::new(),
+            expected_err: String::new(),
+            expected_exit_code: 0,
+        };
+        run_test_with_checker(plan, |_, output: &Output| {
+            assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+            assert_eq!(output.status.code(), Some(0));
+        });
+    }
+    assert!(!a_b.exists());
+    assert!(a.exists());
+
+    // Other errors are still reported
+    run_rmdir_test(
+        vec!["--ignore-fail-on-non-empty", a_b.to_str().unwrap()],
+        1,
+        "No such file or directory",
+    );
+}
@@ -7,8 +7,13 @@
 // SPDX-License-Identifier: MIT
 //
 
+mod chgrp;
+mod chmod;
+mod chown;
+mod common;
 mod cp;
 mod link;
+mod ln;
 mod ls;
 mod mkdir;
 mod mv;